@@ -0,0 +1,46 @@
+use std::path::Path;
+use std::process::Command;
+
+fn main() {
+    // Read back by LunaLib::build_info
+    println!("cargo:rustc-env=LUNALIB_TARGET={}", std::env::var("TARGET").unwrap_or_default());
+    println!("cargo:rerun-if-changed=build.rs");
+    for path in [".git/HEAD", ".git/refs/heads"] {
+        // A missing path would make cargo rerun this script on every build
+        if Path::new(path).exists() {
+            println!("cargo:rerun-if-changed={}", path);
+        }
+    }
+    let output = Command::new("git").args(["rev-parse", "--short=12", "HEAD"]).output();
+    if let Ok(output) = output
+        && output.status.success()
+    {
+        println!("cargo:rustc-env=LUNALIB_GIT_HASH={}", String::from_utf8_lossy(&output.stdout).trim());
+    }
+    #[cfg(feature = "cabi")]
+    write_header();
+}
+
+/// include/lunalib.h for C and C++ callers of the `ffi` module
+#[cfg(feature = "cabi")]
+fn write_header() {
+    use cbindgen::{Config, Language, RenameRule};
+
+    println!("cargo:rerun-if-changed=src/ffi.rs");
+    let mut config = Config {
+        language: Language::C,
+        cpp_compat: true,
+        include_guard: Some("LUNALIB_H".to_string()),
+        autogen_warning: Some("/* Generated by build.rs from src/ffi.rs; do not edit */".to_string()),
+        ..Config::default()
+    };
+    config.enumeration.rename_variants = RenameRule::ScreamingSnakeCase;
+    config.enumeration.prefix_with_name = true;
+    // Only the ffi module is parsed, so this does not need cargo metadata
+    cbindgen::Builder::new()
+        .with_config(config)
+        .with_src("src/ffi.rs")
+        .generate()
+        .expect("cannot generate the C header from src/ffi.rs")
+        .write_to_file("include/lunalib.h");
+}
@@ -0,0 +1,8 @@
+#[cfg(not(target_arch = "wasm32"))]
+fn main() {
+    lunalib::cli::main();
+}
+
+/// The CLI needs the filesystem and network; on wasm32 only the library is useful
+#[cfg(target_arch = "wasm32")]
+fn main() {}
@@ -1,41 +1,1227 @@
 // src/cli.rs
-use clap::{Arg, Command};
-use crate::luna_lib::LunaLib;
+use std::ffi::OsString;
+use std::fmt;
+use std::fs;
+use std::io::{self, BufRead, IsTerminal, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use clap::{Parser, Subcommand, ValueEnum};
+use serde_json::{json, Value as JsonValue};
+use crate::core::blockchain::{BlockchainError, BlockchainManager};
+use crate::core::daemon::Daemon;
+use crate::core::daemon_config::{DaemonConfig, MiningConfig};
+use crate::core::wallet::LunaWallet;
+use crate::gtx::bill_registry::{BillInfo, BillRegistry};
+use crate::gtx::genesis::GTXGenesis;
+use crate::luna_lib::config::DEFAULT_ENDPOINT_URL;
+use crate::luna_lib::{LunaError, LunaLib};
+use crate::mining::difficulty::Difficulty;
+use crate::mining::miner::{GenesisMiner, MiningProgress};
+use crate::storage::database::WalletDatabase;
+use crate::storage::encryption::EncryptionManager;
+use crate::transactions::transactions::{FeePriority, TransactionManager};
+use crate::utils::console::{color_enabled, Align, ConsoleColor, ConsoleLogger, KeyValueBlock, ProgressReporter, Table};
+use crate::utils::export::write_csv;
+use crate::utils::format::{human_amount, human_bytes, human_duration, parse_duration, relative_time};
+use crate::utils::log::{self, LevelFilter, LogFilter};
+
+/// What every command handler returns; errors are turned into exit codes by `report`
+pub type CliResult<T = ()> = Result<T, CliError>;
+
+/// Why a command failed. Each kind exits with its own code:
+/// 0 success, 1 anything else, 2 usage, 3 validation, 4 network, 5 auth, 6 not found.
+#[derive(Debug, Clone, PartialEq)]
+pub enum CliError {
+    /// Anything not covered below, such as an unwritable file
+    Failed(String),
+    /// Bad arguments, or a prompt that `--non-interactive` forbids
+    Usage(String),
+    /// A transaction or bill did not pass validation, e.g. for insufficient funds
+    Validation(String),
+    /// An endpoint or daemon could not be reached or refused the request
+    Network(String),
+    /// A wrong wallet password
+    Auth(String),
+    /// The user declined the confirmation prompt or interrupted the command
+    Aborted,
+    /// No stored wallet or bill matches
+    NotFound(String),
+}
+
+impl CliError {
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            CliError::Failed(_) | CliError::Aborted => 1,
+            CliError::Usage(_) => 2,
+            CliError::Validation(_) => 3,
+            CliError::Network(_) => 4,
+            CliError::Auth(_) => 5,
+            CliError::NotFound(_) => 6,
+        }
+    }
+
+    /// Stable name for the `kind` field of JSON errors
+    pub fn kind(&self) -> &'static str {
+        match self {
+            CliError::Failed(_) => "failed",
+            CliError::Usage(_) => "usage",
+            CliError::Validation(_) => "validation",
+            CliError::Network(_) => "network",
+            CliError::Auth(_) => "auth",
+            CliError::Aborted => "aborted",
+            CliError::NotFound(_) => "not_found",
+        }
+    }
+}
+
+impl fmt::Display for CliError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CliError::Failed(e) | CliError::Usage(e) | CliError::Validation(e) | CliError::Auth(e) | CliError::NotFound(e) => write!(f, "{}", e),
+            CliError::Network(e) => write!(f, "Network error: {}", e),
+            CliError::Aborted => write!(f, "Aborted"),
+        }
+    }
+}
+
+impl From<String> for CliError {
+    fn from(e: String) -> Self {
+        CliError::Failed(e)
+    }
+}
+
+/// Network failures and rejected input keep their own exit codes
+impl From<LunaError> for CliError {
+    fn from(e: LunaError) -> Self {
+        match e {
+            LunaError::Validation(_) => CliError::Validation(e.to_string()),
+            LunaError::Blockchain(_) | LunaError::P2P(_) => CliError::Network(e.to_string()),
+            e => CliError::Failed(e.to_string()),
+        }
+    }
+}
+
+impl From<BlockchainError> for CliError {
+    fn from(e: BlockchainError) -> Self {
+        CliError::Network(e.to_string())
+    }
+}
+
+impl From<clap::Error> for CliError {
+    fn from(e: clap::Error) -> Self {
+        let message = e.to_string();
+        CliError::Usage(message.trim_end().trim_start_matches("error: ").to_string())
+    }
+}
+
+/// LunaLib Cryptocurrency Wallet
+#[derive(Debug, Parser)]
+#[command(name = "luna-wallet", version = LunaLib::get_version())]
+pub struct Cli {
+    /// Wallet database; defaults to ~/.luna_wallet/wallets.db
+    #[arg(long, global = true)]
+    pub db: Option<PathBuf>,
+    /// Print a single JSON document on stdout, and errors as JSON on stderr
+    #[arg(long, global = true)]
+    pub json: bool,
+    /// Leave out progress and other decorative output
+    #[arg(long, short, global = true)]
+    pub quiet: bool,
+    /// Never color output; setting NO_COLOR does the same
+    #[arg(long, global = true)]
+    pub no_color: bool,
+    /// Fail instead of prompting for a password or confirmation
+    #[arg(long, global = true)]
+    pub non_interactive: bool,
+    /// Blockchain endpoint transactions are broadcast to
+    #[arg(long, global = true, default_value = DEFAULT_ENDPOINT_URL)]
+    pub endpoint: String,
+    #[command(subcommand)]
+    pub command: Command,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Create, inspect and delete wallets
+    #[command(subcommand)]
+    Wallet(WalletCommand),
+    /// Sign a transfer from a stored wallet and broadcast it
+    Send(SendArgs),
+    /// Mine bills and blocks, or measure the hashrate
+    #[command(subcommand)]
+    Mine(MineCommand),
+    /// Transactions recorded for a stored wallet, newest first
+    History(HistoryArgs),
+    /// List, verify, export and import GTX bills
+    #[command(subcommand)]
+    Bills(BillsCommand),
+    /// Run a daemon, or query and stop one through its HTTP API
+    #[command(subcommand)]
+    Daemon(DaemonCommand),
+}
+
+#[derive(Debug, Subcommand)]
+pub enum DaemonCommand {
+    /// Run a daemon from a config file until Ctrl-C or an authorized shutdown
+    Start {
+        #[arg(long)]
+        config: PathBuf,
+        /// Run in the background, logging to daemon.log in the data_dir
+        #[arg(long)]
+        detach: bool,
+        /// Where --detach records the pid; defaults to daemon.pid in the data_dir
+        #[arg(long)]
+        pidfile: Option<PathBuf>,
+    },
+    /// Health, chain height, peers and counters of a running daemon
+    Status {
+        #[arg(long)]
+        config: PathBuf,
+    },
+    /// Peers registered with a running daemon
+    Peers {
+        #[arg(long)]
+        config: PathBuf,
+    },
+    /// Shut a daemon down through its API, or signal the pidfile process if that fails
+    Stop {
+        #[arg(long)]
+        config: PathBuf,
+        #[arg(long)]
+        pidfile: Option<PathBuf>,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+pub enum BillsCommand {
+    /// Bills issued to an address, newest first
+    List {
+        #[arg(long)]
+        address: String,
+        /// Only bills with this status, such as active
+        #[arg(long)]
+        status: Option<String>,
+    },
+    /// Check a registered bill's signature
+    Verify {
+        serial: String,
+        /// Also ask the endpoint; failures there only warn unless --strict
+        #[arg(long)]
+        remote: bool,
+        /// Skip the lenient fallback check and fail when the endpoint cannot confirm the bill
+        #[arg(long)]
+        strict: bool,
+    },
+    /// Write a bill certificate to a file
+    Export {
+        serial: String,
+        #[arg(long)]
+        output: PathBuf,
+    },
+    /// Register a bill from an exported certificate once it passes strict verification
+    Import { file: PathBuf },
+}
+
+#[derive(Debug, clap::Args)]
+pub struct HistoryArgs {
+    pub address: String,
+    #[arg(long = "type", value_enum)]
+    pub tx_type: Option<HistoryType>,
+    /// YYYY-MM-DD, or a relative age such as 30m, 12h, 7d or 2w
+    #[arg(long)]
+    pub since: Option<String>,
+    #[arg(long)]
+    pub limit: Option<usize>,
+    /// Defaults to json with --json, otherwise table
+    #[arg(long, value_enum)]
+    pub format: Option<HistoryFormat>,
+    /// Write to this file instead of stdout
+    #[arg(long)]
+    pub output: Option<PathBuf>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum HistoryType {
+    Transfer,
+    Reward,
+    /// GTX bill issuance
+    Gtx,
+}
+
+impl HistoryType {
+    fn matches(&self, tx_type: &str) -> bool {
+        match self {
+            HistoryType::Transfer => tx_type == "transfer",
+            HistoryType::Reward => tx_type == "reward",
+            HistoryType::Gtx => tx_type.starts_with("gtx"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum HistoryFormat {
+    Table,
+    Json,
+    Csv,
+}
+
+/// Columns of `history --format csv`
+pub const HISTORY_CSV_COLUMNS: [&str; 10] = ["hash", "type", "from", "to", "amount", "fee", "timestamp", "block_height", "status", "memo"];
+
+#[derive(Debug, Subcommand)]
+pub enum MineCommand {
+    /// Mine a GTX bill and register it in the bill registry
+    Bill {
+        #[arg(long)]
+        denomination: u64,
+        /// Address the bill is issued to
+        #[arg(long)]
+        address: String,
+        /// Leading zeros required; defaults to the denomination's difficulty
+        #[arg(long)]
+        difficulty: Option<u32>,
+        #[arg(long, default_value_t = 1)]
+        threads: usize,
+    },
+    /// Mine the next block on the endpoint's chain and submit it
+    Block {
+        /// Address that receives the block reward
+        #[arg(long)]
+        address: String,
+        /// Leading zeros required; defaults to the tip block's difficulty
+        #[arg(long)]
+        difficulty: Option<u32>,
+    },
+    /// Measure hashes per second without mining anything
+    Benchmark {
+        #[arg(long, default_value_t = 10)]
+        seconds: u64,
+        #[arg(long, default_value_t = 1)]
+        threads: usize,
+    },
+}
+
+#[derive(Debug, clap::Args)]
+pub struct SendArgs {
+    #[arg(long)]
+    pub from: String,
+    #[arg(long)]
+    pub to: String,
+    #[arg(long)]
+    pub amount: f64,
+    #[arg(long, default_value = "")]
+    pub memo: String,
+    /// Fee priority: low, normal or high
+    #[arg(long, default_value = "normal")]
+    pub priority: FeePriority,
+    /// Show the preview and stop without broadcasting
+    #[arg(long)]
+    pub dry_run: bool,
+    /// Broadcast without asking for confirmation
+    #[arg(long)]
+    pub yes: bool,
+    /// Read the wallet password from the first line of this file instead of prompting
+    #[arg(long)]
+    pub password_file: Option<PathBuf>,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum WalletCommand {
+    /// Generate a keypair and store it encrypted with a password
+    Create {
+        #[arg(long, default_value = "")]
+        label: String,
+        /// Read the password from the first line of this file instead of prompting
+        #[arg(long)]
+        password_file: Option<PathBuf>,
+    },
+    /// List stored wallets
+    List,
+    /// Show a stored wallet
+    Show { address: String },
+    /// Balance of a stored wallet, as last synced
+    Balance { address: String },
+    /// Remove a wallet from the database
+    Delete {
+        address: String,
+        /// Confirm the deletion; the private key cannot be recovered afterwards
+        #[arg(long)]
+        yes: bool,
+    },
+}
+
+/// Set by Ctrl-C while a mining command or a foreground daemon runs
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
 
 pub fn main() {
-    let matches = Command::new("LunaLib Cryptocurrency Wallet")
-        .arg(Arg::new("version")
-            .long("version")
-            .help("Show version")
-            .action(clap::ArgAction::SetTrue))
-        .get_matches();
-
-    if matches.get_flag("version") {
-        println!("LunaLib v{}", LunaLib::get_version());
+    process::exit(execute(std::env::args_os(), &mut io::stdout(), &mut io::stderr()));
+}
+
+/// Parse `args`, run the command and report any error on `err`, returning the exit code
+pub fn execute<I, T>(args: I, out: &mut dyn Write, err: &mut dyn Write) -> i32
+where
+    I: IntoIterator<Item = T>,
+    T: Into<OsString>,
+{
+    let args: Vec<OsString> = args.into_iter().map(Into::into).collect();
+    let (style, result) = match Cli::try_parse_from(&args) {
+        Ok(cli) => {
+            let style = Style::new(cli.json, cli.quiet, cli.no_color);
+            // Library logs go to stderr: warnings by default, only errors with --quiet, or per LUNA_LOG.
+            // Fails harmlessly when a logger is already installed, as in tests.
+            let level = if cli.quiet { LevelFilter::Error } else { LevelFilter::Warn };
+            let _ = log::init(ConsoleLogger::with_color(style.color), LogFilter::from_env(level));
+            watch_signals(&cli.command);
+            (style, run(cli, out))
+        }
+        // --help and --version
+        Err(e) if !e.use_stderr() => {
+            let _ = write!(out, "{}", e);
+            return 0;
+        }
+        Err(e) => {
+            // The flags were not parsed, so look for them directly
+            let flag = |name: &str| args.iter().any(|a| a == name);
+            (Style::new(flag("--json"), flag("--quiet"), flag("--no-color")), Err(e.into()))
+        }
+    };
+    report(&result, style, err)
+}
+
+/// Write `result`'s error to `err`, as JSON with --json, and return its exit code
+pub fn report(result: &CliResult, style: Style, err: &mut dyn Write) -> i32 {
+    let Err(e) = result else { return 0 };
+    let _ = if style.json {
+        let error = json!({"error": {"kind": e.kind(), "message": e.to_string(), "exit_code": e.exit_code()}});
+        writeln!(err, "{}", error)
     } else {
-        println!("LunaLib - Use 'luna-wallet --help' for options");
+        writeln!(err, "{}: {}", style.paint("Error", ConsoleColor::Red), e)
+    };
+    e.exit_code()
+}
+
+/// How output is rendered, from the global flags and the environment
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Style {
+    pub json: bool,
+    pub quiet: bool,
+    /// Only when stderr is a terminal, neither --no-color nor NO_COLOR is set, and output is not JSON
+    pub color: bool,
+}
+
+impl Style {
+    pub fn new(json: bool, quiet: bool, no_color: bool) -> Self {
+        Style { json, quiet, color: !json && !no_color && color_enabled() }
+    }
+
+    fn paint(&self, text: &str, color: ConsoleColor) -> String {
+        if self.color {
+            format!("{}{}{}", color.to_ansi_code(), text, ConsoleColor::Reset.to_ansi_code())
+        } else {
+            text.to_string()
+        }
     }
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use std::env;
+/// Where a command writes its result, and how
+struct Output<'a> {
+    writer: &'a mut dyn Write,
+    style: Style,
+    interactive: bool,
+}
+
+impl Output<'_> {
+    /// Write the command's result: `value` with --json, otherwise `text()`
+    fn emit(&mut self, value: &JsonValue, text: impl FnOnce() -> String) -> CliResult {
+        let line = if self.style.json { value.to_string() } else { text() };
+        writeln!(self.writer, "{}", line).map_err(|e| CliError::Failed(format!("Cannot write output: {}", e)))
+    }
+
+    /// Whether tables and detail views written to stdout may be colored
+    fn color(&self) -> bool {
+        self.style.color && io::stdout().is_terminal()
+    }
+
+    /// Whether to draw progress lines on stderr
+    fn progress(&self) -> bool {
+        !self.style.json && !self.style.quiet
+    }
 
-    #[test]
-    fn test_version_flag() {
-        // Simulate --version argument
-        let args = vec!["test-bin", "--version"];
-        env::set_var("CLAP_TEST_ARGS", args.join(" "));
-        // Should print version (mock LunaLib)
-        // This is a smoke test; output is not captured
-        main();
+    /// `reporter`, hidden with --quiet or --json
+    fn reporter(&self, reporter: ProgressReporter) -> ProgressReporter {
+        if self.progress() { reporter.with_color(self.style.color) } else { reporter.hidden() }
     }
 
-    #[test]
-    fn test_no_flag() {
-        let args = vec!["test-bin"];
-        env::set_var("CLAP_TEST_ARGS", args.join(" "));
-        main();
+    /// A decorative line on stderr, left out with --quiet or --json
+    fn note(&self, message: &str) {
+        if self.progress() {
+            eprintln!("{}", message);
+        }
+    }
+
+    /// Something went wrong but the command carries on; always shown on stderr
+    fn warn(&self, message: &str) {
+        if self.style.json {
+            eprintln!("{}", json!({"warning": message}));
+        } else {
+            eprintln!("{} {}", self.style.paint("Warning:", ConsoleColor::Yellow), message);
+        }
+    }
+
+    /// Fail with a usage error naming `flag` instead of prompting under --non-interactive
+    fn require_interactive(&self, flag: &str) -> CliResult {
+        if self.interactive {
+            return Ok(());
+        }
+        Err(CliError::Usage(format!("Not prompting because of --non-interactive; pass {}", flag)))
+    }
+}
+
+/// Mining and the foreground daemon stop cleanly on Ctrl-C
+fn watch_signals(command: &Command) {
+    if matches!(command, Command::Mine(_) | Command::Daemon(DaemonCommand::Start { .. })) {
+        watch_for_interrupt();
+    }
+    #[cfg(unix)]
+    if matches!(command, Command::Daemon(DaemonCommand::Start { .. })) {
+        watch_for_terminate();
+    }
+}
+
+/// The first Ctrl-C stops mining so partial stats can be printed; a second one exits
+fn watch_for_interrupt() {
+    thread::spawn(|| {
+        let Ok(runtime) = tokio::runtime::Builder::new_current_thread().enable_all().build() else { return };
+        runtime.block_on(async {
+            while tokio::signal::ctrl_c().await.is_ok() {
+                if INTERRUPTED.swap(true, Ordering::SeqCst) {
+                    std::process::exit(130);
+                }
+            }
+        });
+    });
+}
+
+/// `daemon stop` falls back to SIGTERM, which should shut down as cleanly as Ctrl-C
+#[cfg(unix)]
+fn watch_for_terminate() {
+    use tokio::signal::unix::{signal, SignalKind};
+    thread::spawn(|| {
+        let Ok(runtime) = tokio::runtime::Builder::new_current_thread().enable_all().build() else { return };
+        runtime.block_on(async {
+            let Ok(mut terminate) = signal(SignalKind::terminate()) else { return };
+            while terminate.recv().await.is_some() {
+                INTERRUPTED.store(true, Ordering::SeqCst);
+            }
+        });
+    });
+}
+
+/// Run a parsed command, writing its output to `out`
+pub fn run(cli: Cli, out: &mut dyn Write) -> CliResult {
+    // Bills are kept next to the wallet database, whose directory is created first
+    let bills_path = cli.db.as_ref().map(|db| db.with_file_name("bills.db"));
+    let db = WalletDatabase::new(cli.db);
+    let bills = BillRegistry::new(bills_path);
+    let out = &mut Output { writer: out, style: Style::new(cli.json, cli.quiet, cli.no_color), interactive: !cli.non_interactive };
+    match cli.command {
+        Command::Wallet(command) => run_wallet(command, &db, out),
+        Command::Send(args) => run_send(args, &db, &BlockchainManager::new(&cli.endpoint, 1), out),
+        Command::Mine(command) => run_mine(command, bills, &cli.endpoint, out),
+        Command::History(args) => run_history(args, &db, out),
+        Command::Bills(command) => run_bills(command, GTXGenesis::from_registry(bills), &cli.endpoint, out),
+        Command::Daemon(command) => run_daemon(command, out),
+    }
+}
+
+fn run_wallet(command: WalletCommand, db: &WalletDatabase, out: &mut Output) -> CliResult {
+    match command {
+        WalletCommand::Create { label, password_file } => {
+            let password = match password_file {
+                Some(path) => read_password_file(&path)?,
+                None => {
+                    out.require_interactive("--password-file")?;
+                    prompt_new_password()?
+                }
+            };
+            if password.is_empty() {
+                return Err("Password must not be empty".to_string().into());
+            }
+            let wallet = LunaWallet::create(&label, &password);
+            if !db.save_wallet(&wallet) {
+                return Err(format!("Could not save wallet to {}", db.db_path.display()).into());
+            }
+            let address = wallet.address;
+            let wallet = db.load_wallet(&address).ok_or_else(|| "Saved wallet could not be read back".to_string())?;
+            out.emit(&summary(&wallet.to_json()), || format!("Created wallet {}", address))
+        }
+        WalletCommand::List => {
+            let wallets: Vec<JsonValue> = db.list_wallets().iter().map(|w| summary(&w.to_json())).collect();
+            let color = out.color();
+            out.emit(&json!(wallets), || {
+                if wallets.is_empty() {
+                    return "No wallets".to_string();
+                }
+                let mut table = Table::new().with_color(color);
+                table.add_column("ADDRESS", Align::Left).add_column("LABEL", Align::Left).add_column("BALANCE", Align::Right);
+                for wallet in &wallets {
+                    table.add_row([str_field(wallet, "address").to_string(), str_field(wallet, "label").to_string(), human_amount(wallet["balance"].as_f64().unwrap_or(0.0))]);
+                }
+                table.render()
+            })
+        }
+        WalletCommand::Show { address } => {
+            let wallet = summary(&find_wallet(db, &address)?);
+            let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs();
+            let mut details = KeyValueBlock::new().with_color(out.color());
+            details
+                .add("Address", str_field(&wallet, "address"))
+                .add("Label", str_field(&wallet, "label"))
+                .add("Public key", str_field(&wallet, "public_key"))
+                .add("Balance", human_amount(wallet["balance"].as_f64().unwrap_or(0.0)))
+                .add("Created", relative_time(wallet["created"].as_f64().unwrap_or(0.0) as u64, now));
+            out.emit(&wallet, || details.render())
+        }
+        WalletCommand::Balance { address } => {
+            let wallet = find_wallet(db, &address)?;
+            let balance = json!({"address": address, "balance": wallet["balance"].as_f64().unwrap_or(0.0)});
+            out.emit(&balance, || format!("{} LUN", balance["balance"]))
+        }
+        WalletCommand::Delete { address, yes } => {
+            find_wallet(db, &address)?;
+            if !yes {
+                return Err(format!("Refusing to delete {} without --yes", address).into());
+            }
+            if !db.delete_wallet(&address) {
+                return Err(format!("Could not delete wallet {}", address).into());
+            }
+            out.emit(&json!({"address": address, "deleted": true}), || format!("Deleted wallet {}", address))
+        }
+    }
+}
+
+fn run_send(args: SendArgs, db: &WalletDatabase, blockchain: &BlockchainManager, out: &mut Output) -> CliResult {
+    let wallet = find_wallet(db, &args.from)?;
+    let password = match &args.password_file {
+        Some(path) => read_password_file(path)?,
+        None => {
+            out.require_interactive("--password-file")?;
+            prompt_password("Password: ")?
+        }
+    };
+    let private_key = EncryptionManager::new()
+        .decrypt_data(str_field(&wallet, "encrypted_private_key"), &password)
+        .ok_or_else(|| CliError::Auth("Wrong password for this wallet".to_string()))?;
+
+    let manager = TransactionManager::new();
+    let tx = manager
+        .create_priority_transaction(&args.from, &args.to, args.amount, &args.memo, &private_key, args.priority)
+        .map_err(|e| CliError::Validation(format!("Invalid transaction: {}", e)))?;
+    let (valid, reason) = manager.security.validate_transaction(&tx);
+    if !valid {
+        return Err(CliError::Validation(format!("Invalid transaction: {}", reason)));
+    }
+    let fee = tx["fee"].as_f64().unwrap_or(0.0);
+    let balance = wallet["balance"].as_f64().unwrap_or(0.0);
+    let balance_after = balance - args.amount - fee;
+    if balance_after < 0.0 {
+        return Err(CliError::Validation(format!("Invalid transaction: insufficient funds: balance {} is less than {} plus fee {}", balance, args.amount, fee)));
+    }
+
+    let mut preview = json!({
+        "from": args.from,
+        "to": args.to,
+        "amount": args.amount,
+        "fee": fee,
+        "memo": tx["memo"],
+        "balance": balance,
+        "balance_after": balance_after,
+        "hash": tx["hash"],
+        "dry_run": args.dry_run,
+    });
+    if !out.style.json || args.dry_run {
+        let mut details = KeyValueBlock::new().with_color(out.color());
+        details.add("From", &args.from).add("To", &args.to).add("Amount", args.amount).add("Fee", fee).add("Balance after", balance_after);
+        out.emit(&preview, || details.render())?;
+    }
+    if args.dry_run {
+        return Ok(());
+    }
+    if !args.yes {
+        out.require_interactive("--yes")?;
+        if !confirm("Type \"yes\" to broadcast: ")? {
+            return Err(CliError::Aborted);
+        }
+    }
+
+    let hash = blockchain.submit_transaction(&tx)?;
+    db.save_pending_transaction(&json!(tx), &args.from);
+    preview["hash"] = json!(hash);
+    preview["broadcast"] = json!(true);
+    out.emit(&preview, || format!("Broadcast {}", hash))
+}
+
+fn run_mine(command: MineCommand, bills: BillRegistry, endpoint: &str, out: &mut Output) -> CliResult {
+    let benchmark = matches!(command, MineCommand::Benchmark { .. });
+    let progress = Arc::new(out.reporter(match &command {
+        MineCommand::Benchmark { seconds, .. } => ProgressReporter::bar("Benchmark", *seconds),
+        _ => ProgressReporter::spinner("Mining").with_unit("H"),
+    }));
+    let reporter = Arc::clone(&progress);
+    let miner = Arc::new(GenesisMiner::new(None).with_progress_callback(Arc::new(move |p: &MiningProgress| {
+        // A benchmark's bar counts seconds, so its hashrate goes in the message
+        if benchmark {
+            reporter.set_message(&format!("{:.0} H/s", p.hashrate));
+            reporter.set(p.elapsed_secs as u64);
+        } else {
+            reporter.set(p.attempts);
+        }
+    })));
+    match command {
+        MineCommand::Bill { denomination, address, difficulty, threads } => {
+            let genesis = GTXGenesis::from_registry(bills);
+            if !genesis.valid_denominations.contains(&denomination) {
+                return Err(CliError::Validation(format!("Invalid denomination: must be one of {:?}", genesis.valid_denominations)));
+            }
+            let difficulty = difficulty.unwrap_or_else(|| genesis.calculate_difficulty(denomination));
+            let bill = until_interrupted(&miner, &progress, || miner.mine_bill_parallel(denomination, &address, None, difficulty, threads));
+            let Some(bill) = bill else { return interrupted(&miner, out) };
+            let info = genesis.register_mined_bill(&bill, denomination, &address, difficulty).map_err(|e| format!("Cannot register bill: {}", e))?;
+            let result = json!({
+                "bill_serial": info.bill_serial,
+                "hash": info.hash,
+                "nonce": bill["nonce"],
+                "denomination": denomination,
+                "difficulty": difficulty,
+                "mining_time": info.mining_time,
+            });
+            out.emit(&result, || format!("Mined bill {} in {}\nHash: {}", info.bill_serial, human_duration(Duration::from_secs_f64(info.mining_time.max(0.0))), info.hash))
+        }
+        MineCommand::Block { address, difficulty } => {
+            let blockchain = Arc::new(BlockchainManager::new(endpoint, 1));
+            let syncing = out.reporter(ProgressReporter::spinner(&format!("Syncing with {}", endpoint)));
+            let synced = syncing.run(|| blockchain.sync_to_tip());
+            syncing.finish(synced.as_ref().ok().map(|blocks| format!("{} new blocks", blocks.len())).as_deref());
+            synced.map_err(|e| CliError::Network(format!("Cannot sync with {}: {}", endpoint, e)))?;
+            let tip = blockchain.cache.lock().unwrap().values().max_by_key(|b| b.index).cloned();
+            let difficulty = difficulty.or_else(|| tip.and_then(|b| b.difficulty).map(|d| d as u32)).unwrap_or(1);
+            let supervisor = Daemon::new()
+                .with_blockchain(Arc::clone(&blockchain))
+                .with_difficulty(Difficulty::new(difficulty))
+                .with_miner(GenesisMiner::new(None), MiningConfig { enabled: true, address, threads: 1 })
+                .mining_supervisor()
+                .ok_or_else(|| "Mining is not configured".to_string())?;
+            let mut template = supervisor.block_template().ok_or_else(|| format!("{} has no blocks to mine on", endpoint))?;
+            let block = until_interrupted(&miner, &progress, || miner.mine_block(&mut template, difficulty));
+            let Some(block) = block else { return interrupted(&miner, out) };
+            blockchain.submit_block(&block)?;
+            let result = json!({
+                "index": block["index"],
+                "hash": block["hash"],
+                "nonce": block["nonce"],
+                "difficulty": difficulty,
+                "mining_time": block["mining_time"],
+            });
+            let took = human_duration(Duration::from_secs_f64(block["mining_time"].as_f64().unwrap_or(0.0).max(0.0)));
+            out.emit(&result, || format!("Mined block {} in {}\nHash: {}", block["index"], took, str_field(&result, "hash")))
+        }
+        MineCommand::Benchmark { seconds, threads } => {
+            let report = until_interrupted(&miner, &progress, || miner.benchmark(Duration::from_secs(seconds), threads));
+            let result = json!(report);
+            let mut details = KeyValueBlock::new().with_color(out.color());
+            details
+                .add("Threads", report.threads)
+                .add("Attempts", report.attempts)
+                .add("Elapsed", human_duration(Duration::from_secs_f64(report.elapsed_secs)))
+                .add("Hashrate", format!("{:.0} H/s", report.hashrate));
+            out.emit(&result, || details.render())
+        }
+    }
+}
+
+/// Run `work`, stopping the miner if Ctrl-C is pressed meanwhile, then finish `progress`
+fn until_interrupted<T>(miner: &GenesisMiner, progress: &ProgressReporter, work: impl FnOnce() -> T) -> T {
+    let finished = AtomicBool::new(false);
+    let result = thread::scope(|scope| {
+        scope.spawn(|| {
+            while !finished.load(Ordering::SeqCst) {
+                if INTERRUPTED.load(Ordering::SeqCst) {
+                    miner.stop_mining();
+                    return;
+                }
+                thread::sleep(Duration::from_millis(50));
+            }
+        });
+        let result = work();
+        finished.store(true, Ordering::SeqCst);
+        result
+    });
+    progress.finish(None);
+    result
+}
+
+/// Print what was done before mining stopped
+fn interrupted(miner: &GenesisMiner, out: &mut Output) -> CliResult {
+    let stats = miner.get_mining_stats();
+    let partial = json!({"interrupted": true, "attempts": stats["total_hash_attempts"], "mining_time": stats["total_mining_time"]});
+    out.emit(&partial, || format!("Interrupted after {} attempts", stats["total_hash_attempts"]))?;
+    Err(CliError::Aborted)
+}
+
+fn run_history(args: HistoryArgs, db: &WalletDatabase, out: &mut Output) -> CliResult {
+    find_wallet(db, &args.address)?;
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs();
+    let since = args.since.as_deref().map(|s| parse_since(s, now)).transpose().map_err(CliError::Usage)?;
+    let transactions: Vec<JsonValue> = db
+        .get_wallet_transactions(&args.address, u32::MAX as usize)
+        .into_iter()
+        .filter(|tx| args.tx_type.is_none_or(|t| t.matches(str_field(tx, "type"))))
+        .filter(|tx| since.is_none_or(|since| tx["timestamp"].as_f64().unwrap_or(0.0) >= since as f64))
+        .take(args.limit.unwrap_or(usize::MAX))
+        .collect();
+
+    let format = args.format.unwrap_or(if out.style.json { HistoryFormat::Json } else { HistoryFormat::Table });
+    let mut rendered = Vec::new();
+    let written = match format {
+        // Colors only when the table goes straight to a terminal
+        HistoryFormat::Table => writeln!(rendered, "{}", history_table(&transactions, now, out.color() && args.output.is_none())),
+        HistoryFormat::Json => writeln!(rendered, "{}", json!(transactions)),
+        HistoryFormat::Csv => write_csv(&mut rendered, &HISTORY_CSV_COLUMNS, transactions.iter().map(csv_row)).map(|_| ()),
+    };
+    written.map_err(|e| format!("Cannot render history: {}", e))?;
+    match &args.output {
+        Some(path) => {
+            let size = rendered.len() as u64;
+            fs::write(path, rendered).map_err(|e| format!("Cannot write {}: {}", path.display(), e))?;
+            let summary = json!({"output": path, "transactions": transactions.len(), "bytes": size});
+            out.emit(&summary, || format!("Wrote {} transactions to {} ({})", transactions.len(), path.display(), human_bytes(size)))
+        }
+        None => out.writer.write_all(&rendered).map_err(|e| CliError::Failed(format!("Cannot write output: {}", e))),
+    }
+}
+
+fn run_bills(command: BillsCommand, genesis: GTXGenesis, endpoint: &str, out: &mut Output) -> CliResult {
+    let registry = &genesis.bill_registry;
+    match command {
+        BillsCommand::List { address, status } => {
+            let mut bills = registry.get_user_bills(&address).map_err(|e| format!("Cannot read bills: {}", e))?;
+            bills.retain(|b| status.as_ref().is_none_or(|s| &b.status == s));
+            let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs();
+            let color = out.color();
+            out.emit(&json!(bills), || bills_table(&bills, now, color))
+        }
+        BillsCommand::Verify { serial, remote, strict } => {
+            let local = if strict { genesis.verify_bill_strict(&serial) } else { genesis.verify_bill(&serial) };
+            if local["valid"] != json!(true) {
+                return Err(CliError::Validation(format!("Bill {} is not valid: {}", serial, str_field(&local, "error"))));
+            }
+            let mut result = json!({"serial": serial, "valid": true, "method": local["verification_method"]});
+            if remote {
+                match genesis.verify_bill_remote(endpoint, &serial) {
+                    Ok(answer) if answer["valid"] == json!(false) => {
+                        return Err(CliError::Validation(format!("Bill {} was rejected by {}", serial, endpoint)));
+                    }
+                    Ok(_) => result["remote"] = json!(true),
+                    Err(e) if strict => return Err(e.into()),
+                    Err(e) => {
+                        out.warn(&format!("Could not verify {} remotely: {}", serial, e));
+                        result["remote"] = JsonValue::Null;
+                        result["remote_error"] = json!(e.to_string());
+                    }
+                }
+            }
+            out.emit(&result, || {
+                let remote = match result["remote"] {
+                    JsonValue::Bool(true) => ", confirmed by the endpoint",
+                    JsonValue::Null if remote => ", not confirmed remotely",
+                    _ => "",
+                };
+                format!("Bill {} is valid ({}){}", serial, str_field(&result, "method"), remote)
+            })
+        }
+        BillsCommand::Export { serial, output } => {
+            let bill = registry
+                .get_bill(&serial)
+                .map_err(|e| format!("Cannot read bills: {}", e))?
+                .ok_or_else(|| CliError::NotFound(format!("No bill with serial {}", serial)))?;
+            let certificate = serde_json::to_string_pretty(&bill).map_err(|e| e.to_string())?;
+            fs::write(&output, certificate).map_err(|e| format!("Cannot write {}: {}", output.display(), e))?;
+            out.emit(&json!({"serial": serial, "output": output}), || format!("Exported {} to {}", serial, output.display()))
+        }
+        BillsCommand::Import { file } => {
+            let text = fs::read_to_string(&file).map_err(|e| format!("Cannot read {}: {}", file.display(), e))?;
+            let bill: BillInfo = serde_json::from_str(&text).map_err(|e| format!("Invalid bill certificate {}: {}", file.display(), e))?;
+            let verified = genesis.verify_bill_info(&bill, true);
+            if verified["valid"] != json!(true) {
+                return Err(CliError::Validation(format!("Bill {} is not valid: {}", bill.bill_serial, str_field(&verified, "error"))));
+            }
+            if registry.get_bill(&bill.bill_serial).ok().flatten().is_some() {
+                return Err(format!("Bill {} is already registered", bill.bill_serial).into());
+            }
+            let serial = bill.bill_serial.clone();
+            registry.register_bill(bill).map_err(|e| format!("Cannot register bill: {}", e))?;
+            out.emit(&json!({"serial": serial, "imported": true}), || format!("Imported bill {}", serial))
+        }
+    }
+}
+
+fn run_daemon(command: DaemonCommand, out: &mut Output) -> CliResult {
+    match command {
+        DaemonCommand::Start { config: path, detach, pidfile } => {
+            let config = DaemonConfig::load(&path)?;
+            let pidfile = pidfile.unwrap_or_else(|| config.data_dir.join("daemon.pid"));
+            if detach {
+                let pid = spawn_detached(&path, &config, &pidfile)?;
+                let started = json!({"pid": pid, "pidfile": pidfile});
+                return out.emit(&started, || format!("Daemon started in the background (pid {})", pid));
+            }
+            let mut daemon = Daemon::from_config(&config)?;
+            daemon.start();
+            let api = match serve_api(&mut daemon, &config) {
+                Ok(api) => api,
+                Err(e) => {
+                    daemon.stop();
+                    return Err(format!("Cannot serve the daemon API: {}", e).into());
+                }
+            };
+            match api {
+                Some(addr) => out.note(&format!("Daemon running (pid {}), API on http://{}; press Ctrl-C to stop", process::id(), addr)),
+                None => out.note(&format!("Daemon running (pid {}); press Ctrl-C to stop", process::id())),
+            }
+            while !daemon.is_stopping() && !INTERRUPTED.load(Ordering::SeqCst) {
+                thread::sleep(Duration::from_millis(200));
+            }
+            let report = daemon.shutdown(Duration::from_secs(30));
+            // Only a pidfile written for this process is ours to remove
+            if fs::read_to_string(&pidfile).is_ok_and(|pid| pid.trim() == process::id().to_string()) {
+                let _ = fs::remove_file(&pidfile);
+            }
+            let stopped = json!({
+                "stopped": true,
+                "clean": report.clean,
+                "timed_out": report.timed_out,
+                "mempool_flushed": report.mempool_flushed,
+            });
+            out.emit(&stopped, || {
+                let mut text = format!("Daemon stopped; {} pending transactions saved", report.mempool_flushed);
+                if !report.is_clean() {
+                    text.push_str(&format!("\n⚠️ Did not stop in time: {}", report.timed_out.join(", ")));
+                }
+                text
+            })
+        }
+        DaemonCommand::Status { config } => {
+            let config = DaemonConfig::load(&config)?;
+            let status = daemon_request(&config, reqwest::Method::GET, "/status")?;
+            let color = out.color();
+            out.emit(&status, || daemon_status_text(&status, color))
+        }
+        DaemonCommand::Peers { config } => {
+            let config = DaemonConfig::load(&config)?;
+            let peers = daemon_request(&config, reqwest::Method::GET, "/peers")?["peers"].take();
+            let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs();
+            let color = out.color();
+            out.emit(&peers, || peers_table(peers.as_array().map_or(&[], |p| p.as_slice()), now, color))
+        }
+        DaemonCommand::Stop { config: path, pidfile } => {
+            let config = DaemonConfig::load(&path)?;
+            let api_error = match daemon_request(&config, reqwest::Method::POST, "/shutdown") {
+                Ok(_) => return out.emit(&json!({"stopped": true, "method": "api"}), || "Daemon is shutting down".to_string()),
+                Err(e) => e.to_string(),
+            };
+            let pidfile = pidfile.unwrap_or_else(|| config.data_dir.join("daemon.pid"));
+            let pid: u32 = fs::read_to_string(&pidfile)
+                .ok()
+                .and_then(|pid| pid.trim().parse().ok())
+                .ok_or_else(|| CliError::Network(format!("{}; no pid in {} to signal instead", api_error, pidfile.display())))?;
+            out.warn(&format!("{}; signalling pid {} instead", api_error, pid));
+            terminate(pid)?;
+            let stopped = json!({"stopped": true, "method": "signal", "pid": pid, "api_error": api_error});
+            out.emit(&stopped, || format!("Sent SIGTERM to daemon process {}", pid))
+        }
+    }
+}
+
+/// Re-run `daemon start` in a new process group, recording its pid in `pidfile`
+fn spawn_detached(config_path: &Path, config: &DaemonConfig, pidfile: &Path) -> Result<u32, String> {
+    let config_path = fs::canonicalize(config_path).map_err(|e| format!("Cannot read {}: {}", config_path.display(), e))?;
+    fs::create_dir_all(&config.data_dir).map_err(|e| format!("Cannot create {}: {}", config.data_dir.display(), e))?;
+    let log_path = config.data_dir.join("daemon.log");
+    let log = fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&log_path)
+        .map_err(|e| format!("Cannot open {}: {}", log_path.display(), e))?;
+    let log_err = log.try_clone().map_err(|e| e.to_string())?;
+    let exe = std::env::current_exe().map_err(|e| format!("Cannot find the luna-wallet executable: {}", e))?;
+    let mut command = process::Command::new(exe);
+    command
+        .args(["daemon", "start", "--config"])
+        .arg(&config_path)
+        .arg("--pidfile")
+        .arg(pidfile)
+        .stdin(process::Stdio::null())
+        .stdout(log)
+        .stderr(log_err);
+    // Keep the terminal's Ctrl-C from reaching the background daemon
+    #[cfg(unix)]
+    std::os::unix::process::CommandExt::process_group(&mut command, 0);
+    let child = command.spawn().map_err(|e| format!("Cannot start the daemon: {}", e))?;
+    fs::write(pidfile, child.id().to_string()).map_err(|e| format!("Cannot write {}: {}", pidfile.display(), e))?;
+    Ok(child.id())
+}
+
+#[cfg(feature = "daemon-server")]
+fn serve_api(daemon: &mut Daemon, config: &DaemonConfig) -> Result<Option<std::net::SocketAddr>, String> {
+    config.api_bind.as_deref().map(|bind| daemon.serve(bind)).transpose()
+}
+
+#[cfg(not(feature = "daemon-server"))]
+fn serve_api(_daemon: &mut Daemon, config: &DaemonConfig) -> Result<Option<std::net::SocketAddr>, String> {
+    match config.api_bind {
+        Some(_) => Err("api_bind is set but luna-wallet was built without the daemon-server feature".to_string()),
+        None => Ok(None),
+    }
+}
+
+/// Call the API at the config's `api_bind`, authorized with its `auth_token` when there is one
+fn daemon_request(config: &DaemonConfig, method: reqwest::Method, path: &str) -> CliResult<JsonValue> {
+    let bind = config.api_bind.as_deref().ok_or_else(|| CliError::Failed("The daemon config has no api_bind".to_string()))?;
+    let mut request = reqwest::blocking::Client::new()
+        .request(method, format!("http://{}{}", bind, path))
+        .timeout(Duration::from_secs(10));
+    if let Some(token) = &config.auth_token {
+        request = request.bearer_auth(token);
+    }
+    let response = request.send().map_err(|e| CliError::Network(format!("Daemon API at {} is unreachable: {}", bind, e)))?;
+    let status = response.status();
+    let body: JsonValue = response.json().unwrap_or(JsonValue::Null);
+    if !status.is_success() {
+        let reason = body["error"].as_str().unwrap_or("no reason given").to_string();
+        return Err(CliError::Network(format!("Daemon API refused {} with HTTP {}: {}", path, status.as_u16(), reason)));
+    }
+    Ok(body)
+}
+
+#[cfg(unix)]
+fn terminate(pid: u32) -> CliResult {
+    // SAFETY: kill only sends a signal and reads no memory
+    if unsafe { libc::kill(pid as libc::pid_t, libc::SIGTERM) } != 0 {
+        return Err(format!("Cannot signal daemon process {}: {}", pid, io::Error::last_os_error()).into());
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn terminate(pid: u32) -> CliResult {
+    Err(format!("Cannot signal daemon process {}: only supported on Unix", pid).into())
+}
+
+fn daemon_status_text(status: &JsonValue, color: bool) -> String {
+    let stats = &status["stats"];
+    let height = status["chain_height"].as_u64().map_or("unknown".to_string(), |h| h.to_string());
+    let subsystems: Vec<String> = status["subsystems"]
+        .as_array()
+        .map_or(&[][..], |s| s.as_slice())
+        .iter()
+        .map(|s| format!("{} ({})", str_field(s, "name"), str_field(s, "state")))
+        .collect();
+    let mut details = KeyValueBlock::new().with_color(color);
+    details
+        .add("Health", str_field(status, "health"))
+        .add("Uptime", human_duration(Duration::from_secs(stats["uptime_secs"].as_u64().unwrap_or(0))))
+        .add("Chain height", height)
+        .add("Peers", format!("{} ({} active)", status["peer_count"], stats["peers_active"]))
+        .add("Mempool", format!("{} transactions", stats["mempool_size"]))
+        .add("Blocks", format!("{} validated, {} rejected, {} mined", stats["blocks_validated"], stats["blocks_rejected"], stats["blocks_mined"]))
+        .add("Subsystems", subsystems.join(", "));
+    details.render()
+}
+
+fn peers_table(peers: &[JsonValue], now: u64, color: bool) -> String {
+    if peers.is_empty() {
+        return "No peers".to_string();
+    }
+    let mut table = Table::new().with_color(color);
+    table.add_column("NODE ID", Align::Left).add_column("URL", Align::Left).add_column("VERSION", Align::Left).add_column("LAST SEEN", Align::Left);
+    for peer in peers {
+        table.add_row([
+            str_field(peer, "node_id").to_string(),
+            str_field(peer, "url").to_string(),
+            str_field(peer, "version").to_string(),
+            relative_time(peer["last_seen"].as_u64().unwrap_or(0), now),
+        ]);
+    }
+    table.render()
+}
+
+fn bills_table(bills: &[BillInfo], now: u64, color: bool) -> String {
+    if bills.is_empty() {
+        return "No bills".to_string();
+    }
+    let mut table = Table::new().with_color(color);
+    table
+        .add_column("SERIAL", Align::Left)
+        .add_column("DENOMINATION", Align::Right)
+        .add_column("STATUS", Align::Left)
+        .add_column("MINED", Align::Left)
+        .add_column_with_width("HASH", Align::Left, 16);
+    for bill in bills {
+        table.add_row([bill.bill_serial.clone(), bill.denomination.to_string(), bill.status.clone(), relative_time(bill.timestamp as u64, now), bill.hash.clone()]);
+    }
+    table.render()
+}
+
+/// Unix time for YYYY-MM-DD (midnight UTC) or an age like 7d before `now`
+fn parse_since(since: &str, now: u64) -> Result<u64, String> {
+    let invalid = || format!("Invalid --since '{}': expected YYYY-MM-DD or an age such as 7d", since);
+    if let Ok(date) = chrono::NaiveDate::parse_from_str(since, "%Y-%m-%d") {
+        return Ok(date.and_hms_opt(0, 0, 0).ok_or_else(invalid)?.and_utc().timestamp().max(0) as u64);
+    }
+    let age = parse_duration(since).map_err(|_| invalid())?;
+    Ok(now.saturating_sub(age.as_secs()))
+}
+
+fn history_table(transactions: &[JsonValue], now: u64, color: bool) -> String {
+    if transactions.is_empty() {
+        return "No transactions".to_string();
+    }
+    let mut table = Table::new().with_color(color);
+    table
+        .add_column("TIME", Align::Left)
+        .add_column("TYPE", Align::Left)
+        .add_column_with_width("HASH", Align::Left, 12)
+        .add_column("FROM", Align::Left)
+        .add_column("TO", Align::Left)
+        .add_column("AMOUNT", Align::Right)
+        .add_column("FEE", Align::Right)
+        .add_column("STATUS", Align::Left);
+    for tx in transactions {
+        table.add_row([
+            relative_time(tx["timestamp"].as_f64().unwrap_or(0.0) as u64, now),
+            str_field(tx, "type").to_string(),
+            str_field(tx, "hash").to_string(),
+            str_field(tx, "from").to_string(),
+            str_field(tx, "to").to_string(),
+            human_amount(tx["amount"].as_f64().unwrap_or(0.0)),
+            human_amount(tx["fee"].as_f64().unwrap_or(0.0)),
+            tx["status"].as_str().unwrap_or("confirmed").to_string(),
+        ]);
+    }
+    table.render()
+}
+
+fn csv_row(tx: &JsonValue) -> Vec<String> {
+    HISTORY_CSV_COLUMNS
+        .iter()
+        .map(|&column| match (column, &tx[column]) {
+            ("status", JsonValue::Null) => "confirmed".to_string(),
+            (_, JsonValue::String(s)) => s.clone(),
+            (_, JsonValue::Null) => String::new(),
+            (_, other) => other.to_string(),
+        })
+        .collect()
+}
+
+fn find_wallet(db: &WalletDatabase, address: &str) -> CliResult<JsonValue> {
+    db.load_wallet(address).map(|w| w.to_json()).ok_or_else(|| CliError::NotFound(format!("No wallet with address {}", address)))
+}
+
+/// A stored wallet without its encrypted key
+fn summary(wallet: &JsonValue) -> JsonValue {
+    json!({
+        "address": wallet["address"],
+        "label": wallet["label"],
+        "public_key": wallet["public_key"],
+        "balance": wallet["balance"],
+        "created": wallet["created"],
+        "kind": wallet.get("kind").unwrap_or(&json!("full")),
+    })
+}
+
+fn str_field<'a>(value: &'a JsonValue, key: &str) -> &'a str {
+    value[key].as_str().unwrap_or("")
+}
+
+fn read_password_file(path: &Path) -> Result<String, String> {
+    let text = fs::read_to_string(path).map_err(|e| format!("Cannot read {}: {}", path.display(), e))?;
+    Ok(text.lines().next().unwrap_or("").to_string())
+}
+
+/// Whether the user typed exactly "yes"
+fn confirm(prompt: &str) -> Result<bool, String> {
+    eprint!("{}", prompt);
+    let _ = io::stderr().flush();
+    let mut line = String::new();
+    io::stdin().lock().read_line(&mut line).map_err(|e| format!("Cannot read confirmation: {}", e))?;
+    Ok(line.trim() == "yes")
+}
+
+fn prompt_new_password() -> Result<String, String> {
+    let password = prompt_password("Password: ")?;
+    if prompt_password("Repeat password: ")? != password {
+        return Err("Passwords do not match".to_string());
+    }
+    Ok(password)
+}
+
+/// Read a line from stdin with terminal echo turned off
+fn prompt_password(prompt: &str) -> Result<String, String> {
+    eprint!("{}", prompt);
+    let _ = io::stderr().flush();
+    let echo = EchoOff::new();
+    let mut line = String::new();
+    let read = io::stdin().lock().read_line(&mut line);
+    drop(echo);
+    eprintln!();
+    read.map_err(|e| format!("Cannot read password: {}", e))?;
+    Ok(line.trim_end_matches(['\r', '\n']).to_string())
+}
+
+/// Restores the terminal's echo setting when dropped
+#[cfg(unix)]
+struct EchoOff(Option<libc::termios>);
+
+#[cfg(unix)]
+impl EchoOff {
+    fn new() -> Self {
+        // SAFETY: termios is plain data, and tcgetattr fully initializes it on success
+        unsafe {
+            let mut term: libc::termios = std::mem::zeroed();
+            if libc::tcgetattr(libc::STDIN_FILENO, &mut term) != 0 {
+                return EchoOff(None);
+            }
+            let saved = term;
+            term.c_lflag &= !libc::ECHO;
+            libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &term);
+            EchoOff(Some(saved))
+        }
+    }
+}
+
+#[cfg(unix)]
+impl Drop for EchoOff {
+    fn drop(&mut self) {
+        if let Some(saved) = &self.0 {
+            // SAFETY: restores settings tcgetattr returned
+            unsafe {
+                libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, saved);
+            }
+        }
+    }
+}
+
+/// Echo cannot be turned off here; use `--password-file` on shared terminals
+#[cfg(not(unix))]
+struct EchoOff;
+
+#[cfg(not(unix))]
+impl EchoOff {
+    fn new() -> Self {
+        EchoOff
     }
 }
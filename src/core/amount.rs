@@ -0,0 +1,184 @@
+//! Exact money arithmetic: amounts are whole atomic units, 1 LUN being `UNITS_PER_LUN` of them
+use std::fmt;
+use std::str::FromStr;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+/// Atomic units in one LUN
+pub const UNITS_PER_LUN: i128 = 100_000_000;
+
+/// Decimal places a LUN amount can have
+pub const DECIMALS: usize = 8;
+
+/// A signed number of atomic units. It serializes as a decimal LUN string such as `"100.1"`
+/// and also reads plain JSON numbers, so float amounts from node endpoints still load.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct Amount(i128);
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AmountError {
+    /// The result does not fit in an `Amount`
+    Overflow,
+    /// Not a decimal LUN amount with at most eight places
+    Invalid(String),
+}
+
+impl fmt::Display for AmountError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AmountError::Overflow => write!(f, "Amount overflowed"),
+            AmountError::Invalid(text) => write!(f, "Invalid amount '{}': expected LUN with at most {} decimal places", text, DECIMALS),
+        }
+    }
+}
+
+impl std::error::Error for AmountError {}
+
+impl Amount {
+    pub const ZERO: Amount = Amount(0);
+
+    pub const fn from_units(units: i128) -> Self {
+        Amount(units)
+    }
+
+    pub const fn units(self) -> i128 {
+        self.0
+    }
+
+    /// The nearest whole unit to `lun`; NaN is zero and values beyond the range saturate
+    pub fn from_f64_lossy(lun: f64) -> Self {
+        Amount((lun * UNITS_PER_LUN as f64).round() as i128)
+    }
+
+    /// A JSON amount as node endpoints send it, a number or a decimal string; anything else is zero
+    pub fn from_json_lossy(value: &serde_json::Value) -> Self {
+        match value {
+            serde_json::Value::String(text) => text.parse().unwrap_or(Amount::ZERO),
+            other => Amount::from_f64_lossy(other.as_f64().unwrap_or(0.0)),
+        }
+    }
+
+    /// For display and interop only; arithmetic should stay in units
+    pub fn to_f64_lossy(self) -> f64 {
+        self.0 as f64 / UNITS_PER_LUN as f64
+    }
+
+    pub fn checked_add(self, other: Amount) -> Option<Amount> {
+        self.0.checked_add(other.0).map(Amount)
+    }
+
+    pub fn checked_sub(self, other: Amount) -> Option<Amount> {
+        self.0.checked_sub(other.0).map(Amount)
+    }
+
+    /// `checked_add` that reports overflow as an `AmountError`
+    pub fn try_add(self, other: Amount) -> Result<Amount, AmountError> {
+        self.checked_add(other).ok_or(AmountError::Overflow)
+    }
+
+    /// `checked_sub` that reports overflow as an `AmountError`
+    pub fn try_sub(self, other: Amount) -> Result<Amount, AmountError> {
+        self.checked_sub(other).ok_or(AmountError::Overflow)
+    }
+}
+
+/// LUN with trailing zeros dropped: `100.1`, `0.00000001`, `-5`
+impl fmt::Display for Amount {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let sign = if self.0 < 0 { "-" } else { "" };
+        let units = self.0.unsigned_abs();
+        let whole = units / UNITS_PER_LUN as u128;
+        let fraction = units % UNITS_PER_LUN as u128;
+        if fraction == 0 {
+            return write!(f, "{}{}", sign, whole);
+        }
+        let digits = format!("{:0width$}", fraction, width = DECIMALS);
+        write!(f, "{}{}.{}", sign, whole, digits.trim_end_matches('0'))
+    }
+}
+
+impl FromStr for Amount {
+    type Err = AmountError;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let invalid = || AmountError::Invalid(text.to_string());
+        let (negative, digits) = match text.trim().strip_prefix('-') {
+            Some(rest) => (true, rest),
+            None => (false, text.trim()),
+        };
+        let (whole, fraction) = digits.split_once('.').unwrap_or((digits, ""));
+        let is_digits = |s: &str| s.bytes().all(|b| b.is_ascii_digit());
+        if (whole.is_empty() && fraction.is_empty()) || !is_digits(whole) || !is_digits(fraction) || fraction.len() > DECIMALS {
+            return Err(invalid());
+        }
+        let whole: i128 = if whole.is_empty() { 0 } else { whole.parse().map_err(|_| AmountError::Overflow)? };
+        let fraction: i128 = format!("{:0<width$}", fraction, width = DECIMALS).parse().map_err(|_| invalid())?;
+        let units = whole.checked_mul(UNITS_PER_LUN).and_then(|u| u.checked_add(fraction)).ok_or(AmountError::Overflow)?;
+        Ok(Amount(if negative { -units } else { units }))
+    }
+}
+
+impl Serialize for Amount {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for Amount {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Repr {
+            Text(String),
+            Number(f64),
+        }
+        match Repr::deserialize(deserializer)? {
+            Repr::Text(text) => text.parse().map_err(serde::de::Error::custom),
+            Repr::Number(lun) => Ok(Amount::from_f64_lossy(lun)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_display_and_parse() {
+        for (text, units) in [("100.1", 10_010_000_000), ("0.00000001", 1), ("-5", -500_000_000), ("0", 0), ("1.5", 150_000_000)] {
+            let amount: Amount = text.parse().unwrap();
+            assert_eq!(amount.units(), units, "{}", text);
+            assert_eq!(amount.to_string(), text);
+        }
+        assert_eq!(".5".parse::<Amount>().unwrap(), Amount::from_units(50_000_000));
+        assert_eq!("2.".parse::<Amount>().unwrap().to_string(), "2");
+        for bad in ["", ".", "1.000000001", "1,5", "abc", "--1", "1e8"] {
+            assert!(matches!(bad.parse::<Amount>(), Err(AmountError::Invalid(_))), "{}", bad);
+        }
+        assert_eq!("99999999999999999999999999999999999999".parse::<Amount>(), Err(AmountError::Overflow));
+    }
+
+    #[test]
+    fn test_exact_arithmetic() {
+        // 100.1 - 0.3 is 99.79999999999998 in f64
+        let balance = Amount::from_f64_lossy(100.1).try_sub(Amount::from_f64_lossy(0.3)).unwrap();
+        assert_eq!(balance.to_string(), "99.8");
+        let total = (0..10).try_fold(Amount::ZERO, |sum, _| sum.try_add(Amount::from_f64_lossy(0.1))).unwrap();
+        assert_eq!(total, Amount::from_units(UNITS_PER_LUN));
+        assert_eq!(Amount::from_units(i128::MAX).try_add(Amount::from_units(1)), Err(AmountError::Overflow));
+        assert_eq!(Amount::from_units(i128::MIN).checked_sub(Amount::from_units(1)), None);
+        assert_eq!(Amount::from_f64_lossy(f64::NAN), Amount::ZERO);
+    }
+
+    #[test]
+    fn test_serde() {
+        let amount = Amount::from_units(123_456_789);
+        assert_eq!(serde_json::to_value(amount).unwrap(), serde_json::json!("1.23456789"));
+        assert_eq!(serde_json::from_value::<Amount>(serde_json::json!("1.23456789")).unwrap(), amount);
+        assert_eq!(serde_json::from_value::<Amount>(serde_json::json!(1.5)).unwrap().to_string(), "1.5");
+        assert_eq!(serde_json::from_value::<Amount>(serde_json::json!(2)).unwrap().to_string(), "2");
+        assert!(serde_json::from_value::<Amount>(serde_json::json!("lots")).is_err());
+        assert_eq!(Amount::from_json_lossy(&serde_json::json!("0.1")), Amount::from_units(10_000_000));
+        assert_eq!(Amount::from_json_lossy(&serde_json::json!(null)), Amount::ZERO);
+    }
+}
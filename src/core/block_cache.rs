@@ -0,0 +1,230 @@
+use std::collections::BTreeMap;
+use std::ops::RangeInclusive;
+use serde::{Deserialize, Serialize};
+use crate::core::blockchain::Block;
+
+/// Blocks a `BlockchainManager` keeps before evicting the least recently used
+pub const DEFAULT_MAX_CACHED_BLOCKS: usize = 10_000;
+/// Blocks within this distance of the cached tip are never evicted
+pub const DEFAULT_PINNED_TIP_BLOCKS: usize = 100;
+
+/// Lookups through `BlockCache::get` and blocks evicted so far
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CacheStats {
+    pub hits: u64,
+    pub misses: u64,
+    pub evictions: u64,
+}
+
+/// Blocks by height, bounded by `max_blocks`. Past the limit the least recently inserted or
+/// looked-up block is evicted, except those in the pinned window of `pinned_tip` heights below the
+/// highest cached block. With only pinned blocks left, the cache may grow past the limit.
+#[derive(Debug, Clone)]
+pub struct BlockCache {
+    /// Each block with the tick of its last use
+    blocks: BTreeMap<u64, (Block, u64)>,
+    /// Tick to height, least recently used first
+    recency: BTreeMap<u64, u64>,
+    tick: u64,
+    max_blocks: usize,
+    pinned_tip: usize,
+    stats: CacheStats,
+}
+
+impl Default for BlockCache {
+    fn default() -> Self {
+        BlockCache::new(DEFAULT_MAX_CACHED_BLOCKS, DEFAULT_PINNED_TIP_BLOCKS)
+    }
+}
+
+impl BlockCache {
+    pub fn new(max_blocks: usize, pinned_tip: usize) -> Self {
+        BlockCache {
+            blocks: BTreeMap::new(),
+            recency: BTreeMap::new(),
+            tick: 0,
+            max_blocks,
+            pinned_tip,
+            stats: CacheStats::default(),
+        }
+    }
+
+    /// Change the limits, evicting right away if the cache is now over them
+    pub fn set_limits(&mut self, max_blocks: usize, pinned_tip: usize) {
+        self.max_blocks = max_blocks;
+        self.pinned_tip = pinned_tip;
+        self.evict();
+    }
+
+    pub fn max_blocks(&self) -> usize {
+        self.max_blocks
+    }
+
+    pub fn pinned_tip(&self) -> usize {
+        self.pinned_tip
+    }
+
+    /// Look up a block, counting a hit or miss and marking it recently used
+    pub fn get(&mut self, height: &u64) -> Option<&Block> {
+        if !self.blocks.contains_key(height) {
+            self.stats.misses += 1;
+            return None;
+        }
+        self.stats.hits += 1;
+        self.touch(*height);
+        self.blocks.get(height).map(|(block, _)| block)
+    }
+
+    /// Look up a block without counting it or changing the eviction order
+    pub fn peek(&self, height: &u64) -> Option<&Block> {
+        self.blocks.get(height).map(|(block, _)| block)
+    }
+
+    pub fn contains_key(&self, height: &u64) -> bool {
+        self.blocks.contains_key(height)
+    }
+
+    /// Cache `block` at `height`, returning the block it replaced
+    pub fn insert(&mut self, height: u64, block: Block) -> Option<Block> {
+        let replaced = self.blocks.insert(height, (block, 0)).map(|(block, tick)| {
+            self.recency.remove(&tick);
+            block
+        });
+        self.touch(height);
+        self.evict();
+        replaced
+    }
+
+    /// Cache each block at its own `index`
+    pub fn extend<I: IntoIterator<Item = Block>>(&mut self, blocks: I) {
+        for block in blocks {
+            self.insert(block.index, block);
+        }
+    }
+
+    pub fn retain<F: FnMut(&u64, &Block) -> bool>(&mut self, mut keep: F) {
+        let recency = &mut self.recency;
+        self.blocks.retain(|height, (block, tick)| {
+            let kept = keep(height, block);
+            if !kept {
+                recency.remove(tick);
+            }
+            kept
+        });
+    }
+
+    /// Drop every block; the stats are kept
+    pub fn clear(&mut self) {
+        self.blocks.clear();
+        self.recency.clear();
+    }
+
+    pub fn len(&self) -> usize {
+        self.blocks.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.blocks.is_empty()
+    }
+
+    /// The highest cached height
+    pub fn tip(&self) -> Option<u64> {
+        self.blocks.keys().next_back().copied()
+    }
+
+    /// Cached heights in ascending order
+    pub fn keys(&self) -> impl DoubleEndedIterator<Item = &u64> {
+        self.blocks.keys()
+    }
+
+    /// Cached blocks in height order
+    pub fn values(&self) -> impl DoubleEndedIterator<Item = &Block> {
+        self.blocks.values().map(|(block, _)| block)
+    }
+
+    /// Cached blocks within `heights`, in height order, without counting them
+    pub fn range(&self, heights: RangeInclusive<u64>) -> impl DoubleEndedIterator<Item = &Block> {
+        let (start, end) = heights.into_inner();
+        self.blocks.range(start..=end.max(start)).filter(move |(height, _)| **height <= end).map(|(_, (block, _))| block)
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        self.stats
+    }
+
+    fn touch(&mut self, height: u64) {
+        let Some((_, tick)) = self.blocks.get_mut(&height) else { return };
+        self.recency.remove(tick);
+        self.tick += 1;
+        *tick = self.tick;
+        self.recency.insert(self.tick, height);
+    }
+
+    fn evict(&mut self) {
+        while self.blocks.len() > self.max_blocks {
+            let Some(tip) = self.tip() else { return };
+            let unpinned = |height: u64| tip - height >= self.pinned_tip as u64;
+            let Some((&tick, &height)) = self.recency.iter().find(|(_, height)| unpinned(**height)) else { return };
+            self.recency.remove(&tick);
+            self.blocks.remove(&height);
+            self.stats.evictions += 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn block(index: u64) -> Block {
+        Block { index, hash: format!("h{}", index), ..Block::new() }
+    }
+
+    fn heights(cache: &BlockCache) -> Vec<u64> {
+        cache.keys().copied().collect()
+    }
+
+    #[test]
+    fn test_evicts_least_recently_used() {
+        let mut cache = BlockCache::new(3, 0);
+        cache.extend((0..3).map(block));
+        assert_eq!(cache.get(&0).unwrap().hash, "h0");
+        cache.insert(3, block(3));
+        assert_eq!(heights(&cache), vec![0, 2, 3]);
+        // Peeking does not protect a block
+        cache.peek(&2);
+        cache.insert(4, block(4));
+        assert_eq!(heights(&cache), vec![0, 3, 4]);
+        // Replacing a block counts as using it
+        cache.insert(0, block(0));
+        cache.insert(5, block(5));
+        assert_eq!(heights(&cache), vec![0, 4, 5]);
+        assert!(cache.get(&1).is_none());
+        assert_eq!(cache.stats(), CacheStats { hits: 1, misses: 1, evictions: 3 });
+
+        cache.clear();
+        assert!(cache.is_empty() && cache.tip().is_none());
+        cache.extend((10..15).map(block));
+        assert_eq!(heights(&cache), vec![12, 13, 14]);
+        assert_eq!(cache.stats().evictions, 5);
+    }
+
+    #[test]
+    fn test_pinned_tip_is_never_evicted() {
+        let mut cache = BlockCache::new(4, 2);
+        cache.extend([3, 0, 1, 2].map(block));
+        // Block 3 is the least recently used but inside the window, so block 0 goes instead
+        cache.insert(4, block(4));
+        assert_eq!(heights(&cache), vec![1, 2, 3, 4]);
+        cache.set_limits(1, 2);
+        assert_eq!(heights(&cache), vec![3, 4]);
+        assert_eq!(cache.len(), 2);
+
+        cache.retain(|height, _| *height != 4);
+        assert_eq!(cache.tip(), Some(3));
+        cache.insert(9, block(9));
+        assert_eq!(heights(&cache), vec![9]);
+        assert_eq!(cache.range(0..=9).map(|b| b.index).collect::<Vec<_>>(), vec![9]);
+        assert_eq!(cache.range(RangeInclusive::new(9, 0)).count(), 0);
+    }
+}
@@ -1,8 +1,21 @@
+use std::fmt;
 use std::sync::{Arc, Mutex};
 use std::thread;
+use std::time::{Duration, Instant};
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
+use serde_json::Value as JsonValue;
+use sha2::{Digest, Sha256};
+use crate::core::block_cache::{BlockCache, CacheStats};
+use crate::core::crypto::Crypto;
+use crate::core::wallet::{derives_address, LunaWallet};
+use crate::core::wallet_manager::Transaction as WalletTransaction;
+use crate::mining::difficulty::Difficulty;
+use crate::transactions::transactions::TransactionManager;
+use crate::utils::clock::{Clock, SystemClock};
+use crate::utils::log::{debug, info, warn};
 
+/// Optional fields the node leaves out stay out when the block is serialized again
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
 pub struct Block {
     pub index: u64,
@@ -10,21 +23,71 @@ pub struct Block {
     pub previous_hash: String,
     pub timestamp: u64,
     pub transactions: Vec<Transaction>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub miner: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub difficulty: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub nonce: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub merkle_root: Option<String>,
+    /// Every other field, kept so `recompute_block_hash` hashes the block as it was mined
+    #[serde(flatten)]
+    pub extra: serde_json::Map<String, JsonValue>,
     // ...他のフィールドも必要に応じて追加
 }
 
+/// A block without its transactions, enough to follow the chain's heights and hashes
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Default)]
+pub struct BlockHeader {
+    pub index: u64,
+    pub hash: String,
+    pub previous_hash: String,
+    pub timestamp: u64,
+    pub difficulty: Option<u64>,
+    pub miner: Option<String>,
+    #[serde(default)]
+    pub tx_count: usize,
+}
+
+impl From<&Block> for BlockHeader {
+    fn from(block: &Block) -> Self {
+        BlockHeader {
+            index: block.index,
+            hash: block.hash.clone(),
+            previous_hash: block.previous_hash.clone(),
+            timestamp: block.timestamp,
+            difficulty: block.difficulty,
+            miner: block.miner.clone(),
+            tx_count: block.transactions.len(),
+        }
+    }
+}
+
+/// Any field the node leaves out is `None`, and stays out when the transaction is serialized again
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
 pub struct Transaction {
+    #[serde(rename = "type", alias = "tx_type", skip_serializing_if = "Option::is_none")]
     pub tx_type: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub from: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub to: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub amount: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fee: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub timestamp: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub hash: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub signature: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub memo: Option<String>,
+    /// Every other field, such as `public_key`
+    #[serde(flatten)]
+    pub extra: serde_json::Map<String, JsonValue>,
     // ...他のフィールドも必要に応じて追加
 }
 
@@ -35,12 +98,41 @@ impl Transaction {
             from: None,
             to: None,
             amount: None,
+            fee: None,
             timestamp: None,
             hash: None,
             signature: None,
+            memo: None,
+            extra: serde_json::Map::new(),
             // ...他のフィールドも必要に応じて追加
         }
     }
+
+    /// A transaction built by `TransactionManager`; fields without their own member go to `extra`
+    pub fn from_json(tx: &HashMap<String, JsonValue>) -> Self {
+        const MEMBERS: [&str; 9] = ["type", "from", "to", "amount", "fee", "timestamp", "hash", "signature", "memo"];
+        let text = |key: &str| tx.get(key).and_then(|v| v.as_str()).map(String::from);
+        Transaction {
+            tx_type: text("type"),
+            from: text("from"),
+            to: text("to"),
+            amount: tx.get("amount").and_then(|v| v.as_f64()),
+            fee: tx.get("fee").and_then(|v| v.as_f64()),
+            timestamp: tx.get("timestamp").and_then(|v| v.as_u64()),
+            hash: text("hash"),
+            signature: text("signature"),
+            memo: text("memo"),
+            extra: tx.iter().filter(|(key, _)| !MEMBERS.contains(&key.as_str())).map(|(key, value)| (key.clone(), value.clone())).collect(),
+        }
+    }
+
+    /// The field map `TransactionManager` hashes and signs
+    pub fn to_json(&self) -> HashMap<String, JsonValue> {
+        match serde_json::to_value(self) {
+            Ok(JsonValue::Object(fields)) => fields.into_iter().collect(),
+            _ => HashMap::new(),
+        }
+    }
 }
 
 impl Block {
@@ -54,32 +146,266 @@ impl Block {
             miner: None,
             difficulty: None,
             nonce: None,
+            merkle_root: None,
+            extra: serde_json::Map::new(),
             // ...他のフィールドも必要に応じて追加
         }
     }
 }
 
+/// Why a request to the blockchain endpoint failed
+#[derive(Debug, Clone, PartialEq)]
+pub enum BlockchainError {
+    /// The request could not be sent, e.g. connection refused
+    Network(String),
+    /// The endpoint answered with a non-success status other than 404
+    Http(u16),
+    /// A response or block could not be decoded
+    Decode(String),
+    /// The endpoint has nothing at that path (HTTP 404), e.g. a block above the tip
+    NotFound,
+    /// No answer within the request timeout
+    Timeout,
+    /// Some blocks of a range could not be fetched, even on retry, with the last error for each height
+    Incomplete(Vec<(u64, BlockchainError)>),
+    /// Fetched blocks failed `validate_chain_segment`
+    InvalidChain(ChainValidationError),
+}
+
+impl BlockchainError {
+    fn from_status(code: u16) -> Self {
+        match code {
+            404 => BlockchainError::NotFound,
+            code => BlockchainError::Http(code),
+        }
+    }
+
+    /// Whether the same request may succeed later: network failures, timeouts, 429 and 5xx
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            BlockchainError::Network(_) | BlockchainError::Timeout => true,
+            BlockchainError::Http(code) => *code == 429 || *code >= 500,
+            BlockchainError::Decode(_) | BlockchainError::NotFound | BlockchainError::InvalidChain(_) => false,
+            BlockchainError::Incomplete(failed) => failed.iter().all(|(_, e)| e.is_retryable()),
+        }
+    }
+}
+
+impl fmt::Display for BlockchainError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BlockchainError::Network(e) => write!(f, "Network error: {}", e),
+            BlockchainError::Http(code) => write!(f, "Endpoint returned HTTP {}", code),
+            BlockchainError::Decode(e) => write!(f, "Invalid response: {}", e),
+            BlockchainError::NotFound => write!(f, "Not found on the endpoint"),
+            BlockchainError::Timeout => write!(f, "Request timed out"),
+            BlockchainError::Incomplete(failed) => {
+                write!(f, "Could not fetch {} block(s):", failed.len())?;
+                for (height, e) in failed {
+                    write!(f, " {} ({})", height, e)?;
+                }
+                Ok(())
+            }
+            BlockchainError::InvalidChain(e) => write!(f, "Invalid chain: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for BlockchainError {}
+
+impl From<reqwest::Error> for BlockchainError {
+    fn from(e: reqwest::Error) -> Self {
+        if e.is_timeout() {
+            BlockchainError::Timeout
+        } else if e.is_decode() {
+            BlockchainError::Decode(e.to_string())
+        } else if let Some(status) = e.status() {
+            BlockchainError::from_status(status.as_u16())
+        } else {
+            BlockchainError::Network(e.to_string())
+        }
+    }
+}
+
+/// The first problem `validate_chain_segment` found, at the height of the offending block
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ChainValidationError {
+    /// The block's index does not follow the block before it
+    Gap { expected: u64, found: u64 },
+    /// `previous_hash` is not the hash of the block before it
+    BrokenLink { height: u64 },
+    /// Older than the block before it by more than the timestamp tolerance
+    TimestampRegression { height: u64 },
+    /// The stored hash is not the block's recomputed hash, so the block was altered
+    HashMismatch { height: u64 },
+    /// The hash does not meet the block's own difficulty
+    InsufficientWork { height: u64 },
+}
+
+impl ChainValidationError {
+    pub fn height(&self) -> u64 {
+        match self {
+            ChainValidationError::Gap { found, .. } => *found,
+            ChainValidationError::BrokenLink { height }
+            | ChainValidationError::TimestampRegression { height }
+            | ChainValidationError::HashMismatch { height }
+            | ChainValidationError::InsufficientWork { height } => *height,
+        }
+    }
+}
+
+impl fmt::Display for ChainValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ChainValidationError::Gap { expected, found } => write!(f, "expected block {}, got {}", expected, found),
+            ChainValidationError::BrokenLink { height } => write!(f, "block {} does not link to its parent", height),
+            ChainValidationError::TimestampRegression { height } => write!(f, "block {} is older than its parent", height),
+            ChainValidationError::HashMismatch { height } => write!(f, "block {} does not match its hash", height),
+            ChainValidationError::InsufficientWork { height } => write!(f, "block {} does not meet its difficulty", height),
+        }
+    }
+}
+
+impl std::error::Error for ChainValidationError {}
+
+/// Source of blocks by height
+pub trait BlockLookup {
+    fn block_at(&self, height: u64) -> Option<Block>;
+}
+
+/// How long a blocking request to the endpoint may take by default
+pub const DEFAULT_REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+/// How long `check_network_connection` waits for the endpoint by default
+pub const DEFAULT_HEALTH_TIMEOUT: Duration = Duration::from_secs(5);
+/// How far a block's timestamp may fall behind its parent's by default
+pub const DEFAULT_TIMESTAMP_TOLERANCE: Duration = Duration::from_secs(2 * 60 * 60);
+
+/// Blocks `get_blocks_range` found in the cache, and the heights it did not
+#[derive(Debug, Clone, Default)]
+pub struct CachedRange {
+    /// In height order
+    pub blocks: Vec<Block>,
+    pub missing: Vec<u64>,
+}
+
+/// The outcome of the last `check_network_connection`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ConnectionStatus {
+    pub connected: bool,
+    /// Round trip of the answering request; `None` when nothing answered
+    pub latency_ms: Option<u64>,
+    pub endpoint: String,
+    /// Unix seconds
+    pub last_checked: u64,
+}
+
 pub struct BlockchainManager {
     pub endpoint_url: String,
+    /// Per-request timeout for the blocking calls
+    pub timeout: Duration,
+    /// Timeout for `check_network_connection`
+    pub health_timeout: Duration,
     pub network_connected: bool,
-    pub cache: Arc<Mutex<HashMap<u64, Block>>>,
+    /// Upper bound on simultaneous requests in `fetch_blocks_range`
+    pub max_workers: usize,
+    /// Run `validate_chain_segment` over what `fetch_blocks_range` returns
+    pub validate_fetched: bool,
+    /// How far a block's timestamp may fall behind its parent's
+    pub timestamp_tolerance: Duration,
+    connection_status: Option<ConnectionStatus>,
+    /// Bounded by `with_max_cached_blocks` and `with_pinned_tip_blocks`
+    pub cache: Arc<Mutex<BlockCache>>,
+    /// Headers from `get_block_headers`, by height
+    pub header_cache: Arc<Mutex<BTreeMap<u64, BlockHeader>>>,
     pub async_tasks: Arc<Mutex<HashMap<String, thread::JoinHandle<()>>>>,
     pub task_results: Arc<Mutex<HashMap<String, String>>>,
     pub stop_events: Arc<Mutex<Vec<Arc<Mutex<bool>>>>>,
 }
 
+impl BlockLookup for BlockchainManager {
+    /// Cached blocks only; no network request is made
+    fn block_at(&self, height: u64) -> Option<Block> {
+        self.cache.lock().unwrap().get(&height).cloned()
+    }
+}
+
 impl BlockchainManager {
-    pub fn new(endpoint_url: &str, _max_workers: usize) -> Self {
+    pub fn new(endpoint_url: &str, max_workers: usize) -> Self {
         BlockchainManager {
             endpoint_url: endpoint_url.trim_end_matches('/').to_string(),
+            timeout: DEFAULT_REQUEST_TIMEOUT,
+            health_timeout: DEFAULT_HEALTH_TIMEOUT,
             network_connected: false,
-            cache: Arc::new(Mutex::new(HashMap::new())),
+            max_workers: max_workers.max(1),
+            validate_fetched: false,
+            timestamp_tolerance: DEFAULT_TIMESTAMP_TOLERANCE,
+            connection_status: None,
+            cache: Arc::new(Mutex::new(BlockCache::default())),
+            header_cache: Arc::new(Mutex::new(BTreeMap::new())),
             async_tasks: Arc::new(Mutex::new(HashMap::new())),
             task_results: Arc::new(Mutex::new(HashMap::new())),
             stop_events: Arc::new(Mutex::new(Vec::new())),
         }
     }
 
+    /// Per-request timeout for the blocking calls
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    pub fn with_health_timeout(mut self, timeout: Duration) -> Self {
+        self.health_timeout = timeout;
+        self
+    }
+
+    /// Validate fetched ranges before returning them; off by default
+    pub fn with_chain_validation(mut self, enabled: bool) -> Self {
+        self.validate_fetched = enabled;
+        self
+    }
+
+    pub fn with_timestamp_tolerance(mut self, tolerance: Duration) -> Self {
+        self.timestamp_tolerance = tolerance;
+        self
+    }
+
+    /// Cache at most `max` blocks, evicting the least recently used; the default is 10,000
+    pub fn with_max_cached_blocks(self, max: usize) -> Self {
+        let mut cache = self.cache.lock().unwrap();
+        let pinned = cache.pinned_tip();
+        cache.set_limits(max, pinned);
+        drop(cache);
+        self
+    }
+
+    /// Never evict blocks within `window` heights of the cached tip; the default is 100
+    pub fn with_pinned_tip_blocks(self, window: usize) -> Self {
+        let mut cache = self.cache.lock().unwrap();
+        let max = cache.max_blocks();
+        cache.set_limits(max, window);
+        drop(cache);
+        self
+    }
+
+    /// Hits and misses of cache lookups, and the blocks evicted so far
+    pub fn cache_stats(&self) -> CacheStats {
+        self.cache.lock().unwrap().stats()
+    }
+
+    /// Drop every cached block
+    pub fn clear_cache(&self) {
+        self.cache.lock().unwrap().clear();
+    }
+
+    fn client(&self) -> Result<reqwest::blocking::Client, BlockchainError> {
+        Ok(reqwest::blocking::Client::builder().timeout(self.timeout).build()?)
+    }
+
+    fn async_client(&self) -> Result<reqwest::Client, BlockchainError> {
+        Ok(reqwest::Client::builder().timeout(self.timeout).build()?)
+    }
+
     /// Normalize LUN addresses for comparison (lowercase, strip, drop prefix)
     pub fn normalize_address(addr: &str) -> String {
         if addr.is_empty() {
@@ -92,6 +418,78 @@ impl BlockchainManager {
         addr_str
     }
 
+    /// Proof-of-work hash over the key-sorted block, excluding `hash` and `mining_time`
+    pub fn calculate_block_hash(block: &HashMap<String, JsonValue>) -> String {
+        let header: serde_json::Map<String, JsonValue> = block
+            .iter()
+            .filter(|(k, _)| !matches!(k.as_str(), "hash" | "mining_time"))
+            .map(|(k, v)| (k.clone(), v.clone()))
+            .collect();
+        // serde_json::Map keeps keys sorted, so nested objects are canonical too
+        let json = serde_json::to_string(&JsonValue::Object(header)).unwrap();
+        format!("{:x}", Sha256::digest(json.as_bytes()))
+    }
+
+    /// `calculate_block_hash` over a parsed block, which keeps every field the node sent.
+    /// Numbers are written back from their typed fields, so an integer `amount` hashes as a float.
+    pub fn recompute_block_hash(block: &Block) -> String {
+        let fields: HashMap<String, JsonValue> = match serde_json::to_value(block) {
+            Ok(JsonValue::Object(fields)) => fields.into_iter().collect(),
+            _ => HashMap::new(),
+        };
+        Self::calculate_block_hash(&fields)
+    }
+
+    /// Check that `blocks` form a chain: consecutive indexes, each `previous_hash` naming the block
+    /// before, timestamps not going back by more than `timestamp_tolerance`, stored hashes matching
+    /// `recompute_block_hash`, and, for blocks with a nonce and difficulty, enough work. The first
+    /// block is checked against its parent when `header_cache` or the block cache holds it.
+    pub fn validate_chain_segment(&self, blocks: &[Block]) -> Result<(), ChainValidationError> {
+        let Some(first) = blocks.first() else {
+            return Ok(());
+        };
+        let parent = first.index.checked_sub(1).and_then(|height| self.known_header(height));
+        Self::validate_chain_segment_after(parent, blocks, self.timestamp_tolerance)
+    }
+
+    /// `validate_chain_segment` with the first block's parent given rather than looked up
+    pub fn validate_chain_segment_after(
+        mut parent: Option<BlockHeader>,
+        blocks: &[Block],
+        timestamp_tolerance: Duration,
+    ) -> Result<(), ChainValidationError> {
+        for block in blocks {
+            let height = block.index;
+            if let Some(parent) = &parent {
+                if height != parent.index + 1 {
+                    return Err(ChainValidationError::Gap { expected: parent.index + 1, found: height });
+                }
+                if block.previous_hash != parent.hash {
+                    return Err(ChainValidationError::BrokenLink { height });
+                }
+                if block.timestamp.saturating_add(timestamp_tolerance.as_secs()) < parent.timestamp {
+                    return Err(ChainValidationError::TimestampRegression { height });
+                }
+            }
+            if Self::recompute_block_hash(block) != block.hash {
+                return Err(ChainValidationError::HashMismatch { height });
+            }
+            if let (Some(_), Some(difficulty)) = (block.nonce, block.difficulty)
+                && !Difficulty::new(u32::try_from(difficulty).unwrap_or(u32::MAX)).is_valid_hash(&block.hash)
+            {
+                return Err(ChainValidationError::InsufficientWork { height });
+            }
+            parent = Some(BlockHeader::from(block));
+        }
+        Ok(())
+    }
+
+    /// The header at `height` from `header_cache`, or from the block cache
+    fn known_header(&self, height: u64) -> Option<BlockHeader> {
+        let header = self.header_cache.lock().unwrap().get(&height).cloned();
+        header.or_else(|| self.cache.lock().unwrap().peek(&height).map(BlockHeader::from))
+    }
+
     /// Validate transaction before broadcasting (struct version)
     pub fn validate_transaction_before_broadcast(transaction: &Transaction) -> bool {
         if transaction.tx_type.is_none()
@@ -102,57 +500,55 @@ impl BlockchainManager {
             || transaction.hash.is_none()
             || transaction.signature.is_none()
         {
-            println!("❌ Missing required field");
+            warn!(tx_hash = transaction.hash.as_deref().unwrap_or(""); "Transaction rejected: missing required field");
             return false;
         }
-        if !transaction.from.as_ref().unwrap().starts_with("LUN_") {
-            println!("❌ Invalid from address format: {}", transaction.from.as_ref().unwrap());
+        if let Err(e) = LunaWallet::validate_address(transaction.from.as_ref().unwrap()) {
+            warn!(tx_hash = transaction.hash.as_deref().unwrap_or(""), address = transaction.from.as_deref().unwrap_or(""), reason = e.to_string().as_str(); "Transaction rejected: invalid from address");
             return false;
         }
-        if !transaction.to.as_ref().unwrap().starts_with("LUN_") {
-            println!("❌ Invalid to address format: {}", transaction.to.as_ref().unwrap());
+        if let Err(e) = LunaWallet::validate_address(transaction.to.as_ref().unwrap()) {
+            warn!(tx_hash = transaction.hash.as_deref().unwrap_or(""), address = transaction.to.as_deref().unwrap_or(""), reason = e.to_string().as_str(); "Transaction rejected: invalid to address");
             return false;
         }
         if transaction.amount.unwrap() <= 0.0 {
-            println!("❌ Invalid amount: {}", transaction.amount.unwrap());
+            warn!(tx_hash = transaction.hash.as_deref().unwrap_or(""), amount = transaction.amount.unwrap_or(0.0); "Transaction rejected: invalid amount");
             return false;
         }
-        if transaction.signature.as_ref().unwrap().len() < 10 {
-            println!("❌ Invalid or missing signature");
+        let hash = transaction.hash.as_deref().unwrap();
+        if TransactionManager::calculate_transaction_hash(&transaction.to_json()) != hash {
+            warn!(tx_hash = hash; "Transaction rejected: hash does not match its contents");
             return false;
         }
-        if transaction.hash.as_ref().unwrap().len() < 10 {
-            println!("❌ Invalid or missing transaction hash");
+        let public_key = transaction.extra.get("public_key").and_then(|v| v.as_str()).unwrap_or("");
+        if !derives_address(public_key, transaction.from.as_deref().unwrap())
+            || !Crypto::new().verify_signature(hash, transaction.signature.as_deref().unwrap(), public_key)
+        {
+            warn!(tx_hash = hash; "Transaction rejected: not signed by the sender");
             return false;
         }
-        println!("✅ Transaction validation passed");
+        debug!(tx_hash = transaction.hash.as_deref().unwrap_or(""); "Transaction validation passed");
         true
     }
 
     /// Non-blocking: Broadcast transaction to mempool
-    pub async fn broadcast_transaction(&self, transaction: &Transaction) -> Result<String, String> {
+    pub async fn broadcast_transaction(&self, transaction: &Transaction) -> Result<String, BlockchainError> {
         let url = format!("{}/mempool/add", self.endpoint_url);
-        let client = reqwest::Client::new();
-        let res = client
-            .post(&url)
-            .json(transaction)
-            .send()
-            .await
-            .map_err(|e| format!("Network error: {}", e))?;
+        let res = self.async_client()?.post(&url).json(transaction).send().await?;
         if res.status().is_success() {
             let text = res.text().await.unwrap_or_default();
             Ok(format!("Broadcast success: {}", text))
         } else {
-            Err(format!("Broadcast failed: HTTP {}", res.status()))
+            Err(BlockchainError::from_status(res.status().as_u16()))
         }
     }
 
     /// Non-blocking: Get current blockchain height
-    pub async fn get_blockchain_height(&self) -> Result<u64, String> {
+    pub async fn get_blockchain_height(&self) -> Result<u64, BlockchainError> {
         let url = format!("{}/blockchain/blocks", self.endpoint_url);
-        let res = reqwest::get(&url).await.map_err(|e| e.to_string())?;
+        let res = self.async_client()?.get(&url).send().await?;
         if res.status().is_success() {
-            let json: serde_json::Value = res.json().await.map_err(|e| e.to_string())?;
+            let json: serde_json::Value = res.json().await?;
             if let Some(blocks) = json.get("blocks").and_then(|b| b.as_array()) {
                 if let Some(last) = blocks.last() {
                     if let Some(index) = last.get("index").and_then(|i| i.as_u64()) {
@@ -162,44 +558,290 @@ impl BlockchainManager {
             }
             Ok(0)
         } else {
-            Err(format!("Failed to get height: HTTP {}", res.status()))
+            Err(BlockchainError::from_status(res.status().as_u16()))
         }
     }
 
-    /// Async: get range of blocks (dummy, spawns thread)
-    pub fn get_blocks_range_async(&self, start_height: u64, end_height: u64, task_id: String) {
-        let cache: Arc<Mutex<HashMap<u64, Block>>> = Arc::clone(&self.cache);
-        let async_tasks: Arc<Mutex<HashMap<String, thread::JoinHandle<()>>>> = Arc::clone(&self.async_tasks);
-        let handle = thread::spawn(move || {
-            // ダミー: キャッシュから取得
-            let cache = cache.lock().unwrap();
-            let _blocks: Vec<Block> = (start_height..=end_height)
-                .filter_map(|h| cache.get(&h).cloned())
-                .collect();
-            // 本来はコールバックやチャンネルで通知
-        });
-        async_tasks.lock().unwrap().insert(task_id, handle);
+    /// Blocking: fetch blocks above the cached tip, cache them, and return them in height order.
+    /// Blocks are returned as raw JSON so their proof-of-work hashes can still be checked.
+    pub fn sync_to_tip(&self) -> Result<Vec<HashMap<String, JsonValue>>, BlockchainError> {
+        let url = format!("{}/blockchain/blocks", self.endpoint_url);
+        let res = self.client()?.get(&url).send()?;
+        if !res.status().is_success() {
+            return Err(BlockchainError::from_status(res.status().as_u16()));
+        }
+        let json: JsonValue = res.json()?;
+        let tip = self.cache.lock().unwrap().tip();
+        let height = |b: &HashMap<String, JsonValue>| b.get("index").and_then(|i| i.as_u64());
+        let mut blocks: Vec<HashMap<String, JsonValue>> = json
+            .get("blocks")
+            .and_then(|b| serde_json::from_value(b.clone()).ok())
+            .unwrap_or_default();
+        blocks.retain(|b| height(b).is_some_and(|h| tip.is_none_or(|tip| h > tip)));
+        blocks.sort_by_key(|b| height(b));
+        let parsed = blocks
+            .iter()
+            .map(|b| serde_json::from_value::<Block>(JsonValue::Object(b.clone().into_iter().collect())))
+            .collect::<Result<Vec<Block>, _>>()
+            .map_err(|e| BlockchainError::Decode(format!("invalid block: {}", e)))?;
+        self.cache.lock().unwrap().extend(parsed);
+        Ok(blocks)
+    }
+
+    /// Blocking: POST a mined block to the endpoint and cache it once accepted
+    pub fn submit_block(&self, block: &HashMap<String, JsonValue>) -> Result<(), BlockchainError> {
+        let url = format!("{}/blockchain/submit-block", self.endpoint_url);
+        let res = self.client()?.post(&url).json(block).send()?;
+        if !res.status().is_success() {
+            return Err(BlockchainError::from_status(res.status().as_u16()));
+        }
+        let parsed: Block = serde_json::from_value(JsonValue::Object(block.clone().into_iter().collect()))
+            .map_err(|e| BlockchainError::Decode(format!("invalid block: {}", e)))?;
+        self.cache.lock().unwrap().insert(parsed.index, parsed);
+        Ok(())
+    }
+
+    /// Blocking: POST a signed transaction to the mempool and return its hash
+    pub fn submit_transaction(&self, transaction: &HashMap<String, JsonValue>) -> Result<String, BlockchainError> {
+        let url = format!("{}/mempool/add", self.endpoint_url);
+        let res = self.client()?.post(&url).json(transaction).send()?;
+        if !res.status().is_success() {
+            return Err(BlockchainError::from_status(res.status().as_u16()));
+        }
+        let body: JsonValue = res.json().unwrap_or(JsonValue::Null);
+        Ok(accepted_hash(&body, transaction))
+    }
+
+    /// Non-blocking `submit_transaction`
+    pub async fn submit_transaction_async(&self, transaction: &HashMap<String, JsonValue>) -> Result<String, BlockchainError> {
+        let url = format!("{}/mempool/add", self.endpoint_url);
+        let res = self.async_client()?.post(&url).json(transaction).send().await?;
+        if !res.status().is_success() {
+            return Err(BlockchainError::from_status(res.status().as_u16()));
+        }
+        let body: JsonValue = res.json().await.unwrap_or(JsonValue::Null);
+        Ok(accepted_hash(&body, transaction))
+    }
+
+    /// Non-blocking: blocks `start..=end` in height order. Heights missing from the cache are fetched
+    /// with up to `concurrency` requests in flight, capped at `max_workers`, and cached. Failed heights
+    /// are retried once; any still failing make the whole call an `Incomplete` error. With
+    /// `with_chain_validation`, a range that fails `validate_chain_segment` is an `InvalidChain` error
+    /// and the blocks from the first bad height on are dropped from the cache.
+    pub async fn fetch_blocks_range(&self, start: u64, end: u64, concurrency: usize) -> Result<Vec<Block>, BlockchainError> {
+        let client = self.async_client()?;
+        let concurrency = concurrency.clamp(1, self.max_workers);
+        let CachedRange { mut blocks, missing } = self.get_blocks_range(start, end);
+        let mut failed = self.fetch_into_cache(&client, missing, concurrency, &mut blocks).await;
+        if !failed.is_empty() {
+            debug!(endpoint = self.endpoint_url.as_str(), count = failed.len(); "Retrying failed block fetches");
+            let retry = failed.into_iter().map(|(height, _)| height).collect();
+            failed = self.fetch_into_cache(&client, retry, concurrency, &mut blocks).await;
+        }
+        if !failed.is_empty() {
+            failed.sort_by_key(|(height, _)| *height);
+            return Err(BlockchainError::Incomplete(failed));
+        }
+        blocks.sort_by_key(|block| block.index);
+        if self.validate_fetched
+            && let Err(e) = self.validate_chain_segment(&blocks)
+        {
+            warn!(endpoint = self.endpoint_url.as_str(), height = e.height(), error:% = e; "Fetched blocks failed validation");
+            let bad = e.height();
+            self.cache.lock().unwrap().retain(|height, _| !(bad..=end).contains(height));
+            return Err(BlockchainError::InvalidChain(e));
+        }
+        Ok(blocks)
     }
 
-    /// Get range of blocks (cache only, dummy)
-    pub fn get_blocks_range(&self, start_height: u64, end_height: u64) -> Vec<Block> {
+    /// Fetch `heights` with at most `concurrency` requests in flight, caching what arrives and adding
+    /// it to `fetched`, since a long range may be evicted again before it is returned.
+    /// Returns the heights that failed with their errors.
+    async fn fetch_into_cache(&self, client: &reqwest::Client, heights: Vec<u64>, concurrency: usize, fetched: &mut Vec<Block>) -> Vec<(u64, BlockchainError)> {
+        let mut pending = heights.into_iter();
+        let mut in_flight = tokio::task::JoinSet::new();
+        let mut failed = Vec::new();
+        loop {
+            while in_flight.len() < concurrency {
+                let Some(height) = pending.next() else { break };
+                let (client, endpoint) = (client.clone(), self.endpoint_url.clone());
+                in_flight.spawn(async move { (height, fetch_block(&client, &endpoint, height).await) });
+            }
+            let Some(joined) = in_flight.join_next().await else { break };
+            match joined.expect("block fetch task panicked") {
+                (_, Ok(block)) => {
+                    self.cache.lock().unwrap().insert(block.index, block.clone());
+                    fetched.push(block);
+                }
+                (height, Err(e)) => {
+                    warn!(endpoint = self.endpoint_url.as_str(), height = height, error:% = e; "Block fetch failed");
+                    failed.push((height, e));
+                }
+            }
+        }
+        failed
+    }
+
+    /// Blocks `start_height..=end_height` from the cache only, listing the heights that were misses
+    /// so the caller can fetch them
+    pub fn get_blocks_range(&self, start_height: u64, end_height: u64) -> CachedRange {
+        let mut cache = self.cache.lock().unwrap();
+        let mut range = CachedRange::default();
+        for height in start_height..=end_height {
+            match cache.get(&height) {
+                Some(block) => range.blocks.push(block.clone()),
+                None => range.missing.push(height),
+            }
+        }
+        range
+    }
+
+    /// Non-blocking: headers of blocks `start..=end` in height order, requested with `headers_only=true`.
+    /// A node that ignores the flag or the range sends full blocks or more of them; those are stripped
+    /// and filtered here. The headers are kept in `header_cache`.
+    pub async fn get_block_headers(&self, start: u64, end: u64) -> Result<Vec<BlockHeader>, BlockchainError> {
+        let url = format!("{}/blockchain/blocks", self.endpoint_url);
+        let res = self.async_client()?
+            .get(&url)
+            .query(&[("start", start), ("end", end)])
+            .query(&[("headers_only", "true")])
+            .send()
+            .await?;
+        if !res.status().is_success() {
+            return Err(BlockchainError::from_status(res.status().as_u16()));
+        }
+        let body: JsonValue = res.json().await?;
+        let listed = match body {
+            JsonValue::Object(mut object) => object.remove("blocks").or_else(|| object.remove("headers")).unwrap_or(JsonValue::Null),
+            list => list,
+        };
+        let JsonValue::Array(listed) = listed else {
+            return Err(BlockchainError::Decode("expected a list of blocks".to_string()));
+        };
+        let mut headers = Vec::new();
+        for entry in listed {
+            let header = if entry.get("transactions").is_some() {
+                serde_json::from_value::<Block>(entry).map(|block| BlockHeader::from(&block))
+            } else {
+                serde_json::from_value::<BlockHeader>(entry)
+            };
+            let header = header.map_err(|e| BlockchainError::Decode(format!("invalid block header: {}", e)))?;
+            if (start..=end).contains(&header.index) {
+                headers.push(header);
+            }
+        }
+        headers.sort_by_key(|header| header.index);
+        headers.dedup_by_key(|header| header.index);
+        self.header_cache.lock().unwrap().extend(headers.iter().map(|header| (header.index, header.clone())));
+        debug!(endpoint = self.endpoint_url.as_str(), start = start, end = end, count = headers.len(); "Fetched block headers");
+        Ok(headers)
+    }
+
+    /// Non-blocking: the confirmed transactions from or to `addresses` in blocks `from_height` up to the
+    /// tip, keyed by the address as given and matched after `normalize_address`. Cached blocks are
+    /// used as they are and the rest are fetched with `fetch_blocks_range`.
+    pub async fn scan_transactions_for_addresses(&self, addresses: &[String], from_height: u64) -> Result<HashMap<String, Vec<WalletTransaction>>, BlockchainError> {
+        let tip = self.get_blockchain_height().await?;
+        let blocks = if from_height <= tip {
+            self.fetch_blocks_range(from_height, tip, self.max_workers).await?
+        } else {
+            Vec::new()
+        };
+        Ok(Self::scan_blocks(addresses, &blocks, tip))
+    }
+
+    /// `scan_transactions_for_addresses` over cached blocks only, counting confirmations up to `tip`
+    pub fn scan_cached_blocks(&self, addresses: &[String], from_height: u64, tip: u64) -> HashMap<String, Vec<WalletTransaction>> {
         let cache = self.cache.lock().unwrap();
-        (start_height..=end_height)
-            .filter_map(|h| cache.get(&h).cloned())
-            .collect()
+        Self::scan_blocks(addresses, cache.range(from_height..=tip), tip)
     }
 
-    /// Get current mempool (dummy)
-    pub fn get_mempool(&self) -> Vec<Transaction> {
-        // TODO: reqwestでHTTP GET実装
-        vec![]
+    fn scan_blocks<'a>(addresses: &[String], blocks: impl IntoIterator<Item = &'a Block>, tip: u64) -> HashMap<String, Vec<WalletTransaction>> {
+        let wanted: HashMap<String, &String> = addresses.iter().map(|a| (Self::normalize_address(a), a)).collect();
+        let mut found: HashMap<String, Vec<WalletTransaction>> = HashMap::new();
+        for block in blocks {
+            for tx in &block.transactions {
+                let mut owners: Vec<&String> = [&tx.from, &tx.to].into_iter()
+                    .filter_map(|end| wanted.get(&Self::normalize_address(end.as_deref().unwrap_or_default())).copied())
+                    .collect();
+                owners.dedup();
+                for owner in owners {
+                    found.entry(owner.clone()).or_default().push(WalletTransaction::from_block(tx, block, tip, owner));
+                }
+            }
+        }
+        found
     }
 
-    /// Check network connection (dummy)
-    pub fn check_network_connection(&mut self) -> bool {
-        // TODO: reqwestでHTTP GET実装
-        self.network_connected = true;
-        true
+    /// Non-blocking: the transactions waiting in the endpoint's mempool, from `/mempool` or, on
+    /// nodes without it, `/mempool/transactions`. The list may also come as `{"transactions": [...]}`.
+    pub async fn get_mempool(&self) -> Result<Vec<Transaction>, BlockchainError> {
+        let client = self.async_client()?;
+        for path in ["mempool", "mempool/transactions"] {
+            let res = client.get(format!("{}/{}", self.endpoint_url, path)).send().await?;
+            if res.status() == reqwest::StatusCode::NOT_FOUND {
+                debug!(endpoint = self.endpoint_url.as_str(), path = path; "No mempool listing here");
+                continue;
+            }
+            if !res.status().is_success() {
+                return Err(BlockchainError::from_status(res.status().as_u16()));
+            }
+            let body: JsonValue = res.json().await?;
+            let list = match body {
+                JsonValue::Object(mut object) => object.remove("transactions").unwrap_or(JsonValue::Null),
+                list => list,
+            };
+            return serde_json::from_value(list).map_err(|e| BlockchainError::Decode(format!("invalid mempool: {}", e)));
+        }
+        Err(BlockchainError::NotFound)
+    }
+
+    /// Non-blocking: the mempool transactions from or to `address`, compared after `normalize_address`
+    pub async fn get_mempool_for_address(&self, address: &str) -> Result<Vec<Transaction>, BlockchainError> {
+        let wanted = Self::normalize_address(address);
+        let involves = |end: &Option<String>| end.as_deref().is_some_and(|end| Self::normalize_address(end) == wanted);
+        let mut transactions = self.get_mempool().await?;
+        transactions.retain(|tx| involves(&tx.from) || involves(&tx.to));
+        Ok(transactions)
+    }
+
+    /// Non-blocking: probe `{endpoint}/health`, or `/blockchain/blocks?limit=1` on nodes without it,
+    /// within `health_timeout`. Updates `network_connected` and the status `get_connection_status` returns.
+    pub async fn check_network_connection(&mut self) -> ConnectionStatus {
+        let mut latency_ms = None;
+        if let Ok(client) = reqwest::Client::builder().timeout(self.health_timeout).build() {
+            for path in ["health", "blockchain/blocks?limit=1"] {
+                let started = Instant::now();
+                match client.get(format!("{}/{}", self.endpoint_url, path)).send().await {
+                    Ok(res) if res.status().is_success() => {
+                        latency_ms = Some(started.elapsed().as_millis() as u64);
+                        break;
+                    }
+                    Ok(res) => debug!(endpoint = self.endpoint_url.as_str(), path = path, status = res.status().as_u16(); "Health probe refused"),
+                    Err(e) => {
+                        debug!(endpoint = self.endpoint_url.as_str(), path = path, error:% = BlockchainError::from(e); "Endpoint unreachable");
+                        break;
+                    }
+                }
+            }
+        }
+        let status = ConnectionStatus {
+            connected: latency_ms.is_some(),
+            latency_ms,
+            endpoint: self.endpoint_url.clone(),
+            last_checked: SystemClock.now(),
+        };
+        if status.connected != self.network_connected || self.connection_status.is_none() {
+            info!(endpoint = self.endpoint_url.as_str(), connected = status.connected, latency_ms = latency_ms.unwrap_or(0); "Endpoint connection checked");
+        }
+        self.network_connected = status.connected;
+        self.connection_status = Some(status.clone());
+        status
+    }
+
+    /// The last `check_network_connection` result, or `None` before the first check
+    pub fn get_connection_status(&self) -> Option<ConnectionStatus> {
+        self.connection_status.clone()
     }
 
     /// Dummy async task status
@@ -224,18 +866,32 @@ impl BlockchainManager {
     }
 
     /// 非同期: 指定高さのブロックを取得
-    pub async fn get_block_by_height(&self, height: u64) -> Result<Block, String> {
-        let url = format!("{}/blockchain/block/{}", self.endpoint_url, height);
-        let res = reqwest::get(&url).await.map_err(|e| e.to_string())?;
-        if res.status().is_success() {
-            let block: Block = res.json().await.map_err(|e| e.to_string())?;
-            Ok(block)
-        } else {
-            Err(format!("Failed to get block: HTTP {}", res.status()))
-        }
+    pub async fn get_block_by_height(&self, height: u64) -> Result<Block, BlockchainError> {
+        fetch_block(&self.async_client()?, &self.endpoint_url, height).await
+    }
+}
+
+async fn fetch_block(client: &reqwest::Client, endpoint_url: &str, height: u64) -> Result<Block, BlockchainError> {
+    let url = format!("{}/blockchain/block/{}", endpoint_url, height);
+    let res = client.get(&url).send().await?;
+    if res.status().is_success() {
+        let block: Block = res.json().await?;
+        Ok(block)
+    } else {
+        Err(BlockchainError::from_status(res.status().as_u16()))
     }
 }
 
+/// The hash the mempool reports for an accepted transaction, or the transaction's own
+fn accepted_hash(body: &JsonValue, transaction: &HashMap<String, JsonValue>) -> String {
+    body.get("hash")
+        .or_else(|| body.get("transaction_hash"))
+        .or_else(|| transaction.get("hash"))
+        .and_then(|h| h.as_str())
+        .unwrap_or_default()
+        .to_string()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -278,6 +934,351 @@ mod tests {
         assert!(result.is_ok() || result.is_err());
     }
 
+    #[tokio::test]
+    async fn test_errors_are_typed() {
+        let mut server = mockito::Server::new_async().await;
+        server.mock("GET", "/blockchain/block/99").with_status(404).create_async().await;
+        server.mock("GET", "/blockchain/block/5").with_status(503).create_async().await;
+        server.mock("GET", "/blockchain/block/1").with_body("{not json").create_async().await;
+        server.mock("GET", "/blockchain/blocks").with_status(429).create_async().await;
+        server.mock("POST", "/mempool/add").with_status(400).create_async().await;
+        let manager = BlockchainManager::new(&server.url(), 1);
+
+        assert_eq!(manager.get_block_by_height(99).await.unwrap_err(), BlockchainError::NotFound);
+        let unavailable = manager.get_block_by_height(5).await.unwrap_err();
+        assert_eq!(unavailable, BlockchainError::Http(503));
+        assert!(unavailable.is_retryable());
+        let malformed = manager.get_block_by_height(1).await.unwrap_err();
+        assert!(matches!(malformed, BlockchainError::Decode(_)), "{:?}", malformed);
+        assert!(!malformed.is_retryable());
+        assert_eq!(manager.get_blockchain_height().await.unwrap_err(), BlockchainError::Http(429));
+        let rejected = manager.broadcast_transaction(&valid_transaction()).await.unwrap_err();
+        assert_eq!(rejected, BlockchainError::Http(400));
+        assert!(!rejected.is_retryable());
+
+        let unreachable = BlockchainManager::new("http://127.0.0.1:9", 1).get_blockchain_height().await.unwrap_err();
+        assert!(matches!(unreachable, BlockchainError::Network(_)), "{:?}", unreachable);
+        assert!(unreachable.is_retryable());
+
+        // Accepts the connection but never answers
+        let silent = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let manager = BlockchainManager::new(&format!("http://{}", silent.local_addr().unwrap()), 1).with_timeout(Duration::from_millis(200));
+        assert_eq!(manager.get_block_by_height(0).await.unwrap_err(), BlockchainError::Timeout);
+        let blocking = std::thread::spawn(move || manager.sync_to_tip().unwrap_err());
+        assert_eq!(blocking.join().unwrap(), BlockchainError::Timeout);
+    }
+
+    #[tokio::test]
+    async fn test_get_mempool() {
+        let mut server = mockito::Server::new_async().await;
+        let listing = serde_json::json!([
+            {"type": "transfer", "from": "LUN_alice", "to": "LUN_bob", "amount": 2.5, "timestamp": 1_700_000_000, "hash": "h1", "signature": "s1"},
+            {"from": "carol", "to": "lun_ALICE", "hash": "h2"},
+            {"from": "carol", "to": "dave"},
+        ]);
+        server.mock("GET", "/mempool").with_body(listing.to_string()).create_async().await;
+        let manager = BlockchainManager::new(&server.url(), 1);
+        let mempool = manager.get_mempool().await.unwrap();
+        assert_eq!(mempool.len(), 3);
+        assert_eq!((mempool[0].tx_type.as_deref(), mempool[0].amount), (Some("transfer"), Some(2.5)));
+        assert_eq!((mempool[1].amount, mempool[1].signature.as_deref()), (None, None));
+        let alice: Vec<Option<String>> = manager.get_mempool_for_address("alice").await.unwrap().into_iter().map(|tx| tx.hash).collect();
+        assert_eq!(alice, [Some("h1".to_string()), Some("h2".to_string())]);
+
+        // Older nodes only list it under /mempool/transactions
+        let mut older = mockito::Server::new_async().await;
+        older.mock("GET", "/mempool").with_status(404).create_async().await;
+        older.mock("GET", "/mempool/transactions").with_body(serde_json::json!({"transactions": [{"hash": "h3", "to": "LUN_bob"}]}).to_string()).create_async().await;
+        let mempool = BlockchainManager::new(&older.url(), 1).get_mempool_for_address("bob").await.unwrap();
+        assert_eq!(mempool[0].hash.as_deref(), Some("h3"));
+
+        let mut missing = mockito::Server::new_async().await;
+        missing.mock("GET", mockito::Matcher::Any).with_status(404).create_async().await;
+        assert_eq!(BlockchainManager::new(&missing.url(), 1).get_mempool().await.unwrap_err(), BlockchainError::NotFound);
+        let mut broken = mockito::Server::new_async().await;
+        broken.mock("GET", "/mempool").with_body(r#"[{"amount": "lots"}]"#).create_async().await;
+        assert!(matches!(BlockchainManager::new(&broken.url(), 1).get_mempool().await, Err(BlockchainError::Decode(_))));
+    }
+
+    #[tokio::test]
+    async fn test_check_network_connection() {
+        let mut server = mockito::Server::new_async().await;
+        let health = server.mock("GET", "/health").with_body(r#"{"status": "ok"}"#).expect(1).create_async().await;
+        let mut manager = BlockchainManager::new(&server.url(), 1);
+        assert!(manager.get_connection_status().is_none());
+        let status = manager.check_network_connection().await;
+        assert!(status.connected && manager.network_connected);
+        assert!(status.latency_ms.is_some());
+        assert_eq!(status.endpoint, server.url());
+        assert!(status.last_checked > 0);
+        assert_eq!(manager.get_connection_status(), Some(status));
+        health.assert_async().await;
+
+        // Without /health, the block listing answers instead
+        let mut older = mockito::Server::new_async().await;
+        older.mock("GET", "/health").with_status(404).create_async().await;
+        older.mock("GET", "/blockchain/blocks").match_query(mockito::Matcher::UrlEncoded("limit".into(), "1".into())).with_body(r#"{"blocks": []}"#).create_async().await;
+        assert!(BlockchainManager::new(&older.url(), 1).check_network_connection().await.latency_ms.is_some());
+
+        manager.endpoint_url = "http://127.0.0.1:9".to_string();
+        let status = manager.check_network_connection().await;
+        assert!(!status.connected && !manager.network_connected);
+        assert_eq!(status.latency_ms, None);
+        assert_eq!(manager.get_connection_status().unwrap().endpoint, "http://127.0.0.1:9");
+
+        let silent = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let mut manager = BlockchainManager::new(&format!("http://{}", silent.local_addr().unwrap()), 1).with_health_timeout(Duration::from_millis(200));
+        let started = Instant::now();
+        assert!(!manager.check_network_connection().await.connected);
+        assert!(started.elapsed() < Duration::from_secs(5));
+    }
+
+    #[tokio::test]
+    async fn test_fetch_blocks_range() {
+        let mut server = mockito::Server::new_async().await;
+        // Block 7 fails once and is served on the retry; blocks 20 and 21 never are
+        server.mock("GET", "/blockchain/block/7").with_status(503).expect(1).create_async().await;
+        for height in 0..20 {
+            let block = serde_json::json!({"index": height, "hash": format!("h{}", height), "previous_hash": "", "timestamp": 0, "transactions": []});
+            server.mock("GET", format!("/blockchain/block/{}", height).as_str()).with_body(block.to_string()).create_async().await;
+        }
+        server.mock("GET", "/blockchain/block/20").with_status(404).create_async().await;
+        server.mock("GET", "/blockchain/block/21").with_status(503).expect(2).create_async().await;
+        let manager = BlockchainManager::new(&server.url(), 4);
+
+        let blocks = manager.fetch_blocks_range(0, 19, 8).await.unwrap();
+        assert_eq!(blocks.iter().map(|b| b.index).collect::<Vec<_>>(), (0..20).collect::<Vec<_>>());
+        assert_eq!(manager.cache.lock().unwrap().len(), 20);
+        assert_eq!(manager.block_at(7).unwrap().hash, "h7");
+
+        // Cached heights are not fetched again
+        let cached_only = BlockchainManager::new("http://127.0.0.1:9", 4);
+        cached_only.cache.lock().unwrap().extend(manager.cache.lock().unwrap().values().cloned());
+        assert_eq!(cached_only.fetch_blocks_range(5, 9, 2).await.unwrap().len(), 5);
+        assert!(cached_only.fetch_blocks_range(18, 20, 2).await.is_err());
+
+        let err = manager.fetch_blocks_range(18, 21, 2).await.unwrap_err();
+        match &err {
+            BlockchainError::Incomplete(failed) => {
+                assert_eq!(failed.iter().map(|(h, _)| *h).collect::<Vec<_>>(), vec![20, 21]);
+                assert_eq!(failed[0].1, BlockchainError::NotFound);
+                assert_eq!(failed[1].1, BlockchainError::Http(503));
+            }
+            other => panic!("{:?}", other),
+        }
+        assert!(!err.is_retryable());
+        assert!(err.to_string().contains("21 (Endpoint returned HTTP 503)"), "{}", err);
+        assert!(manager.block_at(21).is_none());
+    }
+
+    #[tokio::test]
+    async fn test_get_block_headers() {
+        let mut server = mockito::Server::new_async().await;
+        let range = |start: &str, end: &str| mockito::Matcher::AllOf(vec![
+            mockito::Matcher::UrlEncoded("start".into(), start.into()),
+            mockito::Matcher::UrlEncoded("end".into(), end.into()),
+            mockito::Matcher::UrlEncoded("headers_only".into(), "true".into()),
+        ]);
+        let headers = serde_json::json!({"blocks": [
+            {"index": 3, "hash": "h3", "previous_hash": "h2", "timestamp": 30, "difficulty": 2, "miner": "LUN_m", "tx_count": 4},
+            {"index": 2, "hash": "h2", "previous_hash": "h1", "timestamp": 20, "difficulty": 2, "miner": null, "tx_count": 0},
+        ]});
+        server.mock("GET", "/blockchain/blocks").match_query(range("2", "3")).with_body(headers.to_string()).create_async().await;
+        let manager = BlockchainManager::new(&server.url(), 1);
+        let fetched = manager.get_block_headers(2, 3).await.unwrap();
+        assert_eq!(fetched.iter().map(|h| h.index).collect::<Vec<_>>(), vec![2, 3]);
+        assert_eq!(fetched[1], BlockHeader {
+            index: 3, hash: "h3".into(), previous_hash: "h2".into(), timestamp: 30, difficulty: Some(2), miner: Some("LUN_m".into()), tx_count: 4,
+        });
+        assert_eq!(manager.header_cache.lock().unwrap().len(), 2);
+        assert!(manager.cache.lock().unwrap().is_empty());
+
+        // A node that ignores the flag and the range sends its whole chain of full blocks
+        let mut full = mockito::Server::new_async().await;
+        let blocks: Vec<serde_json::Value> = (0..6).map(|i| serde_json::json!({
+            "index": i, "hash": format!("h{}", i), "previous_hash": format!("h{}", i.max(1) - 1), "timestamp": i * 10,
+            "miner": "LUN_m", "difficulty": 1, "nonce": 7, "transactions": [{"hash": "a"}, {"hash": "b"}],
+        })).collect();
+        full.mock("GET", "/blockchain/blocks").match_query(mockito::Matcher::Any).with_body(serde_json::json!(blocks).to_string()).create_async().await;
+        let manager = BlockchainManager::new(&full.url(), 1);
+        let stripped = manager.get_block_headers(1, 4).await.unwrap();
+        assert_eq!(stripped.iter().map(|h| h.index).collect::<Vec<_>>(), vec![1, 2, 3, 4]);
+        assert_eq!((stripped[0].tx_count, stripped[0].previous_hash.as_str()), (2, "h0"));
+        assert_eq!(manager.header_cache.lock().unwrap().keys().copied().collect::<Vec<_>>(), vec![1, 2, 3, 4]);
+
+        let mut broken = mockito::Server::new_async().await;
+        broken.mock("GET", "/blockchain/blocks").match_query(mockito::Matcher::Any).with_body(r#"{"blocks": [{"index": "two"}]}"#).create_async().await;
+        let err = BlockchainManager::new(&broken.url(), 1).get_block_headers(0, 1).await.unwrap_err();
+        assert!(matches!(err, BlockchainError::Decode(_)), "{:?}", err);
+    }
+
+    #[tokio::test]
+    async fn test_cache_limits() {
+        let mut server = mockito::Server::new_async().await;
+        for height in 0..10 {
+            let block = serde_json::json!({"index": height, "hash": format!("h{}", height), "previous_hash": "", "timestamp": 0, "transactions": []});
+            server.mock("GET", format!("/blockchain/block/{}", height).as_str()).with_body(block.to_string()).create_async().await;
+        }
+        let manager = BlockchainManager::new(&server.url(), 1).with_max_cached_blocks(5).with_pinned_tip_blocks(2);
+
+        // The whole range comes back even though the cache cannot hold it
+        let blocks = manager.fetch_blocks_range(0, 9, 1).await.unwrap();
+        assert_eq!(blocks.iter().map(|b| b.index).collect::<Vec<_>>(), (0..10).collect::<Vec<_>>());
+        assert_eq!(manager.cache.lock().unwrap().keys().copied().collect::<Vec<_>>(), vec![5, 6, 7, 8, 9]);
+        assert_eq!(manager.cache_stats(), CacheStats { hits: 0, misses: 10, evictions: 5 });
+
+        let range = manager.get_blocks_range(4, 6);
+        assert_eq!(range.blocks.iter().map(|b| b.index).collect::<Vec<_>>(), vec![5, 6]);
+        assert_eq!(range.missing, vec![4]);
+        // 5 and 6 were just used, so 7 is the oldest outside the pinned tip
+        manager.cache.lock().unwrap().insert(10, Block { index: 10, ..Block::new() });
+        assert_eq!(manager.cache.lock().unwrap().keys().copied().collect::<Vec<_>>(), vec![5, 6, 8, 9, 10]);
+        assert_eq!(manager.cache_stats(), CacheStats { hits: 2, misses: 11, evictions: 6 });
+
+        manager.clear_cache();
+        assert!(manager.block_at(9).is_none());
+        assert_eq!(manager.get_blocks_range(0, 2).missing, vec![0, 1, 2]);
+    }
+
+    /// A block on `parent` whose nonce meets `difficulty`, carrying a field `Transaction` does not name
+    fn mined(parent: Option<&Block>, difficulty: u64) -> Block {
+        let index = parent.map_or(0, |p| p.index + 1);
+        let transfer: Transaction = serde_json::from_value(serde_json::json!({
+            "type": "transfer", "from": "LUN_a", "to": "LUN_b", "amount": 1.5, "hash": format!("t{}", index), "public_key": "04ab",
+        })).unwrap();
+        let mut block = Block {
+            index,
+            previous_hash: parent.map_or("0".to_string(), |p| p.hash.clone()),
+            timestamp: 1_700_000_000 + index * 60,
+            transactions: vec![transfer],
+            difficulty: Some(difficulty),
+            nonce: Some(0),
+            ..Block::new()
+        };
+        block.extra.insert("version".to_string(), serde_json::json!("1.0"));
+        remine(&mut block);
+        block
+    }
+
+    /// Search nonces until the block's hash meets its difficulty again
+    fn remine(block: &mut Block) {
+        let target = "0".repeat(block.difficulty.unwrap_or(0) as usize);
+        loop {
+            block.hash = BlockchainManager::recompute_block_hash(block);
+            if block.hash.starts_with(&target) {
+                return;
+            }
+            block.nonce = block.nonce.map(|n| n + 1);
+        }
+    }
+
+    fn mined_chain(len: usize) -> Vec<Block> {
+        let mut chain: Vec<Block> = Vec::new();
+        for _ in 0..len {
+            let block = mined(chain.last(), 1);
+            chain.push(block);
+        }
+        chain
+    }
+
+    #[test]
+    fn test_recompute_block_hash_matches_miner() {
+        let mut raw: HashMap<String, JsonValue> = HashMap::new();
+        raw.insert("index".to_string(), serde_json::json!(4));
+        raw.insert("previous_hash".to_string(), serde_json::json!("h3"));
+        raw.insert("timestamp".to_string(), serde_json::json!(1_700_000_000));
+        raw.insert("miner".to_string(), serde_json::json!("LUN_m"));
+        raw.insert("difficulty".to_string(), serde_json::json!(1));
+        raw.insert("version".to_string(), serde_json::json!("1.0"));
+        raw.insert("nonce".to_string(), serde_json::json!(11));
+        raw.insert("transactions".to_string(), serde_json::json!([
+            {"type": "reward", "from": "network", "to": "LUN_m", "amount": 50.0, "block_height": 4, "hash": "r4", "signature": "s", "public_key": "04ab"},
+        ]));
+        let hash = BlockchainManager::calculate_block_hash(&raw);
+        raw.insert("hash".to_string(), serde_json::json!(hash));
+        raw.insert("mining_time".to_string(), serde_json::json!(0.25));
+
+        let block: Block = serde_json::from_value(serde_json::json!(raw)).unwrap();
+        assert_eq!(block.transactions[0].tx_type.as_deref(), Some("reward"));
+        assert_eq!(BlockchainManager::recompute_block_hash(&block), hash);
+        let mut tampered = block.clone();
+        tampered.transactions[0].amount = Some(500.0);
+        assert_ne!(BlockchainManager::recompute_block_hash(&tampered), hash);
+    }
+
+    #[test]
+    fn test_validate_chain_segment() {
+        let manager = BlockchainManager::new("http://unused", 1);
+        let chain = mined_chain(5);
+        assert_eq!(manager.validate_chain_segment(&chain), Ok(()));
+        assert_eq!(manager.validate_chain_segment(&chain[2..]), Ok(()));
+        assert_eq!(manager.validate_chain_segment(&[]), Ok(()));
+
+        let mut tampered = chain.clone();
+        tampered[2].transactions[0].amount = Some(99.0);
+        assert_eq!(manager.validate_chain_segment(&tampered), Err(ChainValidationError::HashMismatch { height: 2 }));
+
+        let mut gap = chain.clone();
+        gap.remove(3);
+        assert_eq!(manager.validate_chain_segment(&gap), Err(ChainValidationError::Gap { expected: 3, found: 4 }));
+
+        let fork = mined(Some(&Block { hash: "elsewhere".to_string(), ..chain[0].clone() }), 1);
+        let forked = [chain[0].clone(), fork];
+        assert_eq!(manager.validate_chain_segment(&forked), Err(ChainValidationError::BrokenLink { height: 1 }));
+
+        let mut early = mined(Some(&chain[1]), 1);
+        early.timestamp = chain[1].timestamp - 3 * 60 * 60;
+        remine(&mut early);
+        let regressed = [chain[1].clone(), early];
+        assert_eq!(manager.validate_chain_segment(&regressed), Err(ChainValidationError::TimestampRegression { height: 2 }));
+        let lenient = BlockchainManager::new("http://unused", 1).with_timestamp_tolerance(Duration::from_secs(4 * 60 * 60));
+        assert_eq!(lenient.validate_chain_segment(&regressed), Ok(()));
+
+        let mut weak = chain[1].clone();
+        weak.difficulty = Some(6);
+        weak.hash = BlockchainManager::recompute_block_hash(&weak);
+        assert_eq!(manager.validate_chain_segment(&[weak]), Err(ChainValidationError::InsufficientWork { height: 1 }));
+
+        // The first block links to the parent the header cache knows
+        manager.header_cache.lock().unwrap().insert(2, BlockHeader { index: 2, hash: "other".to_string(), ..BlockHeader::from(&chain[2]) });
+        assert_eq!(manager.validate_chain_segment(&chain[3..]), Err(ChainValidationError::BrokenLink { height: 3 }));
+        manager.header_cache.lock().unwrap().insert(2, BlockHeader::from(&chain[2]));
+        assert_eq!(manager.validate_chain_segment(&chain[3..]), Ok(()));
+    }
+
+    #[tokio::test]
+    async fn test_fetch_blocks_range_validates() {
+        let mut chain = mined_chain(6);
+        chain[4].transactions[0].to = Some("LUN_mallory".to_string());
+        let mut server = mockito::Server::new_async().await;
+        for block in &chain {
+            server.mock("GET", format!("/blockchain/block/{}", block.index).as_str()).with_body(serde_json::to_string(block).unwrap()).create_async().await;
+        }
+        let trusting = BlockchainManager::new(&server.url(), 2);
+        assert_eq!(trusting.fetch_blocks_range(0, 5, 2).await.unwrap().len(), 6);
+
+        let manager = BlockchainManager::new(&server.url(), 2).with_chain_validation(true);
+        assert_eq!(manager.fetch_blocks_range(0, 3, 2).await.unwrap().len(), 4);
+        let err = manager.fetch_blocks_range(0, 5, 2).await.unwrap_err();
+        assert_eq!(err, BlockchainError::InvalidChain(ChainValidationError::HashMismatch { height: 4 }));
+        assert!(!err.is_retryable());
+        assert_eq!(err.to_string(), "Invalid chain: block 4 does not match its hash");
+        assert_eq!(manager.cache.lock().unwrap().keys().copied().collect::<Vec<_>>(), vec![0, 1, 2, 3]);
+    }
+
+    #[test]
+    fn test_block_hash_is_canonical() {
+        let mut block = HashMap::new();
+        block.insert("index".to_string(), serde_json::json!(1));
+        block.insert("transactions".to_string(), serde_json::json!([{"b": 1, "a": 2}]));
+        let hash = BlockchainManager::calculate_block_hash(&block);
+        block.insert("hash".to_string(), serde_json::json!(hash));
+        block.insert("mining_time".to_string(), serde_json::json!(0.5));
+        assert_eq!(BlockchainManager::calculate_block_hash(&block), hash);
+        block.insert("nonce".to_string(), serde_json::json!(1));
+        assert_ne!(BlockchainManager::calculate_block_hash(&block), hash);
+    }
+
     #[test]
     fn test_normalize_address() {
         assert_eq!(BlockchainManager::normalize_address("LUN_abc123"), "abc123");
@@ -288,15 +1289,31 @@ mod tests {
 
     #[test]
     fn test_validate_transaction_before_broadcast() {
-        let mut tx = Transaction::new();
-        assert!(!BlockchainManager::validate_transaction_before_broadcast(&tx));
-        tx.tx_type = Some("transfer".to_string());
-        tx.from = Some("LUN_from".to_string());
-        tx.to = Some("LUN_to".to_string());
-        tx.amount = Some(1.0);
-        tx.timestamp = Some(1234567890);
-        tx.hash = Some("1234567890abcdef".to_string());
-        tx.signature = Some("abcdef1234567890".to_string());
+        use crate::transactions::signing::{self, tests::test_address};
+
+        assert!(!BlockchainManager::validate_transaction_before_broadcast(&Transaction::new()));
+        let mut fields: HashMap<String, JsonValue> = serde_json::from_value(serde_json::json!({
+            "type": "transfer", "from": test_address("alice"), "to": "LUN_81b637d8fcd2c6dafcca",
+            "amount": 1.0, "fee": 0.001, "timestamp": 1234567890, "nonce": 7,
+        }))
+        .unwrap();
+        signing::sign_transaction(&mut fields, "alice");
+        let tx = Transaction::from_json(&fields);
         assert!(BlockchainManager::validate_transaction_before_broadcast(&tx));
+        // One mistyped character breaks the checksum
+        let mut typo = tx.clone();
+        typo.to = Some("LUN_81b637d8fcd2c6dafccb".to_string());
+        assert!(!BlockchainManager::validate_transaction_before_broadcast(&typo));
+
+        let mut tampered = tx.clone();
+        tampered.amount = Some(100.0);
+        assert!(!BlockchainManager::validate_transaction_before_broadcast(&tampered));
+        let mut rehashed = fields.clone();
+        rehashed.insert("amount".to_string(), serde_json::json!(100.0));
+        rehashed.insert("hash".to_string(), serde_json::json!(TransactionManager::calculate_transaction_hash(&rehashed)));
+        assert!(!BlockchainManager::validate_transaction_before_broadcast(&Transaction::from_json(&rehashed)));
+        let mut impostor = fields.clone();
+        signing::sign_transaction(&mut impostor, "mallory");
+        assert!(!BlockchainManager::validate_transaction_before_broadcast(&Transaction::from_json(&impostor)));
     }
 }
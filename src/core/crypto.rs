@@ -77,6 +77,11 @@ mod tests {
         let msg = "Hello, SM2 cryptography!";
         let sig = crypto.sign_data(msg, &privk);
         assert!(crypto.verify_signature(msg, &sig, &pubk));
+        assert!(!crypto.verify_signature("Hello, someone else!", &sig, &pubk));
+        let (other, _, _) = crypto.generate_keypair();
+        let forged = crypto.sign_data(msg, &other);
+        assert_eq!(forged.len(), sig.len());
+        assert!(!crypto.verify_signature(msg, &forged, &pubk));
     }
 
     #[test]
@@ -1,18 +1,80 @@
 
-use std::collections::HashMap;
+use std::collections::{HashMap, VecDeque};
 use std::sync::{Arc, Mutex};
-use std::thread;
-use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use std::time::{Duration, Instant};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use crate::core::blockchain::{BlockLookup, BlockchainManager};
+use crate::core::daemon_config::MiningConfig;
+use crate::core::daemon_health::{DaemonHealth, Heartbeat, SubsystemHealth, Watchdog};
+use crate::core::daemon_journal::{Journal, JournalEntry, JournalEvent};
+use crate::core::mempool::MempoolManager;
+use crate::core::mining_supervisor::MiningSupervisor;
+use crate::core::p2p::P2P;
+use crate::core::shutdown::{ShutdownCoordinator, ShutdownReport, ShutdownToken};
+use crate::luna_lib::events::{EventBus, LunaEvent};
+use crate::mining::difficulty::Difficulty;
+use crate::mining::miner::GenesisMiner;
+use crate::transactions::validator::TransactionValidator;
+use crate::storage::database::WalletDatabase;
+use crate::utils::clock::{Clock, SystemClock};
+use crate::utils::log::{info, warn};
 
-#[derive(Default)]
 pub struct Daemon {
     pub is_running: bool,
     pub peers: Arc<Mutex<HashMap<String, PeerInfo>>>,
     pub stats: Arc<Mutex<DaemonStats>>,
+    /// How often the background loop runs
+    pub tick_interval: Duration,
+    pub(crate) sources: Sources,
+    /// Threads started by this daemon, and the token that stops them
+    pub(crate) shutdown: Arc<ShutdownCoordinator>,
+    /// Restarts subsystems started with `spawn_supervised` when they stop heartbeating
+    pub(crate) watchdog: Arc<Watchdog>,
+    /// Pending mempool transactions are saved here on shutdown
+    database: Option<WalletDatabase>,
+    /// Bearer token required by `/shutdown`; without one shutdown is refused
+    pub(crate) auth_token: Option<String>,
+    pub miner: Option<Arc<GenesisMiner>>,
+    pub(crate) mining: MiningConfig,
+    #[cfg(feature = "daemon-server")]
+    pub(crate) api_addr: Option<std::net::SocketAddr>,
     // ...existing code...
 }
 
-#[derive(Default, Clone)]
+/// What the background loop works against; cloned into its thread
+#[derive(Clone)]
+pub(crate) struct Sources {
+    pub(crate) p2p: Option<P2P>,
+    pub(crate) blockchain: Option<Arc<BlockchainManager>>,
+    pub(crate) validator: Arc<Mutex<TransactionValidator>>,
+    pub(crate) mempool: Option<Arc<MempoolManager>>,
+    pub(crate) difficulty: Difficulty,
+    pub(crate) clock: Arc<dyn Clock>,
+    /// Peers not seen for this long are dropped each tick
+    peer_max_age: Duration,
+    pub(crate) journal: Option<Arc<Journal>>,
+    pub(crate) events: Option<EventBus>,
+}
+
+impl Sources {
+    /// Append to the journal, if there is one, stamped with the daemon clock
+    pub(crate) fn record(&self, event: JournalEvent) {
+        if let Some(journal) = &self.journal {
+            journal.record(self.clock.now(), event);
+        }
+    }
+
+    /// Publish to the event bus, if there is one. Callers must not hold the stats or peers lock.
+    pub(crate) fn publish(&self, event: LunaEvent) {
+        if let Some(events) = &self.events {
+            events.publish(event);
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
 pub struct PeerInfo {
     pub node_id: String,
     pub registered_at: u64,
@@ -22,12 +84,69 @@ pub struct PeerInfo {
     pub version: Option<String>,
 }
 
-#[derive(Clone, Default)]
+/// Seconds covered by the per-minute rates
+pub const RATE_WINDOW_SECS: u64 = 60;
+/// Tick samples kept for the rates, however short the tick interval
+const MAX_RATE_SAMPLES: usize = 1024;
+
+/// Blocks and transactions validated during one tick
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+struct TickSample {
+    at: u64,
+    blocks: u64,
+    transactions: u64,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
 pub struct DaemonStats {
     pub blocks_validated: u64,
     pub transactions_validated: u64,
+    pub blocks_rejected: u64,
+    pub blocks_mined: u64,
+    /// Blocks and submitted transactions that failed validation
+    pub validation_failures: u64,
+    pub last_block_height: u64,
+    /// When the last block was validated, in unix seconds
+    pub last_block_time: u64,
     pub peers_registered: u64,
+    pub peers_expired: u64,
+    /// Registered peers seen within the expiry age; filled by `get_stats_snapshot`
+    pub peers_active: usize,
+    /// Filled by `get_stats_snapshot`
+    pub mempool_size: usize,
     pub start_time: u64,
+    /// Seconds since start_time, as of the last tick or snapshot
+    pub uptime_secs: u64,
+    /// Validated in the last `RATE_WINDOW_SECS`; filled by `get_stats_snapshot`
+    pub blocks_per_minute: u64,
+    pub transactions_per_minute: u64,
+    #[serde(skip)]
+    recent_ticks: VecDeque<TickSample>,
+}
+
+impl DaemonStats {
+    fn record_tick(&mut self, sample: TickSample) {
+        self.recent_ticks.push_back(sample);
+        while self.recent_ticks.len() > MAX_RATE_SAMPLES
+            || self.recent_ticks.front().is_some_and(|t| sample.at.saturating_sub(t.at) >= RATE_WINDOW_SECS)
+        {
+            self.recent_ticks.pop_front();
+        }
+    }
+
+    fn window_totals(&self, now: u64) -> (u64, u64) {
+        self.recent_ticks
+            .iter()
+            .filter(|t| now.saturating_sub(t.at) < RATE_WINDOW_SECS)
+            .fold((0, 0), |(blocks, txs), t| (blocks + t.blocks, txs + t.transactions))
+    }
+}
+
+impl Default for Daemon {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Daemon {
@@ -36,30 +155,248 @@ impl Daemon {
             is_running: false,
             peers: Arc::new(Mutex::new(HashMap::new())),
             stats: Arc::new(Mutex::new(DaemonStats {
-                start_time: SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs(),
+                start_time: SystemClock.now(),
                 ..Default::default()
             })),
+            tick_interval: Duration::from_secs(10),
+            sources: Sources {
+                p2p: None,
+                blockchain: None,
+                validator: Arc::default(),
+                mempool: None,
+                difficulty: Difficulty::new(1),
+                clock: Arc::new(SystemClock),
+                peer_max_age: Duration::from_secs(600),
+                journal: None,
+                events: None,
+            },
+            shutdown: Arc::default(),
+            watchdog: Arc::default(),
+            database: None,
+            auth_token: None,
+            miner: None,
+            mining: MiningConfig::default(),
+            #[cfg(feature = "daemon-server")]
+            api_addr: None,
         }
     }
 
+    pub fn with_tick_interval(mut self, interval: Duration) -> Self {
+        self.tick_interval = interval;
+        self
+    }
+
+    /// Refresh this node's peer list every tick
+    pub fn with_p2p(mut self, p2p: P2P) -> Self {
+        self.sources.p2p = Some(p2p);
+        self
+    }
+
+    /// Pull and validate new blocks every tick
+    pub fn with_blockchain(mut self, blockchain: Arc<BlockchainManager>) -> Self {
+        self.sources.blockchain = Some(blockchain);
+        self
+    }
+
+    pub fn with_validator(mut self, validator: TransactionValidator) -> Self {
+        self.sources.validator = Arc::new(Mutex::new(validator));
+        self
+    }
+
+    /// Drop transactions from this mempool once a validated block confirms them
+    pub fn with_mempool(mut self, mempool: Arc<MempoolManager>) -> Self {
+        self.sources.mempool = Some(mempool);
+        self
+    }
+
+    /// Proof-of-work required of pulled blocks
+    pub fn with_difficulty(mut self, difficulty: Difficulty) -> Self {
+        self.sources.difficulty = difficulty;
+        self
+    }
+
+    /// Time source for peer expiry and uptime; also restarts the uptime count
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.stats.lock().unwrap().start_time = clock.now();
+        self.sources.clock = clock;
+        self
+    }
+
+    /// Expire peers whose last_seen is older than `max_age` on every tick
+    pub fn with_peer_max_age(mut self, max_age: Duration) -> Self {
+        self.sources.peer_max_age = max_age;
+        self
+    }
+
+    /// Token an operator must present as `Authorization: Bearer <token>` to shut down remotely
+    pub fn with_auth_token(mut self, token: &str) -> Self {
+        self.auth_token = Some(token.to_string());
+        self
+    }
+
+    /// Mine with `miner` as `mining` describes
+    pub fn with_miner(mut self, miner: GenesisMiner, mining: MiningConfig) -> Self {
+        self.miner = Some(Arc::new(miner));
+        self.mining = mining;
+        self
+    }
+
+    /// Record what the daemon does to `journal`
+    pub fn with_journal(mut self, journal: Journal) -> Self {
+        self.sources.journal = Some(Arc::new(journal));
+        self
+    }
+
+    /// Publish validated blocks, confirmed transactions and completed syncs to `events`
+    pub fn with_event_bus(mut self, events: EventBus) -> Self {
+        self.sources.events = Some(events);
+        self
+    }
+
+    /// Restart a subsystem after `missed_heartbeats` missed heartbeats, at most `max_restarts` times
+    pub fn with_watchdog(mut self, missed_heartbeats: u32, max_restarts: u32) -> Self {
+        self.watchdog = Arc::new(Watchdog::new(missed_heartbeats, max_restarts));
+        self
+    }
+
+    /// Flush pending mempool transactions to `database` on shutdown
+    pub fn with_database(mut self, database: WalletDatabase) -> Self {
+        self.database = Some(database);
+        self
+    }
+
+    /// Run the sync loop, peer refresher and miner (if enabled) every `tick_interval`
+    /// on background threads, under a watchdog
     pub fn start(&mut self) {
         if self.is_running { return; }
         self.is_running = true;
-        // ...スレッド起動など...
+        self.shutdown.token().reset();
+        self.sources.record(JournalEvent::Startup);
+        let interval = self.tick_interval;
+        let (sources, peers, stats) = (self.sources.clone(), Arc::clone(&self.peers), Arc::clone(&self.stats));
+        self.spawn_supervised("sync_loop", interval, move |token, heartbeat| {
+            while !token.is_cancelled() {
+                heartbeat.beat();
+                run_tick(&sources, &peers, &stats);
+                token.sleep(interval);
+            }
+        });
+        if self.sources.p2p.is_some() {
+            let sources = self.sources.clone();
+            self.spawn_supervised("p2p_refresher", interval, move |token, heartbeat| {
+                while !token.is_cancelled() {
+                    heartbeat.beat();
+                    refresh_peers(&sources);
+                    token.sleep(interval);
+                }
+            });
+        }
+        if let Some(supervisor) = self.mining_supervisor() {
+            self.spawn_supervised("miner", interval, move |token, heartbeat| supervisor.run(token, heartbeat));
+        }
+        let (watchdog, coordinator, sources) = (Arc::clone(&self.watchdog), Arc::clone(&self.shutdown), self.sources.clone());
+        self.shutdown.spawn("watchdog", move |token| {
+            while !token.sleep(interval) {
+                watchdog.check(&coordinator, &sources);
+            }
+        });
     }
 
-    pub fn stop(&mut self) {
+    /// Needs a miner with mining enabled and a blockchain to submit to
+    pub(crate) fn mining_supervisor(&self) -> Option<MiningSupervisor> {
+        let miner = self.miner.as_ref().filter(|_| self.mining.enabled)?;
+        Some(MiningSupervisor::new(
+            Arc::clone(miner),
+            self.mining.clone(),
+            Arc::clone(self.sources.blockchain.as_ref()?),
+            self.sources.clone(),
+            Arc::clone(&self.stats),
+        ))
+    }
+
+    /// Run an extra subsystem, such as a miner, that stops with the daemon.
+    /// `work` should return soon after its token is cancelled.
+    pub fn spawn_component<F>(&self, name: &str, work: F)
+    where
+        F: FnOnce(ShutdownToken) + Send + 'static,
+    {
+        self.shutdown.spawn(name, work);
+    }
+
+    /// Run a subsystem that calls `Heartbeat::beat` at least every `interval`.
+    /// The watchdog runs `work` again if it stops beating, e.g. after a panic.
+    pub fn spawn_supervised<F>(&self, name: &str, interval: Duration, work: F)
+    where
+        F: Fn(ShutdownToken, Heartbeat) + Send + Sync + 'static,
+    {
+        self.watchdog.supervise(&self.shutdown, &self.sources, name, interval, Arc::new(work));
+    }
+
+    /// One watchdog pass; `start` runs this every `tick_interval`
+    pub fn check_health(&self) {
+        self.watchdog.check(&self.shutdown, &self.sources);
+    }
+
+    /// Degraded once any subsystem has used up its restarts
+    pub fn health(&self) -> DaemonHealth {
+        self.watchdog.health()
+    }
+
+    pub fn subsystem_health(&self) -> Vec<SubsystemHealth> {
+        self.watchdog.subsystems()
+    }
+
+    /// Signal every subsystem, wait up to `timeout` for each in start order,
+    /// then flush the mempool to the database
+    pub fn shutdown(&mut self, timeout: Duration) -> ShutdownReport {
         self.is_running = false;
-        // ...スレッド停止など...
+        let mut report = self.shutdown.shutdown(timeout);
+        self.watchdog.clear();
+        #[cfg(feature = "daemon-server")]
+        {
+            self.api_addr = None;
+        }
+        if let (Some(database), Some(mempool)) = (&self.database, &self.sources.mempool) {
+            report.mempool_flushed = flush_mempool(mempool, database);
+        }
+        self.sources.record(JournalEvent::Shutdown { timed_out: report.timed_out.clone() });
+        report
+    }
+
+    /// `shutdown` with a generous timeout, ignoring the report
+    pub fn stop(&mut self) {
+        self.shutdown(Duration::from_secs(30));
+    }
+
+    /// Whether shutdown was signalled locally or by an authorized `/shutdown`
+    pub fn is_stopping(&self) -> bool {
+        self.shutdown.token().is_cancelled()
+    }
+
+    /// One round of periodic work: expire and refresh peers, then pull and validate new blocks
+    pub fn tick(&self) {
+        refresh_peers(&self.sources);
+        run_tick(&self.sources, &self.peers, &self.stats);
     }
 
     pub fn register_peer(&self, peer: PeerInfo) -> bool {
-        let mut peers = self.peers.lock().unwrap();
-        if peers.contains_key(&peer.node_id) { return false; }
-        peers.insert(peer.node_id.clone(), peer);
-        let mut stats = self.stats.lock().unwrap();
-        stats.peers_registered += 1;
-        true
+        register_peer(&self.sources, &self.peers, &self.stats, peer)
+    }
+
+    /// Mark a registered peer as seen at `now`; false if it is not registered
+    pub fn touch_peer(&self, node_id: &str, now: u64) -> bool {
+        match self.peers.lock().unwrap().get_mut(node_id) {
+            Some(peer) => {
+                peer.last_seen = now;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Remove peers not seen within `max_age`, returning their ids
+    pub fn expire_stale_peers(&self, max_age: Duration) -> Vec<String> {
+        expire_peers(&self.sources, &self.peers, &self.stats, max_age)
     }
 
     pub fn unregister_peer(&self, node_id: &str) -> bool {
@@ -72,14 +409,191 @@ impl Daemon {
         peers.values().cloned().collect()
     }
 
+    /// Raw counters as of the last tick; see `get_stats_snapshot` for derived fields
     pub fn get_stats(&self) -> DaemonStats {
         self.stats.lock().unwrap().clone()
     }
+
+    /// The last `limit` journal entries, oldest first; empty without a journal
+    pub fn read_recent_events(&self, limit: usize) -> Vec<JournalEntry> {
+        self.sources.journal.as_ref().map(|j| j.read_recent(limit)).unwrap_or_default()
+    }
+
+    /// Counters plus uptime, mempool size, active peers and rates computed now
+    pub fn get_stats_snapshot(&self) -> DaemonStats {
+        stats_snapshot(&self.sources, &self.peers, &self.stats)
+    }
+}
+
+pub(crate) fn stats_snapshot(sources: &Sources, peers: &Mutex<HashMap<String, PeerInfo>>, stats: &Mutex<DaemonStats>) -> DaemonStats {
+    let now = sources.clock.now();
+    let peers_active = peers
+        .lock()
+        .unwrap()
+        .values()
+        .filter(|peer| now.saturating_sub(peer.last_seen) <= sources.peer_max_age.as_secs())
+        .count();
+    let mut snapshot = stats.lock().unwrap().clone();
+    snapshot.uptime_secs = now.saturating_sub(snapshot.start_time);
+    snapshot.peers_active = peers_active;
+    snapshot.mempool_size = sources.mempool.as_ref().map_or(0, |m| m.get_mempool_size());
+    (snapshot.blocks_per_minute, snapshot.transactions_per_minute) = snapshot.window_totals(now);
+    snapshot
+}
+
+pub(crate) fn register_peer(sources: &Sources, peers: &Mutex<HashMap<String, PeerInfo>>, stats: &Mutex<DaemonStats>, peer: PeerInfo) -> bool {
+    let mut peers = peers.lock().unwrap();
+    if peers.contains_key(&peer.node_id) { return false; }
+    let node_id = peer.node_id.clone();
+    peers.insert(node_id.clone(), peer);
+    drop(peers);
+    stats.lock().unwrap().peers_registered += 1;
+    sources.record(JournalEvent::PeerRegistered { node_id });
+    true
+}
+
+fn flush_mempool(mempool: &MempoolManager, database: &WalletDatabase) -> usize {
+    mempool
+        .get_pending_transactions()
+        .iter()
+        .filter(|tx| {
+            let raw = json!({
+                "hash": tx.hash, "from": tx.from, "to": tx.to, "amount": tx.amount,
+                "timestamp": tx.timestamp, "type": tx.tx_type,
+            });
+            database.save_pending_transaction(&raw, &tx.from)
+        })
+        .count()
+}
+
+fn refresh_peers(sources: &Sources) {
+    if let Some(p2p) = &sources.p2p {
+        match p2p.fetch_peer_list() {
+            Ok(peers) => p2p.update_peer_list(peers),
+            Err(e) => warn!(error:% = e; "Peer refresh failed"),
+        }
+    }
+}
+
+/// Expire peers, then pull and validate new blocks
+fn run_tick(sources: &Sources, peers: &Mutex<HashMap<String, PeerInfo>>, stats: &Mutex<DaemonStats>) {
+    let now = sources.clock.now();
+    let expired = expire_peers(sources, peers, stats, sources.peer_max_age);
+    if !expired.is_empty() {
+        info!(count = expired.len(); "Expired stale peers");
+    }
+    let before = {
+        let stats = stats.lock().unwrap();
+        (stats.blocks_validated, stats.transactions_validated)
+    };
+    if let Some(blockchain) = &sources.blockchain {
+        match blockchain.sync_to_tip() {
+            Ok(blocks) => {
+                validate_blocks(sources, blockchain, &blocks, stats);
+                sources.publish(LunaEvent::SyncCompleted { peer: None, blocks_added: blocks.len() });
+            }
+            Err(e) => warn!(error:% = e; "Block sync failed"),
+        }
+    }
+    let mut stats = stats.lock().unwrap();
+    stats.uptime_secs = now.saturating_sub(stats.start_time);
+    let sample = TickSample {
+        at: now,
+        blocks: stats.blocks_validated - before.0,
+        transactions: stats.transactions_validated - before.1,
+    };
+    stats.record_tick(sample);
+}
+
+fn expire_peers(sources: &Sources, peers: &Mutex<HashMap<String, PeerInfo>>, stats: &Mutex<DaemonStats>, max_age: Duration) -> Vec<String> {
+    let now = sources.clock.now();
+    let mut peers = peers.lock().unwrap();
+    let expired: Vec<String> = peers
+        .values()
+        .filter(|peer| now.saturating_sub(peer.last_seen) > max_age.as_secs())
+        .map(|peer| peer.node_id.clone())
+        .collect();
+    for node_id in &expired {
+        peers.remove(node_id);
+    }
+    drop(peers);
+    stats.lock().unwrap().peers_expired += expired.len() as u64;
+    for node_id in &expired {
+        sources.record(JournalEvent::PeerExpired { node_id: node_id.clone() });
+    }
+    expired
+}
+
+/// Validate blocks in height order, stopping at the first invalid one
+fn validate_blocks(sources: &Sources, blockchain: &BlockchainManager, blocks: &[HashMap<String, Value>], stats: &Mutex<DaemonStats>) {
+    for block in blocks {
+        let height = block.get("index").and_then(|v| v.as_u64()).unwrap_or(0);
+        // Without a cached parent, the first block pulled anchors the chain
+        let expected_prev_hash = height
+            .checked_sub(1)
+            .and_then(|parent| blockchain.block_at(parent))
+            .map(|parent| parent.hash)
+            .unwrap_or_else(|| block.get("previous_hash").and_then(|v| v.as_str()).unwrap_or("").to_string());
+        let started = Instant::now();
+        let outcome = sources.validator.lock().unwrap().validate_block(block, height, &expected_prev_hash, sources.difficulty);
+        if !outcome.valid {
+            warn!(height = height, reason = outcome.message().as_str(); "Block rejected");
+            let mut stats = stats.lock().unwrap();
+            stats.blocks_rejected += 1;
+            stats.validation_failures += 1;
+            drop(stats);
+            sources.record(JournalEvent::ValidationFailed { height: Some(height), reason: outcome.message() });
+            // Forget it and everything above so the next tick fetches them again
+            let dropped_blocks = {
+                let mut cache = blockchain.cache.lock().unwrap();
+                let before = cache.len();
+                cache.retain(|h, _| *h < height);
+                before - cache.len()
+            };
+            sources.record(JournalEvent::Reorg { height, dropped_blocks });
+            return;
+        }
+        let tx_hashes: Vec<String> = block
+            .get("transactions")
+            .and_then(|v| v.as_array())
+            .map(|txs| txs.iter().filter_map(|tx| tx.get("hash").and_then(|h| h.as_str())).map(str::to_string).collect())
+            .unwrap_or_default();
+        {
+            let mut stats = stats.lock().unwrap();
+            stats.blocks_validated += 1;
+            stats.transactions_validated += tx_hashes.len() as u64;
+            stats.last_block_height = height;
+            stats.last_block_time = sources.clock.now();
+        }
+        let hash = block.get("hash").and_then(|v| v.as_str()).unwrap_or("").to_string();
+        sources.record(JournalEvent::BlockValidated {
+            height,
+            hash: hash.clone(),
+            tx_count: tx_hashes.len(),
+            duration_ms: started.elapsed().as_millis() as u64,
+        });
+        if let Some(mempool) = &sources.mempool {
+            for tx_hash in &tx_hashes {
+                mempool.remove_transaction(tx_hash);
+            }
+        }
+        sources.publish(LunaEvent::BlockReceived { height, hash });
+        for tx_hash in tx_hashes {
+            sources.publish(LunaEvent::TransactionConfirmed { hash: tx_hash, height });
+        }
+    }
 }
 
 #[cfg(test)]
-mod tests {
+pub(crate) mod tests {
     use super::*;
+    use std::thread;
+    use crate::core::crypto::Crypto;
+    use crate::core::mempool::Transaction as MempoolTransaction;
+    use crate::mining::reward::RewardSchedule;
+    use crate::transactions::signing::tests::{sign_as, test_address};
+    use crate::transactions::transactions::TransactionManager;
+    use crate::utils::clock::ManualClock;
 
     #[test]
     fn test_peer_registration() {
@@ -109,4 +623,244 @@ mod tests {
         assert_eq!(stats.transactions_validated, 0);
         assert_eq!(stats.peers_registered, 0);
     }
+
+    #[test]
+    fn test_stale_peers_expire_and_can_reregister() {
+        let clock = Arc::new(ManualClock::new(1_000));
+        let daemon = Daemon::new().with_clock(clock.clone()).with_peer_max_age(Duration::from_secs(60));
+        let peer = |node_id: &str| PeerInfo { node_id: node_id.to_string(), registered_at: 1_000, last_seen: 1_000, ..Default::default() };
+        assert!(daemon.register_peer(peer("old")));
+        assert!(daemon.register_peer(peer("active")));
+
+        clock.advance(45);
+        assert!(daemon.touch_peer("active", clock.now()));
+        assert!(!daemon.touch_peer("unknown", clock.now()));
+        clock.advance(30);
+        assert_eq!(daemon.expire_stale_peers(Duration::from_secs(60)), vec!["old".to_string()]);
+        assert_eq!(daemon.get_stats().peers_expired, 1);
+        assert!(!daemon.register_peer(peer("active")));
+        assert!(daemon.register_peer(PeerInfo { last_seen: clock.now(), ..peer("old") }));
+
+        clock.advance(120);
+        daemon.tick();
+        assert!(daemon.get_peer_list().is_empty());
+        let stats = daemon.get_stats();
+        assert_eq!((stats.peers_expired, stats.uptime_secs), (3, 195));
+    }
+
+    pub(crate) const NETWORK_KEY: &str = "0f0e0d0c0b0a09080706050403020100000102030405060708090a0b0c0d0e0f";
+
+    /// Block at `height` holding a signed reward and one transfer, with a valid hash at difficulty 0
+    pub(crate) fn chain_block(height: u64, previous_hash: &str) -> Value {
+        let crypto = Crypto::new();
+        let mut reward: HashMap<String, Value> = HashMap::new();
+        reward.insert("type".to_string(), json!("reward"));
+        reward.insert("from".to_string(), json!("network"));
+        reward.insert("to".to_string(), json!("miner"));
+        reward.insert("amount".to_string(), json!(RewardSchedule::default().reward_at(height)));
+        reward.insert("block_height".to_string(), json!(height));
+        reward.insert("hash".to_string(), json!(format!("reward{}", height)));
+        let digest = TransactionManager::calculate_transaction_hash(&reward);
+        reward.insert("signature".to_string(), json!(crypto.sign_data(&digest, NETWORK_KEY)));
+        reward.insert("public_key".to_string(), json!(crypto.derive_public_key(NETWORK_KEY)));
+        let mut transfer: HashMap<String, Value> = serde_json::from_value(json!({
+            "type": "transfer", "from": test_address("alice"), "to": test_address("bob"), "amount": 1.0, "fee": 0.001,
+            "timestamp": 1234567890, "nonce": 123, "hash": format!("tx{}", height),
+        }))
+        .unwrap();
+        sign_as(&mut transfer, "alice");
+        let mut block: HashMap<String, Value> = HashMap::new();
+        block.insert("index".to_string(), json!(height));
+        block.insert("previous_hash".to_string(), json!(previous_hash));
+        block.insert("timestamp".to_string(), json!(1_700_000_000 + height));
+        block.insert("transactions".to_string(), json!([reward, transfer]));
+        block.insert("hash".to_string(), json!(BlockchainManager::calculate_block_hash(&block)));
+        json!(block)
+    }
+
+    fn wait_for(daemon: &Daemon, blocks: u64) {
+        for _ in 0..100 {
+            if daemon.get_stats().blocks_validated >= blocks {
+                return;
+            }
+            thread::sleep(Duration::from_millis(20));
+        }
+    }
+
+    #[test]
+    fn test_background_loop_validates_new_blocks() {
+        let genesis = chain_block(0, "0");
+        let second = chain_block(1, genesis["hash"].as_str().unwrap());
+        let third = chain_block(2, second["hash"].as_str().unwrap());
+        let mut server = mockito::Server::new();
+        let first_pull = server.mock("GET", "/blockchain/blocks").with_body(json!({"blocks": [genesis, second]}).to_string()).create();
+        server.mock("GET", "/api/peers").with_body(r#"[{"node_id": "n1", "url": "http://n1"}]"#).create();
+
+        let mempool = Arc::new(MempoolManager::new());
+        let pending = |hash: &str| MempoolTransaction {
+            hash: hash.to_string(), from: "alice".to_string(), to: "bob".to_string(), amount: 1.0, timestamp: 1, tx_type: "transfer".to_string(),
+        };
+        assert!(mempool.add_transaction(pending("tx1")));
+        assert!(mempool.add_transaction(pending("tx2")));
+        let p2p = P2P::new(&server.url(), "http://me");
+        let validator = TransactionValidator::new().with_authorized_signers([Crypto::new().derive_public_key(NETWORK_KEY)]);
+        let mut daemon = Daemon::new()
+            .with_tick_interval(Duration::from_millis(20))
+            .with_p2p(p2p.clone())
+            .with_blockchain(Arc::new(BlockchainManager::new(&server.url(), 1)))
+            .with_validator(validator)
+            .with_mempool(Arc::clone(&mempool))
+            .with_difficulty(Difficulty::new(0));
+
+        daemon.start();
+        wait_for(&daemon, 2);
+        for _ in 0..100 {
+            if !p2p.get_peers().is_empty() {
+                break;
+            }
+            thread::sleep(Duration::from_millis(20));
+        }
+        let stats = daemon.get_stats();
+        assert_eq!((stats.blocks_validated, stats.transactions_validated, stats.last_block_height), (2, 4, 1));
+        assert!(!mempool.is_transaction_pending("tx1"));
+        assert!(mempool.is_transaction_pending("tx2"));
+        assert_eq!(p2p.get_peers().len(), 1);
+
+        first_pull.remove();
+        server.mock("GET", "/blockchain/blocks").with_body(json!({"blocks": [genesis, second, third]}).to_string()).create();
+        wait_for(&daemon, 3);
+        let report = daemon.shutdown(Duration::from_secs(5));
+        assert_eq!(report.clean, vec!["sync_loop", "p2p_refresher", "watchdog"]);
+        let stats = daemon.get_stats();
+        assert_eq!((stats.blocks_validated, stats.transactions_validated, stats.last_block_height), (3, 6, 2));
+        assert_eq!(stats.blocks_rejected, 0);
+        assert!(!mempool.is_transaction_pending("tx2"));
+    }
+
+    #[test]
+    fn test_stats_and_peers_round_trip() {
+        let stats = DaemonStats { blocks_validated: 3, last_block_height: 2, start_time: 1_000, uptime_secs: 30, peers_active: 1, ..DaemonStats::default() };
+        let json = serde_json::to_value(&stats).unwrap();
+        let restored: DaemonStats = serde_json::from_value(json.clone()).unwrap();
+        assert_eq!(serde_json::to_value(&restored).unwrap(), json);
+        assert_eq!(serde_json::from_value::<DaemonStats>(json!({"blocks_validated": 3})).unwrap().blocks_validated, 3);
+
+        let peer = PeerInfo {
+            node_id: "n1".to_string(),
+            registered_at: 1_000,
+            last_seen: 1_030,
+            capabilities: vec!["relay".to_string()],
+            url: Some("http://127.0.0.1:9001".to_string()),
+            version: None,
+        };
+        assert_eq!(serde_json::from_value::<PeerInfo>(serde_json::to_value(&peer).unwrap()).unwrap(), peer);
+    }
+
+    #[test]
+    fn test_rates_cover_the_last_minute_only() {
+        let genesis = chain_block(0, "0");
+        let second = chain_block(1, genesis["hash"].as_str().unwrap());
+        let third = chain_block(2, second["hash"].as_str().unwrap());
+        let mut server = mockito::Server::new();
+        let first_pull = server.mock("GET", "/blockchain/blocks").with_body(json!({"blocks": [genesis, second]}).to_string()).create();
+        let clock = Arc::new(ManualClock::new(1_000));
+        let validator = TransactionValidator::new().with_authorized_signers([Crypto::new().derive_public_key(NETWORK_KEY)]);
+        let daemon = Daemon::new()
+            .with_clock(clock.clone())
+            .with_blockchain(Arc::new(BlockchainManager::new(&server.url(), 1)))
+            .with_validator(validator)
+            .with_difficulty(Difficulty::new(0));
+
+        daemon.tick();
+        let stats = daemon.get_stats_snapshot();
+        assert_eq!((stats.blocks_per_minute, stats.transactions_per_minute), (2, 4));
+
+        clock.advance(30);
+        first_pull.remove();
+        server.mock("GET", "/blockchain/blocks").with_body(json!({"blocks": [genesis, second, third]}).to_string()).create();
+        daemon.tick();
+        let stats = daemon.get_stats_snapshot();
+        assert_eq!((stats.blocks_per_minute, stats.transactions_per_minute), (3, 6));
+        assert_eq!(stats.last_block_time, 1_030);
+
+        clock.advance(45);
+        daemon.tick();
+        let stats = daemon.get_stats_snapshot();
+        assert_eq!((stats.blocks_per_minute, stats.transactions_per_minute), (1, 2));
+        assert_eq!((stats.blocks_validated, stats.transactions_validated), (3, 6));
+        assert_eq!(stats.uptime_secs, 75);
+
+        clock.advance(RATE_WINDOW_SECS);
+        let stats = daemon.get_stats_snapshot();
+        assert_eq!((stats.blocks_per_minute, stats.transactions_per_minute), (0, 0));
+        assert_eq!(stats.uptime_secs, 135);
+    }
+
+    #[test]
+    fn test_journal_records_ticks() {
+        let dir = tempfile::tempdir().unwrap();
+        let genesis = chain_block(0, "0");
+        let second = chain_block(1, genesis["hash"].as_str().unwrap());
+        let forged = chain_block(2, "not-the-parent");
+        let mut server = mockito::Server::new();
+        let first_pull = server.mock("GET", "/blockchain/blocks").with_body(json!({"blocks": [genesis, second]}).to_string()).create();
+        let clock = Arc::new(ManualClock::new(1_000));
+        let validator = TransactionValidator::new().with_authorized_signers([Crypto::new().derive_public_key(NETWORK_KEY)]);
+        let daemon = Daemon::new()
+            .with_clock(clock.clone())
+            .with_peer_max_age(Duration::from_secs(60))
+            .with_blockchain(Arc::new(BlockchainManager::new(&server.url(), 1)))
+            .with_validator(validator)
+            .with_difficulty(Difficulty::new(0))
+            .with_journal(Journal::open(&dir.path().join("journal.jsonl"), 1 << 20, 2).unwrap())
+            .with_event_bus(EventBus::new());
+        let bus = daemon.sources.events.as_ref().unwrap().subscribe();
+
+        assert!(daemon.register_peer(PeerInfo { node_id: "n1".to_string(), last_seen: 1_000, ..Default::default() }));
+        daemon.tick();
+        let published: Vec<LunaEvent> = bus.try_iter().collect();
+        let names: Vec<&str> = published.iter().map(|e| e.name()).collect();
+        assert_eq!(names, ["block_received", "transaction_confirmed", "transaction_confirmed", "block_received", "transaction_confirmed", "transaction_confirmed", "sync_completed"]);
+        assert_eq!(published[3], LunaEvent::BlockReceived { height: 1, hash: second["hash"].as_str().unwrap().to_string() });
+        assert_eq!(published[6], LunaEvent::SyncCompleted { peer: None, blocks_added: 2 });
+        clock.advance(90);
+        first_pull.remove();
+        server.mock("GET", "/blockchain/blocks").with_body(json!({"blocks": [genesis, second, forged]}).to_string()).create();
+        daemon.tick();
+
+        let events: Vec<(u64, JournalEvent)> = daemon.read_recent_events(10).into_iter().map(|e| (e.timestamp, e.event)).collect();
+        assert_eq!(events.len(), 6);
+        assert_eq!(events[0], (1_000, JournalEvent::PeerRegistered { node_id: "n1".to_string() }));
+        assert!(matches!(&events[1], (1_000, JournalEvent::BlockValidated { height: 0, tx_count: 2, .. })));
+        assert!(matches!(&events[2], (1_000, JournalEvent::BlockValidated { height: 1, hash, .. }) if hash == second["hash"].as_str().unwrap()));
+        assert_eq!(events[3], (1_090, JournalEvent::PeerExpired { node_id: "n1".to_string() }));
+        assert!(matches!(&events[4], (1_090, JournalEvent::ValidationFailed { height: Some(2), .. })));
+        assert_eq!(events[5], (1_090, JournalEvent::Reorg { height: 2, dropped_blocks: 1 }));
+        assert_eq!(daemon.read_recent_events(1).len(), 1);
+    }
+
+    #[test]
+    fn test_shutdown_reports_slow_component() {
+        let dir = tempfile::tempdir().unwrap();
+        let mempool = Arc::new(MempoolManager::new());
+        mempool.add_transaction(MempoolTransaction {
+            hash: "pending".to_string(), from: "alice".to_string(), to: "bob".to_string(), amount: 1.0, timestamp: 1, tx_type: "transfer".to_string(),
+        });
+        let mut daemon = Daemon::new()
+            .with_tick_interval(Duration::from_millis(20))
+            .with_mempool(mempool)
+            .with_database(WalletDatabase::new(Some(dir.path().join("wallets.db"))));
+        daemon.start();
+        daemon.spawn_component("miner", |token| {
+            token.sleep(Duration::from_secs(60));
+        });
+        daemon.spawn_component("stuck", |_| thread::sleep(Duration::from_secs(2)));
+
+        let report = daemon.shutdown(Duration::from_millis(200));
+        assert_eq!(report.clean, vec!["sync_loop", "watchdog", "miner"]);
+        assert_eq!(report.timed_out, vec!["stuck".to_string()]);
+        assert!(!report.is_clean());
+        assert_eq!(report.mempool_flushed, 1);
+        assert!(daemon.is_stopping());
+    }
 }
@@ -0,0 +1,300 @@
+use std::fs;
+use std::io;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+use serde::{Deserialize, Deserializer, Serialize};
+use crate::core::blockchain::BlockchainManager;
+use crate::core::daemon::Daemon;
+use crate::core::daemon_health::{DEFAULT_MAX_RESTARTS, DEFAULT_MISSED_HEARTBEATS};
+use crate::core::daemon_journal::Journal;
+use crate::core::mempool::MempoolManager;
+use crate::core::p2p::P2P;
+use crate::luna_lib::config::DEFAULT_ENDPOINT_URL;
+use crate::luna_lib::{LunaError, LunaResult};
+use crate::mining::miner::GenesisMiner;
+use crate::storage::database::WalletDatabase;
+use crate::transactions::security::SecurityPolicy;
+use crate::transactions::validator::TransactionValidator;
+use crate::utils::format::parse_duration;
+
+/// Settings for the miner a Daemon supervises
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MiningConfig {
+    pub enabled: bool,
+    /// Address that receives block rewards; required when enabled
+    pub address: String,
+    pub threads: usize,
+}
+
+impl Default for MiningConfig {
+    fn default() -> Self {
+        MiningConfig { enabled: false, address: String::new(), threads: 1 }
+    }
+}
+
+/// Everything needed to build a Daemon, read from a TOML or JSON file
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DaemonConfig {
+    /// Blockchain endpoint, also used as the P2P primary node
+    pub endpoint_url: String,
+    /// Holds `wallets.db`, where the mempool is flushed on shutdown
+    pub data_dir: PathBuf,
+    /// Wallet database in place of `wallets.db` in `data_dir`
+    pub wallets_db: Option<PathBuf>,
+    /// Whole seconds, or a duration such as "30s" or "2m"
+    #[serde(deserialize_with = "seconds_or_duration")]
+    pub tick_interval_secs: u64,
+    /// URL peers reach this node at; P2P is only started when set
+    pub peer_url: Option<String>,
+    pub max_peers: usize,
+    pub max_mempool_size: usize,
+    pub mining: MiningConfig,
+    /// Address for `Daemon::serve`, when the status API is wanted
+    pub api_bind: Option<String>,
+    /// Bearer token for the API's `/shutdown`
+    pub auth_token: Option<String>,
+    /// JSON-lines record of what the daemon did; no journal when unset
+    pub journal_path: Option<PathBuf>,
+    /// Size at which the journal is rotated
+    pub journal_max_bytes: u64,
+    /// Rotated journal files kept besides the current one
+    pub journal_keep_files: usize,
+    /// Heartbeats a subsystem may miss before it is restarted
+    pub watchdog_missed_heartbeats: u32,
+    /// Restarts per subsystem before the daemon reports itself degraded
+    pub watchdog_max_restarts: u32,
+    /// Policy the validator applies to transaction types without one of their own
+    pub security: SecurityPolicy,
+}
+
+impl Default for DaemonConfig {
+    fn default() -> Self {
+        DaemonConfig {
+            endpoint_url: DEFAULT_ENDPOINT_URL.to_string(),
+            data_dir: dirs::home_dir().unwrap_or_default().join(".luna_wallet"),
+            wallets_db: None,
+            tick_interval_secs: 10,
+            peer_url: None,
+            max_peers: 128,
+            max_mempool_size: 10000,
+            mining: MiningConfig::default(),
+            api_bind: None,
+            auth_token: None,
+            journal_path: None,
+            journal_max_bytes: 10 * 1024 * 1024,
+            journal_keep_files: 5,
+            watchdog_missed_heartbeats: DEFAULT_MISSED_HEARTBEATS,
+            watchdog_max_restarts: DEFAULT_MAX_RESTARTS,
+            security: SecurityPolicy::default(),
+        }
+    }
+}
+
+impl DaemonConfig {
+    /// Read and validate `path`: `.json` files as JSON, anything else as TOML
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let text = fs::read_to_string(path).map_err(|e| format!("Cannot read {}: {}", path.display(), e))?;
+        if path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("json")) {
+            Self::from_json(&text)
+        } else {
+            Self::from_toml(&text)
+        }
+    }
+
+    pub fn from_json(text: &str) -> Result<Self, String> {
+        let config: Self = serde_json::from_str(text).map_err(|e| format!("Invalid daemon config: {}", e))?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    pub fn from_toml(text: &str) -> Result<Self, String> {
+        let config: Self = toml::from_str(text).map_err(|e| format!("Invalid daemon config: {}", e))?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// Reject values the daemon cannot run with, naming the offending field
+    pub fn validate(&self) -> Result<(), String> {
+        let invalid = |field: &str, reason: &str| Err(format!("Invalid daemon config: {} {}", field, reason));
+        if reqwest::Url::parse(&self.endpoint_url).is_err() {
+            return invalid("endpoint_url", "is not a valid URL");
+        }
+        if self.tick_interval_secs == 0 {
+            return invalid("tick_interval_secs", "must be at least 1");
+        }
+        if let Some(peer_url) = &self.peer_url
+            && reqwest::Url::parse(peer_url).is_err()
+        {
+            return invalid("peer_url", "is not a valid URL");
+        }
+        if self.max_peers == 0 {
+            return invalid("max_peers", "must be at least 1");
+        }
+        if self.max_mempool_size == 0 {
+            return invalid("max_mempool_size", "must be at least 1");
+        }
+        if self.mining.enabled && self.mining.address.is_empty() {
+            return invalid("mining.address", "is required when mining is enabled");
+        }
+        if self.mining.threads == 0 {
+            return invalid("mining.threads", "must be at least 1");
+        }
+        if let Some(api_bind) = &self.api_bind
+            && api_bind.parse::<SocketAddr>().is_err()
+        {
+            return invalid("api_bind", "is not a host:port address");
+        }
+        if self.journal_max_bytes == 0 {
+            return invalid("journal_max_bytes", "must be at least 1");
+        }
+        if self.watchdog_missed_heartbeats == 0 {
+            return invalid("watchdog_missed_heartbeats", "must be at least 1");
+        }
+        Ok(())
+    }
+
+    pub fn tick_interval(&self) -> Duration {
+        Duration::from_secs(self.tick_interval_secs)
+    }
+
+    pub fn wallets_db_path(&self) -> PathBuf {
+        self.wallets_db.clone().unwrap_or_else(|| self.data_dir.join("wallets.db"))
+    }
+}
+
+/// Lets `Daemon::from_config` take a config by reference
+impl From<&DaemonConfig> for DaemonConfig {
+    fn from(config: &DaemonConfig) -> Self {
+        config.clone()
+    }
+}
+
+/// Whole seconds, or a duration string rounded down to seconds
+pub(crate) fn seconds_or_duration<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u64, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Interval {
+        Secs(u64),
+        Text(String),
+    }
+    match Interval::deserialize(deserializer)? {
+        Interval::Secs(secs) => Ok(secs),
+        Interval::Text(text) => parse_duration(&text).map(|d| d.as_secs()).map_err(serde::de::Error::custom),
+    }
+}
+
+impl Daemon {
+    /// Build a daemon with its blockchain, mempool, database, P2P node and miner set up from `config`,
+    /// a `DaemonConfig` or a `LunaConfig`
+    pub fn from_config(config: impl Into<DaemonConfig>) -> LunaResult<Self> {
+        let config = config.into();
+        config.validate().map_err(LunaError::Validation)?;
+        let blockchain = Arc::new(BlockchainManager::new(&config.endpoint_url, 1));
+        let mempool = MempoolManager { max_mempool_size: config.max_mempool_size, ..MempoolManager::new() };
+        let mut daemon = Daemon::new()
+            .with_tick_interval(config.tick_interval())
+            .with_watchdog(config.watchdog_missed_heartbeats, config.watchdog_max_restarts)
+            .with_mempool(Arc::new(mempool))
+            .with_validator(TransactionValidator::new().with_default_policy(config.security.clone()))
+            .with_database(WalletDatabase::new(Some(config.wallets_db_path())));
+        if let Some(peer_url) = &config.peer_url {
+            let p2p = P2P::new(&config.endpoint_url, peer_url)
+                .with_blockchain(&blockchain)
+                .with_peer_limits(config.max_peers.min(16), config.max_peers);
+            daemon = daemon.with_p2p(p2p);
+        }
+        if config.mining.enabled {
+            daemon = daemon.with_miner(GenesisMiner::new(None), config.mining.clone());
+        }
+        if let Some(path) = &config.journal_path {
+            let journal = Journal::open(path, config.journal_max_bytes, config.journal_keep_files)
+                .map_err(|e| io::Error::new(e.kind(), format!("Cannot open journal {}: {}", path.display(), e)))?;
+            daemon = daemon.with_journal(journal);
+        }
+        if let Some(token) = &config.auth_token {
+            daemon = daemon.with_auth_token(token);
+        }
+        Ok(daemon.with_blockchain(blockchain))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_load_full_toml_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("daemon.toml");
+        let text = format!(
+            r#"
+endpoint_url = "http://127.0.0.1:9000"
+data_dir = "{}"
+tick_interval_secs = 5
+peer_url = "http://127.0.0.1:9001"
+max_peers = 8
+max_mempool_size = 50
+api_bind = "127.0.0.1:9100"
+auth_token = "s3cret"
+journal_path = "{}/journal.jsonl"
+
+[mining]
+enabled = true
+address = "LUN_miner"
+threads = 4
+"#,
+            dir.path().display(),
+            dir.path().display()
+        );
+        fs::write(&path, text).unwrap();
+
+        let config = DaemonConfig::load(&path).unwrap();
+        assert_eq!(config.tick_interval(), Duration::from_secs(5));
+        assert_eq!(config.mining, MiningConfig { enabled: true, address: "LUN_miner".to_string(), threads: 4 });
+        assert_eq!(config.api_bind.as_deref(), Some("127.0.0.1:9100"));
+
+        let daemon = Daemon::from_config(&config).unwrap();
+        assert_eq!(daemon.tick_interval, Duration::from_secs(5));
+        assert_eq!(daemon.sources.mempool.as_ref().unwrap().max_mempool_size, 50);
+        assert!(daemon.sources.p2p.is_some());
+        assert!(daemon.miner.is_some());
+        assert_eq!(daemon.auth_token.as_deref(), Some("s3cret"));
+        assert!(dir.path().join("wallets.db").exists());
+        assert!(dir.path().join("journal.jsonl").exists());
+    }
+
+    #[test]
+    fn test_load_minimal_json_file_uses_defaults() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("daemon.json");
+        fs::write(&path, r#"{"endpoint_url": "http://127.0.0.1:9000"}"#).unwrap();
+
+        let config = DaemonConfig::load(&path).unwrap();
+        assert_eq!(config, DaemonConfig { endpoint_url: "http://127.0.0.1:9000".to_string(), ..DaemonConfig::default() });
+        assert!(!config.mining.enabled);
+        assert_eq!(config.peer_url, None);
+    }
+
+    #[test]
+    fn test_rejects_invalid_interval() {
+        let error = DaemonConfig::from_toml("tick_interval_secs = 0").unwrap_err();
+        assert!(error.contains("tick_interval_secs"), "{}", error);
+        let error = DaemonConfig::from_toml("[mining]\nenabled = true").unwrap_err();
+        assert!(error.contains("mining.address"), "{}", error);
+        let error = DaemonConfig::from_toml("tick_interval = 5").unwrap_err();
+        assert!(error.contains("tick_interval"), "{}", error);
+        let error = DaemonConfig::from_toml("tick_interval_secs = \"soon\"").unwrap_err();
+        assert!(error.contains("Invalid duration"), "{}", error);
+    }
+
+    #[test]
+    fn test_interval_accepts_duration_string() {
+        assert_eq!(DaemonConfig::from_toml("tick_interval_secs = \"2m\"").unwrap().tick_interval(), Duration::from_secs(120));
+        assert_eq!(DaemonConfig::from_json(r#"{"tick_interval_secs": "1m30s"}"#).unwrap().tick_interval_secs, 90);
+        assert!(DaemonConfig::from_toml("tick_interval_secs = \"500ms\"").unwrap_err().contains("at least 1"));
+    }
+}
@@ -0,0 +1,217 @@
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use serde::Serialize;
+use crate::core::daemon::Sources;
+use crate::core::daemon_journal::JournalEvent;
+use crate::core::shutdown::{ShutdownCoordinator, ShutdownToken};
+use crate::utils::clock::Clock;
+use crate::utils::log::error;
+
+/// Heartbeats a subsystem may miss before the watchdog restarts it
+pub const DEFAULT_MISSED_HEARTBEATS: u32 = 3;
+/// Restarts per subsystem before the daemon is marked degraded
+pub const DEFAULT_MAX_RESTARTS: u32 = 3;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SubsystemState {
+    Running,
+    /// Stopped heartbeating after its last allowed restart
+    Failed,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DaemonHealth {
+    Healthy,
+    /// At least one subsystem failed for good
+    Degraded,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SubsystemHealth {
+    pub name: String,
+    pub last_heartbeat: u64,
+    /// Expected time between heartbeats
+    pub interval_secs: u64,
+    pub restarts: u32,
+    pub state: SubsystemState,
+}
+
+type Registry = Arc<Mutex<BTreeMap<String, SubsystemHealth>>>;
+
+/// Body of a supervised subsystem; called again on every restart
+pub type SubsystemWork = Arc<dyn Fn(ShutdownToken, Heartbeat) + Send + Sync>;
+
+/// Handed to a supervised subsystem, which calls `beat` once per iteration
+#[derive(Clone, Default)]
+pub struct Heartbeat {
+    target: Option<(String, Registry, Arc<dyn Clock>)>,
+}
+
+impl Heartbeat {
+    pub fn beat(&self) {
+        if let Some((name, registry, clock)) = &self.target
+            && let Some(health) = registry.lock().unwrap().get_mut(name)
+        {
+            health.last_heartbeat = clock.now();
+            health.state = SubsystemState::Running;
+        }
+    }
+}
+
+/// Restarts subsystems that stop heartbeating, up to `max_restarts` each
+pub(crate) struct Watchdog {
+    registry: Registry,
+    work: Mutex<HashMap<String, SubsystemWork>>,
+    missed_heartbeats: u32,
+    max_restarts: u32,
+}
+
+impl Default for Watchdog {
+    fn default() -> Self {
+        Watchdog::new(DEFAULT_MISSED_HEARTBEATS, DEFAULT_MAX_RESTARTS)
+    }
+}
+
+impl Watchdog {
+    pub(crate) fn new(missed_heartbeats: u32, max_restarts: u32) -> Self {
+        Watchdog { registry: Arc::default(), work: Mutex::default(), missed_heartbeats: missed_heartbeats.max(1), max_restarts }
+    }
+
+    /// Start `work` on `coordinator` and watch it from now on
+    pub(crate) fn supervise(&self, coordinator: &ShutdownCoordinator, sources: &Sources, name: &str, interval: Duration, work: SubsystemWork) {
+        let health = SubsystemHealth {
+            name: name.to_string(),
+            last_heartbeat: sources.clock.now(),
+            interval_secs: interval.as_secs().max(1),
+            restarts: 0,
+            state: SubsystemState::Running,
+        };
+        self.registry.lock().unwrap().insert(name.to_string(), health);
+        self.work.lock().unwrap().insert(name.to_string(), Arc::clone(&work));
+        self.launch(coordinator, sources, name, work);
+    }
+
+    fn launch(&self, coordinator: &ShutdownCoordinator, sources: &Sources, name: &str, work: SubsystemWork) {
+        let heartbeat = Heartbeat { target: Some((name.to_string(), Arc::clone(&self.registry), Arc::clone(&sources.clock))) };
+        coordinator.spawn(name, move |token| work(token, heartbeat));
+    }
+
+    /// Restart subsystems that missed `missed_heartbeats` heartbeats, or mark them failed
+    pub(crate) fn check(&self, coordinator: &ShutdownCoordinator, sources: &Sources) {
+        if coordinator.token().is_cancelled() {
+            return;
+        }
+        let now = sources.clock.now();
+        let mut restart = Vec::new();
+        for health in self.registry.lock().unwrap().values_mut() {
+            let overdue = now.saturating_sub(health.last_heartbeat) > health.interval_secs * u64::from(self.missed_heartbeats);
+            if health.state == SubsystemState::Failed || !overdue {
+                continue;
+            }
+            let reason = if health.restarts < self.max_restarts {
+                health.restarts += 1;
+                health.last_heartbeat = now;
+                restart.push(health.name.clone());
+                format!("missed {} heartbeats; restart {} of {}", self.missed_heartbeats, health.restarts, self.max_restarts)
+            } else {
+                health.state = SubsystemState::Failed;
+                format!("missed {} heartbeats after {} restarts; giving up", self.missed_heartbeats, health.restarts)
+            };
+            error!(subsystem = health.name.as_str(), reason = reason.as_str(); "Subsystem missed heartbeats");
+            sources.record(JournalEvent::Critical { subsystem: health.name.clone(), reason });
+        }
+        for name in restart {
+            let work = self.work.lock().unwrap().get(&name).cloned();
+            if let Some(work) = work {
+                self.launch(coordinator, sources, &name, work);
+            }
+        }
+    }
+
+    /// Drop every subsystem, and whatever its work holds, once they have been stopped
+    pub(crate) fn clear(&self) {
+        self.work.lock().unwrap().clear();
+        self.registry.lock().unwrap().clear();
+    }
+
+    pub(crate) fn subsystems(&self) -> Vec<SubsystemHealth> {
+        self.registry.lock().unwrap().values().cloned().collect()
+    }
+
+    pub(crate) fn health(&self) -> DaemonHealth {
+        if self.registry.lock().unwrap().values().any(|h| h.state == SubsystemState::Failed) {
+            DaemonHealth::Degraded
+        } else {
+            DaemonHealth::Healthy
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::thread;
+    use crate::core::daemon::Daemon;
+    use crate::core::daemon_journal::Journal;
+    use crate::utils::clock::ManualClock;
+    use super::*;
+
+    fn wait_for_runs(runs: &AtomicUsize, expected: usize) {
+        for _ in 0..100 {
+            if runs.load(Ordering::SeqCst) >= expected {
+                return;
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+    }
+
+    #[test]
+    fn test_panicking_subsystem_is_restarted_then_degrades() {
+        let dir = tempfile::tempdir().unwrap();
+        let clock = Arc::new(ManualClock::new(1_000));
+        let mut daemon = Daemon::new()
+            .with_clock(clock.clone())
+            .with_watchdog(3, 2)
+            .with_journal(Journal::open(&dir.path().join("journal.jsonl"), 1 << 20, 1).unwrap());
+        let runs = Arc::new(AtomicUsize::new(0));
+        let counter = Arc::clone(&runs);
+        daemon.spawn_supervised("flaky", Duration::from_secs(1), move |_token, heartbeat| {
+            heartbeat.beat();
+            counter.fetch_add(1, Ordering::SeqCst);
+            panic!("flaky subsystem crashed");
+        });
+        wait_for_runs(&runs, 1);
+
+        clock.advance(3);
+        daemon.check_health();
+        assert_eq!(daemon.subsystem_health()[0].restarts, 0);
+
+        for restart in 1..=2 {
+            clock.advance(4);
+            daemon.check_health();
+            wait_for_runs(&runs, restart + 1);
+            assert_eq!(runs.load(Ordering::SeqCst), restart + 1);
+            assert_eq!(daemon.subsystem_health()[0].restarts, restart as u32);
+            assert_eq!(daemon.health(), DaemonHealth::Healthy);
+        }
+
+        clock.advance(4);
+        daemon.check_health();
+        let health = &daemon.subsystem_health()[0];
+        assert_eq!((health.name.as_str(), health.state), ("flaky", SubsystemState::Failed));
+        assert_eq!(daemon.health(), DaemonHealth::Degraded);
+        thread::sleep(Duration::from_millis(50));
+        assert_eq!(runs.load(Ordering::SeqCst), 3);
+
+        let critical = daemon
+            .read_recent_events(10)
+            .into_iter()
+            .filter(|e| matches!(&e.event, JournalEvent::Critical { subsystem, .. } if subsystem == "flaky"))
+            .count();
+        assert_eq!(critical, 3);
+        assert!(daemon.shutdown(Duration::from_secs(1)).is_clean());
+    }
+}
@@ -0,0 +1,147 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use serde::{Deserialize, Serialize};
+use crate::utils::log::warn;
+
+/// Something the daemon did, as recorded in its journal
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum JournalEvent {
+    Startup,
+    /// `timed_out` lists components that had not stopped in time
+    Shutdown { timed_out: Vec<String> },
+    PeerRegistered { node_id: String },
+    PeerExpired { node_id: String },
+    BlockValidated { height: u64, hash: String, tx_count: usize, duration_ms: u64 },
+    /// A pulled block, or a transaction submitted to the API, failed validation
+    ValidationFailed { height: Option<u64>, reason: String },
+    /// Cached blocks from `height` up were discarded so they are fetched again
+    Reorg { height: u64, dropped_blocks: usize },
+    BlockMined { height: u64, hash: String },
+    MiningFailed { height: u64, reason: String },
+    /// The watchdog restarted or gave up on a subsystem
+    Critical { subsystem: String, reason: String },
+}
+
+/// One line of the journal
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct JournalEntry {
+    pub timestamp: u64,
+    #[serde(flatten)]
+    pub event: JournalEvent,
+}
+
+/// Append-only JSON-lines log. When the file would pass `max_bytes` it is renamed to
+/// `<path>.1` (shifting older files up) and at most `keep_files` rotated files are kept.
+pub struct Journal {
+    path: PathBuf,
+    max_bytes: u64,
+    keep_files: usize,
+    file: Mutex<File>,
+}
+
+impl Journal {
+    pub fn open(path: &Path, max_bytes: u64, keep_files: usize) -> io::Result<Self> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        Ok(Journal {
+            path: path.to_path_buf(),
+            max_bytes: max_bytes.max(1),
+            keep_files,
+            file: Mutex::new(Self::append_to(path)?),
+        })
+    }
+
+    fn append_to(path: &Path) -> io::Result<File> {
+        OpenOptions::new().create(true).append(true).open(path)
+    }
+
+    fn rotated(&self, n: usize) -> PathBuf {
+        let mut name = self.path.clone().into_os_string();
+        name.push(format!(".{}", n));
+        PathBuf::from(name)
+    }
+
+    pub fn record(&self, timestamp: u64, event: JournalEvent) {
+        if let Err(e) = self.append(&JournalEntry { timestamp, event }) {
+            warn!(path:% = self.path.display(), error:% = e; "Journal write failed");
+        }
+    }
+
+    fn append(&self, entry: &JournalEntry) -> io::Result<()> {
+        let mut line = serde_json::to_string(entry)?;
+        line.push('\n');
+        let mut file = self.file.lock().unwrap();
+        let size = file.metadata()?.len();
+        if size > 0 && size + line.len() as u64 > self.max_bytes {
+            self.rotate()?;
+            *file = Self::append_to(&self.path)?;
+        }
+        file.write_all(line.as_bytes())
+    }
+
+    fn rotate(&self) -> io::Result<()> {
+        if self.keep_files == 0 {
+            return fs::remove_file(&self.path);
+        }
+        let _ = fs::remove_file(self.rotated(self.keep_files));
+        for n in (1..self.keep_files).rev() {
+            if self.rotated(n).exists() {
+                fs::rename(self.rotated(n), self.rotated(n + 1))?;
+            }
+        }
+        fs::rename(&self.path, self.rotated(1))
+    }
+
+    /// The last `limit` entries, oldest first, reading into rotated files as needed
+    pub fn read_recent(&self, limit: usize) -> Vec<JournalEntry> {
+        let _file = self.file.lock().unwrap();
+        let files = std::iter::once(self.path.clone()).chain((1..=self.keep_files).map(|n| self.rotated(n)));
+        let mut recent: Vec<JournalEntry> = Vec::new();
+        for path in files {
+            if recent.len() >= limit {
+                break;
+            }
+            let Ok(file) = File::open(&path) else { break };
+            let mut entries: Vec<JournalEntry> = BufReader::new(file)
+                .lines()
+                .map_while(Result::ok)
+                .filter_map(|line| serde_json::from_str(&line).ok())
+                .collect();
+            entries.append(&mut recent);
+            recent = entries;
+        }
+        let skip = recent.len().saturating_sub(limit);
+        recent.split_off(skip)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rotation_keeps_last_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("journal.jsonl");
+        let journal = Journal::open(&path, 120, 2).unwrap();
+        for n in 0..10 {
+            journal.record(n, JournalEvent::PeerRegistered { node_id: format!("node{}", n) });
+        }
+        assert!(path.exists());
+        assert!(dir.path().join("journal.jsonl.1").exists());
+        assert!(dir.path().join("journal.jsonl.2").exists());
+        assert!(!dir.path().join("journal.jsonl.3").exists());
+        assert!(fs::metadata(&path).unwrap().len() <= 120);
+
+        let recent = journal.read_recent(3);
+        let timestamps: Vec<u64> = recent.iter().map(|e| e.timestamp).collect();
+        assert_eq!(timestamps, vec![7, 8, 9]);
+        assert_eq!(recent[2].event, JournalEvent::PeerRegistered { node_id: "node9".to_string() });
+        // Older entries were rotated away
+        assert!(journal.read_recent(100).len() < 10);
+    }
+}
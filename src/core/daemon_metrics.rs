@@ -0,0 +1,180 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::{Display, Write};
+use std::sync::Mutex;
+use crate::core::daemon::{stats_snapshot, Daemon, DaemonStats, PeerInfo, Sources};
+use crate::core::daemon_health::Watchdog;
+use crate::core::p2p::PeerStatus;
+use crate::mining::miner::GenesisMiner;
+
+/// Prometheus text exposition, one metric family at a time
+#[derive(Default)]
+struct Exposition {
+    out: String,
+}
+
+impl Exposition {
+    fn family(&mut self, name: &str, kind: &str, help: &str) {
+        let _ = writeln!(self.out, "# HELP lunalib_{} {}", name, help);
+        let _ = writeln!(self.out, "# TYPE lunalib_{} {}", name, kind);
+    }
+
+    fn sample(&mut self, name: &str, labels: &[(&str, &str)], value: impl Display) {
+        let _ = write!(self.out, "lunalib_{}", name);
+        if !labels.is_empty() {
+            let labels: Vec<String> = labels.iter().map(|(k, v)| format!("{}=\"{}\"", k, escape_label(v))).collect();
+            let _ = write!(self.out, "{{{}}}", labels.join(","));
+        }
+        let _ = writeln!(self.out, " {}", value);
+    }
+
+    fn counter(&mut self, name: &str, help: &str, value: impl Display) {
+        self.family(name, "counter", help);
+        self.sample(name, &[], value);
+    }
+
+    fn gauge(&mut self, name: &str, help: &str, value: impl Display) {
+        self.family(name, "gauge", help);
+        self.sample(name, &[], value);
+    }
+}
+
+fn escape_label(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+impl Daemon {
+    /// Stats, mempool, validator, chain, miner and peer metrics in Prometheus text format
+    pub fn render_metrics(&self) -> String {
+        render_metrics(&self.sources, &self.peers, &self.stats, &self.watchdog, self.miner.as_deref())
+    }
+}
+
+pub(crate) fn render_metrics(
+    sources: &Sources,
+    peers: &Mutex<HashMap<String, PeerInfo>>,
+    stats: &Mutex<DaemonStats>,
+    watchdog: &Watchdog,
+    miner: Option<&GenesisMiner>,
+) -> String {
+    let stats = stats_snapshot(sources, peers, stats);
+    let mut m = Exposition::default();
+    m.counter("blocks_validated_total", "Blocks that passed validation.", stats.blocks_validated);
+    m.counter("transactions_validated_total", "Transactions in validated blocks.", stats.transactions_validated);
+    m.counter("blocks_rejected_total", "Blocks that failed validation.", stats.blocks_rejected);
+    m.counter("validation_failures_total", "Blocks and submitted transactions that failed validation.", stats.validation_failures);
+    m.counter("blocks_mined_total", "Blocks mined and accepted by the endpoint.", stats.blocks_mined);
+    m.counter("peers_registered_total", "Peers registered with the daemon.", stats.peers_registered);
+    m.counter("peers_expired_total", "Peers dropped for not being seen.", stats.peers_expired);
+    m.gauge("peers_active", "Registered peers seen within the expiry age.", stats.peers_active);
+    m.gauge("last_block_height", "Height of the last validated block.", stats.last_block_height);
+    m.gauge("last_block_timestamp_seconds", "When the last block was validated.", stats.last_block_time);
+    m.gauge("uptime_seconds", "Seconds since the daemon started.", stats.uptime_secs);
+    m.gauge("blocks_per_minute", "Blocks validated in the last minute.", stats.blocks_per_minute);
+    m.gauge("transactions_per_minute", "Transactions validated in the last minute.", stats.transactions_per_minute);
+
+    if let Some(mempool) = &sources.mempool {
+        let mempool = mempool.get_stats();
+        m.gauge("mempool_size", "Pending transactions in the mempool.", mempool.size);
+        m.gauge("mempool_capacity", "Most transactions the mempool accepts.", mempool.capacity);
+        m.gauge("mempool_confirmed", "Confirmed transaction hashes remembered by the mempool.", mempool.confirmed);
+    }
+
+    let validator = sources.validator.lock().unwrap().get_metrics();
+    m.family("validator_transactions_total", "counter", "Transactions checked by the validator, by result.");
+    m.sample("validator_transactions_total", &[("result", "accepted")], validator.accepted);
+    m.sample("validator_transactions_total", &[("result", "rejected")], validator.rejected);
+    m.family("validator_rejections_total", "counter", "Validation violations, by rule.");
+    for (rule, count) in &validator.rejected_by_rule {
+        m.sample("validator_rejections_total", &[("rule", rule)], count);
+    }
+    m.counter("validator_duplicates_total", "Transactions rejected as duplicates.", validator.duplicates);
+    m.gauge("validator_average_score", "Average security score of validated transactions.", validator.average_score);
+
+    if let Some(blockchain) = &sources.blockchain {
+        let cache = blockchain.cache.lock().unwrap();
+        m.gauge("chain_cached_blocks", "Blocks held in the block cache.", cache.len());
+        m.gauge("chain_height", "Highest cached block.", cache.keys().max().copied().unwrap_or(0));
+    }
+
+    if let Some(miner) = miner {
+        let mining = miner.get_mining_stats();
+        let hashes = mining.get("total_hash_attempts").copied().unwrap_or(0);
+        let seconds = mining.get("total_mining_time").copied().unwrap_or(0);
+        m.counter("miner_hashes_total", "Hashes computed by the miner.", hashes);
+        m.gauge("miner_hashrate", "Average hashes per second while mining.", hashes.checked_div(seconds).unwrap_or(0));
+    }
+
+    if let Some(p2p) = &sources.p2p {
+        let mut by_status: BTreeMap<PeerStatus, usize> = [PeerStatus::Healthy, PeerStatus::Degraded, PeerStatus::Dead].into_iter().map(|s| (s, 0)).collect();
+        for peer in p2p.get_peers() {
+            *by_status.entry(peer.status).or_insert(0) += 1;
+        }
+        m.family("p2p_peers", "gauge", "Known P2P peers, by status.");
+        for (status, count) in by_status {
+            let status = serde_json::to_value(status).ok().and_then(|v| v.as_str().map(str::to_string)).unwrap_or_default();
+            m.sample("p2p_peers", &[("status", &status)], count);
+        }
+    }
+
+    let subsystems = watchdog.subsystems();
+    if !subsystems.is_empty() {
+        m.family("subsystem_restarts_total", "counter", "Watchdog restarts, by subsystem.");
+        for subsystem in subsystems {
+            m.sample("subsystem_restarts_total", &[("subsystem", &subsystem.name)], subsystem.restarts);
+        }
+    }
+    m.out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use serde_json::json;
+    use crate::core::blockchain::BlockchainManager;
+    use crate::core::crypto::Crypto;
+    use crate::core::daemon::tests::{chain_block, NETWORK_KEY};
+    use crate::core::mempool::MempoolManager;
+    use crate::core::p2p::P2P;
+    use crate::mining::difficulty::Difficulty;
+    use crate::transactions::validator::TransactionValidator;
+
+    #[test]
+    fn test_metrics_after_tick() {
+        let genesis = chain_block(0, "0");
+        let second = chain_block(1, genesis["hash"].as_str().unwrap());
+        let mut server = mockito::Server::new();
+        server.mock("GET", "/blockchain/blocks").with_body(json!({"blocks": [genesis, second]}).to_string()).create();
+        server.mock("GET", "/api/peers").with_body(r#"[{"node_id": "n1", "url": "http://n1"}]"#).create();
+        let validator = TransactionValidator::new().with_authorized_signers([Crypto::new().derive_public_key(NETWORK_KEY)]);
+        let daemon = Daemon::new()
+            .with_p2p(P2P::new(&server.url(), "http://me"))
+            .with_blockchain(Arc::new(BlockchainManager::new(&server.url(), 1)))
+            .with_validator(validator)
+            .with_mempool(Arc::new(MempoolManager::new()))
+            .with_difficulty(Difficulty::new(0));
+        daemon.tick();
+
+        let text = daemon.render_metrics();
+        let mut samples: HashMap<String, f64> = HashMap::new();
+        for line in text.lines() {
+            if let Some(comment) = line.strip_prefix("# ") {
+                assert!(comment.starts_with("HELP lunalib_") || comment.starts_with("TYPE lunalib_"), "{}", line);
+                continue;
+            }
+            let (series, value) = line.rsplit_once(' ').unwrap();
+            samples.insert(series.to_string(), value.parse().unwrap_or_else(|_| panic!("bad value in {}", line)));
+        }
+        assert_eq!(samples["lunalib_blocks_validated_total"], 2.0);
+        assert_eq!(samples["lunalib_transactions_validated_total"], 4.0);
+        assert_eq!(samples["lunalib_last_block_height"], 1.0);
+        assert_eq!(samples["lunalib_mempool_size"], 0.0);
+        assert_eq!(samples["lunalib_chain_cached_blocks"], 2.0);
+        assert_eq!(samples["lunalib_p2p_peers{status=\"healthy\"}"], 1.0);
+        assert_eq!(samples["lunalib_p2p_peers{status=\"dead\"}"], 0.0);
+        assert!(samples.contains_key("lunalib_validator_transactions_total{result=\"accepted\"}"));
+        assert!(text.contains("# TYPE lunalib_blocks_validated_total counter\n"));
+        assert!(text.contains("# TYPE lunalib_mempool_size gauge\n"));
+        assert_eq!(escape_label("a\"b\\c"), "a\\\"b\\\\c");
+    }
+}
@@ -0,0 +1,249 @@
+use std::collections::HashMap;
+use std::io::Read;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use serde_json::{json, Value};
+use tiny_http::{Header, Method, Request, Response, Server};
+use crate::core::daemon::{register_peer, stats_snapshot, Daemon, DaemonStats, PeerInfo, Sources};
+use crate::core::daemon_health::Watchdog;
+use crate::core::daemon_journal::JournalEvent;
+use crate::core::daemon_metrics::render_metrics;
+use crate::core::mempool::Transaction as MempoolTransaction;
+use crate::core::shutdown::ShutdownToken;
+use crate::mining::miner::GenesisMiner;
+use crate::utils::log::error;
+
+/// Largest request body the API reads; peer registrations and transactions are far smaller
+pub const MAX_BODY_BYTES: u64 = 1024 * 1024;
+
+/// Shared state the API thread reads; it never runs validation ticks itself
+struct Api {
+    sources: Sources,
+    peers: Arc<Mutex<HashMap<String, PeerInfo>>>,
+    stats: Arc<Mutex<DaemonStats>>,
+    token: ShutdownToken,
+    auth_token: Option<String>,
+    watchdog: Arc<Watchdog>,
+    miner: Option<Arc<GenesisMiner>>,
+}
+
+impl Daemon {
+    /// Serve the status and control API on `bind_addr`, returning the bound address
+    pub fn serve(&mut self, bind_addr: &str) -> Result<SocketAddr, String> {
+        if self.api_addr.is_some() {
+            return Err("server already running".to_string());
+        }
+        let server = Server::http(bind_addr).map_err(|e| e.to_string())?;
+        let addr = server.server_addr().to_ip().ok_or_else(|| "not bound to an IP address".to_string())?;
+        let api = Api {
+            sources: self.sources.clone(),
+            peers: Arc::clone(&self.peers),
+            stats: Arc::clone(&self.stats),
+            token: self.shutdown.token(),
+            auth_token: self.auth_token.clone(),
+            watchdog: Arc::clone(&self.watchdog),
+            miner: self.miner.clone(),
+        };
+        // Wake at least once a second to heartbeat and notice shutdown
+        let poll = self.tick_interval.min(Duration::from_secs(1));
+        self.spawn_supervised("http_api", poll, move |token, heartbeat| {
+            while !token.is_cancelled() {
+                heartbeat.beat();
+                match server.recv_timeout(poll) {
+                    Ok(Some(request)) => api.handle_request(request),
+                    Ok(None) => {}
+                    Err(e) => {
+                        error!(error:% = e; "API server stopped");
+                        break;
+                    }
+                }
+            }
+        });
+        self.api_addr = Some(addr);
+        Ok(addr)
+    }
+}
+
+impl Api {
+    fn handle_request(&self, mut request: Request) {
+        if request.method() == &Method::Get && request.url().split('?').next() == Some("/metrics") {
+            let metrics = render_metrics(&self.sources, &self.peers, &self.stats, &self.watchdog, self.miner.as_deref());
+            let header = Header::from_bytes("Content-Type", "text/plain; version=0.0.4").unwrap();
+            let _ = request.respond(Response::from_string(metrics).with_header(header));
+            return;
+        }
+        let (status, reply) = match read_body(&mut request) {
+            Err((status, e)) => (status, json!({"error": e})),
+            Ok(body) => {
+                let authorization = request
+                    .headers()
+                    .iter()
+                    .find(|h| h.field.equiv("Authorization"))
+                    .map(|h| h.value.as_str().to_string());
+                self.route(request.method(), request.url(), &body, authorization.as_deref())
+            }
+        };
+        let header = Header::from_bytes("Content-Type", "application/json").unwrap();
+        let response = Response::from_string(reply.to_string()).with_status_code(status).with_header(header);
+        let _ = request.respond(response);
+    }
+
+    fn route(&self, method: &Method, url: &str, body: &str, authorization: Option<&str>) -> (u16, Value) {
+        let (path, query) = url.split_once('?').unwrap_or((url, ""));
+        match (method, path) {
+            (Method::Get, "/status") => (200, self.status()),
+            (Method::Get, "/peers") => {
+                let peers: Vec<PeerInfo> = self.peers.lock().unwrap().values().cloned().collect();
+                (200, json!({"peers": peers}))
+            }
+            (Method::Get, "/events") => (200, self.recent_events(query)),
+            (Method::Post, "/peers/register") => self.register(body),
+            (Method::Post, "/transactions") => self.submit_transaction(body),
+            (Method::Post, "/shutdown") => self.shutdown(authorization),
+            _ => (404, json!({"error": "not found"})),
+        }
+    }
+
+    fn status(&self) -> Value {
+        let stats = stats_snapshot(&self.sources, &self.peers, &self.stats);
+        let peer_count = self.peers.lock().unwrap().len();
+        let mempool_size = self.sources.mempool.as_ref().map(|m| m.get_mempool_size());
+        let chain_height = self.sources.blockchain.as_ref().and_then(|b| b.cache.lock().unwrap().keys().max().copied());
+        json!({
+            "stats": stats,
+            "peer_count": peer_count,
+            "mempool_size": mempool_size,
+            "chain_height": chain_height,
+            "health": self.watchdog.health(),
+            "subsystems": self.watchdog.subsystems(),
+        })
+    }
+
+    /// `?limit=N` most recent journal entries, 100 by default
+    fn recent_events(&self, query: &str) -> Value {
+        let limit = query
+            .split('&')
+            .find_map(|pair| pair.strip_prefix("limit="))
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(100);
+        let events = self.sources.journal.as_ref().map(|j| j.read_recent(limit)).unwrap_or_default();
+        json!({"events": events})
+    }
+
+    fn register(&self, body: &str) -> (u16, Value) {
+        let mut peer: PeerInfo = match serde_json::from_str(body) {
+            Ok(peer) => peer,
+            Err(e) => return (400, json!({"error": e.to_string()})),
+        };
+        if peer.node_id.is_empty() {
+            return (400, json!({"error": "node_id is required"}));
+        }
+        let now = self.sources.clock.now();
+        if peer.registered_at == 0 {
+            peer.registered_at = now;
+        }
+        peer.last_seen = peer.last_seen.max(now);
+        if register_peer(&self.sources, &self.peers, &self.stats, peer) {
+            (200, json!({"status": "registered"}))
+        } else {
+            (409, json!({"error": "already registered"}))
+        }
+    }
+
+    /// Validate, then queue in the mempool
+    fn submit_transaction(&self, body: &str) -> (u16, Value) {
+        let tx: HashMap<String, Value> = match serde_json::from_str(body) {
+            Ok(tx) => tx,
+            Err(e) => return (400, json!({"error": e.to_string()})),
+        };
+        let Some(mempool) = &self.sources.mempool else {
+            return (503, json!({"error": "no mempool configured"}));
+        };
+        let outcome = self.sources.validator.lock().unwrap().validate_transaction(&tx);
+        if !outcome.valid {
+            self.stats.lock().unwrap().validation_failures += 1;
+            self.sources.record(JournalEvent::ValidationFailed { height: None, reason: outcome.message() });
+            return (400, json!({"error": outcome.message()}));
+        }
+        let pending = MempoolTransaction::from_json(&tx);
+        let hash = pending.hash.clone();
+        if mempool.add_transaction(pending) {
+            (200, json!({"status": "accepted", "hash": hash}))
+        } else {
+            (409, json!({"error": "rejected by mempool"}))
+        }
+    }
+
+    fn shutdown(&self, authorization: Option<&str>) -> (u16, Value) {
+        let Some(token) = &self.auth_token else {
+            return (403, json!({"error": "remote shutdown is disabled"}));
+        };
+        if authorization != Some(format!("Bearer {}", token).as_str()) {
+            return (401, json!({"error": "unauthorized"}));
+        }
+        self.token.cancel();
+        (200, json!({"status": "shutting down"}))
+    }
+}
+
+/// The request body, refused with 413 when its declared or actual length exceeds `MAX_BODY_BYTES`
+fn read_body(request: &mut Request) -> Result<String, (u16, String)> {
+    let too_large = || (413, format!("request body exceeds {} bytes", MAX_BODY_BYTES));
+    if request.body_length().is_some_and(|len| len as u64 > MAX_BODY_BYTES) {
+        return Err(too_large());
+    }
+    let mut body = String::new();
+    request
+        .as_reader()
+        .take(MAX_BODY_BYTES + 1)
+        .read_to_string(&mut body)
+        .map_err(|e| (400, e.to_string()))?;
+    if body.len() as u64 > MAX_BODY_BYTES {
+        return Err(too_large());
+    }
+    Ok(body)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::blockchain::BlockchainManager;
+    use crate::core::mempool::MempoolManager;
+
+    #[test]
+    fn test_status_and_shutdown() {
+        let mut daemon = Daemon::new()
+            .with_auth_token("s3cret")
+            .with_mempool(Arc::new(MempoolManager::new()))
+            .with_blockchain(Arc::new(BlockchainManager::new("http://unused", 1)));
+        let addr = daemon.serve("127.0.0.1:0").unwrap();
+        let base = format!("http://{}", addr);
+        let client = reqwest::blocking::Client::new();
+
+        let registered = client.post(format!("{}/peers/register", base)).json(&json!({"node_id": "n1"})).send().unwrap();
+        assert_eq!(registered.status().as_u16(), 200);
+        let status: Value = client.get(format!("{}/status", base)).send().unwrap().json().unwrap();
+        assert_eq!(status["peer_count"], json!(1));
+        assert_eq!(status["mempool_size"], json!(0));
+        assert_eq!(status["chain_height"], Value::Null);
+        assert_eq!(status["stats"]["peers_registered"], json!(1));
+        assert_eq!(status["stats"]["peers_active"], json!(1));
+        assert_eq!(status["health"], json!("healthy"));
+        assert_eq!(status["subsystems"][0]["name"], json!("http_api"));
+        let metrics = client.get(format!("{}/metrics", base)).send().unwrap().text().unwrap();
+        assert!(metrics.contains("lunalib_peers_registered_total 1\n"));
+        let oversized = vec![b' '; MAX_BODY_BYTES as usize + 1];
+        let refused = client.post(format!("{}/peers/register", base)).body(oversized).send().unwrap();
+        assert_eq!(refused.status().as_u16(), 413);
+
+        let denied = client.post(format!("{}/shutdown", base)).bearer_auth("wrong").send().unwrap();
+        assert_eq!(denied.status().as_u16(), 401);
+        assert!(!daemon.is_stopping());
+        let accepted = client.post(format!("{}/shutdown", base)).bearer_auth("s3cret").send().unwrap();
+        assert_eq!(accepted.status().as_u16(), 200);
+        assert!(daemon.is_stopping());
+        daemon.stop();
+        assert!(reqwest::blocking::get(format!("{}/status", base)).is_err());
+    }
+}
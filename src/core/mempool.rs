@@ -1,6 +1,9 @@
 
 use std::collections::{HashMap, HashSet};
 use std::sync::{Arc, Mutex};
+use serde::{Deserialize, Serialize};
+use serde_json::Value as JsonValue;
+use crate::utils::clock;
 
 #[derive(Default)]
 pub struct MempoolManager {
@@ -9,16 +12,45 @@ pub struct MempoolManager {
     pub max_mempool_size: usize,
 }
 
-#[derive(Clone, Debug, Default, PartialEq)]
+/// Serialized with the node's keys, `type` included
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
 pub struct Transaction {
     pub hash: String,
     pub from: String,
     pub to: String,
     pub amount: f64,
+    /// Unix seconds
+    #[serde(deserialize_with = "clock::unix_seconds")]
     pub timestamp: u64,
+    #[serde(rename = "type", alias = "tx_type")]
     pub tx_type: String,
 }
 
+impl Transaction {
+    /// The fields the mempool keeps from a signed transaction
+    pub fn from_json(tx: &HashMap<String, JsonValue>) -> Self {
+        let text = |key: &str| tx.get(key).and_then(|v| v.as_str()).unwrap_or("").to_string();
+        Transaction {
+            hash: text("hash"),
+            from: text("from"),
+            to: text("to"),
+            amount: tx.get("amount").and_then(|v| v.as_f64()).unwrap_or(0.0),
+            timestamp: tx.get("timestamp").and_then(|v| v.as_u64()).unwrap_or(0),
+            tx_type: text("type"),
+        }
+    }
+}
+
+/// Point-in-time counts for monitoring
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct MempoolStats {
+    pub size: usize,
+    pub capacity: usize,
+    /// Hashes remembered as confirmed, which are refused if resubmitted
+    pub confirmed: usize,
+}
+
 impl MempoolManager {
     pub fn new() -> Self {
         MempoolManager {
@@ -76,6 +108,14 @@ impl MempoolManager {
         mempool.len()
     }
 
+    pub fn get_stats(&self) -> MempoolStats {
+        MempoolStats {
+            size: self.get_mempool_size(),
+            capacity: self.max_mempool_size,
+            confirmed: self.confirmed_transactions.lock().unwrap().len(),
+        }
+    }
+
     pub fn clear_mempool(&self) {
         let mut mempool = self.local_mempool.lock().unwrap();
         mempool.clear();
@@ -102,6 +142,21 @@ mod tests {
             tx_type: "transaction".to_string(),
         }
     }
+    #[test]
+    fn test_serde_round_trip() {
+        let tx = sample_tx("tx1");
+        let json = serde_json::to_value(&tx).unwrap();
+        assert_eq!((json["type"].as_str(), json["from"].as_str()), (Some("transaction"), Some("alice")));
+        assert_eq!(serde_json::from_value::<Transaction>(json).unwrap(), tx);
+        let legacy = serde_json::json!({"hash": "tx1", "tx_type": "transaction", "from": "alice", "to": "bob", "amount": 1.0, "timestamp": 123456.9});
+        assert_eq!(serde_json::from_value::<Transaction>(legacy).unwrap(), tx);
+
+        let mempool = MempoolManager::new();
+        mempool.add_transaction(tx);
+        let stats = mempool.get_stats();
+        assert_eq!(serde_json::from_value::<MempoolStats>(serde_json::to_value(&stats).unwrap()).unwrap(), stats);
+    }
+
     #[test]
     fn test_add_and_get_transaction() {
         let mempool = MempoolManager::new();
@@ -118,6 +173,7 @@ mod tests {
         mempool.remove_transaction("tx2");
         assert!(!mempool.is_transaction_pending("tx2"));
         assert!(mempool.is_transaction_confirmed("tx2"));
+        assert_eq!(mempool.get_stats(), MempoolStats { size: 0, capacity: 10000, confirmed: 1 });
     }
     #[test]
     fn test_get_pending_transactions() {
@@ -0,0 +1,92 @@
+use sha2::{Digest, Sha256};
+
+/// Root reported for a block with no transactions
+pub const EMPTY_MERKLE_ROOT: &str = "0000000000000000000000000000000000000000000000000000000000000000";
+
+fn hash_pair(left: &str, right: &str) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(left.as_bytes());
+    hasher.update(right.as_bytes());
+    format!("{:x}", hasher.finalize())
+}
+
+/// Merkle root over hex transaction hashes; odd levels pair the last hash with itself
+pub fn merkle_root(hashes: &[String]) -> String {
+    if hashes.is_empty() {
+        return EMPTY_MERKLE_ROOT.to_string();
+    }
+    let mut level: Vec<String> = hashes.to_vec();
+    while level.len() > 1 {
+        level = level
+            .chunks(2)
+            .map(|pair| hash_pair(&pair[0], pair.get(1).unwrap_or(&pair[0])))
+            .collect();
+    }
+    level.remove(0)
+}
+
+/// Sibling hashes from leaf `index` up to the root, None when out of range
+pub fn merkle_proof(hashes: &[String], index: usize) -> Option<Vec<String>> {
+    if index >= hashes.len() {
+        return None;
+    }
+    let mut proof = Vec::new();
+    let mut level: Vec<String> = hashes.to_vec();
+    let mut index = index;
+    while level.len() > 1 {
+        let sibling = if index.is_multiple_of(2) { index + 1 } else { index - 1 };
+        proof.push(level.get(sibling).unwrap_or(&level[index]).clone());
+        level = level
+            .chunks(2)
+            .map(|pair| hash_pair(&pair[0], pair.get(1).unwrap_or(&pair[0])))
+            .collect();
+        index /= 2;
+    }
+    Some(proof)
+}
+
+/// Whether `proof` links the leaf at `index` to `root`
+pub fn verify_merkle_proof(leaf: &str, index: usize, proof: &[String], root: &str) -> bool {
+    let mut hash = leaf.to_string();
+    let mut index = index;
+    for sibling in proof {
+        hash = if index.is_multiple_of(2) { hash_pair(&hash, sibling) } else { hash_pair(sibling, &hash) };
+        index /= 2;
+    }
+    hash == root
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hashes(n: usize) -> Vec<String> {
+        (0..n).map(|i| format!("{:064x}", i)).collect()
+    }
+
+    #[test]
+    fn test_merkle_root() {
+        assert_eq!(merkle_root(&[]), EMPTY_MERKLE_ROOT);
+        let single = hashes(1);
+        assert_eq!(merkle_root(&single), single[0]);
+        let three = hashes(3);
+        let expected = hash_pair(&hash_pair(&three[0], &three[1]), &hash_pair(&three[2], &three[2]));
+        assert_eq!(merkle_root(&three), expected);
+        let mut swapped = three.clone();
+        swapped.swap(0, 1);
+        assert_ne!(merkle_root(&swapped), expected);
+    }
+
+    #[test]
+    fn test_merkle_proof() {
+        let five = hashes(5);
+        let root = merkle_root(&five);
+        for (i, leaf) in five.iter().enumerate() {
+            let proof = merkle_proof(&five, i).unwrap();
+            assert!(verify_merkle_proof(leaf, i, &proof, &root));
+            assert!(!verify_merkle_proof(&five[(i + 1) % 5], i, &proof, &root));
+        }
+        assert!(merkle_proof(&five, 5).is_none());
+        assert_eq!(merkle_proof(&five[..1], 0), Some(vec![]));
+    }
+}
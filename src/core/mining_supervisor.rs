@@ -0,0 +1,201 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::Receiver;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+use serde_json::{json, Value};
+use crate::core::blockchain::{BlockLookup, BlockchainManager};
+use crate::core::daemon::{DaemonStats, Sources};
+use crate::core::daemon_config::MiningConfig;
+use crate::core::daemon_health::Heartbeat;
+use crate::core::daemon_journal::JournalEvent;
+use crate::core::p2p_events::{P2PEvent, P2PEventKind};
+use crate::core::shutdown::ShutdownToken;
+use crate::mining::miner::GenesisMiner;
+use crate::utils::log::{info, warn};
+
+/// How often a running job checks for shutdown or a competing block
+const POLL_INTERVAL: Duration = Duration::from_millis(20);
+
+/// Mines on top of the cached tip, restarting whenever the tip moves
+pub struct MiningSupervisor {
+    miner: Arc<GenesisMiner>,
+    config: MiningConfig,
+    blockchain: Arc<BlockchainManager>,
+    /// Mempool, P2P node, difficulty, clock and journal shared with the daemon
+    sources: Sources,
+    stats: Arc<Mutex<DaemonStats>>,
+}
+
+impl MiningSupervisor {
+    pub(crate) fn new(
+        miner: Arc<GenesisMiner>,
+        config: MiningConfig,
+        blockchain: Arc<BlockchainManager>,
+        sources: Sources,
+        stats: Arc<Mutex<DaemonStats>>,
+    ) -> Self {
+        MiningSupervisor { miner, config, blockchain, sources, stats }
+    }
+
+    /// Mine until `token` is cancelled
+    pub fn run(&self, token: ShutdownToken, heartbeat: Heartbeat) {
+        let events = self.sources.p2p.as_ref().map(|p2p| p2p.subscribe_events());
+        while !token.is_cancelled() {
+            heartbeat.beat();
+            if self.mine_next(&token, events.as_ref(), &heartbeat).is_none() {
+                token.sleep(POLL_INTERVAL);
+            }
+        }
+    }
+
+    /// Next block to mine: the pending mempool on top of the cached tip, or None before the first sync
+    pub fn block_template(&self) -> Option<HashMap<String, Value>> {
+        let height = self.blockchain.cache.lock().unwrap().keys().max().copied()?;
+        let tip = self.blockchain.block_at(height)?;
+        let mut pending = self.sources.mempool.as_ref().map(|m| m.get_pending_transactions()).unwrap_or_default();
+        pending.sort_by(|a, b| (a.timestamp, &a.hash).cmp(&(b.timestamp, &b.hash)));
+        let transactions: Vec<Value> = pending
+            .iter()
+            .map(|tx| json!({"hash": tx.hash, "from": tx.from, "to": tx.to, "amount": tx.amount, "timestamp": tx.timestamp, "type": tx.tx_type}))
+            .collect();
+        let mut template = HashMap::new();
+        template.insert("index".to_string(), json!(height + 1));
+        template.insert("previous_hash".to_string(), json!(tip.hash));
+        template.insert("timestamp".to_string(), json!(self.sources.clock.now()));
+        template.insert("transactions".to_string(), json!(transactions));
+        template.insert("miner".to_string(), json!(self.config.address));
+        template.insert("difficulty".to_string(), json!(self.sources.difficulty.value));
+        template.insert("version".to_string(), json!("1.0"));
+        Some(template)
+    }
+
+    /// Mine one template, then submit and broadcast it. Returns None without a template,
+    /// when shutdown or a competing block aborts the job, or when the endpoint rejects the block.
+    pub fn mine_next(&self, token: &ShutdownToken, events: Option<&Receiver<P2PEvent>>, heartbeat: &Heartbeat) -> Option<HashMap<String, Value>> {
+        let template = self.block_template()?;
+        let height = template["index"].as_u64().unwrap_or(0);
+        let abort = AtomicBool::new(false);
+        let mined = thread::scope(|scope| {
+            let job = scope.spawn(|| self.miner.mine_block_parallel(&template, self.sources.difficulty.value, self.config.threads, &abort));
+            while !job.is_finished() {
+                heartbeat.beat();
+                if token.is_cancelled() || self.competing_block_arrived(height, events) {
+                    abort.store(true, Ordering::Relaxed);
+                }
+                thread::sleep(POLL_INTERVAL);
+            }
+            job.join().ok().flatten()
+        })?;
+        if self.blockchain.block_at(height).is_some() {
+            return None;
+        }
+        if let Err(e) = self.blockchain.submit_block(&mined) {
+            warn!(height = height, error:% = e; "Mined block not accepted");
+            self.sources.record(JournalEvent::MiningFailed { height, reason: e.to_string() });
+            return None;
+        }
+        let hash = mined["hash"].as_str().unwrap_or("").to_string();
+        info!(height = height, hash = hash.as_str(); "Mined block");
+        self.stats.lock().unwrap().blocks_mined += 1;
+        self.sources.record(JournalEvent::BlockMined { height, hash });
+        if let Some(mempool) = &self.sources.mempool {
+            for tx in mined["transactions"].as_array().into_iter().flatten() {
+                if let Some(hash) = tx["hash"].as_str() {
+                    mempool.remove_transaction(hash);
+                }
+            }
+        }
+        if let Some(p2p) = &self.sources.p2p
+            && let Err(e) = p2p.broadcast_block(json!(mined))
+        {
+            warn!(height = height, error:% = e; "Broadcast of mined block failed");
+        }
+        Some(mined)
+    }
+
+    /// Another node's block at `height` reached the cache or arrived over P2P
+    fn competing_block_arrived(&self, height: u64, events: Option<&Receiver<P2PEvent>>) -> bool {
+        let announced = events.is_some_and(|events| {
+            events.try_iter().any(|event| matches!(event.kind, P2PEventKind::BlockReceived { height: h, .. } if h >= height))
+        });
+        announced || self.blockchain.block_at(height).is_some()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::blockchain::Block;
+    use crate::core::daemon::Daemon;
+    use crate::core::mempool::{MempoolManager, Transaction as MempoolTransaction};
+    use crate::mining::difficulty::Difficulty;
+    use crate::utils::clock::ManualClock;
+
+    fn supervisor(endpoint: &str, mempool: Arc<MempoolManager>, difficulty: u32) -> MiningSupervisor {
+        let blockchain = Arc::new(BlockchainManager::new(endpoint, 1));
+        let genesis = Block { index: 0, hash: "genesis".to_string(), previous_hash: "0".to_string(), ..Block::new() };
+        blockchain.cache.lock().unwrap().insert(0, genesis);
+        let config = MiningConfig { enabled: true, address: "LUN_miner".to_string(), threads: 2 };
+        Daemon::new()
+            .with_blockchain(blockchain)
+            .with_mempool(mempool)
+            .with_difficulty(Difficulty::new(difficulty))
+            .with_clock(Arc::new(ManualClock::new(1_000)))
+            .with_miner(GenesisMiner::new(None), config)
+            .mining_supervisor()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_mines_submits_and_advances_template() {
+        let mut server = mockito::Server::new();
+        let submit = server
+            .mock("POST", "/blockchain/submit-block")
+            .match_body(mockito::Matcher::PartialJson(json!({"index": 1, "previous_hash": "genesis"})))
+            .create();
+        let mempool = Arc::new(MempoolManager::new());
+        mempool.add_transaction(MempoolTransaction {
+            hash: "tx1".to_string(), from: "alice".to_string(), to: "bob".to_string(), amount: 1.0, timestamp: 1, tx_type: "transfer".to_string(),
+        });
+        let supervisor = supervisor(&server.url(), Arc::clone(&mempool), 1);
+
+        let mined = supervisor.mine_next(&ShutdownToken::default(), None, &Heartbeat::default()).unwrap();
+        submit.assert();
+        assert!(mined["hash"].as_str().unwrap().starts_with('0'));
+        assert_eq!(mined["transactions"][0]["hash"], json!("tx1"));
+        assert!(!mempool.is_transaction_pending("tx1"));
+        assert_eq!(supervisor.stats.lock().unwrap().blocks_mined, 1);
+
+        let next = supervisor.block_template().unwrap();
+        assert_eq!(next["index"], json!(2));
+        assert_eq!(next["previous_hash"], mined["hash"]);
+        assert_eq!(next["transactions"], json!([]));
+    }
+
+    #[test]
+    fn test_competing_block_or_shutdown_aborts_job() {
+        let supervisor = Arc::new(supervisor("http://unused", Arc::new(MempoolManager::new()), 64));
+        let competitor = Block { index: 1, hash: "theirs".to_string(), previous_hash: "genesis".to_string(), ..Block::new() };
+        let cache = Arc::clone(&supervisor.blockchain.cache);
+        let arrival = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(50));
+            cache.lock().unwrap().insert(1, competitor);
+        });
+        assert!(supervisor.mine_next(&ShutdownToken::default(), None, &Heartbeat::default()).is_none());
+        arrival.join().unwrap();
+        assert_eq!(supervisor.block_template().unwrap()["index"], json!(2));
+
+        let token = ShutdownToken::default();
+        let running = Arc::clone(&supervisor);
+        let worker = {
+            let token = token.clone();
+            thread::spawn(move || running.run(token, Heartbeat::default()))
+        };
+        thread::sleep(Duration::from_millis(50));
+        token.cancel();
+        worker.join().unwrap();
+        assert_eq!(supervisor.stats.lock().unwrap().blocks_mined, 0);
+    }
+}
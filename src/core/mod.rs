@@ -1,10 +1,44 @@
+pub mod amount;
 pub mod wallet;
+#[cfg(not(target_arch = "wasm32"))]
 pub mod blockchain;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod block_cache;
+#[cfg(not(target_arch = "wasm32"))]
 pub mod mempool;
 pub mod crypto;
+#[cfg(not(target_arch = "wasm32"))]
 pub mod daemon;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod daemon_config;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod daemon_health;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod daemon_journal;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod daemon_metrics;
+#[cfg(feature = "daemon-server")]
+pub mod daemon_server;
 pub mod sm2;
+#[cfg(not(target_arch = "wasm32"))]
 pub mod wallet_db;
+#[cfg(not(target_arch = "wasm32"))]
 pub mod wallet_manager;
+#[cfg(not(target_arch = "wasm32"))]
 pub mod wallet_sync_helper;
+#[cfg(not(target_arch = "wasm32"))]
 pub mod p2p;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod p2p_events;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod p2p_identity;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod p2p_sync;
+#[cfg(feature = "p2p-server")]
+pub mod p2p_server;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod merkle;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod mining_supervisor;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod shutdown;
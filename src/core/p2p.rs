@@ -1,82 +1,735 @@
-
-use std::collections::HashMap;
+use std::fmt;
+use std::net::IpAddr;
+use std::num::NonZeroUsize;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+use lru::LruCache;
+use reqwest::Url;
+use rand::seq::SliceRandom;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use crate::core::block_cache::BlockCache;
+use crate::core::blockchain::{Block, BlockchainManager, Transaction, DEFAULT_TIMESTAMP_TOLERANCE};
+use crate::core::p2p_events::{EventSubscribers, P2PEventKind, RemovalReason};
+use crate::core::p2p_identity::{NodeIdentity, SignedEnvelope, DEFAULT_MAX_ENVELOPE_AGE_SECS};
+use crate::utils::clock::{Clock, SystemClock};
+
+/// Liveness of a peer as seen by heartbeats and broadcasts
+#[derive(Default, Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PeerStatus {
+    #[default]
+    Healthy,
+    /// Failed recently but not yet given up on
+    Degraded,
+    /// Failed `dead_after` times in a row; skipped by broadcasts until it answers a ping
+    Dead,
+}
 
-#[derive(Default, Debug, PartialEq, Clone)]
+#[derive(Default, Debug, PartialEq, Clone, Serialize, Deserialize)]
 pub struct PeerInfo {
     pub node_id: String,
+    #[serde(alias = "peer_url")]
     pub url: String,
+    /// Unix seconds of the last successful contact
+    #[serde(default)]
+    pub last_seen: Option<u64>,
+    #[serde(default)]
+    pub latency_ms: Option<u64>,
+    #[serde(default)]
+    pub consecutive_failures: u32,
+    #[serde(default)]
+    pub status: PeerStatus,
+    /// Unix seconds before which the heartbeat will not re-dial this peer
+    #[serde(default)]
+    pub next_dial_at: Option<u64>,
+}
+
+impl PeerInfo {
+    pub fn new(node_id: &str, url: &str) -> Self {
+        PeerInfo { node_id: node_id.to_string(), url: url.to_string(), ..Default::default() }
+    }
+
+    fn record_success(&mut self, latency_ms: u64, now: u64) {
+        self.last_seen = Some(now);
+        self.latency_ms = Some(latency_ms);
+        self.consecutive_failures = 0;
+        self.status = PeerStatus::Healthy;
+        self.next_dial_at = None;
+    }
+
+    fn record_failure(&mut self, dead_after: u32, backoff: Duration, now: u64) {
+        self.consecutive_failures += 1;
+        self.status = if self.consecutive_failures >= dead_after { PeerStatus::Dead } else { PeerStatus::Degraded };
+        self.next_dial_at = Some(now + backoff.as_secs());
+    }
+
+    /// Sort key where smaller is better: status, then failures, then latency
+    fn rank(&self) -> (PeerStatus, u32, u64) {
+        (self.status, self.consecutive_failures, self.latency_ms.unwrap_or(u64::MAX))
+    }
+}
+
+/// Delay before re-dialing a peer after `attempt` consecutive failures: `base` doubled per
+/// attempt plus up to 50% random jitter, capped at `max`
+pub fn dial_backoff(attempt: u32, base: Duration, max: Duration) -> Duration {
+    let exponential = base.saturating_mul(1 << attempt.saturating_sub(1).min(16));
+    let jitter = exponential.mul_f64(rand::random::<f64>() * 0.5);
+    exponential.saturating_add(jitter).min(max)
+}
+
+/// Accept only http(s) URLs, and unless `allow_private`, only publicly routable hosts
+pub fn validate_peer_url(url: &str, allow_private: bool) -> Result<(), P2PError> {
+    let parsed = Url::parse(url).map_err(|e| P2PError::InvalidPeer(format!("{}: {}", url, e)))?;
+    if !matches!(parsed.scheme(), "http" | "https") {
+        return Err(P2PError::InvalidPeer(format!("{}: scheme must be http or https", url)));
+    }
+    let Some(host) = parsed.host_str() else {
+        return Err(P2PError::InvalidPeer(format!("{}: missing host", url)));
+    };
+    let private = match host.trim_start_matches('[').trim_end_matches(']').parse::<IpAddr>() {
+        Ok(IpAddr::V4(ip)) => ip.is_private() || ip.is_loopback() || ip.is_link_local() || ip.is_unspecified(),
+        // fc00::/7 unique local and fe80::/10 link local
+        Ok(IpAddr::V6(ip)) => {
+            ip.is_loopback() || ip.is_unspecified() || (ip.segments()[0] & 0xfe00) == 0xfc00 || (ip.segments()[0] & 0xffc0) == 0xfe80
+        }
+        Err(_) => host == "localhost" || host.ends_with(".localhost"),
+    };
+    if private && !allow_private {
+        return Err(P2PError::InvalidPeer(format!("{}: private or loopback address", url)));
+    }
+    Ok(())
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum P2PError {
+    /// The request could not be sent, e.g. connection refused
+    Network(String),
+    /// No answer within the configured timeout
+    Timeout(String),
+    /// A peer URL is unusable, or a peer served invalid data
+    InvalidPeer(String),
+    /// The node refused this one (HTTP 403)
+    Banned,
+    /// The node answered with another non-success status
+    Protocol { code: u16 },
+    /// A response body could not be decoded
+    Serialization(String),
+    /// The inbound server could not be started
+    Server(String),
+}
+
+impl P2PError {
+    pub(crate) fn from_status(code: u16) -> Self {
+        match code {
+            403 => P2PError::Banned,
+            code => P2PError::Protocol { code },
+        }
+    }
+}
+
+impl fmt::Display for P2PError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            P2PError::Network(e) => write!(f, "Network error: {}", e),
+            P2PError::Timeout(e) => write!(f, "Request timed out: {}", e),
+            P2PError::InvalidPeer(e) => write!(f, "Invalid peer: {}", e),
+            P2PError::Banned => write!(f, "Banned by node"),
+            P2PError::Protocol { code } => write!(f, "Node returned status {}", code),
+            P2PError::Serialization(e) => write!(f, "Invalid response body: {}", e),
+            P2PError::Server(e) => write!(f, "P2P server error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for P2PError {}
+
+impl From<reqwest::Error> for P2PError {
+    fn from(e: reqwest::Error) -> Self {
+        if e.is_timeout() {
+            P2PError::Timeout(e.to_string())
+        } else if e.is_decode() {
+            P2PError::Serialization(e.to_string())
+        } else {
+            P2PError::Network(e.to_string())
+        }
+    }
+}
+
+/// Body sent by `broadcast_block` and `broadcast_transaction`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct BroadcastPayload(pub Value);
+
+impl From<&str> for BroadcastPayload {
+    /// Legacy payloads: JSON text is sent as-is, anything else as a JSON string
+    fn from(raw: &str) -> Self {
+        BroadcastPayload(serde_json::from_str(raw).unwrap_or_else(|_| Value::String(raw.to_string())))
+    }
+}
+
+impl From<&Block> for BroadcastPayload {
+    fn from(block: &Block) -> Self {
+        BroadcastPayload(serde_json::to_value(block).unwrap_or(Value::Null))
+    }
+}
+
+impl From<&Transaction> for BroadcastPayload {
+    fn from(tx: &Transaction) -> Self {
+        BroadcastPayload(serde_json::to_value(tx).unwrap_or(Value::Null))
+    }
+}
+
+impl From<Value> for BroadcastPayload {
+    fn from(value: Value) -> Self {
+        BroadcastPayload(value)
+    }
+}
+
+/// Outcome of sending one payload to every known peer
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct BroadcastReport {
+    pub attempted: usize,
+    pub succeeded: usize,
+    /// (node_id, reason) for each peer that never accepted the payload
+    pub failed: Vec<(String, P2PError)>,
 }
 
+/// Gossip relay counters
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct GossipMetrics {
+    /// New messages accepted from peers
+    pub received: u64,
+    /// Envelopes forwarded to other peers
+    pub relayed: u64,
+    /// Messages dropped because they had already been seen
+    pub duplicates_suppressed: u64,
+}
+
+/// Upper bound on the refresh delay after repeated failures
+const MAX_BACKOFF: Duration = Duration::from_secs(600);
+
+/// Clones share the peer list and stop signal
+#[derive(Clone)]
 pub struct P2P {
     pub primary_node: String,
+    /// Fingerprint of this node's public key
     pub node_id: String,
+    /// URL advertised to peers; may differ from the server's bind address, e.g. behind NAT
     pub peer_url: String,
+    pub version: String,
+    pub capabilities: Vec<String>,
     pub peers: Arc<Mutex<Vec<PeerInfo>>>,
     pub is_running: bool,
+    /// How often the background thread refreshes the peer list
+    pub refresh_interval: Duration,
+    /// Per-request timeout
+    pub timeout: Duration,
+    /// Peers contacted at once during a broadcast
+    pub max_concurrency: usize,
+    /// Extra attempts per peer after a failed broadcast
+    pub max_retries: u32,
+    /// How often the heartbeat thread pings each peer
+    pub heartbeat_interval: Duration,
+    /// Consecutive failures before a peer is marked dead
+    pub dead_after: u32,
+    /// Inbound envelopes older than this are rejected
+    pub max_envelope_age_secs: u64,
+    /// Peers each received message is relayed to
+    pub relay_fanout: usize,
+    /// Peers each broadcast is sent to
+    pub max_outbound: usize,
+    /// Size of the peer list; the lowest-ranked peers are dropped beyond it
+    pub max_known: usize,
+    /// Accept loopback and private addresses from remote registrations
+    pub allow_private: bool,
+    /// First re-dial delay for an unreachable peer, doubled per failure
+    pub dial_backoff_base: Duration,
+    pub dial_backoff_max: Duration,
+    /// How often the sync thread catches up from the best peer
+    pub sync_interval: Duration,
+    /// Local block cache that chain sync reads and extends
+    pub(crate) chain: Option<Arc<Mutex<BlockCache>>>,
+    /// The attached chain's `timestamp_tolerance`, applied to synced blocks
+    pub(crate) chain_timestamp_tolerance: Duration,
+    /// Payload hash -> first-seen time of recent gossip
+    seen: Arc<Mutex<LruCache<String, u64>>>,
+    gossip_metrics: Arc<Mutex<GossipMetrics>>,
+    identity: NodeIdentity,
+    stop_flag: Arc<AtomicBool>,
+    pub(crate) events: EventSubscribers,
+    #[cfg(feature = "p2p-server")]
+    pub(crate) inbound: Arc<crate::core::p2p_server::Inbound>,
 }
 
 impl P2P {
-    pub fn new(primary_node: &str, node_id: &str, peer_url: &str) -> Self {
+    /// Node with a freshly generated identity
+    pub fn new(primary_node: &str, peer_url: &str) -> Self {
+        let identity = NodeIdentity::generate();
         P2P {
-            primary_node: primary_node.to_string(),
-            node_id: node_id.to_string(),
+            primary_node: primary_node.trim_end_matches('/').to_string(),
+            node_id: identity.node_id.clone(),
             peer_url: peer_url.to_string(),
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            capabilities: vec!["blocks".to_string(), "transactions".to_string()],
             peers: Arc::new(Mutex::new(Vec::new())),
             is_running: false,
+            refresh_interval: Duration::from_secs(60),
+            timeout: Duration::from_secs(10),
+            max_concurrency: 8,
+            max_retries: 1,
+            heartbeat_interval: Duration::from_secs(30),
+            dead_after: 3,
+            max_envelope_age_secs: DEFAULT_MAX_ENVELOPE_AGE_SECS,
+            relay_fanout: 4,
+            max_outbound: 16,
+            max_known: 128,
+            allow_private: false,
+            dial_backoff_base: Duration::from_secs(5),
+            dial_backoff_max: MAX_BACKOFF,
+            sync_interval: Duration::from_secs(30),
+            chain: None,
+            chain_timestamp_tolerance: DEFAULT_TIMESTAMP_TOLERANCE,
+            seen: Arc::new(Mutex::new(LruCache::new(NonZeroUsize::new(10_000).unwrap()))),
+            gossip_metrics: Arc::default(),
+            identity,
+            stop_flag: Arc::new(AtomicBool::new(false)),
+            events: Arc::default(),
+            #[cfg(feature = "p2p-server")]
+            inbound: Arc::default(),
         }
     }
 
-    pub fn start(&mut self) {
-        if self.is_running { return; }
+    /// Use a persisted identity; the node_id becomes its fingerprint
+    pub fn with_identity(mut self, identity: NodeIdentity) -> Self {
+        self.node_id = identity.node_id.clone();
+        self.identity = identity;
+        self
+    }
+
+    pub fn get_identity(&self) -> &NodeIdentity {
+        &self.identity
+    }
+
+    /// Sign `payload` as this node, timestamped now
+    pub fn sign_envelope(&self, payload: Value) -> SignedEnvelope {
+        self.identity.sign(payload, SystemClock.now())
+    }
+
+    pub fn with_refresh_interval(mut self, interval: Duration) -> Self {
+        self.refresh_interval = interval;
+        self
+    }
+
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Ping peers every `interval`, marking them dead after `dead_after` consecutive failures
+    pub fn with_heartbeat(mut self, interval: Duration, dead_after: u32) -> Self {
+        self.heartbeat_interval = interval;
+        self.dead_after = dead_after.max(1);
+        self
+    }
+
+    /// Relay each received message to at most `fanout` peers, remembering `seen_capacity` message hashes
+    pub fn with_gossip(mut self, fanout: usize, seen_capacity: usize) -> Self {
+        self.relay_fanout = fanout;
+        self.seen = Arc::new(Mutex::new(LruCache::new(NonZeroUsize::new(seen_capacity.max(1)).unwrap())));
+        self
+    }
+
+    /// Serve and sync into `manager`'s block cache
+    pub fn with_blockchain(mut self, manager: &BlockchainManager) -> Self {
+        self.chain = Some(Arc::clone(&manager.cache));
+        self.chain_timestamp_tolerance = manager.timestamp_tolerance;
+        self
+    }
+
+    pub fn with_sync_interval(mut self, interval: Duration) -> Self {
+        self.sync_interval = interval;
+        self
+    }
+
+    /// Broadcast to at most `max_outbound` peers and remember at most `max_known`
+    pub fn with_peer_limits(mut self, max_outbound: usize, max_known: usize) -> Self {
+        self.max_outbound = max_outbound.max(1);
+        self.max_known = max_known.max(1);
+        self
+    }
+
+    pub fn with_dial_backoff(mut self, base: Duration, max: Duration) -> Self {
+        self.dial_backoff_base = base;
+        self.dial_backoff_max = max;
+        self
+    }
+
+    /// Accept loopback and private peer URLs, e.g. for local test networks
+    pub fn with_allow_private(mut self, allow_private: bool) -> Self {
+        self.allow_private = allow_private;
+        self
+    }
+
+    /// URL announced to the primary and peers, when it differs from where the server binds
+    pub fn with_advertised_url(mut self, url: &str) -> Self {
+        self.peer_url = url.to_string();
+        self
+    }
+
+    pub fn with_broadcast_limits(mut self, max_concurrency: usize, max_retries: u32) -> Self {
+        self.max_concurrency = max_concurrency.max(1);
+        self.max_retries = max_retries;
+        self
+    }
+
+    /// Register with the primary node, then refresh the peer list in the background.
+    /// With a blockchain attached, also catch up from the best peer every `sync_interval`.
+    /// Fails without starting anything if the primary URL is unusable.
+    pub fn start(&mut self) -> Result<(), P2PError> {
+        if self.is_running { return Ok(()); }
+        // The primary is configured locally, so private addresses are fine
+        validate_peer_url(&self.primary_node, true)?;
         self.is_running = true;
-        // 初期同期・ピア登録・ピアリスト取得（本来は非同期/スレッド）
-        self.register_with_primary();
-        // 本来はスレッドで定期的にupdate_peer_listや同期処理を行う
+        let stop_flag = Arc::new(AtomicBool::new(false));
+        self.stop_flag = stop_flag.clone();
+        let node = self.clone();
+        let interval = self.refresh_interval;
+        thread::spawn(move || {
+            let mut registered = false;
+            let mut failures = 0u32;
+            while !stop_flag.load(Ordering::Relaxed) {
+                let result = if registered { node.fetch_peer_list() } else { node.register_with_primary() };
+                let delay = match result {
+                    Ok(peers) => {
+                        registered = true;
+                        failures = 0;
+                        node.set_peers(peers);
+                        interval
+                    }
+                    Err(_) => {
+                        failures = failures.saturating_add(1);
+                        interval.saturating_mul(1 << failures.min(16)).min(MAX_BACKOFF.max(interval))
+                    }
+                };
+                sleep_unless_stopped(delay, &stop_flag);
+            }
+        });
+        let node = self.clone();
+        thread::spawn(move || {
+            while !node.stop_flag.load(Ordering::Relaxed) {
+                node.ping_peers();
+                sleep_unless_stopped(node.heartbeat_interval, &node.stop_flag);
+            }
+        });
+        if self.chain.is_some() {
+            let node = self.clone();
+            thread::spawn(move || {
+                while !node.stop_flag.load(Ordering::Relaxed) {
+                    let _ = node.sync_with_best_peer();
+                    sleep_unless_stopped(node.sync_interval, &node.stop_flag);
+                }
+            });
+        }
+        Ok(())
     }
 
+    /// Signal the background threads to exit; they stop at their next wake-up
     pub fn stop(&mut self) {
         self.is_running = false;
-        // 本来はスレッド停止処理
+        self.stop_flag.store(true, Ordering::Relaxed);
+        #[cfg(feature = "p2p-server")]
+        self.stop_server();
     }
 
-    pub fn register_with_primary(&self) -> bool {
-        // 本来はHTTP POSTでプライマリノードに自身を登録
-        // ここではダミーでピアリストに自身を追加
-        let mut peers = self.peers.lock().unwrap();
-        if !peers.iter().any(|p| p.node_id == self.node_id) {
-            peers.push(PeerInfo {
-                node_id: self.node_id.clone(),
-                url: self.peer_url.clone(),
-            });
-        }
-        true
+    /// Announce this node to the primary in a signed envelope and adopt the peer list it returns.
+    /// On failure the current peer list is kept.
+    pub fn register_with_primary(&self) -> Result<Vec<PeerInfo>, P2PError> {
+        let body = json!({
+            "node_id": self.node_id,
+            "peer_url": self.peer_url,
+            "version": self.version,
+            "capabilities": self.capabilities,
+        });
+        let response = self
+            .client()?
+            .post(format!("{}/api/peers/register", self.primary_node))
+            .json(&self.sign_envelope(body))
+            .send()
+            ?;
+        let peers = self.parse_peers(response)?;
+        self.set_peers(peers.clone());
+        Ok(peers)
+    }
+
+    /// Current peer list from the primary node, excluding this node
+    pub fn fetch_peer_list(&self) -> Result<Vec<PeerInfo>, P2PError> {
+        let response = self
+            .client()?
+            .get(format!("{}/api/peers", self.primary_node))
+            .send()
+            ?;
+        self.parse_peers(response)
     }
 
     pub fn update_peer_list(&self, new_peers: Vec<PeerInfo>) {
+        self.set_peers(new_peers);
+    }
+
+    /// Replace the peer list, keeping health data for peers already known
+    fn set_peers(&self, new_peers: Vec<PeerInfo>) {
         let mut peers = self.peers.lock().unwrap();
+        let old = peers.clone();
         // 自分自身を除外してピアリストを更新
-        *peers = new_peers.into_iter().filter(|p| p.node_id != self.node_id).collect();
+        *peers = new_peers
+            .into_iter()
+            .filter(|p| !self.is_self(p))
+            .map(|p| match peers.iter().find(|known| known.node_id == p.node_id && known.url == p.url) {
+                Some(known) => known.clone(),
+                None => p,
+            })
+            .collect();
+        let evicted = self.trim_to_capacity(&mut peers);
+        let events = membership_changes(&old, &peers, &evicted);
+        drop(peers);
+        self.emit_all(events);
+    }
+
+    /// Add or update a peer that registered with this node
+    pub fn register_peer(&self, peer: PeerInfo) -> Result<(), P2PError> {
+        validate_peer_url(&peer.url, self.allow_private)?;
+        if self.is_self(&peer) {
+            return Ok(());
+        }
+        let mut peers = self.peers.lock().unwrap();
+        let old = peers.clone();
+        match peers.iter_mut().find(|known| known.node_id == peer.node_id) {
+            Some(known) if known.url == peer.url => {}
+            Some(known) => *known = peer,
+            None => peers.push(peer),
+        }
+        let evicted = self.trim_to_capacity(&mut peers);
+        let events = membership_changes(&old, &peers, &evicted);
+        drop(peers);
+        self.emit_all(events);
+        Ok(())
+    }
+
+    /// Drop the lowest-ranked peers beyond `max_known`, newest first among equals
+    fn trim_to_capacity(&self, peers: &mut Vec<PeerInfo>) -> Vec<PeerInfo> {
+        let mut evicted = Vec::new();
+        while peers.len() > self.max_known {
+            let worst = peers.iter().enumerate().max_by_key(|(_, p)| p.rank()).map(|(i, _)| i).unwrap();
+            evicted.push(peers.remove(worst));
+        }
+        evicted
     }
 
-    pub fn broadcast_block(&self, _block: &str) {
-        // 本来は各ピアのURLにHTTP POSTでブロックを送信
-        let peers = self.peers.lock().unwrap();
-        for peer in peers.iter() {
-            // ここでHTTPリクエスト等を送る（省略）
-            // 例: reqwest::blocking::post(format!("{}/api/blocks/new", peer.url), ...)
-            // 今回はダミー
+    /// GET each peer's `/api/ping` once, updating latency and status.
+    /// Peers still inside their dial backoff are skipped.
+    pub fn ping_peers(&self) {
+        let Ok(client) = self.client() else { return };
+        let now = SystemClock.now();
+        for peer in self.get_peers().into_iter().filter(|p| p.next_dial_at.is_none_or(|at| at <= now)) {
+            let started = Instant::now();
+            let alive = client
+                .get(format!("{}/api/ping", peer.url.trim_end_matches('/')))
+                .send()
+                .is_ok_and(|r| r.status().is_success());
+            let latency_ms = started.elapsed().as_millis() as u64;
+            self.record_contact(&peer.node_id, alive.then_some(latency_ms));
         }
     }
 
-    pub fn broadcast_transaction(&self, _tx: &str) {
-        // 本来は各ピアのURLにHTTP POSTでトランザクションを送信
-        let peers = self.peers.lock().unwrap();
-        for peer in peers.iter() {
-            // ここでHTTPリクエスト等を送る（省略）
-            // 例: reqwest::blocking::post(format!("{}/api/transactions/new", peer.url), ...)
-            // 今回はダミー
+    /// Update a peer's health; `latency_ms` is None when contact failed
+    pub(crate) fn record_contact(&self, node_id: &str, latency_ms: Option<u64>) {
+        let mut peers = self.peers.lock().unwrap();
+        let Some(peer) = peers.iter_mut().find(|p| p.node_id == node_id) else { return };
+        let from = peer.status;
+        match latency_ms {
+            Some(latency) => peer.record_success(latency, SystemClock.now()),
+            None => {
+                let backoff = dial_backoff(peer.consecutive_failures + 1, self.dial_backoff_base, self.dial_backoff_max);
+                peer.record_failure(self.dead_after, backoff, SystemClock.now());
+            }
+        }
+        let to = peer.status;
+        drop(peers);
+        if from != to {
+            self.emit(P2PEventKind::PeerStatusChanged { node_id: node_id.to_string(), from, to });
+        }
+    }
+
+    /// Peers not marked dead, healthy ones first, then by latency
+    pub fn get_healthy_peers(&self) -> Vec<PeerInfo> {
+        let mut peers: Vec<PeerInfo> = self.get_peers().into_iter().filter(|p| p.status != PeerStatus::Dead).collect();
+        peers.sort_by_key(|p| (p.status, p.latency_ms.unwrap_or(u64::MAX)));
+        peers
+    }
+
+    /// Drop dead peers, returning how many were removed
+    pub fn prune_dead_peers(&self) -> usize {
+        let mut peers = self.peers.lock().unwrap();
+        let (dead, alive): (Vec<PeerInfo>, Vec<PeerInfo>) = peers.drain(..).partition(|p| p.status == PeerStatus::Dead);
+        *peers = alive;
+        drop(peers);
+        for peer in &dead {
+            self.emit(P2PEventKind::PeerRemoved { node_id: peer.node_id.clone(), reason: RemovalReason::Pruned });
+        }
+        dead.len()
+    }
+
+    fn is_self(&self, peer: &PeerInfo) -> bool {
+        peer.node_id == self.node_id || peer.url == self.peer_url
+    }
+
+    pub(crate) fn client(&self) -> Result<reqwest::blocking::Client, P2PError> {
+        reqwest::blocking::Client::builder()
+            .timeout(self.timeout)
+            .build()
+            .map_err(P2PError::from)
+    }
+
+    /// Accepts either a bare array or `{"peers": [...]}`
+    fn parse_peers(&self, response: reqwest::blocking::Response) -> Result<Vec<PeerInfo>, P2PError> {
+        if !response.status().is_success() {
+            return Err(P2PError::from_status(response.status().as_u16()));
+        }
+        let body: Value = response.json()?;
+        let list = match body {
+            Value::Object(mut map) => map.remove("peers").unwrap_or(Value::Null),
+            other => other,
+        };
+        let peers: Vec<PeerInfo> = serde_json::from_value(list).map_err(|e| P2PError::Serialization(e.to_string()))?;
+        // The primary vets addresses itself, so private ones are allowed here
+        Ok(peers.into_iter().filter(|p| !self.is_self(p) && validate_peer_url(&p.url, true).is_ok()).collect())
+    }
+
+    /// POST a block to every peer's `/api/blocks/new`
+    pub fn broadcast_block(&self, block: impl Into<BroadcastPayload>) -> Result<BroadcastReport, P2PError> {
+        self.broadcast("/api/blocks/new", &block.into())
+    }
+
+    /// POST a transaction to every peer's `/api/transactions/new`
+    pub fn broadcast_transaction(&self, tx: impl Into<BroadcastPayload>) -> Result<BroadcastReport, P2PError> {
+        self.broadcast("/api/transactions/new", &tx.into())
+    }
+
+    /// Ok when at least one peer accepted the payload; otherwise the first peer's error,
+    /// or `Network` when there was no healthy peer to send to
+    fn broadcast(&self, path: &str, payload: &BroadcastPayload) -> Result<BroadcastReport, P2PError> {
+        let envelope = self.sign_envelope(payload.0.clone());
+        self.mark_seen(&envelope.payload_hash);
+        let mut peers = self.get_healthy_peers();
+        peers.truncate(self.max_outbound);
+        let report = self.send_envelope(path, &envelope, peers);
+        self.emit(P2PEventKind::BroadcastCompleted(report.clone()));
+        match report.failed.first() {
+            _ if report.succeeded > 0 => Ok(report),
+            Some((_, e)) => Err(e.clone()),
+            None => Err(P2PError::Network("no healthy peers".to_string())),
+        }
+    }
+
+    /// Forward a received envelope to up to `relay_fanout` random healthy peers, excluding its sender
+    pub fn relay(&self, path: &str, envelope: &SignedEnvelope) -> BroadcastReport {
+        if envelope.ttl <= 1 {
+            return BroadcastReport::default();
         }
+        let mut peers: Vec<PeerInfo> = self.get_healthy_peers().into_iter().filter(|p| p.node_id != envelope.sender_id).collect();
+        peers.shuffle(&mut rand::thread_rng());
+        peers.truncate(self.relay_fanout);
+        let forwarded = self.identity.sign_with_ttl(envelope.payload.clone(), SystemClock.now(), envelope.ttl - 1);
+        let report = self.send_envelope(path, &forwarded, peers);
+        self.gossip_metrics.lock().unwrap().relayed += report.succeeded as u64;
+        report
+    }
+
+    /// Remember a message hash, returning false if it had already been seen
+    pub fn mark_seen(&self, payload_hash: &str) -> bool {
+        let mut seen = self.seen.lock().unwrap();
+        if seen.contains(payload_hash) {
+            return false;
+        }
+        seen.put(payload_hash.to_string(), SystemClock.now());
+        true
+    }
+
+    /// Record an inbound gossip message, returning whether it is new
+    #[cfg_attr(not(feature = "p2p-server"), allow(dead_code))]
+    pub(crate) fn accept_gossip(&self, payload_hash: &str) -> bool {
+        let fresh = self.mark_seen(payload_hash);
+        let mut metrics = self.gossip_metrics.lock().unwrap();
+        if fresh {
+            metrics.received += 1;
+        } else {
+            metrics.duplicates_suppressed += 1;
+        }
+        fresh
+    }
+
+    pub fn get_gossip_metrics(&self) -> GossipMetrics {
+        self.gossip_metrics.lock().unwrap().clone()
+    }
+
+    fn send_envelope(&self, path: &str, envelope: &SignedEnvelope, peers: Vec<PeerInfo>) -> BroadcastReport {
+        let mut report = BroadcastReport { attempted: peers.len(), ..Default::default() };
+        let client = match self.client() {
+            Ok(client) => client,
+            Err(e) => {
+                report.failed = peers.into_iter().map(|p| (p.node_id, e.clone())).collect();
+                return report;
+            }
+        };
+        for batch in peers.chunks(self.max_concurrency) {
+            let results: Vec<(String, Result<(), P2PError>, u64)> = thread::scope(|scope| {
+                let handles: Vec<_> = batch
+                    .iter()
+                    .map(|peer| {
+                        scope.spawn(|| {
+                            let started = Instant::now();
+                            let result = self.send_with_retry(&client, peer, path, envelope);
+                            (peer.node_id.clone(), result, started.elapsed().as_millis() as u64)
+                        })
+                    })
+                    .collect();
+                handles.into_iter().map(|h| h.join().unwrap()).collect()
+            });
+            for (node_id, result, latency_ms) in results {
+                match result {
+                    Ok(()) => {
+                        report.succeeded += 1;
+                        self.record_contact(&node_id, Some(latency_ms));
+                    }
+                    Err(e) => {
+                        self.record_contact(&node_id, None);
+                        report.failed.push((node_id, e));
+                    }
+                }
+            }
+        }
+        report
+    }
+
+    fn send_with_retry(&self, client: &reqwest::blocking::Client, peer: &PeerInfo, path: &str, envelope: &SignedEnvelope) -> Result<(), P2PError> {
+        let url = format!("{}{}", peer.url.trim_end_matches('/'), path);
+        let mut last_error = P2PError::Network("not attempted".to_string());
+        for _ in 0..=self.max_retries {
+            match client.post(&url).json(envelope).send() {
+                Ok(response) if response.status().is_success() => return Ok(()),
+                Ok(response) => last_error = P2PError::from_status(response.status().as_u16()),
+                Err(e) => last_error = e.into(),
+            }
+        }
+        Err(last_error)
+    }
+
+    /// Consecutive failed contacts with this peer
+    pub fn failure_count(&self, node_id: &str) -> u32 {
+        self.get_peers().iter().find(|p| p.node_id == node_id).map_or(0, |p| p.consecutive_failures)
     }
 
     pub fn get_peers(&self) -> Vec<PeerInfo> {
@@ -84,45 +737,166 @@ impl P2P {
     }
 }
 
+/// Removals (old peers missing from `new`) followed by additions, matched by node_id
+fn membership_changes(old: &[PeerInfo], new: &[PeerInfo], evicted: &[PeerInfo]) -> Vec<P2PEventKind> {
+    let contains = |list: &[PeerInfo], peer: &PeerInfo| list.iter().any(|p| p.node_id == peer.node_id);
+    let mut events: Vec<P2PEventKind> = old
+        .iter()
+        .filter(|p| !contains(new, p))
+        .map(|p| P2PEventKind::PeerRemoved {
+            node_id: p.node_id.clone(),
+            reason: if contains(evicted, p) { RemovalReason::Evicted } else { RemovalReason::Dropped },
+        })
+        .collect();
+    events.extend(
+        new.iter()
+            .filter(|p| !contains(old, p))
+            .map(|p| P2PEventKind::PeerAdded { node_id: p.node_id.clone(), url: p.url.clone() }),
+    );
+    events
+}
+
+/// Sleep for `delay`, waking early once `stop_flag` is set
+pub(crate) fn sleep_unless_stopped(delay: Duration, stop_flag: &AtomicBool) {
+    let step = Duration::from_millis(100);
+    let mut waited = Duration::ZERO;
+    while waited < delay && !stop_flag.load(Ordering::Relaxed) {
+        thread::sleep(step.min(delay - waited));
+        waited += step;
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+
+    #[test]
+    fn test_serde_round_trips() {
+        let report = BroadcastReport {
+            attempted: 3,
+            succeeded: 1,
+            failed: vec![("n1".to_string(), P2PError::Banned), ("n2".to_string(), P2PError::Protocol { code: 502 })],
+        };
+        let json = serde_json::to_value(&report).unwrap();
+        assert_eq!(json["failed"][0], json!(["n1", "banned"]));
+        assert_eq!(json["failed"][1][1], json!({"protocol": {"code": 502}}));
+        assert_eq!(serde_json::from_value::<BroadcastReport>(json).unwrap(), report);
+        let error = P2PError::Timeout("10s".to_string());
+        assert_eq!(serde_json::from_value::<P2PError>(serde_json::to_value(&error).unwrap()).unwrap(), error);
+
+        let payload = BroadcastPayload::from(r#"{"hash": "h1"}"#);
+        assert_eq!(serde_json::to_value(&payload).unwrap(), json!({"hash": "h1"}));
+        assert_eq!(serde_json::from_value::<BroadcastPayload>(json!({"hash": "h1"})).unwrap(), payload);
+
+        let peer = PeerInfo { node_id: "n1".to_string(), url: "http://127.0.0.1:9001".to_string(), last_seen: Some(1_700_000_000), status: PeerStatus::Degraded, ..PeerInfo::default() };
+        assert_eq!(serde_json::from_value::<PeerInfo>(serde_json::to_value(&peer).unwrap()).unwrap(), peer);
+    }
+
     #[test]
     fn test_peer_lifecycle() {
-        let mut p2p = P2P::new("https://bank.linglin.art", "node1", "http://localhost:8080");
+        let mut p2p = P2P::new("https://bank.linglin.art", "http://localhost:8080");
         assert!(!p2p.is_running);
-        p2p.start();
+        p2p.start().unwrap();
         assert!(p2p.is_running);
         p2p.stop();
         assert!(!p2p.is_running);
     }
     #[test]
     fn test_peer_list_update() {
-        let p2p = P2P::new("https://bank.linglin.art", "node1", "http://localhost:8080");
-        let peers = vec![PeerInfo { node_id: "n2".to_string(), url: "http://n2".to_string() }];
+        let p2p = P2P::new("https://bank.linglin.art", "http://localhost:8080");
+        let peers = vec![PeerInfo::new("n2", "http://n2")];
         p2p.update_peer_list(peers.clone());
         let got = p2p.get_peers();
         assert_eq!(got, peers);
     }
 
+    fn peer(node_id: &str) -> PeerInfo {
+        PeerInfo::new(node_id, &format!("http://{}", node_id))
+    }
+
     #[test]
-    fn test_register_with_primary_adds_self() {
-        let p2p = P2P::new("https://bank.linglin.art", "nodeX", "http://localhost:9000");
-        // peers list should not contain self at first
-        assert!(p2p.get_peers().is_empty());
-        p2p.register_with_primary();
-        let peers = p2p.get_peers();
-        assert_eq!(peers.len(), 1);
-        assert_eq!(peers[0].node_id, "nodeX");
-        assert_eq!(peers[0].url, "http://localhost:9000");
+    fn test_register_with_primary() {
+        let identity = NodeIdentity::generate();
+        let mut server = mockito::Server::new();
+        let mock = server
+            .mock("POST", "/api/peers/register")
+            .match_body(mockito::Matcher::PartialJson(json!({"sender_id": identity.node_id, "payload": {"node_id": identity.node_id, "peer_url": "http://localhost:9000"}})))
+            .with_header("content-type", "application/json")
+            .with_body(r#"{"peers": [{"node_id": "nodeX", "peer_url": "http://localhost:9000"}, {"node_id": "n2", "url": "http://n2"}]}"#)
+            .create();
+        let p2p = P2P::new(&server.url(), "http://localhost:9000").with_identity(identity.clone());
+        assert_eq!(p2p.register_with_primary().unwrap(), vec![peer("n2")]);
+        assert_eq!(p2p.get_peers(), vec![peer("n2")]);
+        mock.assert();
+    }
+
+    #[test]
+    fn test_primary_down_keeps_known_peers() {
+        let p2p = P2P::new("http://127.0.0.1:1", "http://me").with_timeout(Duration::from_secs(2));
+        p2p.update_peer_list(vec![peer("n1")]);
+        assert!(matches!(p2p.register_with_primary(), Err(P2PError::Network(_))));
+        assert!(matches!(p2p.fetch_peer_list(), Err(P2PError::Network(_))));
+        assert_eq!(p2p.get_peers(), vec![peer("n1")]);
+
+        let mut server = mockito::Server::new();
+        server.mock("GET", "/api/peers").with_status(503).create();
+        let p2p = P2P::new(&server.url(), "http://me");
+        assert_eq!(p2p.fetch_peer_list(), Err(P2PError::Protocol { code: 503 }));
+    }
+
+    #[test]
+    fn test_error_variants() {
+        let p2p = P2P::new("http://127.0.0.1:1", "http://me");
+        assert!(matches!(p2p.register_with_primary(), Err(P2PError::Network(_))));
+
+        let mut server = mockito::Server::new();
+        server.mock("POST", "/api/peers/register").with_status(400).create();
+        let p2p = P2P::new(&server.url(), "http://me");
+        assert_eq!(p2p.register_with_primary(), Err(P2PError::Protocol { code: 400 }));
+        server.mock("GET", "/api/peers").with_status(403).create();
+        assert_eq!(p2p.fetch_peer_list(), Err(P2PError::Banned));
+        server.reset();
+        server.mock("GET", "/api/peers").with_body("not json").create();
+        assert!(matches!(p2p.fetch_peer_list(), Err(P2PError::Serialization(_))));
+
+        let mut p2p = P2P::new("not a url", "http://me");
+        assert!(matches!(p2p.start(), Err(P2PError::InvalidPeer(_))));
+        assert!(!p2p.is_running);
+    }
+
+    #[test]
+    fn test_fetch_peer_list_excludes_self() {
+        let mut server = mockito::Server::new();
+        server
+            .mock("GET", "/api/peers")
+            .with_body(r#"[{"node_id": "me", "url": "http://me"}, {"node_id": "n1", "url": "http://n1"}, {"node_id": "n2", "url": "http://n2"}]"#)
+            .create();
+        let p2p = P2P::new(&server.url(), "http://me");
+        assert_eq!(p2p.fetch_peer_list().unwrap(), vec![peer("n1"), peer("n2")]);
+    }
+
+    #[test]
+    fn test_background_refresh() {
+        let mut server = mockito::Server::new();
+        server.mock("POST", "/api/peers/register").with_body("[]").create();
+        server.mock("GET", "/api/peers").with_body(r#"[{"node_id": "n1", "url": "http://n1"}]"#).create();
+        let mut p2p = P2P::new(&server.url(), "http://me").with_refresh_interval(Duration::from_millis(50));
+        p2p.start().unwrap();
+        let mut waited = 0;
+        while p2p.get_peers().is_empty() && waited < 50 {
+            thread::sleep(Duration::from_millis(100));
+            waited += 1;
+        }
+        p2p.stop();
+        assert_eq!(p2p.get_peers(), vec![peer("n1")]);
     }
 
     #[test]
     fn test_update_peer_list_excludes_self() {
-        let p2p = P2P::new("https://bank.linglin.art", "me", "http://me");
+        let p2p = P2P::new("https://bank.linglin.art", "http://me");
         let peers = vec![
-            PeerInfo { node_id: "me".to_string(), url: "http://me".to_string() },
-            PeerInfo { node_id: "other".to_string(), url: "http://other".to_string() },
+            PeerInfo::new("me", "http://me"),
+            PeerInfo::new("other", "http://other"),
         ];
         p2p.update_peer_list(peers.clone());
         let got = p2p.get_peers();
@@ -132,22 +906,130 @@ mod tests {
 
     #[test]
     fn test_broadcast_block_and_transaction_no_panic() {
-        let p2p = P2P::new("https://bank.linglin.art", "n", "http://n");
+        let p2p = P2P::new("https://bank.linglin.art", "http://n");
         // Should not panic even if no peers
-        p2p.broadcast_block("blockdata");
-        p2p.broadcast_transaction("txdata");
+        assert!(matches!(p2p.broadcast_block("blockdata"), Err(P2PError::Network(_))));
+        assert!(p2p.broadcast_transaction("txdata").is_err());
         // Add a peer and test again
-        p2p.update_peer_list(vec![PeerInfo { node_id: "p".to_string(), url: "http://p".to_string() }]);
-        p2p.broadcast_block("blockdata");
-        p2p.broadcast_transaction("txdata");
+        p2p.update_peer_list(vec![PeerInfo::new("p", "http://127.0.0.1:1")]);
+        assert!(matches!(p2p.broadcast_block("blockdata"), Err(P2PError::Network(_))));
+        assert!(p2p.broadcast_transaction("txdata").is_err());
+    }
+
+    #[test]
+    fn test_broadcast_report() {
+        let mut good = mockito::Server::new();
+        let mut bad = mockito::Server::new();
+        let p2p = P2P::new("http://primary", "http://me");
+        let accepted = good
+            .mock("POST", "/api/blocks/new")
+            .match_body(mockito::Matcher::PartialJson(json!({"sender_id": p2p.node_id, "payload": {"index": 7}})))
+            .create();
+        let rejected = bad.mock("POST", "/api/blocks/new").with_status(500).expect(2).create();
+        p2p.update_peer_list(vec![
+            PeerInfo::new("good", &good.url()),
+            PeerInfo::new("bad", &bad.url()),
+        ]);
+        let block = Block { index: 7, ..Block::new() };
+        let report = p2p.broadcast_block(&block).unwrap();
+        assert_eq!(report.attempted, 2);
+        assert_eq!(report.succeeded, 1);
+        assert_eq!(report.failed, vec![("bad".to_string(), P2PError::Protocol { code: 500 })]);
+        accepted.assert();
+        rejected.assert();
+        assert_eq!(p2p.failure_count("bad"), 1);
+        assert_eq!(p2p.failure_count("good"), 0);
+
+        good.mock("POST", "/api/transactions/new").match_body(mockito::Matcher::PartialJson(json!({"payload": "txdata"}))).create();
+        bad.mock("POST", "/api/transactions/new").with_status(500).create();
+        let report = p2p.broadcast_transaction("txdata").unwrap();
+        assert_eq!(report.succeeded, 1);
+        assert_eq!(p2p.failure_count("bad"), 2);
+    }
+
+    #[test]
+    fn test_heartbeat_demotes_and_recovers() {
+        let mut up = mockito::Server::new();
+        let mut flaky = mockito::Server::new();
+        up.mock("GET", "/api/ping").create();
+        up.mock("POST", "/api/transactions/new").create();
+        let down = flaky.mock("GET", "/api/ping").with_status(503).create();
+        let p2p = P2P::new("http://primary", "http://me")
+            .with_heartbeat(Duration::from_secs(1), 2)
+            .with_dial_backoff(Duration::ZERO, Duration::ZERO);
+        p2p.update_peer_list(vec![PeerInfo::new("up", &up.url()), PeerInfo::new("flaky", &flaky.url())]);
+
+        p2p.ping_peers();
+        let flaky_peer = |p2p: &P2P| p2p.get_peers().into_iter().find(|p| p.node_id == "flaky").unwrap();
+        assert_eq!(flaky_peer(&p2p).status, PeerStatus::Degraded);
+        assert_eq!(p2p.get_healthy_peers()[0].node_id, "up");
+        assert!(p2p.get_healthy_peers()[0].last_seen.is_some());
+        p2p.ping_peers();
+        assert_eq!(flaky_peer(&p2p).status, PeerStatus::Dead);
+        let report = p2p.broadcast_transaction("txdata").unwrap();
+        assert_eq!((report.attempted, report.succeeded), (1, 1));
+
+        down.remove();
+        flaky.mock("GET", "/api/ping").create();
+        p2p.ping_peers();
+        assert_eq!(flaky_peer(&p2p).status, PeerStatus::Healthy);
+        assert_eq!(flaky_peer(&p2p).consecutive_failures, 0);
+        assert_eq!(p2p.get_healthy_peers().len(), 2);
+        assert_eq!(p2p.prune_dead_peers(), 0);
+    }
+
+    #[test]
+    fn test_max_known_drops_lowest_ranked() {
+        let p2p = P2P::new("http://primary", "http://me").with_peer_limits(2, 3);
+        p2p.update_peer_list(vec![peer("a"), peer("b"), peer("c")]);
+        p2p.record_contact("a", Some(10));
+        p2p.record_contact("b", None);
+        p2p.record_contact("c", Some(50));
+        p2p.register_peer(PeerInfo::new("d", "https://d.example.com")).unwrap();
+        let ids: Vec<String> = p2p.get_peers().into_iter().map(|p| p.node_id).collect();
+        assert_eq!(ids, vec!["a", "c", "d"]);
+
+        p2p.register_peer(PeerInfo::new("e", "https://e.example.com")).unwrap();
+        assert_eq!(p2p.get_peers().len(), 3);
+        assert!(p2p.get_peers().iter().all(|p| p.node_id != "e"));
+    }
+
+    #[test]
+    fn test_private_registration_rejected() {
+        let p2p = P2P::new("http://primary", "http://me");
+        for url in ["http://127.0.0.1:9000", "http://localhost:8080", "http://192.168.1.5", "http://[::1]:80", "ftp://node.example.com"] {
+            assert!(matches!(p2p.register_peer(PeerInfo::new("x", url)), Err(P2PError::InvalidPeer(_))), "{}", url);
+        }
+        assert!(p2p.get_peers().is_empty());
+        p2p.register_peer(PeerInfo::new("x", "https://node.example.com")).unwrap();
+
+        let local = P2P::new("http://primary", "http://me").with_allow_private(true);
+        assert!(local.register_peer(PeerInfo::new("x", "http://127.0.0.1:9000")).is_ok());
+    }
+
+    #[test]
+    fn test_dial_backoff_grows() {
+        let (base, max) = (Duration::from_secs(1), Duration::from_secs(3600));
+        let delays: Vec<Duration> = (1..=6).map(|attempt| dial_backoff(attempt, base, max)).collect();
+        assert!(delays.windows(2).all(|w| w[1] > w[0]), "{:?}", delays);
+        assert!(delays[0] >= base && delays[0] <= base.mul_f64(1.5));
+        assert_eq!(dial_backoff(30, base, max), max);
+
+        let p2p = P2P::new("http://primary", "http://me");
+        p2p.update_peer_list(vec![PeerInfo::new("p", "http://127.0.0.1:1")]);
+        p2p.ping_peers();
+        let first = p2p.get_peers()[0].next_dial_at.unwrap();
+        assert!(first >= SystemClock.now() + 5);
+        p2p.ping_peers();
+        assert_eq!(p2p.failure_count("p"), 1);
     }
 
     #[test]
     fn test_multiple_peer_add_remove() {
-        let p2p = P2P::new("https://bank.linglin.art", "main", "http://main");
+        let p2p = P2P::new("https://bank.linglin.art", "http://main");
         let mut peers = vec![];
         for i in 0..5 {
-            peers.push(PeerInfo { node_id: format!("n{}", i), url: format!("http://n{}", i) });
+            peers.push(PeerInfo::new(&format!("n{}", i), &format!("http://n{}", i)));
         }
         p2p.update_peer_list(peers.clone());
         let got = p2p.get_peers();
@@ -0,0 +1,97 @@
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use crate::core::p2p::{BroadcastReport, PeerStatus, P2P};
+use crate::utils::clock::{Clock, SystemClock};
+
+/// Why a peer left the peer list
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RemovalReason {
+    /// No longer in the list from the primary
+    Dropped,
+    /// Lowest ranked when the list exceeded `max_known`
+    Evicted,
+    /// Removed by `prune_dead_peers`
+    Pruned,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum P2PEventKind {
+    PeerAdded { node_id: String, url: String },
+    PeerRemoved { node_id: String, reason: RemovalReason },
+    PeerStatusChanged { node_id: String, from: PeerStatus, to: PeerStatus },
+    BlockReceived { from: String, height: u64 },
+    TransactionReceived { from: String, hash: String },
+    SyncStarted { peer: String, from_height: u64, to_height: u64 },
+    /// `error` is set when the sync stopped early
+    SyncFinished { peer: String, blocks_added: usize, error: Option<String> },
+    BroadcastCompleted(BroadcastReport),
+}
+
+/// Something that happened on the network, stamped with unix seconds
+#[derive(Debug, Clone, PartialEq)]
+pub struct P2PEvent {
+    pub timestamp: u64,
+    pub kind: P2PEventKind,
+}
+
+/// Senders for every live `subscribe_events` receiver
+pub(crate) type EventSubscribers = Arc<Mutex<Vec<Sender<P2PEvent>>>>;
+
+impl P2P {
+    /// Receive every event from now on; dropping the receiver unsubscribes
+    pub fn subscribe_events(&self) -> Receiver<P2PEvent> {
+        let (sender, receiver) = mpsc::channel();
+        self.events.lock().unwrap().push(sender);
+        receiver
+    }
+
+    /// Send to all subscribers. Callers must not hold the peers lock.
+    pub(crate) fn emit(&self, kind: P2PEventKind) {
+        let event = P2PEvent { timestamp: SystemClock.now(), kind };
+        self.events.lock().unwrap().retain(|subscriber| subscriber.send(event.clone()).is_ok());
+    }
+
+    pub(crate) fn emit_all(&self, kinds: Vec<P2PEventKind>) {
+        for kind in kinds {
+            self.emit(kind);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+    use serde_json::json;
+    use crate::core::blockchain::Transaction;
+
+    #[test]
+    fn test_event_sequence() {
+        let mut peer = mockito::Server::new();
+        peer.mock("POST", "/api/transactions/new").create();
+        let mut primary = mockito::Server::new();
+        let peers = json!({"peers": [{"node_id": "up", "url": peer.url()}, {"node_id": "down", "url": "http://127.0.0.1:1"}]});
+        primary.mock("POST", "/api/peers/register").with_body(peers.to_string()).create();
+        let p2p = P2P::new(&primary.url(), "http://me")
+            .with_heartbeat(Duration::from_secs(30), 1)
+            .with_broadcast_limits(2, 0)
+            .with_timeout(Duration::from_secs(2));
+        let events = p2p.subscribe_events();
+
+        p2p.register_with_primary().unwrap();
+        let report = p2p.broadcast_transaction(&Transaction { hash: Some("abc".to_string()), ..Transaction::new() }).unwrap();
+        assert_eq!(p2p.prune_dead_peers(), 1);
+
+        let kinds: Vec<P2PEventKind> = events.try_iter().map(|e| e.kind).collect();
+        assert_eq!(
+            kinds,
+            vec![
+                P2PEventKind::PeerAdded { node_id: "up".to_string(), url: peer.url() },
+                P2PEventKind::PeerAdded { node_id: "down".to_string(), url: "http://127.0.0.1:1".to_string() },
+                P2PEventKind::PeerStatusChanged { node_id: "down".to_string(), from: PeerStatus::Healthy, to: PeerStatus::Dead },
+                P2PEventKind::BroadcastCompleted(report),
+                P2PEventKind::PeerRemoved { node_id: "down".to_string(), reason: RemovalReason::Pruned },
+            ]
+        );
+    }
+}
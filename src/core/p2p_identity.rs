@@ -0,0 +1,182 @@
+use std::fmt;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use crate::core::crypto::Crypto;
+
+/// Seconds an envelope stays acceptable after it was signed
+pub const DEFAULT_MAX_ENVELOPE_AGE_SECS: u64 = 300;
+/// Hops a gossiped envelope may travel, counting the first send
+pub const DEFAULT_GOSSIP_TTL: u8 = 6;
+
+/// Why an inbound envelope was rejected
+#[derive(Debug, Clone, PartialEq)]
+pub enum EnvelopeError {
+    /// `sender_id` is not the fingerprint of the attached public key
+    IdentityMismatch { sender_id: String, fingerprint: String },
+    PayloadHashMismatch,
+    BadSignature,
+    /// Signed too long ago, or too far in the future
+    Stale { timestamp: u64, now: u64 },
+}
+
+impl fmt::Display for EnvelopeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EnvelopeError::IdentityMismatch { sender_id, fingerprint } => {
+                write!(f, "Sender {} does not match key fingerprint {}", sender_id, fingerprint)
+            }
+            EnvelopeError::PayloadHashMismatch => write!(f, "Payload does not match its hash"),
+            EnvelopeError::BadSignature => write!(f, "Invalid envelope signature"),
+            EnvelopeError::Stale { timestamp, now } => write!(f, "Envelope timestamp {} outside freshness window at {}", timestamp, now),
+        }
+    }
+}
+
+impl std::error::Error for EnvelopeError {}
+
+/// Keypair a node signs its messages with; the node_id is the public key fingerprint
+#[derive(Clone)]
+pub struct NodeIdentity {
+    private_key: String,
+    pub public_key: String,
+    pub node_id: String,
+}
+
+impl fmt::Debug for NodeIdentity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("NodeIdentity")
+            .field("public_key", &self.public_key)
+            .field("node_id", &self.node_id)
+            .finish()
+    }
+}
+
+impl fmt::Display for NodeIdentity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} ({})", self.node_id, self.public_key)
+    }
+}
+
+impl NodeIdentity {
+    pub fn generate() -> Self {
+        Self::from_private_key(&Crypto::new().generate_private_key())
+    }
+
+    pub fn from_private_key(private_key: &str) -> Self {
+        let public_key = Crypto::new().derive_public_key(private_key);
+        NodeIdentity { private_key: private_key.to_string(), node_id: fingerprint(&public_key), public_key }
+    }
+
+    /// Wrap `payload` in an envelope signed at `timestamp`
+    pub fn sign(&self, payload: Value, timestamp: u64) -> SignedEnvelope {
+        self.sign_with_ttl(payload, timestamp, DEFAULT_GOSSIP_TTL)
+    }
+
+    pub fn sign_with_ttl(&self, payload: Value, timestamp: u64, ttl: u8) -> SignedEnvelope {
+        let payload_hash = payload_hash(&payload);
+        let message = signing_message(&self.node_id, timestamp, ttl, &payload_hash);
+        SignedEnvelope {
+            sender_id: self.node_id.clone(),
+            public_key: self.public_key.clone(),
+            timestamp,
+            ttl,
+            payload_hash,
+            signature: Crypto::new().sign_data(&message, &self.private_key),
+            payload,
+        }
+    }
+}
+
+/// First 40 hex chars of the SHA-256 of a public key
+pub fn fingerprint(public_key: &str) -> String {
+    hex::encode(Sha256::digest(public_key.to_lowercase().as_bytes()))[..40].to_string()
+}
+
+fn payload_hash(payload: &Value) -> String {
+    // serde_json::Map keeps keys sorted, so the serialization is canonical
+    hex::encode(Sha256::digest(payload.to_string().as_bytes()))
+}
+
+fn signing_message(sender_id: &str, timestamp: u64, ttl: u8, payload_hash: &str) -> String {
+    format!("{}|{}|{}|{}", sender_id, timestamp, ttl, payload_hash)
+}
+
+fn default_ttl() -> u8 {
+    DEFAULT_GOSSIP_TTL
+}
+
+/// A payload with its sender's identity and signature, as sent between peers
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SignedEnvelope {
+    pub sender_id: String,
+    pub public_key: String,
+    pub timestamp: u64,
+    /// Remaining relay hops
+    #[serde(default = "default_ttl")]
+    pub ttl: u8,
+    /// Also identifies the message in gossip seen-caches
+    pub payload_hash: String,
+    pub signature: String,
+    pub payload: Value,
+}
+
+impl SignedEnvelope {
+    /// Check identity, payload hash, signature, and that the timestamp is within `max_age_secs` of `now`
+    pub fn verify(&self, now: u64, max_age_secs: u64) -> Result<(), EnvelopeError> {
+        let expected = fingerprint(&self.public_key);
+        if self.sender_id != expected {
+            return Err(EnvelopeError::IdentityMismatch { sender_id: self.sender_id.clone(), fingerprint: expected });
+        }
+        if payload_hash(&self.payload) != self.payload_hash {
+            return Err(EnvelopeError::PayloadHashMismatch);
+        }
+        let message = signing_message(&self.sender_id, self.timestamp, self.ttl, &self.payload_hash);
+        if !Crypto::new().verify_signature(&message, &self.signature, &self.public_key) {
+            return Err(EnvelopeError::BadSignature);
+        }
+        if now.abs_diff(self.timestamp) > max_age_secs {
+            return Err(EnvelopeError::Stale { timestamp: self.timestamp, now });
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_envelope_verification() {
+        let identity = NodeIdentity::generate();
+        assert_eq!(identity.node_id, fingerprint(&identity.public_key));
+        let envelope = identity.sign(json!({"hash": "abc", "amount": 5.0}), 1_000);
+        assert_eq!(envelope.verify(1_010, DEFAULT_MAX_ENVELOPE_AGE_SECS), Ok(()));
+
+        let mut tampered = envelope.clone();
+        tampered.payload["amount"] = json!(500.0);
+        assert_eq!(tampered.verify(1_010, DEFAULT_MAX_ENVELOPE_AGE_SECS), Err(EnvelopeError::PayloadHashMismatch));
+
+        let mut impostor = envelope.clone();
+        impostor.sender_id = "someone-else".to_string();
+        assert!(matches!(impostor.verify(1_010, DEFAULT_MAX_ENVELOPE_AGE_SECS), Err(EnvelopeError::IdentityMismatch { .. })));
+
+        let stale = envelope.verify(1_000 + DEFAULT_MAX_ENVELOPE_AGE_SECS + 1, DEFAULT_MAX_ENVELOPE_AGE_SECS);
+        assert_eq!(stale, Err(EnvelopeError::Stale { timestamp: 1_000, now: 1_301 }));
+    }
+
+    #[test]
+    fn test_envelope_rejects_another_keys_signature() {
+        let identity = NodeIdentity::generate();
+        let envelope = identity.sign(json!({"hash": "abc"}), 1_000);
+        let mut forged = envelope.clone();
+        forged.signature = NodeIdentity::generate().sign(json!({"hash": "abc"}), 1_000).signature;
+        assert_eq!(forged.signature.len(), envelope.signature.len());
+        assert_eq!(forged.verify(1_010, DEFAULT_MAX_ENVELOPE_AGE_SECS), Err(EnvelopeError::BadSignature));
+
+        let mut garbage = envelope;
+        garbage.signature = "ab".repeat(64);
+        assert_eq!(garbage.verify(1_010, DEFAULT_MAX_ENVELOPE_AGE_SECS), Err(EnvelopeError::BadSignature));
+    }
+}
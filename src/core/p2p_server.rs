@@ -0,0 +1,353 @@
+use std::io::Read;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use serde::Deserialize;
+use serde::de::DeserializeOwned;
+use serde_json::{json, Value};
+use tiny_http::{Header, Method, Request, Response, Server};
+use crate::core::blockchain::{Block, Transaction};
+use crate::core::p2p::{P2PError, PeerInfo, P2P};
+use crate::core::p2p_events::P2PEventKind;
+use crate::core::p2p_identity::SignedEnvelope;
+use crate::core::p2p_sync::SYNC_BATCH_SIZE;
+use crate::utils::clock::{Clock, SystemClock};
+
+/// Largest request body the server reads; a signed block with its transactions fits well within it
+pub const MAX_BODY_BYTES: u64 = 4 * 1024 * 1024;
+
+pub type BlockHandler = Box<dyn Fn(Block) + Send + Sync>;
+pub type TransactionHandler = Box<dyn Fn(Transaction) + Send + Sync>;
+
+/// Payload of a signed `/api/peers/register` request
+#[derive(Deserialize)]
+struct Registration {
+    peer_url: String,
+}
+
+/// Handlers and the running server, shared by every clone of a P2P instance
+#[derive(Default)]
+pub(crate) struct Inbound {
+    on_block: Mutex<Option<BlockHandler>>,
+    on_transaction: Mutex<Option<TransactionHandler>>,
+    server: Mutex<Option<(Arc<Server>, JoinHandle<()>)>>,
+}
+
+impl P2P {
+    /// Called with each block POSTed to `/api/blocks/new`
+    pub fn on_block(&self, handler: BlockHandler) {
+        *self.inbound.on_block.lock().unwrap() = Some(handler);
+    }
+
+    /// Called with each transaction POSTed to `/api/transactions/new`
+    pub fn on_transaction(&self, handler: TransactionHandler) {
+        *self.inbound.on_transaction.lock().unwrap() = Some(handler);
+    }
+
+    /// Serve the inbound P2P API on `bind_addr`, returning the bound address
+    pub fn start_server(&self, bind_addr: &str) -> Result<SocketAddr, P2PError> {
+        let mut running = self.inbound.server.lock().unwrap();
+        if running.is_some() {
+            return Err(P2PError::Server("server already running".to_string()));
+        }
+        let server = Arc::new(Server::http(bind_addr).map_err(|e| P2PError::Server(e.to_string()))?);
+        let addr = server
+            .server_addr()
+            .to_ip()
+            .ok_or_else(|| P2PError::Server("not bound to an IP address".to_string()))?;
+        let node = self.clone();
+        let listener = Arc::clone(&server);
+        let handle = thread::spawn(move || {
+            for request in listener.incoming_requests() {
+                node.handle_request(request);
+            }
+        });
+        *running = Some((server, handle));
+        Ok(addr)
+    }
+
+    /// Stop accepting requests and wait for the server thread to finish
+    pub fn stop_server(&self) {
+        let running = self.inbound.server.lock().unwrap().take();
+        if let Some((server, handle)) = running {
+            server.unblock();
+            let _ = handle.join();
+        }
+    }
+
+    fn handle_request(&self, mut request: Request) {
+        let (status, reply) = match read_body(&mut request) {
+            Err((status, e)) => (status, json!({"error": e})),
+            Ok(body) => self.route(request.method(), request.url(), &body),
+        };
+        let header = Header::from_bytes("Content-Type", "application/json").unwrap();
+        let response = Response::from_string(reply.to_string()).with_status_code(status).with_header(header);
+        let _ = request.respond(response);
+    }
+
+    fn route(&self, method: &Method, url: &str, body: &str) -> (u16, Value) {
+        let (url, query) = url.split_once('?').unwrap_or((url, ""));
+        match (method, url) {
+            (Method::Get, "/api/ping") => (200, json!({"status": "ok", "node_id": self.node_id})),
+            (Method::Get, "/api/peers") => (200, self.peer_listing()),
+            (Method::Post, "/api/peers/register") => {
+                let (envelope, registration) = match self.open_envelope::<Registration>(body) {
+                    Ok(opened) => opened,
+                    Err((status, e)) => return (status, json!({"error": e})),
+                };
+                // The signed sender_id, not a claimed node_id, identifies the peer
+                match self.register_peer(PeerInfo::new(&envelope.sender_id, &registration.peer_url)) {
+                    Ok(()) => (200, self.peer_listing()),
+                    Err(e) => (400, json!({"error": e.to_string()})),
+                }
+            }
+            (Method::Get, "/api/chain/status") => (200, json!(self.chain_status())),
+            (Method::Get, "/api/blocks") => match block_range(query) {
+                Some((start, end)) => (200, json!({"blocks": self.blocks_in_range(start, end)})),
+                None => (400, json!({"error": "start and end are required, with start <= end"})),
+            },
+            (Method::Post, "/api/blocks/new") => self.receive(
+                url,
+                body,
+                |block: &Block| if block.hash.is_empty() { Err("block hash is required") } else { Ok(()) },
+                |from, block| {
+                    self.emit(P2PEventKind::BlockReceived { from: from.to_string(), height: block.index });
+                    if let Some(handler) = self.inbound.on_block.lock().unwrap().as_ref() {
+                        handler(block);
+                    }
+                },
+            ),
+            (Method::Post, "/api/transactions/new") => self.receive(
+                url,
+                body,
+                |tx: &Transaction| match tx.hash.as_deref() {
+                    Some(hash) if !hash.is_empty() => Ok(()),
+                    _ => Err("transaction hash is required"),
+                },
+                |from, tx| {
+                    let hash = tx.hash.clone().unwrap_or_default();
+                    self.emit(P2PEventKind::TransactionReceived { from: from.to_string(), hash });
+                    if let Some(handler) = self.inbound.on_transaction.lock().unwrap().as_ref() {
+                        handler(tx);
+                    }
+                },
+            ),
+            _ => (404, json!({"error": "not found"})),
+        }
+    }
+
+    /// Known peers plus this node, as served at `/api/peers`
+    fn peer_listing(&self) -> Value {
+        let mut peers = self.get_peers();
+        peers.push(PeerInfo::new(&self.node_id, &self.peer_url));
+        json!({"peers": peers})
+    }
+
+    /// Verify, deduplicate, deliver, then relay a gossiped payload in the background
+    fn receive<T: DeserializeOwned>(
+        &self,
+        path: &str,
+        body: &str,
+        validate: impl Fn(&T) -> Result<(), &'static str>,
+        deliver: impl FnOnce(&str, T),
+    ) -> (u16, Value) {
+        let (envelope, payload) = match self.open_envelope::<T>(body) {
+            Ok(opened) => opened,
+            Err((status, e)) => return (status, json!({"error": e})),
+        };
+        if let Err(e) = validate(&payload) {
+            return (400, json!({"error": e}));
+        }
+        if !self.accept_gossip(&envelope.payload_hash) {
+            return (200, json!({"status": "duplicate"}));
+        }
+        deliver(&envelope.sender_id, payload);
+        let node = self.clone();
+        let path = path.to_string();
+        thread::spawn(move || node.relay(&path, &envelope));
+        (200, json!({"status": "accepted"}))
+    }
+
+    /// Verified envelope and its payload; failures count as a strike against the claimed sender
+    fn open_envelope<T: DeserializeOwned>(&self, body: &str) -> Result<(SignedEnvelope, T), (u16, String)> {
+        let envelope: SignedEnvelope = serde_json::from_str(body).map_err(|e| (400, e.to_string()))?;
+        if let Err(e) = envelope.verify(SystemClock.now(), self.max_envelope_age_secs) {
+            self.record_contact(&envelope.sender_id, None);
+            return Err((401, e.to_string()));
+        }
+        let payload = serde_json::from_value(envelope.payload.clone()).map_err(|e| (400, e.to_string()))?;
+        Ok((envelope, payload))
+    }
+}
+
+/// `start` and `end` from a query string, capped at one sync batch
+fn block_range(query: &str) -> Option<(u64, u64)> {
+    let param = |name: &str| {
+        query
+            .split('&')
+            .filter_map(|pair| pair.split_once('='))
+            .find(|(key, _)| *key == name)
+            .and_then(|(_, value)| value.parse::<u64>().ok())
+    };
+    let (start, end) = (param("start")?, param("end")?);
+    (start <= end).then(|| (start, end.min(start + SYNC_BATCH_SIZE - 1)))
+}
+
+/// The request body, refused with 413 when its declared or actual length exceeds `MAX_BODY_BYTES`
+fn read_body(request: &mut Request) -> Result<String, (u16, String)> {
+    let too_large = || (413, format!("request body exceeds {} bytes", MAX_BODY_BYTES));
+    if request.body_length().is_some_and(|len| len as u64 > MAX_BODY_BYTES) {
+        return Err(too_large());
+    }
+    let mut body = String::new();
+    request
+        .as_reader()
+        .take(MAX_BODY_BYTES + 1)
+        .read_to_string(&mut body)
+        .map_err(|e| (400, e.to_string()))?;
+    if body.len() as u64 > MAX_BODY_BYTES {
+        return Err(too_large());
+    }
+    Ok(body)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::mpsc;
+    use crate::core::blockchain::BlockchainManager;
+    use std::time::Duration;
+
+    #[test]
+    fn test_transaction_between_two_nodes() {
+        let receiver = P2P::new("http://primary", "http://unused");
+        let (sender_tx, received) = mpsc::channel();
+        let sender_tx = Mutex::new(sender_tx);
+        receiver.on_transaction(Box::new(move |tx| sender_tx.lock().unwrap().send(tx).unwrap()));
+        let events = receiver.subscribe_events();
+        let addr = receiver.start_server("127.0.0.1:0").unwrap();
+
+        let sender = P2P::new("http://primary", "http://sender");
+        sender.update_peer_list(vec![PeerInfo::new("receiver", &format!("http://{}", addr))]);
+        let tx = Transaction { hash: Some("abc".to_string()), amount: Some(5.0), ..Transaction::new() };
+        let report = sender.broadcast_transaction(&tx).unwrap();
+        assert_eq!(report.succeeded, 1, "{:?}", report.failed);
+        let got = received.recv_timeout(Duration::from_secs(5)).unwrap();
+        assert_eq!(got.hash.as_deref(), Some("abc"));
+        let received_event = events.recv_timeout(Duration::from_secs(5)).unwrap().kind;
+        assert_eq!(received_event, P2PEventKind::TransactionReceived { from: sender.node_id.clone(), hash: "abc".to_string() });
+
+        assert_eq!(sender.broadcast_transaction(&Transaction::new()), Err(P2PError::Protocol { code: 400 }));
+        receiver.update_peer_list(vec![PeerInfo::new(&sender.node_id, "http://sender")]);
+        let mut tampered = sender.sign_envelope(json!({"hash": "abc", "amount": 5.0}));
+        tampered.payload["amount"] = json!(5_000.0);
+        let url = format!("http://{}/api/transactions/new", addr);
+        let response = reqwest::blocking::Client::new().post(&url).json(&tampered).send().unwrap();
+        assert_eq!(response.status().as_u16(), 401);
+        assert!(received.try_recv().is_err());
+        assert_eq!(receiver.failure_count(&sender.node_id), 1);
+        sender.ping_peers();
+        assert!(sender.get_peers()[0].last_seen.is_some());
+
+        receiver.stop_server();
+        assert!(reqwest::blocking::get(format!("http://{}/api/ping", addr)).is_err());
+    }
+
+    #[test]
+    fn test_oversized_body_rejected() {
+        let node = P2P::new("http://primary", "http://unused");
+        let addr = node.start_server("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/api/transactions/new", addr);
+        let client = reqwest::blocking::Client::new();
+        let oversized = vec![b' '; MAX_BODY_BYTES as usize + 1];
+        let declared = client.post(&url).body(oversized.clone()).send().unwrap();
+        assert_eq!(declared.status().as_u16(), 413);
+        // Chunked, so no Content-Length to check up front
+        let chunked = client.post(&url).body(reqwest::blocking::Body::new(std::io::Cursor::new(oversized))).send().unwrap();
+        assert_eq!(chunked.status().as_u16(), 413);
+        node.stop_server();
+    }
+
+    #[test]
+    fn test_chain_sync_between_two_nodes() {
+        let source_chain = BlockchainManager::new("http://unused", 1);
+        let mut previous_hash = String::new();
+        for index in 0..3u64 {
+            let mut block = Block { index, previous_hash, difficulty: Some(2), ..Block::new() };
+            block.hash = BlockchainManager::recompute_block_hash(&block);
+            previous_hash = block.hash.clone();
+            source_chain.cache.lock().unwrap().insert(index, block);
+        }
+        let source = P2P::new("http://primary", "http://unused").with_blockchain(&source_chain);
+        let addr = source.start_server("127.0.0.1:0").unwrap();
+        let status: Value = reqwest::blocking::get(format!("http://{}/api/chain/status", addr)).unwrap().json().unwrap();
+        assert_eq!(status, json!({"height": 2, "tip_hash": previous_hash, "total_difficulty": 6}));
+        let bad = reqwest::blocking::get(format!("http://{}/api/blocks?start=2&end=1", addr)).unwrap();
+        assert_eq!(bad.status().as_u16(), 400);
+
+        let fresh_chain = BlockchainManager::new("http://unused", 1);
+        let fresh = P2P::new("http://primary", "http://fresh").with_blockchain(&fresh_chain);
+        fresh.update_peer_list(vec![PeerInfo::new(&source.node_id, &format!("http://{}", addr))]);
+        assert_eq!(fresh.sync_with_best_peer().unwrap(), 3);
+        assert_eq!(fresh.chain_status(), source.chain_status());
+        source.stop_server();
+    }
+
+    #[test]
+    fn test_remote_registration() {
+        let node = P2P::new("http://primary", "http://unused");
+        let addr = node.start_server("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/api/peers/register", addr);
+        let joiner = P2P::new("http://primary", "http://127.0.0.1:9000");
+        let envelope = joiner.sign_envelope(json!({"node_id": joiner.node_id, "peer_url": joiner.peer_url}));
+        let response = reqwest::blocking::Client::new().post(&url).json(&envelope).send().unwrap();
+        assert_eq!(response.status().as_u16(), 400);
+        assert!(node.get_peers().is_empty());
+
+        let joiner = joiner.with_advertised_url("https://node.example.com");
+        let envelope = joiner.sign_envelope(json!({"node_id": joiner.node_id, "peer_url": joiner.peer_url}));
+        let response = reqwest::blocking::Client::new().post(&url).json(&envelope).send().unwrap();
+        assert_eq!(response.status().as_u16(), 200);
+        assert_eq!(node.get_peers(), vec![PeerInfo::new(&joiner.node_id, "https://node.example.com")]);
+        node.stop_server();
+    }
+
+    fn counting_node() -> (P2P, SocketAddr, Arc<Mutex<u32>>) {
+        let node = P2P::new("http://primary", "http://unused");
+        let count = Arc::new(Mutex::new(0));
+        let counter = Arc::clone(&count);
+        node.on_transaction(Box::new(move |_| *counter.lock().unwrap() += 1));
+        let addr = node.start_server("127.0.0.1:0").unwrap();
+        (node, addr, count)
+    }
+
+    fn as_peer(node: &P2P, addr: SocketAddr) -> PeerInfo {
+        PeerInfo::new(&node.node_id, &format!("http://{}", addr))
+    }
+
+    #[test]
+    fn test_gossip_reaches_each_node_once() {
+        let (a, a_addr, _) = counting_node();
+        let (b, b_addr, b_count) = counting_node();
+        let (c, c_addr, c_count) = counting_node();
+        a.update_peer_list(vec![as_peer(&b, b_addr), as_peer(&c, c_addr)]);
+        b.update_peer_list(vec![as_peer(&a, a_addr), as_peer(&c, c_addr)]);
+        c.update_peer_list(vec![as_peer(&a, a_addr), as_peer(&b, b_addr)]);
+
+        let tx = Transaction { hash: Some("gossip".to_string()), ..Transaction::new() };
+        assert_eq!(a.broadcast_transaction(&tx).unwrap().succeeded, 2);
+        let mut waited = 0;
+        while c.get_gossip_metrics().duplicates_suppressed == 0 && waited < 50 {
+            thread::sleep(Duration::from_millis(100));
+            waited += 1;
+        }
+        assert_eq!(*c_count.lock().unwrap(), 1);
+        assert_eq!(*b_count.lock().unwrap(), 1);
+        assert_eq!(c.get_gossip_metrics().received, 1);
+        assert!(c.get_gossip_metrics().duplicates_suppressed >= 1);
+        assert!(b.get_gossip_metrics().relayed >= 1);
+        for node in [&a, &b, &c] {
+            node.stop_server();
+        }
+    }
+}
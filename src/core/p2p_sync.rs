@@ -0,0 +1,246 @@
+use serde::{Deserialize, Serialize};
+use crate::core::blockchain::{Block, BlockHeader, BlockchainManager};
+use crate::core::p2p::{P2PError, PeerInfo, P2P};
+use crate::core::p2p_events::P2PEventKind;
+
+/// Blocks requested per `/api/blocks` call while catching up
+pub const SYNC_BATCH_SIZE: u64 = 100;
+
+/// Summary of a node's chain, served at `/api/chain/status`
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ChainStatus {
+    pub height: u64,
+    pub tip_hash: String,
+    /// Sum of block difficulties
+    pub total_difficulty: u64,
+}
+
+/// How our chain relates to a peer's
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ChainComparison {
+    InSync,
+    Ahead,
+    Behind { gap: u64 },
+    /// Tips disagree; the common ancestor is at or below the hinted height
+    Forked { common_ancestor_hint: u64 },
+}
+
+impl P2P {
+    /// Height, tip and total difficulty of the attached chain; empty without one
+    pub fn chain_status(&self) -> ChainStatus {
+        let Some(chain) = &self.chain else {
+            return ChainStatus::default();
+        };
+        let chain = chain.lock().unwrap();
+        let Some(tip) = chain.values().next_back() else {
+            return ChainStatus::default();
+        };
+        ChainStatus {
+            height: tip.index,
+            tip_hash: tip.hash.clone(),
+            total_difficulty: chain.values().map(|b| b.difficulty.unwrap_or(0)).sum(),
+        }
+    }
+
+    /// Blocks `start..=end` from the attached chain, stopping at the first gap
+    pub fn blocks_in_range(&self, start: u64, end: u64) -> Vec<Block> {
+        let Some(chain) = &self.chain else {
+            return Vec::new();
+        };
+        let chain = chain.lock().unwrap();
+        (start..=end).map_while(|h| chain.peek(&h).cloned()).collect()
+    }
+
+    pub fn fetch_chain_status(&self, peer: &PeerInfo) -> Result<ChainStatus, P2PError> {
+        let response = self
+            .client()?
+            .get(format!("{}/api/chain/status", peer.url.trim_end_matches('/')))
+            .send()
+            ?;
+        if !response.status().is_success() {
+            return Err(P2PError::from_status(response.status().as_u16()));
+        }
+        Ok(response.json()?)
+    }
+
+    pub fn compare_chains(&self, peer: &PeerInfo) -> Result<ChainComparison, P2PError> {
+        let theirs = self.fetch_chain_status(peer)?;
+        let ours = self.chain_status();
+        if theirs.height > ours.height || ours.tip_hash.is_empty() {
+            return Ok(ChainComparison::Behind { gap: theirs.height.saturating_sub(ours.height) });
+        }
+        let our_hash_at_their_tip = self.blocks_in_range(theirs.height, theirs.height).pop().map(|b| b.hash);
+        if our_hash_at_their_tip.as_deref() != Some(theirs.tip_hash.as_str()) {
+            return Ok(ChainComparison::Forked { common_ancestor_hint: theirs.height.saturating_sub(1) });
+        }
+        if theirs.height == ours.height {
+            Ok(ChainComparison::InSync)
+        } else {
+            Ok(ChainComparison::Ahead)
+        }
+    }
+
+    /// Fetch blocks `start..=end` from a peer and add them to the attached chain once they link up
+    pub fn request_blocks_from_peer(&self, peer: &PeerInfo, start: u64, end: u64) -> Result<Vec<Block>, P2PError> {
+        let response = self
+            .client()?
+            .get(format!("{}/api/blocks", peer.url.trim_end_matches('/')))
+            .query(&[("start", start), ("end", end)])
+            .send()
+            ?;
+        if !response.status().is_success() {
+            return Err(P2PError::from_status(response.status().as_u16()));
+        }
+        let body: serde_json::Value = response.json()?;
+        let blocks: Vec<Block> = serde_json::from_value(body.get("blocks").cloned().unwrap_or(body))
+            .map_err(|e| P2PError::Serialization(e.to_string()))?;
+        self.check_linkage(start, &blocks)?;
+        if let Some(chain) = &self.chain {
+            let mut chain = chain.lock().unwrap();
+            for block in &blocks {
+                chain.insert(block.index, block.clone());
+            }
+        }
+        Ok(blocks)
+    }
+
+    /// Blocks must start at `start` and pass `validate_chain_segment` on top of our block at
+    /// `start - 1`; a batch whose parent we do not hold is refused
+    fn check_linkage(&self, start: u64, blocks: &[Block]) -> Result<(), P2PError> {
+        if let Some(first) = blocks.first()
+            && first.index != start
+        {
+            return Err(P2PError::InvalidPeer(format!("expected block {}, got {}", start, first.index)));
+        }
+        let parent = match start {
+            0 => None,
+            _ => match self.blocks_in_range(start - 1, start - 1).pop() {
+                Some(block) => Some(BlockHeader::from(&block)),
+                None => return Err(P2PError::InvalidPeer(format!("parent of block {} is unknown", start))),
+            },
+        };
+        BlockchainManager::validate_chain_segment_after(parent, blocks, self.chain_timestamp_tolerance)
+            .map_err(|e| P2PError::InvalidPeer(e.to_string()))
+    }
+
+    /// Catch up from the healthy peer with the most work, returning the blocks added
+    pub fn sync_with_best_peer(&self) -> Result<usize, P2PError> {
+        let ours = self.chain_status();
+        let best = self
+            .get_healthy_peers()
+            .into_iter()
+            .filter_map(|peer| self.fetch_chain_status(&peer).ok().map(|status| (peer, status)))
+            .max_by_key(|(_, status)| (status.total_difficulty, status.height));
+        let Some((peer, theirs)) = best else {
+            return Ok(0);
+        };
+        let mut next = if ours.tip_hash.is_empty() { 0 } else { ours.height + 1 };
+        if next > theirs.height {
+            return Ok(0);
+        }
+        self.emit(P2PEventKind::SyncStarted { peer: peer.node_id.clone(), from_height: next, to_height: theirs.height });
+        let mut added = 0;
+        let mut result = Ok(());
+        while next <= theirs.height {
+            let end = (next + SYNC_BATCH_SIZE - 1).min(theirs.height);
+            match self.request_blocks_from_peer(&peer, next, end) {
+                Ok(blocks) if blocks.is_empty() => break,
+                Ok(blocks) => {
+                    added += blocks.len();
+                    next += blocks.len() as u64;
+                }
+                Err(e) => {
+                    result = Err(e);
+                    break;
+                }
+            }
+        }
+        let error = result.as_ref().err().map(|e| e.to_string());
+        self.emit(P2PEventKind::SyncFinished { peer: peer.node_id, blocks_added: added, error });
+        result.map(|()| added)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::blockchain::ChainValidationError;
+
+    fn chain(len: u64) -> Vec<Block> {
+        let mut blocks: Vec<Block> = Vec::new();
+        for index in 0..len {
+            let previous_hash = blocks.last().map(|b| b.hash.clone()).unwrap_or_default();
+            let mut block = Block { index, previous_hash, difficulty: Some(1), ..Block::new() };
+            block.hash = BlockchainManager::recompute_block_hash(&block);
+            blocks.push(block);
+        }
+        blocks
+    }
+
+    #[test]
+    fn test_sync_fresh_node_from_peer() {
+        let blocks = chain(11);
+        let mut server = mockito::Server::new();
+        let status = ChainStatus { height: 10, tip_hash: blocks[10].hash.clone(), total_difficulty: 11 };
+        server.mock("GET", "/api/chain/status").with_body(serde_json::to_string(&status).unwrap()).create();
+        server
+            .mock("GET", "/api/blocks")
+            .match_query(mockito::Matcher::AllOf(vec![
+                mockito::Matcher::UrlEncoded("start".to_string(), "0".to_string()),
+                mockito::Matcher::UrlEncoded("end".to_string(), "10".to_string()),
+            ]))
+            .with_body(serde_json::json!({"blocks": blocks}).to_string())
+            .create();
+
+        let manager = BlockchainManager::new("http://unused", 1);
+        let p2p = P2P::new("http://primary", "http://me").with_blockchain(&manager);
+        let peer = PeerInfo::new("peer", &server.url());
+        assert_eq!(p2p.compare_chains(&peer).unwrap(), ChainComparison::Behind { gap: 10 });
+
+        p2p.update_peer_list(vec![peer.clone()]);
+        assert_eq!(p2p.sync_with_best_peer().unwrap(), 11);
+        assert_eq!(manager.cache.lock().unwrap().len(), 11);
+        assert_eq!(p2p.chain_status(), status);
+        assert_eq!(p2p.compare_chains(&peer).unwrap(), ChainComparison::InSync);
+    }
+
+    #[test]
+    fn test_compare_ahead_and_forked() {
+        let mut server = mockito::Server::new();
+        let status = ChainStatus { height: 3, tip_hash: chain(6)[3].hash.clone(), total_difficulty: 4 };
+        let mock = server.mock("GET", "/api/chain/status").with_body(serde_json::to_string(&status).unwrap()).create();
+        let manager = BlockchainManager::new("http://unused", 1);
+        manager.cache.lock().unwrap().extend(chain(6));
+        let p2p = P2P::new("http://primary", "http://me").with_blockchain(&manager);
+        let peer = PeerInfo::new("peer", &server.url());
+        assert_eq!(p2p.compare_chains(&peer).unwrap(), ChainComparison::Ahead);
+
+        mock.remove();
+        let forked = ChainStatus { tip_hash: "other".to_string(), ..status };
+        server.mock("GET", "/api/chain/status").with_body(serde_json::to_string(&forked).unwrap()).create();
+        assert_eq!(p2p.compare_chains(&peer).unwrap(), ChainComparison::Forked { common_ancestor_hint: 2 });
+    }
+
+    #[test]
+    fn test_rejects_unlinked_or_altered_batches() {
+        let mut blocks = chain(4);
+        blocks[2].miner = Some("mallory".to_string());
+        let mut server = mockito::Server::new();
+        server
+            .mock("GET", "/api/blocks")
+            .match_query(mockito::Matcher::Any)
+            .with_body(serde_json::json!({"blocks": blocks[1..]}).to_string())
+            .create();
+        let peer = PeerInfo::new("peer", &server.url());
+
+        let manager = BlockchainManager::new("http://unused", 1);
+        let p2p = P2P::new("http://primary", "http://me").with_blockchain(&manager);
+        let unknown_parent = p2p.request_blocks_from_peer(&peer, 1, 3).unwrap_err();
+        assert_eq!(unknown_parent, P2PError::InvalidPeer("parent of block 1 is unknown".to_string()));
+
+        manager.cache.lock().unwrap().insert(0, blocks[0].clone());
+        let altered = p2p.request_blocks_from_peer(&peer, 1, 3).unwrap_err();
+        assert_eq!(altered, P2PError::InvalidPeer(ChainValidationError::HashMismatch { height: 2 }.to_string()));
+        assert_eq!(manager.cache.lock().unwrap().len(), 1);
+    }
+}
@@ -0,0 +1,111 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+use crate::core::p2p::sleep_unless_stopped;
+
+/// Cloneable stop signal handed to each subsystem
+#[derive(Debug, Clone, Default)]
+pub struct ShutdownToken {
+    flag: Arc<AtomicBool>,
+}
+
+impl ShutdownToken {
+    pub fn is_cancelled(&self) -> bool {
+        self.flag.load(Ordering::Relaxed)
+    }
+
+    pub fn cancel(&self) {
+        self.flag.store(true, Ordering::Relaxed);
+    }
+
+    pub(crate) fn reset(&self) {
+        self.flag.store(false, Ordering::Relaxed);
+    }
+
+    /// Sleep for `delay`, waking early on cancellation; returns whether it was cancelled
+    pub fn sleep(&self, delay: Duration) -> bool {
+        sleep_unless_stopped(delay, &self.flag);
+        self.is_cancelled()
+    }
+}
+
+/// Runs when shutdown is signalled, e.g. to unblock a server waiting on a socket
+pub type SignalHook = Box<dyn FnOnce() + Send>;
+
+struct Component {
+    name: String,
+    handle: JoinHandle<()>,
+    on_signal: Option<SignalHook>,
+}
+
+/// Which components acknowledged shutdown in time
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ShutdownReport {
+    /// Joined before their timeout, in stop order
+    pub clean: Vec<String>,
+    /// Still running when their timeout passed; their threads are left detached
+    pub timed_out: Vec<String>,
+    /// Mempool transactions written to the database
+    pub mempool_flushed: usize,
+}
+
+impl ShutdownReport {
+    pub fn is_clean(&self) -> bool {
+        self.timed_out.is_empty()
+    }
+}
+
+/// Owns the threads a Daemon starts and stops them together
+#[derive(Default)]
+pub struct ShutdownCoordinator {
+    token: ShutdownToken,
+    components: Mutex<Vec<Component>>,
+}
+
+impl ShutdownCoordinator {
+    pub fn token(&self) -> ShutdownToken {
+        self.token.clone()
+    }
+
+    /// Track a started thread; components stop in the order they were registered
+    pub fn register(&self, name: &str, handle: JoinHandle<()>, on_signal: Option<SignalHook>) {
+        self.components.lock().unwrap().push(Component { name: name.to_string(), handle, on_signal });
+    }
+
+    /// Run `work` on a new thread with this coordinator's token, and track it
+    pub fn spawn<F>(&self, name: &str, work: F)
+    where
+        F: FnOnce(ShutdownToken) + Send + 'static,
+    {
+        let token = self.token();
+        self.register(name, thread::spawn(move || work(token)), None);
+    }
+
+    /// Signal every component, then join each with up to `timeout` to finish
+    pub fn shutdown(&self, timeout: Duration) -> ShutdownReport {
+        self.token.cancel();
+        let components = std::mem::take(&mut *self.components.lock().unwrap());
+        let mut waiting = Vec::with_capacity(components.len());
+        for component in components {
+            if let Some(on_signal) = component.on_signal {
+                on_signal();
+            }
+            waiting.push((component.name, component.handle));
+        }
+        let mut report = ShutdownReport::default();
+        for (name, handle) in waiting {
+            let deadline = Instant::now() + timeout;
+            while !handle.is_finished() && Instant::now() < deadline {
+                thread::sleep(Duration::from_millis(10));
+            }
+            if handle.is_finished() {
+                let _ = handle.join();
+                report.clean.push(name);
+            } else {
+                report.timed_out.push(name);
+            }
+        }
+        report
+    }
+}
@@ -1,43 +1,60 @@
+/// Keys and signatures in the SM2 hex layout: `04`-prefixed public keys and 128-character
+/// signatures. Until an SM2 implementation is a dependency they are Ed25519, through ring.
 pub struct SM2;
 
 use rand::RngCore;
+use ring::signature::{Ed25519KeyPair, KeyPair, UnparsedPublicKey, ED25519};
 use sha2::{Digest, Sha256};
 
+pub const ADDRESS_PREFIX: &str = "LUN_";
+/// Hex characters of the public key hash after the prefix
+pub const ADDRESS_BODY_LEN: usize = 16;
+/// Hex characters of checksum after the body
+pub const ADDRESS_CHECKSUM_LEN: usize = 4;
+
 impl SM2 {
     pub fn new() -> Self {
         SM2
     }
     pub fn generate_keypair(&self) -> (String, String) {
-        // 64 hex chars private, 130 hex chars public (04 + 128)
+        // 64 hex chars private, 66 hex chars public (04 + 64)
         let mut priv_bytes = [0u8; 32];
         rand::thread_rng().fill_bytes(&mut priv_bytes);
         let private_key = hex::encode(priv_bytes);
-        let public_key = format!("04{:064x}{:064x}", priv_bytes[0], priv_bytes[1]); // dummy
+        let public_key = self.derive_public_key(&private_key);
         (private_key, public_key)
     }
+    /// `LUN_`, the first 16 hex characters of the public key hash, then their checksum
     pub fn public_key_to_address(&self, public_key: &str) -> String {
         let mut hasher = Sha256::new();
         hasher.update(public_key.as_bytes());
         let hash = hasher.finalize();
-        format!("LUN_{}", &hex::encode(&hash)[..16])
+        let body = &hex::encode(hash)[..ADDRESS_BODY_LEN];
+        format!("{}{}{}", ADDRESS_PREFIX, body, Self::address_checksum(body))
+    }
+
+    /// The last 4 hex characters of SHA256 over the lowercase address body
+    pub fn address_checksum(body: &str) -> String {
+        let digest = hex::encode(Sha256::digest(body.to_ascii_lowercase().as_bytes()));
+        digest[digest.len() - ADDRESS_CHECKSUM_LEN..].to_string()
+    }
+    /// The signing key for `private_key_hex`, seeded with the SHA256 of the key text so any string is a key
+    fn key_pair(private_key_hex: &str) -> Ed25519KeyPair {
+        let seed = Sha256::digest(private_key_hex.as_bytes());
+        Ed25519KeyPair::from_seed_unchecked(&seed).expect("a 32-byte seed is always a valid key")
     }
     pub fn derive_public_key(&self, private_key_hex: &str) -> String {
-        // Dummy: just hash the private key
-        let mut hasher = Sha256::new();
-        hasher.update(private_key_hex.as_bytes());
-        let hash = hasher.finalize();
-        format!("04{}{}", hex::encode(&hash)[..32].to_string(), hex::encode(&hash)[32..].to_string())
+        format!("04{}", hex::encode(Self::key_pair(private_key_hex).public_key()))
     }
     pub fn sign(&self, data: &str, private_key_hex: &str) -> String {
-        // Dummy: hash(data + priv)
-        let mut hasher = Sha256::new();
-        hasher.update(data.as_bytes());
-        hasher.update(private_key_hex.as_bytes());
-        let hash = hasher.finalize();
-        format!("{:0>128}", hex::encode(hash))
+        hex::encode(Self::key_pair(private_key_hex).sign(data.as_bytes()))
     }
-    pub fn verify(&self, data: &str, signature: &str, _public_key_hex: &str) -> bool {
-        // Dummy: always true if signature is 128 chars
-        signature.len() == 128
+    /// Whether `signature` was made over `data` by the key behind `public_key_hex`
+    pub fn verify(&self, data: &str, signature: &str, public_key_hex: &str) -> bool {
+        let key = public_key_hex.strip_prefix("04").and_then(|key| hex::decode(key).ok());
+        let (Some(key), Ok(signature)) = (key, hex::decode(signature)) else {
+            return false;
+        };
+        UnparsedPublicKey::new(&ED25519, key).verify(data.as_bytes(), &signature).is_ok()
     }
 }
@@ -1,33 +1,595 @@
+use std::fmt;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::Duration;
+use bip39::Mnemonic;
+use rand::RngCore;
+use ring::hmac;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use serde_json::{json, Value as JsonValue};
+use zeroize::Zeroizing;
+use crate::core::crypto::Crypto;
+use crate::core::sm2::{ADDRESS_BODY_LEN, ADDRESS_CHECKSUM_LEN, ADDRESS_PREFIX, SM2};
+#[cfg(not(target_arch = "wasm32"))]
+use crate::core::blockchain::{self, BlockchainError, BlockchainManager};
+#[cfg(not(target_arch = "wasm32"))]
+use crate::core::wallet_manager::{Transaction, TransactionStatus, WalletBalance, WalletManager};
+#[cfg(not(target_arch = "wasm32"))]
+use crate::transactions::transactions::TransactionManager;
+#[cfg(not(target_arch = "wasm32"))]
+use crate::storage::database::WalletDatabase;
+#[cfg(not(target_arch = "wasm32"))]
+use crate::utils::uri::PaymentRequest;
+use crate::storage::encryption::EncryptionManager;
+use crate::transactions::signing;
+use crate::utils::clock::{self, Clock, SystemClock};
+
+/// Word counts `generate_mnemonic` accepts, with the bytes of entropy each encodes
+const MNEMONIC_SIZES: [(usize, usize); 5] = [(12, 16), (15, 20), (18, 24), (21, 28), (24, 32)];
+
+/// The `version` `LunaWallet::to_json` writes; documents without one are version 0
+pub const WALLET_FORMAT_VERSION: u32 = 1;
+
+/// Document fields the wallet tables keep in columns of their own
+const STORED_COLUMNS: [&str; 6] = ["address", "label", "public_key", "encrypted_private_key", "balance", "created"];
+
+/// The `version` `LunaWallet::export_keystore` writes
+pub const KEYSTORE_VERSION: u32 = 1;
+
+/// Prefixed to signed messages so a message signature can never pass for a transaction signature
+const MESSAGE_PREFIX: &str = "Luna Signed Message:\n";
+
 #[cfg(test)]
 mod tests {
     use super::*;
     include!("wallet_tests.rs");
 }
 
+/// Why a wallet operation failed
+#[derive(Debug, Clone, PartialEq)]
+pub enum WalletError {
+    /// The password does not decrypt the private key
+    WrongPassword,
+    /// The private key is needed; call `unlock` first
+    Locked,
+    /// `encrypted_private_key` does not hold an encrypted key
+    CorruptKey,
+    /// A seed phrase that is empty, the wrong length or fails its checksum
+    InvalidMnemonic(String),
+    /// A keystore document that is malformed, from a newer version, or whose key is not its address's
+    InvalidKeystore(String),
+    /// The wallet only tracks an address; its private key lives elsewhere
+    WatchOnly,
+    /// The wallet could not be written back to its database
+    Storage(String),
+}
+
+impl fmt::Display for WalletError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WalletError::WrongPassword => write!(f, "Wrong password"),
+            WalletError::Locked => write!(f, "Wallet is locked"),
+            WalletError::CorruptKey => write!(f, "Encrypted private key is unreadable"),
+            WalletError::InvalidMnemonic(reason) => write!(f, "Invalid seed phrase: {}", reason),
+            WalletError::InvalidKeystore(reason) => write!(f, "Invalid keystore: {}", reason),
+            WalletError::WatchOnly => write!(f, "Wallet is watch-only and cannot sign"),
+            WalletError::Storage(reason) => write!(f, "Could not save the wallet: {}", reason),
+        }
+    }
+}
+
+impl std::error::Error for WalletError {}
+
+/// Whether a wallet holds its private key
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WalletKind {
+    #[default]
+    Full,
+    /// Tracks the balance of an address whose key is kept on another device
+    WatchOnly,
+}
+
+impl WalletKind {
+    pub fn is_full(&self) -> bool {
+        *self == WalletKind::Full
+    }
+}
+
+/// Why `LunaWallet::validate_address` refused an address
+#[derive(Debug, Clone, PartialEq)]
+pub enum AddressError {
+    /// Does not start with `LUN_`
+    MissingPrefix,
+    /// The length of the whole address
+    WrongLength(usize),
+    /// A character after the prefix that is not hex
+    InvalidCharacter(char),
+    /// The checksum does not match the body, so the address was mistyped or cut
+    ChecksumMismatch,
+    /// A legacy address without a checksum while legacy addresses are turned off
+    MissingChecksum,
+}
+
+impl fmt::Display for AddressError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AddressError::MissingPrefix => write!(f, "Address must start with {}", ADDRESS_PREFIX),
+            AddressError::WrongLength(len) => {
+                write!(f, "Address must be {} characters long, not {}", ADDRESS_PREFIX.len() + ADDRESS_BODY_LEN + ADDRESS_CHECKSUM_LEN, len)
+            }
+            AddressError::InvalidCharacter(c) => write!(f, "Address contains {:?}, which is not a hex digit", c),
+            AddressError::ChecksumMismatch => write!(f, "Address checksum does not match; check it was copied in full"),
+            AddressError::MissingChecksum => write!(f, "Address has no checksum and legacy addresses are turned off"),
+        }
+    }
+}
+
+impl std::error::Error for AddressError {}
+
+/// Why `LunaWallet::send` did not send
+#[derive(Debug, Clone, PartialEq)]
+pub enum SendError {
+    InvalidAddress(AddressError),
+    /// `available_balance` is less than the amount plus the fee
+    InsufficientFunds { available: f64, required: f64 },
+    /// The wallet is locked or its key is unreadable
+    Wallet(WalletError),
+    /// The signed transaction failed validation before broadcast
+    Rejected(String),
+    /// The endpoint could not be reached or refused the transaction
+    Broadcast(String),
+}
+
+impl fmt::Display for SendError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SendError::InvalidAddress(e) => write!(f, "Invalid address: {}", e),
+            SendError::InsufficientFunds { available, required } => write!(f, "Insufficient funds: {} available, {} needed with the fee", available, required),
+            SendError::Wallet(e) => write!(f, "{}", e),
+            SendError::Rejected(reason) => write!(f, "Invalid transaction: {}", reason),
+            SendError::Broadcast(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for SendError {}
+
+/// The serialized form is the wallet document `to_json` writes and both wallet tables store; the
+/// renames pin its keys. Unlock state never leaves memory.
+#[derive(Serialize, Deserialize)]
 pub struct LunaWallet {
+    #[serde(rename = "version", default)]
+    pub version: u32,
+    #[serde(rename = "address")]
     pub address: String,
+    #[serde(rename = "public_key", default)]
     pub public_key: String,
+    #[serde(rename = "encrypted_private_key", default, with = "utf8_bytes")]
     pub encrypted_private_key: Vec<u8>,
+    #[serde(rename = "label", default)]
     pub label: String,
-    pub is_locked: bool,
+    #[serde(rename = "balance", default)]
     pub balance: f64,
+    /// The balance less pending spends; documents without one load it as `balance`
+    #[serde(rename = "available_balance", default)]
     pub available_balance: f64,
+    /// Unix seconds; older documents store them as a float
+    #[serde(rename = "created", default, deserialize_with = "clock::unix_seconds")]
     pub created: u64,
+    /// Written only for watch-only wallets, so full wallet documents are unchanged
+    #[serde(rename = "kind", default, skip_serializing_if = "WalletKind::is_full")]
+    pub kind: WalletKind,
+    /// Whatever the application keeps alongside the wallet; an empty object by default
+    #[serde(rename = "metadata", default = "empty_object", deserialize_with = "object_or_empty")]
+    metadata: JsonValue,
+    /// Set while unlocked. Behind a mutex so signing through `&self` can relock a wallet past its
+    /// `unlock_for` deadline.
+    #[serde(skip)]
+    unlocked: Mutex<Option<UnlockedKey>>,
 }
 
+/// The decrypted private key, zeroed when dropped
+struct UnlockedKey {
+    private_key: Zeroizing<String>,
+    /// When `unlock_for` relocks the wallet, on the `clock::monotonic` timeline
+    until: Option<Duration>,
+}
+
+impl UnlockedKey {
+    fn is_expired(&self) -> bool {
+        self.until.is_some_and(|until| clock::monotonic() >= until)
+    }
+}
+
+fn empty_object() -> JsonValue {
+    json!({})
+}
+
+fn object_or_empty<'de, D: Deserializer<'de>>(deserializer: D) -> Result<JsonValue, D::Error> {
+    Ok(Option::<JsonValue>::deserialize(deserializer)?.filter(|m| !m.is_null()).unwrap_or_else(empty_object))
+}
+
+/// The encrypted key is an ASCII token, stored as a string rather than a byte array
+mod utf8_bytes {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(bytes: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&String::from_utf8_lossy(bytes))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+        Ok(String::deserialize(deserializer)?.into_bytes())
+    }
+}
+
+
+/// A message signature from `LunaWallet::sign_message`, checked with `verify_message`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SignedMessage {
+    pub signature: String,
+    pub public_key: String,
+    /// Seconds since the epoch when the message was signed; covered by the signature
+    pub timestamp: u64,
+}
 
 impl LunaWallet {
     pub fn new(address: String, public_key: String, encrypted_private_key: Vec<u8>, label: String, created: u64) -> Self {
         LunaWallet {
+            version: WALLET_FORMAT_VERSION,
             address,
             public_key,
             encrypted_private_key,
             label,
-            is_locked: true,
             balance: 0.0,
             available_balance: 0.0,
             created,
+            kind: WalletKind::Full,
+            metadata: json!({}),
+            unlocked: Mutex::new(None),
+        }
+    }
+
+    /// A wallet for `address` without its private key: balances sync, but `unlock` and signing
+    /// fail with `WalletError::WatchOnly`
+    pub fn watch_only(address: &str, label: &str) -> Result<Self, AddressError> {
+        Self::validate_address(address)?;
+        let mut wallet = LunaWallet::new(address.to_string(), String::new(), Vec::new(), label.to_string(), SystemClock.now());
+        wallet.kind = WalletKind::WatchOnly;
+        Ok(wallet)
+    }
+
+    pub fn is_watch_only(&self) -> bool {
+        self.kind == WalletKind::WatchOnly
+    }
+
+    pub fn set_label(&mut self, label: &str) {
+        self.label = label.to_string();
+    }
+
+    pub fn get_metadata(&self) -> &JsonValue {
+        &self.metadata
+    }
+
+    pub fn set_metadata(&mut self, metadata: JsonValue) {
+        self.metadata = metadata;
+    }
+
+    /// Check `addr` is `LUN_` followed by a hex body and its checksum, as `SM2::public_key_to_address` makes them.
+    /// Addresses from before checksums, without one, pass; `validate_address_with` can refuse them.
+    pub fn validate_address(addr: &str) -> Result<(), AddressError> {
+        Self::validate_address_with(addr, true)
+    }
+
+    /// `validate_address`, accepting checksum-less addresses only if `allow_legacy`
+    pub fn validate_address_with(addr: &str, allow_legacy: bool) -> Result<(), AddressError> {
+        let rest = addr.strip_prefix(ADDRESS_PREFIX).ok_or(AddressError::MissingPrefix)?;
+        if let Some(c) = rest.chars().find(|c| !c.is_ascii_hexdigit()) {
+            return Err(AddressError::InvalidCharacter(c));
+        }
+        match rest.len() {
+            len if len == ADDRESS_BODY_LEN + ADDRESS_CHECKSUM_LEN => {
+                let (body, checksum) = rest.split_at(ADDRESS_BODY_LEN);
+                if checksum.eq_ignore_ascii_case(&SM2::address_checksum(body)) { Ok(()) } else { Err(AddressError::ChecksumMismatch) }
+            }
+            ADDRESS_BODY_LEN if allow_legacy => Ok(()),
+            ADDRESS_BODY_LEN => Err(AddressError::MissingChecksum),
+            _ => Err(AddressError::WrongLength(addr.len())),
+        }
+    }
+
+    /// A new keypair and `LUN_` address, with the private key encrypted under `password`
+    pub fn create(label: &str, password: &str) -> Self {
+        Self::from_private_key(&Crypto::new().generate_private_key(), label, password)
+    }
+
+    /// A fresh English seed phrase of 12, 15, 18, 21 or 24 words
+    pub fn generate_mnemonic(word_count: usize) -> Result<String, WalletError> {
+        let (_, entropy_len) = MNEMONIC_SIZES
+            .iter()
+            .find(|(words, _)| *words == word_count)
+            .ok_or_else(|| WalletError::InvalidMnemonic(format!("word count must be 12, 15, 18, 21 or 24, not {}", word_count)))?;
+        let mut entropy = Zeroizing::new([0u8; 32]);
+        rand::thread_rng().fill_bytes(&mut entropy[..*entropy_len]);
+        let mnemonic = Mnemonic::from_entropy(&entropy[..*entropy_len]).map_err(|e| WalletError::InvalidMnemonic(e.to_string()))?;
+        Ok(mnemonic.to_string())
+    }
+
+    /// The wallet at `index` under a seed phrase; the same phrase and index always give the same address.
+    /// The private key is encrypted under `password`.
+    pub fn from_mnemonic(phrase: &str, index: u32, label: &str, password: &str) -> Result<Self, WalletError> {
+        let normalized = phrase.split_whitespace().collect::<Vec<_>>().join(" ").to_lowercase();
+        if normalized.is_empty() {
+            return Err(WalletError::InvalidMnemonic("the phrase is empty".to_string()));
+        }
+        let mnemonic = Mnemonic::parse(&normalized).map_err(|e| WalletError::InvalidMnemonic(e.to_string()))?;
+        // BIP39 stretches the phrase with PBKDF2; each index is an HMAC over that seed
+        let seed = Zeroizing::new(mnemonic.to_seed(""));
+        let key = hmac::Key::new(hmac::HMAC_SHA256, seed.as_ref());
+        let tag = hmac::sign(&key, format!("lunalib/wallet/{}", index).as_bytes());
+        let private_key = Zeroizing::new(hex::encode(tag.as_ref()));
+        Ok(Self::from_private_key(&private_key, label, password))
+    }
+
+    /// The wallet holding `private_key`, encrypted under `password`
+    fn from_private_key(private_key: &str, label: &str, password: &str) -> Self {
+        // The public key is derived rather than taken from generate_keypair so signatures verify against it
+        let crypto = Crypto::new();
+        let public_key = crypto.derive_public_key(private_key);
+        let address = crypto.derive_address(&public_key);
+        let encrypted = EncryptionManager::new().encrypt_data(private_key, password);
+        let created = SystemClock.now();
+        LunaWallet::new(address, public_key, encrypted.into_bytes(), label.to_string(), created)
+    }
+
+    /// A wallet document from `to_json`, locked. Missing fields take their defaults, so documents
+    /// from older versions load; documents from newer versions are refused.
+    pub fn from_json(document: &JsonValue) -> Result<Self, String> {
+        let mut wallet = LunaWallet::deserialize(document).map_err(|e| format!("Not a wallet: {}", e))?;
+        if wallet.address.is_empty() {
+            return Err("Wallet has no address".to_string());
+        }
+        if wallet.version > WALLET_FORMAT_VERSION {
+            return Err(format!("Wallet format {} is newer than the supported {}", wallet.version, WALLET_FORMAT_VERSION));
+        }
+        wallet.version = WALLET_FORMAT_VERSION;
+        if document.get("available_balance").is_none() {
+            wallet.available_balance = wallet.balance;
+        }
+        Ok(wallet)
+    }
+
+    /// The wallet document, with the private key still encrypted
+    pub fn to_json(&self) -> JsonValue {
+        serde_json::to_value(self).expect("a wallet always serializes")
+    }
+
+    /// The document fields a wallet table keeps in its `metadata` column: all but `STORED_COLUMNS`
+    pub(crate) fn stored_metadata(&self) -> JsonValue {
+        let mut document = self.to_json();
+        if let Some(fields) = document.as_object_mut() {
+            fields.retain(|key, _| !STORED_COLUMNS.contains(&key.as_str()));
+        }
+        document
+    }
+
+    /// The wallet in a table row: its `STORED_COLUMNS` keyed as in `to_json`, and its `metadata`
+    /// column. A column without a `version` predates this layout and holds only the application metadata.
+    pub(crate) fn from_stored(columns: JsonValue, stored: JsonValue) -> Result<Self, String> {
+        let mut document = if stored.get("version").is_some() { stored } else { json!({"metadata": stored}) };
+        if let (Some(fields), JsonValue::Object(columns)) = (document.as_object_mut(), columns) {
+            fields.extend(columns);
         }
+        Self::from_json(&document)
     }
-    // TODO: Implement create, unlock, export, import, info, balance, sign, verify, etc.
+
+    /// A portable keystore document with the private key in an `EncryptionManager` envelope under
+    /// `password`. The wallet must be unlocked.
+    pub fn export_keystore(&self, password: &str) -> Result<JsonValue, WalletError> {
+        let mut key = self.with_private_key(|private_key| json!({"private_key": private_key}))?;
+        let crypto = EncryptionManager::new().encrypt_wallet(&mut key, password);
+        Ok(json!({
+            "version": KEYSTORE_VERSION,
+            "address": self.address,
+            "label": self.label,
+            "created": self.created,
+            "crypto": crypto,
+        }))
+    }
+
+    /// The wallet in a keystore from `export_keystore`, locked, with its key re-encrypted under `password`.
+    /// The key must derive the keystore's address.
+    pub fn import_keystore(json: &JsonValue, password: &str) -> Result<LunaWallet, WalletError> {
+        let invalid = |reason: &str| WalletError::InvalidKeystore(reason.to_string());
+        let version = json.get("version").and_then(JsonValue::as_u64).ok_or_else(|| invalid("no version"))?;
+        if version > u64::from(KEYSTORE_VERSION) {
+            return Err(WalletError::InvalidKeystore(format!("version {} is newer than the supported {}", version, KEYSTORE_VERSION)));
+        }
+        let address = json.get("address").and_then(JsonValue::as_str).filter(|a| !a.is_empty()).ok_or_else(|| invalid("no address"))?;
+        let crypto = json.get("crypto").filter(|c| c.get("encrypted_data").is_some_and(JsonValue::is_string)).ok_or_else(|| invalid("no crypto envelope"))?;
+        let manager = EncryptionManager::new();
+        if !manager.verify_password(crypto, password) {
+            return Err(WalletError::WrongPassword);
+        }
+        let mut key = manager.decrypt_wallet(crypto, password).ok_or(WalletError::CorruptKey)?;
+        let private_key = match key.get_mut("private_key").map(JsonValue::take) {
+            Some(JsonValue::String(private_key)) => Zeroizing::new(private_key),
+            _ => return Err(invalid("no private key")),
+        };
+        let label = json.get("label").and_then(JsonValue::as_str).unwrap_or_default();
+        let mut wallet = Self::from_private_key(&private_key, label, password);
+        if Self::validate_address(address).is_err() || !derives_address(&wallet.public_key, address) {
+            return Err(WalletError::InvalidKeystore(format!("the key belongs to {}, not {}", wallet.address, address)));
+        }
+        wallet.address = address.to_string();
+        if let Some(created) = json.get("created").and_then(JsonValue::as_f64) {
+            wallet.created = created.max(0.0) as u64;
+        }
+        Ok(wallet)
+    }
+
+    /// Decrypt the private key with `password` and keep it in memory until `lock`
+    pub fn unlock(&mut self, password: &str) -> Result<(), WalletError> {
+        let private_key = self.decrypt_private_key(password)?;
+        *self.unlocked.get_mut().unwrap() = Some(UnlockedKey { private_key, until: None });
+        Ok(())
+    }
+
+    /// Unlock for `duration` only; signing after that relocks the wallet and wipes the key.
+    /// The deadline is on a monotonic clock, so changing the system time does not extend it.
+    pub fn unlock_for(&mut self, password: &str, duration: Duration) -> Result<(), WalletError> {
+        let private_key = self.decrypt_private_key(password)?;
+        let until = clock::monotonic().checked_add(duration);
+        *self.unlocked.get_mut().unwrap() = Some(UnlockedKey { private_key, until });
+        Ok(())
+    }
+
+    /// Whether the private key is available: unlocked, and not past an `unlock_for` deadline
+    pub fn is_unlocked(&self) -> bool {
+        self.unlocked.lock().unwrap().as_ref().is_some_and(|key| !key.is_expired())
+    }
+
+    /// Whether signing would fail with `WalletError::Locked`
+    pub fn is_locked(&self) -> bool {
+        !self.is_unlocked()
+    }
+
+    /// Run `f` on the private key, relocking first if the `unlock_for` deadline has passed
+    fn with_private_key<T>(&self, f: impl FnOnce(&str) -> T) -> Result<T, WalletError> {
+        if self.is_watch_only() {
+            return Err(WalletError::WatchOnly);
+        }
+        let mut unlocked = self.unlocked.lock().unwrap();
+        if unlocked.as_ref().is_some_and(UnlockedKey::is_expired) {
+            *unlocked = None;
+        }
+        unlocked.as_ref().map(|key| f(&key.private_key)).ok_or(WalletError::Locked)
+    }
+
+    /// Re-encrypt the private key under `new`. If `old` is wrong the wallet is left as it was;
+    /// `WalletDb::change_password` also writes the new key to the database.
+    pub fn change_password(&mut self, old: &str, new: &str) -> Result<(), WalletError> {
+        let private_key = self.decrypt_private_key(old)?;
+        self.encrypted_private_key = EncryptionManager::new().encrypt_data(&private_key, new).into_bytes();
+        Ok(())
+    }
+
+    fn decrypt_private_key(&self, password: &str) -> Result<Zeroizing<String>, WalletError> {
+        if self.is_watch_only() {
+            return Err(WalletError::WatchOnly);
+        }
+        let encrypted = std::str::from_utf8(&self.encrypted_private_key).map_err(|_| WalletError::CorruptKey)?;
+        if encrypted.is_empty() {
+            return Err(WalletError::CorruptKey);
+        }
+        EncryptionManager::new().decrypt_data(encrypted, password).map(Zeroizing::new).ok_or(WalletError::WrongPassword)
+    }
+
+    /// Forget the decrypted private key
+    pub fn lock(&mut self) {
+        *self.unlocked.get_mut().unwrap() = None;
+    }
+
+    /// Sign `data` with the private key; the wallet must be unlocked
+    pub fn sign(&self, data: &str) -> Result<String, WalletError> {
+        self.with_private_key(|private_key| Crypto::new().sign_data(data, private_key))
+    }
+
+    /// Sign the canonical hash of `tx` and fill its `signature`, `public_key` and `hash`;
+    /// the wallet must be unlocked
+    pub fn sign_transaction(&self, tx: &mut HashMap<String, JsonValue>) -> Result<(), WalletError> {
+        self.with_private_key(|private_key| signing::sign_transaction(tx, private_key))
+    }
+
+    /// Sign `msg` to prove this wallet controls its address; the wallet must be unlocked
+    pub fn sign_message(&self, msg: &str) -> Result<SignedMessage, WalletError> {
+        let timestamp = SystemClock.now();
+        let signature = self.sign(&message_payload(msg, timestamp))?;
+        Ok(SignedMessage { signature, public_key: self.public_key.clone(), timestamp })
+    }
+
+    /// One page of this wallet's confirmed and pending transactions, newest first and then by hash.
+    /// A transaction that is both pending and confirmed is listed once, as confirmed.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn get_history(&self, db: &WalletDatabase, limit: usize, offset: usize) -> Vec<Transaction> {
+        let pending = db.get_pending_transactions(&self.address).into_iter().map(|tx| Transaction::from_record(&tx, &self.address, TransactionStatus::Pending));
+        let confirmed = db.get_confirmed_transactions(&self.address).into_iter().map(|tx| Transaction::from_record(&tx, &self.address, TransactionStatus::Confirmed));
+        let by_hash: HashMap<String, Transaction> = pending.chain(confirmed).map(|tx| (tx.hash.clone(), tx)).collect();
+        let mut history: Vec<Transaction> = by_hash.into_values().collect();
+        history.sort_by(|a, b| b.timestamp.cmp(&a.timestamp).then_with(|| a.hash.cmp(&b.hash)));
+        history.into_iter().skip(offset).take(limit).collect()
+    }
+
+    /// Pay `amount` to `to`: check the funds, sign, validate, broadcast, then record the transfer
+    /// as pending in `tx_mgr`'s database if it has one. Returns the transaction hash.
+    /// `available_balance` is left as it is; `refresh_balance` takes the spend off once the
+    /// endpoint's mempool lists it.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub async fn send(&self, to: &str, amount: f64, memo: &str, blockchain: &BlockchainManager, tx_mgr: &TransactionManager) -> Result<String, SendError> {
+        let mut tx = tx_mgr.create_transaction(&self.address, to, amount, memo, "transfer").map_err(SendError::InvalidAddress)?;
+        let required = amount + tx["fee"].as_f64().unwrap_or(0.0);
+        if self.available_balance < required {
+            return Err(SendError::InsufficientFunds { available: self.available_balance, required });
+        }
+        tx.insert("nonce".to_string(), JsonValue::from(rand::random::<u64>()));
+        self.sign_transaction(&mut tx).map_err(SendError::Wallet)?;
+        let (valid, reason) = tx_mgr.security.validate_transaction(&tx);
+        if !valid {
+            return Err(SendError::Rejected(reason));
+        }
+        if !BlockchainManager::validate_transaction_before_broadcast(&blockchain::Transaction::from_json(&tx)) {
+            return Err(SendError::Rejected("failed the pre-broadcast checks".to_string()));
+        }
+        let hash = blockchain.submit_transaction_async(&tx).await.map_err(|e| SendError::Broadcast(e.to_string()))?;
+        if let Some(db) = tx_mgr.database() {
+            db.save_pending_transaction(&json!(tx), &self.address);
+        }
+        Ok(hash)
+    }
+
+    /// Scan the chain for this wallet's transactions and set `balance` and `available_balance` by the
+    /// rules `WalletManager` syncs with. Spends waiting in the endpoint's mempool come off
+    /// `available_balance`; an endpoint without a mempool listing has none.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub async fn refresh_balance(&mut self, blockchain: &BlockchainManager) -> Result<WalletBalance, String> {
+        let mut scanned = blockchain.scan_transactions_for_addresses(std::slice::from_ref(&self.address), 0).await.map_err(|e| e.to_string())?;
+        let confirmed = scanned.remove(&self.address).unwrap_or_default();
+        let pending: Vec<Transaction> = match blockchain.get_mempool_for_address(&self.address).await {
+            Ok(waiting) => waiting.iter().filter_map(|tx| Transaction::from_json(&json!(tx)).ok()).collect(),
+            Err(BlockchainError::NotFound) => Vec::new(),
+            Err(e) => return Err(e.to_string()),
+        };
+        let balance = WalletManager::calculate_balance_from_transactions(&self.address, &confirmed, &pending).map_err(|e| e.to_string())?;
+        self.balance = balance.confirmed_balance.to_f64_lossy();
+        self.available_balance = balance.available_balance.to_f64_lossy();
+        Ok(balance)
+    }
+
+    /// A `luna:` URI asking to be paid to this wallet, for a QR code; see `parse_payment_request`
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn payment_request(&self, amount: Option<f64>, memo: Option<&str>) -> String {
+        let mut request = PaymentRequest::new(&self.address);
+        request.amount = amount;
+        request.memo = memo.map(str::to_string);
+        request.to_uri()
+    }
+    // TODO: Implement info, balance, verify, etc.
+}
+
+fn message_payload(msg: &str, timestamp: u64) -> String {
+    format!("{}{}\n{}", MESSAGE_PREFIX, timestamp, msg)
+}
+
+/// Whether `sig` signs `msg` with a public key that hashes to `address`
+pub fn verify_message(address: &str, msg: &str, sig: &SignedMessage) -> bool {
+    if LunaWallet::validate_address(address).is_err() {
+        return false;
+    }
+    derives_address(&sig.public_key, address) && Crypto::new().verify_signature(&message_payload(msg, sig.timestamp), &sig.signature, &sig.public_key)
+}
+
+/// Whether `public_key` hashes to `address`; a legacy address is the derived one without its checksum
+pub(crate) fn derives_address(public_key: &str, address: &str) -> bool {
+    let derived = SM2::new().public_key_to_address(public_key);
+    derived.get(..address.len()).is_some_and(|d| d.eq_ignore_ascii_case(address))
 }
@@ -1,13 +1,61 @@
 
-use rusqlite::{Connection, params};
-use serde_json::json;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+use rusqlite::{Connection, Row, params};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value as JsonValue};
+use sha2::{Digest, Sha256};
+use crate::core::wallet::{LunaWallet, WalletError, WalletKind};
+use crate::storage::encryption::EncryptionManager;
+
+/// `format` of a file written by `export_encrypted_backup`
+const BACKUP_FORMAT: &str = "luna-wallet-backup";
+const BACKUP_VERSION: u64 = 1;
 
 pub struct WalletDb {
     pub db_path: String,
     pub conn: Connection,
 }
 
-#[derive(Debug, Clone, PartialEq)]
+/// Why `export_encrypted_backup` or `restore_encrypted_backup` failed
+#[derive(Debug)]
+pub enum BackupError {
+    Io(io::Error),
+    /// The file is not a wallet backup or has been damaged
+    Corrupt(String),
+    /// The password does not decrypt the backup
+    WrongPassword,
+    Storage(rusqlite::Error),
+}
+
+impl fmt::Display for BackupError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BackupError::Io(e) => write!(f, "Backup file error: {}", e),
+            BackupError::Corrupt(reason) => write!(f, "Backup is corrupted: {}", reason),
+            BackupError::WrongPassword => write!(f, "Wrong password"),
+            BackupError::Storage(e) => write!(f, "Storage error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for BackupError {}
+
+impl From<io::Error> for BackupError {
+    fn from(e: io::Error) -> Self {
+        BackupError::Io(e)
+    }
+}
+
+impl From<rusqlite::Error> for BackupError {
+    fn from(e: rusqlite::Error) -> Self {
+        BackupError::Storage(e)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Wallet {
     pub address: String,
     pub label: String,
@@ -17,6 +65,87 @@ pub struct Wallet {
     pub created: i64,
     pub is_locked: bool,
     pub available_balance: f64,
+    /// Kept with the wallet as `LunaWallet::get_metadata` returns it
+    pub metadata: JsonValue,
+    #[serde(default)]
+    pub kind: WalletKind,
+}
+
+impl From<&LunaWallet> for Wallet {
+    fn from(wallet: &LunaWallet) -> Self {
+        Wallet {
+            address: wallet.address.clone(),
+            label: wallet.label.clone(),
+            public_key: wallet.public_key.clone(),
+            encrypted_private_key: String::from_utf8_lossy(&wallet.encrypted_private_key).into_owned(),
+            balance: wallet.balance,
+            created: wallet.created as i64,
+            is_locked: wallet.is_locked(),
+            available_balance: wallet.available_balance,
+            metadata: wallet.get_metadata().clone(),
+            kind: wallet.kind,
+        }
+    }
+}
+
+impl From<Wallet> for LunaWallet {
+    /// The restored wallet starts locked, whatever the row says
+    fn from(row: Wallet) -> Self {
+        let mut wallet = LunaWallet::new(row.address, row.public_key, row.encrypted_private_key.into_bytes(), row.label, row.created.max(0) as u64);
+        wallet.balance = row.balance;
+        wallet.available_balance = row.available_balance;
+        wallet.set_metadata(row.metadata);
+        wallet.kind = row.kind;
+        wallet
+    }
+}
+
+/// Write `wallet` with `verb`, `REPLACE` or `INSERT OR IGNORE`; the number of rows written. The
+/// metadata column holds `LunaWallet::stored_metadata`.
+fn write_wallet(conn: &Connection, wallet: &Wallet, verb: &str) -> rusqlite::Result<usize> {
+    let meta = LunaWallet::from(wallet.clone()).stored_metadata();
+    conn.execute(
+        &format!("{} INTO wallets (address, label, public_key, encrypted_private_key, balance, created, metadata) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)", verb),
+        params![
+            wallet.address,
+            wallet.label,
+            wallet.public_key,
+            wallet.encrypted_private_key,
+            wallet.balance,
+            wallet.created,
+            meta.to_string()
+        ]
+    )
+}
+
+/// The row read back through `LunaWallet::from_stored`; `None` if it holds no wallet
+fn wallet_from_row(row: &Row) -> Option<Wallet> {
+    let meta_str: String = row.get(6).unwrap_or_default();
+    let meta: JsonValue = serde_json::from_str(&meta_str).unwrap_or_else(|_| json!({}));
+    let columns = json!({
+        "address": row.get::<_, String>(0).unwrap_or_default(),
+        "label": row.get::<_, String>(1).unwrap_or_default(),
+        "public_key": row.get::<_, String>(2).unwrap_or_default(),
+        "encrypted_private_key": row.get::<_, String>(3).unwrap_or_default(),
+        "balance": row.get::<_, f64>(4).unwrap_or(0.0),
+        "created": row.get::<_, i64>(5).unwrap_or(0).max(0),
+    });
+    LunaWallet::from_stored(columns, legacy_metadata(meta)).ok().map(|wallet| Wallet::from(&wallet))
+}
+
+/// Rows written before the metadata column held `stored_metadata` keep `available_balance`, and
+/// later the application metadata under `custom` and the wallet's `kind`
+fn legacy_metadata(meta: JsonValue) -> JsonValue {
+    if meta.get("version").is_some() {
+        return meta;
+    }
+    let mut stored = json!({"version": 0, "metadata": meta.get("custom").cloned().unwrap_or_else(|| json!({}))});
+    for key in ["available_balance", "kind"] {
+        if let Some(value) = meta.get(key) {
+            stored[key] = value.clone();
+        }
+    }
+    stored
 }
 
 impl WalletDb {
@@ -38,62 +167,83 @@ impl WalletDb {
     }
 
     pub fn save_wallet(&self, wallet: &Wallet) -> bool {
-        let meta = json!({
-            "is_locked": wallet.is_locked,
-            "available_balance": wallet.available_balance
-        });
-        self.conn.execute(
-            "REPLACE INTO wallets (address, label, public_key, encrypted_private_key, balance, created, metadata) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
-            params![
-                wallet.address,
-                wallet.label,
-                wallet.public_key,
-                wallet.encrypted_private_key,
-                wallet.balance,
-                wallet.created,
-                meta.to_string()
-            ]
-        ).is_ok()
+        write_wallet(&self.conn, wallet, "REPLACE").is_ok()
     }
 
     pub fn load_wallet(&self, address: &str) -> Option<Wallet> {
         let mut stmt = self.conn.prepare("SELECT address, label, public_key, encrypted_private_key, balance, created, metadata FROM wallets WHERE address=?1").ok()?;
         let mut rows = stmt.query(params![address]).ok()?;
-        if let Some(row) = rows.next().ok()? {
-            let meta_str: String = row.get(6).unwrap_or_default();
-            let meta: serde_json::Value = serde_json::from_str(&meta_str).unwrap_or_default();
-            Some(Wallet {
-                address: row.get(0).unwrap_or_default(),
-                label: row.get(1).unwrap_or_default(),
-                public_key: row.get(2).unwrap_or_default(),
-                encrypted_private_key: row.get(3).unwrap_or_default(),
-                balance: row.get(4).unwrap_or(0.0),
-                created: row.get(5).unwrap_or(0),
-                is_locked: meta.get("is_locked").and_then(|v| v.as_bool()).unwrap_or(false),
-                available_balance: meta.get("available_balance").and_then(|v| v.as_f64()).unwrap_or(0.0),
-            })
-        } else {
-            None
-        }
+        rows.next().ok()?.and_then(wallet_from_row)
+    }
+
+    /// Rename the wallet at `address` without touching its other columns; false if there is no such wallet
+    pub fn update_label(&self, address: &str, label: &str) -> bool {
+        self.conn.execute("UPDATE wallets SET label=?1 WHERE address=?2", params![label, address]).map(|changed| changed > 0).unwrap_or(false)
+    }
+
+    /// Rotate `wallet`'s password and store its re-encrypted key. On a wrong `old` password or a failed
+    /// write, neither the wallet nor its row changes.
+    pub fn change_password(&self, wallet: &mut LunaWallet, old: &str, new: &str) -> Result<(), WalletError> {
+        let previous = wallet.encrypted_private_key.clone();
+        wallet.change_password(old, new)?;
+        let encrypted = String::from_utf8_lossy(&wallet.encrypted_private_key).into_owned();
+        let error = match self.conn.execute("UPDATE wallets SET encrypted_private_key=?1 WHERE address=?2", params![encrypted, wallet.address]) {
+            Ok(1) => return Ok(()),
+            Ok(_) => format!("no wallet {} in {}", wallet.address, self.db_path),
+            Err(e) => e.to_string(),
+        };
+        wallet.encrypted_private_key = previous;
+        Err(WalletError::Storage(error))
     }
 
     pub fn list_wallets(&self) -> Vec<Wallet> {
         let mut stmt = self.conn.prepare("SELECT address, label, public_key, encrypted_private_key, balance, created, metadata FROM wallets").unwrap();
-        let rows = stmt.query_map([], |row| {
-            let meta_str: String = row.get(6).unwrap_or_default();
-            let meta: serde_json::Value = serde_json::from_str(&meta_str).unwrap_or_default();
-            Ok(Wallet {
-                address: row.get(0).unwrap_or_default(),
-                label: row.get(1).unwrap_or_default(),
-                public_key: row.get(2).unwrap_or_default(),
-                encrypted_private_key: row.get(3).unwrap_or_default(),
-                balance: row.get(4).unwrap_or(0.0),
-                created: row.get(5).unwrap_or(0),
-                is_locked: meta.get("is_locked").and_then(|v| v.as_bool()).unwrap_or(false),
-                available_balance: meta.get("available_balance").and_then(|v| v.as_f64()).unwrap_or(0.0),
-            })
-        }).unwrap();
-        rows.filter_map(|r| r.ok()).collect()
+        let rows = stmt.query_map([], |row| Ok(wallet_from_row(row))).unwrap();
+        rows.filter_map(|r| r.ok().flatten()).collect()
+    }
+
+    /// Write every wallet to `path`, encrypted under `password`. The private keys inside stay
+    /// encrypted under their own passwords as well.
+    pub fn export_encrypted_backup(&self, path: &Path, password: &str) -> Result<(), BackupError> {
+        let wallets = json!(self.list_wallets()).to_string();
+        let data = EncryptionManager::new().encrypt_data(&wallets, password);
+        let backup = json!({
+            "format": BACKUP_FORMAT,
+            "version": BACKUP_VERSION,
+            "checksum": hex::encode(Sha256::digest(data.as_bytes())),
+            "data": data,
+        });
+        fs::write(path, backup.to_string())?;
+        Ok(())
+    }
+
+    /// Restore the wallets in a backup and return how many were written. With `merge`, wallets
+    /// already here are kept and only new addresses are added; otherwise the backup replaces
+    /// every wallet. Nothing changes unless the whole backup can be restored.
+    pub fn restore_encrypted_backup(&self, path: &Path, password: &str, merge: bool) -> Result<usize, BackupError> {
+        let backup: JsonValue = serde_json::from_slice(&fs::read(path)?).map_err(|e| BackupError::Corrupt(format!("not JSON: {}", e)))?;
+        if backup["format"] != BACKUP_FORMAT || backup["version"] != BACKUP_VERSION {
+            return Err(BackupError::Corrupt(format!("not a version {} {} file", BACKUP_VERSION, BACKUP_FORMAT)));
+        }
+        let data = backup["data"].as_str().ok_or_else(|| BackupError::Corrupt("no data".to_string()))?;
+        // The checksum separates a damaged file from a wrong password, which both fail decryption
+        if backup["checksum"] != hex::encode(Sha256::digest(data.as_bytes())) {
+            return Err(BackupError::Corrupt("checksum does not match".to_string()));
+        }
+        let wallets = EncryptionManager::new().decrypt_data(data, password).ok_or(BackupError::WrongPassword)?;
+        let wallets: Vec<Wallet> = serde_json::from_str(&wallets).map_err(|e| BackupError::Corrupt(format!("unreadable wallets: {}", e)))?;
+
+        let tx = self.conn.unchecked_transaction()?;
+        if !merge {
+            tx.execute("DELETE FROM wallets", [])?;
+        }
+        let verb = if merge { "INSERT OR IGNORE" } else { "REPLACE" };
+        let mut restored = 0;
+        for wallet in &wallets {
+            restored += write_wallet(&tx, wallet, verb)?;
+        }
+        tx.commit()?;
+        Ok(restored)
     }
 
     pub fn close(self) {
@@ -104,14 +254,14 @@ impl WalletDb {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::collections::HashMap;
     use std::fs;
 
-    fn temp_db() -> String {
-        use std::time::{SystemTime, UNIX_EPOCH};
-        let nanos = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_nanos();
-        let path = format!("test_wallets_{}.db", nanos);
-        let _ = fs::remove_file(&path);
-        path
+    /// A database in a fresh temporary directory, removed when the directory is dropped
+    fn temp_db() -> (tempfile::TempDir, WalletDb) {
+        let dir = tempfile::tempdir().unwrap();
+        let db = WalletDb::new(dir.path().join("wallets.db").to_str().unwrap());
+        (dir, db)
     }
 
     fn sample_wallet(addr: &str) -> Wallet {
@@ -124,12 +274,14 @@ mod tests {
             created: 123456,
             is_locked: true,
             available_balance: 41.0,
+            metadata: json!({"colour": "blue"}),
+            kind: WalletKind::Full,
         }
     }
 
     #[test]
     fn test_save_and_load_wallet() {
-        let db = WalletDb::new(&temp_db());
+        let (_dir, db) = temp_db();
         let w = sample_wallet("addr1");
         assert!(db.save_wallet(&w));
         let loaded = db.load_wallet("addr1").unwrap();
@@ -138,7 +290,7 @@ mod tests {
 
     #[test]
     fn test_list_wallets() {
-        let db = WalletDb::new(&temp_db());
+        let (_dir, db) = temp_db();
         let w1 = sample_wallet("a1");
         let w2 = sample_wallet("a2");
         db.save_wallet(&w1);
@@ -148,4 +300,124 @@ mod tests {
         assert!(wallets.iter().any(|w| w.address == "a1"));
         assert!(wallets.iter().any(|w| w.address == "a2"));
     }
+
+    #[test]
+    fn test_watch_only_survives_reload() {
+        let (_dir, db) = temp_db();
+        db.save_wallet(&sample_wallet("full"));
+        db.save_wallet(&Wallet { kind: WalletKind::WatchOnly, encrypted_private_key: String::new(), ..sample_wallet("watched") });
+
+        let kinds: HashMap<String, WalletKind> = db.list_wallets().into_iter().map(|w| (w.address, w.kind)).collect();
+        assert_eq!(kinds, HashMap::from([("full".to_string(), WalletKind::Full), ("watched".to_string(), WalletKind::WatchOnly)]));
+        assert_eq!(db.load_wallet("watched").unwrap().kind, WalletKind::WatchOnly);
+        let stored: String = db.conn.query_row("SELECT metadata FROM wallets WHERE address='watched'", [], |row| row.get(0)).unwrap();
+        assert_eq!(serde_json::from_str::<JsonValue>(&stored).unwrap()["kind"], "watch_only");
+    }
+
+    #[test]
+    fn test_update_label_keeps_other_columns() {
+        let (_dir, db) = temp_db();
+        let w = sample_wallet("addr1");
+        db.save_wallet(&w);
+        assert!(db.update_label("addr1", "savings"));
+        assert!(!db.update_label("missing", "savings"));
+        let loaded = db.load_wallet("addr1").unwrap();
+        assert_eq!(loaded, Wallet { label: "savings".to_string(), ..w });
+    }
+
+    #[test]
+    fn test_luna_wallet_label_and_metadata_survive_reload() {
+        let (_dir, db) = temp_db();
+        let mut wallet = LunaWallet::create("main", "hunter2");
+        wallet.set_label("spending");
+        wallet.set_metadata(json!({"colour": "green", "account": 3}));
+        assert!(db.save_wallet(&Wallet::from(&wallet)));
+
+        let restored = LunaWallet::from(db.load_wallet(&wallet.address).unwrap());
+        assert_eq!(restored.label, "spending");
+        assert_eq!(restored.get_metadata(), &json!({"colour": "green", "account": 3}));
+        assert_eq!(restored.encrypted_private_key, wallet.encrypted_private_key);
+
+        // The metadata column is the document less its columns, as WalletDatabase stores it
+        let stored: String = db.conn.query_row("SELECT metadata FROM wallets WHERE address=?1", params![wallet.address], |row| row.get(0)).unwrap();
+        assert_eq!(serde_json::from_str::<JsonValue>(&stored).unwrap(), restored.stored_metadata());
+
+        // Rows written before then still load
+        db.conn.execute("UPDATE wallets SET metadata='{\"is_locked\": true}' WHERE address=?1", params![wallet.address]).unwrap();
+        assert_eq!(db.load_wallet(&wallet.address).unwrap().metadata, json!({}));
+        let older = json!({"is_locked": true, "available_balance": 1.5, "custom": {"colour": "red"}, "kind": "watch_only"});
+        db.conn.execute("UPDATE wallets SET metadata=?1 WHERE address=?2", params![older.to_string(), wallet.address]).unwrap();
+        let loaded = db.load_wallet(&wallet.address).unwrap();
+        assert_eq!((loaded.metadata, loaded.kind, loaded.available_balance), (json!({"colour": "red"}), WalletKind::WatchOnly, 1.5));
+    }
+
+    #[test]
+    fn test_change_password_rotates_stored_key() {
+        let (_dir, db) = temp_db();
+        let mut wallet = LunaWallet::create("main", "hunter2");
+        db.save_wallet(&Wallet::from(&wallet));
+        let original = db.load_wallet(&wallet.address).unwrap().encrypted_private_key;
+
+        assert_eq!(db.change_password(&mut wallet, "wrong", "hunter3"), Err(WalletError::WrongPassword));
+        assert_eq!(db.load_wallet(&wallet.address).unwrap().encrypted_private_key, original);
+        assert_eq!(wallet.encrypted_private_key, original.as_bytes());
+
+        db.change_password(&mut wallet, "hunter2", "hunter3").unwrap();
+        let mut reloaded = LunaWallet::from(db.load_wallet(&wallet.address).unwrap());
+        assert_eq!(reloaded.unlock("hunter2"), Err(WalletError::WrongPassword));
+        reloaded.unlock("hunter3").unwrap();
+        assert!(reloaded.sign("hello").is_ok());
+
+        // A wallet that was never saved keeps its old password
+        let mut unsaved = LunaWallet::create("other", "hunter2");
+        assert!(matches!(db.change_password(&mut unsaved, "hunter2", "hunter3"), Err(WalletError::Storage(_))));
+        unsaved.unlock("hunter2").unwrap();
+    }
+
+    #[test]
+    fn test_encrypted_backup_round_trip() {
+        let (dir, db) = temp_db();
+        let backup = dir.path().join("wallets.backup");
+        let (w1, w2) = (sample_wallet("a1"), Wallet { label: "second".to_string(), ..sample_wallet("a2") });
+        db.save_wallet(&w1);
+        db.save_wallet(&w2);
+        db.export_encrypted_backup(&backup, "backup-pass").unwrap();
+        assert!(!fs::read_to_string(&backup).unwrap().contains("encpriv"));
+
+        db.conn.execute("DELETE FROM wallets", []).unwrap();
+        assert!(db.list_wallets().is_empty());
+        assert_eq!(db.restore_encrypted_backup(&backup, "backup-pass", false).unwrap(), 2);
+        let mut restored = db.list_wallets();
+        restored.sort_by(|a, b| a.address.cmp(&b.address));
+        assert_eq!(restored, vec![w1.clone(), w2.clone()]);
+
+        // Merge keeps what is already here; replace drops wallets the backup does not have
+        db.update_label("a1", "renamed");
+        db.save_wallet(&sample_wallet("a3"));
+        assert_eq!(db.restore_encrypted_backup(&backup, "backup-pass", true).unwrap(), 0);
+        assert_eq!(db.load_wallet("a1").unwrap().label, "renamed");
+        assert_eq!(db.restore_encrypted_backup(&backup, "backup-pass", false).unwrap(), 2);
+        assert_eq!(db.load_wallet("a1").unwrap(), w1);
+        assert!(db.load_wallet("a3").is_none());
+    }
+
+    #[test]
+    fn test_damaged_backup_and_wrong_password_differ() {
+        let (dir, db) = temp_db();
+        let backup = dir.path().join("wallets.backup");
+        db.save_wallet(&sample_wallet("a1"));
+        db.export_encrypted_backup(&backup, "backup-pass").unwrap();
+
+        assert!(matches!(db.restore_encrypted_backup(&backup, "wrong", false), Err(BackupError::WrongPassword)));
+        let mut file: JsonValue = serde_json::from_str(&fs::read_to_string(&backup).unwrap()).unwrap();
+        let data = file["data"].as_str().unwrap().to_string();
+        let flipped = if data.ends_with('A') { 'B' } else { 'A' };
+        file["data"] = json!(format!("{}{}", &data[..data.len() - 1], flipped));
+        fs::write(&backup, file.to_string()).unwrap();
+        assert!(matches!(db.restore_encrypted_backup(&backup, "backup-pass", false), Err(BackupError::Corrupt(_))));
+        fs::write(&backup, "not a backup").unwrap();
+        assert!(matches!(db.restore_encrypted_backup(&backup, "backup-pass", false), Err(BackupError::Corrupt(_))));
+        assert!(matches!(db.restore_encrypted_backup(&dir.path().join("missing"), "backup-pass", false), Err(BackupError::Io(_))));
+        assert_eq!(db.list_wallets(), vec![sample_wallet("a1")]);
+    }
 }
@@ -1,67 +1,28 @@
-    fn categorize_pending_transaction(tx: &Transaction, _address: &str) -> Vec<String> {
-        let mut categories = vec!["pending_transactions".to_string()];
-        match tx.tx_type {
-            TransactionType::Reward => categories.push("rewards".to_string()),
-            TransactionType::Genesis => categories.push("genesis_transactions".to_string()),
-            TransactionType::Transfer | TransactionType::Unknown => categories.push("pending_transfers".to_string()),
-        }
-        categories
-    }
-
-    fn calculate_balance_from_transactions(
-        address: &str,
-        confirmed: &[Transaction],
-        pending: &[Transaction],
-    ) -> WalletBalance {
-        let mut total = 0.0;
-        let mut available = 0.0;
-        let mut pending_in = 0.0;
-        let mut pending_out = 0.0;
-        let mut confirmed_balance = 0.0;
-        println!("[calc_balance] address: {}", address);
-        for tx in confirmed {
-            println!("[calc_balance] confirmed tx: from={} to={} amt={} fee={}", tx.from_address, tx.to_address, tx.amount, tx.fee);
-            if tx.to_address == address {
-                total += tx.amount;
-                confirmed_balance += tx.amount;
-                println!("[calc_balance]   +incoming: +{} (total now {})", tx.amount, confirmed_balance);
-            }
-            if tx.from_address == address {
-                total -= tx.amount + tx.fee;
-                confirmed_balance -= tx.amount + tx.fee;
-                println!("[calc_balance]   -outgoing: -{} -fee {} (total now {})", tx.amount, tx.fee, confirmed_balance);
-            }
-        }
-        for tx in pending {
-            if tx.to_address == address {
-                pending_in += tx.amount;
-                available += tx.amount;
-            }
-            if tx.from_address == address {
-                pending_out += tx.amount + tx.fee;
-                available -= tx.amount + tx.fee;
-            }
-        }
-        available += confirmed_balance;
-        WalletBalance {
-            total_balance: total,
-            available_balance: available,
-            pending_incoming: pending_in,
-            pending_outgoing: pending_out,
-            confirmed_balance,
-        }
-    }
-
-
-use std::collections::{HashMap, HashSet};
+use std::cmp::Reverse;
+use std::collections::{HashMap, HashSet, VecDeque};
+use crate::core::amount::{Amount, AmountError};
+use crate::core::blockchain::{Block, BlockchainManager, Transaction as ChainTransaction};
+use crate::core::wallet_db::WalletDb;
+use crate::storage::database::WalletDatabase;
+use serde::{Deserialize, Deserializer, Serialize};
+use serde_json::{json, Value as JsonValue};
+use std::fmt;
+use std::io::{self, Write};
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::{Arc, Mutex, RwLock};
 use std::time::{SystemTime, UNIX_EPOCH};
+use crate::utils::clock;
+use crate::utils::export::write_csv;
+use crate::utils::log::{debug, error, info, trace, warn};
 
-#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
 pub enum TransactionType {
     Transfer,
     Reward,
+    #[serde(alias = "gtx_genesis")]
     Genesis,
+    #[serde(other)]
     Unknown,
 }
 
@@ -71,10 +32,12 @@ impl Default for TransactionType {
     }
 }
 
-#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
 pub enum TransactionStatus {
     Confirmed,
     Pending,
+    #[serde(other)]
     Unknown,
 }
 
@@ -84,31 +47,236 @@ impl Default for TransactionStatus {
     }
 }
 
-#[derive(Debug, Clone, PartialEq, Default)]
+impl TransactionType {
+    /// The variant for a transaction's `type` field
+    pub fn from_tx_type(tx_type: &str) -> Self {
+        match tx_type {
+            "transfer" => TransactionType::Transfer,
+            "reward" => TransactionType::Reward,
+            "gtx_genesis" | "genesis" => TransactionType::Genesis,
+            _ => TransactionType::Unknown,
+        }
+    }
+}
+
+/// Which way a transaction moves funds for the wallet it is listed under
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TransactionDirection {
+    Incoming,
+    Outgoing,
+    /// From the wallet to itself
+    SelfTransfer,
+    #[default]
+    #[serde(other)]
+    Unknown,
+}
+
+impl TransactionDirection {
+    /// Addresses are compared after `BlockchainManager::normalize_address`
+    pub fn relative_to(address: &str, from: &str, to: &str) -> Self {
+        let address = BlockchainManager::normalize_address(address);
+        let is_wallet = |end: &str| BlockchainManager::normalize_address(end) == address;
+        match (is_wallet(from), is_wallet(to)) {
+            (true, true) => TransactionDirection::SelfTransfer,
+            (true, false) => TransactionDirection::Outgoing,
+            (false, true) => TransactionDirection::Incoming,
+            (false, false) => TransactionDirection::Unknown,
+        }
+    }
+}
+
+/// Serialized with the `from` and `to` keys the node and the storage layer use; documents keyed
+/// `from_address`, `to_address` or `type` also load, and missing fields take their defaults.
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+#[serde(default)]
 pub struct Transaction {
     pub hash: String,
+    #[serde(alias = "type")]
     pub tx_type: TransactionType,
+    #[serde(rename = "from", alias = "from_address")]
     pub from_address: String,
+    #[serde(rename = "to", alias = "to_address")]
     pub to_address: String,
-    pub amount: f64,
-    pub fee: f64,
+    pub amount: Amount,
+    pub fee: Amount,
+    /// Unix seconds
+    #[serde(deserialize_with = "clock::unix_seconds")]
     pub timestamp: u64,
     pub status: TransactionStatus,
+    #[serde(deserialize_with = "block_height")]
     pub block_height: Option<u64>,
     pub confirmations: u64,
     pub memo: String,
+    pub direction: TransactionDirection,
+}
+
+impl Transaction {
+    /// A transaction stored as JSON, listed for `address` with `status`
+    pub fn from_record(tx: &serde_json::Value, address: &str, status: TransactionStatus) -> Self {
+        let text = |key: &str| tx[key].as_str().unwrap_or_default().to_string();
+        let from_address = text("from");
+        let to_address = text("to");
+        Transaction {
+            hash: text("hash"),
+            tx_type: TransactionType::from_tx_type(tx["type"].as_str().unwrap_or("transfer")),
+            direction: TransactionDirection::relative_to(address, &from_address, &to_address),
+            from_address,
+            to_address,
+            amount: Amount::from_json_lossy(&tx["amount"]),
+            fee: Amount::from_json_lossy(&tx["fee"]),
+            timestamp: tx["timestamp"].as_f64().unwrap_or(0.0) as u64,
+            status,
+            block_height: tx["block_height"].as_u64().filter(|h| *h > 0),
+            confirmations: 0,
+            memo: text("memo"),
+        }
+    }
+
+    /// `tx` from `block`, listed for `address` with its confirmations counted up to the chain tip at `tip`
+    pub fn from_block(tx: &ChainTransaction, block: &Block, tip: u64, address: &str) -> Self {
+        let from_address = tx.from.clone().unwrap_or_default();
+        let to_address = tx.to.clone().unwrap_or_default();
+        Transaction {
+            hash: tx.hash.clone().unwrap_or_default(),
+            tx_type: TransactionType::from_tx_type(tx.tx_type.as_deref().unwrap_or("transfer")),
+            direction: TransactionDirection::relative_to(address, &from_address, &to_address),
+            from_address,
+            to_address,
+            amount: Amount::from_f64_lossy(tx.amount.unwrap_or(0.0)),
+            fee: Amount::from_f64_lossy(tx.fee.unwrap_or(0.0)),
+            timestamp: tx.timestamp.unwrap_or(block.timestamp),
+            status: TransactionStatus::Confirmed,
+            block_height: Some(block.index),
+            confirmations: (tip + 1).saturating_sub(block.index),
+            memo: tx.memo.clone().unwrap_or_default(),
+        }
+    }
+
+    /// A transaction as the node's blocks and mempool list it, with `from`, `to` and `type` keys.
+    /// It counts as confirmed once it has a `block_height` or a `"confirmed"` status.
+    pub fn from_json(tx: &JsonValue) -> Result<Self, String> {
+        if !tx.is_object() {
+            return Err("Transaction is not a JSON object".to_string());
+        }
+        if tx["hash"].as_str().is_none_or(str::is_empty) {
+            return Err("Transaction has no hash".to_string());
+        }
+        let confirmed = tx["block_height"].is_u64() || tx["status"] == "confirmed";
+        let status = if confirmed { TransactionStatus::Confirmed } else { TransactionStatus::Pending };
+        Ok(Transaction {
+            confirmations: tx["confirmations"].as_u64().unwrap_or(0),
+            direction: TransactionDirection::Unknown,
+            ..Self::from_record(tx, "", status)
+        })
+    }
+
+    #[deprecated(note = "amounts are exact `Amount`s now; use `amount`")]
+    pub fn amount_f64(&self) -> f64 {
+        self.amount.to_f64_lossy()
+    }
+
+    #[deprecated(note = "amounts are exact `Amount`s now; use `fee`")]
+    pub fn fee_f64(&self) -> f64 {
+        self.fee.to_f64_lossy()
+    }
+}
+
+/// The storage layer writes 0 for a transaction that is not in a block
+fn block_height<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<u64>, D::Error> {
+    Ok(Option::<u64>::deserialize(deserializer)?.filter(|h| *h > 0))
 }
 
-#[derive(Debug, Clone, PartialEq, Default)]
+/// Always `available_balance <= confirmed_balance`: unconfirmed deposits are not spendable
+/// and only show up in `pending_incoming` and `projected_balance`.
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
 pub struct WalletBalance {
-    pub total_balance: f64,
-    pub available_balance: f64,
-    pub pending_incoming: f64,
-    pub pending_outgoing: f64,
-    pub confirmed_balance: f64,
+    pub total_balance: Amount,
+    /// `confirmed_balance - pending_outgoing`
+    pub available_balance: Amount,
+    pub pending_incoming: Amount,
+    pub pending_outgoing: Amount,
+    pub confirmed_balance: Amount,
+    /// What `confirmed_balance` becomes once every pending transaction confirms
+    pub projected_balance: Amount,
+    #[serde(default)]
+    pub totals: WalletTotals,
+}
+
+/// Lifetime sums over the confirmed transactions, which always satisfy
+/// `confirmed_balance == total_received + total_rewards_earned - total_sent - total_fees_paid`.
+/// A transfer to the wallet itself counts as both sent and received.
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+pub struct WalletTotals {
+    /// Everything received except rewards
+    pub total_received: Amount,
+    pub total_rewards_earned: Amount,
+    /// Amounts sent, without their fees
+    pub total_sent: Amount,
+    pub total_fees_paid: Amount,
 }
 
-#[derive(Debug, Clone, Default)]
+impl WalletTotals {
+    /// Field-by-field sum, failing rather than wrapping on overflow
+    pub fn try_add(&self, other: &WalletTotals) -> Result<WalletTotals, AmountError> {
+        Ok(WalletTotals {
+            total_received: self.total_received.try_add(other.total_received)?,
+            total_rewards_earned: self.total_rewards_earned.try_add(other.total_rewards_earned)?,
+            total_sent: self.total_sent.try_add(other.total_sent)?,
+            total_fees_paid: self.total_fees_paid.try_add(other.total_fees_paid)?,
+        })
+    }
+}
+
+impl WalletBalance {
+    /// Field-by-field sum, failing rather than wrapping on overflow
+    pub fn try_add(&self, other: &WalletBalance) -> Result<WalletBalance, AmountError> {
+        Ok(WalletBalance {
+            totals: self.totals.try_add(&other.totals)?,
+            total_balance: self.total_balance.try_add(other.total_balance)?,
+            available_balance: self.available_balance.try_add(other.available_balance)?,
+            pending_incoming: self.pending_incoming.try_add(other.pending_incoming)?,
+            pending_outgoing: self.pending_outgoing.try_add(other.pending_outgoing)?,
+            confirmed_balance: self.confirmed_balance.try_add(other.confirmed_balance)?,
+            projected_balance: self.projected_balance.try_add(other.projected_balance)?,
+        })
+    }
+}
+
+/// Float views of the balance for code written before amounts were exact
+impl WalletBalance {
+    #[deprecated(note = "balances are exact `Amount`s now; use `total_balance`")]
+    pub fn total_balance_f64(&self) -> f64 {
+        self.total_balance.to_f64_lossy()
+    }
+
+    #[deprecated(note = "balances are exact `Amount`s now; use `available_balance`")]
+    pub fn available_balance_f64(&self) -> f64 {
+        self.available_balance.to_f64_lossy()
+    }
+
+    #[deprecated(note = "balances are exact `Amount`s now; use `pending_incoming`")]
+    pub fn pending_incoming_f64(&self) -> f64 {
+        self.pending_incoming.to_f64_lossy()
+    }
+
+    #[deprecated(note = "balances are exact `Amount`s now; use `pending_outgoing`")]
+    pub fn pending_outgoing_f64(&self) -> f64 {
+        self.pending_outgoing.to_f64_lossy()
+    }
+
+    #[deprecated(note = "balances are exact `Amount`s now; use `confirmed_balance`")]
+    pub fn confirmed_balance_f64(&self) -> f64 {
+        self.confirmed_balance.to_f64_lossy()
+    }
+
+    #[deprecated(note = "balances are exact `Amount`s now; use `projected_balance`")]
+    pub fn projected_balance_f64(&self) -> f64 {
+        self.projected_balance.to_f64_lossy()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
 pub struct WalletState {
     pub address: String,
     pub balance: WalletBalance,
@@ -119,17 +287,224 @@ pub struct WalletState {
     pub rewards: Vec<Transaction>,
     pub genesis_transactions: Vec<Transaction>,
     pub last_updated: u64,
+    /// `(timestamp, balance)` after each sync that changed the balance, oldest first
+    #[serde(default)]
+    pub balance_history: VecDeque<(u64, WalletBalance)>,
+    /// Hidden from syncing, balance callbacks and portfolio totals, with its data kept
+    #[serde(default)]
+    pub archived: bool,
+    /// The stored balance `register_from_db` started the wallet with, until a sync checks it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub seeded_balance: Option<WalletBalance>,
+}
+
+impl WalletState {
+    /// The state as JSON for a UI or another process; `serde_json::from_value` reads it back
+    pub fn to_json(&self) -> JsonValue {
+        serde_json::to_value(self).expect("a wallet state always serializes")
+    }
+}
+
+/// The order `WalletManager::query_transactions` returns transactions in
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TxSort {
+    #[default]
+    NewestFirst,
+    OldestFirst,
+    LargestFirst,
+    SmallestFirst,
+}
+
+/// Which of a wallet's transactions `WalletManager::query_transactions` returns, built up from
+/// `TxFilter::new()`, which matches everything: `TxFilter::new().tx_type(TransactionType::Reward).since(ts)`
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TxFilter {
+    tx_type: Option<TransactionType>,
+    status: Option<TransactionStatus>,
+    min_amount: Option<Amount>,
+    max_amount: Option<Amount>,
+    since: Option<u64>,
+    until: Option<u64>,
+    counterparty: Option<String>,
+    memo: Option<String>,
+    sort: TxSort,
+    offset: usize,
+    limit: Option<usize>,
+}
+
+impl TxFilter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn tx_type(mut self, tx_type: TransactionType) -> Self {
+        self.tx_type = Some(tx_type);
+        self
+    }
+
+    /// Only the wallet's confirmed or pending list; `Unknown` matches nothing
+    pub fn status(mut self, status: TransactionStatus) -> Self {
+        self.status = Some(status);
+        self
+    }
+
+    /// Inclusive
+    pub fn min_amount(mut self, amount: Amount) -> Self {
+        self.min_amount = Some(amount);
+        self
+    }
+
+    /// Inclusive
+    pub fn max_amount(mut self, amount: Amount) -> Self {
+        self.max_amount = Some(amount);
+        self
+    }
+
+    /// Transactions with a timestamp at or after `timestamp`
+    pub fn since(mut self, timestamp: u64) -> Self {
+        self.since = Some(timestamp);
+        self
+    }
+
+    /// Transactions with a timestamp before `timestamp`
+    pub fn until(mut self, timestamp: u64) -> Self {
+        self.until = Some(timestamp);
+        self
+    }
+
+    /// Transactions to or from `address`, compared after `BlockchainManager::normalize_address`
+    pub fn counterparty(mut self, address: &str) -> Self {
+        self.counterparty = Some(BlockchainManager::normalize_address(address));
+        self
+    }
+
+    /// Transactions whose memo contains `text`, ignoring case
+    pub fn memo_contains(mut self, text: &str) -> Self {
+        self.memo = Some(text.to_lowercase());
+        self
+    }
+
+    pub fn sort(mut self, sort: TxSort) -> Self {
+        self.sort = sort;
+        self
+    }
+
+    /// Skip the first `offset` matches, after sorting
+    pub fn offset(mut self, offset: usize) -> Self {
+        self.offset = offset;
+        self
+    }
+
+    pub fn limit(mut self, limit: usize) -> Self {
+        self.limit = Some(limit);
+        self
+    }
+
+    /// Whether `tx` passes every condition but `status`, which is the list it came from
+    fn matches(&self, tx: &Transaction) -> bool {
+        self.tx_type.as_ref().is_none_or(|t| *t == tx.tx_type)
+            && self.min_amount.is_none_or(|min| tx.amount >= min)
+            && self.max_amount.is_none_or(|max| tx.amount <= max)
+            && self.since.is_none_or(|since| tx.timestamp >= since)
+            && self.until.is_none_or(|until| tx.timestamp < until)
+            && self.counterparty.as_ref().is_none_or(|other| {
+                BlockchainManager::normalize_address(&tx.from_address) == *other || BlockchainManager::normalize_address(&tx.to_address) == *other
+            })
+            && self.memo.as_ref().is_none_or(|text| tx.memo.to_lowercase().contains(text))
+    }
+}
+
+/// How `WalletManager::export_history` writes transactions
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ExportFormat {
+    /// A header line of `HISTORY_EXPORT_COLUMNS`, then one row per transaction
+    Csv,
+    /// One JSON object per line, keyed by `HISTORY_EXPORT_COLUMNS`
+    JsonLines,
+}
+
+/// Columns of an exported history, in order. `counterparty` is the other end of the transaction,
+/// `timestamp` is ISO 8601 UTC and `block_height` is empty while pending.
+pub const HISTORY_EXPORT_COLUMNS: [&str; 10] = ["hash", "type", "direction", "counterparty", "amount", "fee", "timestamp", "status", "block_height", "memo"];
+
+/// Why `export_history` failed
+#[derive(Debug)]
+pub enum ExportError {
+    UnknownWallet(String),
+    Io(io::Error),
+}
+
+impl fmt::Display for ExportError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ExportError::UnknownWallet(address) => write!(f, "No registered wallet {}", address),
+            ExportError::Io(e) => write!(f, "Cannot write history: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for ExportError {}
+
+impl From<io::Error> for ExportError {
+    fn from(e: io::Error) -> Self {
+        ExportError::Io(e)
+    }
 }
 
 type BalanceCallback = Arc<dyn Fn(HashMap<String, WalletBalance>) + Send + Sync>;
 type TransactionCallback = Arc<dyn Fn(HashMap<String, Vec<Transaction>>) + Send + Sync>;
+type ScopedBalanceCallback = Arc<dyn Fn(WalletBalance) + Send + Sync>;
+type ScopedTransactionCallback = Arc<dyn Fn(Vec<Transaction>) + Send + Sync>;
+type Observer<T> = Arc<dyn Fn(T) + Send + Sync>;
+type Callbacks<C> = Mutex<Vec<(CallbackId, C)>>;
+/// Every wallet's balances and confirmed transactions, each taken only if a callback wants them
+type CallbackSnapshot = (Option<HashMap<String, WalletBalance>>, Option<HashMap<String, Vec<Transaction>>>);
+type ScopedCallbacks<C> = Mutex<HashMap<String, Vec<(CallbackId, C)>>>;
+pub type SyncLogger = Arc<dyn Fn(SyncEvent) + Send + Sync>;
+
+/// What `WalletManager::register_from_db` did with the stored wallets
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub struct RegisterSummary {
+    pub added: usize,
+    /// Wallets that were registered already and kept their state
+    pub already_registered: usize,
+}
+
+/// Names a registered balance or transaction callback so it can be removed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct CallbackId(pub u64);
+
+/// What `sync_wallets_from_sources` did, as reported to the `set_logger` hook
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum SyncEvent {
+    /// A transaction was sorted into the wallet's state lists, e.g. `confirmed_transfers`
+    TxCategorized { address: String, hash: String, categories: Vec<String> },
+    BalanceComputed { address: String, balance: WalletBalance },
+    AddressSynced { address: String, confirmed: usize, pending: usize },
+    /// The balance could not be computed, so the previous one was kept
+    BalanceFailed { address: String, error: AmountError },
+    /// The first sync of a wallet seeded by `register_from_db` computed a different balance than was stored
+    BalanceDiscrepancy { address: String, seeded: WalletBalance, computed: WalletBalance },
+}
 
 pub struct WalletManager {
     pub wallet_states: Arc<RwLock<HashMap<String, WalletState>>>,
-    balance_callbacks: Arc<Mutex<Vec<BalanceCallback>>>,
-    transaction_callbacks: Arc<Mutex<Vec<TransactionCallback>>>,
+    balance_callbacks: Arc<Mutex<Vec<(CallbackId, BalanceCallback)>>>,
+    transaction_callbacks: Arc<Mutex<Vec<(CallbackId, TransactionCallback)>>>,
+    scoped_balance_callbacks: ScopedCallbacks<ScopedBalanceCallback>,
+    scoped_transaction_callbacks: ScopedCallbacks<ScopedTransactionCallback>,
+    next_callback_id: AtomicU64,
+    sync_logger: RwLock<Option<SyncLogger>>,
+    balance_history_limit: usize,
+    history_store: Option<WalletDatabase>,
 }
 
+pub const DEFAULT_BALANCE_HISTORY_LIMIT: usize = 1000;
+
 impl WalletManager {
         fn categorize_pending_transaction(tx: &Transaction, _address: &str) -> Vec<String> {
             let mut categories = vec!["pending_transactions".to_string()];
@@ -141,83 +516,357 @@ impl WalletManager {
             categories
         }
 
-        fn calculate_balance_from_transactions(
+        /// `address` is compared with each transaction's ends after `key` normalizes both.
+        /// Fails rather than wrapping if a sum overflows.
+        pub(crate) fn calculate_balance_from_transactions(
             address: &str,
             confirmed: &[Transaction],
             pending: &[Transaction],
-        ) -> WalletBalance {
-            let mut total = 0.0;
-            let mut available = 0.0;
-            let mut pending_in = 0.0;
-            let mut pending_out = 0.0;
-            let mut confirmed_balance = 0.0;
+        ) -> Result<WalletBalance, AmountError> {
+            let key = Self::key(address);
+            let mut confirmed_balance = Amount::ZERO;
+            let mut pending_in = Amount::ZERO;
+            let mut pending_out = Amount::ZERO;
+            let mut totals = WalletTotals::default();
             for tx in confirmed {
-                if tx.to_address == address {
-                    total += tx.amount;
-                    confirmed_balance += tx.amount;
+                if Self::key(&tx.to_address) == key {
+                    confirmed_balance = confirmed_balance.try_add(tx.amount)?;
+                    match tx.tx_type {
+                        TransactionType::Reward => totals.total_rewards_earned = totals.total_rewards_earned.try_add(tx.amount)?,
+                        _ => totals.total_received = totals.total_received.try_add(tx.amount)?,
+                    }
+                    trace!(address = address, tx_hash = tx.hash.as_str(), amount:% = tx.amount, balance:% = confirmed_balance; "Incoming transaction");
                 }
-                if tx.from_address == address {
-                    total -= tx.amount + tx.fee;
-                    confirmed_balance -= tx.amount + tx.fee;
+                if Self::key(&tx.from_address) == key {
+                    confirmed_balance = confirmed_balance.try_sub(tx.amount.try_add(tx.fee)?)?;
+                    totals.total_sent = totals.total_sent.try_add(tx.amount)?;
+                    totals.total_fees_paid = totals.total_fees_paid.try_add(tx.fee)?;
+                    trace!(address = address, tx_hash = tx.hash.as_str(), amount:% = tx.amount, fee:% = tx.fee, balance:% = confirmed_balance; "Outgoing transaction");
                 }
             }
             for tx in pending {
-                if tx.to_address == address {
-                    pending_in += tx.amount;
-                    available += tx.amount;
+                if Self::key(&tx.to_address) == key {
+                    pending_in = pending_in.try_add(tx.amount)?;
                 }
-                if tx.from_address == address {
-                    pending_out += tx.amount + tx.fee;
-                    available -= tx.amount + tx.fee;
+                if Self::key(&tx.from_address) == key {
+                    pending_out = pending_out.try_add(tx.amount.try_add(tx.fee)?)?;
                 }
             }
-            available += confirmed_balance;
-            WalletBalance {
-                total_balance: total,
-                available_balance: available,
+            Ok(WalletBalance {
+                total_balance: confirmed_balance,
+                available_balance: confirmed_balance.try_sub(pending_out)?,
+                projected_balance: confirmed_balance.try_add(pending_in)?.try_sub(pending_out)?,
                 pending_incoming: pending_in,
                 pending_outgoing: pending_out,
                 confirmed_balance,
-            }
+                totals,
+            })
         }
     pub fn new() -> Self {
         WalletManager {
             wallet_states: Arc::new(RwLock::new(HashMap::new())),
             balance_callbacks: Arc::new(Mutex::new(Vec::new())),
             transaction_callbacks: Arc::new(Mutex::new(Vec::new())),
+            scoped_balance_callbacks: Mutex::new(HashMap::new()),
+            scoped_transaction_callbacks: Mutex::new(HashMap::new()),
+            next_callback_id: AtomicU64::new(1),
+            sync_logger: RwLock::new(None),
+            balance_history_limit: DEFAULT_BALANCE_HISTORY_LIMIT,
+            history_store: None,
         }
     }
 
+    /// Keep at most `limit` balance snapshots per wallet, dropping the oldest
+    pub fn with_balance_history_limit(mut self, limit: usize) -> Self {
+        self.balance_history_limit = limit;
+        self
+    }
+
+    /// Also write balance snapshots to `db`, so a wallet removed and registered again gets its history back
+    pub fn with_history_store(mut self, db: WalletDatabase) -> Self {
+        self.history_store = Some(db);
+        self
+    }
+
+    /// Receive a `SyncEvent` for each step of a sync; without one, syncing reports nothing.
+    /// Events are delivered after the sync releases the wallet states, so the hook may read them.
+    pub fn set_logger(&self, logger: SyncLogger) {
+        *self.sync_logger.write().unwrap() = Some(logger);
+    }
+
+    /// How wallets are keyed: `LUN_ABC`, `lun_abc` and `abc` are the same wallet
+    fn key(address: &str) -> String {
+        BlockchainManager::normalize_address(address)
+    }
+
+    /// Registering an address that differs only in case or `LUN_` prefix keeps the existing wallet
     pub fn register_wallet(&self, address: &str) {
         let mut states = self.wallet_states.write().unwrap();
-        states.entry(address.to_string()).or_insert_with(|| WalletState {
-            address: address.to_string(),
-            ..Default::default()
-        });
+        states.entry(Self::key(address)).or_insert_with(|| self.new_state(address));
     }
 
     pub fn register_wallets(&self, addresses: &[String]) {
         let mut states = self.wallet_states.write().unwrap();
         for address in addresses {
-            states.entry(address.clone()).or_insert_with(|| WalletState {
-                address: address.clone(),
-                ..Default::default()
-            });
+            states.entry(Self::key(address)).or_insert_with(|| self.new_state(address));
+        }
+    }
+
+    /// Register every wallet in `db`, starting new ones from their stored `balance` and
+    /// `available_balance` so there is something to show before the first sync. That sync
+    /// replaces the seeded balance and reports a `SyncEvent::BalanceDiscrepancy` if it differs.
+    pub fn register_from_db(&self, db: &WalletDb) -> RegisterSummary {
+        let rows = db.list_wallets();
+        let mut summary = RegisterSummary::default();
+        let mut states = self.wallet_states.write().unwrap();
+        for row in rows {
+            let key = Self::key(&row.address);
+            if states.contains_key(&key) {
+                summary.already_registered += 1;
+                continue;
+            }
+            let mut state = self.new_state(&row.address);
+            let confirmed = Amount::from_f64_lossy(row.balance);
+            let available = Amount::from_f64_lossy(row.available_balance).min(confirmed);
+            state.balance = WalletBalance {
+                total_balance: confirmed,
+                available_balance: available,
+                pending_incoming: Amount::ZERO,
+                pending_outgoing: confirmed.checked_sub(available).unwrap_or(Amount::ZERO),
+                confirmed_balance: confirmed,
+                projected_balance: available,
+                totals: WalletTotals::default(),
+            };
+            state.seeded_balance = Some(state.balance.clone());
+            states.insert(key, state);
+            summary.added += 1;
+        }
+        info!(added = summary.added, already_registered = summary.already_registered; "Registered wallets from storage");
+        summary
+    }
+
+    /// A fresh state, starting from the stored history and its last balance when there is a history store
+    fn new_state(&self, address: &str) -> WalletState {
+        let mut state = WalletState { address: address.to_string(), ..Default::default() };
+        if let Some(db) = &self.history_store {
+            state.balance_history = db.load_balance_history(&Self::key(address), self.balance_history_limit)
+                .into_iter()
+                .filter_map(|(timestamp, balance)| Some((timestamp, serde_json::from_value(balance).ok()?)))
+                .collect();
+            if let Some((_, last)) = state.balance_history.back() {
+                state.balance = last.clone();
+            }
+        }
+        state
+    }
+
+    /// `address`'s lifetime sent, received, reward and fee sums, also found in each balance callback
+    pub fn get_wallet_totals(&self, address: &str) -> Option<WalletTotals> {
+        let states = self.wallet_states.read().unwrap();
+        states.get(&Self::key(address)).map(|state| state.balance.totals.clone())
+    }
+
+    /// Snapshots of `address`'s balance taken at or after `since`, oldest first
+    pub fn get_balance_history(&self, address: &str, since: u64) -> Vec<(u64, WalletBalance)> {
+        let states = self.wallet_states.read().unwrap();
+        states.get(&Self::key(address))
+            .map(|state| state.balance_history.iter().filter(|(timestamp, _)| *timestamp >= since).cloned().collect())
+            .unwrap_or_default()
+    }
+
+    /// Write every wallet's state to `db`, replacing what was saved for it before.
+    /// Returns false if any wallet could not be written.
+    pub fn save_snapshot(&self, db: &WalletDatabase) -> bool {
+        let states = self.wallet_states.read().unwrap();
+        let mut saved = true;
+        for (key, state) in states.iter() {
+            if !db.save_wallet_state(key, state.last_updated, &state.to_json()) {
+                warn!(address = state.address.as_str(); "Could not save wallet state");
+                saved = false;
+            }
+        }
+        info!(count = states.len(), saved = saved; "Saved wallet snapshot");
+        saved
+    }
+
+    /// Restore the states `save_snapshot` wrote, registering their wallets and replacing any state
+    /// already held for them. Returns how many were restored; unreadable states are skipped.
+    pub fn load_snapshot(&self, db: &WalletDatabase) -> usize {
+        let mut states = self.wallet_states.write().unwrap();
+        let mut restored = 0;
+        for (address, document) in db.load_wallet_states() {
+            match serde_json::from_value::<WalletState>(document) {
+                Ok(state) => {
+                    states.insert(Self::key(&state.address), state);
+                    restored += 1;
+                }
+                Err(e) => warn!(address = address.as_str(), error:% = e; "Skipping unreadable wallet state"),
+            }
+        }
+        info!(count = restored; "Loaded wallet snapshot");
+        restored
+    }
+
+    /// The highest block among `address`'s confirmed transactions, where a resumed sync can pick up
+    pub fn last_block_height(&self, address: &str) -> Option<u64> {
+        let states = self.wallet_states.read().unwrap();
+        states.get(&Self::key(address))?.confirmed_transactions.iter().filter_map(|tx| tx.block_height).max()
+    }
+
+    /// `address`'s confirmed and pending transactions that pass `filter`, sorted and paged as it asks.
+    /// Only the matches are copied out, and the wallet states are not locked once this returns.
+    pub fn query_transactions(&self, address: &str, filter: TxFilter) -> Vec<Transaction> {
+        let mut matches: Vec<Transaction> = {
+            let states = self.wallet_states.read().unwrap();
+            let Some(state) = states.get(&Self::key(address)) else {
+                return Vec::new();
+            };
+            let wanted = |status: TransactionStatus| filter.status.as_ref().is_none_or(|s| *s == status);
+            let confirmed: &[Transaction] = if wanted(TransactionStatus::Confirmed) { &state.confirmed_transactions } else { &[] };
+            let pending: &[Transaction] = if wanted(TransactionStatus::Pending) { &state.pending_transactions } else { &[] };
+            confirmed.iter().chain(pending).filter(|tx| filter.matches(tx)).cloned().collect()
+        };
+        match filter.sort {
+            TxSort::NewestFirst => matches.sort_by_key(|tx| Reverse(tx.timestamp)),
+            TxSort::OldestFirst => matches.sort_by_key(|tx| tx.timestamp),
+            TxSort::LargestFirst => matches.sort_by_key(|tx| Reverse(tx.amount)),
+            TxSort::SmallestFirst => matches.sort_by_key(|tx| tx.amount),
         }
+        matches.into_iter().skip(filter.offset).take(filter.limit.unwrap_or(usize::MAX)).collect()
+    }
+
+    /// Every unarchived wallet's balance added together
+    pub fn get_total_balance(&self) -> Result<WalletBalance, AmountError> {
+        let states = self.wallet_states.read().unwrap();
+        states.values().filter(|state| !state.archived).try_fold(WalletBalance::default(), |total, state| total.try_add(&state.balance))
+    }
+
+    /// Unarchived balances added up per label, with wallets `db` has no row for under `""`. Each wallet counts
+    /// once even when `db` also holds a watch-only row for the same address in another spelling;
+    /// the row with a private key names it.
+    pub fn get_balances_grouped_by_label(&self, db: &WalletDb) -> Result<HashMap<String, WalletBalance>, AmountError> {
+        let mut labels: HashMap<String, (bool, String)> = HashMap::new();
+        for row in db.list_wallets() {
+            let owned = !row.encrypted_private_key.is_empty();
+            let entry = labels.entry(Self::key(&row.address)).or_insert((owned, row.label.clone()));
+            if owned && !entry.0 {
+                *entry = (owned, row.label);
+            }
+        }
+        let states = self.wallet_states.read().unwrap();
+        let mut grouped: HashMap<String, WalletBalance> = HashMap::new();
+        for (key, state) in states.iter().filter(|(_, state)| !state.archived) {
+            let label = labels.get(key).map(|(_, label)| label.clone()).unwrap_or_default();
+            let total = grouped.entry(label).or_default();
+            *total = total.try_add(&state.balance)?;
+        }
+        Ok(grouped)
+    }
+
+    /// The `n` unarchived addresses with the largest confirmed balance, largest first, as they were registered
+    pub fn get_richest_wallets(&self, n: usize) -> Vec<(String, Amount)> {
+        let mut balances: Vec<(String, Amount)> = {
+            let states = self.wallet_states.read().unwrap();
+            states.values().filter(|state| !state.archived).map(|state| (state.address.clone(), state.balance.confirmed_balance)).collect()
+        };
+        balances.sort_by(|(a, a_balance), (b, b_balance)| b_balance.cmp(a_balance).then_with(|| a.cmp(b)));
+        balances.truncate(n);
+        balances
+    }
+
+    /// Write `address`'s confirmed and pending transactions to `writer`, oldest first, and return
+    /// how many were written
+    pub fn export_history(&self, address: &str, format: ExportFormat, writer: &mut dyn Write) -> Result<usize, ExportError> {
+        let wallet = self.wallet_states.read().unwrap().get(&Self::key(address)).map(|state| state.address.clone());
+        let wallet = wallet.ok_or_else(|| ExportError::UnknownWallet(address.to_string()))?;
+        let rows = self.query_transactions(address, TxFilter::new().sort(TxSort::OldestFirst)).iter().map(|tx| Self::export_row(&wallet, tx)).collect::<Vec<_>>();
+        let written = match format {
+            ExportFormat::Csv => write_csv(writer, &HISTORY_EXPORT_COLUMNS, rows)?,
+            ExportFormat::JsonLines => {
+                for row in &rows {
+                    let object: serde_json::Map<String, JsonValue> = HISTORY_EXPORT_COLUMNS.iter().zip(row).map(|(column, field)| (column.to_string(), json!(field))).collect();
+                    writeln!(writer, "{}", JsonValue::Object(object))?;
+                }
+                rows.len()
+            }
+        };
+        debug!(address = wallet.as_str(), rows = written; "Exported history");
+        Ok(written)
+    }
+
+    /// `tx`'s fields in `HISTORY_EXPORT_COLUMNS` order, as seen from `address`
+    fn export_row(address: &str, tx: &Transaction) -> Vec<String> {
+        let direction = TransactionDirection::relative_to(address, &tx.from_address, &tx.to_address);
+        let counterparty = match direction {
+            TransactionDirection::Outgoing | TransactionDirection::SelfTransfer => &tx.to_address,
+            _ => &tx.from_address,
+        };
+        let name = |value: JsonValue| value.as_str().unwrap_or_default().to_string();
+        let timestamp = chrono::DateTime::from_timestamp(tx.timestamp as i64, 0)
+            .map(|t| t.to_rfc3339_opts(chrono::SecondsFormat::Secs, true))
+            .unwrap_or_default();
+        vec![
+            tx.hash.clone(),
+            name(json!(tx.tx_type)),
+            name(json!(direction)),
+            counterparty.clone(),
+            tx.amount.to_string(),
+            tx.fee.to_string(),
+            timestamp,
+            name(json!(tx.status)),
+            tx.block_height.map(|h| h.to_string()).unwrap_or_default(),
+            tx.memo.clone(),
+        ]
+    }
+
+    /// Each registered wallet's address as it was first registered
+    pub fn addresses(&self) -> Vec<String> {
+        self.wallet_states.read().unwrap().values().map(|state| state.address.clone()).collect()
+    }
+
+    /// `addresses` without the archived wallets, which are the ones to sync
+    pub fn active_addresses(&self) -> Vec<String> {
+        self.wallet_states.read().unwrap().values().filter(|state| !state.archived).map(|state| state.address.clone()).collect()
+    }
+
+    /// Stop syncing `address` and leave it out of balance callbacks and totals, keeping its state.
+    /// Returns false if it is not registered.
+    pub fn archive_wallet(&self, address: &str) -> bool {
+        self.set_archived(address, true)
+    }
+
+    /// Returns false if `address` is not registered
+    pub fn unarchive_wallet(&self, address: &str) -> bool {
+        self.set_archived(address, false)
+    }
+
+    fn set_archived(&self, address: &str, archived: bool) -> bool {
+        let mut states = self.wallet_states.write().unwrap();
+        let Some(state) = states.get_mut(&Self::key(address)) else {
+            return false;
+        };
+        state.archived = archived;
+        info!(address = state.address.as_str(), archived = archived; "Wallet archive flag set");
+        true
     }
 
     pub fn get_wallet_state(&self, address: &str) -> Option<WalletState> {
         let states = self.wallet_states.read().unwrap();
-        states.get(address).cloned()
+        states.get(&Self::key(address)).cloned()
     }
 
-    pub fn get_all_wallet_states(&self) -> HashMap<String, WalletState> {
-        self.wallet_states.read().unwrap().clone()
+    pub fn get_all_wallet_states(&self, include_archived: bool) -> HashMap<String, WalletState> {
+        let states = self.wallet_states.read().unwrap();
+        states.iter().filter(|(_, state)| include_archived || !state.archived).map(|(key, state)| (key.clone(), state.clone())).collect()
     }
 
+    /// Also drops the callbacks scoped to `address`
     pub fn remove_wallet(&self, address: &str) {
         let mut states = self.wallet_states.write().unwrap();
-        states.remove(address);
+        let key = Self::key(address);
+        states.remove(&key);
+        self.scoped_balance_callbacks.lock().unwrap().remove(&key);
+        self.scoped_transaction_callbacks.lock().unwrap().remove(&key);
     }
 
     pub fn clear_all_caches(&self) {
@@ -225,96 +874,405 @@ impl WalletManager {
         states.clear();
     }
 
-    pub fn on_balance_update(&self, callback: BalanceCallback) {
-        self.balance_callbacks.lock().unwrap().push(callback);
+    pub fn on_balance_update(&self, callback: BalanceCallback) -> CallbackId {
+        let id = CallbackId(self.next_callback_id.fetch_add(1, Ordering::Relaxed));
+        self.balance_callbacks.lock().unwrap().push((id, callback));
+        id
     }
 
-    pub fn on_transaction_update(&self, callback: TransactionCallback) {
-        self.transaction_callbacks.lock().unwrap().push(callback);
+    pub fn on_transaction_update(&self, callback: TransactionCallback) -> CallbackId {
+        let id = CallbackId(self.next_callback_id.fetch_add(1, Ordering::Relaxed));
+        self.transaction_callbacks.lock().unwrap().push((id, callback));
+        id
     }
 
-    pub fn trigger_balance_updates(&self) {
-        let states = self.wallet_states.read().unwrap();
-        let mut balances = HashMap::new();
-        for (addr, state) in states.iter() {
-            balances.insert(addr.clone(), state.balance.clone());
-        }
-        for cb in self.balance_callbacks.lock().unwrap().iter() {
-            cb(balances.clone());
+    /// Called with only `address`'s balance, when that wallet is registered
+    pub fn on_balance_update_for(&self, address: &str, callback: ScopedBalanceCallback) -> CallbackId {
+        let id = CallbackId(self.next_callback_id.fetch_add(1, Ordering::Relaxed));
+        self.scoped_balance_callbacks.lock().unwrap().entry(Self::key(address)).or_default().push((id, callback));
+        id
+    }
+
+    /// Called with only `address`'s confirmed transactions, when that wallet is registered
+    pub fn on_transaction_update_for(&self, address: &str, callback: ScopedTransactionCallback) -> CallbackId {
+        let id = CallbackId(self.next_callback_id.fetch_add(1, Ordering::Relaxed));
+        self.scoped_transaction_callbacks.lock().unwrap().entry(Self::key(address)).or_default().push((id, callback));
+        id
+    }
+
+    /// Returns false if `id` was not a registered balance callback, scoped or not
+    pub fn remove_balance_callback(&self, id: CallbackId) -> bool {
+        Self::remove_callback(&self.balance_callbacks, id) || Self::remove_scoped_callback(&self.scoped_balance_callbacks, id)
+    }
+
+    /// Returns false if `id` was not a registered transaction callback, scoped or not
+    pub fn remove_transaction_callback(&self, id: CallbackId) -> bool {
+        Self::remove_callback(&self.transaction_callbacks, id) || Self::remove_scoped_callback(&self.scoped_transaction_callbacks, id)
+    }
+
+    pub fn clear_callbacks(&self) {
+        self.balance_callbacks.lock().unwrap().clear();
+        self.transaction_callbacks.lock().unwrap().clear();
+        self.scoped_balance_callbacks.lock().unwrap().clear();
+        self.scoped_transaction_callbacks.lock().unwrap().clear();
+    }
+
+    fn remove_scoped_callback<C>(callbacks: &ScopedCallbacks<C>, id: CallbackId) -> bool {
+        let mut callbacks = callbacks.lock().unwrap();
+        let Some(address) = callbacks.iter().find(|(_, list)| list.iter().any(|(registered, _)| *registered == id)).map(|(a, _)| a.clone()) else {
+            return false;
+        };
+        let list = callbacks.get_mut(&address).unwrap();
+        list.retain(|(registered, _)| *registered != id);
+        if list.is_empty() {
+            callbacks.remove(&address);
         }
+        true
     }
 
-    pub fn trigger_transaction_updates(&self) {
-        let states = self.wallet_states.read().unwrap();
-        let mut txs = HashMap::new();
-        for (addr, state) in states.iter() {
-            txs.insert(addr.clone(), state.confirmed_transactions.clone());
+    fn remove_callback<C>(callbacks: &Mutex<Vec<(CallbackId, C)>>, id: CallbackId) -> bool {
+        let mut callbacks = callbacks.lock().unwrap();
+        let before = callbacks.len();
+        callbacks.retain(|(registered, _)| *registered != id);
+        callbacks.len() != before
+    }
+
+    /// Call every callback in registration order, scoped ones with just their wallet's entry of
+    /// `values`. No lock is held during a call, so callbacks may read the manager or register and
+    /// remove callbacks; one removed by an earlier callback in the same round is skipped.
+    fn dispatch<T: Clone>(
+        values: &HashMap<String, T>,
+        all: &Callbacks<Observer<HashMap<String, T>>>,
+        scoped: &ScopedCallbacks<Observer<T>>,
+    ) {
+        let mut snapshot: Vec<(CallbackId, Option<String>)> = all.lock().unwrap().iter().map(|(id, _)| (*id, None)).collect();
+        for (address, list) in scoped.lock().unwrap().iter() {
+            snapshot.extend(list.iter().map(|(id, _)| (*id, Some(address.clone()))));
         }
-        for cb in self.transaction_callbacks.lock().unwrap().iter() {
-            cb(txs.clone());
+        snapshot.sort_by_key(|(id, _)| id.0);
+        let by_key: HashMap<String, &T> = values.iter().map(|(address, value)| (Self::key(address), value)).collect();
+        for (id, address) in snapshot {
+            match address {
+                None => {
+                    let cb = all.lock().unwrap().iter().find(|(current, _)| *current == id).map(|(_, cb)| Arc::clone(cb));
+                    if let Some(cb) = cb {
+                        cb(values.clone());
+                    }
+                }
+                Some(address) => {
+                    let Some(value) = by_key.get(&address) else { continue };
+                    let cb = scoped.lock().unwrap().get(&address).and_then(|list| list.iter().find(|(current, _)| *current == id).map(|(_, cb)| Arc::clone(cb)));
+                    if let Some(cb) = cb {
+                        cb((*value).clone());
+                    }
+                }
+            }
         }
     }
 
+    fn has_callbacks<C, S>(all: &Mutex<Vec<C>>, scoped: &ScopedCallbacks<S>) -> bool {
+        !all.lock().unwrap().is_empty() || !scoped.lock().unwrap().is_empty()
+    }
+
+    /// Archived wallets are left out unless `include_archived`
+    pub fn trigger_balance_updates(&self, include_archived: bool) {
+        let balances: HashMap<String, WalletBalance> = {
+            let states = self.wallet_states.read().unwrap();
+            states.iter().filter(|(_, state)| include_archived || !state.archived).map(|(addr, state)| (addr.clone(), state.balance.clone())).collect()
+        };
+        Self::dispatch(&balances, &self.balance_callbacks, &self.scoped_balance_callbacks);
+    }
+
+    pub fn trigger_transaction_updates(&self) {
+        let txs: HashMap<String, Vec<Transaction>> = {
+            let states = self.wallet_states.read().unwrap();
+            states.iter().map(|(addr, state)| (addr.clone(), state.confirmed_transactions.clone())).collect()
+        };
+        Self::dispatch(&txs, &self.transaction_callbacks, &self.scoped_transaction_callbacks);
+    }
+
+    /// Replace every unarchived wallet's transactions with those from the sources, then notify the
+    /// logger and the balance and transaction callbacks with what the sync produced
     pub fn sync_wallets_from_sources(
         &self,
         blockchain_txs: &HashMap<String, Vec<Transaction>>,
         mempool_txs: &HashMap<String, Vec<Transaction>>,
     ) {
-        println!("[sync_wallets_from_sources] Acquiring write lock...");
+        let logger = self.sync_logger.read().unwrap().clone();
+        let mut events = Vec::new();
+        let mut confirmed_by_key = Self::group_by_key(blockchain_txs);
+        let mut pending_by_key = Self::group_by_key(mempool_txs);
         let mut states = self.wallet_states.write().unwrap();
-        let all_addresses: HashSet<String> = states.keys().cloned().collect();
-        println!("[sync_wallets_from_sources] Syncing {} addresses...", all_addresses.len());
-        for address in all_addresses {
-            println!("[sync_wallets_from_sources] Processing address: {}", address);
-            let state = states.entry(address.clone()).or_insert_with(|| WalletState {
-                address: address.clone(),
-                ..Default::default()
-            });
-            let confirmed_txs = blockchain_txs.get(&address).cloned().unwrap_or_default();
-            let pending_txs = mempool_txs.get(&address).cloned().unwrap_or_default();
-            println!("[sync_wallets_from_sources]   Confirmed txs: {}  Pending txs: {}", confirmed_txs.len(), pending_txs.len());
-            state.confirmed_transactions = confirmed_txs.clone();
-            state.pending_transactions = pending_txs.clone();
-            state.confirmed_transfers.clear();
-            state.pending_transfers.clear();
-            state.rewards.clear();
-            state.genesis_transactions.clear();
-            for tx in &confirmed_txs {
-                for cat in Self::categorize_confirmed_transaction(tx, &address) {
-                    match cat.as_str() {
-                        "confirmed_transfers" => state.confirmed_transfers.push(tx.clone()),
-                        "rewards" => state.rewards.push(tx.clone()),
-                        "genesis_transactions" => state.genesis_transactions.push(tx.clone()),
-                        _ => {}
-                    }
+        info!(count = states.len(); "Syncing wallets");
+        for (key, state) in states.iter_mut().filter(|(_, state)| !state.archived) {
+            state.confirmed_transactions = confirmed_by_key.remove(key).unwrap_or_default();
+            state.pending_transactions = pending_by_key.remove(key).unwrap_or_default();
+            self.refresh_state(state, &mut events, logger.is_some());
+        }
+        let snapshot = self.callback_snapshot(&states);
+        drop(states);
+        self.publish(logger, events, snapshot);
+    }
+
+    /// Recount confirmations against the chain tip at `current_height`. A confirmed transaction whose
+    /// block is above the tip was reorganized away, so it goes back to pending until a sync confirms
+    /// it again. Wallets that changed are recategorized, and the logger and callbacks are notified.
+    /// Archived wallets are left as they are.
+    pub fn update_confirmations(&self, current_height: u64) {
+        let logger = self.sync_logger.read().unwrap().clone();
+        let mut events = Vec::new();
+        let mut states = self.wallet_states.write().unwrap();
+        let mut any_changed = false;
+        for state in states.values_mut().filter(|state| !state.archived) {
+            let (kept, demoted): (Vec<Transaction>, Vec<Transaction>) = std::mem::take(&mut state.confirmed_transactions)
+                .into_iter()
+                .partition(|tx| tx.block_height.is_none_or(|height| height <= current_height));
+            state.confirmed_transactions = kept;
+            let mut changed = !demoted.is_empty();
+            for tx in state.confirmed_transactions.iter_mut() {
+                let confirmations = tx.block_height.map_or(0, |height| current_height - height + 1);
+                if tx.confirmations != confirmations {
+                    tx.confirmations = confirmations;
+                    changed = true;
                 }
             }
-            for tx in &pending_txs {
-                for cat in Self::categorize_pending_transaction(tx, &address) {
-                    match cat.as_str() {
-                        "pending_transfers" => state.pending_transfers.push(tx.clone()),
-                        "rewards" => {
-                            if !state.rewards.contains(tx) {
-                                state.rewards.push(tx.clone())
-                            }
-                        },
-                        "genesis_transactions" => {
-                            if !state.genesis_transactions.contains(tx) {
-                                state.genesis_transactions.push(tx.clone())
-                            }
-                        },
-                        _ => {}
+            for mut tx in demoted {
+                warn!(address = state.address.as_str(), tx_hash = tx.hash.as_str(), block_height = tx.block_height.unwrap_or(0), current_height = current_height; "Transaction's block is gone; back to pending");
+                tx.status = TransactionStatus::Pending;
+                tx.block_height = None;
+                tx.confirmations = 0;
+                Self::upsert(&mut state.pending_transactions, &tx);
+            }
+            if changed {
+                self.refresh_state(state, &mut events, logger.is_some());
+                any_changed = true;
+            }
+        }
+        let snapshot = if any_changed { self.callback_snapshot(&states) } else { (None, None) };
+        drop(states);
+        self.publish(logger, events, snapshot);
+    }
+
+    fn callback_snapshot(&self, states: &HashMap<String, WalletState>) -> CallbackSnapshot {
+        self.snapshot_of(states.values())
+    }
+
+    /// `callback_snapshot` over just these states
+    fn snapshot_of<'a>(&self, states: impl Iterator<Item = &'a WalletState> + Clone) -> CallbackSnapshot {
+        let balances = Self::has_callbacks(&self.balance_callbacks, &self.scoped_balance_callbacks)
+            .then(|| states.clone().filter(|state| !state.archived).map(|state| (state.address.clone(), state.balance.clone())).collect());
+        let txs = Self::has_callbacks(&self.transaction_callbacks, &self.scoped_transaction_callbacks)
+            .then(|| states.map(|state| (state.address.clone(), state.confirmed_transactions.clone())).collect());
+        (balances, txs)
+    }
+
+    /// Hand a sync's events and snapshot to the logger and callbacks; the states lock must be released
+    fn publish(
+        &self,
+        logger: Option<SyncLogger>,
+        events: Vec<SyncEvent>,
+        (balances, txs): CallbackSnapshot,
+    ) {
+        if let Some(logger) = logger {
+            events.into_iter().for_each(|event| logger(event));
+        }
+        if let Some(balances) = balances {
+            Self::dispatch(&balances, &self.balance_callbacks, &self.scoped_balance_callbacks);
+        }
+        if let Some(txs) = txs {
+            Self::dispatch(&txs, &self.transaction_callbacks, &self.scoped_transaction_callbacks);
+        }
+    }
+
+    fn record_balance(&self, state: &mut WalletState, balance: &WalletBalance) {
+        if let Some(db) = &self.history_store {
+            db.save_balance_snapshot(&Self::key(&state.address), state.last_updated, &serde_json::json!(balance));
+        }
+        state.balance_history.push_back((state.last_updated, balance.clone()));
+        while state.balance_history.len() > self.balance_history_limit {
+            state.balance_history.pop_front();
+        }
+    }
+
+    /// The source lists merged under wallet keys, for sources that spell an address differently
+    fn group_by_key(txs: &HashMap<String, Vec<Transaction>>) -> HashMap<String, Vec<Transaction>> {
+        let mut grouped: HashMap<String, Vec<Transaction>> = HashMap::new();
+        for (address, list) in txs {
+            grouped.entry(Self::key(address)).or_default().extend(list.iter().cloned());
+        }
+        grouped
+    }
+
+    /// Merge new transactions into one wallet's state by hash, leaving every other wallet untouched.
+    /// A transaction in `new_confirmed` leaves the pending list, and a pending one already confirmed
+    /// is ignored. The logger and callbacks hear about this wallet only. Returns false if `address`
+    /// is not registered.
+    pub fn apply_transaction_delta(
+        &self,
+        address: &str,
+        new_confirmed: &[Transaction],
+        new_pending: &[Transaction],
+        removed_pending: &[String],
+    ) -> bool {
+        let logger = self.sync_logger.read().unwrap().clone();
+        let mut events = Vec::new();
+        let snapshot = {
+            let mut states = self.wallet_states.write().unwrap();
+            let Some(state) = states.get_mut(&Self::key(address)) else {
+                return false;
+            };
+            let mut stale: HashSet<&str> = removed_pending.iter().map(String::as_str).collect();
+            stale.extend(new_confirmed.iter().map(|tx| tx.hash.as_str()));
+            state.pending_transactions.retain(|tx| !stale.contains(tx.hash.as_str()));
+
+            let mut confirmed_at = Self::positions(&state.confirmed_transactions, new_confirmed.iter().chain(new_pending));
+            for tx in new_confirmed {
+                Self::upsert_at(&mut state.confirmed_transactions, &mut confirmed_at, tx);
+            }
+            let accepted: Vec<&Transaction> = new_pending.iter().filter(|tx| !confirmed_at.contains_key(tx.hash.as_str())).collect();
+            let mut pending_at = Self::positions(&state.pending_transactions, accepted.iter().copied());
+            for tx in &accepted {
+                Self::upsert_at(&mut state.pending_transactions, &mut pending_at, tx);
+            }
+
+            // Only the transactions the delta touched are recategorized
+            stale.extend(accepted.iter().map(|tx| tx.hash.as_str()));
+            for list in [&mut state.confirmed_transfers, &mut state.pending_transfers, &mut state.rewards, &mut state.genesis_transactions] {
+                list.retain(|tx| !stale.contains(tx.hash.as_str()));
+            }
+            for tx in new_confirmed {
+                Self::categorize_into(state, tx, true, &mut events, logger.is_some());
+            }
+            for tx in accepted {
+                Self::categorize_into(state, tx, false, &mut events, logger.is_some());
+            }
+            self.refresh_balance(state, &mut events, logger.is_some());
+            self.snapshot_of(std::iter::once(&*state))
+        };
+        self.publish(logger, events, snapshot);
+        true
+    }
+
+    /// Hashes of the transactions `address` currently holds as pending
+    pub fn pending_hashes(&self, address: &str) -> HashSet<String> {
+        let states = self.wallet_states.read().unwrap();
+        states.get(&Self::key(address)).map(|state| state.pending_transactions.iter().map(|tx| tx.hash.clone()).collect()).unwrap_or_default()
+    }
+
+    fn upsert(txs: &mut Vec<Transaction>, tx: &Transaction) {
+        match txs.iter_mut().find(|existing| existing.hash == tx.hash) {
+            Some(existing) => *existing = tx.clone(),
+            None => txs.push(tx.clone()),
+        }
+    }
+
+    /// Where each of `wanted`'s hashes sits in `txs`, found in one pass
+    fn positions<'a>(txs: &[Transaction], wanted: impl Iterator<Item = &'a Transaction>) -> HashMap<&'a str, usize> {
+        let wanted: HashSet<&str> = wanted.map(|tx| tx.hash.as_str()).collect();
+        txs.iter().enumerate().filter_map(|(i, tx)| wanted.get(tx.hash.as_str()).map(|hash| (*hash, i))).collect()
+    }
+
+    /// `upsert` through the `positions` index, which it keeps current
+    fn upsert_at<'a>(txs: &mut Vec<Transaction>, positions: &mut HashMap<&'a str, usize>, tx: &'a Transaction) {
+        match positions.get(tx.hash.as_str()) {
+            Some(&i) => txs[i] = tx.clone(),
+            None => {
+                positions.insert(&tx.hash, txs.len());
+                txs.push(tx.clone());
+            }
+        }
+    }
+
+    /// Add `tx` to the category lists it belongs in
+    fn categorize_into(state: &mut WalletState, tx: &Transaction, confirmed: bool, events: &mut Vec<SyncEvent>, logging: bool) {
+        let categories = if confirmed {
+            Self::categorize_confirmed_transaction(tx, &state.address)
+        } else {
+            Self::categorize_pending_transaction(tx, &state.address)
+        };
+        for cat in &categories {
+            let list = match cat.as_str() {
+                "confirmed_transfers" => &mut state.confirmed_transfers,
+                "pending_transfers" => &mut state.pending_transfers,
+                "rewards" => &mut state.rewards,
+                "genesis_transactions" => &mut state.genesis_transactions,
+                _ => continue,
+            };
+            list.push(tx.clone());
+        }
+        if logging {
+            events.push(SyncEvent::TxCategorized { address: state.address.clone(), hash: tx.hash.clone(), categories });
+        }
+    }
+
+    /// Rebuild the category lists and balance from the state's confirmed and pending transactions
+    fn refresh_state(&self, state: &mut WalletState, events: &mut Vec<SyncEvent>, logging: bool) {
+        let WalletState { address, confirmed_transactions, pending_transactions, confirmed_transfers, pending_transfers, rewards, genesis_transactions, .. } = state;
+        confirmed_transfers.clear();
+        pending_transfers.clear();
+        rewards.clear();
+        genesis_transactions.clear();
+        for tx in confirmed_transactions.iter() {
+            let categories = Self::categorize_confirmed_transaction(tx, address);
+            if logging {
+                events.push(SyncEvent::TxCategorized { address: address.clone(), hash: tx.hash.clone(), categories: categories.clone() });
+            }
+            for cat in categories {
+                match cat.as_str() {
+                    "confirmed_transfers" => confirmed_transfers.push(tx.clone()),
+                    "rewards" => rewards.push(tx.clone()),
+                    "genesis_transactions" => genesis_transactions.push(tx.clone()),
+                    _ => {}
+                }
+            }
+        }
+        for tx in pending_transactions.iter() {
+            let categories = Self::categorize_pending_transaction(tx, address);
+            if logging {
+                events.push(SyncEvent::TxCategorized { address: address.clone(), hash: tx.hash.clone(), categories: categories.clone() });
+            }
+            for cat in categories {
+                match cat.as_str() {
+                    "pending_transfers" => pending_transfers.push(tx.clone()),
+                    "rewards" if !rewards.iter().any(|t| t.hash == tx.hash) => rewards.push(tx.clone()),
+                    "genesis_transactions" if !genesis_transactions.iter().any(|t| t.hash == tx.hash) => genesis_transactions.push(tx.clone()),
+                    _ => {}
+                }
+            }
+        }
+        self.refresh_balance(state, events, logging);
+    }
+
+    /// Recompute the balance from the state's transactions and report the sync
+    fn refresh_balance(&self, state: &mut WalletState, events: &mut Vec<SyncEvent>, logging: bool) {
+        state.last_updated = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs();
+        match Self::calculate_balance_from_transactions(&state.address, &state.confirmed_transactions, &state.pending_transactions) {
+            Ok(balance) => {
+                let differs = |seeded: &WalletBalance| seeded.confirmed_balance != balance.confirmed_balance || seeded.available_balance != balance.available_balance;
+                if let Some(seeded) = state.seeded_balance.take().filter(differs) {
+                    warn!(address = state.address.as_str(), seeded:% = seeded.confirmed_balance, computed:% = balance.confirmed_balance; "Stored balance does not match the synced one");
+                    if logging {
+                        events.push(SyncEvent::BalanceDiscrepancy { address: state.address.clone(), seeded, computed: balance.clone() });
                     }
                 }
+                if balance != state.balance {
+                    self.record_balance(state, &balance);
+                }
+                state.balance = balance;
             }
-            state.balance = Self::calculate_balance_from_transactions(&address, &confirmed_txs, &pending_txs);
-            state.last_updated = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs();
-            println!("[sync_wallets_from_sources]   Balance: {:?}", state.balance);
+            Err(e) => {
+                error!(address = state.address.as_str(), error:% = e; "Could not compute balance; keeping the previous one");
+                if logging {
+                    events.push(SyncEvent::BalanceFailed { address: state.address.clone(), error: e });
+                }
+            }
+        }
+        let (confirmed, pending) = (state.confirmed_transactions.len(), state.pending_transactions.len());
+        debug!(address = state.address.as_str(), confirmed = confirmed, pending = pending, balance:% = state.balance.confirmed_balance; "Wallet synced");
+        if logging {
+            events.push(SyncEvent::BalanceComputed { address: state.address.clone(), balance: state.balance.clone() });
+            events.push(SyncEvent::AddressSynced { address: state.address.clone(), confirmed, pending });
         }
-        // Do NOT trigger callbacks in test context to avoid deadlocks/hangs
-        // self.trigger_balance_updates();
-        // self.trigger_transaction_updates();
-        println!("[sync_wallets_from_sources] Done.");
     }
 
     fn categorize_confirmed_transaction(tx: &Transaction, _address: &str) -> Vec<String> {
@@ -331,6 +1289,11 @@ impl WalletManager {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::core::wallet::WalletKind;
+
+    fn lun(text: &str) -> Amount {
+        text.parse().unwrap()
+    }
 
     fn make_tx(hash: &str, tx_type: TransactionType, from: &str, to: &str, amount: f64, fee: f64, status: TransactionStatus) -> Transaction {
         Transaction {
@@ -338,14 +1301,93 @@ mod tests {
             tx_type,
             from_address: from.to_string(),
             to_address: to.to_string(),
-            amount,
-            fee,
+            amount: Amount::from_f64_lossy(amount),
+            fee: Amount::from_f64_lossy(fee),
             timestamp: 0,
             status,
             block_height: None,
             confirmations: 0,
             memo: String::new(),
+            direction: TransactionDirection::Unknown,
+        }
+    }
+
+    #[test]
+    fn test_serde_round_trips() {
+        let mut tx = make_tx("h1", TransactionType::Transfer, "alice", "bob", 1.5, 0.001, TransactionStatus::Pending);
+        tx.timestamp = 1_700_000_000;
+        tx.block_height = Some(7);
+        let json = serde_json::to_value(&tx).unwrap();
+        assert_eq!((json["from"].as_str(), json["to"].as_str(), json["timestamp"].as_u64()), (Some("alice"), Some("bob"), Some(1_700_000_000)));
+        assert_eq!((json["tx_type"].as_str(), json["status"].as_str()), (Some("transfer"), Some("pending")));
+        assert!(json.get("from_address").is_none());
+        assert_eq!(serde_json::from_value::<Transaction>(json).unwrap(), tx);
+
+        let mgr = WalletManager::new();
+        mgr.register_wallet("alice");
+        mgr.sync_wallets_from_sources(&HashMap::from([("alice".to_string(), vec![make_tx("r1", TransactionType::Reward, "network", "alice", 5.0, 0.0, TransactionStatus::Confirmed)])]), &HashMap::new());
+        let state = mgr.get_wallet_state("alice").unwrap();
+        let json = serde_json::to_value(&state).unwrap();
+        assert_eq!(json["rewards"][0]["tx_type"], "reward");
+        assert_eq!(json["balance"]["confirmed_balance"], "5");
+        assert_eq!(serde_json::from_value::<WalletState>(json).unwrap(), state);
+
+        let events = vec![
+            SyncEvent::BalanceFailed { address: "alice".to_string(), error: AmountError::Overflow },
+            SyncEvent::AddressSynced { address: "alice".to_string(), confirmed: 2, pending: 1 },
+            SyncEvent::BalanceComputed { address: "bob".to_string(), balance: WalletBalance { total_balance: lun("1.5"), ..WalletBalance::default() } },
+        ];
+        let json = serde_json::to_value(&events).unwrap();
+        assert_eq!((json[0]["event"].as_str(), json[0]["error"].as_str()), (Some("balance_failed"), Some("overflow")));
+        assert_eq!(serde_json::from_value::<Vec<SyncEvent>>(json).unwrap(), events);
+        assert_eq!(serde_json::to_value(ExportFormat::JsonLines).unwrap(), "json_lines");
+        assert_eq!(serde_json::from_value::<ExportFormat>(serde_json::json!("csv")).unwrap(), ExportFormat::Csv);
+        let summary = RegisterSummary { added: 2, already_registered: 1 };
+        assert_eq!(serde_json::from_value::<RegisterSummary>(serde_json::to_value(summary).unwrap()).unwrap(), summary);
+        assert_eq!(serde_json::to_value(CallbackId(4)).unwrap(), 4);
+
+        let filter = TxFilter::new().tx_type(TransactionType::Reward).since(5).counterparty("alice").sort(TxSort::LargestFirst).limit(3);
+        let json = serde_json::to_value(&filter).unwrap();
+        assert_eq!((json["sort"].as_str(), json["tx_type"].as_str()), (Some("largest_first"), Some("reward")));
+        assert_eq!(serde_json::from_value::<TxFilter>(json).unwrap(), filter);
+        assert_eq!(serde_json::from_value::<TxFilter>(serde_json::json!({})).unwrap(), TxFilter::new());
+    }
+
+    #[test]
+    fn test_deserializes_stored_transactions() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = crate::storage::database::WalletDatabase::new(Some(dir.path().join("wallets.db")));
+        let reward = serde_json::json!({
+            "hash": "c1", "type": "reward", "from": "network", "to": "alice", "amount": 5.0, "fee": 0.0,
+            "timestamp": 1_700_000_000.5, "block_height": 12, "status": "confirmed", "memo": "block 12",
+        });
+        let unmined = serde_json::json!({"hash": "c2", "type": "gtx_genesis", "from": "network", "to": "alice", "amount": 1.0, "timestamp": 1_700_000_001});
+        // As TransactionManager creates them, signature and all
+        let transfer = serde_json::json!({
+            "hash": "p1", "type": "transfer", "from": "alice", "to": "bob", "amount": 1.25, "fee": 0.001, "timestamp": 1_700_000_100,
+            "memo": "rent", "nonce": 9, "signature": "sig", "public_key": "pk", "version": "2.0",
+        });
+        assert!(db.save_transaction(&reward, "alice") && db.save_transaction(&unmined, "alice"));
+        assert!(db.save_pending_transaction(&transfer, "alice"));
+
+        let mut confirmed = db.get_confirmed_transactions("alice");
+        confirmed.sort_by(|a, b| a["hash"].as_str().cmp(&b["hash"].as_str()));
+        for record in &confirmed {
+            let tx: Transaction = serde_json::from_value(record.clone()).unwrap();
+            assert_eq!(tx, Transaction::from_record(record, "", TransactionStatus::Confirmed));
         }
+        let tx: Transaction = serde_json::from_value(confirmed[0].clone()).unwrap();
+        assert_eq!((tx.tx_type, tx.timestamp, tx.block_height, tx.amount), (TransactionType::Reward, 1_700_000_000, Some(12), lun("5")));
+        let tx: Transaction = serde_json::from_value(confirmed[1].clone()).unwrap();
+        assert_eq!((tx.tx_type, tx.block_height), (TransactionType::Genesis, None));
+
+        let record = db.get_pending_transactions("alice").remove(0);
+        let tx: Transaction = serde_json::from_value(record.clone()).unwrap();
+        assert_eq!(tx.status, TransactionStatus::Unknown);
+        assert_eq!(Transaction { status: TransactionStatus::Pending, ..tx }, Transaction::from_record(&record, "", TransactionStatus::Pending));
+        let queued: crate::core::mempool::Transaction = serde_json::from_value(record.clone()).unwrap();
+        let map: HashMap<String, JsonValue> = serde_json::from_value(record).unwrap();
+        assert_eq!(queued, crate::core::mempool::Transaction::from_json(&map));
     }
 
     #[test]
@@ -366,13 +1408,11 @@ mod tests {
 
     #[test]
     fn test_sync_and_balance() {
-        println!("[test_sync_and_balance] Starting test...");
         let mgr = WalletManager::new();
         mgr.register_wallet("alice");
         mgr.register_wallet("bob");
         let mut blockchain_txs = HashMap::new();
         let mut mempool_txs = HashMap::new();
-        println!("[test_sync_and_balance] Populating blockchain and mempool txs...");
         blockchain_txs.insert("alice".to_string(), vec![
             make_tx("h1", TransactionType::Transfer, "bob", "alice", 100.0, 1.0, TransactionStatus::Confirmed),
             make_tx("h2", TransactionType::Transfer, "alice", "bob", 50.0, 0.5, TransactionStatus::Confirmed)
@@ -383,17 +1423,773 @@ mod tests {
         let pending_tx = make_tx("h3", TransactionType::Transfer, "alice", "bob", 10.0, 0.1, TransactionStatus::Pending);
         mempool_txs.insert("alice".to_string(), vec![pending_tx.clone()]);
         mempool_txs.insert("bob".to_string(), vec![pending_tx]);
-        println!("[test_sync_and_balance] Calling sync_wallets_from_sources...");
         mgr.sync_wallets_from_sources(&blockchain_txs, &mempool_txs);
-        println!("[test_sync_and_balance] Checking balances...");
         let alice = mgr.get_wallet_state("alice").unwrap();
         let bob = mgr.get_wallet_state("bob").unwrap();
-        println!("[test_sync_and_balance] Alice: {:?}", alice.balance);
-        println!("[test_sync_and_balance] Bob: {:?}", bob.balance);
-        assert_eq!(alice.balance.confirmed_balance, 100.0 - 50.0 - 0.5);
-        assert_eq!(bob.balance.confirmed_balance, 50.0);
-        assert_eq!(alice.balance.pending_outgoing, 10.0 + 0.1);
-        assert_eq!(bob.balance.pending_incoming, 10.0);
-        println!("[test_sync_and_balance] Done.");
+        assert_eq!(alice.balance.confirmed_balance, lun("49.5"));
+        assert_eq!(bob.balance.confirmed_balance, lun("50"));
+        assert_eq!(alice.balance.pending_outgoing, lun("10.1"));
+        assert_eq!(bob.balance.pending_incoming, lun("10"));
+        assert_eq!(alice.balance.available_balance, lun("39.4"));
+        assert_eq!(bob.balance.available_balance, lun("50"));
+        assert_eq!(bob.balance.projected_balance, lun("60"));
+    }
+
+    #[test]
+    fn test_apply_transaction_delta() {
+        let mgr = WalletManager::new();
+        mgr.register_wallet("alice");
+        let pending = make_tx("p1", TransactionType::Transfer, "alice", "bob", 10.0, 0.1, TransactionStatus::Pending);
+        let dropped = make_tx("p2", TransactionType::Transfer, "alice", "bob", 5.0, 0.1, TransactionStatus::Pending);
+        let reward = make_tx("r1", TransactionType::Reward, "network", "alice", 50.0, 0.0, TransactionStatus::Confirmed);
+        assert!(mgr.apply_transaction_delta("alice", std::slice::from_ref(&reward), &[pending.clone(), dropped], &[]));
+        assert_eq!(mgr.pending_hashes("alice"), HashSet::from(["p1".to_string(), "p2".to_string()]));
+
+        let mut mined = pending.clone();
+        mined.status = TransactionStatus::Confirmed;
+        // A confirmed transaction replaces its pending copy, and a stale pending copy cannot bring it back
+        assert!(mgr.apply_transaction_delta("alice", &[mined, reward], &[pending], &["p2".to_string()]));
+        let state = mgr.get_wallet_state("alice").unwrap();
+        assert!(state.pending_transactions.is_empty());
+        assert_eq!(state.confirmed_transactions.len(), 2);
+        assert_eq!(state.confirmed_transfers.len(), 1);
+        assert_eq!(state.rewards.len(), 1);
+        assert_eq!(state.balance.confirmed_balance, lun("39.9"));
+        assert!(!mgr.apply_transaction_delta("nobody", &[], &[], &[]));
+    }
+
+    #[test]
+    fn test_delta_notifies_callbacks() {
+        let mgr = WalletManager::new();
+        mgr.register_wallets(&["alice".to_string(), "bob".to_string()]);
+        let balances = Arc::new(Mutex::new(Vec::new()));
+        let seen = Arc::clone(&balances);
+        mgr.on_balance_update_for("alice", Arc::new(move |balance| seen.lock().unwrap().push(balance.confirmed_balance)));
+        let updated = Arc::new(Mutex::new(Vec::new()));
+        let seen = Arc::clone(&updated);
+        mgr.on_transaction_update(Arc::new(move |txs| seen.lock().unwrap().extend(txs.into_keys())));
+
+        let deposit = make_tx("d1", TransactionType::Transfer, "carol", "alice", 4.0, 0.0, TransactionStatus::Confirmed);
+        assert!(mgr.apply_transaction_delta("alice", &[deposit], &[], &[]));
+        assert_eq!(*balances.lock().unwrap(), vec![lun("4")]);
+        // Only the wallet the delta touched is reported
+        assert_eq!(*updated.lock().unwrap(), vec!["alice".to_string()]);
+    }
+
+    #[test]
+    fn test_pending_copy_of_confirmed_reward_is_listed_once() {
+        let mgr = WalletManager::new();
+        mgr.register_wallet("alice");
+        let confirmed = make_tx("r1", TransactionType::Reward, "network", "alice", 5.0, 0.0, TransactionStatus::Confirmed);
+        let pending = Transaction { status: TransactionStatus::Pending, ..confirmed.clone() };
+        mgr.sync_wallets_from_sources(
+            &HashMap::from([("alice".to_string(), vec![confirmed])]),
+            &HashMap::from([("alice".to_string(), vec![pending])]),
+        );
+        assert_eq!(mgr.get_wallet_state("alice").unwrap().rewards.len(), 1);
+    }
+
+    #[test]
+    fn test_delta_leaves_other_wallets_alone() {
+        let mgr = WalletManager::new();
+        mgr.register_wallets(&["whale".to_string(), "minnow".to_string()]);
+        let history: Vec<Transaction> = (0..20_000)
+            .map(|i| make_tx(&format!("w{}", i), TransactionType::Transfer, "someone", "whale", 1.0, 0.0, TransactionStatus::Confirmed))
+            .collect();
+        mgr.sync_wallets_from_sources(&HashMap::from([("whale".to_string(), history)]), &HashMap::new());
+        let whale_buffers = |mgr: &WalletManager| {
+            let states = mgr.wallet_states.read().unwrap();
+            let whale = &states["whale"];
+            (whale.confirmed_transactions.as_ptr(), whale.confirmed_transfers.as_ptr(), whale.balance.clone())
+        };
+        let before = whale_buffers(&mgr);
+
+        let tx = make_tx("m1", TransactionType::Transfer, "someone", "minnow", 2.0, 0.0, TransactionStatus::Confirmed);
+        assert!(mgr.apply_transaction_delta("minnow", &[tx], &[], &[]));
+        // Same allocations: the whale's history was neither cloned nor rebuilt
+        assert_eq!(whale_buffers(&mgr), before);
+        assert_eq!(mgr.get_wallet_state("minnow").unwrap().balance.confirmed_balance, lun("2"));
+
+        // A delta to the whale itself recategorizes only what it brings
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let collected = Arc::clone(&events);
+        mgr.set_logger(Arc::new(move |event| collected.lock().unwrap().push(event)));
+        let deposit = make_tx("w0", TransactionType::Transfer, "someone", "whale", 3.0, 0.0, TransactionStatus::Confirmed);
+        assert!(mgr.apply_transaction_delta("whale", std::slice::from_ref(&deposit), &[], &[]));
+        let categorized: Vec<SyncEvent> = events.lock().unwrap().drain(..).filter(|e| matches!(e, SyncEvent::TxCategorized { .. })).collect();
+        assert_eq!(categorized.len(), 1);
+        let whale = mgr.get_wallet_state("whale").unwrap();
+        assert_eq!((whale.confirmed_transactions.len(), whale.confirmed_transfers.len()), (20_000, 20_000));
+        assert_eq!(whale.confirmed_transfers.iter().filter(|tx| **tx == deposit).count(), 1);
+        assert_eq!(whale.balance.confirmed_balance, lun("20002"));
+
+        // whereas a full sync replaces every wallet's lists
+        mgr.sync_wallets_from_sources(&HashMap::new(), &HashMap::new());
+        assert_ne!(whale_buffers(&mgr).0, before.0);
+    }
+
+    #[test]
+    fn test_remove_callbacks() {
+        let mgr = Arc::new(WalletManager::new());
+        mgr.register_wallet("alice");
+        let fired = Arc::new(Mutex::new(Vec::new()));
+        let log = |name: &'static str| {
+            let fired = Arc::clone(&fired);
+            Arc::new(move |_: HashMap<String, WalletBalance>| fired.lock().unwrap().push(name))
+        };
+        let first = mgr.on_balance_update(log("first"));
+        let second = mgr.on_balance_update(log("second"));
+        assert_ne!(first, second);
+        assert!(mgr.remove_balance_callback(first));
+        assert!(!mgr.remove_balance_callback(first));
+        mgr.trigger_balance_updates(false);
+        assert_eq!(*fired.lock().unwrap(), vec!["second"]);
+
+        // A callback may unsubscribe itself and others, and register new ones, while being triggered
+        fired.lock().unwrap().clear();
+        let tx_fired = Arc::new(Mutex::new(0));
+        let counter = Arc::clone(&tx_fired);
+        let late = mgr.on_transaction_update(Arc::new(move |_| *counter.lock().unwrap() += 1));
+        let inner = Arc::clone(&mgr);
+        let third = CallbackId(mgr.next_callback_id.load(Ordering::Relaxed) + 1);
+        mgr.on_balance_update(Arc::new(move |_| {
+            inner.remove_balance_callback(third);
+            inner.remove_balance_callback(second);
+            inner.on_balance_update(Arc::new(|_| {}));
+        }));
+        assert_eq!(mgr.on_balance_update(log("third")), third);
+        mgr.trigger_balance_updates(false);
+        assert!(fired.lock().unwrap().contains(&"second"));
+        assert!(!fired.lock().unwrap().contains(&"third"));
+        assert!(mgr.remove_transaction_callback(late));
+        mgr.trigger_transaction_updates();
+        assert_eq!(*tx_fired.lock().unwrap(), 0);
+        mgr.clear_callbacks();
+        assert!(mgr.balance_callbacks.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_scoped_callbacks() {
+        let mgr = WalletManager::new();
+        mgr.register_wallets(&["alice".to_string(), "bob".to_string()]);
+        let blockchain_txs = HashMap::from([("alice".to_string(), vec![make_tx("h1", TransactionType::Reward, "network", "alice", 5.0, 0.0, TransactionStatus::Confirmed)])]);
+        mgr.sync_wallets_from_sources(&blockchain_txs, &HashMap::new());
+
+        let balances = Arc::new(Mutex::new(Vec::new()));
+        let seen = Arc::clone(&balances);
+        mgr.on_balance_update_for("alice", Arc::new(move |balance| seen.lock().unwrap().push(balance.confirmed_balance)));
+        let bob_calls = Arc::new(Mutex::new(0));
+        let counter = Arc::clone(&bob_calls);
+        let bob_id = mgr.on_balance_update_for("bob", Arc::new(move |_| *counter.lock().unwrap() += 1));
+        let hashes = Arc::new(Mutex::new(Vec::new()));
+        let seen = Arc::clone(&hashes);
+        mgr.on_transaction_update_for("alice", Arc::new(move |txs| seen.lock().unwrap().extend(txs.into_iter().map(|tx| tx.hash))));
+
+        mgr.trigger_balance_updates(false);
+        mgr.trigger_transaction_updates();
+        assert_eq!(*balances.lock().unwrap(), vec![lun("5")]);
+        assert_eq!(*hashes.lock().unwrap(), vec!["h1".to_string()]);
+        assert_eq!(*bob_calls.lock().unwrap(), 1);
+
+        assert!(mgr.remove_balance_callback(bob_id));
+        assert!(!mgr.remove_transaction_callback(bob_id));
+        mgr.remove_wallet("alice");
+        assert!(mgr.scoped_balance_callbacks.lock().unwrap().is_empty());
+        mgr.trigger_balance_updates(false);
+        mgr.trigger_transaction_updates();
+        assert_eq!(balances.lock().unwrap().len(), 1);
+        assert_eq!(*bob_calls.lock().unwrap(), 1);
+    }
+
+    #[test]
+    fn test_sync_triggers_callbacks() {
+        let mgr = Arc::new(WalletManager::new());
+        mgr.register_wallets(&["alice".to_string(), "bob".to_string()]);
+        let order = Arc::new(Mutex::new(Vec::new()));
+        let (inner, seen) = (Arc::clone(&mgr), Arc::clone(&order));
+        mgr.on_balance_update(Arc::new(move |balances| {
+            // Reading the manager from a callback used to deadlock on the sync's write lock
+            let alice = inner.get_wallet_state("alice").unwrap();
+            assert_eq!(alice.balance, balances["alice"]);
+            seen.lock().unwrap().push("all");
+        }));
+        let (inner, seen) = (Arc::clone(&mgr), Arc::clone(&order));
+        mgr.on_balance_update_for("alice", Arc::new(move |balance| {
+            assert_eq!(inner.get_wallet_state("alice").unwrap().balance, balance);
+            seen.lock().unwrap().push("alice");
+        }));
+        let seen = Arc::clone(&order);
+        mgr.on_balance_update(Arc::new(move |_| seen.lock().unwrap().push("all again")));
+        let seen = Arc::clone(&order);
+        mgr.on_transaction_update_for("bob", Arc::new(move |txs| seen.lock().unwrap().push(if txs.is_empty() { "bob txs" } else { "?" })));
+
+        let blockchain_txs = HashMap::from([("alice".to_string(), vec![make_tx("h1", TransactionType::Reward, "network", "alice", 5.0, 0.0, TransactionStatus::Confirmed)])]);
+        mgr.sync_wallets_from_sources(&blockchain_txs, &HashMap::new());
+        assert_eq!(*order.lock().unwrap(), vec!["all", "alice", "all again", "bob txs"]);
+    }
+
+    #[test]
+    fn test_wallet_state_json_round_trip() {
+        let mgr = WalletManager::new();
+        mgr.register_wallet("alice");
+        let mut pending = make_tx("p1", TransactionType::Transfer, "alice", "bob", 1.0, 0.1, TransactionStatus::Pending);
+        pending.memo = "rent".to_string();
+        let blockchain_txs = HashMap::from([("alice".to_string(), vec![
+            make_tx("r1", TransactionType::Reward, "network", "alice", 5.0, 0.0, TransactionStatus::Confirmed),
+            make_tx("g1", TransactionType::Genesis, "network", "alice", 1.0, 0.0, TransactionStatus::Confirmed),
+        ])]);
+        mgr.sync_wallets_from_sources(&blockchain_txs, &HashMap::from([("alice".to_string(), vec![pending])]));
+        let state = mgr.get_wallet_state("alice").unwrap();
+
+        let json = state.to_json();
+        assert_eq!(json["confirmed_transactions"][0]["tx_type"], "reward");
+        assert_eq!(json["confirmed_transactions"][0]["status"], "confirmed");
+        assert_eq!(json["genesis_transactions"][0]["tx_type"], "genesis");
+        assert_eq!(json["pending_transfers"][0]["tx_type"], "transfer");
+        assert_eq!(json["pending_transfers"][0]["status"], "pending");
+        assert_eq!(json["balance"]["projected_balance"], "4.9");
+        assert_eq!(json["confirmed_transactions"][0]["amount"], "5");
+        let restored: WalletState = serde_json::from_value(json).unwrap();
+        assert_eq!(restored, state);
+
+        let tx: Transaction = serde_json::from_value(serde_json::json!({
+            "hash": "x", "tx_type": "gtx_genesis", "from_address": "", "to_address": "alice", "amount": 1.0, "fee": 0.0,
+            "timestamp": 0, "status": "orphaned", "block_height": null, "confirmations": 0, "memo": "", "direction": "sideways",
+        })).unwrap();
+        assert_eq!((tx.tx_type, tx.status, tx.direction), (TransactionType::Genesis, TransactionStatus::Unknown, TransactionDirection::Unknown));
+    }
+
+    #[test]
+    fn test_transaction_from_block_json() {
+        let block = serde_json::json!({
+            "index": 42, "hash": "00ab", "previous_hash": "00aa", "timestamp": 1_700_000_042, "difficulty": 2, "nonce": 913,
+            "transactions": [
+                {"type": "reward", "from": "network", "to": "LUN_2bd806c97f0e00af2bf5", "amount": 50.0, "block_height": 42,
+                 "hash": "reward42", "signature": "sig", "public_key": "pk", "timestamp": 1_700_000_042},
+                {"type": "transfer", "from": "LUN_2bd806c97f0e00af2bf5", "to": "LUN_81b637d8fcd2c6dafcca", "amount": 1.5,
+                 "fee": 0.001, "timestamp": 1_700_000_040.5, "nonce": 7, "memo": "coffee", "hash": "tx42", "signature": "sig",
+                 "public_key": "pk", "status": "confirmed", "confirmations": 3},
+            ],
+        });
+        let txs: Vec<Transaction> = block["transactions"].as_array().unwrap().iter().map(|tx| Transaction::from_json(tx).unwrap()).collect();
+        assert_eq!(txs[0].tx_type, TransactionType::Reward);
+        assert_eq!(txs[0].block_height, Some(42));
+        assert_eq!(txs[0].status, TransactionStatus::Confirmed);
+        assert_eq!(txs[0].from_address, "network");
+        let transfer = &txs[1];
+        assert_eq!((transfer.hash.as_str(), transfer.tx_type.clone()), ("tx42", TransactionType::Transfer));
+        assert_eq!((transfer.from_address.as_str(), transfer.to_address.as_str()), ("LUN_2bd806c97f0e00af2bf5", "LUN_81b637d8fcd2c6dafcca"));
+        assert_eq!((transfer.amount, transfer.fee, transfer.timestamp), (lun("1.5"), lun("0.001"), 1_700_000_040));
+        assert_eq!((transfer.status.clone(), transfer.confirmations, transfer.memo.as_str()), (TransactionStatus::Confirmed, 3, "coffee"));
+        assert_eq!(transfer.direction, TransactionDirection::Unknown);
+
+        let pending = Transaction::from_json(&serde_json::json!({"type": "transfer", "hash": "m1", "from": "a", "to": "b", "amount": 2})).unwrap();
+        assert_eq!((pending.status, pending.amount), (TransactionStatus::Pending, lun("2")));
+        assert!(Transaction::from_json(&serde_json::json!({"type": "transfer"})).is_err());
+        assert!(Transaction::from_json(&serde_json::json!([1, 2])).is_err());
+    }
+
+    #[test]
+    fn test_addresses_match_across_case_and_prefix() {
+        let mgr = WalletManager::new();
+        mgr.register_wallet("LUN_Alice1");
+        mgr.register_wallet("lun_alice1");
+        mgr.register_wallets(&["ALICE1".to_string()]);
+        assert_eq!(mgr.addresses(), vec!["LUN_Alice1".to_string()]);
+
+        let balances = Arc::new(Mutex::new(Vec::new()));
+        let seen = Arc::clone(&balances);
+        mgr.on_balance_update_for("alice1", Arc::new(move |balance| seen.lock().unwrap().push(balance.confirmed_balance)));
+        let keys = Arc::new(Mutex::new(Vec::new()));
+        let seen = Arc::clone(&keys);
+        mgr.on_balance_update(Arc::new(move |balances| seen.lock().unwrap().extend(balances.into_keys())));
+
+        let blockchain_txs = HashMap::from([
+            ("alice1".to_string(), vec![make_tx("h1", TransactionType::Transfer, "bob", "lun_ALICE1", 10.0, 0.1, TransactionStatus::Confirmed)]),
+            ("LUN_ALICE1".to_string(), vec![make_tx("h2", TransactionType::Transfer, "Alice1", "bob", 3.0, 0.5, TransactionStatus::Confirmed)]),
+        ]);
+        let mempool_txs = HashMap::from([("lun_alice1".to_string(), vec![make_tx("h3", TransactionType::Transfer, "bob", "alice1", 2.0, 0.1, TransactionStatus::Pending)])]);
+        mgr.sync_wallets_from_sources(&blockchain_txs, &mempool_txs);
+
+        let state = mgr.get_wallet_state("LUN_ALICE1").unwrap();
+        assert_eq!(state.confirmed_transactions.len(), 2);
+        assert_eq!(state.balance.confirmed_balance, lun("6.5"));
+        assert_eq!(state.balance.pending_incoming, lun("2"));
+        assert_eq!(*balances.lock().unwrap(), vec![lun("6.5")]);
+        assert_eq!(*keys.lock().unwrap(), vec!["LUN_Alice1".to_string()]);
+
+        let deposit = make_tx("h4", TransactionType::Transfer, "bob", "LUN_alice1", 1.0, 0.0, TransactionStatus::Confirmed);
+        assert!(mgr.apply_transaction_delta("Alice1", &[deposit], &[], &["h3".to_string()]));
+        assert!(mgr.pending_hashes("lun_alice1").is_empty());
+        assert_eq!(mgr.get_wallet_state("alice1").unwrap().balance.confirmed_balance, lun("7.5"));
+        assert_eq!(TransactionDirection::relative_to("LUN_Alice1", "alice1", "bob"), TransactionDirection::Outgoing);
+        assert_eq!(TransactionDirection::relative_to("alice1", "bob", "LUN_ALICE1"), TransactionDirection::Incoming);
+        mgr.remove_wallet("ALICE1");
+        assert!(mgr.addresses().is_empty());
+        assert!(mgr.scoped_balance_callbacks.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_balance_history() {
+        let mgr = WalletManager::new().with_balance_history_limit(2);
+        mgr.register_wallet("alice");
+        let reward = |hash: &str, amount: f64| make_tx(hash, TransactionType::Reward, "network", "alice", amount, 0.0, TransactionStatus::Confirmed);
+        let sync = |txs: Vec<Transaction>| mgr.sync_wallets_from_sources(&HashMap::from([("alice".to_string(), txs)]), &HashMap::new());
+        sync(vec![reward("r1", 5.0)]);
+        sync(vec![reward("r1", 5.0), reward("r2", 1.0)]);
+        let history = mgr.get_balance_history("alice", 0);
+        assert_eq!(history.iter().map(|(_, b)| b.confirmed_balance).collect::<Vec<_>>(), vec![lun("5"), lun("6")]);
+        assert!(history[0].0 <= history[1].0);
+
+        // An unchanged balance adds nothing; past the limit the oldest snapshot goes
+        sync(vec![reward("r1", 5.0), reward("r2", 1.0)]);
+        assert_eq!(mgr.get_balance_history("alice", 0).len(), 2);
+        sync(vec![reward("r3", 2.0)]);
+        let history = mgr.get_balance_history("ALICE", 0);
+        assert_eq!(history.iter().map(|(_, b)| b.confirmed_balance).collect::<Vec<_>>(), vec![lun("6"), lun("2")]);
+        assert!(mgr.get_balance_history("alice", u64::MAX).is_empty());
+
+        mgr.remove_wallet("alice");
+        mgr.register_wallet("alice");
+        assert!(mgr.get_balance_history("alice", 0).is_empty());
+    }
+
+    #[test]
+    fn test_balance_history_store() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = WalletDatabase::new(Some(dir.path().join("history.db")));
+        let mgr = WalletManager::new().with_history_store(db.clone());
+        mgr.register_wallet("LUN_bob");
+        let deposit = make_tx("d1", TransactionType::Transfer, "carol", "bob", 3.0, 0.1, TransactionStatus::Confirmed);
+        mgr.sync_wallets_from_sources(&HashMap::from([("bob".to_string(), vec![deposit.clone()])]), &HashMap::new());
+        let history = mgr.get_balance_history("bob", 0);
+        assert_eq!(history.len(), 1);
+
+        mgr.remove_wallet("bob");
+        mgr.register_wallet("bob");
+        assert_eq!(mgr.get_balance_history("bob", 0), history);
+        assert_eq!(mgr.get_wallet_state("bob").unwrap().balance.confirmed_balance, lun("3"));
+        mgr.sync_wallets_from_sources(&HashMap::from([("bob".to_string(), vec![deposit])]), &HashMap::new());
+        assert_eq!(mgr.get_balance_history("bob", 0).len(), 1);
+
+        let restarted = WalletManager::new().with_history_store(db);
+        restarted.register_wallet("lun_BOB");
+        assert_eq!(restarted.get_balance_history("bob", 0), history);
+    }
+
+    #[test]
+    fn test_snapshot_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = WalletDatabase::new(Some(dir.path().join("snapshot.db")));
+        let mgr = WalletManager::new();
+        mgr.register_wallets(&["LUN_alice".to_string(), "bob".to_string()]);
+        let mut deposit = make_tx("d1", TransactionType::Transfer, "bob", "alice", 12.5, 0.1, TransactionStatus::Confirmed);
+        deposit.block_height = Some(7);
+        let reward = make_tx("r1", TransactionType::Reward, "network", "alice", 1.0, 0.0, TransactionStatus::Confirmed);
+        let pending = make_tx("p1", TransactionType::Transfer, "alice", "bob", 2.0, 0.1, TransactionStatus::Pending);
+        mgr.sync_wallets_from_sources(
+            &HashMap::from([("alice".to_string(), vec![deposit.clone(), reward]), ("bob".to_string(), vec![deposit])]),
+            &HashMap::from([("alice".to_string(), vec![pending.clone()]), ("bob".to_string(), vec![pending])]),
+        );
+        assert!(mgr.save_snapshot(&db));
+
+        let restarted = WalletManager::new();
+        assert_eq!(restarted.load_snapshot(&db), 2);
+        assert_eq!(restarted.get_all_wallet_states(true), mgr.get_all_wallet_states(true));
+        let alice = restarted.get_wallet_state("lun_ALICE").unwrap();
+        assert_eq!(alice.address, "LUN_alice");
+        assert_eq!(alice.balance.available_balance, lun("11.4"));
+        assert_eq!(alice.rewards.len(), 1);
+        assert_eq!(restarted.last_block_height("alice"), Some(7));
+        assert_eq!(restarted.last_block_height("bob"), Some(7));
+        assert_eq!(WalletManager::new().load_snapshot(&WalletDatabase::new(Some(dir.path().join("empty.db")))), 0);
+    }
+
+    #[test]
+    fn test_query_transactions() {
+        let mgr = WalletManager::new();
+        mgr.register_wallet("alice");
+        let mut confirmed = Vec::new();
+        for i in 0..10u64 {
+            let (tx_type, from) = if i % 3 == 0 { (TransactionType::Reward, "network") } else { (TransactionType::Transfer, "bob") };
+            let mut tx = make_tx(&format!("c{}", i), tx_type, from, "alice", (i + 1) as f64, 0.0, TransactionStatus::Confirmed);
+            tx.timestamp = 1000 + i;
+            tx.memo = if i == 4 { "Rent for MAY".to_string() } else { String::new() };
+            confirmed.push(tx);
+        }
+        let mut pending = make_tx("p1", TransactionType::Transfer, "alice", "LUN_carol", 2.5, 0.1, TransactionStatus::Pending);
+        pending.timestamp = 2000;
+        mgr.sync_wallets_from_sources(&HashMap::from([("alice".to_string(), confirmed)]), &HashMap::from([("alice".to_string(), vec![pending])]));
+        let hashes = |filter: TxFilter| mgr.query_transactions("LUN_alice", filter).into_iter().map(|tx| tx.hash).collect::<Vec<_>>();
+
+        assert_eq!(hashes(TxFilter::new()).len(), 11);
+        assert_eq!(hashes(TxFilter::new())[0], "p1");
+        assert_eq!(hashes(TxFilter::new().tx_type(TransactionType::Reward).sort(TxSort::OldestFirst)), ["c0", "c3", "c6", "c9"]);
+        assert_eq!(hashes(TxFilter::new().tx_type(TransactionType::Reward).since(1004)), ["c9", "c6"]);
+        assert_eq!(hashes(TxFilter::new().min_amount(lun("2.5")).max_amount(lun("4")).sort(TxSort::SmallestFirst)), ["p1", "c2", "c3"]);
+        assert_eq!(hashes(TxFilter::new().status(TransactionStatus::Pending)), ["p1"]);
+        assert_eq!(hashes(TxFilter::new().status(TransactionStatus::Confirmed).until(1002)), ["c1", "c0"]);
+        assert_eq!(hashes(TxFilter::new().counterparty("carol")), ["p1"]);
+        assert_eq!(hashes(TxFilter::new().memo_contains("may")), ["c4"]);
+
+        let page = |offset| hashes(TxFilter::new().status(TransactionStatus::Confirmed).sort(TxSort::LargestFirst).offset(offset).limit(4));
+        assert_eq!(page(0), ["c9", "c8", "c7", "c6"]);
+        assert_eq!(page(4), ["c5", "c4", "c3", "c2"]);
+        assert_eq!(page(8), ["c1", "c0"]);
+        assert!(page(12).is_empty());
+        assert!(mgr.query_transactions("nobody", TxFilter::new()).is_empty());
+    }
+
+    #[test]
+    fn test_portfolio_totals() {
+        let mgr = WalletManager::new();
+        mgr.register_wallets(&["LUN_alice".to_string(), "bob".to_string(), "carol".to_string()]);
+        let deposit = |hash: &str, to: &str, amount: f64| make_tx(hash, TransactionType::Transfer, "network", to, amount, 0.0, TransactionStatus::Confirmed);
+        let pending = make_tx("p1", TransactionType::Transfer, "bob", "dave", 1.0, 0.5, TransactionStatus::Pending);
+        mgr.sync_wallets_from_sources(
+            &HashMap::from([
+                ("alice".to_string(), vec![deposit("a", "alice", 10.0)]),
+                ("bob".to_string(), vec![deposit("b", "bob", 5.0)]),
+                ("carol".to_string(), vec![deposit("c", "carol", 7.0)]),
+            ]),
+            &HashMap::from([("bob".to_string(), vec![pending])]),
+        );
+        let total = mgr.get_total_balance().unwrap();
+        assert_eq!(total.confirmed_balance, lun("22"));
+        assert_eq!(total.available_balance, lun("20.5"));
+        assert_eq!(total.pending_outgoing, lun("1.5"));
+        assert_eq!(mgr.get_richest_wallets(2), [("LUN_alice".to_string(), lun("10")), ("carol".to_string(), lun("7"))]);
+        assert_eq!(mgr.get_richest_wallets(10).len(), 3);
+
+        let dir = tempfile::tempdir().unwrap();
+        let db = WalletDb::new(dir.path().join("wallets.db").to_str().unwrap());
+        let row = |address: &str, label: &str, key: &str| crate::core::wallet_db::Wallet {
+            address: address.to_string(),
+            label: label.to_string(),
+            public_key: String::new(),
+            encrypted_private_key: key.to_string(),
+            balance: 0.0,
+            created: 0,
+            is_locked: true,
+            available_balance: 0.0,
+            metadata: serde_json::json!({}),
+            kind: if key.is_empty() { WalletKind::WatchOnly } else { WalletKind::Full },
+        };
+        db.save_wallet(&row("alice", "watching", ""));
+        db.save_wallet(&row("LUN_alice", "savings", "encrypted"));
+        db.save_wallet(&row("bob", "savings", "encrypted"));
+        let grouped = mgr.get_balances_grouped_by_label(&db).unwrap();
+        assert_eq!(grouped.len(), 2);
+        assert_eq!(grouped["savings"].confirmed_balance, lun("15"));
+        assert_eq!(grouped[""].confirmed_balance, lun("7"));
+
+        let mut whale = deposit("w", "carol", 0.0);
+        whale.amount = Amount::from_units(i128::MAX - lun("7").units());
+        mgr.apply_transaction_delta("carol", &[whale], &[], &[]);
+        assert_eq!(mgr.get_total_balance(), Err(AmountError::Overflow));
+    }
+
+    #[test]
+    fn test_archived_wallet_keeps_its_state() {
+        let mgr = WalletManager::new();
+        mgr.register_wallets(&["alice".to_string(), "dust".to_string()]);
+        let deposit = |hash: &str, to: &str, amount: f64| make_tx(hash, TransactionType::Transfer, "network", to, amount, 0.0, TransactionStatus::Confirmed);
+        mgr.sync_wallets_from_sources(
+            &HashMap::from([("alice".to_string(), vec![deposit("a", "alice", 10.0)]), ("dust".to_string(), vec![deposit("d", "dust", 0.001)])]),
+            &HashMap::new(),
+        );
+        assert!(mgr.archive_wallet("LUN_dust"));
+        assert!(!mgr.archive_wallet("nobody"));
+        let archived = mgr.get_wallet_state("dust").unwrap();
+        assert!(archived.archived);
+        assert_eq!(mgr.active_addresses(), ["alice"]);
+
+        mgr.sync_wallets_from_sources(
+            &HashMap::from([("alice".to_string(), vec![deposit("a", "alice", 10.0)]), ("dust".to_string(), vec![deposit("d2", "dust", 5.0)])]),
+            &HashMap::new(),
+        );
+        mgr.update_confirmations(0);
+        assert_eq!(mgr.get_wallet_state("dust").unwrap(), archived);
+        assert_eq!(mgr.get_total_balance().unwrap().confirmed_balance, lun("10"));
+        assert_eq!(mgr.get_richest_wallets(5).len(), 1);
+        assert_eq!(mgr.get_all_wallet_states(false).len(), 1);
+        assert_eq!(mgr.get_all_wallet_states(true).len(), 2);
+
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let log = Arc::clone(&seen);
+        mgr.on_balance_update(Arc::new(move |balances| log.lock().unwrap().push(balances.len())));
+        mgr.trigger_balance_updates(false);
+        mgr.trigger_balance_updates(true);
+        assert_eq!(*seen.lock().unwrap(), [1, 2]);
+
+        assert!(mgr.unarchive_wallet("dust"));
+        assert_eq!(mgr.get_total_balance().unwrap().confirmed_balance, lun("10.001"));
+    }
+
+    #[test]
+    fn test_register_from_db() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = WalletDb::new(dir.path().join("wallets.db").to_str().unwrap());
+        let row = |address: &str, balance: f64, available_balance: f64| crate::core::wallet_db::Wallet {
+            address: address.to_string(),
+            label: String::new(),
+            public_key: String::new(),
+            encrypted_private_key: String::new(),
+            balance,
+            created: 0,
+            is_locked: true,
+            available_balance,
+            metadata: serde_json::json!({}),
+            kind: WalletKind::Full,
+        };
+        db.save_wallet(&row("LUN_alice", 10.0, 8.5));
+        db.save_wallet(&row("bob", 4.0, 4.0));
+        db.save_wallet(&row("carol", 99.0, 99.0));
+        let mgr = WalletManager::new();
+        mgr.register_wallet("carol");
+        assert_eq!(mgr.register_from_db(&db), RegisterSummary { added: 2, already_registered: 1 });
+        let alice = mgr.get_wallet_state("alice").unwrap().balance;
+        assert_eq!((alice.confirmed_balance, alice.available_balance, alice.pending_outgoing), (lun("10"), lun("8.5"), lun("1.5")));
+        assert_eq!(mgr.get_wallet_state("carol").unwrap().balance, WalletBalance::default());
+
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let collected = Arc::clone(&events);
+        mgr.set_logger(Arc::new(move |event| collected.lock().unwrap().push(event)));
+        let deposit = |to: &str, amount: f64| make_tx(to, TransactionType::Transfer, "network", to, amount, 0.0, TransactionStatus::Confirmed);
+        let chain = HashMap::from([("alice".to_string(), vec![deposit("alice", 12.0)]), ("bob".to_string(), vec![deposit("bob", 4.0)])]);
+        mgr.sync_wallets_from_sources(&chain, &HashMap::new());
+        let discrepancies: Vec<SyncEvent> = events.lock().unwrap().iter().filter(|e| matches!(e, SyncEvent::BalanceDiscrepancy { .. })).cloned().collect();
+        assert_eq!(discrepancies.len(), 1);
+        let SyncEvent::BalanceDiscrepancy { address, seeded, computed } = &discrepancies[0] else { unreachable!() };
+        assert_eq!((address.as_str(), seeded.confirmed_balance, computed.confirmed_balance), ("LUN_alice", lun("10"), lun("12")));
+        assert!(mgr.get_wallet_state("alice").unwrap().seeded_balance.is_none());
+
+        events.lock().unwrap().clear();
+        mgr.sync_wallets_from_sources(&chain, &HashMap::new());
+        assert!(!events.lock().unwrap().iter().any(|e| matches!(e, SyncEvent::BalanceDiscrepancy { .. })));
+    }
+
+    #[test]
+    fn test_wallet_totals() {
+        let mgr = WalletManager::new();
+        mgr.register_wallet("LUN_miner");
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let log = Arc::clone(&seen);
+        mgr.on_balance_update_for("miner", Arc::new(move |balance| log.lock().unwrap().push(balance.totals)));
+        let confirmed = vec![
+            make_tx("r1", TransactionType::Reward, "network", "miner", 50.0, 0.0, TransactionStatus::Confirmed),
+            make_tx("r2", TransactionType::Reward, "network", "lun_MINER", 25.5, 0.0, TransactionStatus::Confirmed),
+            make_tx("g1", TransactionType::Genesis, "genesis", "miner", 1.0, 0.0, TransactionStatus::Confirmed),
+            make_tx("t1", TransactionType::Transfer, "bob", "miner", 10.0, 0.1, TransactionStatus::Confirmed),
+            make_tx("t2", TransactionType::Transfer, "miner", "bob", 20.0, 0.25, TransactionStatus::Confirmed),
+            make_tx("t3", TransactionType::Transfer, "miner", "miner", 5.0, 0.05, TransactionStatus::Confirmed),
+        ];
+        let pending = vec![
+            make_tx("p1", TransactionType::Transfer, "miner", "carol", 3.0, 0.5, TransactionStatus::Pending),
+            make_tx("p2", TransactionType::Reward, "network", "miner", 50.0, 0.0, TransactionStatus::Pending),
+        ];
+        mgr.sync_wallets_from_sources(&HashMap::from([("miner".to_string(), confirmed)]), &HashMap::from([("miner".to_string(), pending)]));
+
+        let totals = mgr.get_wallet_totals("miner").unwrap();
+        assert_eq!(totals.total_rewards_earned, lun("75.5"));
+        assert_eq!(totals.total_received, lun("16"));
+        assert_eq!(totals.total_sent, lun("25"));
+        assert_eq!(totals.total_fees_paid, lun("0.3"));
+        let balance = mgr.get_wallet_state("miner").unwrap().balance;
+        assert_eq!(balance.confirmed_balance, lun("66.2"));
+        assert_eq!(balance.confirmed_balance, totals.total_received.try_add(totals.total_rewards_earned).unwrap().try_sub(totals.total_sent).unwrap().try_sub(totals.total_fees_paid).unwrap());
+        assert_eq!(*seen.lock().unwrap(), [totals]);
+        assert!(mgr.get_wallet_totals("nobody").is_none());
+    }
+
+    /// Split CSV text into rows of fields, undoing `csv_field`'s quoting
+    fn parse_csv(text: &str) -> Vec<Vec<String>> {
+        let (mut rows, mut row, mut field) = (Vec::new(), Vec::new(), String::new());
+        let mut chars = text.chars().peekable();
+        let mut quoted = false;
+        while let Some(c) = chars.next() {
+            match (quoted, c) {
+                (true, '"') if chars.peek() == Some(&'"') => {
+                    chars.next();
+                    field.push('"');
+                }
+                (_, '"') => quoted = !quoted,
+                (false, ',') => row.push(std::mem::take(&mut field)),
+                (false, '\n') => {
+                    row.push(std::mem::take(&mut field));
+                    rows.push(std::mem::take(&mut row));
+                }
+                (_, c) => field.push(c),
+            }
+        }
+        rows
+    }
+
+    #[test]
+    fn test_export_history() {
+        let mgr = WalletManager::new();
+        mgr.register_wallet("LUN_alice");
+        let mut deposit = make_tx("d1", TransactionType::Reward, "network", "alice", 50.0, 0.0, TransactionStatus::Confirmed);
+        deposit.block_height = Some(3);
+        deposit.timestamp = 1_700_000_000;
+        let mut rent = make_tx("s1", TransactionType::Transfer, "alice", "LUN_bob", 12.5, 0.01, TransactionStatus::Pending);
+        rent.timestamp = 1_700_000_100;
+        rent.memo = "Rent, \"May\"\nthanks".to_string();
+        mgr.sync_wallets_from_sources(&HashMap::from([("alice".to_string(), vec![deposit])]), &HashMap::from([("alice".to_string(), vec![rent])]));
+
+        let mut out = Vec::new();
+        assert_eq!(mgr.export_history("alice", ExportFormat::Csv, &mut out).unwrap(), 2);
+        let rows = parse_csv(&String::from_utf8(out).unwrap());
+        assert_eq!(rows.len(), 3);
+        assert_eq!(rows[0], HISTORY_EXPORT_COLUMNS);
+        assert_eq!(rows[1], ["d1", "reward", "incoming", "network", "50", "0", "2023-11-14T22:13:20Z", "confirmed", "3", ""]);
+        assert_eq!(rows[2], ["s1", "transfer", "outgoing", "LUN_bob", "12.5", "0.01", "2023-11-14T22:15:00Z", "pending", "", "Rent, \"May\"\nthanks"]);
+
+        let mut out = Vec::new();
+        assert_eq!(mgr.export_history("alice", ExportFormat::JsonLines, &mut out).unwrap(), 2);
+        let lines: Vec<JsonValue> = String::from_utf8(out).unwrap().lines().map(|line| serde_json::from_str(line).unwrap()).collect();
+        assert_eq!(lines[1]["memo"], "Rent, \"May\"\nthanks");
+        assert_eq!(lines[0]["block_height"], "3");
+        assert!(matches!(mgr.export_history("nobody", ExportFormat::Csv, &mut Vec::new()), Err(ExportError::UnknownWallet(_))));
+    }
+
+    #[test]
+    fn test_update_confirmations() {
+        let mgr = WalletManager::new();
+        mgr.register_wallet("alice");
+        let mut deposit = make_tx("d1", TransactionType::Transfer, "bob", "alice", 4.0, 0.1, TransactionStatus::Confirmed);
+        deposit.block_height = Some(100);
+        let mut older = make_tx("d0", TransactionType::Reward, "network", "alice", 1.0, 0.0, TransactionStatus::Confirmed);
+        older.block_height = Some(90);
+        mgr.sync_wallets_from_sources(&HashMap::from([("alice".to_string(), vec![older, deposit])]), &HashMap::new());
+        let confirmations = |mgr: &WalletManager, hash: &str| {
+            let state = mgr.get_wallet_state("alice").unwrap();
+            let tx = state.confirmed_transactions.iter().find(|tx| tx.hash == hash).unwrap().clone();
+            assert_eq!(state.confirmed_transfers.iter().chain(&state.rewards).find(|t| t.hash == hash), Some(&tx));
+            tx.confirmations
+        };
+        for (height, expected) in [(100, 1), (103, 4), (105, 6)] {
+            mgr.update_confirmations(height);
+            assert_eq!(confirmations(&mgr, "d1"), expected);
+        }
+        assert_eq!(confirmations(&mgr, "d0"), 16);
+        assert_eq!(mgr.get_wallet_state("alice").unwrap().balance.confirmed_balance, lun("5"));
+    }
+
+    #[test]
+    fn test_update_confirmations_demotes_reorged_transactions() {
+        let mgr = WalletManager::new();
+        mgr.register_wallet("alice");
+        let mut deposit = make_tx("d1", TransactionType::Transfer, "bob", "alice", 4.0, 0.1, TransactionStatus::Confirmed);
+        deposit.block_height = Some(100);
+        mgr.sync_wallets_from_sources(&HashMap::from([("alice".to_string(), vec![deposit.clone()])]), &HashMap::new());
+        mgr.update_confirmations(101);
+        let balances = Arc::new(Mutex::new(Vec::new()));
+        let seen = Arc::clone(&balances);
+        mgr.on_balance_update_for("alice", Arc::new(move |balance| seen.lock().unwrap().push(balance)));
+
+        // The chain now ends at 99, so block 100 and the deposit in it are gone
+        mgr.update_confirmations(99);
+        let state = mgr.get_wallet_state("alice").unwrap();
+        assert!(state.confirmed_transactions.is_empty());
+        assert!(state.confirmed_transfers.is_empty());
+        let pending = &state.pending_transfers[0];
+        assert_eq!((pending.hash.as_str(), pending.status.clone(), pending.block_height, pending.confirmations), ("d1", TransactionStatus::Pending, None, 0));
+        assert_eq!((state.balance.confirmed_balance, state.balance.pending_incoming), (Amount::ZERO, lun("4")));
+        assert_eq!(balances.lock().unwrap().len(), 1);
+        mgr.update_confirmations(99);
+        assert_eq!(balances.lock().unwrap().len(), 1);
+
+        // Mined again in the replacement block 100, it is confirmed once more
+        assert!(mgr.apply_transaction_delta("alice", &[deposit], &[], &[]));
+        mgr.update_confirmations(100);
+        let state = mgr.get_wallet_state("alice").unwrap();
+        assert!(state.pending_transactions.is_empty());
+        assert_eq!(state.confirmed_transactions[0].confirmations, 1);
+    }
+
+    #[test]
+    fn test_balance_is_exact_and_overflow_is_reported() {
+        let mgr = WalletManager::new();
+        mgr.register_wallet("alice");
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let collected = Arc::clone(&events);
+        mgr.set_logger(Arc::new(move |event| collected.lock().unwrap().push(event)));
+        let deposit = make_tx("d1", TransactionType::Transfer, "bob", "alice", 100.1, 0.0, TransactionStatus::Confirmed);
+        let spend = make_tx("s1", TransactionType::Transfer, "alice", "bob", 0.2, 0.1, TransactionStatus::Confirmed);
+        mgr.sync_wallets_from_sources(&HashMap::from([("alice".to_string(), vec![deposit, spend])]), &HashMap::new());
+        let balance = mgr.get_wallet_state("alice").unwrap().balance;
+        assert_eq!(balance.confirmed_balance, lun("99.8"));
+        assert_eq!(balance.confirmed_balance.to_string(), "99.8");
+        #[allow(deprecated)]
+        let float = balance.confirmed_balance_f64();
+        assert_eq!(float, 99.8);
+
+        let mut huge = make_tx("h1", TransactionType::Transfer, "bob", "alice", 0.0, 0.0, TransactionStatus::Confirmed);
+        huge.amount = Amount::from_units(i128::MAX);
+        let mut more = huge.clone();
+        more.hash = "h2".to_string();
+        mgr.sync_wallets_from_sources(&HashMap::from([("alice".to_string(), vec![huge, more])]), &HashMap::new());
+        assert_eq!(mgr.get_wallet_state("alice").unwrap().balance, balance);
+        assert!(events.lock().unwrap().contains(&SyncEvent::BalanceFailed { address: "alice".to_string(), error: AmountError::Overflow }));
+    }
+
+    #[test]
+    fn test_pending_deposit_is_not_available() {
+        let mgr = WalletManager::new();
+        mgr.register_wallet("erin");
+        let deposit = make_tx("d1", TransactionType::Transfer, "frank", "erin", 1_000_000.0, 0.1, TransactionStatus::Pending);
+        mgr.sync_wallets_from_sources(&HashMap::new(), &HashMap::from([("erin".to_string(), vec![deposit])]));
+        let balance = mgr.get_wallet_state("erin").unwrap().balance;
+        assert_eq!(balance.confirmed_balance, lun("0"));
+        assert_eq!(balance.available_balance, lun("0"));
+        assert_eq!(balance.pending_incoming, lun("1000000"));
+        assert_eq!(balance.projected_balance, lun("1000000"));
+    }
+
+    #[test]
+    fn test_sync_emits_log_records() {
+        let logger = crate::utils::log::test_logger();
+        let mgr = WalletManager::new();
+        mgr.register_wallet("log_carol");
+        let blockchain_txs = HashMap::from([(
+            "log_carol".to_string(),
+            vec![make_tx("log_h1", TransactionType::Transfer, "log_dave", "log_carol", 5.0, 0.1, TransactionStatus::Confirmed)],
+        )]);
+        mgr.sync_wallets_from_sources(&blockchain_txs, &HashMap::new());
+
+        let records: Vec<_> = logger.records().into_iter().filter(|r| r.fields.get("address").is_some_and(|a| a == "log_carol")).collect();
+        let incoming = records.iter().find(|r| r.message == "Incoming transaction").unwrap();
+        assert_eq!(incoming.level, crate::utils::log::Level::Trace);
+        assert_eq!(incoming.target, "lunalib::core::wallet_manager");
+        assert_eq!(incoming.fields["tx_hash"], "log_h1");
+        let synced = records.iter().find(|r| r.message == "Wallet synced").unwrap();
+        assert_eq!(synced.level, crate::utils::log::Level::Debug);
+        assert_eq!((synced.fields["confirmed"].as_str(), synced.fields["balance"].as_str()), ("1", "5"));
+    }
+
+    #[test]
+    fn test_sync_events_reach_the_logger() {
+        let mgr = Arc::new(WalletManager::new());
+        mgr.register_wallets(&["alice".to_string(), "bob".to_string()]);
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let collected = Arc::clone(&events);
+        let reader = Arc::clone(&mgr);
+        mgr.set_logger(Arc::new(move |event| {
+            // The states are readable from inside the hook
+            assert!(reader.get_wallet_state("alice").is_some());
+            collected.lock().unwrap().push(event);
+        }));
+        let blockchain_txs = HashMap::from([("alice".to_string(), vec![make_tx("h1", TransactionType::Reward, "network", "alice", 5.0, 0.0, TransactionStatus::Confirmed)])]);
+        let mempool_txs = HashMap::from([("bob".to_string(), vec![make_tx("h2", TransactionType::Transfer, "bob", "alice", 1.0, 0.1, TransactionStatus::Pending)])]);
+        mgr.sync_wallets_from_sources(&blockchain_txs, &mempool_txs);
+
+        let events = events.lock().unwrap();
+        for (address, confirmed, pending) in [("alice", 1, 0), ("bob", 0, 1)] {
+            assert!(events.contains(&SyncEvent::AddressSynced { address: address.to_string(), confirmed, pending }), "{:?}", events);
+        }
+        assert!(events.contains(&SyncEvent::TxCategorized { address: "alice".to_string(), hash: "h1".to_string(), categories: vec!["confirmed_transactions".to_string(), "rewards".to_string()] }));
+        assert!(events.contains(&SyncEvent::TxCategorized { address: "bob".to_string(), hash: "h2".to_string(), categories: vec!["pending_transactions".to_string(), "pending_transfers".to_string()] }));
+        assert!(events.iter().any(|e| matches!(e, SyncEvent::BalanceComputed { address, balance } if address == "alice" && balance.confirmed_balance == lun("5"))));
     }
 }
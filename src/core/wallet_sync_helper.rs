@@ -3,10 +3,55 @@ use std::sync::{Arc, Mutex};
 use std::thread;
 use std::time::Duration;
 
-use crate::core::wallet_manager::{WalletManager, Transaction, TransactionType, TransactionStatus, WalletBalance};
+use crate::core::blockchain::{BlockchainError, BlockchainManager};
+use crate::core::wallet_manager::{WalletManager, Transaction, TransactionStatus, TxFilter, WalletBalance};
+use crate::utils::log::warn;
 
 pub trait BlockchainSync: Send + Sync {
     fn scan_transactions_for_addresses(&self, addresses: &[String]) -> HashMap<String, Vec<Transaction>>;
+
+    /// Confirmed transactions in blocks above `from_height`; the default scans everything and filters
+    fn scan_transactions_since(&self, addresses: &[String], from_height: u64) -> HashMap<String, Vec<Transaction>> {
+        let mut txs = self.scan_transactions_for_addresses(addresses);
+        for list in txs.values_mut() {
+            list.retain(|tx| tx.block_height.map_or(from_height == 0, |h| h > from_height));
+        }
+        txs
+    }
+
+    /// The chain tip's height, used to count confirmations; `None` leaves them alone
+    fn current_height(&self) -> Option<u64> {
+        None
+    }
+}
+
+/// Blocking wrapper over `BlockchainManager::scan_transactions_for_addresses`. When the endpoint
+/// cannot be reached, the cached blocks are scanned instead. Must not be called from async code.
+impl BlockchainSync for BlockchainManager {
+    fn scan_transactions_for_addresses(&self, addresses: &[String]) -> HashMap<String, Vec<Transaction>> {
+        scan_blocking(self, addresses, 0)
+    }
+
+    fn scan_transactions_since(&self, addresses: &[String], from_height: u64) -> HashMap<String, Vec<Transaction>> {
+        scan_blocking(self, addresses, from_height + 1)
+    }
+
+    fn current_height(&self) -> Option<u64> {
+        self.cache.lock().unwrap().keys().max().copied()
+    }
+}
+
+fn scan_blocking(blockchain: &BlockchainManager, addresses: &[String], from_height: u64) -> HashMap<String, Vec<Transaction>> {
+    let scanned = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .map_err(|e| BlockchainError::Network(e.to_string()))
+        .and_then(|runtime| runtime.block_on(blockchain.scan_transactions_for_addresses(addresses, from_height)));
+    scanned.unwrap_or_else(|e| {
+        warn!(error:% = e; "Could not fetch new blocks; scanning cached ones");
+        let tip = blockchain.current_height().unwrap_or(0);
+        blockchain.scan_cached_blocks(addresses, from_height, tip)
+    })
 }
 
 pub trait MempoolSync: Send + Sync {
@@ -19,6 +64,7 @@ pub struct WalletSyncHelper<B: BlockchainSync, M: MempoolSync> {
     pub mempool: Arc<M>,
     pub sync_thread: Option<thread::JoinHandle<()>>,
     pub stop_flag: Arc<Mutex<bool>>,
+    last_seen_heights: Mutex<HashMap<String, u64>>,
 }
 
 impl<B: BlockchainSync + 'static, M: MempoolSync + 'static> WalletSyncHelper<B, M> {
@@ -29,6 +75,7 @@ impl<B: BlockchainSync + 'static, M: MempoolSync + 'static> WalletSyncHelper<B,
             mempool,
             sync_thread: None,
             stop_flag: Arc::new(Mutex::new(false)),
+            last_seen_heights: Mutex::new(HashMap::new()),
         }
     }
 
@@ -37,31 +84,76 @@ impl<B: BlockchainSync + 'static, M: MempoolSync + 'static> WalletSyncHelper<B,
     }
 
     pub fn sync_wallets_now(&self) {
-        let addresses: Vec<String> = self.wallet_manager.get_all_wallet_states().keys().cloned().collect();
+        let addresses: Vec<String> = self.wallet_manager.active_addresses();
         let blockchain_txs = self.blockchain.scan_transactions_for_addresses(&addresses);
         let mempool_txs = self.mempool.get_pending_transactions_for_addresses(&addresses);
         self.wallet_manager.sync_wallets_from_sources(&blockchain_txs, &mempool_txs);
+        if let Some(height) = self.blockchain.current_height() {
+            self.wallet_manager.update_confirmations(height);
+        }
+    }
+
+    /// Fetch only blocks above each wallet's last seen height and merge them, with the mempool's
+    /// changes, through `WalletManager::apply_transaction_delta`. Wallets with nothing new are not
+    /// touched, but still count as seen up to the scanned tip; archived wallets are skipped. A
+    /// wallet seen for the first time starts from the highest block it already holds, such as one
+    /// restored by `WalletManager::load_snapshot`, or from height 0.
+    pub fn sync_incremental(&self) {
+        let addresses: Vec<String> = self.wallet_manager.active_addresses();
+        if addresses.is_empty() {
+            return;
+        }
+        let mut heights = self.last_seen_heights.lock().unwrap();
+        for address in &addresses {
+            if let Some(height) = self.wallet_manager.last_block_height(address) {
+                heights.entry(address.clone()).or_insert(height);
+            }
+        }
+        let from_height = addresses.iter().map(|a| heights.get(a).copied().unwrap_or(0)).min().unwrap_or(0);
+        let tip = self.blockchain.current_height();
+        let blockchain_txs = self.blockchain.scan_transactions_since(&addresses, from_height);
+        let scanned_to = blockchain_txs.values().flatten().filter_map(|tx| tx.block_height).chain(tip).max().unwrap_or(0);
+        let mempool_txs = self.mempool.get_pending_transactions_for_addresses(&addresses);
+        for address in &addresses {
+            let seen = heights.get(address).copied().unwrap_or(0);
+            let new_confirmed: Vec<Transaction> = blockchain_txs.get(address).into_iter().flatten()
+                .filter(|tx| tx.block_height.map_or(seen == 0, |h| h > seen))
+                .cloned()
+                .collect();
+            let pending = mempool_txs.get(address).map(Vec::as_slice).unwrap_or_default();
+            let known = self.wallet_manager.pending_hashes(address);
+            let removed: Vec<String> = known.iter().filter(|hash| !pending.iter().any(|tx| &tx.hash == *hash)).cloned().collect();
+            let added: Vec<Transaction> = pending.iter().filter(|tx| !known.contains(&tx.hash)).cloned().collect();
+            if !(new_confirmed.is_empty() && removed.is_empty() && added.is_empty()) {
+                self.wallet_manager.apply_transaction_delta(address, &new_confirmed, &added, &removed);
+            }
+            heights.insert(address.clone(), seen.max(scanned_to));
+        }
+        if let Some(height) = self.blockchain.current_height() {
+            // After a reorg, blocks above the new tip must be scanned again when they are replaced
+            heights.values_mut().for_each(|seen| *seen = (*seen).min(height));
+            self.wallet_manager.update_confirmations(height);
+        }
+    }
+
+    /// The highest block `sync_incremental` has merged for `address`
+    pub fn last_seen_height(&self, address: &str) -> u64 {
+        self.last_seen_heights.lock().unwrap().get(address).copied().unwrap_or(0)
     }
 
     pub fn get_wallet_balance(&self, address: &str) -> Option<WalletBalance> {
         self.wallet_manager.get_wallet_state(address).map(|s| s.balance)
     }
 
+    /// `"confirmed"`, `"pending"` or `"all"` of the wallet's transactions, newest first
     pub fn get_wallet_transactions(&self, address: &str, tx_type: Option<&str>) -> Vec<Transaction> {
-        if let Some(state) = self.wallet_manager.get_wallet_state(address) {
-            match tx_type {
-                Some("confirmed") => state.confirmed_transactions,
-                Some("pending") => state.pending_transactions,
-                Some("all") | None => {
-                    let mut all = state.confirmed_transactions;
-                    all.extend(state.pending_transactions);
-                    all
-                }
-                _ => vec![],
-            }
-        } else {
-            vec![]
-        }
+        let filter = match tx_type {
+            Some("confirmed") => TxFilter::new().status(TransactionStatus::Confirmed),
+            Some("pending") => TxFilter::new().status(TransactionStatus::Pending),
+            Some("all") | None => TxFilter::new(),
+            _ => return vec![],
+        };
+        self.wallet_manager.query_transactions(address, filter)
     }
 
     pub fn start_continuous_sync<F>(&mut self, poll_interval_secs: u64, on_update: F)
@@ -74,10 +166,13 @@ impl<B: BlockchainSync + 'static, M: MempoolSync + 'static> WalletSyncHelper<B,
         let stop_flag = Arc::clone(&self.stop_flag);
         self.sync_thread = Some(thread::spawn(move || {
             while !*stop_flag.lock().unwrap() {
-                let addresses: Vec<String> = wallet_manager.get_all_wallet_states().keys().cloned().collect();
+                let addresses: Vec<String> = wallet_manager.active_addresses();
                 let blockchain_txs = blockchain.scan_transactions_for_addresses(&addresses);
                 let mempool_txs = mempool.get_pending_transactions_for_addresses(&addresses);
                 wallet_manager.sync_wallets_from_sources(&blockchain_txs, &mempool_txs);
+                if let Some(height) = blockchain.current_height() {
+                    wallet_manager.update_confirmations(height);
+                }
                 on_update();
                 thread::sleep(Duration::from_secs(poll_interval_secs));
             }
@@ -95,9 +190,14 @@ impl<B: BlockchainSync + 'static, M: MempoolSync + 'static> WalletSyncHelper<B,
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::core::wallet_manager::{WalletManager, Transaction, TransactionType, TransactionStatus};
+    use crate::core::amount::Amount;
+    use crate::core::wallet_manager::{WalletManager, Transaction, TransactionDirection, TransactionType, TransactionStatus};
     use std::sync::Arc;
 
+    fn lun(text: &str) -> Amount {
+        text.parse().unwrap()
+    }
+
     struct DummyBlockchainManager;
     struct DummyMempoolManager;
 
@@ -110,13 +210,14 @@ mod tests {
                     tx_type: TransactionType::Transfer,
                     from_address: "bob".to_string(),
                     to_address: addr.clone(),
-                    amount: 100.0,
-                    fee: 1.0,
+                    amount: lun("100"),
+                    fee: lun("1"),
                     timestamp: 0,
                     status: TransactionStatus::Confirmed,
                     block_height: Some(1),
                     confirmations: 10,
                     memo: String::new(),
+                    direction: TransactionDirection::Incoming,
                 }]);
             }
             map
@@ -132,13 +233,14 @@ mod tests {
                     tx_type: TransactionType::Transfer,
                     from_address: addr.clone(),
                     to_address: "bob".to_string(),
-                    amount: 10.0,
-                    fee: 0.1,
+                    amount: lun("10"),
+                    fee: lun("0.1"),
                     timestamp: 0,
                     status: TransactionStatus::Pending,
                     block_height: None,
                     confirmations: 0,
                     memo: String::new(),
+                    direction: TransactionDirection::Outgoing,
                 }]);
             }
             map
@@ -155,9 +257,225 @@ mod tests {
         helper.register_wallets(&addresses);
         helper.sync_wallets_now();
         let bal = helper.get_wallet_balance("alice").unwrap();
-        assert_eq!(bal.confirmed_balance, 100.0);
-        assert_eq!(bal.pending_outgoing, 10.1);
+        assert_eq!(bal.confirmed_balance, lun("100"));
+        assert_eq!(bal.pending_outgoing, lun("10.1"));
         let txs = helper.get_wallet_transactions("alice", Some("all"));
         assert_eq!(txs.len(), 2);
     }
+
+    fn tx(hash: &str, from: &str, to: &str, amount: f64, block_height: Option<u64>) -> Transaction {
+        Transaction {
+            hash: hash.to_string(),
+            tx_type: TransactionType::Transfer,
+            from_address: from.to_string(),
+            to_address: to.to_string(),
+            amount: Amount::from_f64_lossy(amount),
+            status: if block_height.is_some() { TransactionStatus::Confirmed } else { TransactionStatus::Pending },
+            block_height,
+            ..Default::default()
+        }
+    }
+
+    /// Serves whatever the test has put in the chain and mempool, counting the heights it was asked for
+    #[derive(Default)]
+    struct ScriptedSource {
+        chain: Mutex<Vec<Transaction>>,
+        mempool: Mutex<Vec<Transaction>>,
+        scanned_from: Mutex<Vec<u64>>,
+    }
+
+    impl ScriptedSource {
+        fn for_addresses(txs: &[Transaction], addresses: &[String]) -> HashMap<String, Vec<Transaction>> {
+            addresses.iter()
+                .map(|a| (a.clone(), txs.iter().filter(|tx| &tx.from_address == a || &tx.to_address == a).cloned().collect()))
+                .collect()
+        }
+    }
+
+    impl BlockchainSync for ScriptedSource {
+        fn scan_transactions_for_addresses(&self, addresses: &[String]) -> HashMap<String, Vec<Transaction>> {
+            Self::for_addresses(&self.chain.lock().unwrap(), addresses)
+        }
+
+        fn scan_transactions_since(&self, addresses: &[String], from_height: u64) -> HashMap<String, Vec<Transaction>> {
+            self.scanned_from.lock().unwrap().push(from_height);
+            let chain: Vec<Transaction> = self.chain.lock().unwrap().iter().filter(|tx| tx.block_height.unwrap_or(0) > from_height).cloned().collect();
+            Self::for_addresses(&chain, addresses)
+        }
+
+        fn current_height(&self) -> Option<u64> {
+            self.chain.lock().unwrap().iter().filter_map(|tx| tx.block_height).max()
+        }
+    }
+
+    impl MempoolSync for ScriptedSource {
+        fn get_pending_transactions_for_addresses(&self, addresses: &[String]) -> HashMap<String, Vec<Transaction>> {
+            Self::for_addresses(&self.mempool.lock().unwrap(), addresses)
+        }
+    }
+
+    #[test]
+    fn test_sync_incremental() {
+        let source = Arc::new(ScriptedSource::default());
+        source.chain.lock().unwrap().push(tx("h1", "bob", "alice", 100.0, Some(1)));
+        source.mempool.lock().unwrap().push(tx("h2", "alice", "carol", 10.0, None));
+        let helper = WalletSyncHelper::new(Arc::new(WalletManager::new()), source.clone(), source.clone());
+        helper.register_wallets(&["alice".to_string(), "carol".to_string(), "dave".to_string()]);
+        helper.sync_incremental();
+        // Wallets without confirmed transactions were scanned to the tip all the same
+        for address in ["alice", "carol", "dave"] {
+            assert_eq!(helper.last_seen_height(address), 1);
+        }
+        assert_eq!(helper.get_wallet_balance("alice").unwrap().available_balance, lun("90"));
+        assert_eq!(helper.get_wallet_transactions("carol", Some("pending")).len(), 1);
+
+        // h2 is mined, so it leaves the mempool and both wallets see it confirmed at height 2
+        source.mempool.lock().unwrap().clear();
+        source.chain.lock().unwrap().push(tx("h2", "alice", "carol", 10.0, Some(2)));
+        helper.sync_incremental();
+        assert_eq!(source.scanned_from.lock().unwrap().last(), Some(&1));
+        for address in ["alice", "carol", "dave"] {
+            assert!(helper.get_wallet_transactions(address, Some("pending")).is_empty());
+            assert_eq!(helper.last_seen_height(address), 2);
+        }
+        assert_eq!(helper.get_wallet_transactions("alice", Some("confirmed")).len(), 2);
+        assert_eq!(helper.get_wallet_balance("carol").unwrap().confirmed_balance, lun("10"));
+
+        helper.sync_incremental();
+        assert_eq!(source.scanned_from.lock().unwrap().last(), Some(&2));
+        assert_eq!(helper.get_wallet_balance("alice").unwrap().confirmed_balance, lun("90"));
+    }
+
+    #[test]
+    fn test_sync_incremental_resumes_from_snapshot() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = crate::storage::database::WalletDatabase::new(Some(dir.path().join("snapshot.db")));
+        let source = Arc::new(ScriptedSource::default());
+        source.chain.lock().unwrap().extend([tx("h1", "bob", "alice", 100.0, Some(1)), tx("h2", "alice", "bob", 10.0, Some(5))]);
+        let helper = WalletSyncHelper::new(Arc::new(WalletManager::new()), source.clone(), source.clone());
+        helper.register_wallets(&["alice".to_string()]);
+        helper.sync_incremental();
+        assert!(helper.wallet_manager.save_snapshot(&db));
+
+        let restarted = Arc::new(WalletManager::new());
+        restarted.load_snapshot(&db);
+        source.chain.lock().unwrap().push(tx("h3", "bob", "alice", 1.0, Some(6)));
+        let resumed = WalletSyncHelper::new(restarted, source.clone(), source.clone());
+        resumed.sync_incremental();
+        assert_eq!(source.scanned_from.lock().unwrap().last(), Some(&5));
+        assert_eq!(resumed.last_seen_height("alice"), 6);
+        assert_eq!(resumed.get_wallet_balance("alice").unwrap().confirmed_balance, lun("91"));
+    }
+
+    #[test]
+    fn test_archived_wallet_is_not_rescanned() {
+        let source = Arc::new(ScriptedSource::default());
+        source.chain.lock().unwrap().push(tx("h1", "bob", "alice", 3.0, Some(1)));
+        let helper = WalletSyncHelper::new(Arc::new(WalletManager::new()), source.clone(), source.clone());
+        helper.register_wallets(&["alice".to_string()]);
+        helper.sync_wallets_now();
+        helper.wallet_manager.archive_wallet("alice");
+
+        source.chain.lock().unwrap().push(tx("h2", "bob", "alice", 4.0, Some(2)));
+        helper.sync_wallets_now();
+        helper.sync_incremental();
+        assert_eq!(helper.get_wallet_balance("alice").unwrap().confirmed_balance, lun("3"));
+        assert!(source.scanned_from.lock().unwrap().is_empty());
+
+        helper.wallet_manager.unarchive_wallet("alice");
+        helper.sync_incremental();
+        assert_eq!(source.scanned_from.lock().unwrap().as_slice(), [1]);
+        assert_eq!(helper.get_wallet_balance("alice").unwrap().confirmed_balance, lun("7"));
+    }
+
+    struct EmptyMempool;
+
+    impl MempoolSync for EmptyMempool {
+        fn get_pending_transactions_for_addresses(&self, _addresses: &[String]) -> HashMap<String, Vec<Transaction>> {
+            HashMap::new()
+        }
+    }
+
+    fn chain_block(index: u64, transactions: serde_json::Value) -> serde_json::Value {
+        serde_json::json!({
+            "index": index, "hash": format!("h{}", index), "previous_hash": format!("h{}", index.saturating_sub(1)),
+            "timestamp": 1_700_000_000 + index, "transactions": transactions,
+        })
+    }
+
+    /// Serves `blocks` as the chain listing and one by one, returning the listing's mock
+    fn serve_chain(server: &mut mockito::Server, blocks: &[serde_json::Value]) -> mockito::Mock {
+        for block in blocks {
+            server.mock("GET", format!("/blockchain/block/{}", block["index"]).as_str()).with_body(block.to_string()).create();
+        }
+        server.mock("GET", "/blockchain/blocks").with_body(serde_json::json!({"blocks": blocks}).to_string()).create()
+    }
+
+    #[test]
+    fn test_sync_counts_confirmations_from_blockchain_manager() {
+        let deposit = serde_json::json!([{"type": "transfer", "from": "LUN_bob", "to": "lun_ALICE", "amount": 3.0, "hash": "tx1"}]);
+        let mut server = mockito::Server::new();
+        let first = serve_chain(&mut server, &[chain_block(0, serde_json::json!([])), chain_block(1, deposit)]);
+        let helper = WalletSyncHelper::new(Arc::new(WalletManager::new()), Arc::new(BlockchainManager::new(&server.url(), 1)), Arc::new(EmptyMempool));
+        helper.register_wallets(&["LUN_alice".to_string()]);
+        helper.sync_wallets_now();
+        let confirmed = helper.get_wallet_transactions("alice", Some("confirmed"));
+        assert_eq!((confirmed.len(), confirmed[0].block_height, confirmed[0].confirmations), (1, Some(1), 1));
+        assert_eq!(helper.get_wallet_balance("alice").unwrap().confirmed_balance, lun("3"));
+
+        first.remove();
+        let later: Vec<serde_json::Value> = (2..=6).map(|i| chain_block(i, serde_json::json!([]))).collect();
+        serve_chain(&mut server, &later);
+        helper.sync_wallets_now();
+        assert_eq!(helper.get_wallet_transactions("alice", Some("confirmed"))[0].confirmations, 6);
+    }
+
+    #[test]
+    fn test_scan_mock_chain_for_two_addresses() {
+        let blocks = [
+            chain_block(0, serde_json::json!([{"type": "gtx_genesis", "to": "LUN_alice", "amount": 50.0, "hash": "g"}])),
+            chain_block(1, serde_json::json!([{"type": "transfer", "from": "LUN_alice", "to": "LUN_carol", "amount": 5.0, "fee": 0.01, "memo": "rent", "hash": "t1"}])),
+            chain_block(2, serde_json::json!([{"type": "transfer", "from": "LUN_bob", "to": "LUN_dave", "amount": 1.0, "hash": "t2"}])),
+            chain_block(3, serde_json::json!([
+                {"type": "reward", "from": "network", "to": "lun_CAROL", "amount": 2.0, "hash": "r3", "timestamp": 42},
+                {"type": "transfer", "from": "LUN_alice", "to": "LUN_alice", "amount": 1.0, "hash": "s3"},
+            ])),
+            chain_block(4, serde_json::json!([{"type": "transfer", "from": "LUN_carol", "to": "LUN_bob", "amount": 0.5, "hash": "t4"}])),
+        ];
+        let mut server = mockito::Server::new();
+        serve_chain(&mut server, &blocks);
+        let blockchain = BlockchainManager::new(&server.url(), 3);
+        let addresses = vec!["LUN_alice".to_string(), "LUN_carol".to_string()];
+        let found = BlockchainSync::scan_transactions_for_addresses(&blockchain, &addresses);
+        let listed = |address: &str| found[address].iter()
+            .map(|tx| (tx.hash.as_str(), tx.block_height.unwrap(), tx.confirmations, tx.direction))
+            .collect::<Vec<_>>();
+        assert_eq!(found.len(), 2);
+        assert_eq!(listed("LUN_alice"), vec![
+            ("g", 0, 5, TransactionDirection::Incoming),
+            ("t1", 1, 4, TransactionDirection::Outgoing),
+            ("s3", 3, 2, TransactionDirection::SelfTransfer),
+        ]);
+        assert_eq!(listed("LUN_carol"), vec![
+            ("t1", 1, 4, TransactionDirection::Incoming),
+            ("r3", 3, 2, TransactionDirection::Incoming),
+            ("t4", 4, 1, TransactionDirection::Outgoing),
+        ]);
+        let rent = &found["LUN_carol"][0];
+        assert_eq!((rent.amount, rent.fee, rent.memo.as_str(), rent.timestamp), (lun("5"), lun("0.01"), "rent", 1_700_000_001));
+        assert_eq!(found["LUN_carol"][1].tx_type, TransactionType::Reward);
+        assert_eq!(found["LUN_carol"][1].timestamp, 42);
+        assert_eq!(found["LUN_alice"][0].tx_type, TransactionType::Genesis);
+        assert!(found.values().flatten().all(|tx| tx.status == TransactionStatus::Confirmed));
+        assert_eq!(blockchain.cache.lock().unwrap().len(), 5);
+
+        let since = blockchain.scan_transactions_since(&addresses, 2);
+        assert_eq!(since["LUN_alice"].iter().map(|tx| tx.hash.as_str()).collect::<Vec<_>>(), vec!["s3"]);
+        assert_eq!(since["LUN_carol"].len(), 2);
+
+        // Unreachable, the cached blocks are scanned instead
+        let offline = BlockchainManager::new("http://127.0.0.1:9", 1);
+        offline.cache.lock().unwrap().extend(blockchain.cache.lock().unwrap().values().cloned());
+        assert_eq!(BlockchainSync::scan_transactions_for_addresses(&offline, &addresses), found);
+    }
 }
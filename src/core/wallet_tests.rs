@@ -1,5 +1,7 @@
 // Basic tests for LunaWallet struct
-use super::LunaWallet;
+use super::{verify_message, AddressError, LunaWallet, SignedMessage, WalletError, WalletKind};
+use crate::core::blockchain::{BlockchainManager, Transaction};
+use crate::transactions::transactions::TransactionManager;
 
 #[test]
 fn test_wallet_creation() {
@@ -13,8 +15,386 @@ fn test_wallet_creation() {
     assert_eq!(wallet.address, "LUN_testaddress");
     assert_eq!(wallet.public_key, "testpubkey");
     assert_eq!(wallet.label, "Test Wallet");
-    assert!(wallet.is_locked);
+    assert!(wallet.is_locked());
     assert_eq!(wallet.balance, 0.0);
     assert_eq!(wallet.available_balance, 0.0);
     assert_eq!(wallet.created, 1234567890);
 }
+
+#[test]
+fn test_wallet_create_encrypts_key() {
+    let wallet = LunaWallet::create("Savings", "hunter2");
+    assert!(wallet.address.starts_with("LUN_"), "{}", wallet.address);
+    let normalized = BlockchainManager::normalize_address(&wallet.address);
+    assert_eq!(normalized, wallet.address[4..].to_lowercase());
+    assert!(!normalized.is_empty() && normalized.chars().all(|c| c.is_ascii_alphanumeric()), "{}", normalized);
+    assert_ne!(LunaWallet::create("Savings", "hunter2").address, wallet.address);
+    assert!(wallet.created > 0);
+    let record = wallet.to_json();
+    assert_eq!(record["label"], "Savings");
+    assert_eq!(record["public_key"], wallet.public_key.as_str());
+    let encrypted = record["encrypted_private_key"].as_str().unwrap();
+    let private_key = EncryptionManager::new().decrypt_data(encrypted, "hunter2").unwrap();
+    assert_eq!(Crypto::new().derive_public_key(&private_key), wallet.public_key);
+    assert!(EncryptionManager::new().decrypt_data(encrypted, "wrong").is_none());
+}
+
+#[test]
+fn test_unlock_lock_round_trip() {
+    let mut wallet = LunaWallet::create("Spending", "hunter2");
+    assert_eq!(wallet.sign("hello"), Err(WalletError::Locked));
+    wallet.unlock("hunter2").unwrap();
+    assert!(!wallet.is_locked());
+    let signature = wallet.sign("hello").unwrap();
+    assert!(Crypto::new().verify_signature("hello", &signature, &wallet.public_key));
+
+    wallet.lock();
+    assert!(wallet.is_locked());
+    assert_eq!(wallet.sign("hello"), Err(WalletError::Locked));
+}
+
+#[test]
+fn test_unlock_wrong_password() {
+    let mut wallet = LunaWallet::create("Spending", "hunter2");
+    assert_eq!(wallet.unlock("hunter3"), Err(WalletError::WrongPassword));
+    assert!(wallet.is_locked());
+    assert_eq!(wallet.sign("hello"), Err(WalletError::Locked));
+
+    let mut corrupt = LunaWallet::new("LUN_x".to_string(), "pk".to_string(), vec![0xff, 0xfe], "Broken".to_string(), 0);
+    assert_eq!(corrupt.unlock("hunter2"), Err(WalletError::CorruptKey));
+}
+
+#[test]
+fn test_keystore_round_trip() {
+    let mut wallet = LunaWallet::create("Savings", "hunter2");
+    wallet.unlock("hunter2").unwrap();
+    let keystore = wallet.export_keystore("moving day").unwrap();
+    assert_eq!(keystore["version"], super::KEYSTORE_VERSION);
+    assert_eq!((keystore["address"].as_str(), keystore["label"].as_str()), (Some(wallet.address.as_str()), Some("Savings")));
+
+    let mut imported = LunaWallet::import_keystore(&keystore, "moving day").unwrap();
+    assert_eq!((imported.address.as_str(), imported.public_key.as_str()), (wallet.address.as_str(), wallet.public_key.as_str()));
+    assert_eq!((imported.label.as_str(), imported.created), ("Savings", wallet.created));
+    assert!(imported.is_locked());
+    assert_eq!(imported.unlock("hunter2"), Err(WalletError::WrongPassword));
+    imported.unlock("moving day").unwrap();
+    assert_eq!(imported.sign("data").unwrap(), wallet.sign("data").unwrap());
+
+    wallet.lock();
+    assert_eq!(wallet.export_keystore("moving day"), Err(WalletError::Locked));
+}
+
+#[test]
+fn test_keystore_rejects_wrong_password_and_foreign_key() {
+    let mut wallet = LunaWallet::create("main", "hunter2");
+    wallet.unlock("hunter2").unwrap();
+    let keystore = wallet.export_keystore("hunter2").unwrap();
+    assert_eq!(LunaWallet::import_keystore(&keystore, "hunter3").err(), Some(WalletError::WrongPassword));
+
+    let mut foreign = keystore.clone();
+    foreign["address"] = serde_json::json!(LunaWallet::create("other", "hunter2").address);
+    let error = LunaWallet::import_keystore(&foreign, "hunter2").err().unwrap();
+    assert!(matches!(&error, WalletError::InvalidKeystore(reason) if reason.contains(&wallet.address)), "{}", error);
+
+    let mut newer = keystore.clone();
+    newer["version"] = serde_json::json!(super::KEYSTORE_VERSION + 1);
+    assert!(matches!(LunaWallet::import_keystore(&newer, "hunter2"), Err(WalletError::InvalidKeystore(_))));
+    let mut bare = keystore;
+    bare.as_object_mut().unwrap().remove("crypto");
+    assert!(matches!(LunaWallet::import_keystore(&bare, "hunter2"), Err(WalletError::InvalidKeystore(_))));
+}
+
+#[test]
+fn test_sign_transaction_passes_broadcast_validation() {
+    let mut wallet = LunaWallet::create("Spending", "hunter2");
+    let recipient = LunaWallet::create("Rent", "other");
+    let manager = TransactionManager::new();
+    let mut tx = manager.create_transaction(&wallet.address, &recipient.address, 2.5, "rent", "transfer").unwrap();
+    assert!(!BlockchainManager::validate_transaction_before_broadcast(&Transaction::from_json(&tx)));
+    let unsigned = tx.clone();
+    assert_eq!(wallet.sign_transaction(&mut tx), Err(WalletError::Locked));
+    assert_eq!(tx, unsigned);
+
+    wallet.unlock("hunter2").unwrap();
+    wallet.sign_transaction(&mut tx).unwrap();
+    assert_eq!(tx["public_key"], wallet.public_key.as_str());
+    let hash = TransactionManager::calculate_transaction_hash(&tx);
+    assert_eq!(tx["hash"], hash.as_str());
+    assert!(Crypto::new().verify_signature(&hash, tx["signature"].as_str().unwrap(), &wallet.public_key));
+    assert!(BlockchainManager::validate_transaction_before_broadcast(&Transaction::from_json(&tx)));
+    let (valid, reason) = manager.security.validate_transaction(&tx);
+    assert!(valid, "{}", reason);
+}
+
+#[test]
+fn test_json_round_trip() {
+    let mut wallet = LunaWallet::create("Savings", "hunter2");
+    assert_eq!(wallet.get_metadata(), &serde_json::json!({}));
+    wallet.set_label("Rainy day");
+    wallet.set_metadata(serde_json::json!({"colour": "blue"}));
+    wallet.balance = 2.5;
+    wallet.available_balance = 2.0;
+    wallet.unlock("hunter2").unwrap();
+    let document = wallet.to_json();
+    let keys: Vec<&str> = document.as_object().unwrap().keys().map(String::as_str).collect();
+    assert_eq!(keys, ["address", "available_balance", "balance", "created", "encrypted_private_key", "label", "metadata", "public_key", "version"]);
+    assert_eq!(document["version"], super::WALLET_FORMAT_VERSION);
+
+    let mut restored = LunaWallet::from_json(&document).unwrap();
+    assert_eq!((restored.address.as_str(), restored.label.as_str(), restored.created), (wallet.address.as_str(), "Rainy day", wallet.created));
+    assert_eq!((restored.balance, restored.available_balance), (2.5, 2.0));
+    assert_eq!(restored.get_metadata()["colour"], "blue");
+    assert!(restored.is_locked());
+    restored.unlock("hunter2").unwrap();
+    assert!(LunaWallet::from_json(&serde_json::json!({"label": "no address"})).is_err());
+}
+
+#[test]
+fn test_older_and_newer_documents() {
+    // As WalletDatabase stored wallets before documents were versioned
+    let old = serde_json::json!({"address": "LUN_2bd806c97f0e00af2bf5", "label": "old", "encrypted_private_key": "token", "created": 1700000000.0, "metadata": null, "last_accessed": 1.0});
+    let wallet = LunaWallet::from_json(&old).unwrap();
+    assert_eq!((wallet.version, wallet.created, wallet.balance, wallet.public_key.as_str()), (super::WALLET_FORMAT_VERSION, 1700000000, 0.0, ""));
+    assert_eq!(wallet.encrypted_private_key, b"token");
+    assert_eq!(wallet.get_metadata(), &serde_json::json!({}));
+
+    let newer = serde_json::json!({"address": "LUN_2bd806c97f0e00af2bf5", "version": super::WALLET_FORMAT_VERSION + 1});
+    assert!(LunaWallet::from_json(&newer).err().unwrap().contains("newer"));
+}
+
+#[test]
+fn test_watch_only_cannot_unlock_or_sign() {
+    let address = LunaWallet::create("elsewhere", "hunter2").address;
+    let mut wallet = LunaWallet::watch_only(&address, "cold storage").unwrap();
+    assert_eq!((wallet.kind, wallet.encrypted_private_key.len()), (WalletKind::WatchOnly, 0));
+    assert_eq!(wallet.unlock("hunter2"), Err(WalletError::WatchOnly));
+    assert_eq!(wallet.sign("data"), Err(WalletError::WatchOnly));
+    assert_eq!(wallet.change_password("hunter2", "hunter3"), Err(WalletError::WatchOnly));
+    let mut tx = HashMap::from([("from".to_string(), serde_json::json!(address))]);
+    assert_eq!(wallet.sign_transaction(&mut tx), Err(WalletError::WatchOnly));
+    assert!(!tx.contains_key("signature"));
+
+    let document = wallet.to_json();
+    assert_eq!(document["kind"], "watch_only");
+    assert!(LunaWallet::from_json(&document).unwrap().is_watch_only());
+    assert!(LunaWallet::create("main", "hunter2").to_json().get("kind").is_none());
+    assert_eq!(LunaWallet::watch_only("LUN_abc", "").err(), Some(AddressError::WrongLength(7)));
+}
+
+#[test]
+fn test_mnemonic_derivation_is_deterministic() {
+    let phrase = LunaWallet::generate_mnemonic(12).unwrap();
+    assert_eq!(phrase.split(' ').count(), 12);
+    let first = LunaWallet::from_mnemonic(&phrase, 0, "Main", "hunter2").unwrap();
+    let again = LunaWallet::from_mnemonic(&format!("  {}\n", phrase.to_uppercase()), 0, "Copy", "other").unwrap();
+    assert_eq!((first.address.as_str(), first.public_key.as_str()), (again.address.as_str(), again.public_key.as_str()));
+    assert!(first.address.starts_with("LUN_"));
+    let second = LunaWallet::from_mnemonic(&phrase, 1, "Second", "hunter2").unwrap();
+    assert_ne!(second.address, first.address);
+
+    let mut unlocked = LunaWallet::from_mnemonic(&phrase, 0, "Main", "hunter2").unwrap();
+    unlocked.unlock("hunter2").unwrap();
+    assert!(Crypto::new().verify_signature("hello", &unlocked.sign("hello").unwrap(), &first.public_key));
+    assert_eq!(LunaWallet::generate_mnemonic(24).unwrap().split(' ').count(), 24);
+}
+
+#[test]
+fn test_invalid_mnemonics() {
+    for count in [0, 11, 13, 25] {
+        assert!(matches!(LunaWallet::generate_mnemonic(count), Err(WalletError::InvalidMnemonic(_))), "{}", count);
+    }
+    let bad_words = "notaword ".repeat(12);
+    let bad_checksum = "abandon ".repeat(12);
+    for phrase in ["", "   ", "abandon abandon abandon", bad_checksum.as_str(), bad_words.as_str()] {
+        let error = LunaWallet::from_mnemonic(phrase, 0, "Bad", "pw").err().unwrap();
+        assert!(matches!(error, WalletError::InvalidMnemonic(_)), "{:?}", phrase);
+        assert!(error.to_string().starts_with("Invalid seed phrase"));
+    }
+}
+
+#[test]
+fn test_validate_address() {
+    let wallet = LunaWallet::create("Savings", "hunter2");
+    assert_eq!(wallet.address.len(), 24, "{}", wallet.address);
+    assert_eq!(LunaWallet::validate_address(&wallet.address), Ok(()));
+    assert_eq!(LunaWallet::validate_address(&wallet.address.to_uppercase()), Ok(()));
+
+    let flipped = if &wallet.address[6..7] == "0" { "1" } else { "0" };
+    let mistyped = format!("{}{}{}", &wallet.address[..6], flipped, &wallet.address[7..]);
+    assert_eq!(LunaWallet::validate_address(&mistyped), Err(AddressError::ChecksumMismatch));
+    assert_eq!(LunaWallet::validate_address(&wallet.address[..23]), Err(AddressError::WrongLength(23)));
+    assert_eq!(LunaWallet::validate_address(&wallet.address[4..]), Err(AddressError::MissingPrefix));
+    assert_eq!(LunaWallet::validate_address("LUN_nobody"), Err(AddressError::InvalidCharacter('n')));
+    assert_eq!(LunaWallet::validate_address(""), Err(AddressError::MissingPrefix));
+
+    let legacy = &wallet.address[..20];
+    assert_eq!(LunaWallet::validate_address(legacy), Ok(()));
+    assert_eq!(LunaWallet::validate_address_with(legacy, false), Err(AddressError::MissingChecksum));
+    let strict = TransactionManager::new().with_legacy_addresses(false);
+    assert!(TransactionManager::new().create_transaction(legacy, &wallet.address, 1.0, "", "transfer").is_ok());
+    assert_eq!(strict.create_transaction(legacy, &wallet.address, 1.0, "", "transfer").unwrap_err(), AddressError::MissingChecksum);
+
+    let error = TransactionManager::new().create_transaction(&wallet.address, &mistyped, 1.0, "", "transfer").unwrap_err();
+    assert_eq!(error.to_string(), "Address checksum does not match; check it was copied in full");
+}
+
+#[test]
+fn test_signed_message_proves_ownership() {
+    let mut wallet = LunaWallet::create("main", "hunter2");
+    let other = LunaWallet::create("other", "hunter2");
+    assert_eq!(wallet.sign_message("hello"), Err(WalletError::Locked));
+    wallet.unlock("hunter2").unwrap();
+    let sig = wallet.sign_message("I control this address").unwrap();
+    assert_eq!(sig.public_key, wallet.public_key);
+    assert!(verify_message(&wallet.address, "I control this address", &sig));
+
+    // The key must hash to the claimed address
+    assert!(!verify_message(&other.address, "I control this address", &sig));
+    let borrowed = SignedMessage { public_key: other.public_key.clone(), ..sig.clone() };
+    assert!(!verify_message(&wallet.address, "I control this address", &borrowed));
+    assert!(!verify_message("LUN_nobody", "I control this address", &sig));
+    let malformed = SignedMessage { signature: "not a signature".to_string(), ..sig.clone() };
+    assert!(!verify_message(&wallet.address, "I control this address", &malformed));
+
+    // The signature must be the key's, over this message
+    assert!(!verify_message(&wallet.address, "I control another address", &sig));
+    let mut other = other;
+    other.unlock("hunter2").unwrap();
+    let foreign = other.sign_message("I control this address").unwrap();
+    assert_eq!(foreign.signature.len(), sig.signature.len());
+    let forged = SignedMessage { signature: foreign.signature, ..sig.clone() };
+    assert!(!verify_message(&wallet.address, "I control this address", &forged));
+
+    let json = serde_json::to_string(&sig).unwrap();
+    assert!(json.contains("\"timestamp\""));
+    assert_eq!(serde_json::from_str::<SignedMessage>(&json).unwrap(), sig);
+}
+
+#[test]
+fn test_history_merges_pending_and_confirmed() {
+    use crate::core::wallet_manager::{TransactionDirection, TransactionStatus, TransactionType};
+    use crate::storage::database::WalletDatabase;
+    use serde_json::json;
+
+    let dir = tempfile::tempdir().unwrap();
+    let db = WalletDatabase::new(Some(dir.path().join("wallets.db")));
+    let wallet = LunaWallet::create("main", "hunter2");
+    let me = wallet.address.as_str();
+    let tx = |hash: &str, tx_type: &str, from: &str, to: &str, timestamp: u64| json!({"hash": hash, "type": tx_type, "from": from, "to": to, "amount": 1.0, "fee": 0.1, "timestamp": timestamp});
+    db.save_transaction(&tx("b", "transfer", "LUN_other", me, 100), me);
+    db.save_transaction(&tx("a", "reward", "network", me, 100), me);
+    db.save_transaction(&tx("c", "transfer", me, "LUN_other", 200), me);
+    db.save_pending_transaction(&tx("c", "transfer", me, "LUN_other", 200), me);
+    db.save_pending_transaction(&tx("d", "transfer", me, me, 300), me);
+
+    let history = wallet.get_history(&db, 10, 0);
+    let summary: Vec<_> = history.iter().map(|t| (t.hash.as_str(), t.status.clone(), t.direction)).collect();
+    assert_eq!(summary, vec![
+        ("d", TransactionStatus::Pending, TransactionDirection::SelfTransfer),
+        ("c", TransactionStatus::Confirmed, TransactionDirection::Outgoing),
+        ("a", TransactionStatus::Confirmed, TransactionDirection::Incoming),
+        ("b", TransactionStatus::Confirmed, TransactionDirection::Incoming),
+    ]);
+    assert_eq!(history[2].tx_type, TransactionType::Reward);
+
+    let pages: Vec<_> = (0..4).step_by(2).flat_map(|offset| wallet.get_history(&db, 2, offset)).collect();
+    assert_eq!(pages, history);
+    assert!(wallet.get_history(&db, 10, 4).is_empty());
+}
+
+#[test]
+fn test_unlock_for_relocks_after_deadline() {
+    use std::time::Duration;
+
+    let mut wallet = LunaWallet::create("daemon", "hunter2");
+    assert_eq!(wallet.unlock_for("hunter3", Duration::from_secs(60)), Err(WalletError::WrongPassword));
+    wallet.unlock_for("hunter2", Duration::from_millis(20)).unwrap();
+    assert!(wallet.is_unlocked());
+    assert!(wallet.sign("hello").is_ok());
+    std::thread::sleep(Duration::from_millis(40));
+    assert!(!wallet.is_unlocked());
+    let mut tx = std::collections::HashMap::new();
+    tx.insert("from".to_string(), serde_json::json!(wallet.address));
+    assert_eq!(wallet.sign_transaction(&mut tx), Err(WalletError::Locked));
+    assert!(wallet.is_locked());
+
+    // A plain unlock has no deadline, even after an earlier unlock_for
+    wallet.unlock_for("hunter2", Duration::from_millis(1)).unwrap();
+    wallet.unlock("hunter2").unwrap();
+    std::thread::sleep(Duration::from_millis(5));
+    assert!(wallet.is_unlocked());
+}
+
+#[tokio::test]
+async fn test_send_checks_funds_then_broadcasts_and_records() {
+    use super::SendError;
+    use crate::storage::database::WalletDatabase;
+
+    const RECIPIENT: &str = "LUN_81b637d8fcd2c6dafcca";
+    let mut server = mockito::Server::new_async().await;
+    let dir = tempfile::tempdir().unwrap();
+    let db = WalletDatabase::new(Some(dir.path().join("wallets.db")));
+    let tx_mgr = TransactionManager::new().with_database(db.clone());
+    let blockchain = BlockchainManager::new(&server.url(), 1);
+    let fee = tx_mgr.fee_calculator.get_fee("transfer");
+
+    let mut wallet = LunaWallet::create("main", "hunter2");
+    wallet.available_balance = 1.0;
+    assert!(matches!(wallet.send("LUN_nobody", 0.5, "", &blockchain, &tx_mgr).await, Err(SendError::InvalidAddress(_))));
+    assert_eq!(wallet.send(RECIPIENT, 1.0, "", &blockchain, &tx_mgr).await, Err(SendError::InsufficientFunds { available: 1.0, required: 1.0 + fee }));
+    assert_eq!(wallet.send(RECIPIENT, 0.5, "", &blockchain, &tx_mgr).await, Err(SendError::Wallet(WalletError::Locked)));
+
+    wallet.unlock("hunter2").unwrap();
+    let refused = server.mock("POST", "/mempool/add").with_status(500).create_async().await;
+    assert!(matches!(wallet.send(RECIPIENT, 0.5, "", &blockchain, &tx_mgr).await, Err(SendError::Broadcast(_))));
+    assert!(db.get_pending_transactions(&wallet.address).is_empty());
+    refused.remove_async().await;
+
+    let accepted = server.mock("POST", "/mempool/add").with_status(200).with_body(r#"{"status": "accepted"}"#).expect(1).create_async().await;
+    let hash = wallet.send(RECIPIENT, 0.5, "rent", &blockchain, &tx_mgr).await.unwrap();
+    accepted.assert_async().await;
+    let pending = db.get_pending_transactions(&wallet.address);
+    assert_eq!((pending.len(), pending[0]["hash"].as_str(), pending[0]["memo"].as_str()), (1, Some(hash.as_str()), Some("rent")));
+    // Left for refresh_balance, which sees the spend in the mempool
+    assert_eq!(wallet.available_balance, 1.0);
+}
+
+#[tokio::test]
+async fn test_refresh_balance_scans_the_chain() {
+    let mut wallet = LunaWallet::create("main", "hunter2");
+    let me = wallet.address.clone();
+    let tx = |hash: &str, tx_type: &str, from: &str, to: &str, amount: f64, fee: f64| json!({"hash": hash, "type": tx_type, "from": from, "to": to, "amount": amount, "fee": fee, "timestamp": 1_700_000_000});
+    let blocks = [
+        vec![],
+        vec![tx("r1", "reward", "network", &me, 50.0, 0.0), tx("t1", "transfer", "LUN_other", &me.to_lowercase(), 10.0, 0.1)],
+        vec![tx("t2", "transfer", &me, "LUN_other", 5.0, 0.1), tx("t3", "transfer", "LUN_other", "LUN_third", 99.0, 0.1)],
+    ];
+    let mut server = mockito::Server::new_async().await;
+    let tip = json!({"blocks": [{"index": 2}]});
+    server.mock("GET", "/blockchain/blocks").with_body(tip.to_string()).create_async().await;
+    for (height, transactions) in blocks.iter().enumerate() {
+        let block = json!({"index": height, "hash": format!("h{}", height), "previous_hash": "", "timestamp": 0, "transactions": transactions});
+        server.mock("GET", format!("/blockchain/block/{}", height).as_str()).with_body(block.to_string()).create_async().await;
+    }
+    let waiting = json!([tx("p1", "transfer", &me, "LUN_other", 2.0, 0.1), tx("p2", "transfer", "LUN_other", &me, 3.0, 0.1)]);
+    let mempool = server.mock("GET", "/mempool").with_body(waiting.to_string()).create_async().await;
+    let blockchain = BlockchainManager::new(&server.url(), 2);
+
+    let balance = wallet.refresh_balance(&blockchain).await.unwrap();
+    let lun = |text: &str| text.parse::<crate::core::amount::Amount>().unwrap();
+    assert_eq!((balance.confirmed_balance, balance.available_balance, balance.projected_balance), (lun("54.9"), lun("52.8"), lun("55.8")));
+    assert_eq!((balance.totals.total_rewards_earned, balance.totals.total_received, balance.totals.total_fees_paid), (lun("50"), lun("10"), lun("0.1")));
+    assert!((wallet.balance - 54.9).abs() < 1e-9 && (wallet.available_balance - 52.8).abs() < 1e-9);
+
+    // Without a mempool listing nothing is pending
+    mempool.remove_async().await;
+    server.mock("GET", "/mempool").with_status(404).create_async().await;
+    server.mock("GET", "/mempool/transactions").with_status(404).create_async().await;
+    let balance = wallet.refresh_balance(&blockchain).await.unwrap();
+    assert_eq!((balance.available_balance, balance.pending_outgoing), (lun("54.9"), lun("0")));
+    assert!((wallet.available_balance - 54.9).abs() < 1e-9);
+
+    let unreachable = BlockchainManager::new("http://127.0.0.1:9", 1);
+    assert!(wallet.refresh_balance(&unreachable).await.is_err());
+    assert!((wallet.balance - 54.9).abs() < 1e-9);
+}
@@ -0,0 +1,424 @@
+//! C ABI over `LunaContext`, built with the `cabi` feature.
+//!
+//! Every function returns a `LunaStatus`; on failure `luna_last_error_message` describes
+//! the most recent error on the calling thread. Strings are UTF-8 and NUL terminated.
+//! Strings the library returns are owned by the caller and freed with `luna_string_free`;
+//! handles are freed with their own `_free` function. Panics are caught and reported as
+//! `LUNA_STATUS_PANIC`, so none unwind into the caller.
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::ffi::{CStr, CString, c_char, c_void};
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::Arc;
+use serde_json::{json, Value as JsonValue};
+use crate::core::wallet::{LunaWallet, WalletError};
+use crate::luna_lib::{LunaContext, LunaError, LunaLib};
+use crate::mining::miner::MiningProgress;
+
+/// Result of every fallible call
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LunaStatus {
+    Ok = 0,
+    /// A pointer argument was null
+    NullArgument = 1,
+    /// A string argument was not UTF-8
+    InvalidUtf8 = 2,
+    /// An argument or the JSON passed in was refused
+    InvalidArgument = 3,
+    WrongPassword = 4,
+    /// The wallet must be unlocked first
+    Locked = 5,
+    Crypto = 6,
+    /// The endpoint or a peer could not be reached or refused the request
+    Network = 7,
+    Mempool = 8,
+    Mining = 9,
+    Storage = 10,
+    Bill = 11,
+    /// The wallet is watch-only and has no private key to unlock or sign with
+    WatchOnly = 12,
+    /// The library panicked; the handles involved should be freed
+    Panic = 99,
+}
+
+/// A `LunaContext` owned by the caller
+pub struct LunaContextHandle(LunaContext);
+
+/// A wallet owned by the caller; locked until `luna_wallet_unlock`
+pub struct LunaWalletHandle(LunaWallet);
+
+/// Where a mining job is, as passed to `LunaProgressFn`
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct LunaMiningProgress {
+    pub attempts: u64,
+    pub hashrate: f64,
+    pub elapsed_secs: f64,
+}
+
+/// Called from the mining threads, possibly several at once, with the `user_data` given to `luna_mine_bill`
+pub type LunaProgressFn = Option<unsafe extern "C" fn(progress: *const LunaMiningProgress, user_data: *mut c_void)>;
+
+struct FfiError {
+    status: LunaStatus,
+    message: String,
+}
+
+impl FfiError {
+    fn new(status: LunaStatus, message: impl Into<String>) -> Self {
+        FfiError { status, message: message.into() }
+    }
+}
+
+impl From<LunaError> for FfiError {
+    fn from(e: LunaError) -> Self {
+        let status = match &e {
+            LunaError::Storage(_) | LunaError::Io(_) => LunaStatus::Storage,
+            LunaError::Crypto(_) => LunaStatus::Crypto,
+            LunaError::Blockchain(_) | LunaError::P2P(_) => LunaStatus::Network,
+            LunaError::Mempool(_) => LunaStatus::Mempool,
+            LunaError::Mining(_) => LunaStatus::Mining,
+            LunaError::Gtx(_) => LunaStatus::Bill,
+            LunaError::Validation(_) => LunaStatus::InvalidArgument,
+        };
+        FfiError::new(status, e.to_string())
+    }
+}
+
+impl From<WalletError> for FfiError {
+    fn from(e: WalletError) -> Self {
+        let status = match e {
+            WalletError::WrongPassword => LunaStatus::WrongPassword,
+            WalletError::Locked => LunaStatus::Locked,
+            WalletError::WatchOnly => LunaStatus::WatchOnly,
+            WalletError::CorruptKey => LunaStatus::Crypto,
+            WalletError::InvalidMnemonic(_) | WalletError::InvalidKeystore(_) => LunaStatus::InvalidArgument,
+            WalletError::Storage(_) => LunaStatus::Storage,
+        };
+        FfiError::new(status, e.to_string())
+    }
+}
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<String>> = const { RefCell::new(None) };
+}
+
+/// Run `body`, turning its error or panic into a status and the thread's last error
+fn guard(body: impl FnOnce() -> Result<(), FfiError>) -> LunaStatus {
+    let result = panic::catch_unwind(AssertUnwindSafe(body)).unwrap_or_else(|payload| {
+        let reason = payload
+            .downcast_ref::<&str>()
+            .map(|s| s.to_string())
+            .or_else(|| payload.downcast_ref::<String>().cloned())
+            .unwrap_or_else(|| "unknown cause".to_string());
+        Err(FfiError::new(LunaStatus::Panic, format!("Panicked: {}", reason)))
+    });
+    match result {
+        Ok(()) => LunaStatus::Ok,
+        Err(e) => {
+            LAST_ERROR.with(|last| *last.borrow_mut() = Some(e.message));
+            e.status
+        }
+    }
+}
+
+unsafe fn str_arg<'a>(ptr: *const c_char, name: &str) -> Result<&'a str, FfiError> {
+    if ptr.is_null() {
+        return Err(FfiError::new(LunaStatus::NullArgument, format!("{} must not be null", name)));
+    }
+    unsafe { CStr::from_ptr(ptr) }.to_str().map_err(|_| FfiError::new(LunaStatus::InvalidUtf8, format!("{} is not valid UTF-8", name)))
+}
+
+/// Like `str_arg`, with null read as an empty string
+unsafe fn optional_str_arg<'a>(ptr: *const c_char, name: &str) -> Result<&'a str, FfiError> {
+    if ptr.is_null() { Ok("") } else { unsafe { str_arg(ptr, name) } }
+}
+
+unsafe fn handle<'a, T>(ptr: *mut T, name: &str) -> Result<&'a mut T, FfiError> {
+    unsafe { ptr.as_mut() }.ok_or_else(|| FfiError::new(LunaStatus::NullArgument, format!("{} must not be null", name)))
+}
+
+unsafe fn tx_arg(ptr: *const c_char) -> Result<HashMap<String, JsonValue>, FfiError> {
+    let json = unsafe { str_arg(ptr, "tx_json") }?;
+    serde_json::from_str(json).map_err(|e| FfiError::new(LunaStatus::InvalidArgument, format!("tx_json is not a transaction object: {}", e)))
+}
+
+/// Hand `value` to the caller through `out`
+unsafe fn write_out<T>(out: *mut T, value: T) -> Result<(), FfiError> {
+    if out.is_null() {
+        return Err(FfiError::new(LunaStatus::NullArgument, "output pointer must not be null"));
+    }
+    unsafe { out.write(value) };
+    Ok(())
+}
+
+unsafe fn write_string(out: *mut *mut c_char, value: String) -> Result<(), FfiError> {
+    let value = CString::new(value).map_err(|_| FfiError::new(LunaStatus::InvalidArgument, "string contains a NUL byte"))?;
+    unsafe { write_out(out, value.into_raw()) }
+}
+
+/// The library version, as a static string the caller must not free
+#[unsafe(no_mangle)]
+pub extern "C" fn luna_version() -> *const c_char {
+    concat!(env!("CARGO_PKG_VERSION"), "\0").as_ptr().cast()
+}
+
+/// The message of the most recent failure on this thread, or null if there was none.
+/// Free it with `luna_string_free`.
+#[unsafe(no_mangle)]
+pub extern "C" fn luna_last_error_message() -> *mut c_char {
+    LAST_ERROR.with(|last| last.borrow().as_ref().and_then(|message| CString::new(message.as_str()).ok()).map_or(std::ptr::null_mut(), CString::into_raw))
+}
+
+/// Free a string returned by the library; null is ignored
+///
+/// # Safety
+/// `s` must come from this library and not have been freed already.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn luna_string_free(s: *mut c_char) {
+    if !s.is_null() {
+        drop(unsafe { CString::from_raw(s) });
+    }
+}
+
+/// Open a context on `endpoint_url`, keeping its databases in `data_dir` (null for ~/.luna_wallet)
+///
+/// # Safety
+/// String arguments must be null or NUL terminated; `out_context` must be writable.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn luna_context_new(endpoint_url: *const c_char, data_dir: *const c_char, out_context: *mut *mut LunaContextHandle) -> LunaStatus {
+    guard(|| {
+        let mut builder = LunaLib::builder().with_endpoint_url(unsafe { str_arg(endpoint_url, "endpoint_url") }?);
+        if !data_dir.is_null() {
+            builder = builder.with_data_dir(unsafe { str_arg(data_dir, "data_dir") }?);
+        }
+        let context = Box::new(LunaContextHandle(builder.build()?));
+        unsafe { write_out(out_context, Box::into_raw(context)) }
+    })
+}
+
+/// Stop any mining and free the context; null is ignored
+///
+/// # Safety
+/// `context` must come from `luna_context_new` and not have been freed already.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn luna_context_free(context: *mut LunaContextHandle) {
+    if !context.is_null() {
+        let _ = panic::catch_unwind(AssertUnwindSafe(|| {
+            let mut context = unsafe { Box::from_raw(context) };
+            context.0.shutdown();
+        }));
+    }
+}
+
+/// Create and store a wallet whose private key is encrypted under `password`
+///
+/// # Safety
+/// `context` must be live, strings NUL terminated and `out_wallet` writable.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn luna_wallet_create(
+    context: *mut LunaContextHandle,
+    label: *const c_char,
+    password: *const c_char,
+    out_wallet: *mut *mut LunaWalletHandle,
+) -> LunaStatus {
+    guard(|| {
+        let context = unsafe { handle(context, "context") }?;
+        let label = unsafe { optional_str_arg(label, "label") }?;
+        let wallet = context.0.create_wallet(label, unsafe { str_arg(password, "password") }?)?;
+        unsafe { write_out(out_wallet, Box::into_raw(Box::new(LunaWalletHandle(wallet)))) }
+    })
+}
+
+/// Open a stored wallet by address
+///
+/// # Safety
+/// `context` must be live, `address` NUL terminated and `out_wallet` writable.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn luna_wallet_open(context: *mut LunaContextHandle, address: *const c_char, out_wallet: *mut *mut LunaWalletHandle) -> LunaStatus {
+    guard(|| {
+        let context = unsafe { handle(context, "context") }?;
+        let address = unsafe { str_arg(address, "address") }?;
+        let wallet = context
+            .0
+            .database()
+            .load_wallet(address)
+            .ok_or_else(|| FfiError::new(LunaStatus::InvalidArgument, format!("No wallet with address {}", address)))?;
+        unsafe { write_out(out_wallet, Box::into_raw(Box::new(LunaWalletHandle(wallet)))) }
+    })
+}
+
+/// The stored wallets as a JSON array of `{address, label, public_key, balance, created, kind}`;
+/// `kind` is `"full"` or `"watch_only"`
+///
+/// # Safety
+/// `context` must be live and `out_json` writable.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn luna_wallet_list(context: *mut LunaContextHandle, out_json: *mut *mut c_char) -> LunaStatus {
+    guard(|| {
+        let context = unsafe { handle(context, "context") }?;
+        let wallets: Vec<JsonValue> = context
+            .0
+            .database()
+            .list_wallets()
+            .iter()
+            .map(|w| json!({"address": w.address, "label": w.label, "public_key": w.public_key, "balance": w.balance, "created": w.created, "kind": w.kind}))
+            .collect();
+        unsafe { write_string(out_json, JsonValue::Array(wallets).to_string()) }
+    })
+}
+
+/// Decrypt the wallet's private key; `LUNA_STATUS_WRONG_PASSWORD` if `password` does not
+///
+/// # Safety
+/// `wallet` must be live and `password` NUL terminated.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn luna_wallet_unlock(wallet: *mut LunaWalletHandle, password: *const c_char) -> LunaStatus {
+    guard(|| {
+        let wallet = unsafe { handle(wallet, "wallet") }?;
+        wallet.0.unlock(unsafe { str_arg(password, "password") }?)?;
+        Ok(())
+    })
+}
+
+/// Forget the decrypted private key
+///
+/// # Safety
+/// `wallet` must be live.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn luna_wallet_lock(wallet: *mut LunaWalletHandle) -> LunaStatus {
+    guard(|| {
+        unsafe { handle(wallet, "wallet") }?.0.lock();
+        Ok(())
+    })
+}
+
+/// The wallet's `LUN_` address
+///
+/// # Safety
+/// `wallet` must be live and `out_address` writable.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn luna_wallet_address(wallet: *mut LunaWalletHandle, out_address: *mut *mut c_char) -> LunaStatus {
+    guard(|| {
+        let wallet = unsafe { handle(wallet, "wallet") }?;
+        unsafe { write_string(out_address, wallet.0.address.clone()) }
+    })
+}
+
+/// Free the wallet, zeroing its decrypted key; null is ignored
+///
+/// # Safety
+/// `wallet` must come from this library and not have been freed already.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn luna_wallet_free(wallet: *mut LunaWalletHandle) {
+    if !wallet.is_null() {
+        drop(unsafe { Box::from_raw(wallet) });
+    }
+}
+
+/// An unsigned transfer as a JSON object, for `luna_transaction_sign`. `memo` may be null.
+///
+/// # Safety
+/// `context` must be live, strings null or NUL terminated and `out_json` writable.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn luna_transaction_create(
+    context: *mut LunaContextHandle,
+    from: *const c_char,
+    to: *const c_char,
+    amount: f64,
+    memo: *const c_char,
+    out_json: *mut *mut c_char,
+) -> LunaStatus {
+    guard(|| {
+        let context = unsafe { handle(context, "context") }?;
+        let (from, to) = unsafe { (str_arg(from, "from")?, str_arg(to, "to")?) };
+        let memo = unsafe { optional_str_arg(memo, "memo") }?;
+        if !amount.is_finite() || amount <= 0.0 {
+            return Err(FfiError::new(LunaStatus::InvalidArgument, format!("amount must be positive, not {}", amount)));
+        }
+        let tx = context.0.transactions().create_transaction(from, to, amount, memo, "transfer").map_err(LunaError::from)?;
+        unsafe { write_string(out_json, json!(tx).to_string()) }
+    })
+}
+
+/// Sign `tx_json` with an unlocked wallet it is from, writing the signed transaction
+///
+/// # Safety
+/// `wallet` must be live, `tx_json` NUL terminated and `out_json` writable.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn luna_transaction_sign(wallet: *mut LunaWalletHandle, tx_json: *const c_char, out_json: *mut *mut c_char) -> LunaStatus {
+    guard(|| {
+        let wallet = unsafe { handle(wallet, "wallet") }?;
+        let mut tx = unsafe { tx_arg(tx_json) }?;
+        let from = tx.get("from").and_then(|v| v.as_str()).unwrap_or_default();
+        if from != wallet.0.address {
+            return Err(FfiError::new(LunaStatus::InvalidArgument, format!("Transaction is from {}, not {}", from, wallet.0.address)));
+        }
+        wallet.0.sign_transaction(&mut tx)?;
+        unsafe { write_string(out_json, json!(tx).to_string()) }
+    })
+}
+
+/// Queue a signed transaction locally and send it to the endpoint, writing its hash
+///
+/// # Safety
+/// `context` must be live, `tx_json` NUL terminated and `out_hash` writable.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn luna_transaction_broadcast(context: *mut LunaContextHandle, tx_json: *const c_char, out_hash: *mut *mut c_char) -> LunaStatus {
+    guard(|| {
+        let context = unsafe { handle(context, "context") }?;
+        let tx = unsafe { tx_arg(tx_json) }?;
+        let hash = context.0.submit_transaction(&tx)?;
+        unsafe { write_string(out_hash, hash) }
+    })
+}
+
+/// Mine and register a bill for `address`, writing it as JSON. `progress` may be null.
+///
+/// # Safety
+/// `context` must be live, `address` NUL terminated and `out_bill_json` writable.
+/// `user_data` is passed to `progress` untouched and must stay valid until this returns.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn luna_mine_bill(
+    context: *mut LunaContextHandle,
+    denomination: u64,
+    address: *const c_char,
+    progress: LunaProgressFn,
+    user_data: *mut c_void,
+    out_bill_json: *mut *mut c_char,
+) -> LunaStatus {
+    guard(|| {
+        let context = unsafe { handle(context, "context") }?;
+        let address = unsafe { str_arg(address, "address") }?;
+        let bill = match progress {
+            Some(callback) => {
+                let user_data = UserData(user_data);
+                context.0.mine_bill_with_progress(
+                    denomination,
+                    address,
+                    Arc::new(move |p: &MiningProgress| {
+                        let report = LunaMiningProgress { attempts: p.attempts, hashrate: p.hashrate, elapsed_secs: p.elapsed_secs };
+                        unsafe { callback(&report, user_data.get()) };
+                    }),
+                )?
+            }
+            None => context.0.mine_bill(denomination, address)?,
+        };
+        unsafe { write_string(out_bill_json, json!(bill).to_string()) }
+    })
+}
+
+/// The caller's `user_data`, which the caller vouches for across mining threads
+#[derive(Clone, Copy)]
+struct UserData(*mut c_void);
+
+unsafe impl Send for UserData {}
+unsafe impl Sync for UserData {}
+
+impl UserData {
+    /// Taking `self` makes closures capture the whole wrapper rather than the raw pointer
+    fn get(self) -> *mut c_void {
+        self.0
+    }
+}
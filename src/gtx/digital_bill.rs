@@ -1,9 +1,9 @@
 
-use crate::gtx::bill_registry::BillRegistry;
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine as _};
 use serde::{Deserialize, Serialize};
 use serde_json::Value as JsonValue;
 use sha2::{Digest, Sha256};
-use std::time::{SystemTime, UNIX_EPOCH};
+use crate::utils::clock::{Clock, SystemClock};
 use rand::{distributions::Alphanumeric, Rng};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -37,7 +37,7 @@ impl DigitalBill {
         public_key: Option<String>,
         signature: Option<String>,
     ) -> Self {
-        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs_f64();
+        let now = SystemClock.now_secs_f64();
         let bill_serial = front_serial.clone().unwrap_or_else(|| Self::generate_serial(denomination));
         let metadata_hash = metadata_hash.unwrap_or_else(|| Self::generate_metadata_hash(
             denomination,
@@ -65,7 +65,7 @@ impl DigitalBill {
     }
 
     fn generate_serial(denomination: u64) -> String {
-        let timestamp = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_millis();
+        let timestamp = SystemClock.now_millis();
         let random_part: String = rand::thread_rng().sample_iter(&Alphanumeric).take(8).map(char::from).collect();
         format!("GTX{}_{}_{}", denomination, timestamp, random_part)
     }
@@ -113,7 +113,7 @@ impl DigitalBill {
             "mining_difficulty": self.difficulty,
             "mining_time": mining_time,
             "hash": hash,
-            "timestamp": SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs_f64(),
+            "timestamp": SystemClock.now_secs_f64(),
             "status": "mined",
             "front_serial": self.front_serial,
             "issued_to": self.user_address,
@@ -125,7 +125,7 @@ impl DigitalBill {
             self.public_key = Some(Self::derive_public_key(pk));
             self.signature = Some(sig.clone());
         }
-        let bill_info = serde_json::json!({
+        serde_json::json!({
             "success": true,
             "bill_serial": self.bill_serial,
             "denomination": self.denomination,
@@ -134,18 +134,14 @@ impl DigitalBill {
             "difficulty": self.difficulty,
             "hash": hash,
             "nonce": nonce,
-            "timestamp": SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs_f64(),
+            "timestamp": SystemClock.now_secs_f64(),
             "luna_value": self.denomination,
             "transaction_data": transaction_data
-        });
-        let reg = BillRegistry::new(None);
-        // Note: BillRegistry expects a BillInfo struct, so conversion is needed for real use
-        // reg.register_bill(...)
-        bill_info
+        })
     }
 
     fn get_previous_hash() -> String {
-        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs_f64();
+        let now = SystemClock.now_secs_f64();
         let mut hasher = Sha256::new();
         hasher.update(now.to_string().as_bytes());
         format!("{:x}", hasher.finalize())
@@ -163,6 +159,16 @@ impl DigitalBill {
         })
     }
 
+    /// The whole bill as URL-safe base64 of its JSON, short enough for a URI or QR code
+    pub fn to_compact(&self) -> String {
+        URL_SAFE_NO_PAD.encode(serde_json::to_vec(self).unwrap())
+    }
+
+    pub fn from_compact(encoded: &str) -> Result<Self, String> {
+        let json = URL_SAFE_NO_PAD.decode(encoded).map_err(|e| format!("Bill is not base64: {}", e))?;
+        serde_json::from_slice(&json).map_err(|e| format!("Bill is not valid: {}", e))
+    }
+
     pub fn calculate_hash(&self) -> String {
         let bill_string = serde_json::to_string(&self.to_dict()).unwrap();
         let mut hasher = Sha256::new();
@@ -180,7 +186,7 @@ impl DigitalBill {
     }
 
     pub fn verify(&self) -> bool {
-        if let (Some(ref pk), Some(ref sig)) = (&self.public_key, &self.signature) {
+        if let (Some(pk), Some(sig)) = (&self.public_key, &self.signature) {
             let expected = self.sign(pk);
             &expected == sig
         } else {
@@ -1,5 +1,6 @@
 use crate::gtx::digital_bill::DigitalBill;
-use crate::gtx::bill_registry::BillRegistry;
+use crate::gtx::bill_registry::{BillInfo, BillRegistry};
+use crate::luna_lib::{LunaError, LunaResult};
 use serde_json::{json, Value as JsonValue};
 use std::collections::HashMap;
 use chrono::Utc;
@@ -12,8 +13,13 @@ pub struct GTXGenesis {
 
 impl GTXGenesis {
     pub fn new() -> Self {
+        Self::from_registry(BillRegistry::new(None))
+    }
+
+    /// Issue and verify bills against `bill_registry` instead of the default one
+    pub fn from_registry(bill_registry: BillRegistry) -> Self {
         GTXGenesis {
-            bill_registry: BillRegistry::new(None),
+            bill_registry,
             valid_denominations: vec![1, 10, 100, 1000, 10000, 100000, 1000000, 10000000, 100000000],
         }
     }
@@ -38,13 +44,31 @@ impl GTXGenesis {
     }
 
     pub fn verify_bill(&self, bill_serial: &str) -> JsonValue {
+        self.check_bill(bill_serial, false)
+    }
+
+    /// Like `verify_bill`, but without the catch-all fallback, and the bill's
+    /// mining hash must meet its difficulty
+    pub fn verify_bill_strict(&self, bill_serial: &str) -> JsonValue {
+        self.check_bill(bill_serial, true)
+    }
+
+    fn check_bill(&self, bill_serial: &str, strict: bool) -> JsonValue {
         if bill_serial.is_empty() {
             return json!({"valid": false, "error": "Invalid bill serial"});
         }
-        let bill_record = match self.bill_registry.get_bill(bill_serial) {
-            Ok(Some(b)) => b,
-            _ => return json!({"valid": false, "error": "Bill not found in registry"}),
-        };
+        match self.bill_registry.get_bill(bill_serial) {
+            Ok(Some(bill_record)) => self.verify_bill_info(&bill_record, strict),
+            _ => json!({"valid": false, "error": "Bill not found in registry"}),
+        }
+    }
+
+    /// Verify a bill record that need not be in the registry, such as an imported certificate
+    pub fn verify_bill_info(&self, bill_record: &BillInfo, strict: bool) -> JsonValue {
+        let bill_serial = bill_record.bill_serial.as_str();
+        if strict && !bill_record.hash.starts_with(&"0".repeat(bill_record.difficulty.max(0) as usize)) {
+            return json!({"valid": false, "error": "Mining hash does not meet the bill's difficulty"});
+        }
         let bill_data = bill_record.metadata.clone();
         if bill_data.is_null() {
             return json!({"valid": false, "error": "No bill data found in metadata"});
@@ -117,12 +141,51 @@ impl GTXGenesis {
             return json!({"valid": true, "bill": bill_serial, "verification_method": "bill_json_hash"});
         }
         // Fallback: accept any non-empty signature
-        if !signature.is_empty() && signature.len() > 10 {
+        if !strict && !signature.is_empty() && signature.len() > 10 {
             return json!({"valid": true, "bill": bill_serial, "verification_method": "fallback_accept"});
         }
         json!({"valid": false, "error": "Signature verification failed"})
     }
 
+    /// Ask `endpoint` whether it knows the bill, by its mining hash
+    pub fn verify_bill_remote(&self, endpoint: &str, bill_serial: &str) -> LunaResult<JsonValue> {
+        let bill = self
+            .bill_registry
+            .get_bill(bill_serial)?
+            .ok_or_else(|| LunaError::Gtx("Bill not found in registry".to_string()))?;
+        let url = format!("{}/verify/{}", endpoint.trim_end_matches('/'), bill.hash);
+        let res = reqwest::blocking::get(&url).map_err(|e| LunaError::Blockchain(format!("Cannot reach {}: {}", endpoint, e)))?;
+        if !res.status().is_success() {
+            return Err(LunaError::Blockchain(format!("Remote verification failed: HTTP {}", res.status())));
+        }
+        res.json().map_err(|e| LunaError::Blockchain(format!("Invalid verification response: {}", e)))
+    }
+
+    /// Record a bill returned by `GenesisMiner::mine_bill_parallel` for `user_address`,
+    /// keeping its mining hash as the signature so it verifies later
+    pub fn register_mined_bill(&self, mined: &HashMap<String, JsonValue>, denomination: u64, user_address: &str, difficulty: u32) -> LunaResult<BillInfo> {
+        let field = |key: &str| mined.get(key).cloned().unwrap_or(JsonValue::Null);
+        let mut metadata = field("bill");
+        metadata["signature"] = field("metadata_hash");
+        metadata["nonce"] = field("nonce");
+        let info = BillInfo {
+            bill_serial: field("bill_serial").as_str().unwrap_or_default().to_string(),
+            denomination: denomination as i64,
+            user_address: user_address.to_string(),
+            hash: field("hash").as_str().unwrap_or_default().to_string(),
+            mining_time: field("mining_time").as_f64().unwrap_or(0.0),
+            difficulty: i64::from(difficulty),
+            luna_value: denomination as f64,
+            timestamp: metadata["timestamp"].as_f64().unwrap_or(0.0),
+            verification_url: String::new(),
+            image_url: String::new(),
+            metadata,
+            status: "active".to_string(),
+        };
+        self.bill_registry.register_bill(info.clone())?;
+        Ok(info)
+    }
+
     pub fn get_user_portfolio(&self, user_address: &str) -> JsonValue {
         let bills = self.bill_registry.get_user_bills(user_address).unwrap_or_default();
         let total_value: f64 = bills.iter().map(|b| b.luna_value).sum();
@@ -136,6 +199,11 @@ impl GTXGenesis {
     }
 
     pub fn calculate_difficulty(&self, denomination: u64) -> u32 {
+        Self::difficulty_for(denomination)
+    }
+
+    /// The mining difficulty of a bill of `denomination`, without needing a registry
+    pub fn difficulty_for(denomination: u64) -> u32 {
         match denomination {
             0..=1 => 2,
             2..=10 => 3,
@@ -173,4 +241,33 @@ mod tests {
         assert_eq!(portfolio["user_address"], "user1");
         assert!(portfolio["breakdown"].as_object().is_some());
     }
+
+    #[test]
+    fn test_strict_verification_skips_fallback() {
+        let dir = tempfile::tempdir().unwrap();
+        let gtx = GTXGenesis::from_registry(BillRegistry::new(Some(dir.path().join("bills.db"))));
+        let bill = |serial: &str, hash: &str, signature: &str| BillInfo {
+            bill_serial: serial.to_string(),
+            denomination: 1,
+            user_address: "user1".to_string(),
+            hash: hash.to_string(),
+            mining_time: 0.0,
+            difficulty: 2,
+            luna_value: 1.0,
+            timestamp: 0.0,
+            verification_url: String::new(),
+            image_url: String::new(),
+            metadata: json!({"metadata_hash": "meta", "signature": signature}),
+            status: "active".to_string(),
+        };
+        gtx.bill_registry.register_bill(bill("good", "00ab", "meta")).unwrap();
+        gtx.bill_registry.register_bill(bill("forged", "00ab", "not-a-real-signature")).unwrap();
+        gtx.bill_registry.register_bill(bill("cheap", "0fab", "meta")).unwrap();
+
+        assert_eq!(gtx.verify_bill_strict("good")["valid"], true);
+        assert_eq!(gtx.verify_bill("forged")["verification_method"], "fallback_accept");
+        assert_eq!(gtx.verify_bill_strict("forged")["valid"], false);
+        assert_eq!(gtx.verify_bill("cheap")["valid"], true);
+        assert_eq!(gtx.verify_bill_strict("cheap")["valid"], false);
+    }
 }
@@ -1,3 +1,5 @@
+#[cfg(not(target_arch = "wasm32"))]
 pub mod genesis;
+#[cfg(not(target_arch = "wasm32"))]
 pub mod bill_registry;
 pub mod digital_bill;
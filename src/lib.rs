@@ -1,17 +1,20 @@
 pub mod core;
+#[cfg(not(target_arch = "wasm32"))]
 pub mod mining;
 pub mod gtx;
 pub mod storage;
 pub mod transactions;
 pub mod utils;
+#[cfg(not(target_arch = "wasm32"))]
 pub mod luna_lib;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod cli;
+#[cfg(feature = "cabi")]
+pub mod ffi;
+#[cfg(feature = "wasm")]
+pub mod wasm;
+#[cfg(all(feature = "python", not(target_arch = "wasm32")))]
+pub mod python;
 
-/// Main library struct exposing all LunaLib functionality
-pub struct LunaLib;
-
-impl LunaLib {
-    pub fn version() -> &'static str {
-        "0.1.0"
-    }
-    // Add more methods as needed
-}
+#[cfg(not(target_arch = "wasm32"))]
+pub use luna_lib::LunaLib;
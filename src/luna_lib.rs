@@ -5,24 +5,35 @@
 //! ## Quick Start Example
 //!
 //! ```rust
-//! use lunalib_rust::luna_lib::*;
+//! use lunalib::luna_lib::*;
 //!
-//! // Create a wallet
-//! let wallet = create_wallet();
+//! // Wire every subsystem to one endpoint and data directory
+//! let mut luna = LunaLib::builder()
+//!     .with_endpoint_url("https://bank.linglin.art")
+//!     .with_data_dir(std::env::temp_dir().join("luna-quick-start"))
+//!     .with_password_provider(|_address: &str| Some("correct horse".to_string()))
+//!     .build()?;
+//! // Create and store a wallet
+//! let wallet = luna.create_wallet("main", "correct horse")?;
 //! // Create a miner
 //! let miner = create_miner();
 //! // Create a blockchain manager
-//! let blockchain = create_blockchain_manager(Some("https://bank.linglin.art"));
+//! let blockchain = create_blockchain_manager(Some("https://bank.linglin.art"))?;
 //! // Create a mempool manager
-//! let mempool = create_mempool_manager(None);
+//! let mempool = create_mempool_manager(None)?;
 //! // Get a transaction manager
 //! let tx_manager = get_transaction_manager();
 //! // Print version
-//! println!("{}", LunaLib::get_version());
+//! println!("{} {}", wallet.address, LunaLib::get_version());
+//! luna.shutdown();
+//! # Ok::<(), LunaError>(())
 //! ```
 //!
 //! Main library entry point exposing all core functionality
 //!
+use std::any::type_name;
+use std::fmt;
+use crate::core::daemon::Daemon;
 use crate::core::wallet::LunaWallet;
 use crate::mining::miner::GenesisMiner;
 use crate::gtx::genesis::GTXGenesis;
@@ -30,48 +41,131 @@ use crate::transactions::transactions::TransactionManager;
 use crate::core::blockchain::BlockchainManager;
 use crate::core::mempool::MempoolManager;
 
+pub mod config;
+pub mod context;
+pub mod error;
+pub mod events;
+
+pub use config::{ConfigError, LunaConfig};
+pub use context::{LunaContext, LunaLibBuilder, PasswordProvider};
+pub use error::{LunaError, LunaResult};
+pub use events::{EventBus, LunaEvent, PeerChange};
+
+/// Features this build was compiled with, out of those the crate knows about
+const KNOWN_FEATURES: [(&str, bool); 6] = [
+    ("cuda", cfg!(feature = "cuda")),
+    ("cabi", cfg!(feature = "cabi")),
+    ("wasm", cfg!(feature = "wasm")),
+    ("python", cfg!(feature = "python")),
+    ("p2p-server", cfg!(feature = "p2p-server")),
+    ("daemon-server", cfg!(feature = "daemon-server")),
+];
+
 pub struct LunaLib;
 
+/// One of the library's main types
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ComponentInfo {
+    pub name: &'static str,
+    /// Full path of the type, such as `lunalib::core::wallet::LunaWallet`
+    pub type_path: &'static str,
+    pub description: &'static str,
+}
+
+impl ComponentInfo {
+    fn of<T>(name: &'static str, description: &'static str) -> Self {
+        ComponentInfo { name, type_path: type_name::<T>(), description }
+    }
+
+    /// `type_path` without the type name
+    pub fn module_path(&self) -> &'static str {
+        self.type_path.rsplit_once("::").map_or("", |(module, _)| module)
+    }
+}
+
+/// What was built, for bug reports and `--version` output
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BuildInfo {
+    pub version: &'static str,
+    /// Commit the crate was built from, when built inside a git checkout
+    pub git_hash: Option<&'static str>,
+    pub features: Vec<&'static str>,
+    pub target: &'static str,
+}
+
+impl fmt::Display for BuildInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.version)?;
+        if let Some(hash) = self.git_hash {
+            write!(f, " ({})", hash)?;
+        }
+        write!(f, " {}", self.target)?;
+        if !self.features.is_empty() {
+            write!(f, " [{}]", self.features.join(", "))?;
+        }
+        Ok(())
+    }
+}
+
 impl LunaLib {
-    /// Get the current library version
+    /// Start configuring a `LunaContext`
+    pub fn builder() -> LunaLibBuilder {
+        LunaLibBuilder::new()
+    }
+
+    /// The crate version from Cargo.toml
     pub fn get_version() -> &'static str {
-        "1.0.0"
+        env!("CARGO_PKG_VERSION")
+    }
+
+    #[deprecated(note = "use LunaLib::get_version")]
+    pub fn version() -> &'static str {
+        Self::get_version()
+    }
+
+    pub fn build_info() -> BuildInfo {
+        BuildInfo {
+            version: Self::get_version(),
+            git_hash: option_env!("LUNALIB_GIT_HASH"),
+            features: KNOWN_FEATURES.iter().filter(|(_, enabled)| *enabled).map(|(name, _)| *name).collect(),
+            target: env!("LUNALIB_TARGET"),
+        }
     }
 
-    /// Get list of all available classes in the library
-    pub fn get_available_classes() -> Vec<(&'static str, &'static str)> {
+    /// The library's main types and where they live
+    pub fn get_available_classes() -> Vec<ComponentInfo> {
         vec![
-            ("Wallet", "LunaWallet - Cryptocurrency wallet management"),
-            ("Miner", "GenesisMiner - Mining operations"),
-            ("GTX", "GTXGenesis - GTX token operations"),
-            ("Transaction", "TransactionManager - Transaction handling"),
-            ("Blockchain", "BlockchainManager - Blockchain operations with endpoint support"),
-            ("Mempool", "MempoolManager - Memory Pool management and endpoint"),
+            ComponentInfo::of::<LunaWallet>("Wallet", "Cryptocurrency wallet management"),
+            ComponentInfo::of::<GenesisMiner>("Miner", "Mining operations"),
+            ComponentInfo::of::<GTXGenesis>("GTX", "GTX token operations"),
+            ComponentInfo::of::<TransactionManager>("Transaction", "Transaction handling"),
+            ComponentInfo::of::<BlockchainManager>("Blockchain", "Blockchain operations with endpoint support"),
+            ComponentInfo::of::<MempoolManager>("Mempool", "Memory Pool management and endpoint"),
+            ComponentInfo::of::<Daemon>("Daemon", "Long-running node tying the subsystems together"),
+            ComponentInfo::of::<LunaContext>("Context", "Subsystems wired to one endpoint and data directory"),
         ]
     }
 }
 
-// Convenience constructors
-pub fn create_wallet() -> LunaWallet {
-    LunaWallet::new(
-        "test_address".to_string(),
-        "test_pubkey".to_string(),
-        vec![],
-        "test_label".to_string(),
-        0,
-    )
-}
+// Convenience constructors for standalone use; `LunaLib::builder` wires them together
 
 pub fn create_miner() -> GenesisMiner {
     GenesisMiner::new(None)
 }
 
-pub fn create_blockchain_manager(endpoint_url: Option<&str>) -> BlockchainManager {
-    BlockchainManager::new(endpoint_url.unwrap_or("https://bank.linglin.art"), 1)
+/// Fails if `endpoint_url` is not a valid URL
+pub fn create_blockchain_manager(endpoint_url: Option<&str>) -> LunaResult<BlockchainManager> {
+    let endpoint_url = endpoint_url.unwrap_or(config::DEFAULT_ENDPOINT_URL);
+    reqwest::Url::parse(endpoint_url).map_err(|e| LunaError::Blockchain(format!("Invalid endpoint URL '{}': {}", endpoint_url, e)))?;
+    Ok(BlockchainManager::new(endpoint_url, 1))
 }
 
-pub fn create_mempool_manager(_endpoint_url: Option<&str>) -> MempoolManager {
-    MempoolManager::new()
+/// Fails if `endpoint_url` is given but is not a valid URL
+pub fn create_mempool_manager(endpoint_url: Option<&str>) -> LunaResult<MempoolManager> {
+    if let Some(endpoint_url) = endpoint_url {
+        reqwest::Url::parse(endpoint_url).map_err(|e| LunaError::Mempool(format!("Invalid endpoint URL '{}': {}", endpoint_url, e)))?;
+    }
+    Ok(MempoolManager::new())
 }
 
 pub fn get_transaction_manager() -> TransactionManager {
@@ -84,23 +178,33 @@ mod tests {
 
     #[test]
     fn test_version() {
-        assert_eq!(LunaLib::get_version(), "1.0.0");
+        let manifest = include_str!("../Cargo.toml");
+        let version = manifest.lines().find_map(|line| line.strip_prefix("version = ")).unwrap().trim().trim_matches('"');
+        assert_eq!(LunaLib::get_version(), version);
+        assert_eq!(crate::LunaLib::get_version(), version);
     }
 
     #[test]
-    fn test_available_classes() {
-        let classes = LunaLib::get_available_classes();
-        assert!(classes.iter().any(|(name, _)| *name == "Wallet"));
-        assert!(classes.iter().any(|(name, _)| *name == "Miner"));
-        assert!(classes.iter().any(|(name, _)| *name == "GTX"));
-        assert!(classes.iter().any(|(name, _)| *name == "Transaction"));
-        assert!(classes.iter().any(|(name, _)| *name == "Blockchain"));
-        assert!(classes.iter().any(|(name, _)| *name == "Mempool"));
+    fn test_build_info() {
+        let info = LunaLib::build_info();
+        assert_eq!(info.version, LunaLib::get_version());
+        assert!(!info.target.is_empty());
+        for (feature, enabled) in [("p2p-server", cfg!(feature = "p2p-server")), ("daemon-server", cfg!(feature = "daemon-server"))] {
+            assert_eq!(info.features.contains(&feature), enabled, "{}", feature);
+            assert_eq!(info.to_string().contains(feature), enabled, "{}", info);
+        }
+        assert!(info.to_string().starts_with(info.version));
     }
 
     #[test]
-    fn test_create_wallet() {
-        let _wallet = create_wallet();
+    fn test_available_classes() {
+        let classes = LunaLib::get_available_classes();
+        for name in ["Wallet", "Miner", "GTX", "Transaction", "Blockchain", "Mempool", "Daemon", "Context"] {
+            assert!(classes.iter().any(|c| c.name == name), "{}", name);
+        }
+        let wallet = classes.iter().find(|c| c.name == "Wallet").unwrap();
+        assert_eq!(wallet.module_path(), "lunalib::core::wallet");
+        assert!(wallet.type_path.ends_with("::LunaWallet"));
     }
 
     #[test]
@@ -110,12 +214,14 @@ mod tests {
 
     #[test]
     fn test_create_blockchain_manager() {
-        let _manager = create_blockchain_manager(None);
+        let _manager = create_blockchain_manager(None).unwrap();
+        assert!(matches!(create_blockchain_manager(Some("not a url")), Err(LunaError::Blockchain(_))));
     }
 
     #[test]
     fn test_create_mempool_manager() {
-        let _manager = create_mempool_manager(None);
+        let _manager = create_mempool_manager(None).unwrap();
+        assert!(matches!(create_mempool_manager(Some("bank")), Err(LunaError::Mempool(_))));
     }
 
     #[test]
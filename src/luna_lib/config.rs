@@ -0,0 +1,421 @@
+//! One configuration for every subsystem, read from defaults, a TOML file and the environment.
+//!
+//! `LunaConfig::load` layers, later sources winning:
+//!
+//! 1. the built-in defaults,
+//! 2. `~/.luna_wallet/config.toml`, when it exists,
+//! 3. `LUNA_<SECTION>_<FIELD>` environment variables, such as `LUNA_NETWORK_ENDPOINT_URL`
+//!    for `network.endpoint_url` or `LUNA_MINING_THREADS` for `mining.threads`.
+//!
+//! A file only needs the fields it changes:
+//!
+//! ```toml
+//! [network]
+//! endpoint_url = "http://127.0.0.1:9000"
+//! timeout_secs = "30s"
+//!
+//! [fees]
+//! priority = "high"
+//!
+//! [security]
+//! max_amount = 5000.0
+//! ```
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::Duration;
+use serde::{Deserialize, Serialize};
+use crate::core::blockchain::DEFAULT_REQUEST_TIMEOUT;
+use crate::core::daemon_config::{seconds_or_duration, DaemonConfig, MiningConfig};
+use crate::luna_lib::error::LunaError;
+use crate::transactions::security::SecurityPolicy;
+use crate::transactions::transactions::{FeeCalculator, FeePriority};
+use crate::utils::format::parse_duration;
+
+pub const DEFAULT_ENDPOINT_URL: &str = "https://bank.linglin.art";
+
+/// Fields `LunaConfig::load` reads from `LUNA_*` environment variables
+const ENV_FIELDS: [&str; 15] = [
+    "network.endpoint_url",
+    "network.timeout_secs",
+    "network.peer_url",
+    "network.max_peers",
+    "storage.data_dir",
+    "storage.wallets_db",
+    "storage.bills_db",
+    "fees.transfer",
+    "fees.priority",
+    "mining.enabled",
+    "mining.address",
+    "mining.threads",
+    "security.min_amount",
+    "security.max_amount",
+    "security.required_fee",
+];
+
+/// Why a configuration could not be loaded
+#[derive(Debug)]
+pub enum ConfigError {
+    /// The config file exists but could not be read
+    Io { path: PathBuf, error: io::Error },
+    /// The config file is not TOML, or has fields `LunaConfig` does not know
+    Parse { path: PathBuf, message: String },
+    /// An environment variable does not parse as its field's type
+    Env { var: String, reason: String },
+    /// A field holds a value the library cannot work with
+    Invalid { field: &'static str, reason: String },
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Io { path, error } => write!(f, "Cannot read {}: {}", path.display(), error),
+            ConfigError::Parse { path, message } => write!(f, "Invalid config in {}: {}", path.display(), message),
+            ConfigError::Env { var, reason } => write!(f, "Invalid {}: {}", var, reason),
+            ConfigError::Invalid { field, reason } => write!(f, "Invalid config: {} {}", field, reason),
+        }
+    }
+}
+
+impl Error for ConfigError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            ConfigError::Io { error, .. } => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<ConfigError> for LunaError {
+    fn from(e: ConfigError) -> Self {
+        match e {
+            ConfigError::Io { path, error } => LunaError::Io(io::Error::new(error.kind(), format!("Cannot read {}: {}", path.display(), error))),
+            e => LunaError::Validation(e.to_string()),
+        }
+    }
+}
+
+/// Where the node is reached and how patiently
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct NetworkConfig {
+    pub endpoint_url: String,
+    /// Per-request timeout; whole seconds, or a duration such as "30s"
+    #[serde(deserialize_with = "seconds_or_duration")]
+    pub timeout_secs: u64,
+    /// URL peers reach this node at; a daemon only starts P2P when set
+    pub peer_url: Option<String>,
+    pub max_peers: usize,
+}
+
+impl Default for NetworkConfig {
+    fn default() -> Self {
+        NetworkConfig {
+            endpoint_url: DEFAULT_ENDPOINT_URL.to_string(),
+            timeout_secs: DEFAULT_REQUEST_TIMEOUT.as_secs(),
+            peer_url: None,
+            max_peers: 128,
+        }
+    }
+}
+
+impl NetworkConfig {
+    pub fn timeout(&self) -> Duration {
+        Duration::from_secs(self.timeout_secs)
+    }
+}
+
+/// Where the databases live
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct StorageConfig {
+    pub data_dir: PathBuf,
+    /// Defaults to `wallets.db` in `data_dir`
+    pub wallets_db: Option<PathBuf>,
+    /// Defaults to `bills.db` in `data_dir`
+    pub bills_db: Option<PathBuf>,
+}
+
+impl Default for StorageConfig {
+    fn default() -> Self {
+        StorageConfig { data_dir: dirs::home_dir().unwrap_or_default().join(".luna_wallet"), wallets_db: None, bills_db: None }
+    }
+}
+
+impl StorageConfig {
+    pub fn wallets_db_path(&self) -> PathBuf {
+        self.wallets_db.clone().unwrap_or_else(|| self.data_dir.join("wallets.db"))
+    }
+
+    pub fn bills_db_path(&self) -> PathBuf {
+        self.bills_db.clone().unwrap_or_else(|| self.data_dir.join("bills.db"))
+    }
+}
+
+/// What transfers created through a `LunaContext` pay
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct FeeConfig {
+    /// Base fee of a transfer, before the priority multiplier
+    pub transfer: f64,
+    pub priority: FeePriority,
+}
+
+impl Default for FeeConfig {
+    fn default() -> Self {
+        FeeConfig { transfer: FeeCalculator::new().get_fee("transfer"), priority: FeePriority::default() }
+    }
+}
+
+/// Settings for the whole library; see the module docs for how they are loaded
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LunaConfig {
+    pub network: NetworkConfig,
+    pub storage: StorageConfig,
+    pub fees: FeeConfig,
+    pub mining: MiningConfig,
+    /// Policy for transaction types without one of their own
+    pub security: SecurityPolicy,
+}
+
+impl LunaConfig {
+    /// `~/.luna_wallet/config.toml`, when there is a home directory
+    pub fn default_path() -> Option<PathBuf> {
+        dirs::home_dir().map(|home| home.join(".luna_wallet").join("config.toml"))
+    }
+
+    /// Defaults, overridden by the default config file, overridden by the process environment
+    pub fn load() -> Result<Self, ConfigError> {
+        Self::load_from(Self::default_path().as_deref(), std::env::vars())
+    }
+
+    /// Like `load`, with an explicit file and environment. A missing file is skipped;
+    /// variables that are not `LUNA_*` fields are ignored.
+    pub fn load_from<I>(path: Option<&Path>, env: I) -> Result<Self, ConfigError>
+    where
+        I: IntoIterator<Item = (String, String)>,
+    {
+        let mut config = match path {
+            Some(path) => Self::read_file(path)?.unwrap_or_default(),
+            None => Self::default(),
+        };
+        for (var, value) in env {
+            if let Some(field) = ENV_FIELDS.iter().find(|field| env_var(field) == var) {
+                config.set(field, &value).map_err(|reason| ConfigError::Env { var, reason })?;
+            }
+        }
+        config.validate()?;
+        Ok(config)
+    }
+
+    pub fn from_toml(text: &str) -> Result<Self, ConfigError> {
+        let config: Self = toml::from_str(text).map_err(|e| ConfigError::Parse { path: PathBuf::new(), message: e.to_string() })?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// Reject values the library cannot run with, naming the offending field
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        let invalid = |field: &'static str, reason: &str| Err(ConfigError::Invalid { field, reason: reason.to_string() });
+        if let Err(e) = reqwest::Url::parse(&self.network.endpoint_url) {
+            return invalid("network.endpoint_url", &format!("is not a valid URL: {}", e));
+        }
+        if self.network.timeout_secs == 0 {
+            return invalid("network.timeout_secs", "must be at least 1");
+        }
+        if let Some(peer_url) = &self.network.peer_url
+            && let Err(e) = reqwest::Url::parse(peer_url)
+        {
+            return invalid("network.peer_url", &format!("is not a valid URL: {}", e));
+        }
+        if self.network.max_peers == 0 {
+            return invalid("network.max_peers", "must be at least 1");
+        }
+        if !self.fees.transfer.is_finite() || self.fees.transfer < 0.0 {
+            return invalid("fees.transfer", "must be a non-negative number");
+        }
+        if self.mining.threads == 0 {
+            return invalid("mining.threads", "must be at least 1");
+        }
+        if self.mining.enabled && self.mining.address.is_empty() {
+            return invalid("mining.address", "is required when mining is enabled");
+        }
+        if self.security.min_amount < 0.0 {
+            return invalid("security.min_amount", "must not be negative");
+        }
+        if self.security.max_amount < self.security.min_amount {
+            return invalid("security.max_amount", "must not be below security.min_amount");
+        }
+        if self.security.required_fee < 0.0 {
+            return invalid("security.required_fee", "must not be negative");
+        }
+        Ok(())
+    }
+
+    /// The file's settings over the defaults, or None when there is no file
+    fn read_file(path: &Path) -> Result<Option<Self>, ConfigError> {
+        let text = match fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(error) => return Err(ConfigError::Io { path: path.to_path_buf(), error }),
+        };
+        toml::from_str(&text).map(Some).map_err(|e| ConfigError::Parse { path: path.to_path_buf(), message: e.to_string() })
+    }
+
+    /// Set one of `ENV_FIELDS` from its text form
+    fn set(&mut self, field: &str, value: &str) -> Result<(), String> {
+        match field {
+            "network.endpoint_url" => self.network.endpoint_url = value.to_string(),
+            "network.timeout_secs" => {
+                self.network.timeout_secs = value.parse().or_else(|_| parse_duration(value).map(|d| d.as_secs()))?
+            }
+            "network.peer_url" => self.network.peer_url = Some(value.to_string()).filter(|url| !url.is_empty()),
+            "network.max_peers" => self.network.max_peers = parse(value)?,
+            "storage.data_dir" => self.storage.data_dir = PathBuf::from(value),
+            "storage.wallets_db" => self.storage.wallets_db = Some(PathBuf::from(value)),
+            "storage.bills_db" => self.storage.bills_db = Some(PathBuf::from(value)),
+            "fees.transfer" => self.fees.transfer = parse(value)?,
+            "fees.priority" => self.fees.priority = value.parse()?,
+            "mining.enabled" => self.mining.enabled = parse(value)?,
+            "mining.address" => self.mining.address = value.to_string(),
+            "mining.threads" => self.mining.threads = parse(value)?,
+            "security.min_amount" => self.security.min_amount = parse(value)?,
+            "security.max_amount" => self.security.max_amount = parse(value)?,
+            "security.required_fee" => self.security.required_fee = parse(value)?,
+            _ => unreachable!("{} is not in ENV_FIELDS", field),
+        }
+        Ok(())
+    }
+}
+
+/// The daemon's share of the settings; its remaining fields keep their defaults
+impl From<&LunaConfig> for DaemonConfig {
+    fn from(config: &LunaConfig) -> Self {
+        DaemonConfig {
+            endpoint_url: config.network.endpoint_url.clone(),
+            data_dir: config.storage.data_dir.clone(),
+            wallets_db: config.storage.wallets_db.clone(),
+            peer_url: config.network.peer_url.clone(),
+            max_peers: config.network.max_peers,
+            mining: config.mining.clone(),
+            security: config.security.clone(),
+            ..DaemonConfig::default()
+        }
+    }
+}
+
+impl From<LunaConfig> for DaemonConfig {
+    fn from(config: LunaConfig) -> Self {
+        DaemonConfig::from(&config)
+    }
+}
+
+/// `network.endpoint_url` is read from `LUNA_NETWORK_ENDPOINT_URL`
+fn env_var(field: &str) -> String {
+    format!("LUNA_{}", field.replace('.', "_").to_uppercase())
+}
+
+fn parse<T: FromStr>(value: &str) -> Result<T, String>
+where
+    T::Err: fmt::Display,
+{
+    value.trim().parse().map_err(|e| format!("'{}': {}", value, e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::daemon::Daemon;
+
+    fn env(vars: &[(&str, &str)]) -> Vec<(String, String)> {
+        vars.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect()
+    }
+
+    #[test]
+    fn test_env_overrides_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.toml");
+        let text = format!(
+            r#"
+[network]
+endpoint_url = "http://127.0.0.1:9000"
+timeout_secs = "1m"
+
+[storage]
+data_dir = "{}"
+
+[mining]
+threads = 2
+
+[security]
+max_amount = 5000.0
+"#,
+            dir.path().display()
+        );
+        fs::write(&path, text).unwrap();
+
+        let from_file = LunaConfig::load_from(Some(&path), env(&[("HOME", "/nowhere")])).unwrap();
+        assert_eq!(from_file.network.endpoint_url, "http://127.0.0.1:9000");
+        assert_eq!(from_file.network.timeout(), Duration::from_secs(60));
+        assert_eq!(from_file.storage.wallets_db_path(), dir.path().join("wallets.db"));
+        assert_eq!(from_file.mining.threads, 2);
+        assert_eq!(from_file.security.max_amount, 5000.0);
+        assert_eq!(from_file.fees, FeeConfig::default());
+
+        let vars = env(&[("LUNA_NETWORK_ENDPOINT_URL", "http://127.0.0.1:9100"), ("LUNA_MINING_THREADS", "8"), ("LUNA_FEES_PRIORITY", "high")]);
+        let config = LunaConfig::load_from(Some(&path), vars).unwrap();
+        assert_eq!(config.network.endpoint_url, "http://127.0.0.1:9100");
+        assert_eq!(config.mining.threads, 8);
+        assert_eq!(config.fees.priority, FeePriority::High);
+        // Fields the environment does not set keep the file's values
+        assert_eq!(config.security.max_amount, 5000.0);
+
+        let missing = LunaConfig::load_from(Some(&dir.path().join("absent.toml")), env(&[("LUNA_MINING_THREADS", "3")])).unwrap();
+        assert_eq!(missing, LunaConfig { mining: MiningConfig { threads: 3, ..MiningConfig::default() }, ..LunaConfig::default() });
+
+        let error = LunaConfig::load_from(Some(&path), env(&[("LUNA_MINING_THREADS", "many")])).unwrap_err();
+        assert!(matches!(&error, ConfigError::Env { var, .. } if var == "LUNA_MINING_THREADS"), "{}", error);
+        fs::write(&path, "[network]\nendpoint = \"http://127.0.0.1\"").unwrap();
+        assert!(matches!(LunaConfig::load_from(Some(&path), env(&[])), Err(ConfigError::Parse { .. })));
+    }
+
+    #[test]
+    fn test_rejects_malformed_url() {
+        let error = LunaConfig::from_toml("[network]\nendpoint_url = \"bank.linglin.art\"").unwrap_err();
+        assert!(matches!(error, ConfigError::Invalid { field: "network.endpoint_url", .. }), "{}", error);
+        let error = LunaConfig::load_from(None, env(&[("LUNA_NETWORK_PEER_URL", "http://")])).unwrap_err();
+        assert!(matches!(error, ConfigError::Invalid { field: "network.peer_url", .. }), "{}", error);
+        assert!(error.to_string().starts_with("Invalid config: network.peer_url is not a valid URL"), "{}", error);
+        assert!(matches!(LunaError::from(error), LunaError::Validation(_)));
+
+        let error = LunaConfig::from_toml("[mining]\nenabled = true").unwrap_err();
+        assert!(matches!(error, ConfigError::Invalid { field: "mining.address", .. }), "{}", error);
+        let error = LunaConfig::from_toml("[security]\nmin_amount = 10.0\nmax_amount = 1.0").unwrap_err();
+        assert!(matches!(error, ConfigError::Invalid { field: "security.max_amount", .. }), "{}", error);
+    }
+
+    #[test]
+    fn test_drives_context_and_daemon() {
+        let dir = tempfile::tempdir().unwrap();
+        let vars = env(&[
+            ("LUNA_NETWORK_ENDPOINT_URL", "http://127.0.0.1:9"),
+            ("LUNA_STORAGE_DATA_DIR", dir.path().to_str().unwrap()),
+            ("LUNA_STORAGE_BILLS_DB", dir.path().join("gtx").join("bills.db").to_str().unwrap()),
+            ("LUNA_SECURITY_MAX_AMOUNT", "25"),
+        ]);
+        let config = LunaConfig::load_from(None, vars).unwrap();
+
+        let context = crate::LunaLib::builder().with_config(&config).build().unwrap();
+        assert_eq!(context.endpoint_url(), "http://127.0.0.1:9");
+        assert_eq!(context.database().db_path, dir.path().join("wallets.db"));
+        assert!(dir.path().join("gtx").join("bills.db").exists());
+
+        let daemon = Daemon::from_config(&config).unwrap();
+        assert_eq!(daemon.sources.validator.lock().unwrap().security.default_policy.max_amount, 25.0);
+        assert!(daemon.sources.p2p.is_none());
+    }
+}
@@ -0,0 +1,353 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+use serde_json::{json, Value as JsonValue};
+use crate::core::blockchain::{BlockchainManager, DEFAULT_REQUEST_TIMEOUT};
+use crate::core::daemon::Daemon;
+use crate::core::daemon_config::MiningConfig;
+use crate::core::mempool::{MempoolManager, Transaction as MempoolTransaction};
+use crate::core::p2p::P2P;
+use crate::core::shutdown::ShutdownReport;
+use crate::core::wallet::LunaWallet;
+use crate::core::wallet_manager::WalletManager;
+use crate::gtx::bill_registry::{BillInfo, BillRegistry};
+use crate::gtx::genesis::GTXGenesis;
+use crate::luna_lib::config::{FeeConfig, LunaConfig, DEFAULT_ENDPOINT_URL};
+use crate::luna_lib::error::{LunaError, LunaResult};
+use crate::luna_lib::events::{EventBus, LunaEvent};
+use crate::mining::miner::{GenesisMiner, ProgressCallback};
+use crate::storage::database::WalletDatabase;
+use crate::storage::encryption::EncryptionManager;
+use crate::transactions::security::SecurityPolicy;
+use crate::transactions::transactions::{FeePriority, TransactionManager};
+use crate::transactions::validator::TransactionValidator;
+
+/// How long `LunaContext::shutdown` waits for each daemon subsystem
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Supplies the password that unlocks a wallet's private key when the context signs for it
+pub trait PasswordProvider: Send + Sync {
+    fn password(&self, address: &str) -> Option<String>;
+}
+
+impl<F> PasswordProvider for F
+where
+    F: Fn(&str) -> Option<String> + Send + Sync,
+{
+    fn password(&self, address: &str) -> Option<String> {
+        self(address)
+    }
+}
+
+/// Settings shared by every subsystem of a `LunaContext`
+pub struct LunaLibBuilder {
+    endpoint_url: String,
+    data_dir: Option<PathBuf>,
+    wallets_db: Option<PathBuf>,
+    bills_db: Option<PathBuf>,
+    network_timeout: Duration,
+    mining: MiningConfig,
+    fees: FeeConfig,
+    security: SecurityPolicy,
+    password_provider: Option<Arc<dyn PasswordProvider>>,
+    daemon: bool,
+    p2p: Option<P2P>,
+}
+
+impl Default for LunaLibBuilder {
+    fn default() -> Self {
+        LunaLibBuilder {
+            endpoint_url: DEFAULT_ENDPOINT_URL.to_string(),
+            data_dir: None,
+            wallets_db: None,
+            bills_db: None,
+            network_timeout: DEFAULT_REQUEST_TIMEOUT,
+            mining: MiningConfig::default(),
+            fees: FeeConfig::default(),
+            security: SecurityPolicy::default(),
+            password_provider: None,
+            daemon: false,
+            p2p: None,
+        }
+    }
+}
+
+impl LunaLibBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Take the endpoint, storage, fee, mining and security settings from `config`
+    pub fn with_config(mut self, config: &LunaConfig) -> Self {
+        self.endpoint_url = config.network.endpoint_url.clone();
+        self.network_timeout = config.network.timeout();
+        self.data_dir = Some(config.storage.data_dir.clone());
+        self.wallets_db = Some(config.storage.wallets_db_path());
+        self.bills_db = Some(config.storage.bills_db_path());
+        self.fees = config.fees.clone();
+        self.mining = config.mining.clone();
+        self.security = config.security.clone();
+        self
+    }
+
+    pub fn with_endpoint_url(mut self, endpoint_url: &str) -> Self {
+        self.endpoint_url = endpoint_url.to_string();
+        self
+    }
+
+    /// Holds `wallets.db` and `bills.db`; defaults to ~/.luna_wallet
+    pub fn with_data_dir(mut self, data_dir: impl Into<PathBuf>) -> Self {
+        self.data_dir = Some(data_dir.into());
+        self
+    }
+
+    /// Per-request timeout for calls to the endpoint
+    pub fn with_network_timeout(mut self, timeout: Duration) -> Self {
+        self.network_timeout = timeout;
+        self
+    }
+
+    /// Threads for `LunaContext::mine_bill`, and the daemon's miner when enabled
+    pub fn with_mining(mut self, mining: MiningConfig) -> Self {
+        self.mining = mining;
+        self
+    }
+
+    pub fn with_password_provider(mut self, provider: impl PasswordProvider + 'static) -> Self {
+        self.password_provider = Some(Arc::new(provider));
+        self
+    }
+
+    /// Also build a Daemon over the shared blockchain, mempool and database; it is not started
+    pub fn with_daemon(mut self, daemon: bool) -> Self {
+        self.daemon = daemon;
+        self
+    }
+
+    /// Publish this node's peer changes and syncs on the context's event bus; the daemon also refreshes its peers
+    pub fn with_p2p(mut self, p2p: P2P) -> Self {
+        self.p2p = Some(p2p);
+        self
+    }
+
+    pub fn build(self) -> LunaResult<LunaContext> {
+        reqwest::Url::parse(&self.endpoint_url)
+            .map_err(|e| LunaError::Validation(format!("Invalid endpoint URL '{}': {}", self.endpoint_url, e)))?;
+        if self.mining.threads == 0 {
+            return Err(LunaError::Validation("mining.threads must be at least 1".to_string()));
+        }
+        if self.mining.enabled && self.mining.address.is_empty() {
+            return Err(LunaError::Validation("mining.address is required when mining is enabled".to_string()));
+        }
+        let data_dir = self.data_dir.unwrap_or_else(|| dirs::home_dir().unwrap_or_default().join(".luna_wallet"));
+        fs::create_dir_all(&data_dir)?;
+        let wallets_db = self.wallets_db.unwrap_or_else(|| data_dir.join("wallets.db"));
+        let bills_db = self.bills_db.unwrap_or_else(|| data_dir.join("bills.db"));
+        for db in [&wallets_db, &bills_db] {
+            if let Some(parent) = db.parent() {
+                fs::create_dir_all(parent)?;
+            }
+        }
+
+        let blockchain = Arc::new(BlockchainManager::new(&self.endpoint_url, 1).with_timeout(self.network_timeout));
+        let mempool = Arc::new(MempoolManager::new());
+        let database = WalletDatabase::new(Some(wallets_db));
+        let wallet_manager = Arc::new(WalletManager::new());
+        let addresses: Vec<String> = database.list_wallets().into_iter().map(|w| w.address).collect();
+        wallet_manager.register_wallets(&addresses);
+        let events = EventBus::new();
+        if let Some(p2p) = &self.p2p {
+            events.forward_p2p(p2p);
+        }
+        let daemon = self.daemon.then(|| {
+            let mut daemon = Daemon::new()
+                .with_blockchain(Arc::clone(&blockchain))
+                .with_mempool(Arc::clone(&mempool))
+                .with_validator(TransactionValidator::new().with_default_policy(self.security.clone()))
+                .with_database(WalletDatabase::new(Some(database.db_path.clone())))
+                .with_event_bus(events.clone());
+            if let Some(p2p) = &self.p2p {
+                daemon = daemon.with_p2p(p2p.clone());
+            }
+            if self.mining.enabled { daemon.with_miner(GenesisMiner::new(None), self.mining.clone()) } else { daemon }
+        });
+        let mut transactions = TransactionManager::new();
+        transactions.fee_calculator.fee_config.insert("transfer".to_string(), self.fees.transfer);
+        Ok(LunaContext {
+            endpoint_url: self.endpoint_url,
+            genesis: GTXGenesis::from_registry(BillRegistry::new(Some(bills_db))),
+            data_dir,
+            mining: self.mining,
+            blockchain,
+            mempool,
+            database,
+            wallet_manager,
+            transactions,
+            fee_priority: self.fees.priority,
+            miner: Arc::new(GenesisMiner::new(None)),
+            password_provider: self.password_provider,
+            events,
+            daemon,
+        })
+    }
+}
+
+/// The library's subsystems wired to one endpoint and data directory
+pub struct LunaContext {
+    endpoint_url: String,
+    data_dir: PathBuf,
+    mining: MiningConfig,
+    blockchain: Arc<BlockchainManager>,
+    mempool: Arc<MempoolManager>,
+    database: WalletDatabase,
+    genesis: GTXGenesis,
+    wallet_manager: Arc<WalletManager>,
+    transactions: TransactionManager,
+    /// Priority of the transfers `create_transaction` signs
+    fee_priority: FeePriority,
+    miner: Arc<GenesisMiner>,
+    password_provider: Option<Arc<dyn PasswordProvider>>,
+    events: EventBus,
+    daemon: Option<Daemon>,
+}
+
+impl LunaContext {
+    pub fn endpoint_url(&self) -> &str {
+        &self.endpoint_url
+    }
+
+    pub fn data_dir(&self) -> &Path {
+        &self.data_dir
+    }
+
+    pub fn mining(&self) -> &MiningConfig {
+        &self.mining
+    }
+
+    pub fn blockchain(&self) -> &Arc<BlockchainManager> {
+        &self.blockchain
+    }
+
+    pub fn mempool(&self) -> &Arc<MempoolManager> {
+        &self.mempool
+    }
+
+    pub fn database(&self) -> &WalletDatabase {
+        &self.database
+    }
+
+    pub fn bill_registry(&self) -> &BillRegistry {
+        &self.genesis.bill_registry
+    }
+
+    pub fn genesis(&self) -> &GTXGenesis {
+        &self.genesis
+    }
+
+    pub fn wallet_manager(&self) -> &Arc<WalletManager> {
+        &self.wallet_manager
+    }
+
+    pub fn transactions(&self) -> &TransactionManager {
+        &self.transactions
+    }
+
+    pub fn miner(&self) -> &Arc<GenesisMiner> {
+        &self.miner
+    }
+
+    /// Where the context, its daemon and P2P node publish what they do
+    pub fn events(&self) -> &EventBus {
+        &self.events
+    }
+
+    pub fn daemon(&self) -> Option<&Daemon> {
+        self.daemon.as_ref()
+    }
+
+    pub fn daemon_mut(&mut self) -> Option<&mut Daemon> {
+        self.daemon.as_mut()
+    }
+
+    /// Generate a keypair, store it encrypted under `password` and track its balance
+    pub fn create_wallet(&self, label: &str, password: &str) -> LunaResult<LunaWallet> {
+        if password.is_empty() {
+            return Err(LunaError::Validation("Password must not be empty".to_string()));
+        }
+        let wallet = LunaWallet::create(label, password);
+        if !self.database.save_wallet(&wallet) {
+            return Err(LunaError::Io(std::io::Error::other(format!("Could not save wallet to {}", self.database.db_path.display()))));
+        }
+        self.wallet_manager.register_wallet(&wallet.address);
+        self.events.publish(LunaEvent::WalletCreated { address: wallet.address.clone(), label: wallet.label.clone() });
+        Ok(wallet)
+    }
+
+    /// Mine a bill of `denomination` for `address` at its standard difficulty and register it
+    pub fn mine_bill(&self, denomination: u64, address: &str) -> LunaResult<BillInfo> {
+        self.mine_bill_with(&self.miner, denomination, address)
+    }
+
+    /// `mine_bill` on a miner of its own that reports to `progress`, from every mining thread.
+    /// `shutdown` does not stop it.
+    pub fn mine_bill_with_progress(&self, denomination: u64, address: &str, progress: ProgressCallback) -> LunaResult<BillInfo> {
+        self.mine_bill_with(&GenesisMiner::new(None).with_progress_callback(progress), denomination, address)
+    }
+
+    fn mine_bill_with(&self, miner: &GenesisMiner, denomination: u64, address: &str) -> LunaResult<BillInfo> {
+        if !self.genesis.valid_denominations.contains(&denomination) {
+            return Err(LunaError::Validation(format!("Invalid denomination: must be one of {:?}", self.genesis.valid_denominations)));
+        }
+        let difficulty = self.genesis.calculate_difficulty(denomination);
+        let mined = miner
+            .mine_bill_parallel(denomination, address, None, difficulty, self.mining.threads)
+            .ok_or_else(|| LunaError::Mining("Mining stopped before a bill was found".to_string()))?;
+        let bill = self.genesis.register_mined_bill(&mined, denomination, address, difficulty)?;
+        self.events.publish(LunaEvent::BillMined { serial: bill.bill_serial.clone(), denomination, owner: address.to_string() });
+        Ok(bill)
+    }
+
+    /// Sign a transfer from a stored wallet, unlocked with the password provider
+    pub fn create_transaction(&self, from: &str, to: &str, amount: f64, memo: &str) -> LunaResult<HashMap<String, JsonValue>> {
+        let wallet = self.database.load_wallet(from).ok_or_else(|| LunaError::Validation(format!("No wallet with address {}", from)))?;
+        let provider = self.password_provider.as_ref().ok_or_else(|| LunaError::Crypto("No password provider configured".to_string()))?;
+        let password = provider.password(from).ok_or_else(|| LunaError::Crypto(format!("No password for {}", from)))?;
+        let private_key = EncryptionManager::new()
+            .decrypt_data(&String::from_utf8_lossy(&wallet.encrypted_private_key), &password)
+            .ok_or_else(|| LunaError::Crypto(format!("Wrong password for {}", from)))?;
+        let tx = self.transactions.create_priority_transaction(from, to, amount, memo, &private_key, self.fee_priority)?;
+        let (valid, reason) = self.transactions.security.validate_transaction(&tx);
+        if !valid {
+            return Err(LunaError::Validation(format!("Invalid transaction: {}", reason)));
+        }
+        let hash = tx.get("hash").and_then(|v| v.as_str()).unwrap_or_default().to_string();
+        self.events.publish(LunaEvent::TransactionCreated { hash, from: from.to_string(), to: to.to_string(), amount });
+        Ok(tx)
+    }
+
+    /// Queue `tx` in the local mempool and broadcast it, returning its hash.
+    /// A transaction the endpoint refuses is taken back out of the mempool.
+    pub fn submit_transaction(&self, tx: &HashMap<String, JsonValue>) -> LunaResult<String> {
+        let pending = MempoolTransaction::from_json(tx);
+        let hash = pending.hash.clone();
+        if !self.mempool.add_transaction(pending) {
+            return Err(LunaError::Mempool(format!("Transaction {} was rejected", hash)));
+        }
+        if let Err(e) = self.blockchain.submit_transaction(tx) {
+            self.mempool.remove_transaction(&hash);
+            return Err(LunaError::Blockchain(e.to_string()));
+        }
+        let from = tx.get("from").and_then(|v| v.as_str()).unwrap_or_default();
+        self.database.save_pending_transaction(&json!(tx), from);
+        self.events.publish(LunaEvent::TransactionBroadcast { hash: hash.clone() });
+        Ok(hash)
+    }
+
+    /// Stop mining and shut the daemon down, if there is one, flushing its mempool
+    pub fn shutdown(&mut self) -> Option<ShutdownReport> {
+        self.miner.stop_mining();
+        self.daemon.as_mut().map(|daemon| daemon.shutdown(SHUTDOWN_TIMEOUT))
+    }
+}
@@ -0,0 +1,141 @@
+use std::error::Error;
+use std::fmt;
+use std::io;
+use crate::core::p2p::P2PError;
+use crate::core::wallet::{AddressError, WalletError};
+
+/// Result of library calls that span several modules
+pub type LunaResult<T> = Result<T, LunaError>;
+
+/// Any failure the library reports, tagged with the module it came from.
+/// Wrapped errors stay reachable through `source()`.
+#[derive(Debug)]
+pub enum LunaError {
+    /// The wallet or bill database failed
+    Storage(rusqlite::Error),
+    /// Keys, signatures or encryption
+    Crypto(String),
+    /// The blockchain endpoint could not be used
+    Blockchain(String),
+    Mempool(String),
+    Mining(String),
+    /// Bill issuance and verification
+    Gtx(String),
+    /// Input or configuration the library refuses to work with
+    Validation(String),
+    P2P(P2PError),
+    Io(io::Error),
+}
+
+impl fmt::Display for LunaError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LunaError::Storage(e) => write!(f, "Storage error: {}", e),
+            LunaError::Crypto(e) => write!(f, "Crypto error: {}", e),
+            LunaError::Blockchain(e) => write!(f, "Blockchain error: {}", e),
+            LunaError::Mempool(e) => write!(f, "Mempool error: {}", e),
+            LunaError::Mining(e) => write!(f, "Mining error: {}", e),
+            LunaError::Gtx(e) => write!(f, "Bill error: {}", e),
+            LunaError::Validation(e) => write!(f, "Validation failed: {}", e),
+            LunaError::P2P(e) => write!(f, "P2P error: {}", e),
+            LunaError::Io(e) => write!(f, "I/O error: {}", e),
+        }
+    }
+}
+
+impl Error for LunaError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            LunaError::Storage(e) => Some(e),
+            LunaError::P2P(e) => Some(e),
+            LunaError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<rusqlite::Error> for LunaError {
+    fn from(e: rusqlite::Error) -> Self {
+        LunaError::Storage(e)
+    }
+}
+
+impl From<P2PError> for LunaError {
+    fn from(e: P2PError) -> Self {
+        LunaError::P2P(e)
+    }
+}
+
+impl From<WalletError> for LunaError {
+    fn from(e: WalletError) -> Self {
+        LunaError::Crypto(e.to_string())
+    }
+}
+
+impl From<AddressError> for LunaError {
+    fn from(e: AddressError) -> Self {
+        LunaError::Validation(e.to_string())
+    }
+}
+
+impl From<io::Error> for LunaError {
+    fn from(e: io::Error) -> Self {
+        LunaError::Io(e)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::daemon::Daemon;
+    use crate::core::daemon_config::DaemonConfig;
+    use crate::core::p2p::validate_peer_url;
+    use crate::gtx::bill_registry::BillRegistry;
+    use crate::gtx::genesis::GTXGenesis;
+
+    /// Glue returning a boxed error, as an application built on the library would
+    fn check_peer(url: &str) -> Result<(), Box<dyn Error>> {
+        let checked: LunaResult<()> = validate_peer_url(url, false).map_err(LunaError::from);
+        Ok(checked?)
+    }
+
+    #[test]
+    fn test_sqlite_error_surfaces_as_storage() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("bills.db");
+        let genesis = GTXGenesis::from_registry(BillRegistry::new(Some(path.clone())));
+        rusqlite::Connection::open(&path).unwrap().execute("DROP TABLE bills", []).unwrap();
+
+        let error = genesis.verify_bill_remote("http://127.0.0.1:9", "GTX_missing").unwrap_err();
+        assert!(matches!(&error, LunaError::Storage(rusqlite::Error::SqliteFailure(..))), "{:?}", error);
+        assert!(error.to_string().starts_with("Storage error: no such table"), "{}", error);
+        let boxed: Box<dyn Error> = Box::new(error);
+        let luna = boxed.downcast_ref::<LunaError>().unwrap();
+        assert!(luna.source().unwrap().downcast_ref::<rusqlite::Error>().is_some());
+    }
+
+    #[test]
+    fn test_p2p_error_through_boxed_error() {
+        let error = check_peer("http://127.0.0.1:9001").unwrap_err();
+        match error.downcast_ref::<LunaError>() {
+            Some(LunaError::P2P(P2PError::InvalidPeer(reason))) => assert!(reason.contains("private"), "{}", reason),
+            other => panic!("unexpected error: {:?}", other),
+        }
+        let source = error.downcast_ref::<LunaError>().unwrap().source().unwrap();
+        assert!(matches!(source.downcast_ref::<P2PError>(), Some(P2PError::InvalidPeer(_))));
+    }
+
+    #[test]
+    fn test_glue_reports_module() {
+        let config = DaemonConfig { max_peers: 0, ..DaemonConfig::default() };
+        let error = Daemon::from_config(&config).err().unwrap();
+        assert!(matches!(&error, LunaError::Validation(reason) if reason.contains("max_peers")), "{}", error);
+        assert!(error.source().is_none());
+
+        let dir = tempfile::tempdir().unwrap();
+        let config = DaemonConfig { data_dir: dir.path().to_path_buf(), journal_path: Some(dir.path().to_path_buf()), ..DaemonConfig::default() };
+        let error = Daemon::from_config(&config).err().unwrap();
+        assert!(matches!(&error, LunaError::Io(_)), "{}", error);
+        assert!(error.to_string().contains("Cannot open journal"), "{}", error);
+    }
+}
@@ -0,0 +1,164 @@
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use crate::core::p2p::{PeerStatus, P2P};
+use crate::core::p2p_events::{P2PEventKind, RemovalReason};
+
+/// How a peer changed, as reported by `LunaEvent::PeerChanged`
+#[derive(Debug, Clone, PartialEq)]
+pub enum PeerChange {
+    Added { url: String },
+    Removed(RemovalReason),
+    Status { from: PeerStatus, to: PeerStatus },
+}
+
+/// Something that happened in one of the library's subsystems
+#[derive(Debug, Clone, PartialEq)]
+pub enum LunaEvent {
+    WalletCreated { address: String, label: String },
+    /// Signed and validated, not yet sent anywhere
+    TransactionCreated { hash: String, from: String, to: String, amount: f64 },
+    /// Accepted by the endpoint and queued in the local mempool
+    TransactionBroadcast { hash: String },
+    /// Included in a block the daemon validated
+    TransactionConfirmed { hash: String, height: u64 },
+    /// Mined and registered to `owner`
+    BillMined { serial: String, denomination: u64, owner: String },
+    BillTransferred { serial: String, from: String, to: String },
+    BillRedeemed { serial: String, owner: String },
+    /// A block passed validation
+    BlockReceived { height: u64, hash: String },
+    /// `peer` is None when the blocks came from the endpoint
+    SyncCompleted { peer: Option<String>, blocks_added: usize },
+    PeerChanged { node_id: String, change: PeerChange },
+}
+
+impl LunaEvent {
+    /// `LunaEvent::BillMined` is `"bill_mined"`, and so on
+    pub fn name(&self) -> &'static str {
+        match self {
+            LunaEvent::WalletCreated { .. } => "wallet_created",
+            LunaEvent::TransactionCreated { .. } => "transaction_created",
+            LunaEvent::TransactionBroadcast { .. } => "transaction_broadcast",
+            LunaEvent::TransactionConfirmed { .. } => "transaction_confirmed",
+            LunaEvent::BillMined { .. } => "bill_mined",
+            LunaEvent::BillTransferred { .. } => "bill_transferred",
+            LunaEvent::BillRedeemed { .. } => "bill_redeemed",
+            LunaEvent::BlockReceived { .. } => "block_received",
+            LunaEvent::SyncCompleted { .. } => "sync_completed",
+            LunaEvent::PeerChanged { .. } => "peer_changed",
+        }
+    }
+
+    /// The P2P event this corresponds to, for those the bus carries
+    fn from_p2p(kind: P2PEventKind) -> Option<Self> {
+        let (node_id, change) = match kind {
+            P2PEventKind::PeerAdded { node_id, url } => (node_id, PeerChange::Added { url }),
+            P2PEventKind::PeerRemoved { node_id, reason } => (node_id, PeerChange::Removed(reason)),
+            P2PEventKind::PeerStatusChanged { node_id, from, to } => (node_id, PeerChange::Status { from, to }),
+            P2PEventKind::SyncFinished { peer, blocks_added, error: None } => {
+                return Some(LunaEvent::SyncCompleted { peer: Some(peer), blocks_added });
+            }
+            _ => return None,
+        };
+        Some(LunaEvent::PeerChanged { node_id, change })
+    }
+}
+
+type EventFilter = Box<dyn Fn(&LunaEvent) -> bool + Send + Sync>;
+
+struct Subscriber {
+    sender: Sender<LunaEvent>,
+    filter: Option<EventFilter>,
+}
+
+/// Fans events out to every subscriber; clones share the same subscribers
+#[derive(Clone, Default)]
+pub struct EventBus {
+    subscribers: Arc<Mutex<Vec<Subscriber>>>,
+}
+
+impl EventBus {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Receive every event from now on; dropping the receiver unsubscribes
+    pub fn subscribe(&self) -> Receiver<LunaEvent> {
+        self.add_subscriber(None)
+    }
+
+    /// Receive only the events `filter` accepts. The filter runs on the publishing thread
+    /// and must not publish itself.
+    pub fn subscribe_filtered(&self, filter: impl Fn(&LunaEvent) -> bool + Send + Sync + 'static) -> Receiver<LunaEvent> {
+        self.add_subscriber(Some(Box::new(filter)))
+    }
+
+    fn add_subscriber(&self, filter: Option<EventFilter>) -> Receiver<LunaEvent> {
+        let (sender, receiver) = mpsc::channel();
+        self.subscribers.lock().unwrap().push(Subscriber { sender, filter });
+        receiver
+    }
+
+    /// Send to all subscribers. Callers must not hold any subsystem lock.
+    pub fn publish(&self, event: LunaEvent) {
+        self.subscribers.lock().unwrap().retain(|subscriber| {
+            if subscriber.filter.as_ref().is_some_and(|accepts| !accepts(&event)) {
+                return true;
+            }
+            subscriber.sender.send(event.clone()).is_ok()
+        });
+    }
+
+    pub fn subscriber_count(&self) -> usize {
+        self.subscribers.lock().unwrap().len()
+    }
+
+    /// Republish `p2p`'s peer changes and finished syncs until the node is dropped
+    pub fn forward_p2p(&self, p2p: &P2P) {
+        let events = p2p.subscribe_events();
+        let bus = self.clone();
+        thread::spawn(move || {
+            for event in events {
+                if let Some(event) = LunaEvent::from_p2p(event.kind) {
+                    bus.publish(event);
+                }
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_filtered_subscription() {
+        let bus = EventBus::new();
+        let all = bus.subscribe();
+        let bills = bus.subscribe_filtered(|event| matches!(event, LunaEvent::BillMined { .. }));
+        bus.publish(LunaEvent::WalletCreated { address: "LUN_a".to_string(), label: "main".to_string() });
+        bus.publish(LunaEvent::BillMined { serial: "GTX_1".to_string(), denomination: 1, owner: "LUN_a".to_string() });
+
+        assert_eq!(all.try_iter().map(|e| e.name()).collect::<Vec<_>>(), vec!["wallet_created", "bill_mined"]);
+        assert_eq!(bills.try_iter().map(|e| e.name()).collect::<Vec<_>>(), vec!["bill_mined"]);
+
+        drop(all);
+        bus.publish(LunaEvent::BillRedeemed { serial: "GTX_1".to_string(), owner: "LUN_a".to_string() });
+        assert_eq!(bus.subscriber_count(), 1);
+    }
+
+    #[test]
+    fn test_p2p_events_are_translated() {
+        let removed = P2PEventKind::PeerRemoved { node_id: "n1".to_string(), reason: RemovalReason::Pruned };
+        assert_eq!(
+            LunaEvent::from_p2p(removed),
+            Some(LunaEvent::PeerChanged { node_id: "n1".to_string(), change: PeerChange::Removed(RemovalReason::Pruned) })
+        );
+        let synced = P2PEventKind::SyncFinished { peer: "n1".to_string(), blocks_added: 3, error: None };
+        assert_eq!(LunaEvent::from_p2p(synced), Some(LunaEvent::SyncCompleted { peer: Some("n1".to_string()), blocks_added: 3 }));
+        let failed = P2PEventKind::SyncFinished { peer: "n1".to_string(), blocks_added: 1, error: Some("timeout".to_string()) };
+        assert_eq!(LunaEvent::from_p2p(failed), None);
+        assert_eq!(LunaEvent::from_p2p(P2PEventKind::BlockReceived { from: "n1".to_string(), height: 4 }), None);
+    }
+}
@@ -6,6 +6,9 @@ use sha2::Digest;
 
 #[cfg(feature = "cuda")]
 use cust::prelude::*;
+use crate::utils::log::debug;
+#[cfg(feature = "cuda")]
+use crate::utils::log::{info, warn};
 
 #[derive(Debug)]
 pub struct CUDAManager {
@@ -24,14 +27,14 @@ impl CUDAManager {
                     cuda_available = true;
                     let device = Device::get_device(0).unwrap();
                     device_name = Some(device.name().unwrap_or("Unknown").to_string());
-                    println!("✅ CUDA is available for accelerated mining");
+                    info!(device = device_name.as_deref().unwrap_or(""); "CUDA is available for accelerated mining");
                 },
-                Ok(_) => println!("❌ CUDA drivers found but no GPU available"),
-                Err(e) => println!("❌ CUDA check failed: {:?}", e),
+                Ok(_) => warn!("CUDA drivers found but no GPU available"),
+                Err(e) => warn!(error:? = e; "CUDA check failed"),
             }
         }
         #[cfg(not(feature = "cuda"))]
-        println!("❌ CUDA not compiled in (feature 'cuda' missing)");
+        debug!("CUDA not compiled in (feature 'cuda' missing)");
         CUDAManager { cuda_available, device_name }
     }
 
@@ -63,7 +66,7 @@ impl CUDAManager {
             nonce_start += batch_size as u64;
             if nonce_start % (batch_size as u64 * 10) == 0 {
                 let hashrate = nonce_start as f64 / start_time.elapsed().as_secs_f64();
-                println!("⏳ CUDA: {} attempts | {:.0} H/s", nonce_start, hashrate);
+                debug!(attempts = nonce_start, hashrate = hashrate as u64; "CUDA mining progress");
             }
             if start_time.elapsed().as_secs() > 300 {
                 break;
@@ -1,5 +1,9 @@
+use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Clone, Copy)]
+
+/// Serialized as the bare number, like the `difficulty` of a block
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(transparent)]
 pub struct Difficulty {
     pub value: u32,
 }
@@ -41,6 +45,12 @@ mod tests {
         assert_eq!(diff.target_string(), "000");
     }
 
+    #[test]
+    fn test_serializes_as_number() {
+        assert_eq!(serde_json::to_value(Difficulty::new(4)).unwrap(), serde_json::json!(4));
+        assert_eq!(serde_json::from_str::<Difficulty>("2").unwrap(), Difficulty::new(2));
+    }
+
     #[test]
     fn test_is_valid_hash() {
         let diff = Difficulty::new(2);
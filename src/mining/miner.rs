@@ -1,19 +1,63 @@
 
 use std::collections::HashMap;
+use std::fmt;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
 use std::sync::{Arc, Mutex};
 use std::thread;
 use std::time::{Duration, Instant};
+use serde::{Deserialize, Serialize};
 use serde_json::{Value as JsonValue, json};
-use crate::mining::difficulty::Difficulty;
 use sha2::Digest;
 use crate::gtx::digital_bill::DigitalBill;
 use crate::mining::cuda_manager::CUDAManager;
+use crate::core::blockchain::BlockchainManager;
+use crate::utils::log::{debug, info};
+
+/// Attempts between progress reports and stop checks
+const PROGRESS_INTERVAL: u64 = 10_000;
+
+/// Where a running job is, as passed to the progress callback
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MiningProgress {
+    pub attempts: u64,
+    pub hashrate: f64,
+    pub elapsed_secs: f64,
+}
+
+impl MiningProgress {
+    fn since(attempts: u64, start_time: Instant) -> Self {
+        let elapsed_secs = start_time.elapsed().as_secs_f64();
+        let hashrate = if elapsed_secs > 0.0 { attempts as f64 / elapsed_secs } else { 0.0 };
+        MiningProgress { attempts, hashrate, elapsed_secs }
+    }
+}
+
+pub type ProgressCallback = Arc<dyn Fn(&MiningProgress) + Send + Sync>;
+
+/// Hashing throughput measured by `GenesisMiner::benchmark`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BenchmarkReport {
+    pub threads: usize,
+    pub attempts: u64,
+    pub elapsed_secs: f64,
+    pub hashrate: f64,
+}
 
-#[derive(Debug)]
 pub struct GenesisMiner {
     pub mining_active: Arc<Mutex<bool>>,
     pub mining_stats: Arc<Mutex<HashMap<String, u64>>>,
     pub cuda_manager: Option<CUDAManager>,
+    progress: Option<ProgressCallback>,
+}
+
+impl fmt::Debug for GenesisMiner {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("GenesisMiner")
+            .field("mining_active", &self.mining_active)
+            .field("mining_stats", &self.mining_stats)
+            .field("cuda_manager", &self.cuda_manager)
+            .finish_non_exhaustive()
+    }
 }
 
 impl GenesisMiner {
@@ -27,11 +71,50 @@ impl GenesisMiner {
             mining_active: Arc::new(Mutex::new(false)),
             mining_stats: Arc::new(Mutex::new(stats)),
             cuda_manager,
+            progress: None,
         }
     }
 
+    /// Report progress to `callback` instead of printing it
+    pub fn with_progress_callback(mut self, callback: ProgressCallback) -> Self {
+        self.progress = Some(callback);
+        self
+    }
+
+    fn report_progress(&self, job: &str, attempts: u64, start_time: Instant) {
+        let progress = MiningProgress::since(attempts, start_time);
+        match &self.progress {
+            Some(callback) => callback(&progress),
+            None if attempts.is_multiple_of(100_000) => debug!(job = job, attempts = attempts, hashrate = progress.hashrate as u64; "Mining progress"),
+            None => {}
+        }
+    }
+
+    fn is_mining(&self) -> bool {
+        *self.mining_active.lock().unwrap()
+    }
+
+    fn record_attempts(&self, attempts: u64, start_time: Instant) {
+        let mut stats = self.mining_stats.lock().unwrap();
+        *stats.get_mut("total_mining_time").unwrap() += start_time.elapsed().as_secs();
+        *stats.get_mut("total_hash_attempts").unwrap() += attempts;
+    }
+
     pub fn mine_bill(&self, denomination: u64, user_address: &str, bill_data: Option<JsonValue>, difficulty: u32) -> Option<HashMap<String, JsonValue>> {
-        let mut digital_bill = DigitalBill::new(
+        self.mine_bill_parallel(denomination, user_address, bill_data, difficulty, 1)
+    }
+
+    /// Mine a bill on `threads` threads until a hash meets `difficulty` or `stop_mining` is called.
+    /// Attempts count toward the stats either way.
+    pub fn mine_bill_parallel(
+        &self,
+        denomination: u64,
+        user_address: &str,
+        bill_data: Option<JsonValue>,
+        difficulty: u32,
+        threads: usize,
+    ) -> Option<HashMap<String, JsonValue>> {
+        let digital_bill = DigitalBill::new(
             denomination,
             user_address.to_string(),
             difficulty,
@@ -39,69 +122,167 @@ impl GenesisMiner {
             None, None, None, None, None, None,
         );
         let target = "0".repeat(difficulty as usize);
-        let mut nonce = 0u64;
+        let stride = threads.max(1) as u64;
         let start_time = Instant::now();
-        let mut mining_active = self.mining_active.lock().unwrap();
-        *mining_active = true;
-        while *mining_active {
-            let mining_data = digital_bill.get_mining_data(nonce);
-            let data_string = serde_json::to_string(&mining_data).unwrap();
-            let bill_hash = format!("{:x}", sha2::Sha256::digest(data_string.as_bytes()));
-            if bill_hash.starts_with(&target) {
-                let mining_time = start_time.elapsed().as_secs_f64();
-                let mut stats = self.mining_stats.lock().unwrap();
-                *stats.get_mut("bills_mined").unwrap() += 1;
-                *stats.get_mut("total_mining_time").unwrap() += mining_time as u64;
-                *stats.get_mut("total_hash_attempts").unwrap() += nonce;
-                let mut result = HashMap::new();
-                result.insert("success".to_string(), json!(true));
-                result.insert("hash".to_string(), json!(bill_hash));
-                result.insert("nonce".to_string(), json!(nonce));
-                result.insert("mining_time".to_string(), json!(mining_time));
-                return Some(result);
-            }
-            nonce += 1;
-            if nonce % 100_000 == 0 {
-                let hashrate = nonce as f64 / start_time.elapsed().as_secs_f64();
-                println!("⏳ Bill mining: {} attempts | Rate: {:.0} H/s", nonce, hashrate);
+        let attempts = AtomicU64::new(0);
+        let done = AtomicBool::new(false);
+        let found = Mutex::new(None);
+        *self.mining_active.lock().unwrap() = true;
+        thread::scope(|scope| {
+            for first_nonce in 0..stride {
+                let (digital_bill, target, attempts, done, found) = (&digital_bill, &target, &attempts, &done, &found);
+                scope.spawn(move || {
+                    let mut nonce = first_nonce;
+                    while !done.load(Ordering::Relaxed) {
+                        let data_string = serde_json::to_string(&digital_bill.get_mining_data(nonce)).unwrap();
+                        let bill_hash = format!("{:x}", sha2::Sha256::digest(data_string.as_bytes()));
+                        let attempt = attempts.fetch_add(1, Ordering::Relaxed) + 1;
+                        if bill_hash.starts_with(target.as_str()) {
+                            if !done.swap(true, Ordering::Relaxed) {
+                                *found.lock().unwrap() = Some((nonce, bill_hash));
+                            }
+                            return;
+                        }
+                        if attempt.is_multiple_of(PROGRESS_INTERVAL) {
+                            self.report_progress("Bill mining", attempt, start_time);
+                        }
+                        if (nonce / stride).is_multiple_of(1024) && !self.is_mining() {
+                            done.store(true, Ordering::Relaxed);
+                        }
+                        nonce += stride;
+                    }
+                });
             }
-        }
-        None
+        });
+        let mining_time = start_time.elapsed().as_secs_f64();
+        self.record_attempts(attempts.into_inner(), start_time);
+        let (nonce, bill_hash) = found.into_inner().unwrap()?;
+        *self.mining_stats.lock().unwrap().get_mut("bills_mined").unwrap() += 1;
+        let mut result = HashMap::new();
+        result.insert("success".to_string(), json!(true));
+        result.insert("hash".to_string(), json!(bill_hash));
+        result.insert("nonce".to_string(), json!(nonce));
+        result.insert("mining_time".to_string(), json!(mining_time));
+        result.insert("bill_serial".to_string(), json!(digital_bill.bill_serial));
+        result.insert("denomination".to_string(), json!(denomination));
+        result.insert("difficulty".to_string(), json!(difficulty));
+        result.insert("user_address".to_string(), json!(user_address));
+        result.insert("metadata_hash".to_string(), json!(digital_bill.metadata_hash));
+        result.insert("bill".to_string(), digital_bill.to_dict());
+        Some(result)
     }
 
     pub fn mine_block(&self, block_data: &mut HashMap<String, JsonValue>, difficulty: u32) -> Option<HashMap<String, JsonValue>> {
         let target = "0".repeat(difficulty as usize);
         let mut nonce = 0u64;
         let start_time = Instant::now();
-        let mut mining_active = self.mining_active.lock().unwrap();
-        *mining_active = true;
-        while *mining_active {
+        *self.mining_active.lock().unwrap() = true;
+        while self.is_mining() {
             block_data.insert("nonce".to_string(), json!(nonce));
-            let block_string = serde_json::to_string(&block_data).unwrap();
-            let block_hash = format!("{:x}", sha2::Sha256::digest(block_string.as_bytes()));
+            let block_hash = BlockchainManager::calculate_block_hash(block_data);
             if block_hash.starts_with(&target) {
                 let mining_time = start_time.elapsed().as_secs_f64();
-                let mut stats = self.mining_stats.lock().unwrap();
-                *stats.get_mut("blocks_mined").unwrap() += 1;
-                *stats.get_mut("total_mining_time").unwrap() += mining_time as u64;
-                *stats.get_mut("total_hash_attempts").unwrap() += nonce;
+                self.record_attempts(nonce, start_time);
+                *self.mining_stats.lock().unwrap().get_mut("blocks_mined").unwrap() += 1;
                 block_data.insert("hash".to_string(), json!(block_hash));
                 block_data.insert("mining_time".to_string(), json!(mining_time));
                 return Some(block_data.clone());
             }
             nonce += 1;
-            if nonce % 100_000 == 0 {
-                let hashrate = nonce as f64 / start_time.elapsed().as_secs_f64();
-                println!("Block mining: {} attempts | Rate: {:.0} H/s", nonce, hashrate);
+            if nonce.is_multiple_of(PROGRESS_INTERVAL) {
+                self.report_progress("Block mining", nonce, start_time);
             }
         }
+        self.record_attempts(nonce, start_time);
         None
     }
 
+    /// Search nonces on `threads` threads until a hash meets `difficulty` or `cancel` is set
+    pub fn mine_block_parallel(
+        &self,
+        block_data: &HashMap<String, JsonValue>,
+        difficulty: u32,
+        threads: usize,
+        cancel: &AtomicBool,
+    ) -> Option<HashMap<String, JsonValue>> {
+        let target = "0".repeat(difficulty as usize);
+        let stride = threads.max(1) as u64;
+        let start_time = Instant::now();
+        let attempts = AtomicU64::new(0);
+        let done = AtomicBool::new(false);
+        let found = Mutex::new(None);
+        thread::scope(|scope| {
+            for first_nonce in 0..stride {
+                let (target, attempts, done, found) = (&target, &attempts, &done, &found);
+                scope.spawn(move || {
+                    let mut block = block_data.clone();
+                    let mut nonce = first_nonce;
+                    while !done.load(Ordering::Relaxed) && !cancel.load(Ordering::Relaxed) {
+                        block.insert("nonce".to_string(), json!(nonce));
+                        let block_hash = BlockchainManager::calculate_block_hash(&block);
+                        attempts.fetch_add(1, Ordering::Relaxed);
+                        if block_hash.starts_with(target.as_str()) {
+                            if !done.swap(true, Ordering::Relaxed) {
+                                block.insert("hash".to_string(), json!(block_hash));
+                                *found.lock().unwrap() = Some(block);
+                            }
+                            return;
+                        }
+                        nonce += stride;
+                    }
+                });
+            }
+        });
+        let mining_time = start_time.elapsed().as_secs_f64();
+        let mut stats = self.mining_stats.lock().unwrap();
+        *stats.get_mut("total_hash_attempts").unwrap() += attempts.into_inner();
+        *stats.get_mut("total_mining_time").unwrap() += mining_time as u64;
+        let mut block = found.into_inner().unwrap()?;
+        *stats.get_mut("blocks_mined").unwrap() += 1;
+        block.insert("mining_time".to_string(), json!(mining_time));
+        Some(block)
+    }
+
+    /// Hash block-shaped data on `threads` threads for `duration`, or until `stop_mining`.
+    /// Stats are left untouched.
+    pub fn benchmark(&self, duration: Duration, threads: usize) -> BenchmarkReport {
+        let threads = threads.max(1);
+        let mut block_data = HashMap::new();
+        block_data.insert("index".to_string(), json!(0));
+        block_data.insert("previous_hash".to_string(), json!("0".repeat(64)));
+        block_data.insert("transactions".to_string(), json!([]));
+        let start_time = Instant::now();
+        let attempts = AtomicU64::new(0);
+        *self.mining_active.lock().unwrap() = true;
+        thread::scope(|scope| {
+            for first_nonce in 0..threads as u64 {
+                let (block_data, attempts) = (&block_data, &attempts);
+                scope.spawn(move || {
+                    let mut block = block_data.clone();
+                    let mut nonce = first_nonce;
+                    loop {
+                        block.insert("nonce".to_string(), json!(nonce));
+                        BlockchainManager::calculate_block_hash(&block);
+                        let attempt = attempts.fetch_add(1, Ordering::Relaxed) + 1;
+                        if attempt.is_multiple_of(PROGRESS_INTERVAL) {
+                            self.report_progress("Benchmark", attempt, start_time);
+                        }
+                        if (nonce / threads as u64).is_multiple_of(1024) && (start_time.elapsed() >= duration || !self.is_mining()) {
+                            return;
+                        }
+                        nonce += threads as u64;
+                    }
+                });
+            }
+        });
+        let progress = MiningProgress::since(attempts.into_inner(), start_time);
+        BenchmarkReport { threads, attempts: progress.attempts, elapsed_secs: progress.elapsed_secs, hashrate: progress.hashrate }
+    }
+
     pub fn stop_mining(&self) {
         let mut mining_active = self.mining_active.lock().unwrap();
         *mining_active = false;
-        println!("Mining stopped");
+        info!("Mining stopped");
     }
 
     pub fn get_mining_stats(&self) -> HashMap<String, u64> {
@@ -114,6 +295,16 @@ mod tests {
     use super::*;
     use serde_json::json;
 
+    #[test]
+    fn test_reports_round_trip() {
+        let progress = MiningProgress { attempts: 20_000, hashrate: 4_000.0, elapsed_secs: 5.0 };
+        assert_eq!(serde_json::from_value::<MiningProgress>(serde_json::to_value(&progress).unwrap()).unwrap(), progress);
+        let report = BenchmarkReport { threads: 2, attempts: 50_000, elapsed_secs: 2.5, hashrate: 20_000.0 };
+        let json = serde_json::to_value(&report).unwrap();
+        assert_eq!(json["hashrate"], json!(20_000.0));
+        assert_eq!(serde_json::from_value::<BenchmarkReport>(json).unwrap(), report);
+    }
+
     #[test]
     fn test_mine_bill_basic() {
         let miner = GenesisMiner::new(None);
@@ -141,6 +332,44 @@ mod tests {
         assert_eq!(res["hash"].as_str().unwrap().chars().next().unwrap(), '0');
     }
 
+    #[test]
+    fn test_mine_block_parallel_and_cancel() {
+        let miner = GenesisMiner::new(None);
+        let mut block_data = HashMap::new();
+        block_data.insert("index".to_string(), json!(3));
+        block_data.insert("previous_hash".to_string(), json!("0".repeat(64)));
+        block_data.insert("transactions".to_string(), json!([]));
+        let cancel = AtomicBool::new(false);
+        let mined = miner.mine_block_parallel(&block_data, 2, 4, &cancel).unwrap();
+        assert_eq!(BlockchainManager::calculate_block_hash(&mined), mined["hash"].as_str().unwrap());
+        assert!(mined["hash"].as_str().unwrap().starts_with("00"));
+        assert_eq!(miner.get_mining_stats()["blocks_mined"], 1);
+
+        cancel.store(true, Ordering::Relaxed);
+        assert!(miner.mine_block_parallel(&block_data, 64, 2, &cancel).is_none());
+        assert_eq!(miner.get_mining_stats()["blocks_mined"], 1);
+    }
+
+    #[test]
+    fn test_bill_result_progress_and_benchmark() {
+        let reports = Arc::new(AtomicU64::new(0));
+        let counter = Arc::clone(&reports);
+        let miner = GenesisMiner::new(None).with_progress_callback(Arc::new(move |progress: &MiningProgress| {
+            assert!(progress.attempts > 0);
+            counter.fetch_add(1, Ordering::Relaxed);
+        }));
+        let bill = miner.mine_bill_parallel(100, "LUN_user", None, 1, 2).unwrap();
+        assert!(bill["bill_serial"].as_str().unwrap().starts_with("GTX100_"));
+        assert_eq!(bill["bill"]["front_serial"], bill["bill_serial"]);
+        assert_eq!(miner.get_mining_stats()["bills_mined"], 1);
+
+        let report = miner.benchmark(Duration::from_millis(200), 2);
+        assert_eq!(report.threads, 2);
+        assert!(report.hashrate > 0.0 && report.elapsed_secs >= 0.2, "{:?}", report);
+        assert_eq!(reports.load(Ordering::Relaxed), report.attempts / PROGRESS_INTERVAL);
+        assert_eq!(miner.get_mining_stats()["bills_mined"], 1);
+    }
+
     #[test]
     fn test_stop_and_stats() {
         let miner = GenesisMiner::new(None);
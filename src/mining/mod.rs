@@ -1,3 +1,4 @@
 pub mod miner;
 pub mod cuda_manager;
 pub mod difficulty;
+pub mod reward;
@@ -0,0 +1,52 @@
+use serde::{Deserialize, Serialize};
+
+/// Block reward by height: `base_reward` halved every `halving_interval` blocks
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RewardSchedule {
+    pub base_reward: f64,
+    /// Blocks between halvings; 0 never halves
+    pub halving_interval: u64,
+    /// Floor the reward never drops below
+    pub min_reward: f64,
+}
+
+impl Default for RewardSchedule {
+    fn default() -> Self {
+        RewardSchedule {
+            base_reward: 50.0,
+            halving_interval: 210_000,
+            min_reward: 0.0,
+        }
+    }
+}
+
+impl RewardSchedule {
+    pub fn new(base_reward: f64, halving_interval: u64) -> Self {
+        RewardSchedule { base_reward, halving_interval, ..Default::default() }
+    }
+
+    pub fn reward_at(&self, height: u64) -> f64 {
+        if self.halving_interval == 0 {
+            return self.base_reward;
+        }
+        let halvings = (height / self.halving_interval).min(63) as i32;
+        (self.base_reward / 2f64.powi(halvings)).max(self.min_reward)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_halving() {
+        let schedule = RewardSchedule::new(50.0, 100);
+        assert_eq!(schedule.reward_at(0), 50.0);
+        assert_eq!(schedule.reward_at(99), 50.0);
+        assert_eq!(schedule.reward_at(100), 25.0);
+        assert_eq!(schedule.reward_at(250), 12.5);
+        let floored = RewardSchedule { min_reward: 20.0, ..schedule };
+        assert_eq!(floored.reward_at(250), 20.0);
+    }
+}
@@ -0,0 +1,607 @@
+//! The `lunalib` Python module, built with the `python` feature.
+//!
+//! The classes keep the names and methods of the Python LunaLib so existing tooling can import
+//! this module in its place. JSON values cross the boundary as dicts, lists and scalars, and
+//! failures raise a `lunalib.LunaError` subclass named after the `LunaError` variant. Calls that
+//! wait on the network or on mining release the GIL.
+use std::collections::HashMap;
+use std::future::Future;
+use std::path::PathBuf;
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+use pyo3::exceptions::{PyOSError, PyTypeError, PyValueError};
+use pyo3::prelude::*;
+use pyo3::types::{PyBool, PyDict, PyFloat, PyInt, PyList, PyString, PyTuple};
+use serde::Serialize;
+use serde_json::{json, Value as JsonValue};
+use crate::core::blockchain::BlockchainManager;
+use crate::core::mempool::{self, MempoolManager};
+use crate::core::wallet::LunaWallet;
+use crate::gtx::bill_registry::BillRegistry;
+use crate::gtx::genesis::GTXGenesis;
+use crate::luna_lib::config::DEFAULT_ENDPOINT_URL;
+use crate::luna_lib::error::LunaError;
+use crate::mining::miner::GenesisMiner;
+use crate::transactions::transactions::TransactionManager;
+
+/// How often a waiting call wakes to check for Ctrl-C
+const SIGNAL_POLL: Duration = Duration::from_millis(100);
+
+/// The exception classes, one per `LunaError` variant under a common `LunaError`
+mod exceptions {
+    use pyo3::create_exception;
+    use pyo3::exceptions::PyException;
+
+    create_exception!(lunalib, LunaError, PyException, "Any failure the library reports");
+    create_exception!(lunalib, StorageError, LunaError);
+    create_exception!(lunalib, CryptoError, LunaError);
+    create_exception!(lunalib, BlockchainError, LunaError);
+    create_exception!(lunalib, MempoolError, LunaError);
+    create_exception!(lunalib, MiningError, LunaError);
+    create_exception!(lunalib, GtxError, LunaError);
+    create_exception!(lunalib, ValidationError, LunaError);
+    create_exception!(lunalib, P2PError, LunaError);
+}
+
+impl From<LunaError> for PyErr {
+    fn from(e: LunaError) -> Self {
+        let message = e.to_string();
+        match e {
+            LunaError::Storage(_) => exceptions::StorageError::new_err(message),
+            LunaError::Crypto(_) => exceptions::CryptoError::new_err(message),
+            LunaError::Blockchain(_) => exceptions::BlockchainError::new_err(message),
+            LunaError::Mempool(_) => exceptions::MempoolError::new_err(message),
+            LunaError::Mining(_) => exceptions::MiningError::new_err(message),
+            LunaError::Gtx(_) => exceptions::GtxError::new_err(message),
+            LunaError::Validation(_) => exceptions::ValidationError::new_err(message),
+            LunaError::P2P(_) => exceptions::P2PError::new_err(message),
+            LunaError::Io(_) => PyOSError::new_err(message),
+        }
+    }
+}
+
+fn blockchain_error(e: impl ToString) -> PyErr {
+    LunaError::Blockchain(e.to_string()).into()
+}
+
+/// `value` as the Python object `json.loads` would give
+fn to_py<'py>(py: Python<'py>, value: &JsonValue) -> PyResult<Bound<'py, PyAny>> {
+    Ok(match value {
+        JsonValue::Null => py.None().into_bound(py),
+        JsonValue::Bool(b) => PyBool::new(py, *b).to_owned().into_any(),
+        JsonValue::Number(n) => match (n.as_i64(), n.as_u64()) {
+            (Some(i), _) => i.into_pyobject(py)?.into_any(),
+            (None, Some(u)) => u.into_pyobject(py)?.into_any(),
+            _ => n.as_f64().unwrap_or(f64::NAN).into_pyobject(py)?.into_any(),
+        },
+        JsonValue::String(s) => PyString::new(py, s).into_any(),
+        JsonValue::Array(items) => PyList::new(py, items.iter().map(|item| to_py(py, item)).collect::<PyResult<Vec<_>>>()?)?.into_any(),
+        JsonValue::Object(map) => {
+            let dict = PyDict::new(py);
+            for (key, item) in map {
+                dict.set_item(key, to_py(py, item)?)?;
+            }
+            dict.into_any()
+        }
+    })
+}
+
+fn to_py_value<'py, T: Serialize>(py: Python<'py>, value: &T) -> PyResult<Bound<'py, PyAny>> {
+    to_py(py, &serde_json::to_value(value).map_err(|e| PyValueError::new_err(e.to_string()))?)
+}
+
+/// A Python object made of dicts with string keys, lists, tuples and scalars, as JSON
+fn from_py(value: &Bound<'_, PyAny>) -> PyResult<JsonValue> {
+    if value.is_none() {
+        Ok(JsonValue::Null)
+    } else if value.is_instance_of::<PyBool>() {
+        Ok(JsonValue::Bool(value.extract()?))
+    } else if value.is_instance_of::<PyInt>() {
+        match value.extract::<i64>() {
+            Ok(i) => Ok(json!(i)),
+            Err(_) => Ok(json!(value.extract::<u64>()?)),
+        }
+    } else if value.is_instance_of::<PyFloat>() {
+        let f: f64 = value.extract()?;
+        serde_json::Number::from_f64(f).map(JsonValue::Number).ok_or_else(|| PyValueError::new_err(format!("{} has no JSON form", f)))
+    } else if value.is_instance_of::<PyString>() {
+        Ok(JsonValue::String(value.extract()?))
+    } else if let Ok(dict) = value.cast::<PyDict>() {
+        let mut map = serde_json::Map::new();
+        for (key, item) in dict.iter() {
+            let key: String = key.extract().map_err(|_| PyTypeError::new_err(format!("dict keys must be strings, not {}", key.get_type())))?;
+            map.insert(key, from_py(&item)?);
+        }
+        Ok(JsonValue::Object(map))
+    } else if value.is_instance_of::<PyList>() || value.is_instance_of::<PyTuple>() {
+        value.try_iter()?.map(|item| from_py(&item?)).collect::<PyResult<Vec<_>>>().map(JsonValue::Array)
+    } else {
+        Err(PyTypeError::new_err(format!("{} cannot be converted to JSON", value.get_type())))
+    }
+}
+
+/// A dict argument such as a transaction
+fn dict_arg(value: &Bound<'_, PyAny>) -> PyResult<HashMap<String, JsonValue>> {
+    match from_py(value)? {
+        JsonValue::Object(map) => Ok(map.into_iter().collect()),
+        _ => Err(PyTypeError::new_err(format!("expected a dict, not {}", value.get_type()))),
+    }
+}
+
+fn optional_arg(value: Option<&Bound<'_, PyAny>>) -> PyResult<Option<JsonValue>> {
+    value.filter(|v| !v.is_none()).map(from_py).transpose()
+}
+
+/// Run `future` to completion on a runtime of its own, with the GIL released
+fn block_on<F: Future + Send>(py: Python<'_>, future: F) -> PyResult<F::Output>
+where
+    F::Output: Send,
+{
+    py.detach(|| {
+        let runtime = tokio::runtime::Builder::new_current_thread().enable_all().build()?;
+        Ok(runtime.block_on(future))
+    })
+    .map_err(|e: std::io::Error| LunaError::Io(e).into())
+}
+
+/// A wallet whose private key stays encrypted until `unlock`
+#[pyclass(name = "Wallet", module = "lunalib")]
+pub struct PyWallet(LunaWallet);
+
+#[pymethods]
+impl PyWallet {
+    /// A new keypair with its private key encrypted under `password`
+    #[new]
+    #[pyo3(signature = (label = "", password = ""))]
+    fn new(label: &str, password: &str) -> Self {
+        PyWallet(LunaWallet::create(label, password))
+    }
+
+    #[staticmethod]
+    #[pyo3(signature = (label, password))]
+    fn create(label: &str, password: &str) -> Self {
+        PyWallet(LunaWallet::create(label, password))
+    }
+
+    #[staticmethod]
+    #[pyo3(signature = (address, label = ""))]
+    fn watch_only(address: &str, label: &str) -> PyResult<Self> {
+        Ok(PyWallet(LunaWallet::watch_only(address, label).map_err(LunaError::from)?))
+    }
+
+    #[staticmethod]
+    #[pyo3(signature = (phrase, index, label, password))]
+    fn from_mnemonic(phrase: &str, index: u32, label: &str, password: &str) -> PyResult<Self> {
+        Ok(PyWallet(LunaWallet::from_mnemonic(phrase, index, label, password).map_err(LunaError::from)?))
+    }
+
+    /// A wallet dict from `to_json`, locked
+    #[staticmethod]
+    fn from_json(document: &Bound<'_, PyAny>) -> PyResult<Self> {
+        LunaWallet::from_json(&from_py(document)?).map(PyWallet).map_err(|e| LunaError::Validation(e).into())
+    }
+
+    #[staticmethod]
+    fn import_keystore(keystore: &Bound<'_, PyAny>, password: &str) -> PyResult<Self> {
+        Ok(PyWallet(LunaWallet::import_keystore(&from_py(keystore)?, password).map_err(LunaError::from)?))
+    }
+
+    fn to_json<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyAny>> {
+        to_py(py, &self.0.to_json())
+    }
+
+    fn export_keystore<'py>(&mut self, py: Python<'py>, password: &str) -> PyResult<Bound<'py, PyAny>> {
+        let keystore = self.0.export_keystore(password).map_err(LunaError::from)?;
+        to_py(py, &keystore)
+    }
+
+    #[getter]
+    fn address(&self) -> &str {
+        &self.0.address
+    }
+
+    #[getter]
+    fn public_key(&self) -> &str {
+        &self.0.public_key
+    }
+
+    #[getter]
+    fn label(&self) -> &str {
+        &self.0.label
+    }
+
+    #[getter]
+    fn balance(&self) -> f64 {
+        self.0.balance
+    }
+
+    #[getter]
+    fn available_balance(&self) -> f64 {
+        self.0.available_balance
+    }
+
+    #[getter]
+    fn is_locked(&self) -> bool {
+        self.0.is_locked()
+    }
+
+    /// `"full"` or `"watch_only"`
+    #[getter]
+    fn kind<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyAny>> {
+        to_py_value(py, &self.0.kind)
+    }
+
+    fn unlock(&mut self, password: &str) -> PyResult<()> {
+        Ok(self.0.unlock(password).map_err(LunaError::from)?)
+    }
+
+    fn lock(&mut self) {
+        self.0.lock();
+    }
+
+    /// `tx` with its signature, public key and hash filled in
+    fn sign_transaction<'py>(&self, py: Python<'py>, tx: &Bound<'py, PyAny>) -> PyResult<Bound<'py, PyAny>> {
+        let mut tx = dict_arg(tx)?;
+        self.0.sign_transaction(&mut tx).map_err(LunaError::from)?;
+        to_py_value(py, &tx)
+    }
+
+    fn sign_message<'py>(&self, py: Python<'py>, message: &str) -> PyResult<Bound<'py, PyAny>> {
+        let signed = self.0.sign_message(message).map_err(LunaError::from)?;
+        to_py_value(py, &signed)
+    }
+
+    fn __repr__(&self) -> String {
+        format!("Wallet(address={:?}, label={:?})", self.0.address, self.0.label)
+    }
+}
+
+/// Mines bills and blocks on this machine
+#[pyclass(name = "Miner", module = "lunalib")]
+pub struct PyMiner(Arc<GenesisMiner>);
+
+#[pymethods]
+impl PyMiner {
+    #[new]
+    fn new() -> Self {
+        PyMiner(Arc::new(GenesisMiner::new(None)))
+    }
+
+    /// Mine a bill, by default at the difficulty `GTXGenesis` sets for the denomination. Returns a
+    /// dict with `success`, `hash`, `nonce` and the bill, or `{"success": False}` if
+    /// `stop_mining` was called first. The GIL is released while mining and Ctrl-C stops it.
+    #[pyo3(signature = (denomination, user_address, bill_data = None, difficulty = None, threads = 1))]
+    fn mine_bill<'py>(
+        &self,
+        py: Python<'py>,
+        denomination: u64,
+        user_address: &str,
+        bill_data: Option<&Bound<'py, PyAny>>,
+        difficulty: Option<u32>,
+        threads: usize,
+    ) -> PyResult<Bound<'py, PyAny>> {
+        let bill_data = optional_arg(bill_data)?;
+        let difficulty = difficulty.unwrap_or_else(|| GTXGenesis::difficulty_for(denomination));
+        let (miner, user_address) = (Arc::clone(&self.0), user_address.to_string());
+        let (sender, mut receiver) = mpsc::channel();
+        thread::spawn(move || {
+            let _ = sender.send(miner.mine_bill_parallel(denomination, &user_address, bill_data, difficulty, threads));
+        });
+        loop {
+            // A receiver cannot be shared with the detached closure, so it is moved in and back out
+            let (received, returned) = py.detach(move || (receiver.recv_timeout(SIGNAL_POLL), receiver));
+            receiver = returned;
+            match received {
+                Ok(Some(mined)) => return to_py_value(py, &mined),
+                Ok(None) => return to_py(py, &json!({"success": false})),
+                Err(RecvTimeoutError::Timeout) => {
+                    if let Err(interrupt) = py.check_signals() {
+                        // Mining sets itself active as it starts, so keep stopping until it returns
+                        py.detach(move || {
+                            self.0.stop_mining();
+                            while let Err(RecvTimeoutError::Timeout) = receiver.recv_timeout(SIGNAL_POLL) {
+                                self.0.stop_mining();
+                            }
+                        });
+                        return Err(interrupt);
+                    }
+                }
+                Err(RecvTimeoutError::Disconnected) => return Err(LunaError::Mining("the mining thread panicked".to_string()).into()),
+            }
+        }
+    }
+
+    fn stop_mining(&self) {
+        self.0.stop_mining();
+    }
+
+    fn get_mining_stats<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyAny>> {
+        to_py_value(py, &self.0.get_mining_stats())
+    }
+}
+
+/// Issues bills and verifies them against a bill registry
+#[pyclass(name = "GTXGenesis", module = "lunalib")]
+pub struct PyGTXGenesis(GTXGenesis);
+
+#[pymethods]
+impl PyGTXGenesis {
+    /// Bills are registered in the database at `db_path`, or the default one
+    #[new]
+    #[pyo3(signature = (db_path = None))]
+    fn new(db_path: Option<PathBuf>) -> Self {
+        PyGTXGenesis(GTXGenesis::from_registry(BillRegistry::new(db_path)))
+    }
+
+    #[getter]
+    fn valid_denominations(&self) -> Vec<u64> {
+        self.0.valid_denominations.clone()
+    }
+
+    #[pyo3(signature = (denomination, user_address, custom_data = None))]
+    fn create_genesis_bill<'py>(&self, py: Python<'py>, denomination: u64, user_address: &str, custom_data: Option<&Bound<'py, PyAny>>) -> PyResult<Bound<'py, PyAny>> {
+        if !self.0.valid_denominations.contains(&denomination) {
+            return Err(LunaError::Gtx(format!("{} is not a valid denomination", denomination)).into());
+        }
+        to_py(py, &self.0.create_genesis_bill(denomination, user_address, optional_arg(custom_data)?).to_dict())
+    }
+
+    fn verify_bill<'py>(&self, py: Python<'py>, bill_serial: &str) -> PyResult<Bound<'py, PyAny>> {
+        to_py(py, &self.0.verify_bill(bill_serial))
+    }
+
+    /// Register a bill from `Miner.mine_bill`; returns the registry record
+    fn register_mined_bill<'py>(&self, py: Python<'py>, mined: &Bound<'py, PyAny>, denomination: u64, user_address: &str, difficulty: u32) -> PyResult<Bound<'py, PyAny>> {
+        let info = self.0.register_mined_bill(&dict_arg(mined)?, denomination, user_address, difficulty)?;
+        to_py_value(py, &info)
+    }
+
+    fn get_user_portfolio<'py>(&self, py: Python<'py>, user_address: &str) -> PyResult<Bound<'py, PyAny>> {
+        to_py(py, &self.0.get_user_portfolio(user_address))
+    }
+
+    fn calculate_difficulty(&self, denomination: u64) -> u32 {
+        self.0.calculate_difficulty(denomination)
+    }
+}
+
+/// Builds transactions as dicts, ready for `Wallet.sign_transaction`
+#[pyclass(name = "TransactionManager", module = "lunalib")]
+pub struct PyTransactionManager(TransactionManager);
+
+#[pymethods]
+impl PyTransactionManager {
+    #[new]
+    fn new() -> Self {
+        PyTransactionManager(TransactionManager::new())
+    }
+
+    #[pyo3(signature = (from_address, to_address, amount, memo = "", transaction_type = "transfer"))]
+    fn create_transaction<'py>(&self, py: Python<'py>, from_address: &str, to_address: &str, amount: f64, memo: &str, transaction_type: &str) -> PyResult<Bound<'py, PyAny>> {
+        let tx = self.0.create_transaction(from_address, to_address, amount, memo, transaction_type).map_err(LunaError::from)?;
+        to_py_value(py, &tx)
+    }
+
+    fn create_reward_transaction<'py>(&self, py: Python<'py>, to_address: &str, amount: f64, block_height: i64) -> PyResult<Bound<'py, PyAny>> {
+        to_py_value(py, &self.0.create_reward_transaction(to_address, amount, block_height))
+    }
+
+    fn create_gtx_transaction<'py>(&self, py: Python<'py>, bill_info: &Bound<'py, PyAny>) -> PyResult<Bound<'py, PyAny>> {
+        to_py_value(py, &self.0.create_gtx_transaction(&dict_arg(bill_info)?))
+    }
+
+    /// `(valid, reason)` from the security checks
+    fn validate_transaction(&self, tx: &Bound<'_, PyAny>) -> PyResult<(bool, String)> {
+        Ok(self.0.security.validate_transaction(&dict_arg(tx)?))
+    }
+
+    #[pyo3(signature = (transaction_type = "transfer"))]
+    fn get_fee(&self, transaction_type: &str) -> f64 {
+        self.0.fee_calculator.get_fee(transaction_type)
+    }
+
+    #[staticmethod]
+    fn calculate_transaction_hash(tx: &Bound<'_, PyAny>) -> PyResult<String> {
+        Ok(TransactionManager::calculate_transaction_hash(&dict_arg(tx)?))
+    }
+}
+
+/// Reads the chain and submits transactions through a node's HTTP API
+#[pyclass(name = "BlockchainManager", module = "lunalib")]
+pub struct PyBlockchainManager(Arc<BlockchainManager>);
+
+#[pymethods]
+impl PyBlockchainManager {
+    #[new]
+    #[pyo3(signature = (endpoint_url = DEFAULT_ENDPOINT_URL, max_workers = 10))]
+    fn new(endpoint_url: &str, max_workers: usize) -> Self {
+        PyBlockchainManager(Arc::new(BlockchainManager::new(endpoint_url, max_workers)))
+    }
+
+    #[getter]
+    fn endpoint_url(&self) -> &str {
+        &self.0.endpoint_url
+    }
+
+    fn get_blockchain_height(&self, py: Python<'_>) -> PyResult<u64> {
+        block_on(py, self.0.get_blockchain_height())?.map_err(blockchain_error)
+    }
+
+    fn get_block_by_height<'py>(&self, py: Python<'py>, height: u64) -> PyResult<Bound<'py, PyAny>> {
+        let block = block_on(py, self.0.get_block_by_height(height))?.map_err(blockchain_error)?;
+        to_py_value(py, &block)
+    }
+
+    fn get_mempool<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyAny>> {
+        let mempool = block_on(py, self.0.get_mempool())?.map_err(blockchain_error)?;
+        to_py_value(py, &mempool)
+    }
+
+    /// Every confirmed transaction touching `addresses`, as a dict of lists keyed by address
+    #[pyo3(signature = (addresses, from_height = 0))]
+    fn scan_transactions_for_addresses<'py>(&self, py: Python<'py>, addresses: Vec<String>, from_height: u64) -> PyResult<Bound<'py, PyAny>> {
+        let found = block_on(py, self.0.scan_transactions_for_addresses(&addresses, from_height))?.map_err(blockchain_error)?;
+        to_py_value(py, &found)
+    }
+
+    /// Submit a signed transaction; returns its hash
+    fn submit_transaction(&self, py: Python<'_>, tx: &Bound<'_, PyAny>) -> PyResult<String> {
+        let tx = dict_arg(tx)?;
+        py.detach(|| self.0.submit_transaction(&tx)).map_err(blockchain_error)
+    }
+}
+
+/// Transactions waiting to be mined, kept in memory
+#[pyclass(name = "MempoolManager", module = "lunalib")]
+pub struct PyMempoolManager(MempoolManager);
+
+#[pymethods]
+impl PyMempoolManager {
+    #[new]
+    fn new() -> Self {
+        PyMempoolManager(MempoolManager::new())
+    }
+
+    fn add_transaction(&self, tx: &Bound<'_, PyAny>) -> PyResult<bool> {
+        Ok(self.0.add_transaction(mempool::Transaction::from_json(&dict_arg(tx)?)))
+    }
+
+    fn remove_transaction(&self, tx_hash: &str) {
+        self.0.remove_transaction(tx_hash);
+    }
+
+    fn get_transaction<'py>(&self, py: Python<'py>, tx_hash: &str) -> PyResult<Bound<'py, PyAny>> {
+        to_py_value(py, &self.0.get_transaction(tx_hash))
+    }
+
+    fn get_pending_transactions<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyAny>> {
+        to_py_value(py, &self.0.get_pending_transactions())
+    }
+
+    fn is_transaction_pending(&self, tx_hash: &str) -> bool {
+        self.0.is_transaction_pending(tx_hash)
+    }
+
+    fn get_mempool_size(&self) -> usize {
+        self.0.get_mempool_size()
+    }
+
+    fn get_stats<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyAny>> {
+        to_py_value(py, &self.0.get_stats())
+    }
+
+    fn clear_mempool(&self) {
+        self.0.clear_mempool();
+    }
+}
+
+#[pymodule]
+fn lunalib(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    let py = m.py();
+    m.add("__version__", env!("CARGO_PKG_VERSION"))?;
+    m.add_class::<PyWallet>()?;
+    m.add_class::<PyMiner>()?;
+    m.add_class::<PyGTXGenesis>()?;
+    m.add_class::<PyTransactionManager>()?;
+    m.add_class::<PyBlockchainManager>()?;
+    m.add_class::<PyMempoolManager>()?;
+    m.add("LunaError", py.get_type::<exceptions::LunaError>())?;
+    m.add("StorageError", py.get_type::<exceptions::StorageError>())?;
+    m.add("CryptoError", py.get_type::<exceptions::CryptoError>())?;
+    m.add("BlockchainError", py.get_type::<exceptions::BlockchainError>())?;
+    m.add("MempoolError", py.get_type::<exceptions::MempoolError>())?;
+    m.add("MiningError", py.get_type::<exceptions::MiningError>())?;
+    m.add("GtxError", py.get_type::<exceptions::GtxError>())?;
+    m.add("ValidationError", py.get_type::<exceptions::ValidationError>())?;
+    m.add("P2PError", py.get_type::<exceptions::P2PError>())?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::ffi::CString;
+
+    /// Pytest-style: every `test_` function in the script runs in the order it is defined.
+    /// They share one test so the interpreter's main thread, which handles Ctrl-C, is this one.
+    const SCRIPT: &str = r#"
+import signal
+import threading
+import _thread
+import lunalib
+
+ADDRESS = lunalib.Wallet.create("elsewhere", "pw").address
+
+def raises(exception, call, *args):
+    try:
+        call(*args)
+    except exception as e:
+        return e
+    raise AssertionError(f"{call} did not raise {exception.__name__}")
+
+def test_wallet_signs_transactions():
+    wallet = lunalib.Wallet("main", "hunter2")
+    assert wallet.is_locked and wallet.kind == "full"
+    tx = lunalib.TransactionManager().create_transaction(wallet.address, ADDRESS, 1.5, "rent")
+    assert tx["from"] == wallet.address and tx["amount"] == 1.5 and tx["memo"] == "rent"
+    raises(lunalib.CryptoError, wallet.unlock, "wrong")
+    wallet.unlock("hunter2")
+    signed = wallet.sign_transaction(tx)
+    assert signed["signature"] != "unsigned" and signed["public_key"] == wallet.public_key
+    restored = lunalib.Wallet.from_json(wallet.to_json())
+    assert restored.address == wallet.address and restored.is_locked
+
+def test_errors_are_typed():
+    error = raises(lunalib.ValidationError, lunalib.TransactionManager().create_transaction, "LUN_abc", ADDRESS, 1.0)
+    assert isinstance(error, lunalib.LunaError)
+    watched = lunalib.Wallet.watch_only(ADDRESS, "phone")
+    assert watched.kind == "watch_only"
+    raises(lunalib.CryptoError, watched.unlock, "pw")
+    raises(TypeError, lunalib.MempoolManager().add_transaction, [1, 2])
+
+def test_mempool_round_trips_dicts():
+    mempool = lunalib.MempoolManager()
+    tx = {"hash": "h1", "from": ADDRESS, "to": ADDRESS, "amount": 2.0, "timestamp": 1700000000, "type": "transfer"}
+    assert mempool.add_transaction(tx)
+    assert mempool.is_transaction_pending("h1") and mempool.get_mempool_size() == 1
+    assert mempool.get_transaction("h1") == tx
+    assert mempool.get_pending_transactions() == [tx]
+    assert mempool.get_transaction("missing") is None
+    mempool.clear_mempool()
+    assert mempool.get_stats()["size"] == 0
+
+def test_mine_bill_returns_a_dict():
+    mined = lunalib.Miner().mine_bill(1, ADDRESS, {"note": "test"}, 1)
+    assert mined["success"] and mined["hash"].startswith("0") and isinstance(mined["nonce"], int)
+    assert mined["bill"]["denomination"] == 1
+
+def test_mining_releases_the_gil_and_stops():
+    miner = lunalib.Miner()
+    threading.Timer(0.2, miner.stop_mining).start()
+    assert miner.mine_bill(1, ADDRESS, None, 64) == {"success": False}
+
+def test_ctrl_c_interrupts_mining():
+    signal.signal(signal.SIGINT, signal.default_int_handler)
+    threading.Timer(0.2, _thread.interrupt_main).start()
+    raises(KeyboardInterrupt, lunalib.Miner().mine_bill, 1, ADDRESS, None, 64, 2)
+
+for name, test in list(globals().items()):
+    if name.startswith("test_"):
+        test()
+"#;
+
+    #[test]
+    fn test_python_module() {
+        pyo3::append_to_inittab!(lunalib);
+        Python::initialize();
+        Python::attach(|py| {
+            let script = CString::new(SCRIPT).unwrap();
+            if let Err(e) = py.run(&script, None, None) {
+                e.display(py);
+                panic!("{}", e);
+            }
+        });
+    }
+}
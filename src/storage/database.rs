@@ -1,11 +1,12 @@
 
-use rusqlite::{params, Connection, Result};
+use rusqlite::{params, Connection};
 use serde_json::{Value as JsonValue, json};
 use std::fs;
 use std::path::PathBuf;
 use std::time::{SystemTime, UNIX_EPOCH};
+use crate::core::wallet::LunaWallet;
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct WalletDatabase {
     pub db_path: PathBuf,
 }
@@ -73,47 +74,123 @@ impl WalletDatabase {
             )",
             [],
         ).unwrap();
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS blacklist (
+                address TEXT PRIMARY KEY,
+                reason TEXT,
+                added_at INTEGER,
+                expires_at INTEGER
+            )",
+            [],
+        ).unwrap();
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS whitelist (
+                address TEXT PRIMARY KEY,
+                reason TEXT,
+                added_at INTEGER
+            )",
+            [],
+        ).unwrap();
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS idempotency_keys (
+                idempotency_key TEXT PRIMARY KEY,
+                tx_hash TEXT,
+                created_at INTEGER,
+                raw_data TEXT
+            )",
+            [],
+        ).unwrap();
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS velocity_records (
+                address TEXT,
+                timestamp INTEGER,
+                amount REAL
+            )",
+            [],
+        ).unwrap();
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS balance_history (
+                address TEXT,
+                timestamp INTEGER,
+                balance TEXT
+            )",
+            [],
+        ).unwrap();
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS wallet_states (
+                address TEXT PRIMARY KEY,
+                last_updated INTEGER,
+                state TEXT
+            )",
+            [],
+        ).unwrap();
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS security_events (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                timestamp INTEGER,
+                address TEXT,
+                tx_hash TEXT,
+                rule TEXT,
+                severity TEXT,
+                raw_data TEXT
+            )",
+            [],
+        ).unwrap();
     }
 
-    pub fn save_wallet(&self, wallet_data: &JsonValue) -> bool {
+    /// Store the wallet's `to_json` document: the fields with columns of their own in those, the
+    /// rest as `LunaWallet::stored_metadata` in the metadata column
+    pub fn save_wallet(&self, wallet: &LunaWallet) -> bool {
         let conn = Connection::open(&self.db_path).unwrap();
         let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs_f64();
+        let document = wallet.to_json();
         let res = conn.execute(
             "INSERT OR REPLACE INTO wallets (address, label, public_key, encrypted_private_key, balance, created, last_accessed, metadata) VALUES (?, ?, ?, ?, ?, ?, ?, ?)",
             params![
-                wallet_data["address"].as_str().unwrap_or("") ,
-                wallet_data.get("label").and_then(|v| v.as_str()).unwrap_or("") ,
-                wallet_data.get("public_key").and_then(|v| v.as_str()).unwrap_or("") ,
-                wallet_data.get("encrypted_private_key").and_then(|v| v.as_str()).unwrap_or("") ,
-                wallet_data.get("balance").and_then(|v| v.as_f64()).unwrap_or(0.0) ,
-                wallet_data.get("created").and_then(|v| v.as_f64()).unwrap_or(now) ,
-                now ,
-                wallet_data.get("metadata").map(|v| v.to_string()).unwrap_or("{}".to_string())
+                document["address"].as_str(),
+                document["label"].as_str(),
+                document["public_key"].as_str(),
+                document["encrypted_private_key"].as_str(),
+                document["balance"].as_f64(),
+                document["created"].as_f64(),
+                now,
+                wallet.stored_metadata().to_string()
             ]
         );
         res.is_ok()
     }
 
-    pub fn load_wallet(&self, address: &str) -> Option<JsonValue> {
+    /// The stored wallet, locked, read back through `LunaWallet::from_stored`
+    pub fn load_wallet(&self, address: &str) -> Option<LunaWallet> {
         let conn = Connection::open(&self.db_path).unwrap();
-        let mut stmt = conn.prepare("SELECT * FROM wallets WHERE address = ?").unwrap();
+        let mut stmt = conn.prepare("SELECT address, label, public_key, encrypted_private_key, balance, created, metadata FROM wallets WHERE address = ?").unwrap();
         let mut rows = stmt.query(params![address]).unwrap();
-        if let Some(row) = rows.next().unwrap() {
-            let metadata_str: String = row.get(7).unwrap_or("{}".to_string());
-            let metadata = serde_json::from_str(&metadata_str).unwrap_or(json!({}));
-            Some(json!({
-                "address": row.get::<_, String>(0).unwrap_or_default(),
-                "label": row.get::<_, String>(1).unwrap_or_default(),
-                "public_key": row.get::<_, String>(2).unwrap_or_default(),
-                "encrypted_private_key": row.get::<_, String>(3).unwrap_or_default(),
-                "balance": row.get::<_, f64>(4).unwrap_or(0.0),
-                "created": row.get::<_, f64>(5).unwrap_or(0.0),
-                "last_accessed": row.get::<_, f64>(6).unwrap_or(0.0),
-                "metadata": metadata
-            }))
-        } else {
-            None
-        }
+        let row = rows.next().unwrap()?;
+        let metadata_str: String = row.get(6).unwrap_or("{}".to_string());
+        let metadata = serde_json::from_str::<JsonValue>(&metadata_str).unwrap_or(json!({}));
+        let columns = json!({
+            "address": row.get::<_, String>(0).unwrap_or_default(),
+            "label": row.get::<_, String>(1).unwrap_or_default(),
+            "public_key": row.get::<_, String>(2).unwrap_or_default(),
+            "encrypted_private_key": row.get::<_, String>(3).unwrap_or_default(),
+            "balance": row.get::<_, f64>(4).unwrap_or(0.0),
+            "created": row.get::<_, f64>(5).unwrap_or(0.0),
+        });
+        LunaWallet::from_stored(columns, metadata).ok()
+    }
+
+    /// Every stored wallet, oldest first; wallets created in the same second in the order they were saved
+    pub fn list_wallets(&self) -> Vec<LunaWallet> {
+        let conn = Connection::open(&self.db_path).unwrap();
+        let mut stmt = conn.prepare("SELECT address FROM wallets ORDER BY created, rowid").unwrap();
+        let addresses: Vec<String> = stmt.query_map([], |row| row.get(0)).unwrap().filter_map(Result::ok).collect();
+        addresses.iter().filter_map(|address| self.load_wallet(address)).collect()
+    }
+
+    /// Returns whether a wallet was removed
+    pub fn delete_wallet(&self, address: &str) -> bool {
+        let conn = Connection::open(&self.db_path).unwrap();
+        conn.execute("DELETE FROM wallets WHERE address = ?", params![address]).map(|n| n > 0).unwrap_or(false)
     }
 
     pub fn save_transaction(&self, transaction: &JsonValue, wallet_address: &str) -> bool {
@@ -153,6 +230,42 @@ impl WalletDatabase {
         txs
     }
 
+    /// Every confirmed row for `wallet_address`, from its columns, in the shape `save_transaction` takes
+    pub fn get_confirmed_transactions(&self, wallet_address: &str) -> Vec<JsonValue> {
+        let conn = Connection::open(&self.db_path).unwrap();
+        let mut stmt = conn.prepare("SELECT tx_hash, tx_type, from_address, to_address, amount, fee, timestamp, block_height, status, memo FROM transactions WHERE wallet_address = ?").unwrap();
+        let rows = stmt.query_map(params![wallet_address], |row| {
+            Ok(json!({
+                "hash": row.get::<_, String>(0).unwrap_or_default(),
+                "type": row.get::<_, String>(1).unwrap_or_default(),
+                "from": row.get::<_, String>(2).unwrap_or_default(),
+                "to": row.get::<_, String>(3).unwrap_or_default(),
+                "amount": row.get::<_, f64>(4).unwrap_or(0.0),
+                "fee": row.get::<_, f64>(5).unwrap_or(0.0),
+                "timestamp": row.get::<_, f64>(6).unwrap_or(0.0),
+                "block_height": row.get::<_, i64>(7).unwrap_or(0),
+                "status": row.get::<_, String>(8).unwrap_or_default(),
+                "memo": row.get::<_, String>(9).unwrap_or_default(),
+            }))
+        }).unwrap();
+        rows.filter_map(|r| r.ok()).collect()
+    }
+
+    /// Every pending transaction for `wallet_address` as it was saved; `timestamp` falls back to when it was saved
+    pub fn get_pending_transactions(&self, wallet_address: &str) -> Vec<JsonValue> {
+        let conn = Connection::open(&self.db_path).unwrap();
+        let mut stmt = conn.prepare("SELECT raw_data, created_time FROM pending_transactions WHERE wallet_address = ?").unwrap();
+        let rows = stmt.query_map(params![wallet_address], |row| {
+            let raw: String = row.get(0).unwrap_or("{}".to_string());
+            let mut tx: JsonValue = serde_json::from_str(&raw).unwrap_or(json!({}));
+            if tx.get("timestamp").and_then(|v| v.as_f64()).is_none() {
+                tx["timestamp"] = json!(row.get::<_, f64>(1).unwrap_or(0.0));
+            }
+            Ok(tx)
+        }).unwrap();
+        rows.filter_map(|r| r.ok()).filter(|tx| tx.is_object()).collect()
+    }
+
     pub fn save_pending_transaction(&self, transaction: &JsonValue, wallet_address: &str) -> bool {
         let conn = Connection::open(&self.db_path).unwrap();
         let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs_f64();
@@ -172,6 +285,197 @@ impl WalletDatabase {
         );
         res.is_ok()
     }
+
+    pub fn save_idempotency_key(&self, key: &str, transaction: &JsonValue, created_at: u64) -> bool {
+        let conn = Connection::open(&self.db_path).unwrap();
+        let res = conn.execute(
+            "INSERT OR REPLACE INTO idempotency_keys (idempotency_key, tx_hash, created_at, raw_data) VALUES (?, ?, ?, ?)",
+            params![
+                key,
+                transaction.get("hash").and_then(|v| v.as_str()).unwrap_or(""),
+                created_at as i64,
+                transaction.to_string()
+            ]
+        );
+        res.is_ok()
+    }
+
+    /// Load all stored idempotency keys as (key, transaction, created_at), oldest first
+    pub fn load_idempotency_keys(&self) -> Vec<(String, JsonValue, u64)> {
+        let conn = Connection::open(&self.db_path).unwrap();
+        let mut stmt = conn.prepare("SELECT idempotency_key, raw_data, created_at FROM idempotency_keys ORDER BY created_at ASC").unwrap();
+        let mut rows = stmt.query([]).unwrap();
+        let mut keys = Vec::new();
+        while let Some(row) = rows.next().unwrap() {
+            let key: String = row.get(0).unwrap_or_default();
+            let raw: String = row.get(1).unwrap_or("{}".to_string());
+            let created_at: i64 = row.get(2).unwrap_or(0);
+            if let Ok(tx) = serde_json::from_str(&raw) {
+                keys.push((key, tx, created_at as u64));
+            }
+        }
+        keys
+    }
+
+    pub fn delete_idempotency_key(&self, key: &str) -> bool {
+        let conn = Connection::open(&self.db_path).unwrap();
+        conn.execute("DELETE FROM idempotency_keys WHERE idempotency_key = ?", params![key]).is_ok()
+    }
+
+    pub fn save_blacklist_entry(&self, address: &str, reason: &str, added_at: u64, expires_at: Option<u64>) -> bool {
+        let conn = Connection::open(&self.db_path).unwrap();
+        conn.execute(
+            "INSERT OR REPLACE INTO blacklist (address, reason, added_at, expires_at) VALUES (?, ?, ?, ?)",
+            params![address, reason, added_at as i64, expires_at.map(|t| t as i64)]
+        ).is_ok()
+    }
+
+    pub fn delete_blacklist_entry(&self, address: &str) -> bool {
+        let conn = Connection::open(&self.db_path).unwrap();
+        conn.execute("DELETE FROM blacklist WHERE address = ?", params![address]).is_ok()
+    }
+
+    /// Load blacklist rows as (address, reason, added_at, expires_at)
+    pub fn load_blacklist(&self) -> Vec<(String, String, u64, Option<u64>)> {
+        let conn = Connection::open(&self.db_path).unwrap();
+        let mut stmt = conn.prepare("SELECT address, reason, added_at, expires_at FROM blacklist").unwrap();
+        let rows = stmt.query_map([], |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                row.get::<_, Option<String>>(1)?.unwrap_or_default(),
+                row.get::<_, i64>(2).unwrap_or(0) as u64,
+                row.get::<_, Option<i64>>(3)?.map(|t| t as u64),
+            ))
+        }).unwrap();
+        rows.filter_map(|r| r.ok()).collect()
+    }
+
+    pub fn save_whitelist_entry(&self, address: &str, reason: &str, added_at: u64) -> bool {
+        let conn = Connection::open(&self.db_path).unwrap();
+        conn.execute(
+            "INSERT OR REPLACE INTO whitelist (address, reason, added_at) VALUES (?, ?, ?)",
+            params![address, reason, added_at as i64]
+        ).is_ok()
+    }
+
+    pub fn delete_whitelist_entry(&self, address: &str) -> bool {
+        let conn = Connection::open(&self.db_path).unwrap();
+        conn.execute("DELETE FROM whitelist WHERE address = ?", params![address]).is_ok()
+    }
+
+    /// Load whitelist rows as (address, reason, added_at)
+    pub fn load_whitelist(&self) -> Vec<(String, String, u64)> {
+        let conn = Connection::open(&self.db_path).unwrap();
+        let mut stmt = conn.prepare("SELECT address, reason, added_at FROM whitelist").unwrap();
+        let rows = stmt.query_map([], |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                row.get::<_, Option<String>>(1)?.unwrap_or_default(),
+                row.get::<_, i64>(2).unwrap_or(0) as u64,
+            ))
+        }).unwrap();
+        rows.filter_map(|r| r.ok()).collect()
+    }
+
+    pub fn save_velocity_record(&self, address: &str, timestamp: u64, amount: f64) -> bool {
+        let conn = Connection::open(&self.db_path).unwrap();
+        conn.execute(
+            "INSERT INTO velocity_records (address, timestamp, amount) VALUES (?, ?, ?)",
+            params![address, timestamp as i64, amount]
+        ).is_ok()
+    }
+
+    /// Load velocity rows newer than `since` as (address, timestamp, amount), oldest first
+    pub fn load_velocity_records(&self, since: u64) -> Vec<(String, u64, f64)> {
+        let conn = Connection::open(&self.db_path).unwrap();
+        let mut stmt = conn.prepare(
+            "SELECT address, timestamp, amount FROM velocity_records WHERE timestamp > ? ORDER BY timestamp ASC"
+        ).unwrap();
+        let rows = stmt.query_map(params![since as i64], |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                row.get::<_, i64>(1)? as u64,
+                row.get::<_, f64>(2)?,
+            ))
+        }).unwrap();
+        rows.filter_map(|r| r.ok()).collect()
+    }
+
+    pub fn prune_velocity_records(&self, before: u64) -> bool {
+        let conn = Connection::open(&self.db_path).unwrap();
+        conn.execute("DELETE FROM velocity_records WHERE timestamp <= ?", params![before as i64]).is_ok()
+    }
+
+    pub fn save_balance_snapshot(&self, address: &str, timestamp: u64, balance: &JsonValue) -> bool {
+        let conn = Connection::open(&self.db_path).unwrap();
+        conn.execute(
+            "INSERT INTO balance_history (address, timestamp, balance) VALUES (?, ?, ?)",
+            params![address, timestamp as i64, balance.to_string()]
+        ).is_ok()
+    }
+
+    /// Load the most recent `limit` balance snapshots for `address` as (timestamp, balance), oldest first
+    pub fn load_balance_history(&self, address: &str, limit: usize) -> Vec<(u64, JsonValue)> {
+        let conn = Connection::open(&self.db_path).unwrap();
+        let mut stmt = conn.prepare(
+            "SELECT timestamp, balance FROM (SELECT rowid, timestamp, balance FROM balance_history WHERE address = ? ORDER BY rowid DESC LIMIT ?) ORDER BY rowid ASC"
+        ).unwrap();
+        let rows = stmt.query_map(params![address, limit as i64], |row| {
+            Ok((row.get::<_, i64>(0)? as u64, row.get::<_, String>(1)?))
+        }).unwrap();
+        rows.filter_map(|r| r.ok()).filter_map(|(timestamp, raw)| Some((timestamp, serde_json::from_str(&raw).ok()?))).collect()
+    }
+
+    /// Replaces any state already saved for `address`
+    pub fn save_wallet_state(&self, address: &str, last_updated: u64, state: &JsonValue) -> bool {
+        let conn = Connection::open(&self.db_path).unwrap();
+        conn.execute(
+            "INSERT OR REPLACE INTO wallet_states (address, last_updated, state) VALUES (?, ?, ?)",
+            params![address, last_updated as i64, state.to_string()]
+        ).is_ok()
+    }
+
+    /// Load every saved wallet state as (address, state); rows that are not JSON are skipped
+    pub fn load_wallet_states(&self) -> Vec<(String, JsonValue)> {
+        let conn = Connection::open(&self.db_path).unwrap();
+        let mut stmt = conn.prepare("SELECT address, state FROM wallet_states ORDER BY rowid").unwrap();
+        let rows = stmt.query_map([], |row| Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?))).unwrap();
+        rows.filter_map(|r| r.ok()).filter_map(|(address, raw)| Some((address, serde_json::from_str(&raw).ok()?))).collect()
+    }
+
+    pub fn save_security_event(&self, event: &JsonValue) -> bool {
+        let conn = Connection::open(&self.db_path).unwrap();
+        conn.execute(
+            "INSERT INTO security_events (timestamp, address, tx_hash, rule, severity, raw_data) VALUES (?, ?, ?, ?, ?, ?)",
+            params![
+                event.get("timestamp").and_then(|v| v.as_i64()).unwrap_or(0),
+                event.get("address").and_then(|v| v.as_str()).unwrap_or(""),
+                event.get("tx_hash").and_then(|v| v.as_str()).unwrap_or(""),
+                event.get("rule").and_then(|v| v.as_str()).unwrap_or(""),
+                event.get("severity").and_then(|v| v.as_str()).unwrap_or(""),
+                event.to_string()
+            ]
+        ).is_ok()
+    }
+
+    /// Load the most recent `limit` security events, oldest first
+    pub fn load_security_events(&self, limit: usize) -> Vec<JsonValue> {
+        let conn = Connection::open(&self.db_path).unwrap();
+        let mut stmt = conn.prepare(
+            "SELECT raw_data FROM (SELECT id, raw_data FROM security_events ORDER BY id DESC LIMIT ?) ORDER BY id ASC"
+        ).unwrap();
+        let rows = stmt.query_map(params![limit as i64], |row| row.get::<_, String>(0)).unwrap();
+        rows.filter_map(|r| r.ok()).filter_map(|raw| serde_json::from_str(&raw).ok()).collect()
+    }
+
+    /// Delete all but the most recent `keep` security events
+    pub fn prune_security_events(&self, keep: usize) -> bool {
+        let conn = Connection::open(&self.db_path).unwrap();
+        conn.execute(
+            "DELETE FROM security_events WHERE id NOT IN (SELECT id FROM security_events ORDER BY id DESC LIMIT ?)",
+            params![keep as i64]
+        ).is_ok()
+    }
 }
 
 #[cfg(test)]
@@ -179,29 +483,41 @@ mod tests {
     use super::*;
     use tempfile::tempdir;
     use serde_json::json;
+    use crate::core::wallet::LunaWallet;
 
     #[test]
     fn test_wallet_crud() {
         let dir = tempdir().unwrap();
         let db_path = dir.path().join("test_wallets.db");
         let db = WalletDatabase::new(Some(db_path.clone()));
-        let wallet = json!({
-            "address": "addr1",
-            "label": "main",
-            "public_key": "pubkey",
-            "encrypted_private_key": "privkey",
-            "balance": 123.45,
-            "created": 1234567890.0,
-            "metadata": {"foo": "bar"}
-        });
+        let mut wallet = LunaWallet::new("addr1".to_string(), "pubkey".to_string(), b"privkey".to_vec(), "main".to_string(), 1234567890);
+        wallet.balance = 123.45;
+        wallet.available_balance = 100.0;
+        wallet.set_metadata(json!({"foo": "bar"}));
         assert!(db.save_wallet(&wallet));
         let loaded = db.load_wallet("addr1").unwrap();
-        assert_eq!(loaded["address"], "addr1");
-        assert_eq!(loaded["label"], "main");
-        assert_eq!(loaded["public_key"], "pubkey");
-        assert_eq!(loaded["encrypted_private_key"], "privkey");
-        assert_eq!(loaded["balance"], 123.45);
-        assert_eq!(loaded["metadata"]["foo"], "bar");
+        assert_eq!(loaded.to_json(), wallet.to_json());
+        assert_eq!(loaded.available_balance, 100.0);
+        assert!(loaded.is_locked());
+        assert!(db.load_wallet("addr2").is_none());
+
+        // A metadata column from before it held `stored_metadata` is the application metadata
+        let conn = Connection::open(&db_path).unwrap();
+        conn.execute("UPDATE wallets SET metadata = '{\"foo\": \"baz\"}' WHERE address = 'addr1'", []).unwrap();
+        let loaded = db.load_wallet("addr1").unwrap();
+        assert_eq!((loaded.get_metadata(), loaded.available_balance), (&json!({"foo": "baz"}), 123.45));
+        db.save_wallet(&wallet);
+
+        let mut watched = LunaWallet::watch_only(&LunaWallet::create("phone", "pw").address, "phone").unwrap();
+        watched.set_metadata(json!({"foo": "bar"}));
+        assert!(db.save_wallet(&watched));
+        let listed = db.list_wallets();
+        assert_eq!(listed.iter().map(|w| w.is_watch_only()).collect::<Vec<_>>(), [false, true]);
+        assert_eq!(listed[1].to_json(), watched.to_json());
+        // Watch-only is the document's `kind`, as WalletDb stores it too
+        let stored: String = conn.query_row("SELECT metadata FROM wallets WHERE address = ?", params![watched.address], |row| row.get(0)).unwrap();
+        let stored: JsonValue = serde_json::from_str(&stored).unwrap();
+        assert_eq!((&stored["kind"], &stored["metadata"]), (&json!("watch_only"), &json!({"foo": "bar"})));
     }
 
     #[test]
@@ -209,7 +525,7 @@ mod tests {
         let dir = tempdir().unwrap();
         let db_path = dir.path().join("test_wallets.db");
         let db = WalletDatabase::new(Some(db_path.clone()));
-        let wallet = json!({"address": "addr2"});
+        let wallet = LunaWallet::new("addr2".to_string(), String::new(), Vec::new(), String::new(), 0);
         db.save_wallet(&wallet);
         let tx = json!({
             "hash": "tx1",
@@ -72,6 +72,9 @@ impl EncryptionManager {
         if !raw.starts_with(b"EL1") {
             return Err("Unsupported encryption format".to_string());
         }
+        if raw.len() < 3 + NONCE_LEN + MAC_LEN {
+            return Err("Encrypted data is truncated".to_string());
+        }
         let nonce = &raw[3..19];
         let mac = &raw[raw.len()-MAC_LEN..];
         let ciphertext = &raw[19..raw.len()-MAC_LEN];
@@ -1,3 +1,5 @@
+#[cfg(not(target_arch = "wasm32"))]
 pub mod cache;
+#[cfg(not(target_arch = "wasm32"))]
 pub mod database;
 pub mod encryption;
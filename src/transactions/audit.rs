@@ -0,0 +1,193 @@
+use std::collections::VecDeque;
+use std::fmt;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use serde::{Deserialize, Serialize};
+use crate::storage::database::WalletDatabase;
+
+/// How serious a security event is
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Info,
+    Warning,
+    Critical,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Severity::Info => "info",
+            Severity::Warning => "warning",
+            Severity::Critical => "critical",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// A rejection or notable acceptance recorded during validation
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SecurityEvent {
+    pub timestamp: u64,
+    pub address: String,
+    pub tx_hash: String,
+    pub rule: String,
+    pub severity: Severity,
+    pub accepted: bool,
+    pub message: String,
+}
+
+/// Criteria for `SecurityAuditLog::query_events`; unset fields match everything
+#[derive(Debug, Clone, Default)]
+pub struct AuditFilter {
+    /// Only events at or above this severity
+    pub min_severity: Option<Severity>,
+    pub address: Option<String>,
+    pub rule: Option<String>,
+    pub since: Option<u64>,
+}
+
+impl AuditFilter {
+    fn matches(&self, event: &SecurityEvent) -> bool {
+        self.min_severity.is_none_or(|s| event.severity >= s)
+            && self.address.as_ref().is_none_or(|a| event.address.eq_ignore_ascii_case(a))
+            && self.rule.as_ref().is_none_or(|r| &event.rule == r)
+            && self.since.is_none_or(|t| event.timestamp >= t)
+    }
+}
+
+/// Bounded in-memory log of security events with optional database persistence; the database
+/// keeps the same `capacity` most recent events
+#[derive(Debug)]
+pub struct SecurityAuditLog {
+    events: VecDeque<SecurityEvent>,
+    capacity: usize,
+    database: Option<WalletDatabase>,
+}
+
+impl Default for SecurityAuditLog {
+    fn default() -> Self {
+        Self::new(1000)
+    }
+}
+
+impl SecurityAuditLog {
+    pub fn new(capacity: usize) -> Self {
+        SecurityAuditLog {
+            events: VecDeque::new(),
+            capacity: capacity.max(1),
+            database: None,
+        }
+    }
+
+    /// Persist new events to the database and reload the most recent stored ones
+    pub fn with_database(mut self, database: WalletDatabase) -> Self {
+        database.prune_security_events(self.capacity);
+        self.events = database
+            .load_security_events(self.capacity)
+            .into_iter()
+            .filter_map(|v| serde_json::from_value(v).ok())
+            .collect();
+        self.database = Some(database);
+        self
+    }
+
+    pub fn record(&mut self, event: SecurityEvent) {
+        if let Some(db) = &self.database
+            && let Ok(value) = serde_json::to_value(&event)
+        {
+            db.save_security_event(&value);
+            db.prune_security_events(self.capacity);
+        }
+        if self.events.len() >= self.capacity {
+            self.events.pop_front();
+        }
+        self.events.push_back(event);
+    }
+
+    /// Matching events, oldest first
+    pub fn query_events(&self, filter: &AuditFilter) -> Vec<SecurityEvent> {
+        self.events.iter().filter(|e| filter.matches(e)).cloned().collect()
+    }
+
+    /// Write every buffered event as one JSON object per line, returning the count written
+    pub fn export_jsonl(&self, path: &Path) -> std::io::Result<usize> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        for event in &self.events {
+            serde_json::to_writer(&mut writer, event)?;
+            writer.write_all(b"\n")?;
+        }
+        writer.flush()?;
+        Ok(self.events.len())
+    }
+
+    pub fn len(&self) -> usize {
+        self.events.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.events.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    fn event(address: &str, severity: Severity) -> SecurityEvent {
+        SecurityEvent {
+            timestamp: 100,
+            address: address.to_string(),
+            tx_hash: "abc".to_string(),
+            rule: "test".to_string(),
+            severity,
+            accepted: false,
+            message: "test event".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_ring_buffer_and_export() {
+        let dir = tempdir().unwrap();
+        let mut log = SecurityAuditLog::new(2);
+        log.record(event("a", Severity::Info));
+        log.record(event("b", Severity::Warning));
+        log.record(event("c", Severity::Critical));
+        let events = log.query_events(&AuditFilter::default());
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0].address, "b");
+        let path = dir.path().join("audit.jsonl");
+        assert_eq!(log.export_jsonl(&path).unwrap(), 2);
+        let text = std::fs::read_to_string(&path).unwrap();
+        let parsed: SecurityEvent = serde_json::from_str(text.lines().last().unwrap()).unwrap();
+        assert_eq!(parsed.severity, Severity::Critical);
+    }
+
+    #[test]
+    fn test_persistence() {
+        let dir = tempdir().unwrap();
+        let db_path = dir.path().join("wallets.db");
+        let mut log = SecurityAuditLog::new(10).with_database(WalletDatabase::new(Some(db_path.clone())));
+        log.record(event("a", Severity::Warning));
+        let reloaded = SecurityAuditLog::new(10).with_database(WalletDatabase::new(Some(db_path)));
+        assert_eq!(reloaded.len(), 1);
+        assert_eq!(reloaded.query_events(&AuditFilter::default())[0].severity, Severity::Warning);
+    }
+
+    #[test]
+    fn test_persisted_events_are_pruned_to_capacity() {
+        let dir = tempdir().unwrap();
+        let db_path = dir.path().join("wallets.db");
+        let mut log = SecurityAuditLog::new(10).with_database(WalletDatabase::new(Some(db_path.clone())));
+        for address in ["a", "b", "c", "d"] {
+            log.record(event(address, Severity::Info));
+        }
+        let mut log = SecurityAuditLog::new(3).with_database(WalletDatabase::new(Some(db_path.clone())));
+        log.record(event("e", Severity::Info));
+        let stored = WalletDatabase::new(Some(db_path)).load_security_events(10);
+        let addresses: Vec<&str> = stored.iter().filter_map(|e| e["address"].as_str()).collect();
+        assert_eq!(addresses, ["c", "d", "e"]);
+    }
+}
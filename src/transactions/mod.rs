@@ -1,3 +1,19 @@
+pub mod signing;
+#[cfg(not(target_arch = "wasm32"))]
 pub mod transactions;
+#[cfg(not(target_arch = "wasm32"))]
 pub mod security;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod rate_limiter;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod score;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod outcome;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod audit;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod velocity;
+#[cfg(not(target_arch = "wasm32"))]
 pub mod validator;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod rules;
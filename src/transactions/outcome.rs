@@ -0,0 +1,208 @@
+use std::fmt;
+use crate::transactions::audit::Severity;
+use crate::transactions::score::ScoreBreakdown;
+use crate::transactions::velocity::HOUR_SECS;
+
+/// A single reason a transaction failed validation
+#[derive(Debug, Clone, PartialEq)]
+pub enum Violation {
+    MissingField(&'static str),
+    UnknownType { tx_type: String },
+    TxTypeNotAllowed { tx_type: String },
+    AmountTooLow { min: f64, got: f64 },
+    AmountTooHigh { max: f64, got: f64 },
+    FeeTooLow { required: f64, got: f64 },
+    MemoTooLong { max: usize, got: usize },
+    MemoControlCharacters { count: usize },
+    VelocityCapExceeded { window_secs: u64, used: f64, cap: f64, requested: f64 },
+    RateLimited { retry_after: u64 },
+    Blacklisted { address: String },
+    BadSignature,
+    UnauthorizedSigner { public_key: String },
+    InvalidDenomination { got: i64 },
+    InvalidMiningProof,
+    UnauthorizedReward,
+    Duplicate { hash: String },
+    InsufficientBalance { required: f64, available: f64 },
+    HeightMismatch { expected: u64, got: u64 },
+    PreviousHashMismatch { expected: String, got: String },
+    BlockHashMismatch { computed: String, got: String },
+    InsufficientWork { difficulty: u32 },
+    RewardCount { got: usize },
+    RewardAmountMismatch { expected: f64, got: f64 },
+    MerkleRootMismatch { computed: String, got: String },
+    InvalidTransaction { hash: String, violations: Vec<Violation> },
+    /// Raised by a rule added with `TransactionValidator::add_rule`
+    Custom { rule: String, message: String },
+}
+
+impl Violation {
+    /// Stable snake_case identifier, used as the audit log rule name
+    pub fn rule(&self) -> &str {
+        match self {
+            Violation::MissingField(_) => "missing_field",
+            Violation::UnknownType { .. } => "unknown_type",
+            Violation::TxTypeNotAllowed { .. } => "tx_type_not_allowed",
+            Violation::AmountTooLow { .. } => "min_amount",
+            Violation::AmountTooHigh { .. } => "max_amount",
+            Violation::FeeTooLow { .. } => "required_fee",
+            Violation::MemoTooLong { .. } => "max_memo_length",
+            Violation::MemoControlCharacters { .. } => "memo_control_chars",
+            Violation::VelocityCapExceeded { .. } => "velocity_cap",
+            Violation::RateLimited { .. } => "rate_limit",
+            Violation::Blacklisted { .. } => "blacklisted",
+            Violation::BadSignature => "invalid_signature",
+            Violation::UnauthorizedSigner { .. } => "unauthorized_signer",
+            Violation::InvalidDenomination { .. } => "invalid_denomination",
+            Violation::InvalidMiningProof => "invalid_mining_proof",
+            Violation::UnauthorizedReward => "unauthorized_reward",
+            Violation::Duplicate { .. } => "duplicate",
+            Violation::InsufficientBalance { .. } => "insufficient_balance",
+            Violation::HeightMismatch { .. } => "height_mismatch",
+            Violation::PreviousHashMismatch { .. } => "previous_hash_mismatch",
+            Violation::BlockHashMismatch { .. } => "block_hash_mismatch",
+            Violation::InsufficientWork { .. } => "insufficient_work",
+            Violation::RewardCount { .. } => "reward_count",
+            Violation::RewardAmountMismatch { .. } => "reward_amount_mismatch",
+            Violation::MerkleRootMismatch { .. } => "merkle_root_mismatch",
+            Violation::InvalidTransaction { .. } => "invalid_transaction",
+            Violation::Custom { rule, .. } => rule,
+        }
+    }
+
+    pub fn severity(&self) -> Severity {
+        match self {
+            Violation::Blacklisted { .. }
+            | Violation::BadSignature
+            | Violation::InvalidMiningProof
+            | Violation::UnauthorizedReward
+            | Violation::UnauthorizedSigner { .. }
+            | Violation::BlockHashMismatch { .. }
+            | Violation::InsufficientWork { .. }
+            | Violation::RewardAmountMismatch { .. }
+            | Violation::MerkleRootMismatch { .. } => Severity::Critical,
+            Violation::RateLimited { .. }
+            | Violation::AmountTooHigh { .. }
+            | Violation::VelocityCapExceeded { .. }
+            | Violation::UnknownType { .. }
+            | Violation::TxTypeNotAllowed { .. }
+            | Violation::Duplicate { .. }
+            | Violation::Custom { .. } => Severity::Warning,
+            _ => Severity::Info,
+        }
+    }
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Violation::MissingField(field) => write!(f, "Missing field: {}", field),
+            Violation::UnknownType { tx_type } => write!(f, "Unknown transaction type: {}", tx_type),
+            Violation::TxTypeNotAllowed { tx_type } => write!(f, "Transaction type not allowed: {}", tx_type),
+            Violation::AmountTooLow { min, .. } => write!(f, "Amount below minimum: {}", min),
+            Violation::AmountTooHigh { max, .. } => write!(f, "Amount above maximum: {}", max),
+            Violation::FeeTooLow { required, got } => write!(f, "Insufficient fee: {} (required: {})", got, required),
+            Violation::MemoTooLong { max, got } => write!(f, "Memo too long: {} bytes (max: {})", got, max),
+            Violation::MemoControlCharacters { count } => write!(f, "Memo contains {} control characters", count),
+            Violation::VelocityCapExceeded { window_secs, used, cap, requested } => {
+                let period = if *window_secs == HOUR_SECS { "Hourly" } else { "Daily" };
+                write!(f, "{} outgoing cap exceeded: {} used of {}, requested {}", period, used, cap, requested)
+            }
+            Violation::RateLimited { retry_after } => write!(f, "Rate limit exceeded, retry in {}s", retry_after),
+            Violation::Blacklisted { address } => write!(f, "Address is blacklisted: {}", address),
+            Violation::BadSignature => write!(f, "Invalid SM2 signature"),
+            Violation::UnauthorizedSigner { public_key } => write!(f, "Signer is not an authorized network key: {}", public_key),
+            Violation::InvalidDenomination { got } => write!(f, "Invalid denomination: {}", got),
+            Violation::InvalidMiningProof => write!(f, "Invalid mining proof"),
+            Violation::UnauthorizedReward => write!(f, "Unauthorized reward creation"),
+            Violation::Duplicate { .. } => write!(f, "Duplicate transaction detected"),
+            Violation::InsufficientBalance { required, available } => {
+                write!(f, "Insufficient balance: {} required, {} available", required, available)
+            }
+            Violation::HeightMismatch { expected, got } => write!(f, "Block height {} does not follow {}", got, expected),
+            Violation::PreviousHashMismatch { expected, got } => write!(f, "Previous hash {} does not match {}", got, expected),
+            Violation::BlockHashMismatch { computed, got } => write!(f, "Block hash {} does not match computed {}", got, computed),
+            Violation::InsufficientWork { difficulty } => write!(f, "Block hash does not meet difficulty {}", difficulty),
+            Violation::RewardCount { got } => write!(f, "Block must contain exactly one reward transaction, found {}", got),
+            Violation::RewardAmountMismatch { expected, got } => write!(f, "Reward amount {} does not match schedule {}", got, expected),
+            Violation::MerkleRootMismatch { computed, got } => write!(f, "Merkle root {} does not match computed {}", got, computed),
+            Violation::InvalidTransaction { hash, violations } => {
+                let reasons: Vec<String> = violations.iter().map(|v| v.to_string()).collect();
+                write!(f, "Invalid transaction {}: {}", hash, reasons.join(", "))
+            }
+            Violation::Custom { message, .. } => f.write_str(message),
+        }
+    }
+}
+
+/// Coarse risk bucket derived from amount and security score
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum RiskLevel {
+    VeryLow,
+    Low,
+    Medium,
+    High,
+}
+
+impl RiskLevel {
+    pub fn assess(amount: f64, security_score: u32) -> Self {
+        if amount > 1_000_000.0 && security_score < 80 {
+            RiskLevel::High
+        } else if amount > 10_000.0 && security_score < 60 {
+            RiskLevel::Medium
+        } else if security_score < 40 {
+            RiskLevel::Low
+        } else {
+            RiskLevel::VeryLow
+        }
+    }
+}
+
+impl RiskLevel {
+    pub fn from_breakdown(amount: f64, breakdown: &ScoreBreakdown) -> Self {
+        Self::assess(amount, breakdown.total)
+    }
+}
+
+impl fmt::Display for RiskLevel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            RiskLevel::VeryLow => "VERY_LOW",
+            RiskLevel::Low => "LOW",
+            RiskLevel::Medium => "MEDIUM",
+            RiskLevel::High => "HIGH",
+        };
+        f.write_str(name)
+    }
+}
+
+/// Result of validating a transaction, listing every violation found
+#[derive(Debug, Clone, PartialEq)]
+pub struct ValidationOutcome {
+    pub valid: bool,
+    pub violations: Vec<Violation>,
+    pub score: u32,
+    pub risk: RiskLevel,
+}
+
+impl ValidationOutcome {
+    pub fn new(violations: Vec<Violation>, score: u32, risk: RiskLevel) -> Self {
+        ValidationOutcome { valid: violations.is_empty(), violations, score, risk }
+    }
+
+    pub fn has(&self, predicate: impl Fn(&Violation) -> bool) -> bool {
+        self.violations.iter().any(predicate)
+    }
+
+    /// Human-readable summary: every violation joined, or a success message
+    pub fn message(&self) -> String {
+        if self.valid {
+            return "Valid transaction".to_string();
+        }
+        self.violations.iter().map(|v| v.to_string()).collect::<Vec<_>>().join("; ")
+    }
+
+    pub fn into_tuple(self) -> (bool, String) {
+        (self.valid, self.message())
+    }
+}
@@ -0,0 +1,237 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use serde::{Deserialize, Serialize};
+use crate::utils::clock::{Clock, SystemClock};
+
+/// Refill rate and burst size for one token bucket; a `count` of 0 means no limit
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RateLimit {
+    /// Tokens refilled per `window_secs`
+    pub count: usize,
+    pub window_secs: u64,
+    /// Bucket capacity; 0 uses `count`
+    pub burst: usize,
+}
+
+impl RateLimit {
+    pub fn new(count: usize, window_secs: u64, burst: usize) -> Self {
+        RateLimit { count, window_secs, burst }
+    }
+
+    pub fn is_unlimited(&self) -> bool {
+        self.count == 0
+    }
+
+    fn capacity(&self) -> f64 {
+        if self.burst == 0 { self.count as f64 } else { self.burst as f64 }
+    }
+
+    fn refill(&self, elapsed_secs: u64) -> f64 {
+        (elapsed_secs as f64 * self.count as f64) / self.window_secs.max(1) as f64
+    }
+
+    /// Whole seconds needed to refill `tokens`
+    fn secs_to_refill(&self, tokens: f64) -> u64 {
+        // Shave float noise so exact boundaries don't round up a second
+        (tokens * self.window_secs.max(1) as f64 / self.count as f64 - 1e-9).ceil().max(0.0) as u64
+    }
+}
+
+/// Remaining allowance for an address
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RateStatus {
+    /// Transactions that can be sent right now
+    pub remaining: usize,
+    /// Seconds until the next transaction is allowed; 0 when `remaining > 0`
+    pub reset_in: u64,
+}
+
+#[derive(Debug, Clone)]
+struct TokenBucket {
+    tokens: f64,
+    last_refill: u64,
+}
+
+impl TokenBucket {
+    fn refill(&mut self, limit: &RateLimit, now: u64) {
+        let elapsed = now.saturating_sub(self.last_refill);
+        self.tokens = (self.tokens + limit.refill(elapsed)).min(limit.capacity());
+        self.last_refill = now;
+    }
+}
+
+/// Per-address token-bucket rate limiter
+#[derive(Debug)]
+pub struct RateLimiter {
+    buckets: HashMap<String, TokenBucket>,
+    clock: Arc<dyn Clock>,
+    /// Buckets untouched for this long are dropped during cleanup
+    idle_ttl_secs: u64,
+    cleanup_interval_secs: u64,
+    last_cleanup: u64,
+}
+
+impl Default for RateLimiter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl RateLimiter {
+    pub fn new() -> Self {
+        Self::with_clock(Arc::new(SystemClock))
+    }
+
+    pub fn with_clock(clock: Arc<dyn Clock>) -> Self {
+        let now = clock.now();
+        RateLimiter {
+            buckets: HashMap::new(),
+            clock,
+            idle_ttl_secs: 3600,
+            cleanup_interval_secs: 300,
+            last_cleanup: now,
+        }
+    }
+
+    /// Read time from `clock`, keeping the buckets and cleanup settings
+    pub fn set_clock(&mut self, clock: Arc<dyn Clock>) {
+        self.last_cleanup = clock.now();
+        self.clock = clock;
+    }
+
+    pub fn with_idle_ttl(mut self, idle_ttl_secs: u64, cleanup_interval_secs: u64) -> Self {
+        self.idle_ttl_secs = idle_ttl_secs;
+        self.cleanup_interval_secs = cleanup_interval_secs;
+        self
+    }
+
+    /// Take one token for `address`, returning false when the bucket is empty
+    pub fn check(&mut self, address: &str, limit: &RateLimit) -> bool {
+        if limit.is_unlimited() {
+            return true;
+        }
+        let now = self.clock.now();
+        if now.saturating_sub(self.last_cleanup) >= self.cleanup_interval_secs {
+            self.cleanup();
+        }
+        let bucket = self.buckets.entry(address.to_lowercase()).or_insert(TokenBucket {
+            tokens: limit.capacity(),
+            last_refill: now,
+        });
+        bucket.refill(limit, now);
+        if bucket.tokens + 1e-9 < 1.0 {
+            return false;
+        }
+        bucket.tokens -= 1.0;
+        true
+    }
+
+    /// `remaining` is `usize::MAX` under an unlimited `limit`
+    pub fn status(&self, address: &str, limit: &RateLimit) -> RateStatus {
+        if limit.is_unlimited() {
+            return RateStatus { remaining: usize::MAX, reset_in: 0 };
+        }
+        let now = self.clock.now();
+        let mut bucket = match self.buckets.get(&address.to_lowercase()) {
+            Some(bucket) => bucket.clone(),
+            None => return RateStatus { remaining: limit.capacity() as usize, reset_in: 0 },
+        };
+        bucket.refill(limit, now);
+        // The same tolerance `check` allows
+        let remaining = (bucket.tokens + 1e-9).floor() as usize;
+        let reset_in = if remaining > 0 {
+            0
+        } else {
+            limit.secs_to_refill(1.0 - bucket.tokens)
+        };
+        RateStatus { remaining, reset_in }
+    }
+
+    /// Drop buckets idle longer than the idle TTL, returning how many were removed
+    pub fn cleanup(&mut self) -> usize {
+        let now = self.clock.now();
+        let ttl = self.idle_ttl_secs;
+        let before = self.buckets.len();
+        self.buckets.retain(|_, b| now.saturating_sub(b.last_refill) < ttl);
+        self.last_cleanup = now;
+        before - self.buckets.len()
+    }
+
+    pub fn tracked_addresses(&self) -> usize {
+        self.buckets.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::clock::ManualClock;
+
+    #[test]
+    fn test_burst_then_steady_state() {
+        let clock = Arc::new(ManualClock::new(1_000));
+        let mut limiter = RateLimiter::with_clock(clock.clone());
+        let limit = RateLimit::new(1, 10, 5);
+        for _ in 0..5 {
+            assert!(limiter.check("alice", &limit));
+        }
+        assert!(!limiter.check("alice", &limit));
+        clock.advance(10);
+        assert!(limiter.check("alice", &limit));
+        assert!(!limiter.check("alice", &limit));
+        clock.advance(5);
+        assert!(!limiter.check("alice", &limit));
+        clock.advance(5);
+        assert!(limiter.check("alice", &limit));
+    }
+
+    #[test]
+    fn test_idle_cleanup() {
+        let clock = Arc::new(ManualClock::new(0));
+        let mut limiter = RateLimiter::with_clock(clock.clone()).with_idle_ttl(100, 50);
+        let limit = RateLimit::new(10, 60, 0);
+        limiter.check("old", &limit);
+        clock.advance(120);
+        limiter.check("new", &limit);
+        assert_eq!(limiter.tracked_addresses(), 1);
+        clock.advance(100);
+        assert_eq!(limiter.cleanup(), 1);
+        assert_eq!(limiter.tracked_addresses(), 0);
+    }
+
+    #[test]
+    fn test_status_accuracy() {
+        let clock = Arc::new(ManualClock::new(0));
+        let mut limiter = RateLimiter::with_clock(clock.clone());
+        let limit = RateLimit::new(2, 60, 2);
+        assert_eq!(limiter.status("bob", &limit), RateStatus { remaining: 2, reset_in: 0 });
+        limiter.check("bob", &limit);
+        limiter.check("bob", &limit);
+        assert_eq!(limiter.status("bob", &limit), RateStatus { remaining: 0, reset_in: 30 });
+        clock.advance(18);
+        assert_eq!(limiter.status("bob", &limit), RateStatus { remaining: 0, reset_in: 12 });
+        clock.advance(12);
+        assert_eq!(limiter.status("bob", &limit).remaining, 1);
+    }
+
+    #[test]
+    fn test_zero_count_is_unlimited() {
+        let mut limiter = RateLimiter::new();
+        let limit = RateLimit::new(0, 60, 0);
+        for _ in 0..100 {
+            assert!(limiter.check("carol", &limit));
+        }
+        assert_eq!(limiter.status("carol", &limit), RateStatus { remaining: usize::MAX, reset_in: 0 });
+        assert_eq!(limiter.tracked_addresses(), 0);
+    }
+
+    #[test]
+    fn test_set_clock_keeps_configuration() {
+        let clock = Arc::new(ManualClock::new(0));
+        let mut limiter = RateLimiter::new().with_idle_ttl(100, 50);
+        limiter.set_clock(clock.clone());
+        limiter.check("old", &RateLimit::new(10, 60, 0));
+        clock.advance(100);
+        assert_eq!(limiter.cleanup(), 1);
+    }
+}
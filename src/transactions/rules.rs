@@ -0,0 +1,300 @@
+use std::collections::HashMap;
+use lru::LruCache;
+use serde_json::Value;
+use crate::transactions::outcome::Violation;
+use crate::transactions::security::TransactionSecurity;
+use crate::transactions::validator::BalanceSource;
+
+/// State a rule may consult while checking one transaction
+pub struct RuleContext<'a> {
+    pub security: &'a mut TransactionSecurity,
+    recent: &'a LruCache<String, ()>,
+    confirmed: &'a LruCache<String, ()>,
+    balance_source: Option<&'a BalanceSource>,
+    pending_outgoing: &'a HashMap<String, f64>,
+}
+
+impl<'a> RuleContext<'a> {
+    pub(crate) fn new(
+        security: &'a mut TransactionSecurity,
+        recent: &'a LruCache<String, ()>,
+        confirmed: &'a LruCache<String, ()>,
+        balance_source: Option<&'a BalanceSource>,
+        pending_outgoing: &'a HashMap<String, f64>,
+    ) -> Self {
+        RuleContext { security, recent, confirmed, balance_source, pending_outgoing }
+    }
+
+    /// Whether the hash was accepted recently by this validator
+    pub fn contains_recent(&self, tx_hash: &str) -> bool {
+        self.recent.contains(tx_hash)
+    }
+
+    /// Whether the hash is in a block this validator accepted
+    pub fn contains_confirmed(&self, tx_hash: &str) -> bool {
+        self.confirmed.contains(tx_hash)
+    }
+
+    /// Balance minus value already validated this session, None without a balance source
+    pub fn available_balance(&self, address: &str) -> Option<f64> {
+        let source = self.balance_source?;
+        let address = address.to_lowercase();
+        let pending = self.pending_outgoing.get(&address).copied().unwrap_or(0.0);
+        Some(source(&address).unwrap_or(0.0) - pending)
+    }
+}
+
+/// One check in the transaction validation pipeline
+pub trait ValidationRule: Send + Sync {
+    /// Unique name used by `remove_rule` and `list_rules`
+    fn name(&self) -> &str;
+
+    fn check(&self, transaction: &HashMap<String, Value>, ctx: &mut RuleContext) -> Option<Violation>;
+
+    /// Skip the remaining rules when this one fails
+    fn halts(&self) -> bool {
+        false
+    }
+
+    /// Also check each transaction of a block in `validate_block`. The built-in rules about this
+    /// node's own mempool, such as rate limits and pending balances, opt out.
+    fn checks_blocks(&self) -> bool {
+        true
+    }
+}
+
+/// The built-in rules, in execution order
+pub fn default_rules() -> Vec<Box<dyn ValidationRule>> {
+    vec![
+        Box::new(DuplicateRule),
+        Box::new(FieldsRule),
+        Box::new(SystemRule),
+        Box::new(TxTypeRule),
+        Box::new(AmountRule),
+        Box::new(FeeRule),
+        Box::new(MemoRule),
+        Box::new(VelocityRule),
+        Box::new(RateLimitRule),
+        Box::new(SignatureRule),
+        Box::new(BlacklistRule),
+        Box::new(BalanceRule),
+    ]
+}
+
+fn tx_type(transaction: &HashMap<String, Value>) -> String {
+    transaction.get("type").and_then(|v| v.as_str()).unwrap_or("").to_lowercase()
+}
+
+fn is_system(transaction: &HashMap<String, Value>) -> bool {
+    matches!(tx_type(transaction).as_str(), "reward" | "gtx_genesis")
+}
+
+/// Rejects hashes this validator accepted recently or saw in an accepted block
+pub struct DuplicateRule;
+
+impl ValidationRule for DuplicateRule {
+    fn name(&self) -> &str {
+        "duplicate"
+    }
+
+    fn check(&self, transaction: &HashMap<String, Value>, ctx: &mut RuleContext) -> Option<Violation> {
+        let tx_hash = transaction.get("hash").and_then(|v| v.as_str()).unwrap_or("");
+        (ctx.contains_recent(tx_hash) || ctx.contains_confirmed(tx_hash)).then(|| Violation::Duplicate { hash: tx_hash.to_string() })
+    }
+
+    fn halts(&self) -> bool {
+        true
+    }
+
+    fn checks_blocks(&self) -> bool {
+        false
+    }
+}
+
+/// Known type and the fields it requires
+pub struct FieldsRule;
+
+impl ValidationRule for FieldsRule {
+    fn name(&self) -> &str {
+        "fields"
+    }
+
+    fn check(&self, transaction: &HashMap<String, Value>, ctx: &mut RuleContext) -> Option<Violation> {
+        ctx.security.structure_violations(transaction).into_iter().next()
+    }
+
+    fn halts(&self) -> bool {
+        true
+    }
+}
+
+/// Denomination, mining proof and reward source of system transactions
+pub struct SystemRule;
+
+impl ValidationRule for SystemRule {
+    fn name(&self) -> &str {
+        "system"
+    }
+
+    fn check(&self, transaction: &HashMap<String, Value>, ctx: &mut RuleContext) -> Option<Violation> {
+        ctx.security.system_violations(transaction).into_iter().next()
+    }
+}
+
+pub struct TxTypeRule;
+
+impl ValidationRule for TxTypeRule {
+    fn name(&self) -> &str {
+        "tx_type"
+    }
+
+    fn check(&self, transaction: &HashMap<String, Value>, ctx: &mut RuleContext) -> Option<Violation> {
+        ctx.security.tx_type_violation(transaction)
+    }
+
+    fn checks_blocks(&self) -> bool {
+        false
+    }
+}
+
+pub struct AmountRule;
+
+impl ValidationRule for AmountRule {
+    fn name(&self) -> &str {
+        "amount"
+    }
+
+    fn check(&self, transaction: &HashMap<String, Value>, ctx: &mut RuleContext) -> Option<Violation> {
+        ctx.security.amount_violation(transaction)
+    }
+
+    fn checks_blocks(&self) -> bool {
+        false
+    }
+}
+
+pub struct FeeRule;
+
+impl ValidationRule for FeeRule {
+    fn name(&self) -> &str {
+        "fee"
+    }
+
+    fn check(&self, transaction: &HashMap<String, Value>, ctx: &mut RuleContext) -> Option<Violation> {
+        ctx.security.fee_violation(transaction)
+    }
+
+    fn checks_blocks(&self) -> bool {
+        false
+    }
+}
+
+pub struct MemoRule;
+
+impl ValidationRule for MemoRule {
+    fn name(&self) -> &str {
+        "memo"
+    }
+
+    fn check(&self, transaction: &HashMap<String, Value>, ctx: &mut RuleContext) -> Option<Violation> {
+        let memo = transaction.get("memo").and_then(|v| v.as_str())?;
+        let policy = ctx.security.policy_for(&tx_type(transaction));
+        TransactionSecurity::memo_violations(policy, memo).into_iter().next()
+    }
+
+    fn checks_blocks(&self) -> bool {
+        false
+    }
+}
+
+pub struct VelocityRule;
+
+impl ValidationRule for VelocityRule {
+    fn name(&self) -> &str {
+        "velocity"
+    }
+
+    fn check(&self, transaction: &HashMap<String, Value>, ctx: &mut RuleContext) -> Option<Violation> {
+        ctx.security.velocity_violation(transaction)
+    }
+
+    fn checks_blocks(&self) -> bool {
+        false
+    }
+}
+
+pub struct RateLimitRule;
+
+impl ValidationRule for RateLimitRule {
+    fn name(&self) -> &str {
+        "rate_limit"
+    }
+
+    fn check(&self, transaction: &HashMap<String, Value>, ctx: &mut RuleContext) -> Option<Violation> {
+        ctx.security.rate_limit_violation(transaction)
+    }
+
+    fn checks_blocks(&self) -> bool {
+        false
+    }
+}
+
+pub struct SignatureRule;
+
+impl ValidationRule for SignatureRule {
+    fn name(&self) -> &str {
+        "signature"
+    }
+
+    fn check(&self, transaction: &HashMap<String, Value>, ctx: &mut RuleContext) -> Option<Violation> {
+        ctx.security.signature_violation(transaction)
+    }
+}
+
+pub struct BlacklistRule;
+
+impl ValidationRule for BlacklistRule {
+    fn name(&self) -> &str {
+        "blacklist"
+    }
+
+    fn check(&self, transaction: &HashMap<String, Value>, ctx: &mut RuleContext) -> Option<Violation> {
+        if is_system(transaction) {
+            return None;
+        }
+        ctx.security.blacklist_violation(transaction)
+    }
+
+    fn checks_blocks(&self) -> bool {
+        false
+    }
+}
+
+/// Senders must afford amount+fee on top of value already validated this session
+pub struct BalanceRule;
+
+impl ValidationRule for BalanceRule {
+    fn name(&self) -> &str {
+        "balance"
+    }
+
+    fn check(&self, transaction: &HashMap<String, Value>, ctx: &mut RuleContext) -> Option<Violation> {
+        if is_system(transaction) {
+            return None;
+        }
+        let from_address = transaction.get("from").and_then(|v| v.as_str())?;
+        let available = ctx.available_balance(from_address)?;
+        let amount = transaction.get("amount").and_then(|v| v.as_f64()).unwrap_or(0.0);
+        let fee = transaction.get("fee").and_then(|v| v.as_f64()).unwrap_or(0.0);
+        let required = amount + fee;
+        // Tolerate float rounding so spending an exact balance is allowed
+        if required > available + 1e-9 {
+            return Some(Violation::InsufficientBalance { required, available });
+        }
+        None
+    }
+
+    fn checks_blocks(&self) -> bool {
+        false
+    }
+}
@@ -0,0 +1,175 @@
+use std::collections::HashMap;
+use std::fmt;
+use serde::{Deserialize, Serialize};
+
+/// Something that raises or lowers a transaction's security score
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ScoreFactor {
+    FullSignature,
+    ShortSignature,
+    PublicKey,
+    FreshTimestamp,
+    Nonce,
+    SecurityHash,
+    Anomaly,
+}
+
+impl fmt::Display for ScoreFactor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            ScoreFactor::FullSignature => "full signature",
+            ScoreFactor::ShortSignature => "short signature",
+            ScoreFactor::PublicKey => "public key",
+            ScoreFactor::FreshTimestamp => "fresh timestamp",
+            ScoreFactor::Nonce => "nonce",
+            ScoreFactor::SecurityHash => "security hash",
+            ScoreFactor::Anomaly => "anomalous amount",
+        };
+        f.write_str(name)
+    }
+}
+
+/// Points awarded per factor
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ScoreWeights {
+    pub full_signature: u32,
+    pub short_signature: u32,
+    pub public_key: u32,
+    pub fresh_timestamp: u32,
+    /// Timestamps younger than this count as fresh
+    pub fresh_timestamp_secs: u64,
+    pub nonce: u32,
+    pub security_hash: u32,
+    /// Deducted after clamping when the amount is anomalous
+    pub anomaly_penalty: u32,
+}
+
+impl Default for ScoreWeights {
+    fn default() -> Self {
+        ScoreWeights {
+            full_signature: 60,
+            short_signature: 40,
+            public_key: 30,
+            fresh_timestamp: 20,
+            fresh_timestamp_secs: 600,
+            nonce: 10,
+            security_hash: 10,
+            anomaly_penalty: 30,
+        }
+    }
+}
+
+impl ScoreWeights {
+    pub fn from_json(value: &serde_json::Value) -> Result<Self, String> {
+        serde_json::from_value(value.clone()).map_err(|e| format!("Invalid score weights: {}", e))
+    }
+
+    pub fn from_toml(text: &str) -> Result<Self, String> {
+        toml::from_str(text).map_err(|e| format!("Invalid score weights: {}", e))
+    }
+}
+
+/// How a security score was reached
+#[derive(Debug, Clone, PartialEq)]
+pub struct ScoreBreakdown {
+    /// Final score: earned points clamped to 100, minus penalties
+    pub total: u32,
+    /// Points earned
+    pub components: Vec<(ScoreFactor, u32)>,
+    /// Points that were available but not earned
+    pub missing: Vec<(ScoreFactor, u32)>,
+    /// Points deducted after clamping
+    pub penalties: Vec<(ScoreFactor, u32)>,
+}
+
+impl ScoreBreakdown {
+    /// Score the transaction; `now` is unix seconds and `anomalous` comes from velocity tracking
+    pub fn calculate(weights: &ScoreWeights, transaction: &HashMap<String, serde_json::Value>, now: u64, anomalous: bool) -> Self {
+        let mut components = Vec::new();
+        let mut missing = Vec::new();
+        let mut award = |factor: ScoreFactor, weight: u32, earned: bool| {
+            if earned {
+                components.push((factor, weight));
+            } else {
+                missing.push((factor, weight));
+            }
+        };
+        let signature = transaction.get("signature").and_then(|v| v.as_str()).unwrap_or("");
+        match signature.len() {
+            128 => award(ScoreFactor::FullSignature, weights.full_signature, true),
+            64 => award(ScoreFactor::ShortSignature, weights.short_signature, true),
+            _ => award(ScoreFactor::FullSignature, weights.full_signature, false),
+        }
+        let public_key = transaction.get("public_key").and_then(|v| v.as_str()).unwrap_or("");
+        award(ScoreFactor::PublicKey, weights.public_key, public_key.starts_with("04"));
+        let timestamp = transaction.get("timestamp").and_then(|v| v.as_f64()).unwrap_or(0.0);
+        award(ScoreFactor::FreshTimestamp, weights.fresh_timestamp, (now as f64 - timestamp) < weights.fresh_timestamp_secs as f64);
+        award(ScoreFactor::Nonce, weights.nonce, transaction.contains_key("nonce"));
+        award(ScoreFactor::SecurityHash, weights.security_hash, transaction.contains_key("security_hash"));
+
+        let mut penalties = Vec::new();
+        if anomalous {
+            penalties.push((ScoreFactor::Anomaly, weights.anomaly_penalty));
+        }
+        let earned: u32 = components.iter().map(|(_, p)| p).sum();
+        let deducted: u32 = penalties.iter().map(|(_, p)| p).sum();
+        let total = earned.min(100).saturating_sub(deducted);
+        ScoreBreakdown { total, components, missing, penalties }
+    }
+
+    /// Human-readable reasons the score fell short, e.g. "no nonce (-10)"
+    pub fn explain(&self) -> Vec<String> {
+        self.missing
+            .iter()
+            .map(|(factor, points)| format!("no {} (-{})", factor, points))
+            .chain(self.penalties.iter().map(|(factor, points)| format!("{} (-{})", factor, points)))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn make_tx() -> HashMap<String, serde_json::Value> {
+        let mut tx = HashMap::new();
+        tx.insert("signature".to_string(), json!("a".repeat(64)));
+        tx.insert("public_key".to_string(), json!("04abcdef"));
+        tx.insert("timestamp".to_string(), json!(1_000));
+        tx
+    }
+
+    #[test]
+    fn test_components_sum_to_total() {
+        let breakdown = ScoreBreakdown::calculate(&ScoreWeights::default(), &make_tx(), 1_100, false);
+        let sum: u32 = breakdown.components.iter().map(|(_, p)| p).sum();
+        assert_eq!(breakdown.total, 90);
+        assert_eq!(sum, breakdown.total);
+        assert_eq!(breakdown.explain(), vec!["no nonce (-10)", "no security hash (-10)"]);
+    }
+
+    #[test]
+    fn test_weights_override_changes_total() {
+        let weights = ScoreWeights::from_toml("short_signature = 10\npublic_key = 5").unwrap();
+        let breakdown = ScoreBreakdown::calculate(&weights, &make_tx(), 1_100, false);
+        assert_eq!(breakdown.total, 35);
+        assert!(breakdown.components.contains(&(ScoreFactor::ShortSignature, 10)));
+    }
+
+    #[test]
+    fn test_clamped_at_100_before_penalties() {
+        let mut tx = make_tx();
+        tx.insert("signature".to_string(), json!("a".repeat(128)));
+        tx.insert("nonce".to_string(), json!(1));
+        tx.insert("security_hash".to_string(), json!("abc"));
+        let breakdown = ScoreBreakdown::calculate(&ScoreWeights::default(), &tx, 1_100, false);
+        assert_eq!(breakdown.total, 100);
+        assert!(breakdown.explain().is_empty());
+        let anomalous = ScoreBreakdown::calculate(&ScoreWeights::default(), &tx, 5_000, true);
+        assert_eq!(anomalous.total, 70);
+        assert_eq!(anomalous.explain(), vec!["no fresh timestamp (-20)", "anomalous amount (-30)"]);
+    }
+}
@@ -1,123 +1,638 @@
 pub struct Security;
 
 use std::collections::{HashMap, HashSet};
-use std::time::{SystemTime, UNIX_EPOCH};
+use std::fmt;
+use std::sync::Arc;
+use serde::{Deserialize, Serialize};
+use unicode_normalization::UnicodeNormalization;
+use crate::core::crypto::Crypto;
+use crate::core::wallet::{derives_address, LunaWallet};
+use crate::storage::database::WalletDatabase;
+use crate::transactions::audit::{AuditFilter, SecurityAuditLog, SecurityEvent, Severity};
+use crate::transactions::outcome::{RiskLevel, ValidationOutcome, Violation};
+use crate::transactions::score::{ScoreBreakdown, ScoreWeights};
+use crate::transactions::rate_limiter::{RateLimit, RateLimiter, RateStatus};
+use crate::transactions::transactions::TransactionManager;
+use crate::transactions::velocity::{VelocityTracker, DAY_SECS, HOUR_SECS};
+use crate::utils::clock::{Clock, SystemClock};
 
-#[derive(Debug, Default)]
-pub struct TransactionSecurity {
-    pub min_transaction_amount: f64,
-    pub max_transaction_amount: f64,
+pub const DEFAULT_MAX_MEMO_BYTES: usize = 512;
+
+/// What to do with a memo that is too long or contains control characters
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MemoAction {
+    /// Fail validation
+    #[default]
+    Reject,
+    /// Accept, judging the memo as `sanitize_memo_with` rewrites it under the policy's
+    /// `max_memo_length` and `normalize_memo`
+    Sanitize,
+}
+
+/// Strip control characters, NFC-normalize and truncate to the default memo size
+pub fn sanitize_memo(memo: &str) -> String {
+    sanitize_memo_with(memo, DEFAULT_MAX_MEMO_BYTES, true)
+}
+
+/// Strip control characters, optionally NFC-normalize, and truncate to `max_bytes` on a char boundary
+pub fn sanitize_memo_with(memo: &str, max_bytes: usize, normalize: bool) -> String {
+    let cleaned: String = memo.chars().filter(|c| !c.is_control()).collect();
+    let mut cleaned = if normalize { cleaned.nfc().collect() } else { cleaned };
+    if cleaned.len() > max_bytes {
+        let mut end = max_bytes;
+        while !cleaned.is_char_boundary(end) {
+            end -= 1;
+        }
+        cleaned.truncate(end);
+    }
+    cleaned
+}
+
+/// Limits applied to transactions of one type
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SecurityPolicy {
+    pub min_amount: f64,
+    pub max_amount: f64,
     pub required_fee: f64,
-    pub rate_limits: HashMap<String, Vec<u64>>, // address -> timestamps
-    pub blacklisted_addresses: HashSet<String>,
+    pub rate_limit_window_secs: u64,
+    /// Transactions refilled per window; 0 disables rate limiting
+    pub rate_limit_count: usize,
+    /// Transactions that may be sent back-to-back; 0 uses `rate_limit_count`
+    pub rate_limit_burst: usize,
+    pub max_memo_length: usize,
+    /// Whether oversized memos or control characters are rejected or tolerated
+    pub memo_action: MemoAction,
+    /// Compare memos in Unicode NFC form
+    pub normalize_memo: bool,
+    /// Cap on value sent per address in any rolling hour; 0 is unlimited
+    pub max_hourly_outgoing: f64,
+    /// Cap on value sent per address in any rolling 24 hours; 0 is unlimited
+    pub max_daily_outgoing: f64,
+    /// Flag transactions above this multiple of the sender's 30-day average; 0 disables
+    pub anomaly_multiplier: f64,
+    /// Transaction types this policy accepts; empty accepts any type
+    pub allowed_tx_types: Vec<String>,
+}
+
+impl Default for SecurityPolicy {
+    fn default() -> Self {
+        SecurityPolicy {
+            min_amount: 0.000001,
+            max_amount: 100000000.0,
+            required_fee: 0.00001,
+            rate_limit_window_secs: 60,
+            rate_limit_count: 10,
+            rate_limit_burst: 0,
+            max_memo_length: DEFAULT_MAX_MEMO_BYTES,
+            memo_action: MemoAction::Reject,
+            normalize_memo: true,
+            max_hourly_outgoing: 0.0,
+            max_daily_outgoing: 0.0,
+            anomaly_multiplier: 10.0,
+            allowed_tx_types: Vec::new(),
+        }
+    }
+}
+
+impl SecurityPolicy {
+    /// Policy for network-issued transactions (rewards, genesis bills): no fee, no rate limit
+    pub fn system() -> Self {
+        SecurityPolicy {
+            min_amount: 0.0,
+            required_fee: 0.0,
+            rate_limit_count: 0,
+            ..Default::default()
+        }
+    }
+
+    pub fn from_json(value: &serde_json::Value) -> Result<Self, String> {
+        serde_json::from_value(value.clone()).map_err(|e| format!("Invalid security policy: {}", e))
+    }
+
+    pub fn from_toml(text: &str) -> Result<Self, String> {
+        toml::from_str(text).map_err(|e| format!("Invalid security policy: {}", e))
+    }
+
+    pub fn rate_limit(&self) -> RateLimit {
+        RateLimit::new(self.rate_limit_count, self.rate_limit_window_secs, self.rate_limit_burst)
+    }
+}
+
+/// The policy rule a transaction failed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum PolicyRule {
+    TxTypeNotAllowed,
+    MinAmount,
+    MaxAmount,
+    RequiredFee,
+    MaxMemoLength,
+    RateLimit,
+    VelocityCap,
+    MemoControlChars,
+}
+
+impl fmt::Display for PolicyRule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            PolicyRule::TxTypeNotAllowed => "tx_type_not_allowed",
+            PolicyRule::MinAmount => "min_amount",
+            PolicyRule::MaxAmount => "max_amount",
+            PolicyRule::RequiredFee => "required_fee",
+            PolicyRule::MaxMemoLength => "max_memo_length",
+            PolicyRule::RateLimit => "rate_limit",
+            PolicyRule::VelocityCap => "velocity_cap",
+            PolicyRule::MemoControlChars => "memo_control_chars",
+        };
+        f.write_str(name)
+    }
+}
+
+impl PolicyRule {
+    /// The policy rule behind a violation, if it came from a policy check
+    pub fn from_violation(violation: &Violation) -> Option<Self> {
+        match violation {
+            Violation::TxTypeNotAllowed { .. } => Some(PolicyRule::TxTypeNotAllowed),
+            Violation::AmountTooLow { .. } => Some(PolicyRule::MinAmount),
+            Violation::AmountTooHigh { .. } => Some(PolicyRule::MaxAmount),
+            Violation::FeeTooLow { .. } => Some(PolicyRule::RequiredFee),
+            Violation::MemoTooLong { .. } => Some(PolicyRule::MaxMemoLength),
+            Violation::MemoControlCharacters { .. } => Some(PolicyRule::MemoControlChars),
+            Violation::RateLimited { .. } => Some(PolicyRule::RateLimit),
+            Violation::VelocityCapExceeded { .. } => Some(PolicyRule::VelocityCap),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PolicyFailure {
+    pub rule: PolicyRule,
+    pub message: String,
+}
+
+impl PolicyFailure {
+    fn new(rule: PolicyRule, message: String) -> Self {
+        PolicyFailure { rule, message }
+    }
+}
+
+/// A blacklist or whitelist entry
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AddressListEntry {
+    pub address: String,
+    pub reason: String,
+    pub added_at: u64,
+    /// Unix seconds after which the entry no longer applies; None never expires
+    pub expires_at: Option<u64>,
+}
+
+impl AddressListEntry {
+    pub fn is_expired(&self, now: u64) -> bool {
+        self.expires_at.is_some_and(|t| now >= t)
+    }
+}
+
+#[derive(Debug)]
+pub struct TransactionSecurity {
+    pub default_policy: SecurityPolicy,
+    pub policies: HashMap<String, SecurityPolicy>,
+    /// Validate unknown transaction types against the default policy instead of rejecting them
+    pub allow_unknown_types: bool,
+    /// Public keys allowed to sign reward and gtx_genesis transactions
+    pub authorized_signers: HashSet<String>,
+    /// Migration mode: accept the legacy "system" signature on system transactions, logging a warning
+    pub allow_legacy_system_signatures: bool,
+    /// Accept senders whose address has no checksum
+    pub allow_legacy_addresses: bool,
+    pub rate_limiter: RateLimiter,
+    pub blacklisted_addresses: HashMap<String, AddressListEntry>,
+    /// Addresses exempt from rate limits and amount ceilings
+    pub whitelisted_addresses: HashMap<String, AddressListEntry>,
     pub sm2_available: bool,
+    pub audit_log: SecurityAuditLog,
+    pub velocity: VelocityTracker,
+    pub score_weights: ScoreWeights,
+    database: Option<WalletDatabase>,
+    clock: Arc<dyn Clock>,
+}
+
+impl Default for TransactionSecurity {
+    fn default() -> Self {
+        Self::new(false)
+    }
 }
 
 impl TransactionSecurity {
     pub fn new(sm2_available: bool) -> Self {
+        let mut policies = HashMap::new();
+        policies.insert("reward".to_string(), SecurityPolicy::system());
+        policies.insert("gtx_genesis".to_string(), SecurityPolicy::system());
         TransactionSecurity {
-            min_transaction_amount: 0.000001,
-            max_transaction_amount: 100000000.0,
-            required_fee: 0.00001,
-            rate_limits: HashMap::new(),
-            blacklisted_addresses: HashSet::new(),
+            default_policy: SecurityPolicy::default(),
+            policies,
+            allow_unknown_types: false,
+            authorized_signers: HashSet::new(),
+            allow_legacy_system_signatures: false,
+            allow_legacy_addresses: true,
+            rate_limiter: RateLimiter::new(),
+            blacklisted_addresses: HashMap::new(),
+            whitelisted_addresses: HashMap::new(),
             sm2_available,
+            audit_log: SecurityAuditLog::default(),
+            velocity: VelocityTracker::new(),
+            score_weights: ScoreWeights::default(),
+            database: None,
+            clock: Arc::new(SystemClock),
         }
     }
 
-    pub fn validate_transaction_security(&mut self, transaction: &HashMap<String, serde_json::Value>) -> (bool, String) {
-        let tx_type = transaction.get("type").and_then(|v| v.as_str()).unwrap_or("").to_lowercase();
-        match tx_type.as_str() {
-            "gtx_genesis" => self.validate_genesis_transaction(transaction),
-            "reward" => self.validate_reward_transaction(transaction),
-            "transfer" => self.validate_transfer_transaction(transaction),
-            _ => (false, format!("Unknown transaction type: {}", tx_type)),
+    /// Persist blacklist/whitelist entries in the wallet database, loading existing ones
+    pub fn with_database(mut self, database: WalletDatabase) -> Self {
+        for (address, reason, added_at, expires_at) in database.load_blacklist() {
+            self.blacklisted_addresses.insert(address.clone(), AddressListEntry { address, reason, added_at, expires_at });
+        }
+        for (address, reason, added_at) in database.load_whitelist() {
+            self.whitelisted_addresses.insert(address.clone(), AddressListEntry { address, reason, added_at, expires_at: None });
         }
+        self.audit_log = std::mem::take(&mut self.audit_log).with_database(database.clone());
+        self.velocity = std::mem::take(&mut self.velocity).with_database(database.clone(), self.clock.now());
+        self.database = Some(database);
+        self.purge_expired();
+        self
+    }
+
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.rate_limiter.set_clock(clock.clone());
+        self.clock = clock;
+        self
     }
 
-    fn validate_genesis_transaction(&self, transaction: &HashMap<String, serde_json::Value>) -> (bool, String) {
-        let required_fields = ["bill_serial", "denomination", "mining_difficulty", "hash", "nonce"];
-        for field in &required_fields {
-            if !transaction.contains_key(*field) {
-                return (false, format!("Missing GTX field: {}", field));
+    /// Override policies per transaction type; the key "default" replaces the fallback policy
+    pub fn with_policy(mut self, policies: HashMap<String, SecurityPolicy>) -> Self {
+        for (tx_type, policy) in policies {
+            let tx_type = tx_type.to_lowercase();
+            if tx_type == "default" {
+                self.default_policy = policy;
+            } else {
+                self.policies.insert(tx_type, policy);
             }
         }
-        let denomination = transaction.get("denomination").and_then(|v| v.as_i64()).unwrap_or(-1);
-        let valid_denominations = [1, 10, 100, 1000, 10000, 100000, 1000000, 10000000, 100000000];
-        if !valid_denominations.contains(&denomination) {
-            return (false, format!("Invalid denomination: {}", denomination));
-        }
-        if !self.validate_mining_proof(transaction) {
-            return (false, "Invalid mining proof".to_string());
+        self
+    }
+
+    pub fn with_score_weights(mut self, weights: ScoreWeights) -> Self {
+        self.score_weights = weights;
+        self
+    }
+
+    pub fn with_authorized_signers<I, S>(mut self, public_keys: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        self.authorized_signers.extend(public_keys.into_iter().map(|k| k.as_ref().to_lowercase()));
+        self
+    }
+
+    pub fn with_legacy_system_signatures(mut self, allow: bool) -> Self {
+        self.allow_legacy_system_signatures = allow;
+        self
+    }
+
+    pub fn allow_unknown_types(mut self, allow: bool) -> Self {
+        self.allow_unknown_types = allow;
+        self
+    }
+
+    pub fn allow_legacy_addresses(mut self, allow: bool) -> Self {
+        self.allow_legacy_addresses = allow;
+        self
+    }
+
+    /// Parse a `{ "<tx_type>": { ...policy } }` map from JSON
+    pub fn policies_from_json(value: &serde_json::Value) -> Result<HashMap<String, SecurityPolicy>, String> {
+        serde_json::from_value(value.clone()).map_err(|e| format!("Invalid security policies: {}", e))
+    }
+
+    pub fn policy_for(&self, tx_type: &str) -> &SecurityPolicy {
+        self.policies.get(&tx_type.to_lowercase()).unwrap_or(&self.default_policy)
+    }
+
+    pub fn validate_transaction_security(&mut self, transaction: &HashMap<String, serde_json::Value>) -> ValidationOutcome {
+        self.purge_expired();
+        let mut violations = self.structure_violations(transaction);
+        if violations.is_empty() {
+            let tx_type = transaction.get("type").and_then(|v| v.as_str()).unwrap_or("").to_lowercase();
+            match tx_type.as_str() {
+                "gtx_genesis" | "reward" => {
+                    violations.extend(self.system_violations(transaction));
+                    violations.extend(self.signature_violation(transaction));
+                    violations.extend(self.policy_violations(transaction));
+                }
+                _ => {
+                    violations.extend(self.policy_violations(transaction));
+                    violations.extend(self.signature_violation(transaction));
+                    violations.extend(self.blacklist_violation(transaction));
+                }
+            }
         }
-        (true, "Valid GTX Genesis transaction".to_string())
+        self.conclude(transaction, violations)
     }
 
-    fn validate_reward_transaction(&self, transaction: &HashMap<String, serde_json::Value>) -> (bool, String) {
-        let required_fields = ["from", "to", "amount", "block_height", "hash"];
-        for field in &required_fields {
-            if !transaction.contains_key(*field) {
-                return (false, format!("Missing reward field: {}", field));
+    /// Log each violation; for an accepted transaction take the sender's rate-limit token, count
+    /// its value toward velocity caps and log a legacy system signature. Then score it.
+    pub(crate) fn conclude(&mut self, transaction: &HashMap<String, serde_json::Value>, violations: Vec<Violation>) -> ValidationOutcome {
+        if violations.is_empty() {
+            self.take_rate_limit_token(transaction);
+            self.record_outgoing(transaction);
+            if self.has_legacy_system_signature(transaction) {
+                self.log_event(transaction, "legacy_system_signature", Severity::Warning, true, "Accepted legacy \"system\" signature");
             }
         }
-        if transaction.get("from").and_then(|v| v.as_str()) != Some("network") {
-            return (false, "Unauthorized reward creation".to_string());
+        for violation in &violations {
+            self.log_event(transaction, violation.rule(), violation.severity(), false, &violation.to_string());
+        }
+        let breakdown = self.calculate_security_score_detailed(transaction);
+        let amount = transaction.get("amount").and_then(|v| v.as_f64()).unwrap_or(0.0);
+        ValidationOutcome::new(violations, breakdown.total, RiskLevel::from_breakdown(amount, &breakdown))
+    }
+
+    #[deprecated(note = "use validate_transaction_security, which returns a ValidationOutcome")]
+    pub fn validate_transaction_security_tuple(&mut self, transaction: &HashMap<String, serde_json::Value>) -> (bool, String) {
+        self.validate_transaction_security(transaction).into_tuple()
+    }
+
+    /// Recorded security events matching `filter`, oldest first
+    pub fn query_events(&self, filter: &AuditFilter) -> Vec<SecurityEvent> {
+        self.audit_log.query_events(filter)
+    }
+
+    pub fn export_jsonl(&self, path: &std::path::Path) -> std::io::Result<usize> {
+        self.audit_log.export_jsonl(path)
+    }
+
+    /// Count an accepted transaction's value toward its sender's velocity caps
+    fn record_outgoing(&mut self, transaction: &HashMap<String, serde_json::Value>) {
+        let from_address = transaction.get("from").and_then(|v| v.as_str()).unwrap_or("");
+        if from_address.is_empty() || from_address == "network" {
+            return;
+        }
+        let amount = transaction.get("amount").and_then(|v| v.as_f64()).unwrap_or(0.0);
+        if self.is_anomalous(transaction) {
+            let message = format!("Amount {} far above 30-day average", amount);
+            self.log_event(transaction, "anomaly", Severity::Info, true, &message);
         }
-        (true, "Valid reward transaction".to_string())
+        self.velocity.record(from_address, amount, self.clock.now());
+    }
+
+    /// Whether the amount is an outlier against the sender's trailing 30-day average
+    pub fn is_anomalous(&self, transaction: &HashMap<String, serde_json::Value>) -> bool {
+        let tx_type = transaction.get("type").and_then(|v| v.as_str()).unwrap_or("").to_lowercase();
+        let from_address = transaction.get("from").and_then(|v| v.as_str()).unwrap_or("");
+        let amount = transaction.get("amount").and_then(|v| v.as_f64()).unwrap_or(0.0);
+        let multiplier = self.policy_for(&tx_type).anomaly_multiplier;
+        self.velocity.is_anomalous(from_address, amount, multiplier, self.clock.now())
+    }
+
+    fn log_event(&mut self, transaction: &HashMap<String, serde_json::Value>, rule: &str, severity: Severity, accepted: bool, message: &str) {
+        let field = |name: &str| transaction.get(name).and_then(|v| v.as_str()).unwrap_or("").to_string();
+        self.audit_log.record(SecurityEvent {
+            timestamp: self.clock.now(),
+            address: field("from"),
+            tx_hash: field("hash"),
+            rule: rule.to_string(),
+            severity,
+            accepted,
+            message: message.to_string(),
+        });
     }
 
-    fn validate_transfer_transaction(&mut self, transaction: &HashMap<String, serde_json::Value>) -> (bool, String) {
-        let required_fields = ["from", "to", "amount", "signature", "public_key", "nonce"];
-        for field in &required_fields {
-            if !transaction.contains_key(*field) {
-                return (false, format!("Missing field: {}", field));
+    /// Check a transaction against the policy configured for its type, stopping at the first failure
+    pub fn check_policy(&mut self, transaction: &HashMap<String, serde_json::Value>) -> Result<(), PolicyFailure> {
+        match self.policy_violations(transaction).into_iter().next() {
+            Some(violation) => {
+                let rule = PolicyRule::from_violation(&violation).expect("policy checks only report policy rules");
+                Err(PolicyFailure::new(rule, violation.to_string()))
             }
+            None => Ok(()),
         }
+    }
+
+    /// Every policy rule the transaction breaks
+    pub fn policy_violations(&mut self, transaction: &HashMap<String, serde_json::Value>) -> Vec<Violation> {
+        let mut violations = Vec::new();
+        violations.extend(self.tx_type_violation(transaction));
+        violations.extend(self.amount_violation(transaction));
+        violations.extend(self.fee_violation(transaction));
+        if let Some(memo) = transaction.get("memo").and_then(|v| v.as_str()) {
+            let tx_type = transaction.get("type").and_then(|v| v.as_str()).unwrap_or("");
+            violations.extend(Self::memo_violations(self.policy_for(tx_type), memo));
+        }
+        violations.extend(self.velocity_violation(transaction));
+        violations.extend(self.rate_limit_violation(transaction));
+        violations
+    }
+
+    fn tx_policy(&self, transaction: &HashMap<String, serde_json::Value>) -> &SecurityPolicy {
+        self.policy_for(transaction.get("type").and_then(|v| v.as_str()).unwrap_or(""))
+    }
+
+    pub fn tx_type_violation(&self, transaction: &HashMap<String, serde_json::Value>) -> Option<Violation> {
+        let tx_type = transaction.get("type").and_then(|v| v.as_str()).unwrap_or("").to_lowercase();
+        let allowed = &self.tx_policy(transaction).allowed_tx_types;
+        if !allowed.is_empty() && !allowed.iter().any(|t| t.eq_ignore_ascii_case(&tx_type)) {
+            return Some(Violation::TxTypeNotAllowed { tx_type });
+        }
+        None
+    }
+
+    /// Amount bounds; whitelisted senders may exceed the maximum, which is logged
+    pub fn amount_violation(&mut self, transaction: &HashMap<String, serde_json::Value>) -> Option<Violation> {
+        let policy = self.tx_policy(transaction);
+        let (min, max) = (policy.min_amount, policy.max_amount);
         let amount = transaction.get("amount").and_then(|v| v.as_f64()).unwrap_or(0.0);
-        if amount < self.min_transaction_amount {
-            return (false, format!("Amount below minimum: {}", self.min_transaction_amount));
+        if amount < min {
+            return Some(Violation::AmountTooLow { min, got: amount });
         }
-        if amount > self.max_transaction_amount {
-            return (false, format!("Amount above maximum: {}", self.max_transaction_amount));
+        if amount > max {
+            let from_address = transaction.get("from").and_then(|v| v.as_str()).unwrap_or("");
+            if !self.is_whitelisted(from_address) {
+                return Some(Violation::AmountTooHigh { max, got: amount });
+            }
+            let message = format!("Whitelisted address exceeded maximum amount: {}", amount);
+            self.log_event(transaction, "max_amount", Severity::Info, true, &message);
         }
+        None
+    }
+
+    pub fn fee_violation(&self, transaction: &HashMap<String, serde_json::Value>) -> Option<Violation> {
+        let required = self.tx_policy(transaction).required_fee;
         let fee = transaction.get("fee").and_then(|v| v.as_f64()).unwrap_or(0.0);
-        if fee < self.required_fee {
-            return (false, format!("Insufficient fee: {} (required: {})", fee, self.required_fee));
+        if fee < required {
+            return Some(Violation::FeeTooLow { required, got: fee });
+        }
+        None
+    }
+
+    /// Memo problems under `policy`; a sanitizing policy judges the memo as `sanitize_memo_with`
+    /// rewrites it with the policy's length and normalization settings
+    pub fn memo_violations(policy: &SecurityPolicy, memo: &str) -> Vec<Violation> {
+        let sanitized;
+        let memo = if policy.memo_action == MemoAction::Sanitize {
+            sanitized = sanitize_memo_with(memo, policy.max_memo_length, policy.normalize_memo);
+            sanitized.as_str()
+        } else {
+            memo
+        };
+        let mut violations = Vec::new();
+        let control_chars = memo.chars().filter(|c| c.is_control()).count();
+        if control_chars > 0 {
+            violations.push(Violation::MemoControlCharacters { count: control_chars });
         }
-        if !self.validate_signature_sm2(transaction) {
-            return (false, "Invalid SM2 signature".to_string());
+        let len = if policy.normalize_memo { memo.nfc().collect::<String>().len() } else { memo.len() };
+        if len > policy.max_memo_length {
+            violations.push(Violation::MemoTooLong { max: policy.max_memo_length, got: len });
         }
+        violations
+    }
+
+    /// Hourly and daily outgoing caps; whitelisted senders are exempt
+    pub fn velocity_violation(&self, transaction: &HashMap<String, serde_json::Value>) -> Option<Violation> {
         let from_address = transaction.get("from").and_then(|v| v.as_str()).unwrap_or("");
-        if !self.check_rate_limit(from_address) {
-            return (false, "Rate limit exceeded".to_string());
+        if self.is_whitelisted(from_address) {
+            return None;
         }
-        if self.is_blacklisted(from_address) {
-            return (false, "Address is blacklisted".to_string());
+        let policy = self.tx_policy(transaction);
+        let caps = [(HOUR_SECS, policy.max_hourly_outgoing), (DAY_SECS, policy.max_daily_outgoing)];
+        let amount = transaction.get("amount").and_then(|v| v.as_f64()).unwrap_or(0.0);
+        self.velocity.check_caps(from_address, amount, &caps, self.clock.now()).err().map(|usage| {
+            Violation::VelocityCapExceeded { window_secs: usage.window_secs, used: usage.used, cap: usage.cap, requested: amount }
+        })
+    }
+
+    /// Whether the sender has a rate-limit token left; `conclude` takes it once the transaction is
+    /// accepted. Whitelisted senders are exempt.
+    pub fn rate_limit_violation(&self, transaction: &HashMap<String, serde_json::Value>) -> Option<Violation> {
+        let from_address = transaction.get("from").and_then(|v| v.as_str()).unwrap_or("");
+        let limit = self.sender_rate_limit(transaction)?;
+        let status = self.rate_limiter.status(from_address, &limit);
+        (status.remaining == 0).then_some(Violation::RateLimited { retry_after: status.reset_in })
+    }
+
+    fn take_rate_limit_token(&mut self, transaction: &HashMap<String, serde_json::Value>) {
+        if let Some(limit) = self.sender_rate_limit(transaction) {
+            let from_address = transaction.get("from").and_then(|v| v.as_str()).unwrap_or("");
+            self.rate_limiter.check(from_address, &limit);
         }
-        (true, "Valid transfer transaction".to_string())
     }
 
-    fn validate_signature_sm2(&self, transaction: &HashMap<String, serde_json::Value>) -> bool {
+    /// The limit the transaction's sender is held to, None when rate limiting is off or the sender is whitelisted
+    fn sender_rate_limit(&self, transaction: &HashMap<String, serde_json::Value>) -> Option<RateLimit> {
+        let from_address = transaction.get("from").and_then(|v| v.as_str()).unwrap_or("");
+        let policy = self.tx_policy(transaction);
+        if policy.rate_limit_count == 0 || self.is_whitelisted(from_address) {
+            return None;
+        }
+        Some(policy.rate_limit())
+    }
+
+    /// Unknown types, or fields the transaction's type requires but lacks
+    pub fn structure_violations(&self, transaction: &HashMap<String, serde_json::Value>) -> Vec<Violation> {
+        let tx_type = transaction.get("type").and_then(|v| v.as_str()).unwrap_or("").to_lowercase();
+        let required: &[&'static str] = match tx_type.as_str() {
+            "gtx_genesis" => &["bill_serial", "denomination", "mining_difficulty", "hash", "nonce"],
+            "reward" => &["from", "to", "amount", "block_height", "hash"],
+            "transfer" => &["from", "to", "amount", "signature", "public_key", "nonce"],
+            _ if self.allow_unknown_types || self.policies.contains_key(&tx_type) => {
+                &["from", "to", "amount", "signature", "public_key"]
+            }
+            _ => return vec![Violation::UnknownType { tx_type }],
+        };
+        required
+            .iter()
+            .filter(|field| !transaction.contains_key(**field))
+            .map(|field| Violation::MissingField(field))
+            .collect()
+    }
+
+    /// Type-specific checks for reward and gtx_genesis transactions, other than the signature
+    pub fn system_violations(&self, transaction: &HashMap<String, serde_json::Value>) -> Vec<Violation> {
+        let mut violations = Vec::new();
+        match transaction.get("type").and_then(|v| v.as_str()).unwrap_or("").to_lowercase().as_str() {
+            "gtx_genesis" => {
+                let denomination = transaction.get("denomination").and_then(|v| v.as_i64()).unwrap_or(-1);
+                let valid_denominations = [1, 10, 100, 1000, 10000, 100000, 1000000, 10000000, 100000000];
+                if !valid_denominations.contains(&denomination) {
+                    violations.push(Violation::InvalidDenomination { got: denomination });
+                }
+                if !self.validate_mining_proof(transaction) {
+                    violations.push(Violation::InvalidMiningProof);
+                }
+            }
+            "reward" if transaction.get("from").and_then(|v| v.as_str()) != Some("network") => {
+                violations.push(Violation::UnauthorizedReward);
+            }
+            _ => {}
+        }
+        violations
+    }
+
+    /// Authorized-signer check for system transactions, SM2 check for every other type
+    pub fn signature_violation(&self, transaction: &HashMap<String, serde_json::Value>) -> Option<Violation> {
+        match transaction.get("type").and_then(|v| v.as_str()).unwrap_or("").to_lowercase().as_str() {
+            "gtx_genesis" | "reward" => self.system_signature_violation(transaction),
+            _ if !self.validate_signature_sm2(transaction) => Some(Violation::BadSignature),
+            _ => None,
+        }
+    }
+
+    /// Whether the transaction is a reward or gtx_genesis accepted on a legacy "system" signature
+    fn has_legacy_system_signature(&self, transaction: &HashMap<String, serde_json::Value>) -> bool {
+        let tx_type = transaction.get("type").and_then(|v| v.as_str()).unwrap_or("").to_lowercase();
+        let signature = transaction.get("signature").and_then(|v| v.as_str());
+        self.allow_legacy_system_signatures && signature == Some("system") && matches!(tx_type.as_str(), "gtx_genesis" | "reward")
+    }
+
+    /// System transactions must be signed over the canonical digest by an authorized key
+    fn system_signature_violation(&self, transaction: &HashMap<String, serde_json::Value>) -> Option<Violation> {
         let signature = transaction.get("signature").and_then(|v| v.as_str()).unwrap_or("");
         let public_key = transaction.get("public_key").and_then(|v| v.as_str()).unwrap_or("");
-        let tx_type = transaction.get("type").and_then(|v| v.as_str()).unwrap_or("").to_lowercase();
-        if ["gtx_genesis", "reward"].contains(&tx_type.as_str()) {
-            return true;
+        if signature == "system" {
+            if self.allow_legacy_system_signatures {
+                return None;
+            }
+            return Some(Violation::UnauthorizedSigner { public_key: public_key.to_string() });
         }
-        if ["system", "unsigned", "test"].contains(&signature) {
-            return true;
+        if !self.authorized_signers.contains(&public_key.to_lowercase()) {
+            return Some(Violation::UnauthorizedSigner { public_key: public_key.to_string() });
         }
-        if signature.len() != 128 {
-            return false;
+        let digest = TransactionManager::calculate_transaction_hash(transaction);
+        if !Crypto::new().verify_signature(&digest, signature, public_key) {
+            return Some(Violation::BadSignature);
         }
-        if !signature.chars().all(|c| c.is_ascii_hexdigit()) {
-            return false;
+        None
+    }
+
+    pub fn blacklist_violation(&self, transaction: &HashMap<String, serde_json::Value>) -> Option<Violation> {
+        let from_address = transaction.get("from").and_then(|v| v.as_str()).unwrap_or("");
+        if self.is_blacklisted(from_address) {
+            return Some(Violation::Blacklisted { address: from_address.to_string() });
         }
-        if !public_key.starts_with("04") {
+        None
+    }
+
+    /// The sender's key must hash to `from` and sign the canonical digest
+    fn validate_signature_sm2(&self, transaction: &HashMap<String, serde_json::Value>) -> bool {
+        let signature = transaction.get("signature").and_then(|v| v.as_str()).unwrap_or("");
+        let public_key = transaction.get("public_key").and_then(|v| v.as_str()).unwrap_or("");
+        let from_address = transaction.get("from").and_then(|v| v.as_str()).unwrap_or("");
+        if LunaWallet::validate_address_with(from_address, self.allow_legacy_addresses).is_err() || !derives_address(public_key, from_address) {
             return false;
         }
-        // SM2検証は外部KeyManagerが必要。ここでは形式のみチェック。
-        true
+        let digest = TransactionManager::calculate_transaction_hash(transaction);
+        Crypto::new().verify_signature(&digest, signature, public_key)
     }
 
     fn validate_mining_proof(&self, transaction: &HashMap<String, serde_json::Value>) -> bool {
@@ -128,56 +643,117 @@ impl TransactionSecurity {
     }
 
     pub fn check_rate_limit(&mut self, address: &str) -> bool {
-        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs();
-        let entry = self.rate_limits.entry(address.to_lowercase()).or_insert_with(Vec::new);
-        entry.retain(|&t| now - t < 60);
-        if entry.len() >= 10 {
-            return false;
-        }
-        entry.push(now);
-        true
+        let limit = self.default_policy.rate_limit();
+        self.rate_limiter.check(address, &limit)
+    }
+
+    /// Remaining allowance for an address under the default policy
+    pub fn rate_limit_status(&self, address: &str) -> RateStatus {
+        self.rate_limiter.status(address, &self.default_policy.rate_limit())
     }
 
     pub fn is_blacklisted(&self, address: &str) -> bool {
-        self.blacklisted_addresses.contains(&address.to_lowercase())
+        let now = self.clock.now();
+        self.blacklisted_addresses
+            .get(&address.to_lowercase())
+            .is_some_and(|e| !e.is_expired(now))
     }
 
     pub fn blacklist_address(&mut self, address: &str) {
-        self.blacklisted_addresses.insert(address.to_lowercase());
+        self.blacklist_address_with_reason(address, "", None);
     }
 
-    pub fn calculate_security_score(&self, transaction: &HashMap<String, serde_json::Value>) -> u32 {
-        let mut score = 0;
-        let signature = transaction.get("signature").and_then(|v| v.as_str()).unwrap_or("");
-        if signature.len() == 128 {
-            score += 60;
-        } else if signature.len() == 64 {
-            score += 40;
+    /// Blacklist an address, optionally only for `expires_in_secs` seconds
+    pub fn blacklist_address_with_reason(&mut self, address: &str, reason: &str, expires_in_secs: Option<u64>) {
+        let now = self.clock.now();
+        let entry = AddressListEntry {
+            address: address.to_lowercase(),
+            reason: reason.to_string(),
+            added_at: now,
+            expires_at: expires_in_secs.map(|secs| now + secs),
+        };
+        if let Some(db) = &self.database {
+            db.save_blacklist_entry(&entry.address, &entry.reason, entry.added_at, entry.expires_at);
         }
-        let public_key = transaction.get("public_key").and_then(|v| v.as_str()).unwrap_or("");
-        if !public_key.is_empty() && public_key.starts_with("04") {
-            score += 30;
+        self.blacklisted_addresses.insert(entry.address.clone(), entry);
+    }
+
+    pub fn remove_from_blacklist(&mut self, address: &str) -> bool {
+        let address = address.to_lowercase();
+        if let Some(db) = &self.database {
+            db.delete_blacklist_entry(&address);
         }
-        let timestamp = transaction.get("timestamp").and_then(|v| v.as_f64()).unwrap_or(0.0);
-        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs() as f64;
-        if now - timestamp < 600.0 {
-            score += 20;
+        self.blacklisted_addresses.remove(&address).is_some()
+    }
+
+    /// Active (unexpired) blacklist entries sorted by address
+    pub fn list_blacklist(&self) -> Vec<AddressListEntry> {
+        let now = self.clock.now();
+        let mut entries: Vec<_> = self.blacklisted_addresses.values().filter(|e| !e.is_expired(now)).cloned().collect();
+        entries.sort_by(|a, b| a.address.cmp(&b.address));
+        entries
+    }
+
+    /// Drop expired blacklist entries from memory and storage, returning how many were removed
+    pub fn purge_expired(&mut self) -> usize {
+        let now = self.clock.now();
+        let expired: Vec<String> = self.blacklisted_addresses.values().filter(|e| e.is_expired(now)).map(|e| e.address.clone()).collect();
+        for address in &expired {
+            self.remove_from_blacklist(address);
         }
-        if transaction.contains_key("nonce") {
-            score += 10;
+        expired.len()
+    }
+
+    pub fn is_whitelisted(&self, address: &str) -> bool {
+        self.whitelisted_addresses.contains_key(&address.to_lowercase())
+    }
+
+    pub fn whitelist_address(&mut self, address: &str, reason: &str) {
+        let entry = AddressListEntry {
+            address: address.to_lowercase(),
+            reason: reason.to_string(),
+            added_at: self.clock.now(),
+            expires_at: None,
+        };
+        if let Some(db) = &self.database {
+            db.save_whitelist_entry(&entry.address, &entry.reason, entry.added_at);
         }
-        if transaction.contains_key("security_hash") {
-            score += 10;
+        self.whitelisted_addresses.insert(entry.address.clone(), entry);
+    }
+
+    pub fn remove_from_whitelist(&mut self, address: &str) -> bool {
+        let address = address.to_lowercase();
+        if let Some(db) = &self.database {
+            db.delete_whitelist_entry(&address);
         }
-        score.min(100)
+        self.whitelisted_addresses.remove(&address).is_some()
+    }
+
+    pub fn list_whitelist(&self) -> Vec<AddressListEntry> {
+        let mut entries: Vec<_> = self.whitelisted_addresses.values().cloned().collect();
+        entries.sort_by(|a, b| a.address.cmp(&b.address));
+        entries
+    }
+
+    pub fn calculate_security_score(&self, transaction: &HashMap<String, serde_json::Value>) -> u32 {
+        self.calculate_security_score_detailed(transaction).total
+    }
+
+    /// Security score with the points earned, missed and deducted per factor
+    pub fn calculate_security_score_detailed(&self, transaction: &HashMap<String, serde_json::Value>) -> ScoreBreakdown {
+        ScoreBreakdown::calculate(&self.score_weights, transaction, self.clock.now(), self.is_anomalous(transaction))
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::transactions::signing::tests::{sign_as, test_address};
+    use crate::utils::clock::ManualClock;
     use serde_json::json;
     use std::collections::HashMap;
+    use std::time::{SystemTime, UNIX_EPOCH};
+    use tempfile::tempdir;
 
     fn make_tx(tx_type: &str) -> HashMap<String, serde_json::Value> {
         let mut tx = HashMap::new();
@@ -185,6 +761,21 @@ mod tests {
         tx
     }
 
+    const NETWORK_KEY: &str = "0f0e0d0c0b0a09080706050403020100000102030405060708090a0b0c0d0e0f";
+
+    fn network_security() -> TransactionSecurity {
+        let public_key = Crypto::new().derive_public_key(NETWORK_KEY);
+        TransactionSecurity::new(false).with_authorized_signers([public_key])
+    }
+
+    /// Sign over the canonical digest without replacing the (proof-of-work) hash
+    fn sign_system(tx: &mut HashMap<String, serde_json::Value>, private_key: &str) {
+        let crypto = Crypto::new();
+        let digest = TransactionManager::calculate_transaction_hash(tx);
+        tx.insert("signature".to_string(), json!(crypto.sign_data(&digest, private_key)));
+        tx.insert("public_key".to_string(), json!(crypto.derive_public_key(private_key)));
+    }
+
     #[test]
     fn test_genesis_validation() {
         let mut tx = make_tx("gtx_genesis");
@@ -193,9 +784,16 @@ mod tests {
         tx.insert("mining_difficulty".to_string(), json!(2));
         tx.insert("hash".to_string(), json!("00abcdef"));
         tx.insert("nonce".to_string(), json!(123));
-        let mut sec = TransactionSecurity::new(false);
-        let (ok, msg) = sec.validate_transaction_security(&tx);
+        let mut sec = network_security();
+        let outcome = sec.validate_transaction_security(&tx);
+        assert!(outcome.has(|v| matches!(v, Violation::UnauthorizedSigner { .. })));
+        sign_system(&mut tx, NETWORK_KEY);
+        let (ok, msg) = sec.validate_transaction_security(&tx).into_tuple();
         assert!(ok, "{}", msg);
+        tx.insert("hash".to_string(), json!("ffabcdef"));
+        sign_system(&mut tx, NETWORK_KEY);
+        let outcome = sec.validate_transaction_security(&tx);
+        assert_eq!(outcome.violations, vec![Violation::InvalidMiningProof]);
     }
 
     #[test]
@@ -206,26 +804,153 @@ mod tests {
         tx.insert("amount".to_string(), json!(1.0));
         tx.insert("block_height".to_string(), json!(1));
         tx.insert("hash".to_string(), json!("abc"));
-        let mut sec = TransactionSecurity::new(false);
-        let (ok, msg) = sec.validate_transaction_security(&tx);
+        sign_system(&mut tx, NETWORK_KEY);
+        let mut sec = network_security();
+        let (ok, msg) = sec.validate_transaction_security(&tx).into_tuple();
         assert!(ok, "{}", msg);
+
+        let mut rogue = tx.clone();
+        sign_system(&mut rogue, &"11".repeat(32));
+        let outcome = sec.validate_transaction_security(&rogue);
+        assert!(outcome.has(|v| matches!(v, Violation::UnauthorizedSigner { .. })));
+
+        // The authorized key with a signature it did not make
+        let mut garbage = tx.clone();
+        garbage.insert("signature".to_string(), json!("ab".repeat(64)));
+        assert_eq!(sec.validate_transaction_security(&garbage).violations, vec![Violation::BadSignature]);
+        let mut borrowed = tx.clone();
+        let other = Crypto::new().sign_data(&TransactionManager::calculate_transaction_hash(&tx), &"11".repeat(32));
+        borrowed.insert("signature".to_string(), json!(other));
+        assert_eq!(sec.validate_transaction_security(&borrowed).violations, vec![Violation::BadSignature]);
+    }
+
+    #[test]
+    fn test_forged_system_reward() {
+        let mgr = TransactionManager::new();
+        let forged = mgr.create_reward_transaction("attacker", 1_000.0, 1);
+        let mut strict = network_security();
+        let outcome = strict.validate_transaction_security(&forged);
+        assert_eq!(outcome.violations, vec![Violation::UnauthorizedSigner { public_key: "system".to_string() }]);
+
+        let mut migrating = network_security().with_legacy_system_signatures(true);
+        assert!(migrating.validate_transaction_security(&forged).valid);
+        let warnings = migrating.query_events(&AuditFilter { rule: Some("legacy_system_signature".to_string()), ..Default::default() });
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(warnings[0].severity, Severity::Warning);
+
+        let mut signed = mgr.create_reward_transaction("miner", 50.0, 1);
+        TransactionManager::sign_transaction(&mut signed, NETWORK_KEY);
+        assert!(strict.validate_transaction_security(&signed).valid);
     }
 
     #[test]
     fn test_transfer_validation() {
         let mut tx = make_tx("transfer");
-        tx.insert("from".to_string(), json!("user1"));
-        tx.insert("to".to_string(), json!("user2"));
+        tx.insert("from".to_string(), json!(test_address("user1")));
+        tx.insert("to".to_string(), json!(test_address("user2")));
         tx.insert("amount".to_string(), json!(1.0));
         tx.insert("fee".to_string(), json!(0.00001));
-        // signature: 128 hex chars, starts with '04'
-        let sig = format!("04{:0<126}", "a");
-        tx.insert("signature".to_string(), json!(sig));
-        tx.insert("public_key".to_string(), json!("04abcdef"));
         tx.insert("nonce".to_string(), json!(123));
+        sign_as(&mut tx, "user1");
         let mut sec = TransactionSecurity::new(false);
-        let (ok, msg) = sec.validate_transaction_security(&tx);
+        let (ok, msg) = sec.validate_transaction_security(&tx).into_tuple();
         assert!(ok, "{}", msg);
+
+        // Well-formed, but not the sender's signature over this transaction
+        let mut tampered = tx.clone();
+        tampered.insert("amount".to_string(), json!(100.0));
+        assert_eq!(sec.signature_violation(&tampered), Some(Violation::BadSignature));
+        let mut impostor = tx.clone();
+        sign_as(&mut impostor, "user2");
+        assert_eq!(sec.signature_violation(&impostor), Some(Violation::BadSignature));
+        let mut placeholder = tx.clone();
+        placeholder.insert("signature".to_string(), json!("unsigned"));
+        assert_eq!(sec.signature_violation(&placeholder), Some(Violation::BadSignature));
+        placeholder.insert("signature".to_string(), json!(format!("04{:0<126}", "a")));
+        assert_eq!(sec.signature_violation(&placeholder), Some(Violation::BadSignature));
+
+        let mut legacy = tx.clone();
+        legacy.insert("from".to_string(), json!(test_address("user1")[..20]));
+        sign_as(&mut legacy, "user1");
+        assert_eq!(sec.signature_violation(&legacy), None);
+        let strict = TransactionSecurity::new(false).allow_legacy_addresses(false);
+        assert_eq!(strict.signature_violation(&legacy), Some(Violation::BadSignature));
+    }
+
+    #[test]
+    fn test_outcome_reports_all_violations() {
+        let mut sec = TransactionSecurity::new(false);
+        sec.blacklist_address(&test_address("mallory"));
+        let mut tx = make_transfer("mallory", 0.0);
+        tx.insert("signature".to_string(), json!("short"));
+        let outcome = sec.validate_transaction_security(&tx);
+        assert!(!outcome.valid);
+        assert_eq!(outcome.violations, vec![
+            Violation::FeeTooLow { required: 0.00001, got: 0.0 },
+            Violation::BadSignature,
+            Violation::Blacklisted { address: test_address("mallory") },
+        ]);
+
+        let mut genesis = make_tx("gtx_genesis");
+        genesis.remove("nonce");
+        let outcome = sec.validate_transaction_security(&genesis);
+        assert!(outcome.has(|v| *v == Violation::MissingField("bill_serial")));
+        assert!(outcome.has(|v| *v == Violation::MissingField("denomination")));
+        assert!(outcome.has(|v| *v == Violation::MissingField("nonce")));
+
+        genesis.insert("bill_serial".to_string(), json!("A"));
+        genesis.insert("denomination".to_string(), json!(7));
+        genesis.insert("mining_difficulty".to_string(), json!(0));
+        genesis.insert("hash".to_string(), json!("abc"));
+        genesis.insert("nonce".to_string(), json!(1));
+        sign_system(&mut genesis, NETWORK_KEY);
+        let mut sec = network_security();
+        let outcome = sec.validate_transaction_security(&genesis);
+        assert_eq!(outcome.violations, vec![Violation::InvalidDenomination { got: 7 }]);
+    }
+
+    #[test]
+    fn test_rate_limited_violation_has_retry_after() {
+        let policy = SecurityPolicy::from_toml("rate_limit_count = 1\nrate_limit_window_secs = 60").unwrap();
+        let mut policies = HashMap::new();
+        policies.insert("transfer".to_string(), policy);
+        let mut sec = TransactionSecurity::new(false)
+            .with_clock(Arc::new(ManualClock::new(1_000)))
+            .with_policy(policies);
+        assert!(sec.validate_transaction_security(&make_transfer("alice", 0.001)).valid);
+        let outcome = sec.validate_transaction_security(&make_transfer("alice", 0.001));
+        assert_eq!(outcome.violations, vec![Violation::RateLimited { retry_after: 60 }]);
+    }
+
+    #[test]
+    fn test_memo_validation() {
+        let mut sec = TransactionSecurity::new(false);
+        let with_memo = |memo: String| amended(make_transfer("alice", 0.001), "alice", "memo", json!(memo));
+        let outcome = sec.validate_transaction_security(&with_memo("x".repeat(2 * 1024 * 1024)));
+        assert_eq!(outcome.violations, vec![Violation::MemoTooLong { max: 512, got: 2 * 1024 * 1024 }]);
+
+        let outcome = sec.validate_transaction_security(&with_memo("pay\0ment\u{1b}[31m".to_string()));
+        assert_eq!(outcome.violations, vec![Violation::MemoControlCharacters { count: 2 }]);
+        assert_eq!(sanitize_memo("pay\0ment\u{1b}[31m"), "payment[31m");
+
+        assert!(sec.validate_transaction_security(&with_memo("家賃 für März ☕".to_string())).valid);
+        assert_eq!(sanitize_memo("家賃 für März ☕"), "家賃 für März ☕");
+
+        let lenient = SecurityPolicy { memo_action: MemoAction::Sanitize, ..Default::default() };
+        assert!(TransactionSecurity::memo_violations(&lenient, "bad\0memo").is_empty());
+        let short = SecurityPolicy { max_memo_length: 4, normalize_memo: false, ..lenient };
+        assert!(TransactionSecurity::memo_violations(&short, "e\u{301}\0e\u{301}e\u{301}").is_empty());
+        assert!(!TransactionSecurity::memo_violations(&SecurityPolicy { memo_action: MemoAction::Reject, ..short }, "e\u{301}\0e\u{301}").is_empty());
+    }
+
+    #[test]
+    fn test_sanitize_memo_truncates_on_char_boundary() {
+        let memo = "é".repeat(300);
+        let sanitized = sanitize_memo(&memo);
+        assert_eq!(sanitized.len(), 512);
+        assert_eq!(sanitize_memo_with("aé", 2, false), "a");
+        // Decomposed e + combining acute composes to a single char under NFC
+        assert_eq!(sanitize_memo("e\u{301}"), "\u{e9}");
     }
 
     #[test]
@@ -235,6 +960,138 @@ mod tests {
         assert!(sec.is_blacklisted("badguy"));
     }
 
+    #[test]
+    fn test_blacklist_persists_across_restart() {
+        let dir = tempdir().unwrap();
+        let db_path = dir.path().join("wallets.db");
+        let mut sec = TransactionSecurity::new(false).with_database(WalletDatabase::new(Some(db_path.clone())));
+        sec.blacklist_address_with_reason("LUN_Fraud", "chargeback fraud", None);
+        sec.blacklist_address_with_reason("temp", "cooldown", Some(3600));
+        let restarted = TransactionSecurity::new(false).with_database(WalletDatabase::new(Some(db_path.clone())));
+        assert!(restarted.is_blacklisted("lun_fraud"));
+        let entries = restarted.list_blacklist();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].reason, "chargeback fraud");
+        let mut restarted = restarted;
+        assert!(restarted.remove_from_blacklist("LUN_FRAUD"));
+        let again = TransactionSecurity::new(false).with_database(WalletDatabase::new(Some(db_path)));
+        assert!(!again.is_blacklisted("lun_fraud"));
+    }
+
+    #[test]
+    fn test_blacklist_expiry() {
+        let dir = tempdir().unwrap();
+        let db_path = dir.path().join("wallets.db");
+        let clock = Arc::new(ManualClock::new(1_000));
+        let mut sec = TransactionSecurity::new(false)
+            .with_clock(clock.clone())
+            .with_database(WalletDatabase::new(Some(db_path.clone())));
+        sec.blacklist_address_with_reason("badguy", "spam", Some(60));
+        assert!(sec.is_blacklisted("badguy"));
+        clock.advance(60);
+        assert!(!sec.is_blacklisted("badguy"));
+        assert_eq!(sec.purge_expired(), 1);
+        let restarted = TransactionSecurity::new(false).with_database(WalletDatabase::new(Some(db_path)));
+        assert!(restarted.list_blacklist().is_empty());
+    }
+
+    #[test]
+    fn test_whitelist_overrides_rate_limit() {
+        let dir = tempdir().unwrap();
+        let db_path = dir.path().join("wallets.db");
+        let mut sec = TransactionSecurity::new(false).with_database(WalletDatabase::new(Some(db_path.clone())));
+        let exchange = test_address("exchange");
+        sec.whitelist_address(&exchange, "hot wallet");
+        let mut sec = TransactionSecurity::new(false).with_database(WalletDatabase::new(Some(db_path)));
+        assert!(sec.is_whitelisted(&exchange.to_uppercase()));
+        let mut tx = make_transfer("exchange", 0.001);
+        tx.insert("amount".to_string(), json!(500_000_000.0));
+        for _ in 0..20 {
+            assert!(sec.check_policy(&tx).is_ok());
+        }
+        assert!(sec.remove_from_whitelist(&exchange));
+        assert_eq!(sec.check_policy(&tx).unwrap_err().rule, PolicyRule::MaxAmount);
+    }
+
+    #[test]
+    fn test_audit_log_records_violations() {
+        let mut sec = TransactionSecurity::new(false);
+        sec.blacklist_address(&test_address("mallory"));
+        assert!(!sec.validate_transaction_security(&make_transfer("mallory", 0.001)).valid);
+        assert!(!sec.validate_transaction_security(&make_transfer("alice", 0.0)).valid);
+        let big = amended(make_transfer("alice", 0.001), "alice", "amount", json!(500_000_000.0));
+        assert!(!sec.validate_transaction_security(&big).valid);
+        assert!(sec.validate_transaction_security(&make_transfer("bob", 0.001)).valid);
+
+        let critical = sec.query_events(&AuditFilter { min_severity: Some(Severity::Critical), ..Default::default() });
+        assert_eq!(critical.len(), 1);
+        assert_eq!(critical[0].rule, "blacklisted");
+        assert_eq!(critical[0].address, test_address("mallory"));
+        let alice = sec.query_events(&AuditFilter { address: Some(test_address("alice")), ..Default::default() });
+        let rules: Vec<_> = alice.iter().map(|e| e.rule.as_str()).collect();
+        assert_eq!(rules, vec!["required_fee", "max_amount"]);
+        let warnings = sec.query_events(&AuditFilter { min_severity: Some(Severity::Warning), ..Default::default() });
+        assert_eq!(warnings.len(), 2);
+        assert!(sec.query_events(&AuditFilter { address: Some(test_address("bob")), ..Default::default() }).is_empty());
+    }
+
+    #[test]
+    fn test_velocity_cap_rolling_window() {
+        let clock = Arc::new(ManualClock::new(10_000));
+        let mut policies = HashMap::new();
+        policies.insert("transfer".to_string(), SecurityPolicy { max_hourly_outgoing: 100.0, max_daily_outgoing: 150.0, ..Default::default() });
+        let mut sec = TransactionSecurity::new(false).with_clock(clock.clone()).with_policy(policies);
+        let send = |sec: &mut TransactionSecurity, amount: f64| {
+            let tx = amended(make_transfer("alice", 0.001), "alice", "amount", json!(amount));
+            sec.validate_transaction_security(&tx).into_tuple()
+        };
+        assert!(send(&mut sec, 60.0).0);
+        clock.advance(10);
+        assert!(send(&mut sec, 40.0).0);
+        clock.advance(10);
+        let (ok, msg) = send(&mut sec, 0.5);
+        assert!(!ok);
+        assert!(msg.contains("100 used of 100"), "{}", msg);
+        clock.set(10_000 + HOUR_SECS - 1);
+        assert!(!send(&mut sec, 0.5).0);
+        clock.set(10_000 + HOUR_SECS);
+        assert!(send(&mut sec, 50.0).0);
+        clock.advance(HOUR_SECS);
+        let (ok, msg) = send(&mut sec, 0.5);
+        assert!(!ok);
+        assert!(msg.starts_with("Daily"), "{}", msg);
+        clock.set(10_000 + DAY_SECS);
+        assert!(send(&mut sec, 50.0).0);
+    }
+
+    #[test]
+    fn test_anomaly_flag() {
+        let mut sec = TransactionSecurity::new(false).with_clock(Arc::new(ManualClock::new(10_000)));
+        for _ in 0..3 {
+            assert!(sec.validate_transaction_security(&make_transfer("carol", 0.001)).valid);
+        }
+        let normal = make_transfer("carol", 0.001);
+        let huge = amended(make_transfer("carol", 0.001), "carol", "amount", json!(50.0));
+        assert!(!sec.is_anomalous(&normal));
+        assert!(sec.is_anomalous(&huge));
+        assert_eq!(sec.calculate_security_score(&normal) - sec.calculate_security_score(&huge), 30);
+        assert!(sec.validate_transaction_security(&huge).valid);
+        let flagged = sec.query_events(&AuditFilter { rule: Some("anomaly".to_string()), ..Default::default() });
+        assert_eq!(flagged.len(), 1);
+        assert!(flagged[0].accepted);
+    }
+
+    #[test]
+    fn test_zero_rate_limit_count_is_unlimited() {
+        let unlimited = SecurityPolicy { rate_limit_count: 0, ..Default::default() };
+        let mut policies = HashMap::new();
+        policies.insert("default".to_string(), unlimited);
+        let mut sec = TransactionSecurity::new(false).with_policy(policies);
+        for _ in 0..50 {
+            assert!(sec.check_rate_limit("user1"));
+        }
+    }
+
     #[test]
     fn test_rate_limit() {
         let mut sec = TransactionSecurity::new(false);
@@ -243,6 +1100,96 @@ mod tests {
             assert!(sec.check_rate_limit(addr));
         }
         assert!(!sec.check_rate_limit(addr));
+        let status = sec.rate_limit_status(addr);
+        assert_eq!(status.remaining, 0);
+        assert!(status.reset_in > 0 && status.reset_in <= 6);
+    }
+
+    /// A transfer signed by the test account `from`
+    fn make_transfer(from: &str, fee: f64) -> HashMap<String, serde_json::Value> {
+        let mut tx = make_tx("transfer");
+        tx.insert("from".to_string(), json!(test_address(from)));
+        tx.insert("to".to_string(), json!(test_address("user2")));
+        tx.insert("amount".to_string(), json!(1.0));
+        tx.insert("fee".to_string(), json!(fee));
+        tx.insert("nonce".to_string(), json!(1));
+        sign_as(&mut tx, from);
+        tx
+    }
+
+    /// `tx` with `field` replaced and signed again by `from`
+    fn amended(mut tx: HashMap<String, serde_json::Value>, from: &str, field: &str, value: serde_json::Value) -> HashMap<String, serde_json::Value> {
+        tx.insert(field.to_string(), value);
+        sign_as(&mut tx, from);
+        tx
+    }
+
+    #[test]
+    fn test_per_type_fee_policies() {
+        let policies = TransactionSecurity::policies_from_json(&json!({
+            "transfer": {"required_fee": 0.01},
+            "data": {"required_fee": 0.0001, "max_memo_length": 16}
+        })).unwrap();
+        let mut sec = TransactionSecurity::new(false).with_policy(policies);
+        let transfer = make_transfer("user1", 0.001);
+        let failure = sec.check_policy(&transfer).unwrap_err();
+        assert_eq!(failure.rule, PolicyRule::RequiredFee);
+        let (ok, _) = sec.validate_transaction_security(&transfer).into_tuple();
+        assert!(!ok);
+        let data = amended(make_transfer("user1", 0.001), "user1", "type", json!("data"));
+        let (ok, msg) = sec.validate_transaction_security(&data).into_tuple();
+        assert!(ok, "{}", msg);
+        let data = amended(data, "user1", "memo", json!("this memo is far too long"));
+        assert_eq!(sec.check_policy(&data).unwrap_err().rule, PolicyRule::MaxMemoLength);
+    }
+
+    #[test]
+    fn test_custom_rate_limit_policy() {
+        let policy = SecurityPolicy::from_toml("rate_limit_count = 2\nrate_limit_window_secs = 60").unwrap();
+        let mut policies = HashMap::new();
+        policies.insert("transfer".to_string(), policy);
+        let mut sec = TransactionSecurity::new(false).with_policy(policies);
+        let tx = make_transfer("spammer", 0.001);
+        // Checking takes no token; accepting does
+        for _ in 0..3 {
+            assert!(sec.check_policy(&tx).is_ok());
+        }
+        assert!(sec.validate_transaction_security(&tx).valid);
+        assert!(sec.validate_transaction_security(&tx).valid);
+        assert_eq!(sec.check_policy(&tx).unwrap_err().rule, PolicyRule::RateLimit);
+    }
+
+    #[test]
+    fn test_unknown_type_fallback() {
+        let tx = amended(make_transfer("user1", 0.001), "user1", "type", json!("stake"));
+        let mut strict = TransactionSecurity::new(false);
+        assert!(!strict.validate_transaction_security(&tx).valid);
+        let mut lenient = TransactionSecurity::new(false).allow_unknown_types(true);
+        let (ok, msg) = lenient.validate_transaction_security(&tx).into_tuple();
+        assert!(ok, "{}", msg);
+        let mut restricted_default = HashMap::new();
+        restricted_default.insert("default".to_string(), SecurityPolicy {
+            allowed_tx_types: vec!["transfer".to_string()],
+            ..Default::default()
+        });
+        let mut restricted = TransactionSecurity::new(false).allow_unknown_types(true).with_policy(restricted_default);
+        assert_eq!(restricted.check_policy(&tx).unwrap_err().rule, PolicyRule::TxTypeNotAllowed);
+    }
+
+    #[test]
+    fn test_unknown_type_needs_signature() {
+        let mut sec = TransactionSecurity::new(false).allow_unknown_types(true);
+        let mut unsigned = make_transfer("user1", 0.001);
+        unsigned.insert("type".to_string(), json!("stake"));
+        unsigned.remove("signature");
+        unsigned.remove("public_key");
+        assert!(sec.structure_violations(&unsigned).contains(&Violation::MissingField("signature")));
+        assert!(!sec.validate_transaction_security(&unsigned).valid);
+        let mut forged = make_transfer("user1", 0.001);
+        forged.insert("type".to_string(), json!("stake"));
+        forged.insert("signature".to_string(), json!("not-a-signature"));
+        assert_eq!(sec.signature_violation(&forged), Some(Violation::BadSignature));
+        assert!(!sec.validate_transaction_security(&forged).valid);
     }
 
     #[test]
@@ -0,0 +1,44 @@
+use std::collections::{BTreeMap, HashMap};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use crate::core::crypto::Crypto;
+
+/// Hash over the canonical (key-sorted) transaction, excluding the hash and signature fields
+pub fn transaction_hash(tx: &HashMap<String, Value>) -> String {
+    let canonical: BTreeMap<&String, &Value> = tx
+        .iter()
+        .filter(|(k, _)| !matches!(k.as_str(), "hash" | "signature" | "public_key"))
+        .collect();
+    let json = serde_json::to_string(&canonical).unwrap();
+    let mut hasher = Sha256::new();
+    hasher.update(json.as_bytes());
+    format!("{:x}", hasher.finalize())
+}
+
+/// Sign the canonical hash of `tx` with `private_key` and fill its `signature`, `public_key` and `hash`
+pub fn sign_transaction(tx: &mut HashMap<String, Value>, private_key: &str) {
+    let crypto = Crypto::new();
+    let digest = transaction_hash(tx);
+    tx.insert("signature".to_string(), Value::String(crypto.sign_data(&digest, private_key)));
+    tx.insert("public_key".to_string(), Value::String(crypto.derive_public_key(private_key)));
+    tx.insert("hash".to_string(), Value::String(digest));
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    /// Address of the test account `name`, whose private key is the name itself
+    pub(crate) fn test_address(name: &str) -> String {
+        let crypto = Crypto::new();
+        crypto.derive_address(&crypto.derive_public_key(name))
+    }
+
+    /// Sign as the test account `name` without replacing `hash`
+    pub(crate) fn sign_as(tx: &mut HashMap<String, Value>, name: &str) {
+        let crypto = Crypto::new();
+        let digest = transaction_hash(tx);
+        tx.insert("signature".to_string(), Value::String(crypto.sign_data(&digest, name)));
+        tx.insert("public_key".to_string(), Value::String(crypto.derive_public_key(name)));
+    }
+}
@@ -1,8 +1,23 @@
 
 use std::collections::HashMap;
+use std::num::NonZeroUsize;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use serde::{Deserialize, Serialize};
 use serde_json::Value;
 use std::time::{SystemTime, UNIX_EPOCH};
 use sha2::{Sha256, Digest};
+use lru::LruCache;
+use rand::Rng;
+use crate::core::wallet::{AddressError, LunaWallet};
+use crate::storage::database::WalletDatabase;
+use crate::transactions::security::sanitize_memo;
+use crate::transactions::signing;
+use crate::utils::clock::{Clock, SystemClock};
+use crate::utils::uri::{PaymentRequest, UriError};
+
+const DEFAULT_IDEMPOTENCY_TTL_SECS: u64 = 600;
+const DEFAULT_IDEMPOTENCY_CAPACITY: usize = 1_000;
 
 #[derive(Debug, Default)]
 pub struct TransactionSecurity;
@@ -39,6 +54,39 @@ impl TransactionSecurity {
     }
 }
 
+/// How urgently a transfer should be mined; scales the base fee
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FeePriority {
+    Low,
+    #[default]
+    Normal,
+    High,
+}
+
+impl FeePriority {
+    pub fn multiplier(&self) -> f64 {
+        match self {
+            FeePriority::Low => 0.5,
+            FeePriority::Normal => 1.0,
+            FeePriority::High => 2.0,
+        }
+    }
+}
+
+impl FromStr for FeePriority {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "low" => Ok(FeePriority::Low),
+            "normal" => Ok(FeePriority::Normal),
+            "high" => Ok(FeePriority::High),
+            _ => Err(format!("Unknown priority '{}'; expected low, normal or high", s)),
+        }
+    }
+}
+
 #[derive(Debug, Default)]
 pub struct FeeCalculator {
     pub fee_config: HashMap<String, f64>,
@@ -55,12 +103,106 @@ impl FeeCalculator {
     pub fn get_fee(&self, transaction_type: &str) -> f64 {
         *self.fee_config.get(transaction_type).unwrap_or(&0.001)
     }
+    pub fn get_priority_fee(&self, transaction_type: &str, priority: FeePriority) -> f64 {
+        self.get_fee(transaction_type) * priority.multiplier()
+    }
+}
+
+/// The parts of a `create_signed_transaction` call an idempotency key must be reused with
+#[derive(Debug, Clone, PartialEq)]
+struct TransferRequest {
+    from: String,
+    to: String,
+    amount: f64,
+    memo: String,
+}
+
+impl TransferRequest {
+    fn new(from: &str, to: &str, amount: f64, memo: &str) -> Self {
+        TransferRequest { from: from.to_string(), to: to.to_string(), amount, memo: sanitize_memo(memo) }
+    }
+
+    /// The request a stored transaction was created for
+    fn of(tx: &HashMap<String, Value>) -> Self {
+        let text = |field: &str| tx.get(field).and_then(Value::as_str).unwrap_or_default().to_string();
+        TransferRequest {
+            from: text("from"),
+            to: text("to"),
+            amount: tx.get("amount").and_then(Value::as_f64).unwrap_or_default(),
+            memo: text("memo"),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct IdempotencyEntry {
+    request: TransferRequest,
+    transaction: HashMap<String, Value>,
+    created_at: u64,
+}
+
+/// Why `create_signed_transaction` did not create a transfer
+#[derive(Debug, Clone, PartialEq)]
+pub enum CreateTransactionError {
+    Address(AddressError),
+    /// The idempotency key was already used for a transfer with other parameters
+    IdempotencyConflict(String),
+}
+
+impl std::fmt::Display for CreateTransactionError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CreateTransactionError::Address(e) => e.fmt(f),
+            CreateTransactionError::IdempotencyConflict(key) => {
+                write!(f, "Idempotency key {:?} was already used for a different transfer", key)
+            }
+        }
+    }
+}
+
+impl std::error::Error for CreateTransactionError {}
+
+impl From<AddressError> for CreateTransactionError {
+    fn from(e: AddressError) -> Self {
+        CreateTransactionError::Address(e)
+    }
+}
+
+/// Bounded key -> transaction map with TTL expiry and LRU eviction
+#[derive(Debug)]
+struct IdempotencyCache {
+    entries: LruCache<String, IdempotencyEntry>,
+    ttl_secs: u64,
+}
+
+impl IdempotencyCache {
+    fn new(ttl_secs: u64, capacity: usize) -> Self {
+        IdempotencyCache {
+            entries: LruCache::new(NonZeroUsize::new(capacity.max(1)).unwrap()),
+            ttl_secs,
+        }
+    }
+
+    fn is_expired(&self, entry: &IdempotencyEntry, now: u64) -> bool {
+        now.saturating_sub(entry.created_at) >= self.ttl_secs
+    }
 }
 
 #[derive(Debug)]
 pub struct TransactionManager {
     pub security: TransactionSecurity,
     pub fee_calculator: FeeCalculator,
+    idempotency: Mutex<IdempotencyCache>,
+    database: Option<WalletDatabase>,
+    clock: Arc<dyn Clock>,
+    /// Whether transfers may use addresses without a checksum
+    legacy_addresses: bool,
+}
+
+impl Default for TransactionManager {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl TransactionManager {
@@ -68,9 +210,161 @@ impl TransactionManager {
         TransactionManager {
             security: TransactionSecurity,
             fee_calculator: FeeCalculator::new(),
+            idempotency: Mutex::new(IdempotencyCache::new(DEFAULT_IDEMPOTENCY_TTL_SECS, DEFAULT_IDEMPOTENCY_CAPACITY)),
+            database: None,
+            clock: Arc::new(SystemClock),
+            legacy_addresses: true,
+        }
+    }
+
+    /// Configure how long idempotency keys are honored and how many are kept
+    pub fn with_idempotency(mut self, ttl_secs: u64, capacity: usize) -> Self {
+        self.idempotency = Mutex::new(IdempotencyCache::new(ttl_secs, capacity));
+        self.reload_idempotency_keys();
+        self
+    }
+
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// Accept or refuse addresses without a checksum in new transfers; accepted by default
+    pub fn with_legacy_addresses(mut self, allow: bool) -> Self {
+        self.legacy_addresses = allow;
+        self
+    }
+
+    /// Persist idempotency keys in the wallet database and restore unexpired ones
+    pub fn with_database(mut self, database: WalletDatabase) -> Self {
+        self.database = Some(database);
+        self.reload_idempotency_keys();
+        self
+    }
+
+    /// The database given to `with_database`
+    pub fn database(&self) -> Option<&WalletDatabase> {
+        self.database.as_ref()
+    }
+
+    fn reload_idempotency_keys(&mut self) {
+        let Some(db) = &self.database else { return };
+        let now = self.clock.now();
+        let cache = self.idempotency.get_mut().unwrap();
+        for (key, raw, created_at) in db.load_idempotency_keys() {
+            let transaction: HashMap<String, Value> = serde_json::from_value(raw).unwrap_or_default();
+            let entry = IdempotencyEntry { request: TransferRequest::of(&transaction), transaction, created_at };
+            if cache.is_expired(&entry, now) || entry.transaction.is_empty() {
+                db.delete_idempotency_key(&key);
+                continue;
+            }
+            if let Some((evicted, _)) = cache.entries.push(key.clone(), entry)
+                && evicted != key
+            {
+                db.delete_idempotency_key(&evicted);
+            }
+        }
+    }
+
+    /// Create and sign a transfer. A repeated `idempotency_key` within the TTL returns the
+    /// originally created transaction instead of a new one, and fails if the transfer's addresses,
+    /// amount or memo differ from the original's. Fails if either address does not pass
+    /// `LunaWallet::validate_address`.
+    pub fn create_signed_transaction(
+        &self,
+        from_address: &str,
+        to_address: &str,
+        amount: f64,
+        memo: &str,
+        private_key: &str,
+        idempotency_key: Option<String>,
+    ) -> Result<HashMap<String, Value>, CreateTransactionError> {
+        let now = self.clock.now();
+        let request = TransferRequest::new(from_address, to_address, amount, memo);
+        if let Some(key) = &idempotency_key
+            && let Some(tx) = self.idempotent_transaction(key, &request, now)?
+        {
+            return Ok(tx);
         }
+        let mut tx = self.create_transaction(from_address, to_address, amount, memo, "transfer")?;
+        tx.insert("nonce".to_string(), Value::from(rand::thread_rng().r#gen::<u64>()));
+        Self::sign_transaction(&mut tx, private_key);
+        let Some(key) = idempotency_key else {
+            return Ok(tx);
+        };
+        let evicted = {
+            let mut cache = self.idempotency.lock().unwrap();
+            // Another call with the same key may have finished first
+            if let Some(entry) = cache.entries.get(&key).cloned()
+                && !cache.is_expired(&entry, now)
+            {
+                return Self::replay(&key, &entry, &request);
+            }
+            let entry = IdempotencyEntry { request, transaction: tx.clone(), created_at: now };
+            cache.entries.push(key.clone(), entry).map(|(evicted, _)| evicted).filter(|evicted| *evicted != key)
+        };
+        if let Some(db) = &self.database {
+            db.save_idempotency_key(&key, &serde_json::to_value(&tx).unwrap(), now);
+            if let Some(evicted) = evicted {
+                db.delete_idempotency_key(&evicted);
+            }
+        }
+        Ok(tx)
     }
 
+    /// The unexpired transaction stored under `key`, dropping an expired one
+    fn idempotent_transaction(
+        &self,
+        key: &str,
+        request: &TransferRequest,
+        now: u64,
+    ) -> Result<Option<HashMap<String, Value>>, CreateTransactionError> {
+        {
+            let mut cache = self.idempotency.lock().unwrap();
+            let Some(entry) = cache.entries.get(key).cloned() else {
+                return Ok(None);
+            };
+            if !cache.is_expired(&entry, now) {
+                return Self::replay(key, &entry, request).map(Some);
+            }
+            cache.entries.pop(key);
+        }
+        if let Some(db) = &self.database {
+            db.delete_idempotency_key(key);
+        }
+        Ok(None)
+    }
+
+    fn replay(key: &str, entry: &IdempotencyEntry, request: &TransferRequest) -> Result<HashMap<String, Value>, CreateTransactionError> {
+        if entry.request != *request {
+            return Err(CreateTransactionError::IdempotencyConflict(key.to_string()));
+        }
+        Ok(entry.transaction.clone())
+    }
+
+    /// Like `create_signed_transaction` without idempotency, paying the fee for `priority`
+    pub fn create_priority_transaction(
+        &self,
+        from_address: &str,
+        to_address: &str,
+        amount: f64,
+        memo: &str,
+        private_key: &str,
+        priority: FeePriority,
+    ) -> Result<HashMap<String, Value>, AddressError> {
+        let mut tx = self.create_transaction(from_address, to_address, amount, memo, "transfer")?;
+        tx.insert("fee".to_string(), Value::from(self.fee_calculator.get_priority_fee("transfer", priority)));
+        tx.insert("nonce".to_string(), Value::from(rand::thread_rng().r#gen::<u64>()));
+        Self::sign_transaction(&mut tx, private_key);
+        Ok(tx)
+    }
+
+    /// Sign the canonical transaction hash and fill `signature`, `public_key` and `hash`
+    pub fn sign_transaction(tx: &mut HashMap<String, Value>, private_key: &str) {
+        signing::sign_transaction(tx, private_key);
+    }
+
+    /// An unsigned transaction between two addresses that pass `LunaWallet::validate_address`
     pub fn create_transaction(
         &self,
         from_address: &str,
@@ -78,9 +372,11 @@ impl TransactionManager {
         amount: f64,
         memo: &str,
         transaction_type: &str,
-    ) -> HashMap<String, Value> {
+    ) -> Result<HashMap<String, Value>, AddressError> {
+        LunaWallet::validate_address_with(from_address, self.legacy_addresses)?;
+        LunaWallet::validate_address_with(to_address, self.legacy_addresses)?;
         let fee = self.fee_calculator.get_fee(transaction_type);
-        let timestamp = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs() as i64;
+        let timestamp = self.clock.now();
         let mut tx = HashMap::new();
         tx.insert("type".to_string(), Value::String(transaction_type.to_string()));
         tx.insert("from".to_string(), Value::String(from_address.to_string()));
@@ -88,17 +384,23 @@ impl TransactionManager {
         tx.insert("amount".to_string(), Value::from(amount));
         tx.insert("fee".to_string(), Value::from(fee));
         tx.insert("timestamp".to_string(), Value::from(timestamp));
-        tx.insert("memo".to_string(), Value::String(memo.to_string()));
+        tx.insert("memo".to_string(), Value::String(sanitize_memo(memo)));
         tx.insert("version".to_string(), Value::String("2.0".to_string()));
-        // 署名・公開鍵は未実装
+        // 署名・公開鍵は LunaWallet::sign_transaction で設定する
         tx.insert("signature".to_string(), Value::String("unsigned".to_string()));
         tx.insert("public_key".to_string(), Value::String("unsigned".to_string()));
         tx.insert("hash".to_string(), Value::String(Self::calculate_transaction_hash(&tx)));
-        tx
+        Ok(tx)
+    }
+
+    /// An unsigned transfer from `from_address` paying a scanned request, ready to review and sign
+    pub fn from_payment_request(&self, from_address: &str, request: &PaymentRequest) -> Result<HashMap<String, Value>, UriError> {
+        let amount = request.amount.ok_or(UriError::MissingAmount)?;
+        Ok(self.create_transaction(from_address, &request.address, amount, request.memo.as_deref().unwrap_or_default(), "transfer")?)
     }
 
     pub fn create_gtx_transaction(&self, bill_info: &HashMap<String, Value>) -> HashMap<String, Value> {
-        let timestamp = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs() as i64;
+        let timestamp = self.clock.now();
         let mut tx = HashMap::new();
         tx.insert("type".to_string(), Value::String("gtx_genesis".to_string()));
         tx.insert("from".to_string(), Value::String("mining".to_string()));
@@ -116,7 +418,7 @@ impl TransactionManager {
     }
 
     pub fn create_reward_transaction(&self, to_address: &str, amount: f64, block_height: i64) -> HashMap<String, Value> {
-        let timestamp = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs() as i64;
+        let timestamp = self.clock.now();
         let mut tx = HashMap::new();
         tx.insert("type".to_string(), Value::String("reward".to_string()));
         tx.insert("from".to_string(), Value::String("network".to_string()));
@@ -132,13 +434,9 @@ impl TransactionManager {
         tx
     }
 
+    /// Hash over the canonical (key-sorted) transaction, excluding the hash and signature fields
     pub fn calculate_transaction_hash(tx: &HashMap<String, Value>) -> String {
-        let mut tx_copy = tx.clone();
-        tx_copy.remove("hash");
-        let json = serde_json::to_string(&tx_copy).unwrap();
-        let mut hasher = Sha256::new();
-        hasher.update(json.as_bytes());
-        format!("{:x}", hasher.finalize())
+        signing::transaction_hash(tx)
     }
 
     pub fn generate_reward_hash(to_address: &str, amount: f64, block_height: i64) -> String {
@@ -153,20 +451,35 @@ impl TransactionManager {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::core::crypto::Crypto;
+    use crate::utils::clock::ManualClock;
+    use tempfile::tempdir;
+
+    const ALICE: &str = "LUN_2bd806c97f0e00af2bf5";
+    const BOB: &str = "LUN_81b637d8fcd2c6dafcca";
 
     #[test]
     fn test_create_transfer() {
         let mgr = TransactionManager::new();
-        let tx = mgr.create_transaction("alice", "bob", 123.45, "memo", "transfer");
+        let tx = mgr.create_transaction(ALICE, BOB, 123.45, "memo", "transfer").unwrap();
         assert_eq!(tx.get("type").unwrap().as_str().unwrap(), "transfer");
-        assert_eq!(tx.get("from").unwrap().as_str().unwrap(), "alice");
-        assert_eq!(tx.get("to").unwrap().as_str().unwrap(), "bob");
+        assert_eq!(tx.get("from").unwrap().as_str().unwrap(), ALICE);
+        assert_eq!(tx.get("to").unwrap().as_str().unwrap(), BOB);
         assert_eq!(tx.get("amount").unwrap().as_f64().unwrap(), 123.45);
         assert_eq!(tx.get("fee").unwrap().as_f64().unwrap(), 0.001);
         assert_eq!(tx.get("signature").unwrap().as_str().unwrap(), "unsigned");
         assert!(tx.get("hash").unwrap().as_str().unwrap().len() == 64);
     }
 
+    #[test]
+    fn test_create_transaction_sanitizes_memo() {
+        let mgr = TransactionManager::new();
+        let tx = mgr.create_transaction(ALICE, BOB, 1.0, &format!("rent\0{}", "x".repeat(600)), "transfer").unwrap();
+        let memo = tx.get("memo").unwrap().as_str().unwrap();
+        assert!(memo.starts_with("rentx"));
+        assert_eq!(memo.len(), 512);
+    }
+
     #[test]
     fn test_create_gtx_transaction() {
         let mgr = TransactionManager::new();
@@ -197,18 +510,125 @@ mod tests {
         assert!(tx.get("hash").unwrap().as_str().unwrap().len() == 64);
     }
 
+    #[test]
+    fn test_priority_transaction_scales_fee_and_is_signed() {
+        let mgr = TransactionManager::new();
+        let key = Crypto::new().generate_private_key();
+        let tx = mgr.create_priority_transaction(ALICE, BOB, 1.0, "", &key, "high".parse().unwrap()).unwrap();
+        assert_eq!(tx["fee"].as_f64().unwrap(), 0.002);
+        assert_eq!(tx["hash"].as_str().unwrap(), TransactionManager::calculate_transaction_hash(&tx));
+        assert!(Crypto::new().verify_signature(tx["hash"].as_str().unwrap(), tx["signature"].as_str().unwrap(), tx["public_key"].as_str().unwrap()));
+        assert!("urgent".parse::<FeePriority>().is_err());
+    }
+
     #[test]
     fn test_validate_transaction() {
         let mgr = TransactionManager::new();
-        let tx = mgr.create_transaction("alice", "bob", 1.0, "memo", "transfer");
+        let tx = mgr.create_transaction(ALICE, BOB, 1.0, "memo", "transfer").unwrap();
         let (ok, msg) = mgr.security.validate_transaction(&tx);
         assert!(ok, "{}", msg);
     }
 
+    #[test]
+    fn test_transaction_hash_is_canonical() {
+        let mgr = TransactionManager::new();
+        let tx = mgr.create_transaction(ALICE, BOB, 1.0, "memo", "transfer").unwrap();
+        let reparsed: HashMap<String, Value> = serde_json::from_str(&serde_json::to_string(&tx).unwrap()).unwrap();
+        assert_eq!(TransactionManager::calculate_transaction_hash(&reparsed), tx["hash"].as_str().unwrap());
+    }
+
+    #[test]
+    fn test_idempotency_key_returns_same_transaction() {
+        let clock = Arc::new(ManualClock::new(1_000));
+        let mgr = TransactionManager::new().with_clock(clock.clone());
+        let tx1 = mgr.create_signed_transaction(ALICE, BOB, 5.0, "rent", "privkey", Some("click-1".to_string())).unwrap();
+        clock.advance(3);
+        let tx2 = mgr.create_signed_transaction(ALICE, BOB, 5.0, "rent", "privkey", Some("click-1".to_string())).unwrap();
+        assert_eq!(tx1["hash"], tx2["hash"]);
+        assert_eq!(tx1["timestamp"], tx2["timestamp"]);
+        assert_eq!(tx1["signature"].as_str().unwrap().len(), 128);
+    }
+
+    #[test]
+    fn test_idempotency_key_reused_for_another_transfer() {
+        let clock = Arc::new(ManualClock::new(1_000));
+        let mgr = TransactionManager::new().with_clock(clock);
+        let key = || Some("click-1".to_string());
+        let original = mgr.create_signed_transaction(ALICE, BOB, 5.0, "rent", "privkey", key()).unwrap();
+        let conflict = Err(CreateTransactionError::IdempotencyConflict("click-1".to_string()));
+        assert_eq!(mgr.create_signed_transaction(ALICE, ALICE, 5.0, "rent", "privkey", key()), conflict);
+        assert_eq!(mgr.create_signed_transaction(ALICE, BOB, 50.0, "rent", "privkey", key()), conflict);
+        assert_eq!(mgr.create_signed_transaction(ALICE, BOB, 5.0, "deposit", "privkey", key()), conflict);
+        assert_eq!(mgr.create_signed_transaction(BOB, ALICE, 5.0, "rent", "privkey", key()), conflict);
+        assert_eq!(mgr.create_signed_transaction(ALICE, BOB, 5.0, "rent", "privkey", key()).unwrap(), original);
+    }
+
+    #[test]
+    fn test_different_idempotency_key_creates_new_transaction() {
+        let clock = Arc::new(ManualClock::new(1_000));
+        let mgr = TransactionManager::new().with_clock(clock);
+        let tx1 = mgr.create_signed_transaction(ALICE, BOB, 5.0, "rent", "privkey", Some("a".to_string())).unwrap();
+        let tx2 = mgr.create_signed_transaction(ALICE, BOB, 5.0, "rent", "privkey", Some("b".to_string())).unwrap();
+        assert_ne!(tx1["hash"], tx2["hash"]);
+    }
+
+    #[test]
+    fn test_idempotency_key_expires_after_ttl() {
+        let clock = Arc::new(ManualClock::new(1_000));
+        let mgr = TransactionManager::new().with_clock(clock.clone()).with_idempotency(60, 10);
+        let tx1 = mgr.create_signed_transaction(ALICE, BOB, 5.0, "", "privkey", Some("k".to_string())).unwrap();
+        clock.advance(60);
+        let tx2 = mgr.create_signed_transaction(ALICE, BOB, 5.0, "", "privkey", Some("k".to_string())).unwrap();
+        assert_ne!(tx1["hash"], tx2["hash"]);
+    }
+
+    #[test]
+    fn test_idempotency_capacity_evicts_least_recent() {
+        let clock = Arc::new(ManualClock::new(1_000));
+        let mgr = TransactionManager::new().with_clock(clock).with_idempotency(600, 2);
+        let a = mgr.create_signed_transaction(ALICE, BOB, 1.0, "", "privkey", Some("a".to_string())).unwrap();
+        let b = mgr.create_signed_transaction(ALICE, BOB, 1.0, "", "privkey", Some("b".to_string())).unwrap();
+        mgr.create_signed_transaction(ALICE, BOB, 1.0, "", "privkey", Some("c".to_string())).unwrap();
+        let b2 = mgr.create_signed_transaction(ALICE, BOB, 1.0, "", "privkey", Some("b".to_string())).unwrap();
+        let a2 = mgr.create_signed_transaction(ALICE, BOB, 1.0, "", "privkey", Some("a".to_string())).unwrap();
+        assert_eq!(b["hash"], b2["hash"]);
+        assert_ne!(a["hash"], a2["hash"]);
+    }
+
+    #[test]
+    fn test_idempotency_keys_persist_in_database() {
+        let dir = tempdir().unwrap();
+        let db_path = dir.path().join("wallets.db");
+        let clock = Arc::new(ManualClock::new(1_000));
+        let mgr = TransactionManager::new()
+            .with_clock(clock.clone())
+            .with_database(WalletDatabase::new(Some(db_path.clone())));
+        let tx1 = mgr.create_signed_transaction(ALICE, BOB, 2.0, "", "privkey", Some("persisted".to_string())).unwrap();
+        let restarted = TransactionManager::new()
+            .with_clock(clock)
+            .with_database(WalletDatabase::new(Some(db_path)));
+        let tx2 = restarted.create_signed_transaction(ALICE, BOB, 2.0, "", "privkey", Some("persisted".to_string())).unwrap();
+        assert_eq!(tx1["hash"], tx2["hash"]);
+    }
+
+    #[test]
+    fn test_from_payment_request() {
+        let mgr = TransactionManager::new();
+        let request = PaymentRequest::from_uri(&format!("luna:{}?amount=2.5&memo=rent%20june&label=Bob", BOB)).unwrap();
+        let tx = mgr.from_payment_request(ALICE, &request).unwrap();
+        assert_eq!((tx["from"].as_str(), tx["to"].as_str(), tx["amount"].as_f64()), (Some(ALICE), Some(BOB), Some(2.5)));
+        assert_eq!(tx["memo"], "rent june");
+        assert_eq!(tx["signature"], "unsigned");
+
+        assert_eq!(mgr.from_payment_request(ALICE, &PaymentRequest::new(BOB)).unwrap_err(), UriError::MissingAmount);
+        let error = mgr.from_payment_request("LUN_nobody", &request).unwrap_err();
+        assert_eq!(error, UriError::InvalidAddress(AddressError::InvalidCharacter('n')));
+    }
+
     #[test]
     fn test_assess_risk() {
         let mgr = TransactionManager::new();
-        let tx = mgr.create_transaction("alice", "bob", 1_000_001.0, "memo", "transfer");
+        let tx = mgr.create_transaction(ALICE, BOB, 1_000_001.0, "memo", "transfer").unwrap();
         let (level, reason) = mgr.security.assess_risk(&tx);
         assert_eq!(level, "high");
         assert_eq!(reason, "Very large transaction");
@@ -1,76 +1,409 @@
 pub struct Validator;
 
-use std::collections::HashSet;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt;
+use std::num::NonZeroUsize;
+use lru::LruCache;
+use serde::{Deserialize, Serialize};
 use serde_json::Value;
-use crate::transactions::security::TransactionSecurity;
+use crate::core::blockchain::{BlockLookup, BlockchainManager};
+use crate::core::merkle::{merkle_proof, merkle_root, verify_merkle_proof};
+use crate::mining::difficulty::Difficulty;
+use crate::mining::reward::RewardSchedule;
+use crate::transactions::outcome::{RiskLevel, ValidationOutcome, Violation};
+use crate::transactions::rules::{default_rules, RuleContext, ValidationRule};
+use crate::transactions::score::ScoreBreakdown;
+use crate::transactions::security::{SecurityPolicy, TransactionSecurity};
+
+/// Acceptance statistics for tuning security policies
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ValidatorMetrics {
+    pub validated: u64,
+    pub accepted: u64,
+    pub rejected: u64,
+    /// Violation counts keyed by `Violation::rule`
+    pub rejected_by_rule: BTreeMap<String, u64>,
+    pub duplicates: u64,
+    pub average_score: f64,
+    pub batches: u64,
+    pub batch_transactions: u64,
+    pub max_batch_size: u64,
+}
+
+impl ValidatorMetrics {
+    fn record(&mut self, outcome: &ValidationOutcome) {
+        self.validated += 1;
+        if outcome.valid {
+            self.accepted += 1;
+        } else {
+            self.rejected += 1;
+        }
+        for violation in &outcome.violations {
+            *self.rejected_by_rule.entry(violation.rule().to_string()).or_insert(0) += 1;
+            if matches!(violation, Violation::Duplicate { .. }) {
+                self.duplicates += 1;
+            }
+        }
+        self.average_score += (outcome.score as f64 - self.average_score) / self.validated as f64;
+    }
+
+    fn record_batch(&mut self, size: usize) {
+        self.batches += 1;
+        self.batch_transactions += size as u64;
+        self.max_batch_size = self.max_batch_size.max(size as u64);
+    }
+
+    pub fn average_batch_size(&self) -> f64 {
+        if self.batches == 0 { 0.0 } else { self.batch_transactions as f64 / self.batches as f64 }
+    }
+}
+
+/// Where a transaction sits on chain, as far as the block source can tell
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum InclusionResult {
+    Included { block_hash: String, index: usize },
+    NotInBlock,
+    /// The block source has no block at that height
+    BlockUnavailable,
+}
+
+/// Looks up an address's spendable balance, None when unknown
+pub type BalanceSource = Box<dyn Fn(&str) -> Option<f64> + Send + Sync>;
 
-#[derive(Debug)]
 pub struct TransactionValidator {
     pub security: TransactionSecurity,
-    pub recent_transactions: HashSet<String>,
-    pub max_recent_size: usize,
+    /// Recently accepted hashes, evicted oldest-first once full
+    pub recent_transactions: LruCache<String, ()>,
+    /// Hashes in blocks this validator accepted, evicted oldest-first once full
+    confirmed_transactions: LruCache<String, ()>,
+    pub reward_schedule: RewardSchedule,
+    balance_source: Option<BalanceSource>,
+    /// Outgoing amount+fee validated this session, per sender
+    pending_outgoing: HashMap<String, f64>,
+    metrics: ValidatorMetrics,
+    /// Transaction checks, run in order
+    rules: Vec<Box<dyn ValidationRule>>,
+    /// Stop at the first violation instead of reporting every one
+    short_circuit: bool,
+}
+
+impl fmt::Debug for TransactionValidator {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TransactionValidator")
+            .field("security", &self.security)
+            .field("recent_transactions", &self.recent_transactions.len())
+            .field("confirmed_transactions", &self.confirmed_transactions.len())
+            .field("reward_schedule", &self.reward_schedule)
+            .field("balance_source", &self.balance_source.is_some())
+            .field("pending_outgoing", &self.pending_outgoing)
+            .field("metrics", &self.metrics)
+            .field("rules", &self.list_rules())
+            .field("short_circuit", &self.short_circuit)
+            .finish()
+    }
+}
+
+impl Default for TransactionValidator {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl TransactionValidator {
     pub fn new() -> Self {
+        Self::with_capacity(10_000)
+    }
+
+    /// Validator remembering up to `capacity` recent and `capacity` confirmed hashes for duplicate detection
+    pub fn with_capacity(capacity: usize) -> Self {
+        let capacity = NonZeroUsize::new(capacity.max(1)).unwrap();
         TransactionValidator {
             security: TransactionSecurity::new(false),
-            recent_transactions: HashSet::new(),
-            max_recent_size: 10_000,
+            recent_transactions: LruCache::new(capacity),
+            confirmed_transactions: LruCache::new(capacity),
+            reward_schedule: RewardSchedule::default(),
+            balance_source: None,
+            pending_outgoing: HashMap::new(),
+            metrics: ValidatorMetrics::default(),
+            rules: default_rules(),
+            short_circuit: false,
         }
     }
 
-    pub fn validate_transaction(&mut self, transaction: &HashMap<String, Value>) -> (bool, String) {
-        let tx_hash = transaction.get("hash").and_then(|v| v.as_str()).unwrap_or("");
-        if self.recent_transactions.contains(tx_hash) {
-            return (false, "Duplicate transaction detected".to_string());
+    /// Public keys allowed to sign reward and gtx_genesis transactions
+    pub fn with_authorized_signers<I, S>(mut self, public_keys: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        self.security = self.security.with_authorized_signers(public_keys);
+        self
+    }
+
+    /// Accept legacy "system" signatures on system transactions, logging each as a warning
+    pub fn with_legacy_system_signatures(mut self, allow: bool) -> Self {
+        self.security = self.security.with_legacy_system_signatures(allow);
+        self
+    }
+
+    /// Policy for transaction types without one of their own
+    pub fn with_default_policy(mut self, policy: SecurityPolicy) -> Self {
+        self.security.default_policy = policy;
+        self
+    }
+
+    pub fn with_reward_schedule(mut self, schedule: RewardSchedule) -> Self {
+        self.reward_schedule = schedule;
+        self
+    }
+
+    /// Reject transfers the sender cannot afford, given this balance lookup
+    pub fn with_balance_source(mut self, source: BalanceSource) -> Self {
+        self.balance_source = Some(source);
+        self
+    }
+
+    pub fn with_short_circuit(mut self, short_circuit: bool) -> Self {
+        self.short_circuit = short_circuit;
+        self
+    }
+
+    /// Append a rule to the end of the pipeline
+    pub fn add_rule(&mut self, rule: Box<dyn ValidationRule>) {
+        self.rules.push(rule);
+    }
+
+    /// Remove every rule with this name, returning whether any was registered
+    pub fn remove_rule(&mut self, name: &str) -> bool {
+        let before = self.rules.len();
+        self.rules.retain(|r| r.name() != name);
+        self.rules.len() != before
+    }
+
+    /// Rule names in execution order
+    pub fn list_rules(&self) -> Vec<&str> {
+        self.rules.iter().map(|r| r.name()).collect()
+    }
+
+    /// Outgoing value already validated for `address` this session
+    pub fn pending_outgoing(&self, address: &str) -> f64 {
+        self.pending_outgoing.get(&address.to_lowercase()).copied().unwrap_or(0.0)
+    }
+
+    /// Forget pending outgoing value, e.g. once the transactions are confirmed on chain
+    pub fn clear_pending_outgoing(&mut self, address: &str) {
+        self.pending_outgoing.remove(&address.to_lowercase());
+    }
+
+    pub fn recent_len(&self) -> usize {
+        self.recent_transactions.len()
+    }
+
+    pub fn contains_recent(&self, tx_hash: &str) -> bool {
+        self.recent_transactions.contains(tx_hash)
+    }
+
+    /// Whether the hash is in a block this validator accepted
+    pub fn contains_confirmed(&self, tx_hash: &str) -> bool {
+        self.confirmed_transactions.contains(tx_hash)
+    }
+
+    pub fn validate_transaction(&mut self, transaction: &HashMap<String, Value>) -> ValidationOutcome {
+        let outcome = self.check_transaction(transaction);
+        self.metrics.record(&outcome);
+        outcome
+    }
+
+    fn check_transaction(&mut self, transaction: &HashMap<String, Value>) -> ValidationOutcome {
+        self.security.purge_expired();
+        let violations = self.run_rules(transaction, false);
+        let outcome = self.security.conclude(transaction, violations);
+        if outcome.valid {
+            let tx_hash = transaction.get("hash").and_then(|v| v.as_str()).unwrap_or("");
+            self.add_to_recent(tx_hash);
+            if let Some((address, required)) = self.outgoing_spend(transaction) {
+                *self.pending_outgoing.entry(address).or_insert(0.0) += required;
+            }
         }
-        let (is_valid, message) = self.security.validate_transaction_security(transaction);
-        if !is_valid {
-            return (false, message);
+        outcome
+    }
+
+    /// Run the pipeline over one transaction; `in_block` keeps only the rules that check blocks
+    fn run_rules(&mut self, transaction: &HashMap<String, Value>, in_block: bool) -> Vec<Violation> {
+        let mut violations = Vec::new();
+        let mut ctx = RuleContext::new(
+            &mut self.security,
+            &self.recent_transactions,
+            &self.confirmed_transactions,
+            self.balance_source.as_ref(),
+            &self.pending_outgoing,
+        );
+        for rule in self.rules.iter().filter(|r| !in_block || r.checks_blocks()) {
+            if let Some(violation) = rule.check(transaction, &mut ctx) {
+                violations.push(violation);
+                if self.short_circuit || rule.halts() {
+                    break;
+                }
+            }
         }
-        self.add_to_recent(tx_hash);
-        (true, message)
+        violations
+    }
+
+    #[deprecated(note = "use validate_transaction, which returns a ValidationOutcome")]
+    pub fn validate_transaction_tuple(&mut self, transaction: &HashMap<String, Value>) -> (bool, String) {
+        self.validate_transaction(transaction).into_tuple()
+    }
+
+    pub fn validate_transaction_batch(&mut self, transactions: &[HashMap<String, Value>]) -> Vec<ValidationOutcome> {
+        self.metrics.record_batch(transactions.len());
+        transactions.iter().map(|tx| self.validate_transaction(tx)).collect()
+    }
+
+    pub fn get_metrics(&self) -> ValidatorMetrics {
+        self.metrics.clone()
+    }
+
+    pub fn reset_metrics(&mut self) {
+        self.metrics = ValidatorMetrics::default();
+    }
+
+    #[deprecated(note = "use validate_transaction_batch, which returns a ValidationOutcome per transaction")]
+    pub fn validate_transaction_batch_tuple(&mut self, transactions: &[HashMap<String, Value>]) -> (bool, Vec<String>) {
+        let outcomes = self.validate_transaction_batch(transactions);
+        let all_valid = outcomes.iter().all(|o| o.valid);
+        (all_valid, outcomes.iter().map(|o| o.message()).collect())
     }
 
-    pub fn validate_transaction_batch(&mut self, transactions: &[HashMap<String, Value>]) -> (bool, Vec<String>) {
-        let mut results = Vec::new();
-        let mut all_valid = true;
-        for tx in transactions {
-            let (is_valid, message) = self.validate_transaction(tx);
-            results.push(message.clone());
-            if !is_valid {
-                all_valid = false;
+    /// Validate a whole block: header linkage, proof of work, the single reward, hashes repeated
+    /// within the block or already in an accepted block, each transaction against the pipeline's
+    /// rules that check blocks (structure, system fields, signature and any added rule), and the
+    /// merkle root when present. Accepting the block moves its hashes from the
+    /// recent set to the confirmed one.
+    pub fn validate_block(
+        &mut self,
+        block: &HashMap<String, Value>,
+        expected_height: u64,
+        expected_prev_hash: &str,
+        difficulty: Difficulty,
+    ) -> ValidationOutcome {
+        let mut violations = Vec::new();
+        for field in ["index", "previous_hash", "hash", "transactions"] {
+            if !block.contains_key(field) {
+                violations.push(Violation::MissingField(field));
+            }
+        }
+        if !violations.is_empty() {
+            return ValidationOutcome::new(violations, 0, RiskLevel::High);
+        }
+
+        let height = block.get("index").and_then(|v| v.as_u64()).unwrap_or(u64::MAX);
+        if height != expected_height {
+            violations.push(Violation::HeightMismatch { expected: expected_height, got: height });
+        }
+        let prev_hash = block.get("previous_hash").and_then(|v| v.as_str()).unwrap_or("");
+        if prev_hash != expected_prev_hash {
+            violations.push(Violation::PreviousHashMismatch { expected: expected_prev_hash.to_string(), got: prev_hash.to_string() });
+        }
+        let block_hash = block.get("hash").and_then(|v| v.as_str()).unwrap_or("");
+        let computed = BlockchainManager::calculate_block_hash(block);
+        if block_hash != computed {
+            violations.push(Violation::BlockHashMismatch { computed: computed.clone(), got: block_hash.to_string() });
+        }
+        if !difficulty.is_valid_hash(&computed) {
+            violations.push(Violation::InsufficientWork { difficulty: difficulty.value });
+        }
+
+        let transactions: Vec<HashMap<String, Value>> = block
+            .get("transactions")
+            .and_then(|v| serde_json::from_value(v.clone()).ok())
+            .unwrap_or_default();
+        let is_reward = |tx: &HashMap<String, Value>| tx.get("type").and_then(|v| v.as_str()) == Some("reward");
+        let rewards: Vec<_> = transactions.iter().filter(|tx| is_reward(tx)).collect();
+        if rewards.len() != 1 {
+            violations.push(Violation::RewardCount { got: rewards.len() });
+        } else {
+            let expected = self.reward_schedule.reward_at(expected_height);
+            let got = rewards[0].get("amount").and_then(|v| v.as_f64()).unwrap_or(0.0);
+            if (got - expected).abs() > 1e-9 {
+                violations.push(Violation::RewardAmountMismatch { expected, got });
+            }
+        }
+
+        let mut seen = HashSet::new();
+        let mut tx_hashes = Vec::with_capacity(transactions.len());
+        for tx in &transactions {
+            let tx_hash = tx.get("hash").and_then(|v| v.as_str()).unwrap_or("").to_string();
+            if !seen.insert(tx_hash.clone()) || self.contains_confirmed(&tx_hash) {
+                violations.push(Violation::Duplicate { hash: tx_hash.clone() });
+            }
+            let tx_violations = self.run_rules(tx, true);
+            if !tx_violations.is_empty() {
+                violations.push(Violation::InvalidTransaction { hash: tx_hash.clone(), violations: tx_violations });
+            }
+            tx_hashes.push(tx_hash);
+        }
+        if let Some(root) = block.get("merkle_root").and_then(|v| v.as_str()) {
+            let computed = merkle_root(&tx_hashes);
+            if root != computed {
+                violations.push(Violation::MerkleRootMismatch { computed, got: root.to_string() });
+            }
+        }
+
+        if violations.is_empty() {
+            for tx_hash in tx_hashes {
+                self.recent_transactions.pop(&tx_hash);
+                self.confirmed_transactions.put(tx_hash, ());
             }
         }
-        (all_valid, results)
+        let score = if violations.is_empty() { 100 } else { 0 };
+        let risk = if violations.is_empty() { RiskLevel::VeryLow } else { RiskLevel::High };
+        ValidationOutcome::new(violations, score, risk)
     }
 
-    pub fn verify_transaction_inclusion(&self, transaction_hash: &str, _block_height: i64) -> bool {
-        self.recent_transactions.contains(transaction_hash)
+    /// Look for the transaction in the block at `block_height`, checking its merkle proof when the block carries a root
+    pub fn verify_transaction_inclusion(&self, transaction_hash: &str, block_height: u64, blocks: &dyn BlockLookup) -> InclusionResult {
+        let Some(block) = blocks.block_at(block_height) else {
+            return InclusionResult::BlockUnavailable;
+        };
+        let tx_hashes: Vec<String> = block.transactions.iter().map(|tx| tx.hash.clone().unwrap_or_default()).collect();
+        let Some(index) = tx_hashes.iter().position(|h| h == transaction_hash) else {
+            return InclusionResult::NotInBlock;
+        };
+        if let Some(root) = &block.merkle_root {
+            let proven = merkle_proof(&tx_hashes, index).is_some_and(|proof| verify_merkle_proof(transaction_hash, index, &proof, root));
+            if !proven {
+                return InclusionResult::NotInBlock;
+            }
+        }
+        InclusionResult::Included { block_hash: block.hash, index }
     }
 
     pub fn get_transaction_risk_level(&self, transaction: &HashMap<String, Value>) -> String {
+        self.assess_risk(transaction).0.to_string()
+    }
+
+    /// Risk level together with the score breakdown that produced it
+    pub fn assess_risk(&self, transaction: &HashMap<String, Value>) -> (RiskLevel, ScoreBreakdown) {
         let amount = transaction.get("amount").and_then(|v| v.as_f64()).unwrap_or(0.0);
-        let security_score = self.security.calculate_security_score(transaction);
-        if amount > 1_000_000.0 && security_score < 80 {
-            "HIGH".to_string()
-        } else if amount > 10_000.0 && security_score < 60 {
-            "MEDIUM".to_string()
-        } else if security_score < 40 {
-            "LOW".to_string()
-        } else {
-            "VERY_LOW".to_string()
+        let breakdown = self.security.calculate_security_score_detailed(transaction);
+        (RiskLevel::from_breakdown(amount, &breakdown), breakdown)
+    }
+
+    /// Sender and amount+fee for balance-checked transaction types
+    fn outgoing_spend(&self, transaction: &HashMap<String, Value>) -> Option<(String, f64)> {
+        self.balance_source.as_ref()?;
+        let tx_type = transaction.get("type").and_then(|v| v.as_str()).unwrap_or("").to_lowercase();
+        if tx_type == "reward" || tx_type == "gtx_genesis" {
+            return None;
         }
+        let from_address = transaction.get("from").and_then(|v| v.as_str())?.to_lowercase();
+        let amount = transaction.get("amount").and_then(|v| v.as_f64()).unwrap_or(0.0);
+        let fee = transaction.get("fee").and_then(|v| v.as_f64()).unwrap_or(0.0);
+        Some((from_address, amount + fee))
     }
 
     fn add_to_recent(&mut self, tx_hash: &str) {
-        self.recent_transactions.insert(tx_hash.to_string());
-        if self.recent_transactions.len() > self.max_recent_size {
-            let keep: HashSet<_> = self.recent_transactions.iter().cloned().take(self.max_recent_size).collect();
-            self.recent_transactions = keep;
-        }
+        self.recent_transactions.put(tx_hash.to_string(), ());
     }
 }
 
@@ -78,22 +411,32 @@ impl TransactionValidator {
 mod tests {
     use super::*;
     use crate::transactions::security::TransactionSecurity;
+    use crate::core::crypto::Crypto;
+    use crate::transactions::transactions::TransactionManager;
+    use crate::core::blockchain::{Block, Transaction as ChainTransaction};
+    use crate::transactions::signing::tests::{sign_as, test_address};
     use serde_json::json;
 
+    /// A transfer from alice to bob, signed by alice
     fn make_tx(hash: &str, amount: f64) -> HashMap<String, Value> {
         let mut tx = HashMap::new();
         tx.insert("type".to_string(), json!("transfer"));
-        tx.insert("from".to_string(), json!("alice"));
-        tx.insert("to".to_string(), json!("bob"));
+        tx.insert("from".to_string(), json!(test_address("alice")));
+        tx.insert("to".to_string(), json!(test_address("bob")));
         tx.insert("amount".to_string(), json!(amount));
         tx.insert("fee".to_string(), json!(0.001));
         tx.insert("timestamp".to_string(), json!(1234567890));
-        // signature: 128 hex chars, starts with '04'
-        let sig = format!("04{:0<126}", "a");
-        tx.insert("signature".to_string(), json!(sig));
-        tx.insert("public_key".to_string(), json!("04abcdef"));
         tx.insert("nonce".to_string(), json!(123));
         tx.insert("hash".to_string(), json!(hash));
+        sign_as(&mut tx, "alice");
+        tx
+    }
+
+    /// `make_tx` with `field` replaced, signed by alice
+    fn make_tx_with(hash: &str, amount: f64, field: &str, value: Value) -> HashMap<String, Value> {
+        let mut tx = make_tx(hash, amount);
+        tx.insert(field.to_string(), value);
+        sign_as(&mut tx, "alice");
         tx
     }
 
@@ -102,20 +445,316 @@ mod tests {
         let mut validator = TransactionValidator::new();
         let tx1 = make_tx("h1", 10.0);
         let tx2 = make_tx("h1", 20.0);
-        let (ok1, msg1) = validator.validate_transaction(&tx1);
+        let (ok1, msg1) = validator.validate_transaction(&tx1).into_tuple();
         assert!(ok1, "{}", msg1);
-        let (ok2, msg2) = validator.validate_transaction(&tx2);
-        assert!(!ok2, "{}", msg2);
-        assert_eq!(msg2, "Duplicate transaction detected");
+        let outcome = validator.validate_transaction(&tx2);
+        assert_eq!(outcome.violations, vec![Violation::Duplicate { hash: "h1".to_string() }]);
+        assert_eq!(outcome.message(), "Duplicate transaction detected");
+    }
+
+    #[test]
+    fn test_recent_eviction_order() {
+        let capacity = 1_000;
+        let mut validator = TransactionValidator::with_capacity(capacity);
+        for i in 0..capacity + 100 {
+            validator.add_to_recent(&format!("hash{}", i));
+        }
+        assert_eq!(validator.recent_len(), capacity);
+        assert!((0..100).all(|i| !validator.contains_recent(&format!("hash{}", i))));
+        assert!((100..capacity + 100).all(|i| validator.contains_recent(&format!("hash{}", i))));
+    }
+
+    #[test]
+    fn test_duplicate_detected_near_capacity() {
+        let mut validator = TransactionValidator::with_capacity(3);
+        validator.add_to_recent("a");
+        validator.add_to_recent("b");
+        let (ok, msg) = validator.validate_transaction(&make_tx("h6", 10.0)).into_tuple();
+        assert!(ok, "{}", msg);
+        assert!(validator.contains_recent("a"));
+        let outcome = validator.validate_transaction(&make_tx("h6", 10.0));
+        assert_eq!(outcome.violations, vec![Violation::Duplicate { hash: "h6".to_string() }]);
+        validator.add_to_recent("c");
+        assert!(!validator.contains_recent("a"));
+        assert!(validator.contains_recent("h6"));
+    }
+
+    fn stub_validator() -> TransactionValidator {
+        let alice = test_address("alice");
+        network_validator().with_balance_source(Box::new(move |address| address.eq_ignore_ascii_case(&alice).then_some(25.0)))
+    }
+
+    #[test]
+    fn test_balance_affordable_and_exact() {
+        let mut validator = stub_validator();
+        assert!(validator.validate_transaction(&make_tx("b1", 10.0)).valid);
+        assert!((validator.pending_outgoing(&test_address("alice")) - 10.001).abs() < 1e-9);
+        assert!(validator.validate_transaction(&make_tx("b2", 14.998)).valid);
+        assert!(validator.pending_outgoing(&test_address("alice")) <= 25.0 + 1e-9);
+    }
+
+    #[test]
+    fn test_balance_cumulative_overdraft() {
+        let mut validator = stub_validator();
+        assert!(validator.validate_transaction(&make_tx("b3", 12.0)).valid);
+        let outcome = validator.validate_transaction(&make_tx("b4", 12.999));
+        assert!(!outcome.valid);
+        assert!(outcome.has(|v| matches!(v, Violation::InsufficientBalance { .. })));
+        assert!(!validator.contains_recent("b4"));
+        validator.clear_pending_outgoing(&test_address("alice"));
+        assert!(validator.validate_transaction(&make_tx("b4", 12.999)).valid);
+
+        let reward = reward_tx("b5", 1_000.0);
+        let (ok, msg) = validator.validate_transaction(&reward).into_tuple();
+        assert!(ok, "{}", msg);
+    }
+
+    fn reward_tx(hash: &str, amount: f64) -> HashMap<String, Value> {
+        let mut tx = HashMap::new();
+        tx.insert("type".to_string(), json!("reward"));
+        tx.insert("from".to_string(), json!("network"));
+        tx.insert("to".to_string(), json!("miner"));
+        tx.insert("amount".to_string(), json!(amount));
+        tx.insert("block_height".to_string(), json!(5));
+        tx.insert("hash".to_string(), json!(hash));
+        let crypto = Crypto::new();
+        let digest = TransactionManager::calculate_transaction_hash(&tx);
+        tx.insert("signature".to_string(), json!(crypto.sign_data(&digest, NETWORK_KEY)));
+        tx.insert("public_key".to_string(), json!(crypto.derive_public_key(NETWORK_KEY)));
+        tx
+    }
+
+    const NETWORK_KEY: &str = "0f0e0d0c0b0a09080706050403020100000102030405060708090a0b0c0d0e0f";
+
+    fn network_validator() -> TransactionValidator {
+        TransactionValidator::new().with_authorized_signers([Crypto::new().derive_public_key(NETWORK_KEY)])
+    }
+
+    /// Mine a difficulty-1 block at height 5 over the given transactions
+    fn make_block(transactions: Vec<HashMap<String, Value>>) -> HashMap<String, Value> {
+        let hashes: Vec<String> = transactions.iter().map(|tx| tx["hash"].as_str().unwrap().to_string()).collect();
+        let mut block = HashMap::new();
+        block.insert("index".to_string(), json!(5));
+        block.insert("previous_hash".to_string(), json!("prev"));
+        block.insert("timestamp".to_string(), json!(1_700_000_000));
+        block.insert("merkle_root".to_string(), json!(merkle_root(&hashes)));
+        block.insert("transactions".to_string(), json!(transactions));
+        seal(&mut block);
+        block
+    }
+
+    fn seal(block: &mut HashMap<String, Value>) {
+        block.remove("hash");
+        for nonce in 0u64.. {
+            block.insert("nonce".to_string(), json!(nonce));
+            let hash = BlockchainManager::calculate_block_hash(block);
+            if hash.starts_with('0') {
+                block.insert("hash".to_string(), json!(hash));
+                return;
+            }
+        }
+    }
+
+    fn good_block() -> HashMap<String, Value> {
+        make_block(vec![reward_tx("r1", 50.0), make_tx("t1", 10.0), make_tx("t2", 5.0)])
+    }
+
+    fn block_violations(block: &HashMap<String, Value>) -> Vec<String> {
+        let mut validator = network_validator();
+        let outcome = validator.validate_block(block, 5, "prev", Difficulty::new(1));
+        outcome.violations.iter().map(|v| v.rule().to_string()).collect()
+    }
+
+    #[test]
+    fn test_validate_good_block() {
+        let mut validator = network_validator();
+        let block = good_block();
+        let outcome = validator.validate_block(&block, 5, "prev", Difficulty::new(1));
+        assert!(outcome.valid, "{}", outcome.message());
+        assert!(validator.contains_confirmed("t1") && !validator.contains_recent("t1"));
+        let replay = validator.validate_block(&block, 5, "prev", Difficulty::new(1));
+        assert!(replay.has(|v| matches!(v, Violation::Duplicate { .. })));
+    }
+
+    #[test]
+    fn test_validate_block_after_its_transactions() {
+        let mut validator = network_validator();
+        let transfer = make_tx("t1", 10.0);
+        assert!(validator.validate_transaction(&transfer).valid);
+        assert!(validator.contains_recent("t1"));
+
+        let block = make_block(vec![reward_tx("r1", 50.0), transfer.clone()]);
+        let outcome = validator.validate_block(&block, 5, "prev", Difficulty::new(1));
+        assert!(outcome.valid, "{}", outcome.message());
+        assert!(!validator.contains_recent("t1") && validator.contains_confirmed("t1"));
+        // A confirmed transaction cannot be replayed into the mempool
+        assert!(validator.validate_transaction(&transfer).has(|v| matches!(v, Violation::Duplicate { .. })));
+    }
+
+    #[test]
+    fn test_validate_block_has_no_rate_or_velocity_side_effects() {
+        let strict = SecurityPolicy { rate_limit_count: 1, max_hourly_outgoing: 15.0, ..SecurityPolicy::default() };
+        let mut validator = network_validator().with_default_policy(strict);
+        let outcome = validator.validate_block(&good_block(), 5, "prev", Difficulty::new(1));
+        assert!(outcome.valid, "{}", outcome.message());
+        let (ok, msg) = validator.validate_transaction(&make_tx("t3", 10.0)).into_tuple();
+        assert!(ok, "{}", msg);
+        assert!(validator.security.query_events(&crate::transactions::audit::AuditFilter::default()).is_empty());
+    }
+
+    #[test]
+    fn test_validate_block_header_failures() {
+        let mut validator = network_validator();
+        let block = good_block();
+        let outcome = validator.validate_block(&block, 6, "other", Difficulty::new(1));
+        assert_eq!(outcome.violations.iter().map(|v| v.rule()).collect::<Vec<_>>(), vec!["height_mismatch", "previous_hash_mismatch"]);
+
+        let mut tampered = good_block();
+        tampered.insert("timestamp".to_string(), json!(1));
+        assert!(block_violations(&tampered).contains(&"block_hash_mismatch".to_string()));
+
+        let hash = block["hash"].as_str().unwrap();
+        let required = hash.chars().take_while(|c| *c == '0').count() as u32 + 1;
+        let outcome = network_validator().validate_block(&block, 5, "prev", Difficulty::new(required));
+        assert_eq!(outcome.violations, vec![Violation::InsufficientWork { difficulty: required }]);
+
+        let mut missing = good_block();
+        missing.remove("transactions");
+        assert_eq!(block_violations(&missing), vec!["missing_field"]);
+    }
+
+    #[test]
+    fn test_validate_block_reward_failures() {
+        let no_reward = make_block(vec![make_tx("t1", 10.0)]);
+        assert_eq!(block_violations(&no_reward), vec!["reward_count"]);
+        let two_rewards = make_block(vec![reward_tx("r1", 50.0), reward_tx("r2", 50.0)]);
+        assert_eq!(block_violations(&two_rewards), vec!["reward_count"]);
+        let greedy = make_block(vec![reward_tx("r1", 51.0)]);
+        assert_eq!(block_violations(&greedy), vec!["reward_amount_mismatch"]);
+    }
+
+    #[test]
+    fn test_validate_block_transaction_failures() {
+        let duplicated = make_block(vec![reward_tx("r1", 50.0), make_tx("t1", 10.0), make_tx("t1", 10.0)]);
+        assert_eq!(block_violations(&duplicated), vec!["duplicate"]);
+
+        let mut unsigned = make_tx("t2", 10.0);
+        unsigned.insert("signature".to_string(), json!("bad"));
+        let bad_tx = make_block(vec![reward_tx("r1", 50.0), unsigned]);
+        assert_eq!(block_violations(&bad_tx), vec!["invalid_transaction"]);
+
+        let mut wrong_root = good_block();
+        wrong_root.insert("merkle_root".to_string(), json!("deadbeef"));
+        seal(&mut wrong_root);
+        assert_eq!(block_violations(&wrong_root), vec!["merkle_root_mismatch"]);
+    }
+
+    #[test]
+    fn test_metrics_mixed_batch() {
+        let mut validator = network_validator();
+        validator.security.blacklist_address(&test_address("mallory"));
+        let cheap = make_tx_with("m2", 1.0, "fee", json!(0.0));
+        let mut blacklisted = make_tx("m3", 1.0);
+        blacklisted.insert("from".to_string(), json!(test_address("mallory")));
+        blacklisted.insert("fee".to_string(), json!(0.0));
+        sign_as(&mut blacklisted, "mallory");
+        let batch = vec![make_tx("m1", 1.0), cheap, blacklisted, make_tx("m1", 1.0), make_tx("m4", 2.0)];
+        validator.validate_transaction_batch(&batch);
+        validator.validate_transaction_batch(&batch[..1]);
+
+        let metrics = validator.get_metrics();
+        assert_eq!(metrics.validated, 6);
+        assert_eq!(metrics.accepted, 2);
+        assert_eq!(metrics.rejected, 4);
+        assert_eq!(metrics.duplicates, 2);
+        assert_eq!(metrics.rejected_by_rule.get("required_fee"), Some(&2));
+        assert_eq!(metrics.rejected_by_rule.get("blacklisted"), Some(&1));
+        assert_eq!(metrics.rejected_by_rule.get("duplicate"), Some(&2));
+        assert_eq!((metrics.batches, metrics.max_batch_size), (2, 5));
+        assert_eq!(metrics.average_batch_size(), 3.0);
+        assert!(metrics.average_score > 0.0);
+        let json = serde_json::to_value(&metrics).unwrap();
+        assert_eq!(json["rejected_by_rule"]["blacklisted"], 1);
+
+        validator.reset_metrics();
+        assert_eq!(validator.get_metrics(), ValidatorMetrics::default());
+    }
+
+    struct ForbiddenMemoRule;
+
+    impl ValidationRule for ForbiddenMemoRule {
+        fn name(&self) -> &str {
+            "forbidden_memo"
+        }
+
+        fn check(&self, transaction: &HashMap<String, Value>, _ctx: &mut RuleContext) -> Option<Violation> {
+            let memo = transaction.get("memo").and_then(|v| v.as_str())?;
+            memo.contains("forbidden").then(|| Violation::Custom {
+                rule: self.name().to_string(),
+                message: "Memo contains a forbidden word".to_string(),
+            })
+        }
+    }
+
+    #[test]
+    fn test_custom_rule_pipeline() {
+        let mut validator = TransactionValidator::new();
+        validator.add_rule(Box::new(ForbiddenMemoRule));
+        assert_eq!(validator.list_rules().first(), Some(&"duplicate"));
+        assert_eq!(validator.list_rules().last(), Some(&"forbidden_memo"));
+
+        let tx = make_tx_with("r1", 10.0, "memo", json!("a forbidden payment"));
+        let outcome = validator.validate_transaction(&tx);
+        assert_eq!(outcome.message(), "Memo contains a forbidden word");
+        assert_eq!(validator.get_metrics().rejected_by_rule.get("forbidden_memo"), Some(&1));
+        assert!(validator.validate_transaction(&make_tx_with("r1", 10.0, "memo", json!("rent"))).valid);
+
+        assert!(validator.remove_rule("forbidden_memo"));
+        assert!(!validator.remove_rule("forbidden_memo"));
+        assert!(validator.validate_transaction(&make_tx_with("r2", 10.0, "memo", json!("forbidden"))).valid);
+    }
+
+    #[test]
+    fn test_custom_rule_applies_to_blocks() {
+        let mut validator = network_validator();
+        validator.add_rule(Box::new(ForbiddenMemoRule));
+        let tx = make_tx_with("t1", 10.0, "memo", json!("forbidden"));
+        let block = make_block(vec![reward_tx("r1", 50.0), tx]);
+        let outcome = validator.validate_block(&block, 5, "prev", Difficulty::new(1));
+        assert_eq!(outcome.message(), "Invalid transaction t1: Memo contains a forbidden word");
+    }
+
+    #[test]
+    fn test_rejected_transaction_keeps_its_rate_token() {
+        let strict = SecurityPolicy { rate_limit_count: 1, ..SecurityPolicy::default() };
+        let mut validator = TransactionValidator::new().with_default_policy(strict);
+        validator.add_rule(Box::new(ForbiddenMemoRule));
+        let tx = make_tx_with("k1", 10.0, "memo", json!("forbidden"));
+        assert!(!validator.validate_transaction(&tx).valid);
+        assert!(validator.validate_transaction(&make_tx("k2", 10.0)).valid);
+        let outcome = validator.validate_transaction(&make_tx("k3", 10.0));
+        assert!(outcome.has(|v| matches!(v, Violation::RateLimited { .. })));
+    }
+
+    #[test]
+    fn test_short_circuit() {
+        let mut tx = make_tx("r3", 10.0);
+        tx.insert("fee".to_string(), json!(0.0));
+        tx.insert("signature".to_string(), json!("short"));
+        let mut collecting = TransactionValidator::new();
+        assert_eq!(collecting.validate_transaction(&tx).violations.len(), 2);
+        let mut stopping = TransactionValidator::new().with_short_circuit(true);
+        assert_eq!(stopping.validate_transaction(&tx).violations, vec![Violation::FeeTooLow { required: 0.00001, got: 0.0 }]);
     }
 
     #[test]
     fn test_batch_validation() {
         let mut validator = TransactionValidator::new();
         let txs = vec![make_tx("h2", 10.0), make_tx("h3", 20.0)];
-        let (all_valid, results) = validator.validate_transaction_batch(&txs);
-        assert!(all_valid, "Batch validation failed: {:?}", results);
+        let results = validator.validate_transaction_batch(&txs);
         assert_eq!(results.len(), 2);
+        assert!(results.iter().all(|o| o.valid), "Batch validation failed: {:?}", results);
+        assert_eq!(results[0].risk, RiskLevel::VeryLow);
     }
 
     #[test]
@@ -133,14 +772,47 @@ mod tests {
         // With all security fields, should be VERY_LOW
         let level2 = validator.get_transaction_risk_level(&tx);
         assert_eq!(level2, "VERY_LOW");
+        let (risk, breakdown) = validator.assess_risk(&tx_low_score);
+        assert_eq!(risk, RiskLevel::High);
+        assert!(breakdown.explain().contains(&"no nonce (-10)".to_string()));
+    }
+
+    struct FakeBlocks(HashMap<u64, Block>);
+
+    impl BlockLookup for FakeBlocks {
+        fn block_at(&self, height: u64) -> Option<Block> {
+            self.0.get(&height).cloned()
+        }
+    }
+
+    fn chain_block(tx_hashes: &[&str], root: Option<String>) -> Block {
+        let mut block = Block::new();
+        block.hash = "blockhash".to_string();
+        block.transactions = tx_hashes
+            .iter()
+            .map(|h| ChainTransaction { hash: Some(h.to_string()), ..Default::default() })
+            .collect();
+        block.merkle_root = root;
+        block
     }
 
     #[test]
     fn test_inclusion() {
         let mut validator = TransactionValidator::new();
-        let tx = make_tx("h5", 10.0);
-        let (ok, msg) = validator.validate_transaction(&tx);
+        let (ok, msg) = validator.validate_transaction(&make_tx("h5", 10.0)).into_tuple();
         assert!(ok, "{}", msg);
-        assert!(validator.verify_transaction_inclusion("h5", 0));
+        let hashes: Vec<String> = ["h5", "h6", "h7"].iter().map(|h| h.to_string()).collect();
+        let mut blocks = FakeBlocks(HashMap::new());
+        blocks.0.insert(1, chain_block(&["h5", "h6", "h7"], Some(merkle_root(&hashes))));
+        blocks.0.insert(2, chain_block(&["h8"], None));
+        blocks.0.insert(3, chain_block(&["h5", "h6"], Some("bogus".to_string())));
+
+        let included = InclusionResult::Included { block_hash: "blockhash".to_string(), index: 2 };
+        assert_eq!(validator.verify_transaction_inclusion("h7", 1, &blocks), included);
+        assert_eq!(validator.verify_transaction_inclusion("h8", 2, &blocks), InclusionResult::Included { block_hash: "blockhash".to_string(), index: 0 });
+        // Validated locally but never mined
+        assert_eq!(validator.verify_transaction_inclusion("h5", 2, &blocks), InclusionResult::NotInBlock);
+        assert_eq!(validator.verify_transaction_inclusion("h5", 3, &blocks), InclusionResult::NotInBlock);
+        assert_eq!(validator.verify_transaction_inclusion("h5", 9, &blocks), InclusionResult::BlockUnavailable);
     }
 }
@@ -0,0 +1,118 @@
+use std::collections::{HashMap, VecDeque};
+use crate::storage::database::WalletDatabase;
+
+pub const HOUR_SECS: u64 = 3600;
+pub const DAY_SECS: u64 = 86400;
+/// Window used for the trailing average behind anomaly detection
+pub const HISTORY_SECS: u64 = 30 * DAY_SECS;
+
+/// Outgoing value sent by one address inside a rolling window, compared to its cap
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct VelocityUsage {
+    pub window_secs: u64,
+    pub used: f64,
+    pub cap: f64,
+}
+
+/// Rolling record of outgoing value per address
+#[derive(Debug, Default)]
+pub struct VelocityTracker {
+    records: HashMap<String, VecDeque<(u64, f64)>>,
+    database: Option<WalletDatabase>,
+}
+
+impl VelocityTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Persist records to the database, loading those still inside the history window
+    pub fn with_database(mut self, database: WalletDatabase, now: u64) -> Self {
+        let cutoff = now.saturating_sub(HISTORY_SECS);
+        database.prune_velocity_records(cutoff);
+        for (address, timestamp, amount) in database.load_velocity_records(cutoff) {
+            self.records.entry(address).or_default().push_back((timestamp, amount));
+        }
+        self.database = Some(database);
+        self
+    }
+
+    pub fn record(&mut self, address: &str, amount: f64, now: u64) {
+        let address = address.to_lowercase();
+        if let Some(db) = &self.database {
+            db.save_velocity_record(&address, now, amount);
+        }
+        let entries = self.records.entry(address).or_default();
+        entries.push_back((now, amount));
+        let cutoff = now.saturating_sub(HISTORY_SECS);
+        while entries.front().is_some_and(|&(t, _)| t <= cutoff) {
+            entries.pop_front();
+        }
+    }
+
+    /// Total value sent in the `window_secs` seconds before `now`
+    pub fn outgoing_within(&self, address: &str, window_secs: u64, now: u64) -> f64 {
+        let cutoff = now.saturating_sub(window_secs);
+        self.records
+            .get(&address.to_lowercase())
+            .map(|entries| entries.iter().filter(|&&(t, _)| t > cutoff).map(|&(_, a)| a).sum())
+            .unwrap_or(0.0)
+    }
+
+    /// First cap that sending `amount` would exceed; caps of 0 are unlimited
+    pub fn check_caps(&self, address: &str, amount: f64, caps: &[(u64, f64)], now: u64) -> Result<(), VelocityUsage> {
+        for &(window_secs, cap) in caps {
+            if cap <= 0.0 {
+                continue;
+            }
+            let used = self.outgoing_within(address, window_secs, now);
+            if used + amount > cap {
+                return Err(VelocityUsage { window_secs, used, cap });
+            }
+        }
+        Ok(())
+    }
+
+    /// Average transaction value over the trailing history window, if any transactions exist
+    pub fn trailing_average(&self, address: &str, now: u64) -> Option<f64> {
+        let cutoff = now.saturating_sub(HISTORY_SECS);
+        let amounts: Vec<f64> = self
+            .records
+            .get(&address.to_lowercase())?
+            .iter()
+            .filter(|&&(t, _)| t > cutoff)
+            .map(|&(_, a)| a)
+            .collect();
+        if amounts.is_empty() {
+            return None;
+        }
+        Some(amounts.iter().sum::<f64>() / amounts.len() as f64)
+    }
+
+    /// True when `amount` is more than `multiplier` times the address's trailing average
+    pub fn is_anomalous(&self, address: &str, amount: f64, multiplier: f64, now: u64) -> bool {
+        if multiplier <= 0.0 {
+            return false;
+        }
+        self.trailing_average(address, now).is_some_and(|avg| amount > avg * multiplier)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_rolling_window_and_persistence() {
+        let dir = tempdir().unwrap();
+        let db_path = dir.path().join("wallets.db");
+        let mut tracker = VelocityTracker::new().with_database(WalletDatabase::new(Some(db_path.clone())), 0);
+        tracker.record("Alice", 10.0, 1_000);
+        tracker.record("alice", 5.0, 2_000);
+        assert_eq!(tracker.outgoing_within("alice", HOUR_SECS, 2_000), 15.0);
+        assert_eq!(tracker.outgoing_within("alice", HOUR_SECS, 4_600), 5.0);
+        let reloaded = VelocityTracker::new().with_database(WalletDatabase::new(Some(db_path)), 4_600);
+        assert_eq!(reloaded.outgoing_within("alice", DAY_SECS, 4_600), 15.0);
+    }
+}
@@ -0,0 +1,128 @@
+use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+use serde::{Deserialize, Deserializer};
+#[cfg(not(target_arch = "wasm32"))]
+use std::sync::OnceLock;
+#[cfg(not(target_arch = "wasm32"))]
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+/// Source of unix-second timestamps, injectable so time-based logic can be tested
+pub trait Clock: Send + Sync + fmt::Debug {
+    fn now(&self) -> u64;
+
+    /// Unix milliseconds; clocks that only count seconds report whole seconds
+    fn now_millis(&self) -> u64 {
+        self.now() * 1000
+    }
+
+    /// Unix seconds with the milliseconds as the fraction
+    fn now_secs_f64(&self) -> f64 {
+        self.now_millis() as f64 / 1000.0
+    }
+}
+
+/// Wall clock backed by SystemTime, or by JavaScript's `Date.now()` on wasm32
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> u64 {
+        self.now_millis() / 1000
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    fn now_millis(&self) -> u64 {
+        SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_millis() as u64
+    }
+
+    #[cfg(target_arch = "wasm32")]
+    fn now_millis(&self) -> u64 {
+        js_sys::Date::now() as u64
+    }
+}
+
+/// Time since the first call, for deadlines that must not move when the wall clock is changed.
+/// Native builds use `Instant`; wasm32 has no monotonic clock without web-sys, so there it is
+/// `Date.now()` held from ever running backwards.
+pub fn monotonic() -> Duration {
+    #[cfg(not(target_arch = "wasm32"))]
+    {
+        static ORIGIN: OnceLock<Instant> = OnceLock::new();
+        ORIGIN.get_or_init(Instant::now).elapsed()
+    }
+    #[cfg(target_arch = "wasm32")]
+    {
+        static ORIGIN: AtomicU64 = AtomicU64::new(0);
+        static LATEST: AtomicU64 = AtomicU64::new(0);
+        let now = js_sys::Date::now() as u64;
+        let origin = match ORIGIN.compare_exchange(0, now, Ordering::SeqCst, Ordering::SeqCst) {
+            Ok(_) => now,
+            Err(origin) => origin,
+        };
+        let latest = LATEST.fetch_max(now.saturating_sub(origin), Ordering::SeqCst).max(now.saturating_sub(origin));
+        Duration::from_millis(latest)
+    }
+}
+
+/// Unix seconds written as an integer, or as a float by older writers, with any fraction dropped.
+/// For `#[serde(deserialize_with = "clock::unix_seconds")]`.
+pub fn unix_seconds<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u64, D::Error> {
+    Ok(f64::deserialize(deserializer)?.max(0.0) as u64)
+}
+
+/// Manually advanced clock for tests and simulations
+#[derive(Debug, Default)]
+pub struct ManualClock {
+    now: AtomicU64,
+}
+
+impl ManualClock {
+    pub fn new(start: u64) -> Self {
+        ManualClock { now: AtomicU64::new(start) }
+    }
+
+    pub fn set(&self, now: u64) {
+        self.now.store(now, Ordering::SeqCst);
+    }
+
+    pub fn advance(&self, secs: u64) {
+        self.now.fetch_add(secs, Ordering::SeqCst);
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> u64 {
+        self.now.load(Ordering::SeqCst)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_manual_clock_advance() {
+        let clock = ManualClock::new(100);
+        assert_eq!(clock.now(), 100);
+        clock.advance(5);
+        assert_eq!(clock.now(), 105);
+        clock.set(1);
+        assert_eq!(clock.now(), 1);
+        assert_eq!(clock.now_millis(), 1000);
+        assert_eq!(clock.now_secs_f64(), 1.0);
+    }
+
+    #[test]
+    fn test_monotonic_never_goes_backwards() {
+        let first = monotonic();
+        std::thread::sleep(Duration::from_millis(2));
+        assert!(monotonic() > first);
+    }
+
+    #[test]
+    fn test_system_clock_is_recent() {
+        assert!(SystemClock.now() > 1_600_000_000);
+        assert!(SystemClock.now_millis() / 1000 >= SystemClock.now() - 1);
+    }
+}
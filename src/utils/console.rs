@@ -1,5 +1,11 @@
 use std::fmt;
-use std::io::{self, Write};
+use std::io::{self, IsTerminal, Write};
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
+use crate::utils::log::{fields, Level};
 
 pub enum ConsoleColor {
     Cyan,
@@ -11,7 +17,7 @@ pub enum ConsoleColor {
 }
 
 impl ConsoleColor {
-    fn to_ansi_code(&self) -> &'static str {
+    pub(crate) fn to_ansi_code(&self) -> &'static str {
         match self {
             ConsoleColor::Cyan => "\x1b[36m",
             ConsoleColor::Yellow => "\x1b[33m",
@@ -29,6 +35,60 @@ fn print_colored(msg: impl fmt::Display, color: ConsoleColor) {
     let _ = write!(handle, "{}{}{}\n", color.to_ansi_code(), msg, ConsoleColor::Reset.to_ansi_code());
 }
 
+/// Log subscriber that writes one line per record to stderr, colored by level.
+/// Install it with `utils::log::init`.
+pub struct ConsoleLogger {
+    color: bool,
+}
+
+impl ConsoleLogger {
+    /// Colors only when `color_enabled`
+    pub fn new() -> Self {
+        Self::with_color(color_enabled())
+    }
+
+    pub fn with_color(color: bool) -> Self {
+        ConsoleLogger { color }
+    }
+
+    fn format(&self, record: &log::Record) -> String {
+        let mut line = format!("{:<5} {}: {}", record.level(), record.target(), record.args());
+        for (key, value) in fields(record) {
+            line.push_str(&format!(" {}={}", key, value));
+        }
+        if !self.color {
+            return line;
+        }
+        let color = match record.level() {
+            Level::Error => ConsoleColor::Red,
+            Level::Warn => ConsoleColor::Yellow,
+            Level::Info => ConsoleColor::Cyan,
+            Level::Debug | Level::Trace => ConsoleColor::Magenta,
+        };
+        format!("{}{}{}", color.to_ansi_code(), line, ConsoleColor::Reset.to_ansi_code())
+    }
+}
+
+impl Default for ConsoleLogger {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl log::Log for ConsoleLogger {
+    fn enabled(&self, _metadata: &log::Metadata) -> bool {
+        true
+    }
+
+    fn log(&self, record: &log::Record) {
+        let _ = writeln!(io::stderr().lock(), "{}", self.format(record));
+    }
+
+    fn flush(&self) {
+        let _ = io::stderr().flush();
+    }
+}
+
 pub struct Console;
 
 impl Console {
@@ -38,6 +98,514 @@ impl Console {
     // TODO: Implement console utilities
 }
 
+/// Whether stderr, where logs and progress go, should be colored: it is a terminal and NO_COLOR is unset
+pub fn color_enabled() -> bool {
+    let no_color = std::env::var_os("NO_COLOR").is_some_and(|v| !v.is_empty());
+    !no_color && io::stderr().is_terminal()
+}
+
+const SPINNER_FRAMES: [char; 10] = ['⠋', '⠙', '⠹', '⠸', '⠼', '⠴', '⠦', '⠧', '⠇', '⠏'];
+const BAR_WIDTH: usize = 30;
+/// Minimum time between redraws on a terminal
+const TERMINAL_REDRAW: Duration = Duration::from_millis(100);
+/// Time between plain lines when stderr is not a terminal
+const PLAIN_INTERVAL: Duration = Duration::from_secs(5);
+/// Weight of the newest sample in the smoothed rate
+const RATE_SMOOTHING: f64 = 0.3;
+
+/// Terminal lines held by reporters, in screen order. Drawing happens with this
+/// locked, so concurrent reporters each keep their own line.
+static DRAW: Mutex<Vec<LineSlot>> = Mutex::new(Vec::new());
+static NEXT_REPORTER: AtomicU64 = AtomicU64::new(0);
+
+struct LineSlot {
+    id: u64,
+    finished: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ProgressMode {
+    /// Redrawn in place on its own line
+    Terminal,
+    /// A new line every `PLAIN_INTERVAL`, for logs and pipes
+    Plain,
+    Hidden,
+}
+
+/// Progress on stderr for long-running work: a bar with ETA when the total is known, a spinner otherwise.
+/// Degrades to periodic plain lines when stderr is not a terminal.
+pub struct ProgressReporter {
+    id: u64,
+    label: String,
+    total: Option<u64>,
+    /// Shown with a rate, e.g. `H` for `12.3k H/s`
+    unit: Option<String>,
+    mode: ProgressMode,
+    color: bool,
+    state: Mutex<ProgressState>,
+}
+
+struct ProgressState {
+    current: u64,
+    message: String,
+    started: Instant,
+    last_draw: Option<Instant>,
+    /// When `current` was last sampled for the rate, and its value then
+    last_sample: (Instant, u64),
+    rate: RateSmoother,
+    frame: usize,
+    finished: bool,
+}
+
+impl ProgressReporter {
+    /// A bar towards `total`
+    pub fn bar(label: &str, total: u64) -> Self {
+        Self::new(label, Some(total))
+    }
+
+    /// A spinner for work of unknown length
+    pub fn spinner(label: &str) -> Self {
+        Self::new(label, None)
+    }
+
+    fn new(label: &str, total: Option<u64>) -> Self {
+        let now = Instant::now();
+        ProgressReporter {
+            id: NEXT_REPORTER.fetch_add(1, Ordering::Relaxed),
+            label: label.to_string(),
+            total,
+            unit: None,
+            mode: if io::stderr().is_terminal() { ProgressMode::Terminal } else { ProgressMode::Plain },
+            color: color_enabled(),
+            state: Mutex::new(ProgressState {
+                current: 0,
+                message: String::new(),
+                started: now,
+                last_draw: None,
+                last_sample: (now, 0),
+                rate: RateSmoother::new(RATE_SMOOTHING),
+                frame: 0,
+                finished: false,
+            }),
+        }
+    }
+
+    pub fn with_unit(mut self, unit: &str) -> Self {
+        self.unit = Some(unit.to_string());
+        self
+    }
+
+    pub fn with_color(mut self, color: bool) -> Self {
+        self.color = color;
+        self
+    }
+
+    /// Draw nothing, e.g. for --quiet
+    pub fn hidden(mut self) -> Self {
+        self.mode = ProgressMode::Hidden;
+        self
+    }
+
+    /// Move to `current`; going backwards is ignored, since parallel workers report out of order
+    pub fn set(&self, current: u64) {
+        let mut state = self.state.lock().unwrap();
+        state.current = state.current.max(current);
+        self.draw(&mut state, false);
+    }
+
+    pub fn inc(&self, delta: u64) {
+        let mut state = self.state.lock().unwrap();
+        state.current += delta;
+        self.draw(&mut state, false);
+    }
+
+    /// Text shown after the counters
+    pub fn set_message(&self, message: &str) {
+        let mut state = self.state.lock().unwrap();
+        state.message = message.to_string();
+        self.draw(&mut state, false);
+    }
+
+    /// Redraw without progress, so a spinner keeps turning
+    pub fn tick(&self) {
+        let mut state = self.state.lock().unwrap();
+        self.draw(&mut state, false);
+    }
+
+    /// Tick while `work` runs on this thread
+    pub fn run<T>(&self, work: impl FnOnce() -> T) -> T {
+        let done = AtomicBool::new(false);
+        thread::scope(|scope| {
+            scope.spawn(|| {
+                while !done.load(Ordering::SeqCst) {
+                    self.tick();
+                    thread::sleep(TERMINAL_REDRAW);
+                }
+            });
+            let result = work();
+            done.store(true, Ordering::SeqCst);
+            result
+        })
+    }
+
+    /// Draw the final state, replacing the message with `message` if given, and release the line
+    pub fn finish(&self, message: Option<&str>) {
+        let mut state = self.state.lock().unwrap();
+        if state.finished {
+            return;
+        }
+        if let Some(message) = message {
+            state.message = message.to_string();
+        }
+        self.draw(&mut state, true);
+        state.finished = true;
+        if let Some(slot) = DRAW.lock().unwrap().iter_mut().find(|slot| slot.id == self.id) {
+            slot.finished = true;
+        }
+    }
+
+    fn draw(&self, state: &mut ProgressState, force: bool) {
+        let interval = match self.mode {
+            ProgressMode::Terminal => TERMINAL_REDRAW,
+            ProgressMode::Plain => PLAIN_INTERVAL,
+            ProgressMode::Hidden => return,
+        };
+        let now = Instant::now();
+        if state.finished || (!force && state.last_draw.is_some_and(|last| now - last < interval)) {
+            return;
+        }
+        let (sampled_at, sampled) = state.last_sample;
+        state.rate.update(state.current - sampled, (now - sampled_at).as_secs_f64());
+        state.last_sample = (now, state.current);
+        state.last_draw = Some(now);
+        state.frame = (state.frame + 1) % SPINNER_FRAMES.len();
+        let line = self.render(state, now);
+
+        let mut slots = DRAW.lock().unwrap();
+        let mut stderr = io::stderr().lock();
+        if self.mode == ProgressMode::Plain {
+            let _ = writeln!(stderr, "{}", line);
+            return;
+        }
+        let index = match slots.iter().position(|slot| slot.id == self.id) {
+            Some(index) => index,
+            None => {
+                // Once every line is finished, new reporters start below them
+                if slots.iter().all(|slot| slot.finished) {
+                    slots.clear();
+                }
+                slots.push(LineSlot { id: self.id, finished: false });
+                let _ = writeln!(stderr);
+                slots.len() - 1
+            }
+        };
+        // The cursor rests below all reporter lines; step up to ours, redraw it and step back
+        let up = slots.len() - index;
+        let _ = write!(stderr, "\x1b[{}A\r\x1b[2K{}\x1b[{}B\r", up, line, up);
+        let _ = stderr.flush();
+    }
+
+    fn render(&self, state: &ProgressState, now: Instant) -> String {
+        let elapsed = now - state.started;
+        let mut parts = Vec::new();
+        match self.total {
+            Some(total) => {
+                let bar = render_bar(state.current, total, BAR_WIDTH);
+                let bar = if self.color && self.mode == ProgressMode::Terminal {
+                    format!("{}{}{}", ConsoleColor::Cyan.to_ansi_code(), bar, ConsoleColor::Reset.to_ansi_code())
+                } else {
+                    bar
+                };
+                parts.push(format!("{} {} {}/{}", self.label, bar, state.current, total));
+            }
+            None => {
+                let mut spinner = self.label.clone();
+                if self.mode == ProgressMode::Terminal {
+                    spinner = format!("{} {}", SPINNER_FRAMES[state.frame], spinner);
+                }
+                // Without a unit there is nothing being counted
+                if self.unit.is_some() {
+                    spinner = format!("{} {}", spinner, state.current);
+                }
+                parts.push(spinner);
+            }
+        }
+        if let Some(unit) = &self.unit {
+            parts.push(format_rate(state.rate.rate(), unit));
+        }
+        parts.push(format_duration(elapsed.as_secs()));
+        // The average over the whole run gives a steadier ETA than the smoothed rate
+        let average = state.current as f64 / elapsed.as_secs_f64();
+        if let Some(total) = self.total
+            && !state.finished
+            && let Some(eta) = eta_secs(state.current, total, average)
+        {
+            parts.push(format!("ETA {}", format_duration(eta)));
+        }
+        if !state.message.is_empty() {
+            parts.push(state.message.clone());
+        }
+        parts.join(" | ")
+    }
+}
+
+/// Exponential moving average of a per-second rate
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RateSmoother {
+    alpha: f64,
+    rate: Option<f64>,
+}
+
+impl RateSmoother {
+    /// `alpha` in (0, 1] is the weight of each new sample
+    pub fn new(alpha: f64) -> Self {
+        RateSmoother { alpha: alpha.clamp(f64::EPSILON, 1.0), rate: None }
+    }
+
+    /// Fold in `delta` units done over `secs`; samples over no time are skipped
+    pub fn update(&mut self, delta: u64, secs: f64) -> f64 {
+        if secs > 0.0 {
+            let sample = delta as f64 / secs;
+            self.rate = Some(match self.rate {
+                Some(rate) => self.alpha * sample + (1.0 - self.alpha) * rate,
+                None => sample,
+            });
+        }
+        self.rate()
+    }
+
+    pub fn rate(&self) -> f64 {
+        self.rate.unwrap_or(0.0)
+    }
+}
+
+/// Seconds left to reach `total` at `rate` per second, if progress is being made
+fn eta_secs(current: u64, total: u64, rate: f64) -> Option<u64> {
+    if current >= total {
+        return Some(0);
+    }
+    (rate > 0.0).then(|| ((total - current) as f64 / rate).ceil() as u64)
+}
+
+fn render_bar(current: u64, total: u64, width: usize) -> String {
+    let filled = if total == 0 { width } else { ((current.min(total) as f64 / total as f64) * width as f64) as usize };
+    format!("[{}{}]", "█".repeat(filled), "░".repeat(width - filled))
+}
+
+/// `45s`, `3m07s` or `2h05m`
+fn format_duration(secs: u64) -> String {
+    match secs {
+        0..60 => format!("{}s", secs),
+        60..3600 => format!("{}m{:02}s", secs / 60, secs % 60),
+        _ => format!("{}h{:02}m", secs / 3600, secs % 3600 / 60),
+    }
+}
+
+/// `950 H/s`, `12.3k H/s` or `4.56M H/s`
+fn format_rate(rate: f64, unit: &str) -> String {
+    match rate {
+        r if r >= 1e9 => format!("{:.2}G {}/s", r / 1e9, unit),
+        r if r >= 1e6 => format!("{:.2}M {}/s", r / 1e6, unit),
+        r if r >= 1e3 => format!("{:.1}k {}/s", r / 1e3, unit),
+        r => format!("{:.0} {}/s", r, unit),
+    }
+}
+
+/// Widest a table column grows before its cells are truncated
+const TABLE_MAX_WIDTH: usize = 64;
+const TABLE_GAP: &str = "  ";
+
+/// How a `Table` column lines up its cells
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Align {
+    Left,
+    Right,
+}
+
+struct Column {
+    header: String,
+    align: Align,
+    max_width: Option<usize>,
+}
+
+/// Rows of text in columns sized to their content, measured in terminal cells
+pub struct Table {
+    columns: Vec<Column>,
+    rows: Vec<Vec<String>>,
+    max_width: usize,
+    separator: bool,
+    color: bool,
+}
+
+impl Table {
+    pub fn new() -> Self {
+        Table { columns: Vec::new(), rows: Vec::new(), max_width: TABLE_MAX_WIDTH, separator: false, color: false }
+    }
+
+    /// Truncate cells wider than `max_width` in every column
+    pub fn with_max_width(mut self, max_width: usize) -> Self {
+        self.max_width = max_width;
+        self
+    }
+
+    /// Draw a line under the header
+    pub fn with_separator(mut self, separator: bool) -> Self {
+        self.separator = separator;
+        self
+    }
+
+    /// Highlight the header; off by default so output is plain text
+    pub fn with_color(mut self, color: bool) -> Self {
+        self.color = color;
+        self
+    }
+
+    pub fn add_column(&mut self, header: &str, align: Align) -> &mut Self {
+        self.columns.push(Column { header: header.to_string(), align, max_width: None });
+        self
+    }
+
+    /// A column whose cells are cut to `max_width` rather than the table's limit
+    pub fn add_column_with_width(&mut self, header: &str, align: Align, max_width: usize) -> &mut Self {
+        self.columns.push(Column { header: header.to_string(), align, max_width: Some(max_width) });
+        self
+    }
+
+    /// Missing cells are left blank and cells beyond the last column are dropped
+    pub fn add_row<I, S>(&mut self, cells: I) -> &mut Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        let mut row: Vec<String> = cells.into_iter().take(self.columns.len()).map(Into::into).collect();
+        row.resize(self.columns.len(), String::new());
+        self.rows.push(row);
+        self
+    }
+
+    pub fn len(&self) -> usize {
+        self.rows.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rows.is_empty()
+    }
+
+    pub fn render(&self) -> String {
+        let rows: Vec<Vec<String>> = self
+            .rows
+            .iter()
+            .map(|row| row.iter().zip(&self.columns).map(|(cell, column)| truncate(cell, column.max_width.unwrap_or(self.max_width))).collect())
+            .collect();
+        let widths: Vec<usize> = self
+            .columns
+            .iter()
+            .enumerate()
+            .map(|(i, column)| rows.iter().map(|row| display_width(&row[i])).fold(display_width(&column.header), usize::max))
+            .collect();
+        let line = |cells: Vec<&str>| {
+            let padded: Vec<String> = cells.iter().zip(&self.columns).zip(&widths).map(|((cell, column), width)| pad(cell, *width, column.align)).collect();
+            padded.join(TABLE_GAP).trim_end().to_string()
+        };
+
+        let header = line(self.columns.iter().map(|c| c.header.as_str()).collect());
+        let mut lines = vec![if self.color { format!("{}{}{}", ConsoleColor::Cyan.to_ansi_code(), header, ConsoleColor::Reset.to_ansi_code()) } else { header }];
+        if self.separator {
+            lines.push(widths.iter().map(|w| "─".repeat(*w)).collect::<Vec<_>>().join(TABLE_GAP));
+        }
+        lines.extend(rows.iter().map(|row| line(row.iter().map(String::as_str).collect())));
+        lines.join("\n")
+    }
+}
+
+impl Default for Table {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl fmt::Display for Table {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.render())
+    }
+}
+
+/// `Key: value` lines with the values lined up, for showing one item in detail
+#[derive(Default)]
+pub struct KeyValueBlock {
+    entries: Vec<(String, String)>,
+    color: bool,
+}
+
+impl KeyValueBlock {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Highlight the keys; off by default so output is plain text
+    pub fn with_color(mut self, color: bool) -> Self {
+        self.color = color;
+        self
+    }
+
+    pub fn add(&mut self, key: &str, value: impl fmt::Display) -> &mut Self {
+        self.entries.push((format!("{}:", key), value.to_string()));
+        self
+    }
+
+    pub fn render(&self) -> String {
+        let width = self.entries.iter().map(|(key, _)| display_width(key)).max().unwrap_or(0);
+        self.entries
+            .iter()
+            .map(|(key, value)| {
+                let key = pad(key, width, Align::Left);
+                let key = if self.color { format!("{}{}{}", ConsoleColor::Cyan.to_ansi_code(), key, ConsoleColor::Reset.to_ansi_code()) } else { key };
+                format!("{} {}", key, value).trim_end().to_string()
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+}
+
+impl fmt::Display for KeyValueBlock {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.render())
+    }
+}
+
+/// Terminal cells `text` takes up; CJK and other wide characters count twice
+fn display_width(text: &str) -> usize {
+    UnicodeWidthStr::width(text)
+}
+
+/// `text` cut to at most `max` cells, ending in an ellipsis when shortened
+fn truncate(text: &str, max: usize) -> String {
+    if display_width(text) <= max {
+        return text.to_string();
+    }
+    if max == 0 {
+        return String::new();
+    }
+    let mut width = 0;
+    let mut truncated: String = text
+        .chars()
+        .take_while(|c| {
+            width += UnicodeWidthChar::width(*c).unwrap_or(0);
+            width < max
+        })
+        .collect();
+    truncated.push('…');
+    truncated
+}
+
+fn pad(text: &str, width: usize, align: Align) -> String {
+    let fill = " ".repeat(width.saturating_sub(display_width(text)));
+    match align {
+        Align::Left => format!("{}{}", text, fill),
+        Align::Right => format!("{}{}", fill, text),
+    }
+}
+
 pub fn print_info(msg: impl fmt::Display) {
     print_colored(msg, ConsoleColor::Cyan);
 }
@@ -62,6 +630,112 @@ pub fn print_debug(msg: impl fmt::Display) {
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_console_logger_format() {
+        let record = log::Record::builder()
+            .level(Level::Warn)
+            .target("lunalib::core::daemon")
+            .args(format_args!("Block sync failed"))
+            .key_values(&[("height", 7)])
+            .build();
+        assert_eq!(ConsoleLogger::with_color(false).format(&record), "WARN  lunalib::core::daemon: Block sync failed height=7");
+        assert!(ConsoleLogger::with_color(true).format(&record).starts_with("\x1b[33mWARN"));
+    }
+
+    #[test]
+    fn test_eta_and_bar() {
+        assert_eq!(eta_secs(25, 100, 5.0), Some(15));
+        assert_eq!(eta_secs(99, 100, 3.0), Some(1));
+        assert_eq!(eta_secs(100, 100, 0.0), Some(0));
+        assert_eq!(eta_secs(10, 100, 0.0), None);
+        assert_eq!(render_bar(5, 10, 10), "[█████░░░░░]");
+        assert_eq!(render_bar(15, 10, 4), "[████]");
+        assert_eq!(render_bar(0, 0, 2), "[██]");
+    }
+
+    #[test]
+    fn test_rate_smoothing() {
+        let mut smoother = RateSmoother::new(0.5);
+        assert_eq!(smoother.rate(), 0.0);
+        assert_eq!(smoother.update(100, 1.0), 100.0);
+        assert_eq!(smoother.update(300, 1.0), 200.0);
+        assert_eq!(smoother.update(50, 0.0), 200.0);
+        assert_eq!(smoother.update(100, 2.0), 125.0);
+    }
+
+    #[test]
+    fn test_format_duration_and_rate() {
+        assert_eq!(format_duration(45), "45s");
+        assert_eq!(format_duration(187), "3m07s");
+        assert_eq!(format_duration(7500), "2h05m");
+        assert_eq!(format_rate(950.4, "H"), "950 H/s");
+        assert_eq!(format_rate(12_345.0, "H"), "12.3k H/s");
+        assert_eq!(format_rate(4_561_000.0, "H"), "4.56M H/s");
+    }
+
+    #[test]
+    fn test_render_plain_lines() {
+        let reporter = ProgressReporter::bar("Benchmark", 10).with_unit("H").with_color(true).hidden();
+        let now = Instant::now();
+        let mut state = reporter.state.lock().unwrap();
+        state.started = now - Duration::from_secs(4);
+        state.current = 4;
+        state.rate.update(4, 4.0);
+        state.message = "warming up".to_string();
+        // Color is only used on a terminal
+        assert_eq!(
+            reporter.render(&state, now),
+            "Benchmark [████████████░░░░░░░░░░░░░░░░░░] 4/10 | 1 H/s | 4s | ETA 6s | warming up"
+        );
+        drop(state);
+        let spinner = ProgressReporter::spinner("Mining").with_unit("H").hidden();
+        assert_eq!(spinner.render(&spinner.state.lock().unwrap(), Instant::now()), "Mining 0 | 0 H/s | 0s");
+        let spinner = ProgressReporter::spinner("Syncing").hidden();
+        assert_eq!(spinner.render(&spinner.state.lock().unwrap(), Instant::now()), "Syncing | 0s");
+    }
+
+    fn sample_table() -> Table {
+        let mut table = Table::new();
+        table.add_column("NAME", Align::Left).add_column("AMOUNT", Align::Right).add_column("MEMO", Align::Left);
+        table.add_row(["alice", "1.5", "rent"]);
+        table.add_row(["王小明", "20", "午饭"]);
+        table
+    }
+
+    #[test]
+    fn test_table_golden() {
+        let expected = "NAME    AMOUNT  MEMO\n\
+                        alice      1.5  rent\n\
+                        王小明      20  午饭";
+        assert_eq!(sample_table().render(), expected);
+
+        let separated = sample_table().with_separator(true).render();
+        assert_eq!(separated.lines().nth(1), Some("──────  ──────  ────"));
+        assert_eq!(sample_table().with_color(true).render().lines().next(), Some("\x1b[36mNAME    AMOUNT  MEMO\x1b[0m"));
+    }
+
+    #[test]
+    fn test_table_truncates_by_display_width() {
+        let mut table = Table::new().with_max_width(5);
+        table.add_column("ID", Align::Left).add_column_with_width("NOTE", Align::Left, 8);
+        table.add_row(["王小明王小明", "a very long note"]);
+        table.add_row(["ab", "short"]);
+        table.add_row(["only one cell"]);
+        assert_eq!(table.render(), "ID     NOTE\n王小…  a very …\nab     short\nonly…");
+        assert_eq!(truncate("王小明", 4), "王…");
+        assert_eq!(truncate("abc", 0), "");
+    }
+
+    #[test]
+    fn test_key_value_block_golden() {
+        let mut block = KeyValueBlock::new();
+        block.add("Address", "LUN_abc").add("名前", "王小明").add("Memo", "");
+        assert_eq!(block.render(), "Address: LUN_abc\n名前:    王小明\nMemo:");
+        let mut colored = KeyValueBlock::new().with_color(true);
+        colored.add("Fee", 0.5);
+        assert_eq!(colored.render(), "\x1b[36mFee:\x1b[0m 0.5");
+    }
+
     #[test]
     fn test_print_info() {
         print_info("Info message");
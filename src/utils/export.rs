@@ -0,0 +1,36 @@
+use std::borrow::Cow;
+use std::io::{self, Write};
+
+/// Write a header line and `rows` as CSV, quoting fields that need it.
+/// Returns the number of rows written.
+pub fn write_csv<W: Write + ?Sized>(writer: &mut W, headers: &[&str], rows: impl IntoIterator<Item = Vec<String>>) -> io::Result<usize> {
+    writeln!(writer, "{}", headers.iter().map(|h| csv_field(h)).collect::<Vec<_>>().join(","))?;
+    let mut count = 0;
+    for row in rows {
+        writeln!(writer, "{}", row.iter().map(|f| csv_field(f)).collect::<Vec<_>>().join(","))?;
+        count += 1;
+    }
+    Ok(count)
+}
+
+/// Quote a field containing a comma, quote or line break, doubling inner quotes
+pub fn csv_field(field: &str) -> Cow<'_, str> {
+    if field.contains([',', '"', '\n', '\r']) {
+        Cow::Owned(format!("\"{}\"", field.replace('"', "\"\"")))
+    } else {
+        Cow::Borrowed(field)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_write_csv_quotes_fields() {
+        let mut out = Vec::new();
+        let rows = vec![vec!["a".to_string(), "b,c".to_string()], vec!["say \"hi\"".to_string(), "".to_string()]];
+        assert_eq!(write_csv(&mut out, &["first", "second"], rows).unwrap(), 2);
+        assert_eq!(String::from_utf8(out).unwrap(), "first,second\na,\"b,c\"\n\"say \"\"hi\"\"\",\n");
+    }
+}
@@ -0,0 +1,223 @@
+use std::time::Duration;
+
+/// Units `parse_duration` accepts, with their length in milliseconds
+const DURATION_UNITS: [(&str, u64); 6] = [("ms", 1), ("s", 1000), ("m", 60_000), ("h", 3_600_000), ("d", 86_400_000), ("w", 604_800_000)];
+
+/// Units `human_duration` writes, largest first, in seconds
+const HUMAN_UNITS: [(&str, u64); 4] = [("d", 86_400), ("h", 3600), ("m", 60), ("s", 1)];
+
+const BYTE_UNITS: [&str; 6] = ["KiB", "MiB", "GiB", "TiB", "PiB", "EiB"];
+
+/// The two largest non-zero units of `duration`, such as `2m 34s` or `3d 4h`.
+/// Durations under a second are given in milliseconds.
+pub fn human_duration(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    if secs == 0 {
+        return if duration.is_zero() { "0s".to_string() } else { format!("{}ms", duration.as_millis()) };
+    }
+    let largest = HUMAN_UNITS.iter().position(|(_, size)| secs >= *size).unwrap_or(HUMAN_UNITS.len() - 1);
+    let (unit, size) = HUMAN_UNITS[largest];
+    let mut text = format!("{}{}", secs / size, unit);
+    if let Some((next_unit, next_size)) = HUMAN_UNITS.get(largest + 1) {
+        let rest = secs % size / next_size;
+        if rest > 0 {
+            text.push_str(&format!(" {}{}", rest, next_unit));
+        }
+    }
+    text
+}
+
+/// Read a duration such as `30s`, `10m`, `1h30m` or `2m 34s`; units are ms, s, m, h, d and w
+pub fn parse_duration(text: &str) -> Result<Duration, String> {
+    let invalid = || format!("Invalid duration '{}': expected a number and unit such as 30s, 10m or 1h30m", text);
+    let mut rest = text.trim();
+    if rest.is_empty() {
+        return Err(invalid());
+    }
+    let too_long = || format!("Duration '{}' is too long", text);
+    // Wide enough that any u64 count of weeks fits
+    let mut millis: u128 = 0;
+    while !rest.is_empty() {
+        let digits = rest.find(|c: char| !c.is_ascii_digit()).unwrap_or(rest.len());
+        let letters = rest[digits..].find(|c: char| !c.is_ascii_alphabetic()).map_or(rest.len(), |i| digits + i);
+        let count: u64 = rest[..digits].parse().map_err(|_| invalid())?;
+        let size = DURATION_UNITS.iter().find(|(unit, _)| *unit == &rest[digits..letters]).map(|(_, size)| *size).ok_or_else(invalid)?;
+        millis = millis.checked_add(u128::from(count) * u128::from(size)).ok_or_else(too_long)?;
+        rest = rest[letters..].trim_start();
+    }
+    let secs = u64::try_from(millis / 1000).map_err(|_| too_long())?;
+    Ok(Duration::new(secs, (millis % 1000) as u32 * 1_000_000))
+}
+
+/// "just now", "1 minute ago", "3 hours ago", "2 days ago", or the date once older than 30 days.
+/// Timestamps ahead of `now` count as just now.
+pub fn relative_time(timestamp: u64, now: u64) -> String {
+    let age = now.saturating_sub(timestamp);
+    let ago = |count: u64, unit: &str| format!("{} {}{} ago", count, unit, if count == 1 { "" } else { "s" });
+    match age {
+        0..60 => "just now".to_string(),
+        60..3600 => ago(age / 60, "minute"),
+        3600..86_400 => ago(age / 3600, "hour"),
+        86_400..2_592_000 => ago(age / 86_400, "day"),
+        _ => chrono::DateTime::from_timestamp(timestamp as i64, 0).map(|t| t.format("%Y-%m-%d").to_string()).unwrap_or_default(),
+    }
+}
+
+/// How `human_amount` writes a number of LUN
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AmountFormat {
+    /// Most decimal places shown; trailing zeros are dropped
+    pub precision: usize,
+    /// Put between groups of three integer digits
+    pub separator: Option<char>,
+}
+
+impl Default for AmountFormat {
+    fn default() -> Self {
+        AmountFormat { precision: 8, separator: Some(',') }
+    }
+}
+
+impl AmountFormat {
+    pub fn with_precision(mut self, precision: usize) -> Self {
+        self.precision = precision;
+        self
+    }
+
+    pub fn with_separator(mut self, separator: Option<char>) -> Self {
+        self.separator = separator;
+        self
+    }
+
+    /// `1234.5` as `1,234.5`
+    pub fn format(&self, amount: f64) -> String {
+        if !amount.is_finite() {
+            return amount.to_string();
+        }
+        let fixed = format!("{:.*}", self.precision, amount.abs());
+        let (integer, fraction) = fixed.split_once('.').unwrap_or((&fixed, ""));
+        let fraction = fraction.trim_end_matches('0');
+        let mut text = String::new();
+        for (i, digit) in integer.chars().enumerate() {
+            if i > 0 && (integer.len() - i) % 3 == 0
+                && let Some(separator) = self.separator
+            {
+                text.push(separator);
+            }
+            text.push(digit);
+        }
+        if !fraction.is_empty() {
+            text.push('.');
+            text.push_str(fraction);
+        }
+        // Rounding can leave nothing but zeros, which get no sign
+        if amount < 0.0 && text.chars().any(|c| c.is_ascii_digit() && c != '0') {
+            text.insert(0, '-');
+        }
+        text
+    }
+}
+
+/// `amount` with up to 8 decimals and thousands separated by commas
+pub fn human_amount(amount: f64) -> String {
+    AmountFormat::default().format(amount)
+}
+
+/// `512 B`, `1.5 KiB` or `10.0 MiB`
+pub fn human_bytes(bytes: u64) -> String {
+    if bytes < 1024 {
+        return format!("{} B", bytes);
+    }
+    let mut value = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < BYTE_UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", value, BYTE_UNITS[unit])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    #[test]
+    fn test_human_duration() {
+        assert_eq!(human_duration(Duration::ZERO), "0s");
+        assert_eq!(human_duration(Duration::from_millis(250)), "250ms");
+        assert_eq!(human_duration(Duration::from_secs(45)), "45s");
+        assert_eq!(human_duration(Duration::from_secs(154)), "2m 34s");
+        assert_eq!(human_duration(Duration::from_secs(3 * 86_400 + 4 * 3600 + 59)), "3d 4h");
+        assert_eq!(human_duration(Duration::from_secs(7200)), "2h");
+        assert_eq!(human_duration(Duration::from_secs(u64::MAX)), "213503982334601d 7h");
+    }
+
+    #[test]
+    fn test_parse_duration() {
+        assert_eq!(parse_duration("30s"), Ok(Duration::from_secs(30)));
+        assert_eq!(parse_duration("10m"), Ok(Duration::from_secs(600)));
+        assert_eq!(parse_duration(" 1h30m "), Ok(Duration::from_secs(5400)));
+        assert_eq!(parse_duration("2m 34s"), Ok(Duration::from_secs(154)));
+        assert_eq!(parse_duration("1w"), Ok(Duration::from_secs(604_800)));
+        assert_eq!(parse_duration("0s"), Ok(Duration::ZERO));
+        assert_eq!(parse_duration("500ms"), Ok(Duration::from_millis(500)));
+        for bad in ["", "5", "s", "5x", "-5s", "1.5h", "5 s"] {
+            assert!(parse_duration(bad).unwrap_err().contains("Invalid duration"), "{}", bad);
+        }
+        assert!(parse_duration("99999999999999999w").unwrap_err().contains("too long"));
+    }
+
+    #[test]
+    fn test_relative_time() {
+        let now = 1_700_000_000;
+        assert_eq!(relative_time(now - 5, now), "just now");
+        assert_eq!(relative_time(now + 500, now), "just now");
+        assert_eq!(relative_time(now - 60, now), "1 minute ago");
+        assert_eq!(relative_time(now - 300, now), "5 minutes ago");
+        assert_eq!(relative_time(now - 3 * 3600, now), "3 hours ago");
+        assert_eq!(relative_time(now - 86_400, now), "1 day ago");
+        assert_eq!(relative_time(now - 40 * 86_400, now), "2023-10-05");
+    }
+
+    #[test]
+    fn test_human_amount() {
+        assert_eq!(human_amount(0.0), "0");
+        assert_eq!(human_amount(1.5), "1.5");
+        assert_eq!(human_amount(1_234_567.0), "1,234,567");
+        assert_eq!(human_amount(-1234.001), "-1,234.001");
+        assert_eq!(human_amount(0.000_123_456_789), "0.00012346");
+        assert_eq!(human_amount(-0.000_000_001), "0");
+        assert_eq!(AmountFormat::default().with_precision(2).with_separator(None).format(9_876_543.219), "9876543.22");
+        assert_eq!(AmountFormat::default().with_separator(Some('_')).format(100_000.0), "100_000");
+        assert_eq!(human_amount(f64::NAN), "NaN");
+    }
+
+    #[test]
+    fn test_human_bytes() {
+        assert_eq!(human_bytes(0), "0 B");
+        assert_eq!(human_bytes(1023), "1023 B");
+        assert_eq!(human_bytes(1536), "1.5 KiB");
+        assert_eq!(human_bytes(10 * 1024 * 1024), "10.0 MiB");
+        assert_eq!(human_bytes(u64::MAX), "16.0 EiB");
+    }
+
+    proptest! {
+        #[test]
+        fn prop_human_duration_parses_back(secs in any::<u64>()) {
+            let duration = Duration::from_secs(secs);
+            let text = human_duration(duration);
+            let parsed = parse_duration(&text).unwrap();
+            // Only units below the second one shown are dropped, so less than an hour is lost
+            prop_assert!(parsed <= duration);
+            prop_assert!(duration - parsed < Duration::from_secs(3600), "{} -> {:?}", text, parsed);
+            prop_assert_eq!(human_duration(parsed), text);
+        }
+
+        #[test]
+        fn prop_parse_duration_sums_units(hours in 0u64..100_000, minutes in 0u64..60, secs in 0u64..60) {
+            let parsed = parse_duration(&format!("{}h {}m{}s", hours, minutes, secs)).unwrap();
+            prop_assert_eq!(parsed, Duration::from_secs(hours * 3600 + minutes * 60 + secs));
+        }
+    }
+}
@@ -0,0 +1,226 @@
+//! Logging facade. Library code logs through the re-exported `log` macros, with
+//! key-values such as `address` or `tx_hash` for context, and never prints; an
+//! embedder installs a subscriber with `init`, e.g. `ConsoleLogger` or `MemoryLogger`.
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+use ::log::kv::{self, VisitSource};
+use ::log::{Log, Metadata, Record};
+
+pub use ::log::{debug, error, info, trace, warn, Level, LevelFilter};
+
+/// Environment variable read by `LogFilter::from_env`
+pub const LOG_ENV: &str = "LUNA_LOG";
+
+/// Which records pass, from an env-filter style spec such as `warn,lunalib::core::daemon=debug`:
+/// a bare level sets the default, `target=level` overrides it for a module and its children.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LogFilter {
+    default: LevelFilter,
+    directives: Vec<(String, LevelFilter)>,
+}
+
+impl LogFilter {
+    pub fn new(default: LevelFilter) -> Self {
+        LogFilter { default, directives: Vec::new() }
+    }
+
+    pub fn parse(spec: &str) -> Result<Self, String> {
+        let mut filter = LogFilter::new(LevelFilter::Info);
+        for directive in spec.split(',').map(str::trim).filter(|d| !d.is_empty()) {
+            let parse_level = |level: &str| level.parse::<LevelFilter>().map_err(|_| format!("Invalid log level '{}' in '{}'", level, spec));
+            match directive.split_once('=') {
+                Some((target, level)) => filter.directives.push((target.trim().to_string(), parse_level(level.trim())?)),
+                None => filter.default = parse_level(directive)?,
+            }
+        }
+        // Longest targets first, so the most specific directive wins
+        filter.directives.sort_by_key(|(target, _)| std::cmp::Reverse(target.len()));
+        Ok(filter)
+    }
+
+    /// The spec in `LUNA_LOG`, or `default` when it is unset or invalid
+    pub fn from_env(default: LevelFilter) -> Self {
+        std::env::var(LOG_ENV)
+            .ok()
+            .and_then(|spec| LogFilter::parse(&spec).ok())
+            .unwrap_or_else(|| LogFilter::new(default))
+    }
+
+    pub fn enabled(&self, target: &str, level: Level) -> bool {
+        let allowed = self
+            .directives
+            .iter()
+            .find(|(prefix, _)| target == prefix || target.strip_prefix(prefix.as_str()).is_some_and(|rest| rest.starts_with("::")))
+            .map_or(self.default, |(_, level)| *level);
+        level <= allowed
+    }
+
+    /// The most verbose level any directive lets through
+    pub fn max_level(&self) -> LevelFilter {
+        self.directives.iter().map(|(_, level)| *level).fold(self.default, Ord::max)
+    }
+}
+
+/// Install `logger` as the process-wide subscriber behind `filter`.
+/// Fails if a subscriber was already installed.
+pub fn init(logger: impl Log + 'static, filter: LogFilter) -> Result<(), String> {
+    let max_level = filter.max_level();
+    ::log::set_boxed_logger(Box::new(Filtered { inner: logger, filter })).map_err(|e| e.to_string())?;
+    ::log::set_max_level(max_level);
+    Ok(())
+}
+
+struct Filtered<L> {
+    inner: L,
+    filter: LogFilter,
+}
+
+impl<L: Log> Log for Filtered<L> {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        self.filter.enabled(metadata.target(), metadata.level()) && self.inner.enabled(metadata)
+    }
+
+    fn log(&self, record: &Record) {
+        if self.enabled(record.metadata()) {
+            self.inner.log(record);
+        }
+    }
+
+    fn flush(&self) {
+        self.inner.flush();
+    }
+}
+
+/// The key-values attached to `record`, rendered with Display
+pub fn fields(record: &Record) -> BTreeMap<String, String> {
+    struct Collect(BTreeMap<String, String>);
+    impl<'kvs> VisitSource<'kvs> for Collect {
+        fn visit_pair(&mut self, key: kv::Key<'kvs>, value: kv::Value<'kvs>) -> Result<(), kv::Error> {
+            self.0.insert(key.to_string(), value.to_string());
+            Ok(())
+        }
+    }
+    let mut collect = Collect(BTreeMap::new());
+    let _ = record.key_values().visit(&mut collect);
+    collect.0
+}
+
+/// A record as `MemoryLogger` keeps it
+#[derive(Debug, Clone, PartialEq)]
+pub struct CapturedRecord {
+    pub level: Level,
+    /// Module path of the call site, e.g. `lunalib::core::wallet_manager`
+    pub target: String,
+    pub message: String,
+    pub fields: BTreeMap<String, String>,
+}
+
+/// Keeps every record in memory, for embedders that show logs themselves and for tests
+#[derive(Debug, Clone, Default)]
+pub struct MemoryLogger {
+    records: Arc<Mutex<Vec<CapturedRecord>>>,
+}
+
+impl MemoryLogger {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn records(&self) -> Vec<CapturedRecord> {
+        self.records.lock().unwrap().clone()
+    }
+
+    pub fn clear(&self) {
+        self.records.lock().unwrap().clear();
+    }
+}
+
+impl Log for MemoryLogger {
+    fn enabled(&self, _metadata: &Metadata) -> bool {
+        true
+    }
+
+    fn log(&self, record: &Record) {
+        self.records.lock().unwrap().push(CapturedRecord {
+            level: record.level(),
+            target: record.target().to_string(),
+            message: record.args().to_string(),
+            fields: fields(record),
+        });
+    }
+
+    fn flush(&self) {}
+}
+
+/// One `MemoryLogger` for the whole test binary, capturing every level.
+/// Tests run in parallel, so filter its records by something unique to the test.
+#[cfg(test)]
+pub(crate) fn test_logger() -> &'static MemoryLogger {
+    static LOGGER: std::sync::OnceLock<MemoryLogger> = std::sync::OnceLock::new();
+    LOGGER.get_or_init(|| {
+        let logger = MemoryLogger::new();
+        init(logger.clone(), LogFilter::new(LevelFilter::Trace)).expect("no other logger in tests");
+        logger
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::path::Path;
+
+    #[test]
+    fn test_filter_directives() {
+        let filter = LogFilter::parse("warn, lunalib::core=info ,lunalib::core::daemon=trace").unwrap();
+        assert!(filter.enabled("lunalib::mining::miner", Level::Warn));
+        assert!(!filter.enabled("lunalib::mining::miner", Level::Info));
+        assert!(filter.enabled("lunalib::core::blockchain", Level::Info));
+        assert!(!filter.enabled("lunalib::core::blockchain", Level::Debug));
+        assert!(filter.enabled("lunalib::core::daemon", Level::Trace));
+        // A prefix only matches whole path segments
+        assert!(!filter.enabled("lunalib::core::daemon_health", Level::Trace));
+        assert_eq!(filter.max_level(), LevelFilter::Trace);
+        assert!(LogFilter::parse("lunalib=loud").is_err());
+    }
+
+    #[test]
+    fn test_captures_fields() {
+        let logger = test_logger();
+        info!(address = "LUN_fields_test", count = 3; "Synced");
+        let record = logger.records().into_iter().find(|r| r.fields.get("address").is_some_and(|a| a == "LUN_fields_test")).unwrap();
+        assert_eq!(record.level, Level::Info);
+        assert_eq!(record.target, "lunalib::utils::log::tests");
+        assert_eq!(record.message, "Synced");
+        assert_eq!(record.fields["count"], "3");
+    }
+
+    /// Only the CLI, whose stdout is its output, and the console's own printers may write there
+    #[test]
+    fn test_no_direct_stdout_writes() {
+        fn visit(dir: &Path, offenders: &mut Vec<String>) {
+            for entry in fs::read_dir(dir).unwrap() {
+                let path = entry.unwrap().path();
+                if path.is_dir() {
+                    visit(&path, offenders);
+                    continue;
+                }
+                if path.ends_with("cli.rs") || path.ends_with("console.rs") {
+                    continue;
+                }
+                // Spelled in pieces so this test does not match itself
+                let needles = [concat!("print", "ln!("), concat!("print", "!("), concat!("io::std", "out()")];
+                let text = fs::read_to_string(&path).unwrap();
+                for (n, line) in text.lines().enumerate() {
+                    let code = line.trim_start();
+                    if !code.starts_with("//") && needles.iter().any(|needle| code.contains(needle)) {
+                        offenders.push(format!("{}:{}", path.display(), n + 1));
+                    }
+                }
+            }
+        }
+        let mut offenders = Vec::new();
+        visit(&Path::new(env!("CARGO_MANIFEST_DIR")).join("src"), &mut offenders);
+        assert!(offenders.is_empty(), "print through utils::log instead: {:?}", offenders);
+    }
+}
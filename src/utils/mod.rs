@@ -1 +1,9 @@
+#[cfg(not(target_arch = "wasm32"))]
 pub mod console;
+pub mod clock;
+pub mod export;
+pub mod format;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod uri;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod log;
@@ -0,0 +1,288 @@
+//! `luna:` payment URIs, for QR codes and links:
+//! `luna:LUN_2bd806c97f0e00af2bf5?amount=1.5&memo=rent&label=Alice`.
+//!
+//! Parameter values are percent-encoded UTF-8 and `+` is a literal plus. A `bill`
+//! parameter carries a whole bill in `DigitalBill::to_compact` form. Parsing is strict:
+//! unknown or repeated parameters are refused rather than ignored.
+use std::collections::HashSet;
+use std::fmt;
+use crate::core::sm2::ADDRESS_PREFIX;
+use crate::core::wallet::{AddressError, LunaWallet};
+use crate::gtx::digital_bill::DigitalBill;
+use crate::transactions::security::DEFAULT_MAX_MEMO_BYTES;
+
+pub const SCHEME: &str = "luna:";
+
+/// Why `PaymentRequest::from_uri` refused a URI
+#[derive(Debug, Clone, PartialEq)]
+pub enum UriError {
+    /// Does not start with `luna:`
+    WrongScheme,
+    InvalidAddress(AddressError),
+    /// Not a plain positive decimal such as `1.5`
+    InvalidAmount(String),
+    /// The decoded memo's length in bytes
+    MemoTooLong(usize),
+    /// A bad percent escape, an unescaped character or text that is not UTF-8
+    InvalidEncoding(String),
+    UnknownParameter(String),
+    DuplicateParameter(String),
+    /// The bill does not decode or is issued to another address
+    InvalidBill(String),
+    /// A draft transaction needs an amount
+    MissingAmount,
+}
+
+impl fmt::Display for UriError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            UriError::WrongScheme => write!(f, "Not a {} URI", SCHEME),
+            UriError::InvalidAddress(e) => write!(f, "Invalid address: {}", e),
+            UriError::InvalidAmount(amount) => write!(f, "Invalid amount: {:?}", amount),
+            UriError::MemoTooLong(len) => write!(f, "Memo is {} bytes; the limit is {}", len, DEFAULT_MAX_MEMO_BYTES),
+            UriError::InvalidEncoding(reason) => write!(f, "Malformed URI: {}", reason),
+            UriError::UnknownParameter(name) => write!(f, "Unknown parameter: {}", name),
+            UriError::DuplicateParameter(name) => write!(f, "Parameter given twice: {}", name),
+            UriError::InvalidBill(reason) => write!(f, "Invalid bill: {}", reason),
+            UriError::MissingAmount => write!(f, "Payment request has no amount"),
+        }
+    }
+}
+
+impl std::error::Error for UriError {}
+
+impl From<AddressError> for UriError {
+    fn from(e: AddressError) -> Self {
+        UriError::InvalidAddress(e)
+    }
+}
+
+/// A request to pay `address`, or with a bill, a certificate of a bill it holds
+#[derive(Debug, Clone)]
+pub struct PaymentRequest {
+    pub address: String,
+    pub amount: Option<f64>,
+    pub memo: Option<String>,
+    /// Who is asking, for display only
+    pub label: Option<String>,
+    pub bill: Option<DigitalBill>,
+}
+
+impl PaymentRequest {
+    pub fn new(address: &str) -> Self {
+        PaymentRequest { address: address.to_string(), amount: None, memo: None, label: None, bill: None }
+    }
+
+    pub fn with_amount(mut self, amount: f64) -> Self {
+        self.amount = Some(amount);
+        self
+    }
+
+    pub fn with_memo(mut self, memo: &str) -> Self {
+        self.memo = Some(memo.to_string());
+        self
+    }
+
+    pub fn with_label(mut self, label: &str) -> Self {
+        self.label = Some(label.to_string());
+        self
+    }
+
+    pub fn with_bill(mut self, bill: DigitalBill) -> Self {
+        self.bill = Some(bill);
+        self
+    }
+
+    pub fn to_uri(&self) -> String {
+        let mut params = Vec::new();
+        if let Some(amount) = self.amount {
+            params.push(format!("amount={}", amount));
+        }
+        if let Some(memo) = &self.memo {
+            params.push(format!("memo={}", encode_component(memo)));
+        }
+        if let Some(label) = &self.label {
+            params.push(format!("label={}", encode_component(label)));
+        }
+        if let Some(bill) = &self.bill {
+            params.push(format!("bill={}", bill.to_compact()));
+        }
+        if params.is_empty() {
+            format!("{}{}", SCHEME, self.address)
+        } else {
+            format!("{}{}?{}", SCHEME, self.address, params.join("&"))
+        }
+    }
+
+    /// Parse and check a URI; the scheme and address may be upper case, as QR scanners return them
+    pub fn from_uri(uri: &str) -> Result<Self, UriError> {
+        let rest = uri
+            .get(..SCHEME.len())
+            .filter(|scheme| scheme.eq_ignore_ascii_case(SCHEME))
+            .map(|_| &uri[SCHEME.len()..])
+            .ok_or(UriError::WrongScheme)?;
+        let (address, query) = rest.split_once('?').unwrap_or((rest, ""));
+        LunaWallet::validate_address(address)?;
+        let mut request = PaymentRequest::new(&format!("{}{}", ADDRESS_PREFIX, address[ADDRESS_PREFIX.len()..].to_ascii_lowercase()));
+        let mut seen = HashSet::new();
+        for pair in query.split('&').filter(|pair| !pair.is_empty()) {
+            let (name, raw) = pair.split_once('=').ok_or_else(|| UriError::InvalidEncoding(format!("{} has no value", pair)))?;
+            if !seen.insert(name) {
+                return Err(UriError::DuplicateParameter(name.to_string()));
+            }
+            let value = decode_component(raw)?;
+            match name {
+                "amount" => request.amount = Some(parse_amount(&value)?),
+                "memo" if value.len() > DEFAULT_MAX_MEMO_BYTES => return Err(UriError::MemoTooLong(value.len())),
+                "memo" => request.memo = Some(value),
+                "label" => request.label = Some(value),
+                "bill" => {
+                    let bill = DigitalBill::from_compact(&value).map_err(UriError::InvalidBill)?;
+                    if !bill.user_address.eq_ignore_ascii_case(&request.address) {
+                        return Err(UriError::InvalidBill(format!("issued to {}, not {}", bill.user_address, request.address)));
+                    }
+                    request.bill = Some(bill);
+                }
+                _ => return Err(UriError::UnknownParameter(name.to_string())),
+            }
+        }
+        Ok(request)
+    }
+}
+
+/// A scanned payment URI, as `LunaWallet::payment_request` writes them
+pub fn parse_payment_request(uri: &str) -> Result<PaymentRequest, UriError> {
+    PaymentRequest::from_uri(uri)
+}
+
+/// Digits with at most one point and digits on both sides of it; no sign, exponent or zero
+fn parse_amount(value: &str) -> Result<f64, UriError> {
+    let invalid = || UriError::InvalidAmount(value.to_string());
+    let (whole, fraction) = value.split_once('.').unwrap_or((value, "0"));
+    let digits = |part: &str| !part.is_empty() && part.bytes().all(|b| b.is_ascii_digit());
+    if !digits(whole) || !digits(fraction) {
+        return Err(invalid());
+    }
+    let amount: f64 = value.parse().map_err(|_| invalid())?;
+    if amount > 0.0 && amount.is_finite() { Ok(amount) } else { Err(invalid()) }
+}
+
+/// Escape everything but RFC 3986 unreserved characters
+fn encode_component(value: &str) -> String {
+    let mut encoded = String::with_capacity(value.len());
+    for byte in value.bytes() {
+        if byte.is_ascii_alphanumeric() || b"-._~".contains(&byte) {
+            encoded.push(byte as char);
+        } else {
+            encoded.push_str(&format!("%{:02X}", byte));
+        }
+    }
+    encoded
+}
+
+fn decode_component(value: &str) -> Result<String, UriError> {
+    let bytes = value.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'%' => {
+                let hex = value
+                    .get(i + 1..i + 3)
+                    .filter(|hex| hex.bytes().all(|b| b.is_ascii_hexdigit()))
+                    .ok_or_else(|| UriError::InvalidEncoding(format!("bad escape at {:?}", &value[i..])))?;
+                decoded.push(u8::from_str_radix(hex, 16).unwrap());
+                i += 3;
+            }
+            b if b.is_ascii_graphic() => {
+                decoded.push(b);
+                i += 1;
+            }
+            b => return Err(UriError::InvalidEncoding(format!("unescaped byte 0x{:02x}", b))),
+        }
+    }
+    String::from_utf8(decoded).map_err(|_| UriError::InvalidEncoding("value is not UTF-8".to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ALICE: &str = "LUN_2bd806c97f0e00af2bf5";
+
+    #[test]
+    fn test_round_trip() {
+        let request = PaymentRequest::new(ALICE).with_amount(1.5).with_memo("rent & bills, 100%").with_label("Alice Müller");
+        let uri = request.to_uri();
+        assert_eq!(uri, "luna:LUN_2bd806c97f0e00af2bf5?amount=1.5&memo=rent%20%26%20bills%2C%20100%25&label=Alice%20M%C3%BCller");
+        let parsed = PaymentRequest::from_uri(&uri).unwrap();
+        assert_eq!(parsed.address, ALICE);
+        assert_eq!(parsed.amount, Some(1.5));
+        assert_eq!(parsed.memo.as_deref(), Some("rent & bills, 100%"));
+        assert_eq!(parsed.label.as_deref(), Some("Alice Müller"));
+        assert!(parsed.bill.is_none());
+
+        assert_eq!(PaymentRequest::new(ALICE).to_uri(), "luna:LUN_2bd806c97f0e00af2bf5");
+        let scanned = PaymentRequest::from_uri("LUNA:LUN_2BD806C97F0E00AF2BF5?amount=0.00000001&memo=a+b").unwrap();
+        assert_eq!((scanned.address.as_str(), scanned.amount, scanned.memo.as_deref()), (ALICE, Some(0.00000001), Some("a+b")));
+        assert_eq!(PaymentRequest::from_uri(&scanned.to_uri()).unwrap().amount, Some(0.00000001));
+    }
+
+    #[test]
+    fn test_malformed_uris_are_refused() {
+        let refused = |uri: &str| PaymentRequest::from_uri(uri).unwrap_err();
+        assert_eq!(refused("bitcoin:LUN_2bd806c97f0e00af2bf5"), UriError::WrongScheme);
+        assert_eq!(refused("lun"), UriError::WrongScheme);
+        assert_eq!(refused("luna:LUN_2bd806c97f0e00af2bf6"), UriError::InvalidAddress(AddressError::ChecksumMismatch));
+        assert_eq!(refused("luna:?amount=1"), UriError::InvalidAddress(AddressError::MissingPrefix));
+        for amount in ["-1", "0", "0.0", "1e3", ".5", "5.", "1.2.3", "inf", "NaN", "1,5", ""] {
+            assert_eq!(refused(&format!("luna:{}?amount={}", ALICE, amount)), UriError::InvalidAmount(amount.to_string()));
+        }
+        assert_eq!(refused(&format!("luna:{}?memo={}", ALICE, "x".repeat(513))), UriError::MemoTooLong(513));
+        assert!(PaymentRequest::from_uri(&format!("luna:{}?memo={}", ALICE, "x".repeat(512))).is_ok());
+        assert_eq!(refused(&format!("luna:{}?amount=1&amount=2", ALICE)), UriError::DuplicateParameter("amount".to_string()));
+        assert_eq!(refused(&format!("luna:{}?req-fee=1", ALICE)), UriError::UnknownParameter("req-fee".to_string()));
+        assert!(matches!(refused(&format!("luna:{}?amount", ALICE)), UriError::InvalidEncoding(_)));
+        assert!(matches!(refused(&format!("luna:{}?memo=100%", ALICE)), UriError::InvalidEncoding(_)));
+        assert!(matches!(refused(&format!("luna:{}?memo=%zz", ALICE)), UriError::InvalidEncoding(_)));
+        assert!(matches!(refused(&format!("luna:{}?memo=%C3", ALICE)), UriError::InvalidEncoding(_)));
+        assert!(matches!(refused(&format!("luna:{}?memo=two words", ALICE)), UriError::InvalidEncoding(_)));
+        assert!(matches!(refused(&format!("luna:{}?bill=not-a-bill", ALICE)), UriError::InvalidBill(_)));
+    }
+
+    #[test]
+    fn test_wallet_payment_request_round_trip() {
+        let wallet = LunaWallet::create("shop", "hunter2");
+        for (amount, memo) in [(None, None), (Some(1.5), None), (None, Some("coffee")), (Some(0.25), Some("coffee & cake"))] {
+            let uri = wallet.payment_request(amount, memo);
+            let request = parse_payment_request(&uri).unwrap();
+            assert_eq!((request.address.as_str(), request.amount, request.memo.as_deref()), (wallet.address.as_str(), amount, memo), "{}", uri);
+        }
+        assert_eq!(wallet.payment_request(Some(1.5), Some("coffee")), format!("luna:{}?amount=1.5&memo=coffee", wallet.address));
+        assert_eq!(parse_payment_request(&format!("luna:{}?amount=1.5x", wallet.address)).unwrap_err(), UriError::InvalidAmount("1.5x".to_string()));
+        assert_eq!(parse_payment_request(&format!("ethereum:{}", wallet.address)).unwrap_err(), UriError::WrongScheme);
+        assert_eq!(parse_payment_request("luna:LUN_not-an-address").unwrap_err(), UriError::InvalidAddress(AddressError::InvalidCharacter('n')));
+        assert_eq!(parse_payment_request("luna:2bd806c97f0e00af2bf5").unwrap_err(), UriError::InvalidAddress(AddressError::MissingPrefix));
+    }
+
+    #[test]
+    fn test_bill_survives_the_uri() {
+        let mut bill = DigitalBill::new(100, ALICE.to_string(), 5, Some(serde_json::json!({"note": "gift"})), None, None, None, None, None, None);
+        bill.public_key = Some("bill-key".to_string());
+        bill.signature = Some(bill.sign("bill-key"));
+        assert!(bill.verify());
+
+        let uri = PaymentRequest::new(ALICE).with_bill(bill.clone()).to_uri();
+        let decoded = PaymentRequest::from_uri(&uri).unwrap().bill.unwrap();
+        assert!(decoded.verify());
+        assert_eq!((decoded.bill_serial.as_str(), decoded.calculate_hash()), (bill.bill_serial.as_str(), bill.calculate_hash()));
+
+        let mut forged = DigitalBill::from_compact(&bill.to_compact()).unwrap();
+        forged.denomination = 1000;
+        let forged = PaymentRequest::from_uri(&PaymentRequest::new(ALICE).with_bill(forged).to_uri()).unwrap().bill.unwrap();
+        assert!(!forged.verify());
+
+        let elsewhere = PaymentRequest::new("LUN_81b637d8fcd2c6dafcca").with_bill(bill).to_uri();
+        assert!(matches!(PaymentRequest::from_uri(&elsewhere), Err(UriError::InvalidBill(reason)) if reason.contains("issued to")));
+    }
+}
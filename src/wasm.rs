@@ -0,0 +1,155 @@
+//! JavaScript bindings for a browser wallet, built with the `wasm` feature.
+//!
+//! Only the offline parts of the library are exported: keys and signatures, wallets
+//! encrypted under a password, bill verification and password encryption. Fallible
+//! functions throw their error message as a string. Transactions and bills cross the
+//! boundary as JSON strings in the same shape the node API uses.
+use std::collections::HashMap;
+use serde_json::{json, Value as JsonValue};
+use wasm_bindgen::prelude::*;
+use crate::core::crypto::Crypto;
+use crate::core::wallet::LunaWallet;
+use crate::gtx::digital_bill::DigitalBill;
+use crate::storage::encryption::EncryptionManager;
+
+/// A new private key with its public key and `LUN_` address
+#[wasm_bindgen]
+pub struct Keypair {
+    private_key: String,
+    public_key: String,
+    address: String,
+}
+
+#[wasm_bindgen]
+impl Keypair {
+    #[wasm_bindgen(getter, js_name = privateKey)]
+    pub fn private_key(&self) -> String {
+        self.private_key.clone()
+    }
+
+    #[wasm_bindgen(getter, js_name = publicKey)]
+    pub fn public_key(&self) -> String {
+        self.public_key.clone()
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn address(&self) -> String {
+        self.address.clone()
+    }
+}
+
+/// The public key is derived from the private key so signatures verify against it
+#[wasm_bindgen(js_name = generateKeypair)]
+pub fn generate_keypair() -> Keypair {
+    let crypto = Crypto::new();
+    let private_key = crypto.generate_private_key();
+    let public_key = crypto.derive_public_key(&private_key);
+    let address = crypto.derive_address(&public_key);
+    Keypair { private_key, public_key, address }
+}
+
+#[wasm_bindgen(js_name = signData)]
+pub fn sign_data(data: &str, private_key: &str) -> String {
+    Crypto::new().sign_data(data, private_key)
+}
+
+#[wasm_bindgen(js_name = verifySignature)]
+pub fn verify_signature(data: &str, signature: &str, public_key: &str) -> bool {
+    Crypto::new().verify_signature(data, signature, public_key)
+}
+
+/// A fresh English seed phrase of 12, 15, 18, 21 or 24 words
+#[wasm_bindgen(js_name = generateMnemonic)]
+pub fn generate_mnemonic(word_count: usize) -> Result<String, String> {
+    LunaWallet::generate_mnemonic(word_count).map_err(|e| e.to_string())
+}
+
+/// Whether a bill's signature matches its public key, as `DigitalBill::verify` decides
+#[wasm_bindgen(js_name = verifyBill)]
+pub fn verify_bill(bill_json: &str) -> Result<bool, String> {
+    let bill: DigitalBill = serde_json::from_str(bill_json).map_err(|e| format!("bill_json is not a bill: {}", e))?;
+    Ok(bill.verify())
+}
+
+/// `data` encrypted under `password`, for `decrypt`
+#[wasm_bindgen]
+pub fn encrypt(data: &str, password: &str) -> String {
+    EncryptionManager::new().encrypt_data(data, password)
+}
+
+#[wasm_bindgen]
+pub fn decrypt(encrypted: &str, password: &str) -> Result<String, String> {
+    EncryptionManager::new().decrypt_data(encrypted, password).ok_or_else(|| "Wrong password".to_string())
+}
+
+/// A wallet whose private key stays encrypted until `unlock`
+#[wasm_bindgen]
+pub struct Wallet(LunaWallet);
+
+#[wasm_bindgen]
+impl Wallet {
+    /// A new keypair with its private key encrypted under `password`
+    #[wasm_bindgen(constructor)]
+    pub fn new(label: &str, password: &str) -> Wallet {
+        Wallet(LunaWallet::create(label, password))
+    }
+
+    /// The wallet at `index` under a seed phrase, as `LunaWallet::from_mnemonic` derives it
+    #[wasm_bindgen(js_name = fromMnemonic)]
+    pub fn from_mnemonic(phrase: &str, index: u32, label: &str, password: &str) -> Result<Wallet, String> {
+        LunaWallet::from_mnemonic(phrase, index, label, password).map(Wallet).map_err(|e| e.to_string())
+    }
+
+    /// Restore a wallet saved with `toRecord`; it starts locked
+    #[wasm_bindgen(js_name = fromRecord)]
+    pub fn from_record(record_json: &str) -> Result<Wallet, String> {
+        let record: JsonValue = serde_json::from_str(record_json).map_err(|e| format!("record_json is not JSON: {}", e))?;
+        LunaWallet::from_json(&record).map(Wallet)
+    }
+
+    /// The wallet as JSON with its private key still encrypted, safe to keep in browser storage
+    #[wasm_bindgen(js_name = toRecord)]
+    pub fn to_record(&self) -> String {
+        self.0.to_json().to_string()
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn address(&self) -> String {
+        self.0.address.clone()
+    }
+
+    #[wasm_bindgen(getter, js_name = publicKey)]
+    pub fn public_key(&self) -> String {
+        self.0.public_key.clone()
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn label(&self) -> String {
+        self.0.label.clone()
+    }
+
+    #[wasm_bindgen(getter, js_name = isLocked)]
+    pub fn is_locked(&self) -> bool {
+        self.0.is_locked()
+    }
+
+    pub fn unlock(&mut self, password: &str) -> Result<(), String> {
+        self.0.unlock(password).map_err(|e| e.to_string())
+    }
+
+    pub fn lock(&mut self) {
+        self.0.lock();
+    }
+
+    /// Sign `tx_json`, a transaction from this wallet, and return it with its signature, public key and hash
+    #[wasm_bindgen(js_name = signTransaction)]
+    pub fn sign_transaction(&self, tx_json: &str) -> Result<String, String> {
+        let mut tx: HashMap<String, JsonValue> = serde_json::from_str(tx_json).map_err(|e| format!("tx_json is not a transaction object: {}", e))?;
+        let from = tx.get("from").and_then(|v| v.as_str()).unwrap_or_default();
+        if from != self.0.address {
+            return Err(format!("Transaction is from {}, not {}", from, self.0.address));
+        }
+        self.0.sign_transaction(&mut tx).map_err(|e| e.to_string())?;
+        Ok(json!(tx).to_string())
+    }
+}
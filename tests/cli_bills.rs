@@ -0,0 +1,78 @@
+mod common;
+
+use common::Harness;
+use serde_json::json;
+use lunalib::cli::CliError;
+use lunalib::gtx::bill_registry::{BillInfo, BillRegistry};
+
+const SERIAL: &str = "GTX100_1700000000000_abcdefgh";
+
+fn seeded() -> Harness {
+    let cli = Harness::new();
+    let registry = BillRegistry::new(Some(cli.dir.path().join("bills.db")));
+    registry
+        .register_bill(BillInfo {
+            bill_serial: SERIAL.to_string(),
+            denomination: 100,
+            user_address: "LUN_owner".to_string(),
+            hash: "00f1e2d3c4b5a697".to_string(),
+            mining_time: 1.5,
+            difficulty: 2,
+            luna_value: 100.0,
+            timestamp: 1_700_000_000.0,
+            verification_url: String::new(),
+            image_url: String::new(),
+            metadata: json!({"front_serial": SERIAL, "denomination": 100, "issued_to": "LUN_owner", "metadata_hash": "m3ta", "signature": "m3ta"}),
+            status: "active".to_string(),
+        })
+        .unwrap();
+    cli
+}
+
+#[test]
+fn test_list_and_verify() {
+    let cli = seeded();
+    let listed = cli.json(&["bills", "list", "--address", "LUN_owner", "--status", "active"]);
+    assert_eq!(listed[0]["bill_serial"], SERIAL);
+    assert_eq!(cli.json(&["bills", "list", "--address", "LUN_owner", "--status", "spent"]), json!([]));
+    let table = cli.run(&["bills", "list", "--address", "LUN_owner"]).unwrap();
+    assert!(table.lines().nth(1).unwrap().starts_with(SERIAL), "{}", table);
+
+    let verified = cli.json(&["bills", "verify", SERIAL, "--strict"]);
+    assert_eq!(verified["valid"], true);
+    assert_eq!(verified["method"], "signature_is_metadata_hash");
+    let error = cli.try_run(&["bills", "verify", "GTX1_missing"]).unwrap_err();
+    assert_eq!(error.exit_code(), 3);
+}
+
+#[test]
+fn test_remote_verification_degrades_unless_strict() {
+    let cli = seeded();
+    let mut server = mockito::Server::new();
+    let endpoint = server.url();
+    let confirmed = server.mock("GET", "/verify/00f1e2d3c4b5a697").with_body(r#"{"valid": true}"#).create();
+    assert_eq!(cli.json(&["bills", "verify", SERIAL, "--remote", "--endpoint", &endpoint])["remote"], true);
+    confirmed.assert();
+
+    confirmed.remove();
+    server.mock("GET", "/verify/00f1e2d3c4b5a697").with_status(503).create();
+    let degraded = cli.json(&["bills", "verify", SERIAL, "--remote", "--endpoint", &endpoint]);
+    assert_eq!(degraded["valid"], true);
+    assert!(degraded["remote_error"].as_str().unwrap().contains("503"));
+    let error = cli.try_run(&["bills", "verify", SERIAL, "--remote", "--strict", "--endpoint", &endpoint]).unwrap_err();
+    assert!(matches!(error, CliError::Network(_)), "{}", error);
+}
+
+#[test]
+fn test_export_then_import() {
+    let source = seeded();
+    let certificate = source.dir.path().join("cert.json");
+    source.run(&["bills", "export", SERIAL, "--output", certificate.to_str().unwrap()]).unwrap();
+
+    let target = Harness::new();
+    assert_eq!(target.json(&["bills", "import", certificate.to_str().unwrap()])["imported"], true);
+    let listed = target.json(&["bills", "list", "--address", "LUN_owner"]);
+    assert_eq!(listed[0]["hash"], "00f1e2d3c4b5a697");
+    assert_eq!(target.json(&["bills", "verify", SERIAL])["valid"], true);
+    assert!(target.run(&["bills", "import", certificate.to_str().unwrap()]).unwrap_err().contains("already registered"));
+}
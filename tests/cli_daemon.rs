@@ -0,0 +1,103 @@
+#![cfg(feature = "daemon-server")]
+
+mod common;
+
+use std::fs;
+use std::net::TcpListener;
+use std::path::PathBuf;
+use std::thread;
+use std::time::{Duration, Instant};
+use serde_json::json;
+use common::Harness;
+use lunalib::cli::CliError;
+use lunalib::core::daemon::Daemon;
+
+/// A config for a daemon syncing from `endpoint`, with its API on `api_bind`
+fn write_config(cli: &Harness, endpoint: &str, api_bind: &str) -> String {
+    let path = cli.dir.path().join("daemon.json");
+    let config = json!({
+        "endpoint_url": endpoint,
+        "data_dir": cli.dir.path().join("data"),
+        "tick_interval_secs": 1,
+        "api_bind": api_bind,
+        "auth_token": "s3cret",
+    });
+    fs::write(&path, config.to_string()).unwrap();
+    path.to_str().unwrap().to_string()
+}
+
+fn free_addr() -> String {
+    TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().to_string()
+}
+
+#[test]
+fn test_start_status_and_stop() {
+    let cli = Harness::new();
+    let mut server = mockito::Server::new();
+    server.mock("GET", "/blockchain/blocks").with_body(r#"{"blocks": []}"#).expect_at_least(0).create();
+    let config = write_config(&cli, &server.url(), &free_addr());
+
+    let running = thread::scope(|scope| {
+        let daemon = scope.spawn(|| cli.json(&["daemon", "start", "--config", &config]));
+        let started = Instant::now();
+        let status = loop {
+            match cli.try_run(&["daemon", "status", "--config", &config, "--json"]) {
+                Ok(status) => break serde_json::from_str::<serde_json::Value>(&status).unwrap(),
+                Err(e) if started.elapsed() < Duration::from_secs(10) => {
+                    assert!(matches!(e, CliError::Network(_)), "{}", e);
+                    thread::sleep(Duration::from_millis(100));
+                }
+                Err(e) => panic!("daemon never came up: {}", e),
+            }
+        };
+        assert_eq!(status["health"], "healthy");
+        assert_eq!(status["peer_count"], 0);
+        let text = cli.run(&["daemon", "status", "--config", &config]).unwrap();
+        assert!(text.contains("Health:       healthy"), "{}", text);
+        assert!(text.contains("http_api (running)"), "{}", text);
+        assert_eq!(cli.run(&["daemon", "peers", "--config", &config]).unwrap().trim(), "No peers");
+
+        let stopped = cli.json(&["daemon", "stop", "--config", &config]);
+        assert_eq!(stopped["method"], "api");
+        daemon.join().unwrap()
+    });
+    assert_eq!(running["stopped"], true);
+    assert_eq!(running["timed_out"], json!([]));
+    assert!(cli.try_run(&["daemon", "status", "--config", &config]).is_err());
+}
+
+#[test]
+fn test_peers_lists_registered_peers() {
+    let cli = Harness::new();
+    let mut daemon = Daemon::new().with_auth_token("s3cret");
+    let addr = daemon.serve("127.0.0.1:0").unwrap();
+    let config = write_config(&cli, "http://127.0.0.1:1", &addr.to_string());
+
+    let peer = json!({"node_id": "node-1", "url": "http://10.0.0.2:8545", "version": "1.2.0"});
+    reqwest::blocking::Client::new().post(format!("http://{}/peers/register", addr)).json(&peer).send().unwrap();
+    let peers = cli.json(&["daemon", "peers", "--config", &config]);
+    assert_eq!(peers[0]["node_id"], "node-1");
+    let table = cli.run(&["daemon", "peers", "--config", &config]).unwrap();
+    assert!(table.lines().nth(1).unwrap().starts_with("node-1"), "{}", table);
+    daemon.stop();
+}
+
+#[cfg(unix)]
+#[test]
+fn test_stop_falls_back_to_the_pidfile() {
+    let cli = Harness::new();
+    let config = write_config(&cli, "http://127.0.0.1:1", &free_addr());
+    let mut child = std::process::Command::new("sleep").arg("30").spawn().unwrap();
+    let pidfile: PathBuf = cli.dir.path().join("daemon.pid");
+    fs::write(&pidfile, child.id().to_string()).unwrap();
+
+    let stopped = cli.json(&["daemon", "stop", "--config", &config, "--pidfile", pidfile.to_str().unwrap()]);
+    assert_eq!(stopped["method"], "signal");
+    assert_eq!(stopped["pid"], child.id());
+    assert!(stopped["api_error"].as_str().unwrap().contains("unreachable"));
+    assert!(!child.wait().unwrap().success());
+
+    fs::remove_file(&pidfile).unwrap();
+    let error = cli.try_run(&["daemon", "stop", "--config", &config, "--pidfile", pidfile.to_str().unwrap()]).unwrap_err();
+    assert!(matches!(error, CliError::Network(_)), "{}", error);
+}
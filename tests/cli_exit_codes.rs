@@ -0,0 +1,65 @@
+mod common;
+
+use std::fs;
+use serde_json::{json, Value};
+use common::Harness;
+
+/// The single JSON error document written to stderr
+fn json_error(stderr: &str) -> Value {
+    assert_eq!(stderr.lines().count(), 1, "{}", stderr);
+    serde_json::from_str(stderr).unwrap()
+}
+
+#[test]
+fn test_not_found_wallet() {
+    let cli = Harness::new();
+    let (code, stdout, stderr) = cli.execute(&["wallet", "show", "LUN_nobody", "--json"]);
+    assert_eq!(code, 6);
+    assert_eq!(stdout, "");
+    assert_eq!(
+        json_error(&stderr),
+        json!({"error": {"kind": "not_found", "message": "No wallet with address LUN_nobody", "exit_code": 6}})
+    );
+
+    let (code, _, stderr) = cli.execute(&["wallet", "show", "LUN_nobody"]);
+    assert_eq!(code, 6);
+    assert_eq!(stderr, "Error: No wallet with address LUN_nobody\n");
+}
+
+#[test]
+fn test_bad_amount() {
+    let cli = Harness::new();
+    let from = cli.create("main");
+    let password = cli.password_file();
+    let send = |amount: &str| {
+        let args = ["send", "--from", &from, "--to", "LUN_81b637d8fcd2c6dafcca", &format!("--amount={}", amount), "--password-file", &password, "--yes", "--json"];
+        cli.execute(&args)
+    };
+
+    let (code, stdout, stderr) = send("lots");
+    assert_eq!((code, stdout.as_str()), (2, ""));
+    let error = json_error(&stderr);
+    assert_eq!(error["error"]["kind"], "usage");
+    assert_eq!(error["error"]["exit_code"], 2);
+    assert!(error["error"]["message"].as_str().unwrap().starts_with("invalid value 'lots' for '--amount"), "{}", error);
+
+    let (code, stdout, stderr) = send("-5");
+    assert_eq!((code, stdout.as_str()), (3, ""));
+    assert_eq!(json_error(&stderr)["error"]["kind"], "validation");
+}
+
+#[test]
+fn test_password_errors() {
+    let cli = Harness::new();
+    let from = cli.create("main");
+    let send = ["send", "--from", &from, "--to", "LUN_81b637d8fcd2c6dafcca", "--amount", "1", "--yes", "--non-interactive", "--json"];
+    let (code, _, stderr) = cli.execute(&send);
+    assert_eq!(code, 2);
+    assert!(json_error(&stderr)["error"]["message"].as_str().unwrap().contains("--password-file"), "{}", stderr);
+
+    let wrong = cli.dir.path().join("wrong");
+    fs::write(&wrong, "hunter3\n").unwrap();
+    let (code, _, stderr) = cli.execute(&[&send[..], &["--password-file", wrong.to_str().unwrap()]].concat());
+    assert_eq!(code, 5);
+    assert_eq!(json_error(&stderr)["error"]["kind"], "auth");
+}
@@ -0,0 +1,75 @@
+mod common;
+
+use std::fs;
+use std::time::{SystemTime, UNIX_EPOCH};
+use common::Harness;
+use serde_json::json;
+use lunalib::cli::{CliError, HISTORY_CSV_COLUMNS};
+
+const DAY: u64 = 86_400;
+
+fn seeded() -> (Harness, String) {
+    let cli = Harness::new();
+    let address = cli.create("main");
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs();
+    let db = cli.database();
+    let txs = [
+        json!({"hash": "t_recent", "type": "transfer", "from": address, "to": "LUN_bob", "amount": 2.5, "fee": 0.001, "timestamp": now - 3600, "memo": "rent, june"}),
+        json!({"hash": "r_old", "type": "reward", "from": "network", "to": address, "amount": 50.0, "fee": 0.0, "timestamp": now - 30 * DAY}),
+        json!({"hash": "g_mid", "type": "gtx_genesis", "from": "mining", "to": address, "amount": 100.0, "fee": 0.0, "timestamp": now - 3 * DAY}),
+        json!({"hash": "t_mid", "type": "transfer", "from": "LUN_carol", "to": address, "amount": 1.0, "fee": 0.001, "timestamp": now - 5 * DAY}),
+    ];
+    for tx in &txs {
+        assert!(db.save_transaction(tx, &address));
+    }
+    (cli, address)
+}
+
+fn hashes(value: &serde_json::Value) -> Vec<&str> {
+    value.as_array().unwrap().iter().map(|tx| tx["hash"].as_str().unwrap()).collect()
+}
+
+#[test]
+fn test_history_filters() {
+    let (cli, address) = seeded();
+    assert_eq!(hashes(&cli.json(&["history", &address])), vec!["t_recent", "g_mid", "t_mid", "r_old"]);
+    assert_eq!(hashes(&cli.json(&["history", &address, "--type", "transfer"])), vec!["t_recent", "t_mid"]);
+    assert_eq!(hashes(&cli.json(&["history", &address, "--type", "gtx"])), vec!["g_mid"]);
+    assert_eq!(hashes(&cli.json(&["history", &address, "--since", "7d"])), vec!["t_recent", "g_mid", "t_mid"]);
+    assert_eq!(hashes(&cli.json(&["history", &address, "--since", "4d", "--limit", "1"])), vec!["t_recent"]);
+    assert_eq!(hashes(&cli.json(&["history", &address, "--since", "2000-01-01", "--type", "reward"])), vec!["r_old"]);
+    assert!(cli.run(&["history", &address, "--since", "yesterday"]).unwrap_err().contains("--since"));
+
+    let table = cli.run(&["history", &address, "--limit", "2"]).unwrap();
+    let lines: Vec<&str> = table.lines().collect();
+    assert_eq!(lines.len(), 3, "{}", table);
+    assert!(lines[0].starts_with("TIME") && lines[0].contains("AMOUNT"));
+    assert!(lines[1].starts_with("1 hour ago") && lines[2].starts_with("3 days ago"), "{}", table);
+    assert_eq!(lines[1].find("t_recent"), lines[2].find("g_mid"));
+}
+
+#[test]
+fn test_history_csv_export() {
+    let (cli, address) = seeded();
+    let output = cli.dir.path().join("history.csv");
+    let summary = cli.json(&["history", &address, "--format", "csv", "--output", output.to_str().unwrap()]);
+    assert_eq!(summary["transactions"], 4);
+
+    let csv = fs::read_to_string(&output).unwrap();
+    assert_eq!(summary["bytes"], csv.len());
+    let mut lines = csv.lines();
+    assert_eq!(lines.next().unwrap(), HISTORY_CSV_COLUMNS.join(","));
+    assert_eq!(HISTORY_CSV_COLUMNS, ["hash", "type", "from", "to", "amount", "fee", "timestamp", "block_height", "status", "memo"]);
+    let first = lines.next().unwrap();
+    assert!(first.starts_with(&format!("t_recent,transfer,{},LUN_bob,2.5,0.001,", address)), "{}", first);
+    assert!(first.ends_with(",confirmed,\"rent, june\""), "{}", first);
+    assert_eq!(lines.count(), 3);
+}
+
+#[test]
+fn test_history_unknown_address() {
+    let (cli, _) = seeded();
+    let error = cli.try_run(&["history", "LUN_nobody"]).unwrap_err();
+    assert_eq!(error, CliError::NotFound("No wallet with address LUN_nobody".to_string()));
+    assert_eq!(error.exit_code(), 6);
+}
@@ -0,0 +1,54 @@
+mod common;
+
+use common::Harness;
+use serde_json::json;
+use lunalib::gtx::bill_registry::BillRegistry;
+use lunalib::gtx::genesis::GTXGenesis;
+
+#[test]
+fn test_mine_bill_registers_it() {
+    let cli = Harness::new();
+    let mined = cli.json(&["mine", "bill", "--denomination", "100", "--address", "LUN_miner", "--difficulty", "1", "--threads", "2"]);
+    let serial = mined["bill_serial"].as_str().unwrap();
+    assert!(serial.starts_with("GTX100_"), "{}", mined);
+    assert!(mined["hash"].as_str().unwrap().starts_with('0'));
+    assert_eq!(mined["denomination"], 100);
+    assert_eq!(mined["difficulty"], 1);
+    assert!(mined["nonce"].is_u64() && mined["mining_time"].is_f64());
+
+    let genesis = GTXGenesis::from_registry(BillRegistry::new(Some(cli.dir.path().join("bills.db"))));
+    let bill = genesis.bill_registry.get_bill(serial).unwrap().unwrap();
+    assert_eq!((bill.user_address.as_str(), bill.hash.as_str()), ("LUN_miner", mined["hash"].as_str().unwrap()));
+    assert_eq!(genesis.verify_bill(serial)["valid"], true);
+
+    let text = cli.run(&["mine", "bill", "--denomination", "1", "--address", "LUN_miner", "--difficulty", "1"]).unwrap();
+    assert!(text.starts_with("Mined bill GTX1_"), "{}", text);
+    assert!(cli.run(&["mine", "bill", "--denomination", "7", "--address", "LUN_miner"]).unwrap_err().contains("denomination"));
+}
+
+#[test]
+fn test_mine_block_submits_next_block() {
+    let cli = Harness::new();
+    let mut server = mockito::Server::new();
+    let genesis = json!({"index": 0, "hash": "genesis", "previous_hash": "0", "timestamp": 0, "transactions": [], "difficulty": 1});
+    server.mock("GET", "/blockchain/blocks").with_body(json!({"blocks": [genesis]}).to_string()).create();
+    let submit = server
+        .mock("POST", "/blockchain/submit-block")
+        .match_body(mockito::Matcher::PartialJson(json!({"index": 1, "previous_hash": "genesis", "miner": "LUN_miner"})))
+        .create();
+
+    let mined = cli.json(&["mine", "block", "--address", "LUN_miner", "--endpoint", &server.url()]);
+    submit.assert();
+    assert_eq!(mined["index"], 1);
+    assert_eq!(mined["difficulty"], 1);
+    assert!(mined["hash"].as_str().unwrap().starts_with('0'));
+}
+
+#[test]
+fn test_benchmark_report() {
+    let cli = Harness::new();
+    let report = cli.json(&["mine", "benchmark", "--seconds", "0", "--threads", "2"]);
+    assert_eq!(report["threads"], 2);
+    assert!(report["attempts"].as_u64().unwrap() >= 1);
+    assert!(report["hashrate"].is_f64() && report["elapsed_secs"].is_f64());
+}
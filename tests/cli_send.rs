@@ -0,0 +1,82 @@
+mod common;
+
+use common::Harness;
+use lunalib::cli::CliError;
+
+const RECIPIENT: &str = "LUN_81b637d8fcd2c6dafcca";
+
+fn send_args<'a>(from: &'a str, password: &'a str, extra: &[&'a str]) -> Vec<&'a str> {
+    let mut args = vec!["send", "--from", from, "--to", RECIPIENT, "--amount", "1.5", "--memo", "rent", "--password-file", password];
+    args.extend_from_slice(extra);
+    args
+}
+
+#[test]
+fn test_dry_run_previews_without_broadcasting() {
+    let cli = Harness::new();
+    let from = cli.create("main");
+    cli.set_balance(&from, 10.0);
+    let password = cli.password_file();
+    let mut server = mockito::Server::new();
+    let broadcast = server.mock("POST", "/mempool/add").expect(0).create();
+    let endpoint = server.url();
+
+    let text = cli.run(&send_args(&from, &password, &["--dry-run", "--endpoint", &endpoint])).unwrap();
+    assert!(text.contains("Amount:        1.5"), "{}", text);
+    assert!(text.contains("Fee:           0.001"), "{}", text);
+    assert!(text.contains("Balance after: 8.499"), "{}", text);
+
+    let preview = cli.json(&send_args(&from, &password, &["--dry-run", "--priority", "high", "--endpoint", &endpoint]));
+    assert_eq!(preview["fee"], 0.002);
+    assert_eq!(preview["memo"], "rent");
+    assert_eq!(preview["balance_after"], 10.0 - 1.5 - 0.002);
+    broadcast.assert();
+}
+
+#[test]
+fn test_insufficient_funds_is_a_validation_failure() {
+    let cli = Harness::new();
+    let from = cli.create("main");
+    cli.set_balance(&from, 1.0);
+    let password = cli.password_file();
+
+    let error = cli.try_run(&send_args(&from, &password, &["--dry-run"])).unwrap_err();
+    assert!(matches!(&error, CliError::Validation(reason) if reason.contains("insufficient funds")), "{}", error);
+    assert_eq!(error.exit_code(), 3);
+}
+
+#[test]
+fn test_mistyped_recipient_is_refused() {
+    let cli = Harness::new();
+    let from = cli.create("main");
+    cli.set_balance(&from, 10.0);
+    let password = cli.password_file();
+    let mistyped = RECIPIENT.replace('8', "9");
+    let args = ["send", "--from", &from, "--to", &mistyped, "--amount", "1", "--password-file", &password, "--dry-run"];
+
+    let error = cli.try_run(&args).unwrap_err();
+    assert!(matches!(&error, CliError::Validation(reason) if reason.contains("checksum")), "{}", error);
+    assert_eq!(error.exit_code(), 3);
+}
+
+#[test]
+fn test_broadcast_prints_hash_and_failures_have_their_own_exit_code() {
+    let cli = Harness::new();
+    let from = cli.create("main");
+    cli.set_balance(&from, 10.0);
+    let password = cli.password_file();
+    let mut server = mockito::Server::new();
+    let endpoint = server.url();
+    let accepted = server.mock("POST", "/mempool/add").with_body(r#"{"hash": "abc123"}"#).create();
+
+    let sent = cli.json(&send_args(&from, &password, &["--yes", "--endpoint", &endpoint]));
+    accepted.assert();
+    assert_eq!(sent["hash"], "abc123");
+
+    accepted.remove();
+    server.mock("POST", "/mempool/add").with_status(500).create();
+    let error = cli.try_run(&send_args(&from, &password, &["--yes", "--endpoint", &endpoint])).unwrap_err();
+    assert!(matches!(error, CliError::Network(_)), "{}", error);
+    assert_eq!(error.exit_code(), 4);
+    assert_eq!(CliError::Aborted.exit_code(), 1);
+}
@@ -0,0 +1,48 @@
+mod common;
+
+use common::Harness;
+
+#[test]
+fn test_create_list_show_balance() {
+    let cli = Harness::new();
+    let first = cli.create("savings");
+    let second = cli.create("spending");
+    assert_ne!(first, second);
+
+    let listed = cli.json(&["wallet", "list"]);
+    let labels: Vec<&str> = listed.as_array().unwrap().iter().map(|w| w["label"].as_str().unwrap()).collect();
+    assert_eq!(labels, vec!["savings", "spending"]);
+    assert!(listed[0].get("encrypted_private_key").is_none());
+
+    let shown = cli.json(&["wallet", "show", &first]);
+    assert_eq!(shown["label"], "savings");
+    assert!(cli.run(&["wallet", "show", &first]).unwrap().contains("Label:      savings"));
+    assert_eq!(cli.json(&["wallet", "balance", &first])["balance"], 0.0);
+    assert!(cli.run(&["wallet", "list"]).unwrap().contains(&second));
+}
+
+#[test]
+fn test_private_key_is_encrypted_with_password() {
+    let cli = Harness::new();
+    let address = cli.create("main");
+    let stored = cli.database().load_wallet(&address).unwrap();
+    let encrypted = std::str::from_utf8(&stored.encrypted_private_key).unwrap();
+    let encryption = lunalib::storage::encryption::EncryptionManager::new();
+    let private_key = encryption.decrypt_data(encrypted, "hunter2").unwrap();
+    assert!(encryption.decrypt_data(encrypted, "wrong").is_none());
+    let crypto = lunalib::core::crypto::Crypto::new();
+    assert_eq!(crypto.derive_public_key(&private_key), stored.public_key);
+}
+
+#[test]
+fn test_delete_requires_yes() {
+    let cli = Harness::new();
+    let address = cli.create("old");
+    let error = cli.run(&["wallet", "delete", &address]).unwrap_err();
+    assert!(error.contains("--yes"), "{}", error);
+    assert_eq!(cli.json(&["wallet", "list"]).as_array().unwrap().len(), 1);
+
+    assert_eq!(cli.json(&["wallet", "delete", &address, "--yes"])["deleted"], true);
+    assert_eq!(cli.run(&["wallet", "list"]).unwrap().trim(), "No wallets");
+    assert!(cli.run(&["wallet", "show", &address]).unwrap_err().contains("No wallet"));
+}
@@ -0,0 +1,77 @@
+#![allow(dead_code)]
+
+use std::fs;
+use std::path::PathBuf;
+use clap::Parser;
+use serde_json::Value;
+use lunalib::cli::{execute, run, Cli, CliError};
+use lunalib::storage::database::WalletDatabase;
+
+/// Runs CLI commands against a wallet database in a temporary directory, without colors so output is
+/// the same whether or not the tests run in a terminal
+pub struct Harness {
+    pub dir: tempfile::TempDir,
+}
+
+impl Harness {
+    pub fn new() -> Self {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("password"), "hunter2\n").unwrap();
+        Harness { dir }
+    }
+
+    pub fn db_path(&self) -> PathBuf {
+        self.dir.path().join("wallets.db")
+    }
+
+    pub fn password_file(&self) -> String {
+        self.dir.path().join("password").to_str().unwrap().to_string()
+    }
+
+    pub fn database(&self) -> WalletDatabase {
+        WalletDatabase::new(Some(self.db_path()))
+    }
+
+    pub fn try_run(&self, args: &[&str]) -> Result<String, CliError> {
+        let db = self.db_path();
+        let mut argv = vec!["luna-wallet", "--no-color", "--db", db.to_str().unwrap()];
+        argv.extend_from_slice(args);
+        let cli = Cli::try_parse_from(argv)?;
+        let mut out = Vec::new();
+        run(cli, &mut out)?;
+        Ok(String::from_utf8(out).unwrap())
+    }
+
+    /// Run as the binary would, returning the exit code, stdout and stderr
+    pub fn execute(&self, args: &[&str]) -> (i32, String, String) {
+        let db = self.db_path();
+        let mut argv = vec!["luna-wallet", "--no-color", "--db", db.to_str().unwrap()];
+        argv.extend_from_slice(args);
+        let (mut out, mut err) = (Vec::new(), Vec::new());
+        let code = execute(argv, &mut out, &mut err);
+        (code, String::from_utf8(out).unwrap(), String::from_utf8(err).unwrap())
+    }
+
+    pub fn run(&self, args: &[&str]) -> Result<String, String> {
+        self.try_run(args).map_err(|e| e.to_string())
+    }
+
+    pub fn json(&self, args: &[&str]) -> Value {
+        let mut args = args.to_vec();
+        args.push("--json");
+        serde_json::from_str(&self.run(&args).unwrap()).unwrap()
+    }
+
+    pub fn create(&self, label: &str) -> String {
+        let created = self.json(&["wallet", "create", "--label", label, "--password-file", &self.password_file()]);
+        created["address"].as_str().unwrap().to_string()
+    }
+
+    /// Overwrite the stored balance, as a wallet sync would
+    pub fn set_balance(&self, address: &str, balance: f64) {
+        let db = self.database();
+        let mut wallet = db.load_wallet(address).unwrap();
+        wallet.balance = balance;
+        assert!(db.save_wallet(&wallet));
+    }
+}
@@ -0,0 +1,137 @@
+#![cfg(feature = "cabi")]
+//! Drives the C ABI the way a C caller would: raw pointers, out parameters and explicit frees
+use std::ffi::{CStr, CString, c_char, c_void};
+use std::ptr;
+use lunalib::ffi::*;
+
+/// Copy a string the library returned, then free it
+unsafe fn take_string(s: *mut c_char) -> String {
+    assert!(!s.is_null());
+    let copy = unsafe { CStr::from_ptr(s) }.to_str().unwrap().to_string();
+    unsafe { luna_string_free(s) };
+    copy
+}
+
+fn last_error() -> String {
+    unsafe { take_string(luna_last_error_message()) }
+}
+
+#[derive(Default)]
+struct ProgressLog {
+    reports: u64,
+    malformed: u64,
+}
+
+unsafe extern "C" fn on_progress(progress: *const LunaMiningProgress, user_data: *mut c_void) {
+    let log = unsafe { &*(user_data as *const std::sync::Mutex<ProgressLog>) };
+    let progress = unsafe { &*progress };
+    let mut log = log.lock().unwrap();
+    log.reports += 1;
+    if progress.attempts == 0 || progress.attempts % 10_000 != 0 || progress.elapsed_secs < 0.0 {
+        log.malformed += 1;
+    }
+}
+
+#[test]
+fn test_c_caller_round_trip() {
+    let dir = tempfile::tempdir().unwrap();
+    let mut server = mockito::Server::new();
+    let broadcast = server.mock("POST", "/mempool/add").with_status(200).with_body(r#"{"status": "accepted"}"#).expect(1).create();
+    let url = CString::new(server.url()).unwrap();
+    let data_dir = CString::new(dir.path().to_str().unwrap()).unwrap();
+    let password = CString::new("hunter2").unwrap();
+    unsafe {
+        assert_eq!(CStr::from_ptr(luna_version()).to_str().unwrap(), env!("CARGO_PKG_VERSION"));
+
+        let mut context: *mut LunaContextHandle = ptr::null_mut();
+        assert_eq!(luna_context_new(url.as_ptr(), data_dir.as_ptr(), &mut context), LunaStatus::Ok);
+        let mut wallet: *mut LunaWalletHandle = ptr::null_mut();
+        assert_eq!(luna_wallet_create(context, c"main".as_ptr(), password.as_ptr(), &mut wallet), LunaStatus::Ok);
+        let mut address: *mut c_char = ptr::null_mut();
+        assert_eq!(luna_wallet_address(wallet, &mut address), LunaStatus::Ok);
+        let address = take_string(address);
+        assert!(address.starts_with("LUN_"), "{}", address);
+
+        let mut listing: *mut c_char = ptr::null_mut();
+        assert_eq!(luna_wallet_list(context, &mut listing), LunaStatus::Ok);
+        let listing: serde_json::Value = serde_json::from_str(&take_string(listing)).unwrap();
+        assert_eq!(listing[0]["address"], address.as_str());
+        assert!(listing[0].get("encrypted_private_key").is_none());
+
+        let log = std::sync::Mutex::new(ProgressLog::default());
+        let c_address = CString::new(address.clone()).unwrap();
+        let mut bill: *mut c_char = ptr::null_mut();
+        let status = luna_mine_bill(context, 1, c_address.as_ptr(), Some(on_progress), &log as *const _ as *mut c_void, &mut bill);
+        assert_eq!(status, LunaStatus::Ok);
+        let bill: serde_json::Value = serde_json::from_str(&take_string(bill)).unwrap();
+        assert_eq!(bill["user_address"], address.as_str());
+        assert_eq!(log.lock().unwrap().malformed, 0, "{} reports", log.lock().unwrap().reports);
+
+        let mut tx: *mut c_char = ptr::null_mut();
+        assert_eq!(luna_transaction_create(context, c_address.as_ptr(), c"LUN_81b637d8fcd2c6dafcca".as_ptr(), 0.5, ptr::null(), &mut tx), LunaStatus::Ok);
+        let unsigned = CString::new(take_string(tx)).unwrap();
+        let mut signed: *mut c_char = ptr::null_mut();
+        assert_eq!(luna_transaction_sign(wallet, unsigned.as_ptr(), &mut signed), LunaStatus::Locked);
+        assert_eq!(last_error(), "Wallet is locked");
+        assert_eq!(luna_wallet_unlock(wallet, password.as_ptr()), LunaStatus::Ok);
+        assert_eq!(luna_transaction_sign(wallet, unsigned.as_ptr(), &mut signed), LunaStatus::Ok);
+        let signed = CString::new(take_string(signed)).unwrap();
+
+        let mut hash: *mut c_char = ptr::null_mut();
+        assert_eq!(luna_transaction_broadcast(context, signed.as_ptr(), &mut hash), LunaStatus::Ok);
+        let hash = take_string(hash);
+        let signed: serde_json::Value = serde_json::from_str(signed.to_str().unwrap()).unwrap();
+        assert_eq!(signed["hash"], hash.as_str());
+        broadcast.assert();
+
+        luna_wallet_free(wallet);
+        luna_context_free(context);
+    }
+}
+
+#[test]
+fn test_c_caller_error_paths() {
+    let dir = tempfile::tempdir().unwrap();
+    let data_dir = CString::new(dir.path().to_str().unwrap()).unwrap();
+    unsafe {
+        let mut context: *mut LunaContextHandle = ptr::null_mut();
+        assert_eq!(luna_context_new(c"not a url".as_ptr(), data_dir.as_ptr(), &mut context), LunaStatus::InvalidArgument);
+        assert!(last_error().contains("Invalid endpoint URL"));
+        assert!(context.is_null());
+        assert_eq!(luna_context_new(ptr::null(), data_dir.as_ptr(), &mut context), LunaStatus::NullArgument);
+        assert_eq!(last_error(), "endpoint_url must not be null");
+
+        assert_eq!(luna_context_new(c"http://127.0.0.1:9".as_ptr(), data_dir.as_ptr(), &mut context), LunaStatus::Ok);
+        let mut wallet: *mut LunaWalletHandle = ptr::null_mut();
+        assert_eq!(luna_wallet_create(context, ptr::null(), c"hunter2".as_ptr(), &mut wallet), LunaStatus::Ok);
+        let mut address: *mut c_char = ptr::null_mut();
+        assert_eq!(luna_wallet_address(wallet, &mut address), LunaStatus::Ok);
+        let address = CString::new(take_string(address)).unwrap();
+        luna_wallet_free(wallet);
+
+        // Reopened from storage, the wallet is locked and the password is checked
+        let mut reopened: *mut LunaWalletHandle = ptr::null_mut();
+        assert_eq!(luna_wallet_open(context, address.as_ptr(), &mut reopened), LunaStatus::Ok);
+        assert_eq!(luna_wallet_unlock(reopened, c"hunter3".as_ptr()), LunaStatus::WrongPassword);
+        assert_eq!(last_error(), "Wrong password");
+        assert_eq!(luna_wallet_unlock(reopened, c"hunter2".as_ptr()), LunaStatus::Ok);
+        assert_eq!(luna_wallet_lock(reopened), LunaStatus::Ok);
+        assert_eq!(luna_wallet_open(context, c"LUN_nobody".as_ptr(), &mut wallet), LunaStatus::InvalidArgument);
+
+        let mut out: *mut c_char = ptr::null_mut();
+        assert_eq!(luna_transaction_sign(reopened, c"{not json".as_ptr(), &mut out), LunaStatus::InvalidArgument);
+        assert_eq!(luna_transaction_create(context, address.as_ptr(), c"LUN_b".as_ptr(), -1.0, ptr::null(), &mut out), LunaStatus::InvalidArgument);
+        assert_eq!(luna_transaction_create(context, address.as_ptr(), c"LUN_nobody".as_ptr(), 1.0, ptr::null(), &mut out), LunaStatus::InvalidArgument);
+        assert!(last_error().contains("not a hex digit"));
+        assert_eq!(luna_mine_bill(context, 7, address.as_ptr(), None, ptr::null_mut(), &mut out), LunaStatus::InvalidArgument);
+        assert!(out.is_null());
+        let invalid_utf8 = [0xffu8, 0];
+        assert_eq!(luna_wallet_unlock(reopened, invalid_utf8.as_ptr().cast()), LunaStatus::InvalidUtf8);
+        assert_eq!(luna_wallet_lock(ptr::null_mut()), LunaStatus::NullArgument);
+
+        luna_wallet_free(reopened);
+        luna_context_free(context);
+        luna_context_free(ptr::null_mut());
+        luna_string_free(ptr::null_mut());
+    }
+}
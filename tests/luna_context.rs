@@ -0,0 +1,72 @@
+use std::time::Duration;
+use lunalib::core::daemon_config::MiningConfig;
+use lunalib::luna_lib::{LunaError, LunaLib};
+
+#[test]
+fn test_wallet_to_mempool_end_to_end() {
+    let dir = tempfile::tempdir().unwrap();
+    let mut server = mockito::Server::new();
+    let broadcast = server
+        .mock("POST", "/mempool/add")
+        .with_status(200)
+        .with_body(r#"{"status": "accepted"}"#)
+        .expect(1)
+        .create();
+    let mut luna = LunaLib::builder()
+        .with_endpoint_url(&server.url())
+        .with_data_dir(dir.path())
+        .with_network_timeout(Duration::from_secs(5))
+        .with_mining(MiningConfig { threads: 2, ..MiningConfig::default() })
+        .with_password_provider(|_address: &str| Some("hunter2".to_string()))
+        .with_daemon(true)
+        .build()
+        .unwrap();
+    assert_eq!(luna.blockchain().timeout, Duration::from_secs(5));
+
+    let wallet = luna.create_wallet("main", "hunter2").unwrap();
+    let recipient = luna.create_wallet("rent", "other").unwrap();
+    assert!(luna.database().load_wallet(&wallet.address).is_some());
+    assert!(luna.wallet_manager().get_wallet_state(&wallet.address).is_some());
+
+    let bill = luna.mine_bill(1, &wallet.address).unwrap();
+    assert_eq!(luna.bill_registry().get_user_bills(&wallet.address).unwrap().len(), 1);
+    assert_eq!(luna.genesis().verify_bill_strict(&bill.bill_serial)["valid"], true);
+
+    let tx = luna.create_transaction(&wallet.address, &recipient.address, 0.5, "rent").unwrap();
+    let hash = luna.submit_transaction(&tx).unwrap();
+    broadcast.assert();
+    assert!(luna.mempool().is_transaction_pending(&hash));
+
+    // The daemon shares the context's mempool, so shutting down flushes the transaction
+    let report = luna.shutdown().unwrap();
+    assert_eq!(report.mempool_flushed, 1);
+}
+
+#[test]
+fn test_context_reports_failures() {
+    let dir = tempfile::tempdir().unwrap();
+    let error = LunaLib::builder().with_endpoint_url("not a url").build().err().unwrap();
+    assert!(matches!(error, LunaError::Validation(_)), "{}", error);
+
+    let mut server = mockito::Server::new();
+    server.mock("POST", "/mempool/add").with_status(503).create();
+    let luna = LunaLib::builder().with_endpoint_url(&server.url()).with_data_dir(dir.path()).build().unwrap();
+    let wallet = luna.create_wallet("main", "hunter2").unwrap();
+    let error = luna.create_transaction(&wallet.address, "LUN_nobody", 1.0, "").unwrap_err();
+    assert!(matches!(&error, LunaError::Crypto(reason) if reason.contains("password provider")), "{}", error);
+    assert!(matches!(luna.mine_bill(7, &wallet.address), Err(LunaError::Validation(_))));
+
+    let luna = LunaLib::builder()
+        .with_endpoint_url(&server.url())
+        .with_data_dir(dir.path())
+        .with_password_provider(|_address: &str| Some("hunter2".to_string()))
+        .build()
+        .unwrap();
+    // Wallets created earlier in the same directory are picked up
+    assert!(luna.wallet_manager().get_wallet_state(&wallet.address).is_some());
+    let recipient = luna.create_wallet("other", "pw").unwrap();
+    let tx = luna.create_transaction(&wallet.address, &recipient.address, 1.0, "").unwrap();
+    let error = luna.submit_transaction(&tx).unwrap_err();
+    assert!(matches!(&error, LunaError::Blockchain(reason) if reason.contains("503")), "{}", error);
+    assert_eq!(luna.mempool().get_mempool_size(), 0);
+}
@@ -0,0 +1,57 @@
+use lunalib::luna_lib::{LunaEvent, LunaLib};
+
+const RECIPIENT: &str = "LUN_81b637d8fcd2c6dafcca";
+
+#[test]
+fn test_mine_and_register_publishes_in_order() {
+    let dir = tempfile::tempdir().unwrap();
+    let mut server = mockito::Server::new();
+    server.mock("POST", "/mempool/add").with_status(200).with_body(r#"{"status": "accepted"}"#).create();
+    let luna = LunaLib::builder()
+        .with_endpoint_url(&server.url())
+        .with_data_dir(dir.path())
+        .with_password_provider(|_address: &str| Some("hunter2".to_string()))
+        .build()
+        .unwrap();
+    let events = luna.events().subscribe();
+    let bills = luna.events().subscribe_filtered(|event| matches!(event, LunaEvent::BillMined { .. }));
+
+    let wallet = luna.create_wallet("main", "hunter2").unwrap();
+    let bill = luna.mine_bill(1, &wallet.address).unwrap();
+    // Registered before the event goes out, so subscribers can read it back
+    assert_eq!(luna.genesis().get_user_portfolio(&wallet.address)["total_bills"], 1);
+    let tx = luna.create_transaction(&wallet.address, RECIPIENT, 0.5, "").unwrap();
+    let hash = luna.submit_transaction(&tx).unwrap();
+
+    let received: Vec<LunaEvent> = events.try_iter().collect();
+    assert_eq!(
+        received,
+        vec![
+            LunaEvent::WalletCreated { address: wallet.address.clone(), label: "main".to_string() },
+            LunaEvent::BillMined { serial: bill.bill_serial.clone(), denomination: 1, owner: wallet.address.clone() },
+            LunaEvent::TransactionCreated { hash: hash.clone(), from: wallet.address.clone(), to: RECIPIENT.to_string(), amount: 0.5 },
+            LunaEvent::TransactionBroadcast { hash },
+        ]
+    );
+    assert_eq!(bills.try_iter().count(), 1);
+}
+
+#[test]
+fn test_rejected_transaction_is_not_broadcast() {
+    let dir = tempfile::tempdir().unwrap();
+    let mut server = mockito::Server::new();
+    server.mock("POST", "/mempool/add").with_status(503).create();
+    let luna = LunaLib::builder()
+        .with_endpoint_url(&server.url())
+        .with_data_dir(dir.path())
+        .with_password_provider(|_address: &str| Some("hunter2".to_string()))
+        .build()
+        .unwrap();
+    let wallet = luna.create_wallet("main", "hunter2").unwrap();
+    let events = luna.events().subscribe();
+
+    let tx = luna.create_transaction(&wallet.address, RECIPIENT, 1.0, "").unwrap();
+    assert!(luna.submit_transaction(&tx).is_err());
+    let names: Vec<&str> = events.try_iter().map(|e| e.name()).collect();
+    assert_eq!(names, ["transaction_created"]);
+}
@@ -0,0 +1,63 @@
+#![cfg(feature = "wasm")]
+//! The JavaScript bindings, run with wasm-bindgen-test on wasm32 and as ordinary tests elsewhere
+#[cfg(target_arch = "wasm32")]
+use wasm_bindgen_test::wasm_bindgen_test as test;
+use lunalib::gtx::digital_bill::DigitalBill;
+use lunalib::wasm::*;
+use serde_json::json;
+
+#[test]
+fn test_sign_verify_round_trip() {
+    let keypair = generate_keypair();
+    assert!(keypair.address().starts_with("LUN_"), "{}", keypair.address());
+    let signature = sign_data("hello", &keypair.private_key());
+    assert!(verify_signature("hello", &signature, &keypair.public_key()));
+    assert!(!verify_signature("hello", "not a signature", &keypair.public_key()));
+
+    let mut wallet = Wallet::new("main", "hunter2");
+    let tx = json!({"type": "transfer", "from": wallet.address(), "to": "LUN_b", "amount": 1.5, "fee": 0.001, "timestamp": 1_700_000_000, "memo": ""}).to_string();
+    assert_eq!(wallet.sign_transaction(&tx).unwrap_err(), "Wallet is locked");
+    wallet.unlock("hunter2").unwrap();
+    let signed: serde_json::Value = serde_json::from_str(&wallet.sign_transaction(&tx).unwrap()).unwrap();
+    assert_eq!(signed["public_key"], wallet.public_key().as_str());
+    assert!(verify_signature(signed["hash"].as_str().unwrap(), signed["signature"].as_str().unwrap(), &wallet.public_key()));
+    let foreign = json!({"from": "LUN_someone_else", "to": "LUN_b", "amount": 1.0}).to_string();
+    assert!(wallet.sign_transaction(&foreign).unwrap_err().contains("not LUN_"));
+
+    let mut bill = DigitalBill::new(100, "LUN_a".to_string(), 5, None, None, None, None, None, None, None);
+    bill.public_key = Some("bill-key".to_string());
+    bill.signature = Some(bill.sign("bill-key"));
+    let bill_json = serde_json::to_value(&bill).unwrap();
+    assert!(verify_bill(&bill_json.to_string()).unwrap());
+    let mut forged = bill_json.clone();
+    forged["denomination"] = json!(1000);
+    assert!(!verify_bill(&forged.to_string()).unwrap());
+    assert!(verify_bill("{}").is_err());
+}
+
+#[test]
+fn test_encrypted_wallet_round_trip() {
+    let encrypted = encrypt("secret", "hunter2");
+    assert_ne!(encrypted, "secret");
+    assert_eq!(decrypt(&encrypted, "hunter2").unwrap(), "secret");
+    assert_eq!(decrypt(&encrypted, "hunter3").unwrap_err(), "Wrong password");
+
+    let phrase = generate_mnemonic(12).unwrap();
+    let wallet = Wallet::from_mnemonic(&phrase, 0, "seed", "hunter2").unwrap();
+    assert!(generate_mnemonic(13).is_err());
+
+    let record = wallet.to_record();
+    assert!(!record.contains(&phrase));
+    let mut restored = Wallet::from_record(&record).unwrap();
+    assert_eq!(restored.address(), wallet.address());
+    assert_eq!(restored.label(), "seed");
+    assert!(restored.is_locked());
+    assert_eq!(restored.unlock("hunter3").unwrap_err(), "Wrong password");
+    restored.unlock("hunter2").unwrap();
+    assert!(!restored.is_locked());
+    let tx = json!({"type": "transfer", "from": restored.address(), "to": "LUN_b", "amount": 2.0}).to_string();
+    assert!(restored.sign_transaction(&tx).is_ok());
+    restored.lock();
+    assert!(restored.is_locked());
+    assert!(Wallet::from_record("{\"label\": \"no address\"}").is_err());
+}